@@ -3,7 +3,7 @@ extern crate criterion;
 
 use askar_crypto::{
     alg::{
-        aes::{A128CbcHs256, A128Gcm, AesKey},
+        aes::{A128CbcHs256, A128Gcm, A256Gcm, AesKey},
         chacha20::{Chacha20Key, C20P},
         AnyKey, AnyKeyCreate, Chacha20Types, KeyAlg,
     },
@@ -77,6 +77,35 @@ fn criterion_benchmark(c: &mut Criterion) {
             })
         });
     }
+
+    // larger payloads, comparable to stored entry values
+    for size in &[1024usize, 16384, 262144] {
+        let message = vec![0x55u8; *size];
+
+        let msg = message.clone();
+        c.bench_function(&format!("aes256gcm encrypt {} bytes", size), move |b| {
+            let key = AesKey::<A256Gcm>::generate().unwrap();
+            let nonce = AesKey::<A256Gcm>::random_nonce();
+            b.iter(|| {
+                let mut buffer = SecretBytes::with_capacity(msg.len() + 16);
+                buffer.buffer_write(black_box(&msg[..])).unwrap();
+                key.encrypt_in_place(&mut buffer, &nonce, &[]).unwrap();
+            })
+        });
+
+        c.bench_function(
+            &format!("chacha20-poly1305 encrypt {} bytes", size),
+            move |b| {
+                let key = Chacha20Key::<C20P>::generate().unwrap();
+                let nonce = Chacha20Key::<C20P>::random_nonce();
+                b.iter(|| {
+                    let mut buffer = SecretBytes::with_capacity(message.len() + 16);
+                    buffer.buffer_write(black_box(&message[..])).unwrap();
+                    key.encrypt_in_place(&mut buffer, &nonce, &[]).unwrap();
+                })
+            },
+        );
+    }
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,40 @@
+#[macro_use]
+extern crate criterion;
+
+use askar_crypto::{
+    alg::{AnyKey, AnyKeyCreate, KeyAlg},
+    random::RandomDet,
+};
+
+use criterion::{black_box, Criterion};
+
+const ALGS: &[&str] = &[
+    "a256gcm",
+    "bls12381g1",
+    "c20p",
+    "ed25519",
+    "k256",
+    "p256",
+    "x25519",
+];
+
+fn criterion_benchmark(c: &mut Criterion) {
+    for alg_name in ALGS {
+        let alg: KeyAlg = alg_name.parse().unwrap();
+
+        c.bench_function(&format!("{} generate", alg_name), move |b| {
+            b.iter(|| Box::<AnyKey>::generate(black_box(alg)).unwrap())
+        });
+
+        // excludes the overhead of the system RNG
+        c.bench_function(&format!("{} generate deterministic", alg_name), move |b| {
+            b.iter(|| {
+                let rng = RandomDet::new(black_box(&[1u8; 32])).unwrap();
+                Box::<AnyKey>::generate_with_rng(alg, rng).unwrap()
+            })
+        });
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
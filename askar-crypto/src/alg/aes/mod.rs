@@ -15,7 +15,7 @@ use crate::{
     generic_array::{typenum::Unsigned, GenericArray},
     jwk::{JwkEncoder, ToJwk},
     kdf::{FromKeyDerivation, FromKeyExchange, KeyDerivation, KeyExchange},
-    random::fill_random_deterministic,
+    random::{fill_random_deterministic, KeyMaterial},
     repr::{KeyGen, KeyMeta, KeySecretBytes, Seed, SeedMethod},
 };
 
@@ -91,8 +91,8 @@ impl<T: AesType> KeyMeta for AesKey<T> {
 }
 
 impl<T: AesType> KeyGen for AesKey<T> {
-    fn generate() -> Result<Self, Error> {
-        Ok(AesKey(KeyType::<T>::random()))
+    fn generate_with_rng(rng: impl KeyMaterial) -> Result<Self, Error> {
+        Ok(AesKey(KeyType::<T>::generate(rng)))
     }
 
     fn from_seed(seed: Seed<'_>) -> Result<Self, Error>
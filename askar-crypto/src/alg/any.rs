@@ -42,6 +42,11 @@ use crate::{
     error::Error,
     jwk::{FromJwk, JwkEncoder, JwkParts, ToJwk},
     kdf::{KeyDerivation, KeyExchange},
+    pkcs8::{
+        der::{AlgorithmId, PrivateKeyInfo, PublicKeyInfo},
+        FromPkcs8, ToPkcs8,
+    },
+    random::{default_rng, KeyMaterial},
     repr::{KeyGen, KeyPublicBytes, KeySecretBytes, Seed, ToPublicBytes, ToSecretBytes},
     sign::{KeySigVerify, KeySign, SignatureType},
 };
@@ -86,8 +91,14 @@ impl std::panic::RefUnwindSafe for AnyKey {}
 
 /// Create `AnyKey` instances from various sources
 pub trait AnyKeyCreate: Sized {
+    /// Generate a new key for the given key algorithm from a source of
+    /// key material, such as a random number generator.
+    fn generate_with_rng(alg: KeyAlg, rng: impl KeyMaterial) -> Result<Self, Error>;
+
     /// Generate a new random key for the given key algorithm.
-    fn generate(alg: KeyAlg) -> Result<Self, Error>;
+    fn generate(alg: KeyAlg) -> Result<Self, Error> {
+        Self::generate_with_rng(alg, default_rng())
+    }
 
     /// Generate a new deterministic key for the given key algorithm.
     fn from_seed(alg: KeyAlg, seed: Seed<'_>) -> Result<Self, Error>;
@@ -115,8 +126,8 @@ pub trait AnyKeyCreate: Sized {
 }
 
 impl AnyKeyCreate for Box<AnyKey> {
-    fn generate(alg: KeyAlg) -> Result<Self, Error> {
-        generate_any(alg)
+    fn generate_with_rng(alg: KeyAlg, rng: impl KeyMaterial) -> Result<Self, Error> {
+        generate_any(alg, rng)
     }
 
     fn from_seed(alg: KeyAlg, seed: Seed<'_>) -> Result<Self, Error> {
@@ -154,8 +165,8 @@ impl AnyKeyCreate for Box<AnyKey> {
 }
 
 impl AnyKeyCreate for Arc<AnyKey> {
-    fn generate(alg: KeyAlg) -> Result<Self, Error> {
-        generate_any(alg)
+    fn generate_with_rng(alg: KeyAlg, rng: impl KeyMaterial) -> Result<Self, Error> {
+        generate_any(alg, rng)
     }
 
     fn from_seed(alg: KeyAlg, seed: Seed<'_>) -> Result<Self, Error> {
@@ -193,40 +204,60 @@ impl AnyKeyCreate for Arc<AnyKey> {
 }
 
 #[inline]
-fn generate_any<R: AllocKey>(alg: KeyAlg) -> Result<R, Error> {
+fn generate_any<R: AllocKey>(alg: KeyAlg, rng: impl KeyMaterial) -> Result<R, Error> {
     match alg {
         #[cfg(feature = "aes")]
-        KeyAlg::Aes(AesTypes::A128Gcm) => AesKey::<A128Gcm>::generate().map(R::alloc_key),
+        KeyAlg::Aes(AesTypes::A128Gcm) => {
+            AesKey::<A128Gcm>::generate_with_rng(rng).map(R::alloc_key)
+        }
         #[cfg(feature = "aes")]
-        KeyAlg::Aes(AesTypes::A256Gcm) => AesKey::<A256Gcm>::generate().map(R::alloc_key),
+        KeyAlg::Aes(AesTypes::A256Gcm) => {
+            AesKey::<A256Gcm>::generate_with_rng(rng).map(R::alloc_key)
+        }
         #[cfg(feature = "aes")]
-        KeyAlg::Aes(AesTypes::A128CbcHs256) => AesKey::<A128CbcHs256>::generate().map(R::alloc_key),
+        KeyAlg::Aes(AesTypes::A128CbcHs256) => {
+            AesKey::<A128CbcHs256>::generate_with_rng(rng).map(R::alloc_key)
+        }
         #[cfg(feature = "aes")]
-        KeyAlg::Aes(AesTypes::A256CbcHs512) => AesKey::<A256CbcHs512>::generate().map(R::alloc_key),
+        KeyAlg::Aes(AesTypes::A256CbcHs512) => {
+            AesKey::<A256CbcHs512>::generate_with_rng(rng).map(R::alloc_key)
+        }
         #[cfg(feature = "aes")]
-        KeyAlg::Aes(AesTypes::A128Kw) => AesKey::<A128Kw>::generate().map(R::alloc_key),
+        KeyAlg::Aes(AesTypes::A128Kw) => AesKey::<A128Kw>::generate_with_rng(rng).map(R::alloc_key),
         #[cfg(feature = "aes")]
-        KeyAlg::Aes(AesTypes::A256Kw) => AesKey::<A256Kw>::generate().map(R::alloc_key),
+        KeyAlg::Aes(AesTypes::A256Kw) => AesKey::<A256Kw>::generate_with_rng(rng).map(R::alloc_key),
         #[cfg(feature = "bls")]
-        KeyAlg::Bls12_381(BlsCurves::G1) => BlsKeyPair::<G1>::generate().map(R::alloc_key),
+        KeyAlg::Bls12_381(BlsCurves::G1) => {
+            BlsKeyPair::<G1>::generate_with_rng(rng).map(R::alloc_key)
+        }
         #[cfg(feature = "bls")]
-        KeyAlg::Bls12_381(BlsCurves::G2) => BlsKeyPair::<G2>::generate().map(R::alloc_key),
+        KeyAlg::Bls12_381(BlsCurves::G2) => {
+            BlsKeyPair::<G2>::generate_with_rng(rng).map(R::alloc_key)
+        }
         #[cfg(feature = "bls")]
-        KeyAlg::Bls12_381(BlsCurves::G1G2) => BlsKeyPair::<G1G2>::generate().map(R::alloc_key),
+        KeyAlg::Bls12_381(BlsCurves::G1G2) => {
+            BlsKeyPair::<G1G2>::generate_with_rng(rng).map(R::alloc_key)
+        }
         #[cfg(feature = "chacha")]
-        KeyAlg::Chacha20(Chacha20Types::C20P) => Chacha20Key::<C20P>::generate().map(R::alloc_key),
+        KeyAlg::Chacha20(Chacha20Types::C20P) => {
+            Chacha20Key::<C20P>::generate_with_rng(rng).map(R::alloc_key)
+        }
         #[cfg(feature = "chacha")]
         KeyAlg::Chacha20(Chacha20Types::XC20P) => {
-            Chacha20Key::<XC20P>::generate().map(R::alloc_key)
+            Chacha20Key::<XC20P>::generate_with_rng(rng).map(R::alloc_key)
         }
         #[cfg(feature = "ed25519")]
-        KeyAlg::Ed25519 => Ed25519KeyPair::generate().map(R::alloc_key),
+        KeyAlg::Ed25519 => Ed25519KeyPair::generate_with_rng(rng).map(R::alloc_key),
         #[cfg(feature = "ed25519")]
-        KeyAlg::X25519 => X25519KeyPair::generate().map(R::alloc_key),
+        KeyAlg::X25519 => X25519KeyPair::generate_with_rng(rng).map(R::alloc_key),
         #[cfg(feature = "k256")]
-        KeyAlg::EcCurve(EcCurves::Secp256k1) => K256KeyPair::generate().map(R::alloc_key),
+        KeyAlg::EcCurve(EcCurves::Secp256k1) => {
+            K256KeyPair::generate_with_rng(rng).map(R::alloc_key)
+        }
         #[cfg(feature = "p256")]
-        KeyAlg::EcCurve(EcCurves::Secp256r1) => P256KeyPair::generate().map(R::alloc_key),
+        KeyAlg::EcCurve(EcCurves::Secp256r1) => {
+            P256KeyPair::generate_with_rng(rng).map(R::alloc_key)
+        }
         #[allow(unreachable_patterns)]
         _ => {
             return Err(err_msg!(
@@ -576,6 +607,72 @@ fn from_jwk_any<R: AllocKey>(jwk: JwkParts<'_>) -> Result<R, Error> {
     }
 }
 
+impl FromPkcs8 for Box<AnyKey> {
+    fn from_pkcs8_der(der: &[u8]) -> Result<Self, Error> {
+        from_pkcs8_any(der)
+    }
+
+    fn from_spki_der(der: &[u8]) -> Result<Self, Error> {
+        from_spki_any(der)
+    }
+}
+
+impl FromPkcs8 for Arc<AnyKey> {
+    fn from_pkcs8_der(der: &[u8]) -> Result<Self, Error> {
+        from_pkcs8_any(der)
+    }
+
+    fn from_spki_der(der: &[u8]) -> Result<Self, Error> {
+        from_spki_any(der)
+    }
+}
+
+#[inline]
+fn from_pkcs8_any<R: AllocKey>(der: &[u8]) -> Result<R, Error> {
+    let alg = PrivateKeyInfo::decode(der)?.alg;
+    match pkcs8_key_alg(alg)? {
+        #[cfg(feature = "ed25519")]
+        KeyAlg::Ed25519 => Ed25519KeyPair::from_pkcs8_der(der).map(R::alloc_key),
+        #[cfg(feature = "ed25519")]
+        KeyAlg::X25519 => X25519KeyPair::from_pkcs8_der(der).map(R::alloc_key),
+        #[cfg(feature = "p256")]
+        KeyAlg::EcCurve(EcCurves::Secp256r1) => P256KeyPair::from_pkcs8_der(der).map(R::alloc_key),
+        #[allow(unreachable_patterns)]
+        _ => Err(err_msg!(Unsupported, "Unsupported PKCS#8 key algorithm")),
+    }
+}
+
+#[inline]
+fn from_spki_any<R: AllocKey>(der: &[u8]) -> Result<R, Error> {
+    let alg = PublicKeyInfo::decode(der)?.alg;
+    match pkcs8_key_alg(alg)? {
+        #[cfg(feature = "ed25519")]
+        KeyAlg::Ed25519 => Ed25519KeyPair::from_spki_der(der).map(R::alloc_key),
+        #[cfg(feature = "ed25519")]
+        KeyAlg::X25519 => X25519KeyPair::from_spki_der(der).map(R::alloc_key),
+        #[cfg(feature = "p256")]
+        KeyAlg::EcCurve(EcCurves::Secp256r1) => P256KeyPair::from_spki_der(der).map(R::alloc_key),
+        #[allow(unreachable_patterns)]
+        _ => Err(err_msg!(Unsupported, "Unsupported SPKI key algorithm")),
+    }
+}
+
+fn pkcs8_key_alg(alg: AlgorithmId<'_>) -> Result<KeyAlg, Error> {
+    use crate::pkcs8::der::{OID_ED25519, OID_X25519};
+    match (alg.oid, alg.curve) {
+        (oid, None) if oid == OID_ED25519 => Ok(KeyAlg::Ed25519),
+        (oid, None) if oid == OID_X25519 => Ok(KeyAlg::X25519),
+        #[cfg(feature = "p256")]
+        (oid, Some(curve))
+            if oid == crate::pkcs8::der::OID_EC_PUBLIC_KEY
+                && curve == crate::pkcs8::der::OID_P256 =>
+        {
+            Ok(KeyAlg::EcCurve(EcCurves::Secp256r1))
+        }
+        _ => Err(err_msg!(Unsupported, "Unsupported PKCS#8 key algorithm")),
+    }
+}
+
 macro_rules! match_key_alg {
     ($slf:expr, $ty:ty, $($kty:ident),+ $(,$errmsg:literal)?) => {{
         fn matcher(key: &AnyKey) -> Result<$ty, Error> {
@@ -696,6 +793,17 @@ impl AnyKey {
         }
     }
 
+    fn key_to_pkcs8(&self) -> Result<&dyn ToPkcs8, Error> {
+        match_key_alg! {
+            self,
+            &dyn ToPkcs8,
+            Ed25519,
+            P256,
+            X25519,
+            "PKCS#8 export is not supported for this key type"
+        }
+    }
+
     fn key_to_public(&self) -> Result<&dyn ToPublicBytes, Error> {
         match_key_alg! {
             self,
@@ -710,6 +818,16 @@ impl AnyKey {
     }
 }
 
+impl ToPkcs8 for AnyKey {
+    fn write_pkcs8_der(&self, out: &mut dyn WriteBuffer) -> Result<(), Error> {
+        self.key_to_pkcs8()?.write_pkcs8_der(out)
+    }
+
+    fn write_spki_der(&self, out: &mut dyn WriteBuffer) -> Result<(), Error> {
+        self.key_to_pkcs8()?.write_spki_der(out)
+    }
+}
+
 impl ToPublicBytes for AnyKey {
     fn public_bytes_length(&self) -> Result<usize, Error> {
         self.key_to_public()?.public_bytes_length()
@@ -895,6 +1013,30 @@ mod tests {
         let _ = key.to_jwk_public(None).unwrap();
     }
 
+    #[cfg(all(feature = "chacha", feature = "ed25519", feature = "p256"))]
+    #[test]
+    fn pkcs8_any() {
+        for alg in &[
+            KeyAlg::Ed25519,
+            KeyAlg::X25519,
+            KeyAlg::EcCurve(EcCurves::Secp256r1),
+        ] {
+            let key = Box::<AnyKey>::generate(*alg).unwrap();
+            let cmp =
+                Box::<AnyKey>::from_pkcs8_pem(key.to_pkcs8_pem().unwrap().as_opt_str().unwrap())
+                    .unwrap();
+            assert_eq!(cmp.algorithm(), *alg);
+            assert_eq!(cmp.to_jwk_secret().unwrap(), key.to_jwk_secret().unwrap());
+            let pk = Box::<AnyKey>::from_spki_der(&key.to_spki_der().unwrap()).unwrap();
+            assert_eq!(
+                pk.to_jwk_public(None).unwrap(),
+                key.to_jwk_public(None).unwrap()
+            );
+        }
+        let sym = Box::<AnyKey>::generate(KeyAlg::Chacha20(Chacha20Types::C20P)).unwrap();
+        assert!(sym.to_pkcs8_der().is_err());
+    }
+
     #[cfg(feature = "aes")]
     #[test]
     fn key_exchange_any() {
@@ -23,7 +23,7 @@ use crate::{
     buffer::ArrayKey,
     error::Error,
     jwk::{FromJwk, JwkEncoder, JwkParts, ToJwk},
-    random::fill_random,
+    random::KeyMaterial,
     repr::{KeyGen, KeyMeta, KeyPublicBytes, KeySecretBytes, KeypairMeta, Seed, SeedMethod},
 };
 
@@ -95,8 +95,8 @@ where
 }
 
 impl<Pk: BlsPublicKeyType> KeyGen for BlsKeyPair<Pk> {
-    fn generate() -> Result<Self, Error> {
-        let secret = BlsSecretKey::generate()?;
+    fn generate_with_rng(rng: impl KeyMaterial) -> Result<Self, Error> {
+        let secret = BlsSecretKey::generate(rng)?;
         Ok(Self::from_secret_key(secret))
     }
 
@@ -202,9 +202,9 @@ impl<Pk: BlsPublicKeyType> FromJwk for BlsKeyPair<Pk> {
 struct BlsSecretKey(Scalar);
 
 impl BlsSecretKey {
-    pub fn generate() -> Result<Self, Error> {
+    pub fn generate(mut rng: impl KeyMaterial) -> Result<Self, Error> {
         let mut secret = Zeroizing::new([0u8; 64]);
-        fill_random(&mut secret[..]);
+        rng.read_okm(&mut secret[..]);
         Ok(Self(Scalar::from_bytes_wide(&secret)))
     }
 
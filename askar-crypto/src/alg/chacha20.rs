@@ -15,7 +15,7 @@ use crate::{
     generic_array::{typenum::Unsigned, GenericArray},
     jwk::{JwkEncoder, ToJwk},
     kdf::{FromKeyDerivation, FromKeyExchange, KeyDerivation, KeyExchange},
-    random::fill_random_deterministic,
+    random::{fill_random_deterministic, KeyMaterial},
     repr::{KeyGen, KeyMeta, KeySecretBytes, Seed, SeedMethod},
 };
 
@@ -116,8 +116,8 @@ impl<T: Chacha20Type> KeyMeta for Chacha20Key<T> {
 }
 
 impl<T: Chacha20Type> KeyGen for Chacha20Key<T> {
-    fn generate() -> Result<Self, Error> {
-        Ok(Chacha20Key(KeyType::<T>::random()))
+    fn generate_with_rng(rng: impl KeyMaterial) -> Result<Self, Error> {
+        Ok(Chacha20Key(KeyType::<T>::generate(rng)))
     }
 
     fn from_seed(seed: Seed<'_>) -> Result<Self, Error>
@@ -19,7 +19,7 @@ use crate::{
     generic_array::typenum::{U32, U33, U65},
     jwk::{FromJwk, JwkEncoder, JwkParts, ToJwk},
     kdf::KeyExchange,
-    random::with_rng,
+    random::KeyMaterial,
     repr::{KeyGen, KeyMeta, KeyPublicBytes, KeySecretBytes, KeypairBytes, KeypairMeta},
     sign::{KeySigVerify, KeySign, SignatureType},
 };
@@ -112,8 +112,14 @@ impl KeyMeta for K256KeyPair {
 }
 
 impl KeyGen for K256KeyPair {
-    fn generate() -> Result<Self, Error> {
-        Ok(Self::from_secret_key(with_rng(|r| SecretKey::random(r))))
+    fn generate_with_rng(mut rng: impl KeyMaterial) -> Result<Self, Error> {
+        ArrayKey::<U32>::temp(|buf| loop {
+            rng.read_okm(buf);
+            // retry in the (unlikely) case that the scalar is out of range
+            if let Ok(sk) = SecretKey::from_bytes(&buf[..]) {
+                return Ok(Self::from_secret_key(sk));
+            }
+        })
     }
 }
 
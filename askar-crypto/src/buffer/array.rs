@@ -13,7 +13,7 @@ use super::HexRepr;
 use crate::{
     error::Error,
     kdf::{FromKeyDerivation, KeyDerivation},
-    random::fill_random,
+    random::{fill_random, KeyMaterial},
 };
 
 /// A secure representation for fixed-length keys
@@ -75,6 +75,12 @@ impl<L: ArrayLength<u8>> ArrayKey<L> {
         Self::new_with(fill_random)
     }
 
+    /// Create a new array using a source of key material
+    #[inline]
+    pub fn generate(mut rng: impl KeyMaterial) -> Self {
+        Self::new_with(|buf| rng.read_okm(buf))
+    }
+
     /// Get a hex formatter for the key data
     pub fn as_hex(&self) -> HexRepr<&[u8]> {
         HexRepr(self.0.as_ref())
@@ -11,6 +11,12 @@ use crate::{
 #[cfg_attr(docsrs, doc(cfg(feature = "crypto_box")))]
 pub mod crypto_box;
 
+mod stream;
+pub use self::stream::{stream_prefix_length, DecryptStream, EncryptStream, STREAM_NONCE_OVERHEAD};
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+pub use self::stream::{DecryptWriter, EncryptWriter};
+
 /// Object-safe trait for key types which perform AEAD encryption
 pub trait KeyAeadInPlace {
     /// Encrypt a secret value in place, appending the verification tag and
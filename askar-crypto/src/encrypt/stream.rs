@@ -0,0 +1,437 @@
+//! Segmented streaming AEAD encryption
+//!
+//! Large payloads are divided into segments which are encrypted separately,
+//! following the STREAM construction. The nonce of each segment is formed
+//! from a random prefix, a 32-bit big-endian segment counter, and a single
+//! byte flag which is set for the final segment. Segments may not be
+//! reordered or dropped, and a truncated stream is detected when the final
+//! segment is missing.
+
+use core::fmt::{self, Debug, Formatter};
+
+#[cfg(feature = "alloc")]
+use crate::buffer::{SecretBytes, WriteBuffer};
+use crate::{buffer::ResizeBuffer, encrypt::KeyAeadInPlace, error::Error, random::fill_random};
+
+/// The length of the segment counter and final segment flag in each nonce
+pub const STREAM_NONCE_OVERHEAD: usize = 5;
+
+/// The maximum supported length of the AEAD nonce
+const MAX_NONCE_LENGTH: usize = 32;
+
+/// The state shared by the encryption and decryption streams
+struct StreamNonce {
+    nonce: [u8; MAX_NONCE_LENGTH],
+    length: usize,
+    counter: u32,
+    done: bool,
+}
+
+impl StreamNonce {
+    fn new<K: KeyAeadInPlace + ?Sized>(key: &K, prefix: &[u8]) -> Result<Self, Error> {
+        let length = key.aead_params().nonce_length;
+        if !(STREAM_NONCE_OVERHEAD + 4..=MAX_NONCE_LENGTH).contains(&length) {
+            return Err(err_msg!(
+                Unsupported,
+                "Key type does not support streaming encryption"
+            ));
+        }
+        if prefix.len() != length - STREAM_NONCE_OVERHEAD {
+            return Err(err_msg!(InvalidNonce));
+        }
+        let mut nonce = [0u8; MAX_NONCE_LENGTH];
+        nonce[..prefix.len()].copy_from_slice(prefix);
+        Ok(Self {
+            nonce,
+            length,
+            counter: 0,
+            done: false,
+        })
+    }
+
+    fn prefix(&self) -> &[u8] {
+        &self.nonce[..(self.length - STREAM_NONCE_OVERHEAD)]
+    }
+
+    /// Get the nonce for the next segment, advancing the segment counter
+    fn next(&mut self, last: bool) -> Result<&[u8], Error> {
+        if self.done {
+            return Err(err_msg!(Usage, "Stream has already been finalized"));
+        }
+        let pos = self.length - STREAM_NONCE_OVERHEAD;
+        self.nonce[pos..(pos + 4)].copy_from_slice(&self.counter.to_be_bytes());
+        self.nonce[pos + 4] = last as u8;
+        if last {
+            self.done = true;
+        } else {
+            self.counter = self
+                .counter
+                .checked_add(1)
+                .ok_or_else(|| err_msg!(Usage, "Exceeded maximum number of stream segments"))?;
+        }
+        Ok(&self.nonce[..self.length])
+    }
+}
+
+/// Determine the length of the nonce prefix for a streaming encryption key
+pub fn stream_prefix_length<K: KeyAeadInPlace + ?Sized>(key: &K) -> usize {
+    key.aead_params()
+        .nonce_length
+        .saturating_sub(STREAM_NONCE_OVERHEAD)
+}
+
+/// Encrypt a payload as a sequence of segments
+pub struct EncryptStream<'k, K: KeyAeadInPlace + ?Sized> {
+    key: &'k K,
+    nonce: StreamNonce,
+}
+
+impl<'k, K: KeyAeadInPlace + ?Sized> EncryptStream<'k, K> {
+    /// Start a new stream with a random nonce prefix
+    pub fn new(key: &'k K) -> Result<Self, Error> {
+        let mut prefix = [0u8; MAX_NONCE_LENGTH];
+        let prefix = &mut prefix[..stream_prefix_length(key)];
+        fill_random(prefix);
+        Self::with_prefix(key, prefix)
+    }
+
+    /// Start a new stream with a given nonce prefix. The prefix must not be
+    /// reused for another stream encrypted by the same key
+    pub fn with_prefix(key: &'k K, prefix: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            key,
+            nonce: StreamNonce::new(key, prefix)?,
+        })
+    }
+
+    /// Accessor for the nonce prefix, which must be provided for decryption
+    pub fn prefix(&self) -> &[u8] {
+        self.nonce.prefix()
+    }
+
+    /// Encrypt a segment in place, appending the verification tag and
+    /// returning the length of the ciphertext
+    pub fn encrypt_segment(&mut self, buffer: &mut dyn ResizeBuffer) -> Result<usize, Error> {
+        let nonce = self.nonce.next(false)?;
+        self.key.encrypt_in_place(buffer, nonce, &[])
+    }
+
+    /// Encrypt the final segment of the stream in place, which may be empty
+    pub fn encrypt_last(mut self, buffer: &mut dyn ResizeBuffer) -> Result<usize, Error> {
+        let nonce = self.nonce.next(true)?;
+        self.key.encrypt_in_place(buffer, nonce, &[])
+    }
+}
+
+impl<K: KeyAeadInPlace + ?Sized> Debug for EncryptStream<'_, K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptStream")
+            .field("segment", &self.nonce.counter)
+            .finish()
+    }
+}
+
+/// Decrypt a payload produced by an `EncryptStream`
+pub struct DecryptStream<'k, K: KeyAeadInPlace + ?Sized> {
+    key: &'k K,
+    nonce: StreamNonce,
+}
+
+impl<'k, K: KeyAeadInPlace + ?Sized> DecryptStream<'k, K> {
+    /// Start decrypting a stream with the nonce prefix used for encryption
+    pub fn new(key: &'k K, prefix: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            key,
+            nonce: StreamNonce::new(key, prefix)?,
+        })
+    }
+
+    /// Decrypt a segment (verification tag appended) in place
+    pub fn decrypt_segment(&mut self, buffer: &mut dyn ResizeBuffer) -> Result<(), Error> {
+        let nonce = self.nonce.next(false)?;
+        self.key.decrypt_in_place(buffer, nonce, &[])
+    }
+
+    /// Decrypt the final segment of the stream in place. Decryption fails if
+    /// the segment was not encrypted as the final segment
+    pub fn decrypt_last(mut self, buffer: &mut dyn ResizeBuffer) -> Result<(), Error> {
+        let nonce = self.nonce.next(true)?;
+        self.key.decrypt_in_place(buffer, nonce, &[])
+    }
+}
+
+impl<K: KeyAeadInPlace + ?Sized> Debug for DecryptStream<'_, K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecryptStream")
+            .field("segment", &self.nonce.counter)
+            .finish()
+    }
+}
+
+/// Buffer input until a complete segment is available, passing each segment
+/// except the last to `f`
+#[cfg(feature = "alloc")]
+fn write_segments(
+    pending: &mut SecretBytes,
+    data: &[u8],
+    segment_len: usize,
+    mut f: impl FnMut(&mut SecretBytes) -> Result<(), Error>,
+) -> Result<(), Error> {
+    // segments are taken from the buffered input and then from `data` by
+    // offset, so that the buffer is only shifted once
+    let mut start = 0;
+    let mut data = data;
+    // the last segment is retained, as it must be processed by `finish`
+    while pending.len() - start + data.len() > segment_len {
+        let buffered = pending.len() - start;
+        let mut segment = if buffered >= segment_len {
+            start += segment_len;
+            SecretBytes::from_slice(&pending[(start - segment_len)..start])
+        } else {
+            let take = segment_len - buffered;
+            let mut segment = SecretBytes::from_slice_reserve(&pending[start..], take);
+            segment.extend_from_slice(&data[..take]);
+            start = pending.len();
+            data = &data[take..];
+            segment
+        };
+        f(&mut segment)?;
+    }
+    if start > 0 {
+        pending.buffer_remove(0..start)?;
+    }
+    pending.extend_from_slice(data);
+    Ok(())
+}
+
+/// A `WriteBuffer` which encrypts the data written to it in fixed-size
+/// segments, writing the ciphertext to an output buffer. Only the final,
+/// incomplete segment is buffered, and `finish` must be called to write it
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+pub struct EncryptWriter<'w, K: KeyAeadInPlace + ?Sized> {
+    stream: EncryptStream<'w, K>,
+    output: &'w mut dyn WriteBuffer,
+    segment_len: usize,
+    pending: SecretBytes,
+}
+
+#[cfg(feature = "alloc")]
+impl<'w, K: KeyAeadInPlace + ?Sized> EncryptWriter<'w, K> {
+    /// Create a new writer encrypting segments of `segment_len` bytes of
+    /// plaintext. The nonce prefix is written to the output first
+    pub fn new(
+        key: &'w K,
+        segment_len: usize,
+        output: &'w mut dyn WriteBuffer,
+    ) -> Result<Self, Error> {
+        if segment_len == 0 {
+            return Err(err_msg!(Usage, "Invalid stream segment length"));
+        }
+        let stream = EncryptStream::new(key)?;
+        output.buffer_write(stream.prefix())?;
+        Ok(Self {
+            stream,
+            output,
+            segment_len,
+            pending: SecretBytes::with_capacity(segment_len),
+        })
+    }
+
+    /// Encrypt the final segment and write it to the output
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.stream.encrypt_last(&mut self.pending)?;
+        self.output.buffer_write(&self.pending)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<K: KeyAeadInPlace + ?Sized> WriteBuffer for EncryptWriter<'_, K> {
+    fn buffer_write(&mut self, data: &[u8]) -> Result<(), Error> {
+        let stream = &mut self.stream;
+        let output = &mut self.output;
+        write_segments(&mut self.pending, data, self.segment_len, |segment| {
+            stream.encrypt_segment(segment)?;
+            output.buffer_write(segment)
+        })
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<K: KeyAeadInPlace + ?Sized> Debug for EncryptWriter<'_, K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptWriter")
+            .field("stream", &self.stream)
+            .field("segment_len", &self.segment_len)
+            .finish()
+    }
+}
+
+/// A `WriteBuffer` which decrypts the output of an `EncryptWriter` written
+/// to it, writing the plaintext to an output buffer. The plaintext of each
+/// segment is only written once it has been verified, and `finish` must be
+/// called to verify that the stream was not truncated
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+pub struct DecryptWriter<'w, K: KeyAeadInPlace + ?Sized> {
+    key: &'w K,
+    stream: Option<DecryptStream<'w, K>>,
+    output: &'w mut dyn WriteBuffer,
+    segment_len: usize,
+    pending: SecretBytes,
+}
+
+#[cfg(feature = "alloc")]
+impl<'w, K: KeyAeadInPlace + ?Sized> DecryptWriter<'w, K> {
+    /// Create a new writer decrypting segments of `segment_len` bytes of
+    /// plaintext, which must match the length used for encryption
+    pub fn new(
+        key: &'w K,
+        segment_len: usize,
+        output: &'w mut dyn WriteBuffer,
+    ) -> Result<Self, Error> {
+        if segment_len == 0 {
+            return Err(err_msg!(Usage, "Invalid stream segment length"));
+        }
+        let segment_len = segment_len + key.aead_params().tag_length;
+        Ok(Self {
+            key,
+            stream: None,
+            output,
+            segment_len,
+            pending: SecretBytes::with_capacity(segment_len),
+        })
+    }
+
+    /// Read the nonce prefix from the start of the input
+    fn start_stream(&mut self) -> Result<(), Error> {
+        let prefix_len = stream_prefix_length(self.key);
+        if self.stream.is_none() && self.pending.len() >= prefix_len {
+            self.stream
+                .replace(DecryptStream::new(self.key, &self.pending[..prefix_len])?);
+            self.pending.buffer_remove(0..prefix_len)?;
+        }
+        Ok(())
+    }
+
+    /// Decrypt the final segment and write it to the output
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.start_stream()?;
+        let stream = self
+            .stream
+            .take()
+            .ok_or_else(|| err_msg!(InvalidData, "Incomplete encrypted stream"))?;
+        stream.decrypt_last(&mut self.pending)?;
+        self.output.buffer_write(&self.pending)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<K: KeyAeadInPlace + ?Sized> WriteBuffer for DecryptWriter<'_, K> {
+    fn buffer_write(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        if self.stream.is_none() {
+            self.pending.extend_from_slice(data);
+            self.start_stream()?;
+            data = &[];
+        }
+        if let Some(stream) = self.stream.as_mut() {
+            let output = &mut self.output;
+            write_segments(&mut self.pending, data, self.segment_len, |segment| {
+                stream.decrypt_segment(segment)?;
+                output.buffer_write(segment)
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<K: KeyAeadInPlace + ?Sized> Debug for DecryptWriter<'_, K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecryptWriter")
+            .field("stream", &self.stream)
+            .field("segment_len", &self.segment_len)
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "alloc", feature = "chacha"))]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::alg::chacha20::{Chacha20Key, Chacha20Type, C20P, XC20P};
+    use crate::repr::KeyGen;
+
+    #[test]
+    fn stream_segments_round_trip() {
+        let key = Chacha20Key::<C20P>::generate().unwrap();
+        let mut enc = EncryptStream::new(&key).unwrap();
+        let prefix = enc.prefix().to_vec();
+        let mut first = SecretBytes::from_slice(b"hello ");
+        let mut last = SecretBytes::from_slice(b"world");
+        enc.encrypt_segment(&mut first).unwrap();
+        enc.encrypt_last(&mut last).unwrap();
+
+        let mut dec = DecryptStream::new(&key, &prefix).unwrap();
+        let mut check = first.clone();
+        dec.decrypt_segment(&mut check).unwrap();
+        assert_eq!(&check[..], b"hello ");
+        let mut check = last.clone();
+        dec.decrypt_last(&mut check).unwrap();
+        assert_eq!(&check[..], b"world");
+
+        // the final segment may not be dropped or reordered
+        let dec = DecryptStream::new(&key, &prefix).unwrap();
+        assert!(dec.decrypt_last(&mut first.clone()).is_err());
+        let mut dec = DecryptStream::new(&key, &prefix).unwrap();
+        assert!(dec.decrypt_segment(&mut last.clone()).is_err());
+
+        // the prefix must match the nonce length of the key
+        assert!(DecryptStream::new(&key, &prefix[1..]).is_err());
+    }
+
+    #[test]
+    fn stream_writer_round_trip() {
+        fn test_writer<T: Chacha20Type>() {
+            let key = Chacha20Key::<T>::generate().unwrap();
+            let input = (0..1000u32).map(|i| i as u8).collect::<Vec<_>>();
+            // the input is written in small chunks, and all at once
+            for (segment_len, enc_chunk, dec_chunk) in [
+                (1, 37, 53),
+                (64, 37, 53),
+                (999, 37, 53),
+                (1000, 37, 53),
+                (4096, 37, 53),
+                (1, usize::MAX, usize::MAX),
+                (64, usize::MAX, usize::MAX),
+                (1000, usize::MAX, usize::MAX),
+            ] {
+                let mut ciphertext = Vec::new();
+                let mut enc = EncryptWriter::new(&key, segment_len, &mut ciphertext).unwrap();
+                for chunk in input.chunks(enc_chunk) {
+                    enc.buffer_write(chunk).unwrap();
+                }
+                enc.finish().unwrap();
+
+                let mut output = Vec::new();
+                let mut dec = DecryptWriter::new(&key, segment_len, &mut output).unwrap();
+                for chunk in ciphertext.chunks(dec_chunk) {
+                    dec.buffer_write(chunk).unwrap();
+                }
+                dec.finish().unwrap();
+                assert_eq!(output, input);
+
+                // a truncated stream is rejected
+                let mut output = Vec::new();
+                let mut dec = DecryptWriter::new(&key, segment_len, &mut output).unwrap();
+                dec.buffer_write(&ciphertext[..(ciphertext.len() - 1)])
+                    .unwrap();
+                assert!(dec.finish().is_err());
+            }
+        }
+        test_writer::<C20P>();
+        test_writer::<XC20P>();
+    }
+}
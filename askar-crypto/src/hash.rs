@@ -0,0 +1,245 @@
+//! Streaming hash functions
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+pub use sha2::{Sha256, Sha384, Sha512};
+
+#[cfg(feature = "sha3")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sha3")))]
+pub use sha3::{Sha3_256, Sha3_384, Sha3_512};
+
+use crate::{
+    buffer::{HashBuffer, WriteBuffer},
+    error::Error,
+};
+
+/// Supported hash algorithms
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HashAlg {
+    /// SHA-256
+    Sha256,
+    /// SHA-384
+    Sha384,
+    /// SHA-512
+    Sha512,
+    /// SHA3-256
+    #[cfg(feature = "sha3")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sha3")))]
+    Sha3_256,
+    /// SHA3-384
+    #[cfg(feature = "sha3")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sha3")))]
+    Sha3_384,
+    /// SHA3-512
+    #[cfg(feature = "sha3")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sha3")))]
+    Sha3_512,
+}
+
+impl HashAlg {
+    /// Get the length of the hash output in bytes
+    pub const fn output_size(&self) -> usize {
+        match self {
+            Self::Sha256 => 32,
+            Self::Sha384 => 48,
+            Self::Sha512 => 64,
+            #[cfg(feature = "sha3")]
+            Self::Sha3_256 => 32,
+            #[cfg(feature = "sha3")]
+            Self::Sha3_384 => 48,
+            #[cfg(feature = "sha3")]
+            Self::Sha3_512 => 64,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum HasherInner {
+    Sha256(HashBuffer<Sha256>),
+    Sha384(HashBuffer<Sha384>),
+    Sha512(HashBuffer<Sha512>),
+    #[cfg(feature = "sha3")]
+    Sha3_256(HashBuffer<Sha3_256>),
+    #[cfg(feature = "sha3")]
+    Sha3_384(HashBuffer<Sha3_384>),
+    #[cfg(feature = "sha3")]
+    Sha3_512(HashBuffer<Sha3_512>),
+}
+
+/// A streaming hasher for any supported algorithm.
+///
+/// Input may be written directly or through the `WriteBuffer` trait,
+/// allowing structured data such as JWKs to be hashed without
+/// intermediate allocation.
+#[derive(Debug)]
+pub struct Hasher(HasherInner);
+
+impl Hasher {
+    /// Create a new hasher for the given algorithm
+    pub fn new(alg: HashAlg) -> Self {
+        Self(match alg {
+            HashAlg::Sha256 => HasherInner::Sha256(HashBuffer::new()),
+            HashAlg::Sha384 => HasherInner::Sha384(HashBuffer::new()),
+            HashAlg::Sha512 => HasherInner::Sha512(HashBuffer::new()),
+            #[cfg(feature = "sha3")]
+            HashAlg::Sha3_256 => HasherInner::Sha3_256(HashBuffer::new()),
+            #[cfg(feature = "sha3")]
+            HashAlg::Sha3_384 => HasherInner::Sha3_384(HashBuffer::new()),
+            #[cfg(feature = "sha3")]
+            HashAlg::Sha3_512 => HasherInner::Sha3_512(HashBuffer::new()),
+        })
+    }
+
+    /// Get the hash algorithm in use
+    pub fn alg(&self) -> HashAlg {
+        match &self.0 {
+            HasherInner::Sha256(_) => HashAlg::Sha256,
+            HasherInner::Sha384(_) => HashAlg::Sha384,
+            HasherInner::Sha512(_) => HashAlg::Sha512,
+            #[cfg(feature = "sha3")]
+            HasherInner::Sha3_256(_) => HashAlg::Sha3_256,
+            #[cfg(feature = "sha3")]
+            HasherInner::Sha3_384(_) => HashAlg::Sha3_384,
+            #[cfg(feature = "sha3")]
+            HasherInner::Sha3_512(_) => HashAlg::Sha3_512,
+        }
+    }
+
+    /// Add input to the hasher
+    pub fn update(&mut self, data: &[u8]) {
+        // writing to a HashBuffer cannot fail
+        self.buffer_write(data).unwrap();
+    }
+
+    /// Finalize the hasher and write the output to a buffer
+    pub fn finalize_into(self, out: &mut dyn WriteBuffer) -> Result<(), Error> {
+        match self.0 {
+            HasherInner::Sha256(h) => out.buffer_write(&h.finalize()[..]),
+            HasherInner::Sha384(h) => out.buffer_write(&h.finalize()[..]),
+            HasherInner::Sha512(h) => out.buffer_write(&h.finalize()[..]),
+            #[cfg(feature = "sha3")]
+            HasherInner::Sha3_256(h) => out.buffer_write(&h.finalize()[..]),
+            #[cfg(feature = "sha3")]
+            HasherInner::Sha3_384(h) => out.buffer_write(&h.finalize()[..]),
+            #[cfg(feature = "sha3")]
+            HasherInner::Sha3_512(h) => out.buffer_write(&h.finalize()[..]),
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    /// Finalize the hasher and return the output in a new allocated buffer
+    pub fn finalize(self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.alg().output_size());
+        self.finalize_into(&mut out).unwrap();
+        out
+    }
+}
+
+impl WriteBuffer for Hasher {
+    fn buffer_write(&mut self, data: &[u8]) -> Result<(), Error> {
+        match &mut self.0 {
+            HasherInner::Sha256(h) => h.buffer_write(data),
+            HasherInner::Sha384(h) => h.buffer_write(data),
+            HasherInner::Sha512(h) => h.buffer_write(data),
+            #[cfg(feature = "sha3")]
+            HasherInner::Sha3_256(h) => h.buffer_write(data),
+            #[cfg(feature = "sha3")]
+            HasherInner::Sha3_384(h) => h.buffer_write(data),
+            #[cfg(feature = "sha3")]
+            HasherInner::Sha3_512(h) => h.buffer_write(data),
+        }
+    }
+}
+
+/// Hash a single input, writing the output to a buffer
+pub fn hash_into(alg: HashAlg, data: &[u8], out: &mut dyn WriteBuffer) -> Result<(), Error> {
+    let mut hasher = Hasher::new(alg);
+    hasher.update(data);
+    hasher.finalize_into(out)
+}
+
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+/// Hash a single input and return the output in a new allocated buffer
+pub fn hash(alg: HashAlg, data: &[u8]) -> Vec<u8> {
+    let mut hasher = Hasher::new(alg);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Writer;
+
+    #[test]
+    fn sha2_expected() {
+        let tests: &[(HashAlg, &[u8])] = &[
+            (
+                HashAlg::Sha256,
+                &hex!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            ),
+            (
+                HashAlg::Sha384,
+                &hex!(
+                    "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed
+                    8086072ba1e7cc2358baeca134c825a7"
+                ),
+            ),
+            (
+                HashAlg::Sha512,
+                &hex!(
+                    "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a
+                    2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+                ),
+            ),
+        ];
+        for (alg, expected) in tests {
+            let mut buf = [0u8; 64];
+            let mut out = Writer::from_slice(&mut buf[..]);
+            hash_into(*alg, b"abc", &mut out).unwrap();
+            assert_eq!(out.position(), alg.output_size());
+            assert_eq!(&buf[..alg.output_size()], *expected);
+        }
+    }
+
+    #[cfg(all(feature = "alloc", feature = "sha3"))]
+    #[test]
+    fn sha3_expected() {
+        let tests: &[(HashAlg, &[u8])] = &[
+            (
+                HashAlg::Sha3_256,
+                &hex!("3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"),
+            ),
+            (
+                HashAlg::Sha3_384,
+                &hex!(
+                    "ec01498288516fc926459f58e2c6ad8df9b473cb0fc08c2596da7cf0e49be4b2
+                    98d88cea927ac7f539f1edf228376d25"
+                ),
+            ),
+            (
+                HashAlg::Sha3_512,
+                &hex!(
+                    "b751850b1a57168a5693cd924b6b096e08f621827444f70d884f5d0240d2712e
+                    10e116e9192af3c91a7ec57647e3934057340b4cf408d5a56592f8274eec53f0"
+                ),
+            ),
+        ];
+        for (alg, expected) in tests {
+            assert_eq!(&hash(*alg, b"abc")[..], *expected);
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn streaming_matches_single() {
+        let mut hasher = Hasher::new(HashAlg::Sha256);
+        hasher.update(b"a");
+        hasher.buffer_write(b"bc").unwrap();
+        assert_eq!(hasher.alg(), HashAlg::Sha256);
+        assert_eq!(hasher.finalize(), hash(HashAlg::Sha256, b"abc"));
+    }
+}
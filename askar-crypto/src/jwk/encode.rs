@@ -1,11 +1,5 @@
-use core::fmt::Write;
-
 use super::ops::KeyOpsSet;
-use crate::{
-    alg::KeyAlg,
-    buffer::{WriteBuffer, Writer},
-    error::Error,
-};
+use crate::{alg::KeyAlg, buffer::WriteBuffer, error::Error};
 
 /// Supported modes for JWK encoding
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -77,12 +71,12 @@ impl JwkEncoder<'_> {
         self.start_attr(key)?;
         let buffer = &mut *self.buffer;
         buffer.buffer_write(b"\"")?;
-        write!(
-            Writer::from_buffer(&mut *buffer),
-            "{}",
-            base64::display::Base64Display::with_config(value, base64::URL_SAFE_NO_PAD)
-        )
-        .map_err(|_| err_msg!(Unexpected, "Error writing to JWK buffer"))?;
+        // encode in whole 3-byte groups so that buffer errors are passed through
+        let mut chunk = [0u8; 64];
+        for part in value.chunks(48) {
+            let len = base64::encode_config_slice(part, base64::URL_SAFE_NO_PAD, &mut chunk);
+            buffer.buffer_write(&chunk[..len])?;
+        }
         buffer.buffer_write(b"\"")?;
         Ok(())
     }
@@ -1,16 +1,20 @@
 //! JSON Web Key (JWK) support
+//!
+//! Parsing and encoding are supported without the `alloc` feature, using
+//! borrowed input and caller-provided output buffers.
 
 #[cfg(feature = "alloc")]
 use alloc::{string::String, vec::Vec};
 
 use sha2::Sha256;
+use zeroize::Zeroize;
 
 #[cfg(feature = "alloc")]
 use crate::buffer::SecretBytes;
 use crate::{
     alg::KeyAlg,
-    buffer::{HashBuffer, ResizeBuffer},
-    error::Error,
+    buffer::{HashBuffer, ResizeBuffer, WriteBuffer, Writer},
+    error::{Error, ErrorKind},
 };
 
 mod encode;
@@ -22,6 +26,12 @@ pub use self::ops::{KeyOps, KeyOpsSet};
 mod parts;
 pub use self::parts::JwkParts;
 
+#[cfg(feature = "alloc")]
+mod set;
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+pub use self::set::JwkSet;
+
 /// Support for converting a key into a JWK
 pub trait ToJwk {
     /// Write the JWK representation to an encoder
@@ -74,6 +84,86 @@ pub fn write_jwk_thumbprint<K: ToJwk + ?Sized>(
     Ok(())
 }
 
+/// An upper bound on the encoded length of a JWK for the supported key types,
+/// suitable for sizing a stack buffer when the `alloc` feature is not enabled
+pub const MAX_JWK_LENGTH: usize = 512;
+
+/// Determine the exact length of a key's JWK in the given encoding mode
+pub fn jwk_length<K: ToJwk + ?Sized>(
+    key: &K,
+    alg: Option<KeyAlg>,
+    mode: JwkEncoderMode,
+) -> Result<usize, Error> {
+    let mut counter = LengthCounter(0);
+    let mut buf = JwkEncoder::new(alg, &mut counter, mode)?;
+    key.encode_jwk(&mut buf)?;
+    buf.finalize()?;
+    Ok(counter.0)
+}
+
+/// Encode the JWK of a public key into a fixed-size buffer, returning the
+/// length of the output
+pub fn write_jwk_public<K: ToJwk + ?Sized>(
+    key: &K,
+    alg: Option<KeyAlg>,
+    output: &mut [u8],
+) -> Result<usize, Error> {
+    write_jwk_slice(key, alg, JwkEncoderMode::PublicKey, output)
+}
+
+/// Encode the JWK of a secret key into a fixed-size buffer, returning the
+/// length of the output. The buffer is cleared if the encoding fails
+pub fn write_jwk_secret<K: ToJwk + ?Sized>(key: &K, output: &mut [u8]) -> Result<usize, Error> {
+    write_jwk_slice(key, None, JwkEncoderMode::SecretKey, output).map_err(|err| {
+        output.zeroize();
+        err
+    })
+}
+
+fn write_jwk_slice<K: ToJwk + ?Sized>(
+    key: &K,
+    alg: Option<KeyAlg>,
+    mode: JwkEncoderMode,
+    output: &mut [u8],
+) -> Result<usize, Error> {
+    let mut writer = Writer::from_slice(output);
+    let mut buf = JwkEncoder::new(alg, &mut writer, mode)?;
+    key.encode_jwk(&mut buf)
+        .and_then(|_| buf.finalize())
+        .map_err(|err| match err.kind() {
+            ErrorKind::ExceededBuffer => {
+                err_msg!(ExceededBuffer, "Insufficient space in buffer for JWK")
+            }
+            _ => err,
+        })?;
+    Ok(writer.position())
+}
+
+/// A buffer which only tracks the length of the written data
+#[derive(Debug)]
+struct LengthCounter(usize);
+
+impl WriteBuffer for LengthCounter {
+    fn buffer_write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.0 += data.len();
+        Ok(())
+    }
+}
+
+/// Compare two keys by their JWK thumbprints, which are independent of
+/// key metadata and attribute ordering
+pub fn jwk_thumbprint_eq<A, B>(a: &A, b: &B) -> Result<bool, Error>
+where
+    A: ToJwk + ?Sized,
+    B: ToJwk + ?Sized,
+{
+    let mut a_buf = [0u8; 43];
+    let mut b_buf = [0u8; 43];
+    write_jwk_thumbprint(a, None, &mut Writer::from_slice(&mut a_buf[..]))?;
+    write_jwk_thumbprint(b, None, &mut Writer::from_slice(&mut b_buf[..]))?;
+    Ok(a_buf == b_buf)
+}
+
 /// Support for loading a key instance from a JWK
 pub trait FromJwk: Sized {
     /// Import the key from a JWK string reference
@@ -89,3 +179,43 @@ pub trait FromJwk: Sized {
     /// Import the key from a pre-parsed JWK
     fn from_jwk_parts(jwk: JwkParts<'_>) -> Result<Self, Error>;
 }
+
+#[cfg(all(test, feature = "alloc", feature = "ed25519"))]
+mod tests {
+    use super::*;
+    use crate::{alg::ed25519::Ed25519KeyPair, repr::KeySecretBytes};
+
+    #[test]
+    fn write_jwk_fixed_buffer() {
+        let test_pvt = base64::decode_config(
+            "nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A",
+            base64::URL_SAFE,
+        )
+        .unwrap();
+        let kp = Ed25519KeyPair::from_secret_bytes(&test_pvt).unwrap();
+
+        let mut buf = [0u8; MAX_JWK_LENGTH];
+        let pub_len = write_jwk_public(&kp, None, &mut buf).unwrap();
+        let expected = kp.to_jwk_public(None).unwrap();
+        assert_eq!(&buf[..pub_len], expected.as_bytes());
+        assert_eq!(
+            jwk_length(&kp, None, JwkEncoderMode::PublicKey).unwrap(),
+            pub_len
+        );
+
+        let len = write_jwk_secret(&kp, &mut buf).unwrap();
+        let expected = kp.to_jwk_secret().unwrap();
+        assert_eq!(&buf[..len], expected.as_ref());
+        assert_eq!(
+            jwk_length(&kp, None, JwkEncoderMode::SecretKey).unwrap(),
+            len
+        );
+
+        let mut short = [0u8; 64];
+        let err = write_jwk_secret(&kp, &mut short).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ExceededBuffer);
+        assert_eq!(short, [0u8; 64]);
+        let err = write_jwk_public(&kp, None, &mut buf[..pub_len - 1]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ExceededBuffer);
+    }
+}
@@ -5,6 +5,7 @@ use core::{
 
 #[cfg(feature = "arbitrary")]
 use arbitrary::Arbitrary;
+use serde::ser::{Serialize, SerializeSeq, Serializer};
 
 static OPS: &[KeyOps] = &[
     KeyOps::Encrypt,
@@ -105,6 +106,32 @@ impl KeyOpsSet {
     pub fn is_empty(&self) -> bool {
         self.value == 0
     }
+
+    /// Add an operation to the set
+    pub const fn with(self, op: KeyOps) -> Self {
+        Self {
+            value: self.value | op as usize,
+        }
+    }
+
+    /// Check if the set contains a given operation
+    pub fn contains(&self, op: KeyOps) -> bool {
+        *self & op
+    }
+
+    /// Check if every operation in another set is contained in this set
+    pub fn contains_all(&self, ops: KeyOpsSet) -> bool {
+        self.value & ops.value == ops.value
+    }
+
+    /// Get the operation set corresponding to a JWK `use` value
+    pub fn from_use(key_use: &str) -> Option<Self> {
+        match key_use {
+            "enc" => Some(KeyOps::Encrypt | KeyOps::Decrypt | KeyOps::WrapKey | KeyOps::UnwrapKey),
+            "sig" => Some(KeyOps::Sign | KeyOps::Verify),
+            _ => None,
+        }
+    }
 }
 
 impl Default for KeyOpsSet {
@@ -159,6 +186,19 @@ impl Debug for KeyOpsSet {
     }
 }
 
+impl Serialize for KeyOpsSet {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(None)?;
+        for op in self {
+            seq.serialize_element(op.as_str())?;
+        }
+        seq.end()
+    }
+}
+
 impl From<KeyOps> for KeyOpsSet {
     fn from(op: KeyOps) -> Self {
         Self { value: op as usize }
@@ -219,6 +259,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn builder() {
+        let ops = KeyOpsSet::new().with(KeyOps::Sign).with(KeyOps::Verify);
+        assert_eq!(ops, KeyOps::Sign | KeyOps::Verify);
+        assert!(ops.contains(KeyOps::Sign));
+        assert!(!ops.contains(KeyOps::Encrypt));
+        assert!(ops.contains_all(KeyOps::Verify.into()));
+        assert!(!ops.contains_all(KeyOps::Verify | KeyOps::Decrypt));
+        assert_eq!(KeyOpsSet::from_use("sig"), Some(ops));
+        assert_eq!(KeyOpsSet::from_use("other"), None);
+    }
+
     #[test]
     fn debug_format() {
         assert_eq!(
@@ -5,12 +5,19 @@ use core::{
 
 #[cfg(feature = "arbitrary")]
 use arbitrary::Arbitrary;
-use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::de::{Deserialize, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
 
-use super::ops::{KeyOps, KeyOpsSet};
+use super::{
+    encode::JwkEncoder,
+    ops::{KeyOps, KeyOpsSet},
+    ToJwk,
+};
 use crate::error::Error;
 
-/// A parsed JWK
+/// A parsed JWK.
+///
+/// Parsing does not allocate: all attributes are borrowed from the input,
+/// and may be decoded into fixed-size buffers using `OptAttr::decode_base64`.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub struct JwkParts<'a> {
@@ -18,6 +25,8 @@ pub struct JwkParts<'a> {
     pub kty: &'a str,
     /// Key ID
     pub kid: OptAttr<'a>,
+    /// Intended algorithm
+    pub alg: OptAttr<'a>,
     /// Curve type
     pub crv: OptAttr<'a>,
     /// Curve key public x coordinate
@@ -46,6 +55,54 @@ impl<'de> JwkParts<'de> {
             serde_json_core::from_slice(jwk).map_err(err_map!(InvalidData, "Error parsing JWK"))?;
         Ok(parts)
     }
+
+    /// Compare the key material of two JWKs (`kty`, `crv`, `x`, `y` and `k`),
+    /// ignoring metadata such as `kid` and `key_ops` as well as differences
+    /// in base64 padding. The private key component is not compared, so a
+    /// public JWK is considered equal to the secret JWK of the same key.
+    pub fn canonical_eq(&self, other: &JwkParts<'_>) -> bool {
+        self.kty == other.kty
+            && self.crv == other.crv
+            && self.x.base64_eq(&other.x)
+            && self.y.base64_eq(&other.y)
+            && self.k.base64_eq(&other.k)
+    }
+}
+
+impl ToJwk for JwkParts<'_> {
+    fn encode_jwk(&self, enc: &mut JwkEncoder<'_>) -> Result<(), Error> {
+        fn attr<'a>(attr: &OptAttr<'a>) -> Result<&'a str, Error> {
+            attr.to_option()
+                .map(|s| s.trim_end_matches('='))
+                .ok_or_else(|| err_msg!(InvalidKeyData, "Missing JWK attribute"))
+        }
+
+        // the thumbprint members are written in lexicographic order (RFC 7638)
+        match self.kty {
+            "OKP" => {
+                enc.add_str("crv", attr(&self.crv)?)?;
+                enc.add_str("kty", self.kty)?;
+                enc.add_str("x", attr(&self.x)?)?;
+            }
+            "EC" => {
+                enc.add_str("crv", attr(&self.crv)?)?;
+                enc.add_str("kty", self.kty)?;
+                enc.add_str("x", attr(&self.x)?)?;
+                enc.add_str("y", attr(&self.y)?)?;
+            }
+            "oct" if !enc.is_public() => {
+                enc.add_str("k", attr(&self.k)?)?;
+                enc.add_str("kty", self.kty)?;
+            }
+            _ => return Err(err_msg!(Unsupported, "Unsupported JWK key type")),
+        }
+        if enc.is_secret() && self.kty != "oct" {
+            if let Some(d) = self.d.to_option() {
+                enc.add_str("d", d.trim_end_matches('='))?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Copy, Clone, Default, PartialEq, Eq)]
@@ -53,7 +110,7 @@ impl<'de> JwkParts<'de> {
 #[repr(transparent)]
 pub struct OptAttr<'a>(Option<&'a str>);
 
-impl OptAttr<'_> {
+impl<'a> OptAttr<'a> {
     pub fn is_none(&self) -> bool {
         self.0.is_none()
     }
@@ -62,10 +119,18 @@ impl OptAttr<'_> {
         self.0.is_some()
     }
 
-    pub fn to_option(&self) -> Option<&str> {
+    pub fn to_option(self) -> Option<&'a str> {
         self.0
     }
 
+    pub fn base64_eq(&self, other: &OptAttr<'_>) -> bool {
+        match (self.0, other.0) {
+            (None, None) => true,
+            (Some(a), Some(b)) => a.trim_end_matches('=') == b.trim_end_matches('='),
+            _ => false,
+        }
+    }
+
     pub fn decode_base64(&self, output: &mut [u8]) -> Result<usize, Error> {
         if let Some(s) = self.0 {
             let max_input = (output.len() * 4 + 2) / 3; // ceil(4*n/3)
@@ -138,6 +203,7 @@ impl<'de> Visitor<'de> for JwkMapVisitor<'de> {
     {
         let mut kty = None;
         let mut kid = None;
+        let mut alg = None;
         let mut crv = None;
         let mut x = None;
         let mut y = None;
@@ -149,25 +215,22 @@ impl<'de> Visitor<'de> for JwkMapVisitor<'de> {
             match key {
                 "kty" => kty = Some(access.next_value()?),
                 "kid" => kid = Some(access.next_value()?),
+                "alg" => alg = Some(access.next_value()?),
                 "crv" => crv = Some(access.next_value()?),
                 "x" => x = Some(access.next_value()?),
                 "y" => y = Some(access.next_value()?),
                 "d" => d = Some(access.next_value()?),
                 "k" => k = Some(access.next_value()?),
                 "use" => {
-                    let ops = match access.next_value()? {
-                        "enc" => {
-                            KeyOps::Encrypt | KeyOps::Decrypt | KeyOps::WrapKey | KeyOps::UnwrapKey
-                        }
-                        "sig" => KeyOps::Sign | KeyOps::Verify,
-                        _ => KeyOpsSet::new(),
-                    };
-                    if !ops.is_empty() {
+                    if let Some(ops) = KeyOpsSet::from_use(access.next_value()?) {
                         key_ops = Some(key_ops.unwrap_or_default() | ops);
                     }
                 }
                 "key_ops" => key_ops = Some(access.next_value()?),
-                _ => (),
+                _ => {
+                    // skip unrecognized members without allocating
+                    access.next_value::<IgnoredAny>()?;
+                }
             }
         }
 
@@ -175,6 +238,7 @@ impl<'de> Visitor<'de> for JwkMapVisitor<'de> {
             Ok(JwkParts {
                 kty,
                 kid: kid.into(),
+                alg: alg.into(),
                 crv: crv.into(),
                 x: x.into(),
                 y: y.into(),
@@ -260,4 +324,60 @@ mod tests {
         assert_eq!(parts.k, None);
         assert_eq!(parts.key_ops, Some(KeyOps::Sign | KeyOps::Verify));
     }
+
+    #[test]
+    fn canonical_eq_ignores_metadata() {
+        let public = JwkParts::from_str(
+            r#"{"kty":"OKP","crv":"Ed25519","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}"#,
+        )
+        .unwrap();
+        let secret = JwkParts::from_str(
+            r#"{
+                "kid": "key-1",
+                "x": "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo=",
+                "crv": "Ed25519",
+                "kty": "OKP",
+                "d": "nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A",
+                "key_ops": ["sign"]
+            }"#,
+        )
+        .unwrap();
+        assert_ne!(public, secret);
+        assert!(public.canonical_eq(&secret));
+        assert!(secret.canonical_eq(&public));
+
+        let other = JwkParts::from_str(
+            r#"{"kty":"OKP","crv":"X25519","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}"#,
+        )
+        .unwrap();
+        assert!(!public.canonical_eq(&other));
+    }
+
+    #[test]
+    fn parse_ignore_unknown_members() {
+        let jwk = br#"{
+            "kty": "OKP",
+            "alg": "EdDSA",
+            "ext": true,
+            "x5c": ["MIIB", "MIIC"],
+            "extra": {"nested": [1, 2]},
+            "crv": "Ed25519",
+            "x": "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo",
+            "use": "sig"
+        }"#;
+        let parts = JwkParts::from_slice(jwk).unwrap();
+        assert_eq!(parts.kty, "OKP");
+        assert_eq!(parts.alg, Some("EdDSA"));
+        assert_eq!(parts.crv, Some("Ed25519"));
+        assert_eq!(parts.key_ops, Some(KeyOps::Sign | KeyOps::Verify));
+
+        let mut pk = [0u8; 32];
+        assert_eq!(parts.x.decode_base64(&mut pk).unwrap(), 32);
+        assert_eq!(
+            pk,
+            hex!("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a")
+        );
+        let mut short = [0u8; 16];
+        assert!(parts.x.decode_base64(&mut short).is_err());
+    }
 }
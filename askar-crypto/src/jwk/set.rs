@@ -0,0 +1,294 @@
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::{fmt, marker::PhantomData};
+
+use serde::de::{Deserialize, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+
+use super::{ops::KeyOps, parts::JwkParts, ToJwk};
+use crate::{error::Error, sign::SignatureType};
+
+/// A parsed JWK set (JWKS) document.
+///
+/// The keys are borrowed from the input document and indexed by their key
+/// identifier (`kid`) and JWK thumbprint for lookup.
+#[derive(Clone, Debug)]
+pub struct JwkSet<'a> {
+    keys: Vec<JwkParts<'a>>,
+    thumbprints: Vec<Option<String>>,
+    kids: BTreeMap<&'a str, usize>,
+}
+
+impl<'a> JwkSet<'a> {
+    /// Parse a JWK set from a string reference
+    pub fn from_str(jwks: &'a str) -> Result<Self, Error> {
+        let (keys, _read) = serde_json_core::from_str::<KeyList<'a>>(jwks)
+            .map_err(err_map!(InvalidData, "Error parsing JWK set"))?;
+        Self::from_keys(keys.0)
+    }
+
+    /// Parse a JWK set from a byte slice
+    pub fn from_slice(jwks: &'a [u8]) -> Result<Self, Error> {
+        let (keys, _read) = serde_json_core::from_slice::<KeyList<'a>>(jwks)
+            .map_err(err_map!(InvalidData, "Error parsing JWK set"))?;
+        Self::from_keys(keys.0)
+    }
+
+    /// Create a JWK set from a list of parsed JWKs
+    pub fn from_keys(keys: Vec<JwkParts<'a>>) -> Result<Self, Error> {
+        let mut kids = BTreeMap::new();
+        let mut thumbprints = Vec::with_capacity(keys.len());
+        for (idx, key) in keys.iter().enumerate() {
+            if let Some(kid) = key.kid.to_option() {
+                if kids.insert(kid, idx).is_some() {
+                    return Err(err_msg!(InvalidData, "Duplicate key ID in JWK set"));
+                }
+            }
+            // keys of unsupported types cannot be indexed by thumbprint
+            thumbprints.push(key.to_jwk_thumbprint(None).ok());
+        }
+        Ok(Self {
+            keys,
+            thumbprints,
+            kids,
+        })
+    }
+
+    /// Accessor for the keys in the set
+    pub fn keys(&self) -> &[JwkParts<'a>] {
+        &self.keys
+    }
+
+    /// Get the number of keys in the set
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Check if the set contains no keys
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Find a key by its key identifier
+    pub fn find_kid(&self, kid: &str) -> Option<&JwkParts<'a>> {
+        self.kids.get(kid).map(|idx| &self.keys[*idx])
+    }
+
+    /// Find a key by its JWK thumbprint
+    pub fn find_thumbprint(&self, thumbprint: &str) -> Option<&JwkParts<'a>> {
+        self.thumbprints
+            .iter()
+            .position(|t| t.as_deref() == Some(thumbprint))
+            .map(|idx| &self.keys[idx])
+    }
+
+    /// Select the keys which may be used to verify signatures of the given
+    /// type. Keys declaring a different `alg`, or operations which exclude
+    /// verification, are skipped.
+    pub fn verification_keys(
+        &self,
+        sig_type: SignatureType,
+    ) -> impl Iterator<Item = &JwkParts<'a>> + '_ {
+        self.keys
+            .iter()
+            .filter(move |key| verifies_with(key, sig_type))
+    }
+
+    /// Select a verification key for a signature, using the `kid` from the
+    /// signature header if provided. Without a `kid`, a key is only selected
+    /// when exactly one key in the set is suitable.
+    pub fn select_verification_key(
+        &self,
+        sig_type: SignatureType,
+        kid: Option<&str>,
+    ) -> Result<&JwkParts<'a>, Error> {
+        if let Some(kid) = kid {
+            match self.find_kid(kid) {
+                Some(key) if verifies_with(key, sig_type) => Ok(key),
+                Some(_) => Err(err_msg!(
+                    Unsupported,
+                    "Key is not usable for the signature algorithm"
+                )),
+                None => Err(err_msg!(InvalidKeyData, "Key not found in JWK set")),
+            }
+        } else {
+            let mut keys = self.verification_keys(sig_type);
+            match (keys.next(), keys.next()) {
+                (Some(key), None) => Ok(key),
+                (None, _) => Err(err_msg!(InvalidKeyData, "No suitable key in JWK set")),
+                _ => Err(err_msg!(
+                    Usage,
+                    "Multiple suitable keys in JWK set, a key ID is required"
+                )),
+            }
+        }
+    }
+}
+
+fn verifies_with(key: &JwkParts<'_>, sig_type: SignatureType) -> bool {
+    if matches!(key.key_ops, Some(ops) if !ops.contains(KeyOps::Verify)) {
+        return false;
+    }
+    if let Some(alg) = key.alg.to_option() {
+        if alg.parse::<SignatureType>().ok() != Some(sig_type) {
+            return false;
+        }
+    }
+    let (kty, crv) = match sig_type {
+        SignatureType::EdDSA => ("OKP", "Ed25519"),
+        SignatureType::ES256 => ("EC", "P-256"),
+        SignatureType::ES256K => ("EC", "secp256k1"),
+    };
+    key.kty == kty && key.crv == crv
+}
+
+struct KeyList<'a>(Vec<JwkParts<'a>>);
+
+struct KeyListVisitor<'de>(PhantomData<&'de ()>);
+
+impl<'de> Visitor<'de> for KeyListVisitor<'de> {
+    type Value = KeyList<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("an object representing a JWK set")
+    }
+
+    fn visit_map<M>(self, mut access: M) -> Result<Self::Value, M::Error>
+    where
+        M: MapAccess<'de>,
+    {
+        let mut keys = None;
+        while let Some(key) = access.next_key::<&str>()? {
+            match key {
+                "keys" => keys = Some(access.next_value::<KeySeq<'de>>()?.0),
+                _ => {
+                    access.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        keys.map(KeyList)
+            .ok_or_else(|| serde::de::Error::missing_field("keys"))
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyList<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(KeyListVisitor(PhantomData))
+    }
+}
+
+struct KeySeq<'a>(Vec<JwkParts<'a>>);
+
+struct KeySeqVisitor<'de>(PhantomData<&'de ()>);
+
+impl<'de> Visitor<'de> for KeySeqVisitor<'de> {
+    type Value = KeySeq<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("an array of JWKs")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut keys = Vec::new();
+        while let Some(key) = seq.next_element()? {
+            keys.push(key);
+        }
+        Ok(KeySeq(keys))
+    }
+}
+
+impl<'de> Deserialize<'de> for KeySeq<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(KeySeqVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JWKS: &str = r#"{
+        "keys": [
+            {
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "kid": "ed-1",
+                "alg": "EdDSA",
+                "use": "sig",
+                "x": "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"
+            },
+            {
+                "kty": "EC",
+                "crv": "P-256",
+                "kid": "p256-1",
+                "x": "f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU",
+                "y": "x_FEzRu9m36HLN_tue659LNpXW6pCyStikYjKIWI5a0"
+            },
+            {
+                "kty": "EC",
+                "crv": "P-256",
+                "kid": "p256-enc",
+                "use": "enc",
+                "x": "f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU",
+                "y": "x_FEzRu9m36HLN_tue659LNpXW6pCyStikYjKIWI5a0"
+            },
+            {
+                "kty": "RSA",
+                "kid": "rsa-1",
+                "n": "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4",
+                "e": "AQAB"
+            }
+        ],
+        "issuer": "https://example.com"
+    }"#;
+
+    #[test]
+    fn parse_and_index() {
+        let jwks = JwkSet::from_str(JWKS).unwrap();
+        assert_eq!(jwks.len(), 4);
+        assert_eq!(jwks.find_kid("p256-1").unwrap().crv, Some("P-256"));
+        assert!(jwks.find_kid("missing").is_none());
+
+        // thumbprint from RFC 8037 appendix A.3
+        let key = jwks
+            .find_thumbprint("kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k")
+            .unwrap();
+        assert_eq!(key.kid, Some("ed-1"));
+    }
+
+    #[test]
+    fn select_verification_keys() {
+        let jwks = JwkSet::from_str(JWKS).unwrap();
+        let ed = jwks
+            .select_verification_key(SignatureType::EdDSA, None)
+            .unwrap();
+        assert_eq!(ed.kid, Some("ed-1"));
+
+        // the encryption key is excluded
+        let es: Vec<_> = jwks.verification_keys(SignatureType::ES256).collect();
+        assert_eq!(es.len(), 1);
+        assert_eq!(es[0].kid, Some("p256-1"));
+        assert!(jwks
+            .select_verification_key(SignatureType::ES256, Some("p256-enc"))
+            .is_err());
+        assert!(jwks
+            .select_verification_key(SignatureType::ES256K, None)
+            .is_err());
+    }
+
+    #[test]
+    fn reject_duplicate_kid() {
+        let jwks = r#"{"keys": [
+            {"kty": "OKP", "crv": "Ed25519", "kid": "a", "x": "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"},
+            {"kty": "OKP", "crv": "X25519", "kid": "a", "x": "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}
+        ]}"#;
+        assert!(JwkSet::from_str(jwks).is_err());
+    }
+}
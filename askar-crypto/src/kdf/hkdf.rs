@@ -0,0 +1,95 @@
+//! HKDF from RFC 5869
+
+use core::marker::PhantomData;
+
+use digest::{BlockInput, FixedOutput, Reset, Update};
+use sha2::{Sha256, Sha512};
+
+use super::KeyDerivation;
+use crate::{error::Error, generic_array::ArrayLength};
+
+/// HKDF using SHA-256
+pub type HkdfSha256<'d> = Hkdf<'d, Sha256>;
+
+/// HKDF using SHA-512
+pub type HkdfSha512<'d> = Hkdf<'d, Sha512>;
+
+/// An instantiation of the HKDF key derivation for a particular hash function
+#[derive(Debug)]
+pub struct Hkdf<'d, H> {
+    ikm: &'d [u8],
+    salt: Option<&'d [u8]>,
+    info: &'d [u8],
+    _pd: PhantomData<H>,
+}
+
+impl<'d, H> Hkdf<'d, H>
+where
+    H: Update + BlockInput + FixedOutput + Reset + Default + Clone,
+    H::BlockSize: ArrayLength<u8>,
+    H::OutputSize: ArrayLength<u8>,
+{
+    /// Create a new KDF instance
+    pub fn new(ikm: &'d [u8], salt: Option<&'d [u8]>, info: &'d [u8]) -> Self {
+        Self {
+            ikm,
+            salt,
+            info,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Perform the key derivation and write the result to the provided buffer
+    pub fn derive_key(
+        ikm: &[u8],
+        salt: Option<&[u8]>,
+        info: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        ::hkdf::Hkdf::<H>::new(salt, ikm)
+            .expand(info, output)
+            .map_err(|_| err_msg!(Usage, "Exceeded max output size for HKDF"))
+    }
+}
+
+impl<H> KeyDerivation for Hkdf<'_, H>
+where
+    H: Update + BlockInput + FixedOutput + Reset + Default + Clone,
+    H::BlockSize: ArrayLength<u8>,
+    H::OutputSize: ArrayLength<u8>,
+{
+    fn derive_key_bytes(&mut self, key_output: &mut [u8]) -> Result<(), Error> {
+        Self::derive_key(self.ikm, self.salt, self.info, key_output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // RFC 5869 test case 1
+    fn expected_sha256_output() {
+        let ikm = [0x0bu8; 22];
+        let salt = hex!("000102030405060708090a0b0c");
+        let info = hex!("f0f1f2f3f4f5f6f7f8f9");
+        let mut output = [0u8; 42];
+        HkdfSha256::new(&ikm, Some(&salt), &info)
+            .derive_key_bytes(&mut output)
+            .unwrap();
+        assert_eq!(
+            output[..],
+            hex!(
+                "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf
+                34007208d5b887185865"
+            )[..]
+        );
+    }
+
+    #[test]
+    fn exceeded_output_size() {
+        let mut output = [0u8; 255 * 64 + 1];
+        assert!(HkdfSha512::derive_key(b"ikm", None, b"info", &mut output).is_err());
+        assert!(HkdfSha512::derive_key(b"ikm", None, b"info", &mut output[1..]).is_ok());
+    }
+}
@@ -14,6 +14,8 @@ pub mod ecdh_1pu;
 
 pub mod ecdh_es;
 
+pub mod hkdf;
+
 /// Trait for keys supporting Diffie-Helman key exchange
 pub trait KeyExchange<Rhs: ?Sized = Self> {
     /// Perform a key exchange, writing the result to the provided buffer.
@@ -33,10 +33,14 @@ pub mod buffer;
 
 pub mod encrypt;
 
+pub mod hash;
+
 pub mod jwk;
 
 pub mod kdf;
 
+pub mod pkcs8;
+
 pub mod random;
 
 pub mod sign;
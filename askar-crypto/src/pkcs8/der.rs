@@ -0,0 +1,273 @@
+//! Minimal DER support for the fixed structures used by PKCS#8 and SPKI
+
+use crate::{buffer::WriteBuffer, error::Error};
+
+pub(crate) const TAG_INTEGER: u8 = 0x02;
+pub(crate) const TAG_BIT_STRING: u8 = 0x03;
+pub(crate) const TAG_OCTET_STRING: u8 = 0x04;
+pub(crate) const TAG_NULL: u8 = 0x05;
+pub(crate) const TAG_OID: u8 = 0x06;
+pub(crate) const TAG_SEQUENCE: u8 = 0x30;
+pub(crate) const TAG_CONTEXT_0: u8 = 0xa0;
+pub(crate) const TAG_CONTEXT_1: u8 = 0xa1;
+// [1] IMPLICIT BIT STRING, used for the public key in OneAsymmetricKey
+const TAG_CONTEXT_1_PRIMITIVE: u8 = 0x81;
+
+/// Object identifier for Ed25519 keys (1.3.101.112)
+pub(crate) const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+/// Object identifier for X25519 keys (1.3.101.110)
+pub(crate) const OID_X25519: &[u8] = &[0x2b, 0x65, 0x6e];
+/// Object identifier for elliptic curve public keys (1.2.840.10045.2.1)
+pub(crate) const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+/// Object identifier for the P-256 curve (1.2.840.10045.3.1.7)
+pub(crate) const OID_P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+
+/// The algorithm identifier of an encoded key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct AlgorithmId<'a> {
+    pub oid: &'a [u8],
+    pub curve: Option<&'a [u8]>,
+}
+
+impl<'a> AlgorithmId<'a> {
+    pub const fn new(oid: &'a [u8], curve: Option<&'a [u8]>) -> Self {
+        Self { oid, curve }
+    }
+
+    fn encoded_len(&self) -> usize {
+        tlv_len(self.content_len())
+    }
+
+    fn content_len(&self) -> usize {
+        tlv_len(self.oid.len()) + self.curve.map(|c| tlv_len(c.len())).unwrap_or(0)
+    }
+
+    fn write(&self, out: &mut dyn WriteBuffer) -> Result<(), Error> {
+        write_header(out, TAG_SEQUENCE, self.content_len())?;
+        write_tlv(out, TAG_OID, self.oid)?;
+        if let Some(curve) = self.curve {
+            write_tlv(out, TAG_OID, curve)?;
+        }
+        Ok(())
+    }
+
+    fn read(reader: &mut DerReader<'a>) -> Result<Self, Error> {
+        let mut seq = DerReader::new(reader.read(TAG_SEQUENCE)?);
+        let oid = seq.read(TAG_OID)?;
+        let curve = match seq.peek_tag() {
+            Some(TAG_OID) => Some(seq.read(TAG_OID)?),
+            Some(TAG_NULL) => {
+                seq.read(TAG_NULL)?;
+                None
+            }
+            _ => None,
+        };
+        seq.finish()?;
+        Ok(Self { oid, curve })
+    }
+}
+
+/// A decoded PKCS#8 PrivateKeyInfo (or OneAsymmetricKey) structure
+#[derive(Debug)]
+pub(crate) struct PrivateKeyInfo<'a> {
+    pub alg: AlgorithmId<'a>,
+    /// The contents of the private key octet string
+    pub private_key: &'a [u8],
+    /// The optional public key included in a version 2 structure
+    pub public_key: Option<&'a [u8]>,
+}
+
+impl<'a> PrivateKeyInfo<'a> {
+    pub fn decode(der: &'a [u8]) -> Result<Self, Error> {
+        let mut outer = DerReader::new(der);
+        let mut seq = DerReader::new(outer.read(TAG_SEQUENCE)?);
+        outer.finish()?;
+        match seq.read(TAG_INTEGER)? {
+            [0] | [1] => (),
+            _ => return Err(err_msg!(Unsupported, "Unsupported PKCS#8 version")),
+        }
+        let alg = AlgorithmId::read(&mut seq)?;
+        let private_key = seq.read(TAG_OCTET_STRING)?;
+        if seq.peek_tag() == Some(TAG_CONTEXT_0) {
+            // attributes are ignored
+            seq.read(TAG_CONTEXT_0)?;
+        }
+        let public_key = if seq.peek_tag() == Some(TAG_CONTEXT_1_PRIMITIVE) {
+            Some(bit_string_contents(seq.read(TAG_CONTEXT_1_PRIMITIVE)?)?)
+        } else {
+            None
+        };
+        seq.finish()?;
+        Ok(Self {
+            alg,
+            private_key,
+            public_key,
+        })
+    }
+}
+
+/// A decoded SubjectPublicKeyInfo structure
+#[derive(Debug)]
+pub(crate) struct PublicKeyInfo<'a> {
+    pub alg: AlgorithmId<'a>,
+    pub public_key: &'a [u8],
+}
+
+impl<'a> PublicKeyInfo<'a> {
+    pub fn decode(der: &'a [u8]) -> Result<Self, Error> {
+        let mut outer = DerReader::new(der);
+        let mut seq = DerReader::new(outer.read(TAG_SEQUENCE)?);
+        outer.finish()?;
+        let alg = AlgorithmId::read(&mut seq)?;
+        let public_key = bit_string_contents(seq.read(TAG_BIT_STRING)?)?;
+        seq.finish()?;
+        Ok(Self { alg, public_key })
+    }
+}
+
+/// Write a version 1 PKCS#8 PrivateKeyInfo structure. The private key
+/// octet string contents are produced by `write_key`.
+pub(crate) fn write_private_key_info(
+    out: &mut dyn WriteBuffer,
+    alg: AlgorithmId<'_>,
+    key_len: usize,
+    write_key: impl FnOnce(&mut dyn WriteBuffer) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let content_len = tlv_len(1) + alg.encoded_len() + tlv_len(key_len);
+    write_header(out, TAG_SEQUENCE, content_len)?;
+    write_tlv(out, TAG_INTEGER, &[0])?;
+    alg.write(out)?;
+    write_header(out, TAG_OCTET_STRING, key_len)?;
+    write_key(out)
+}
+
+/// Write a SubjectPublicKeyInfo structure
+pub(crate) fn write_public_key_info(
+    out: &mut dyn WriteBuffer,
+    alg: AlgorithmId<'_>,
+    public_key: &[u8],
+) -> Result<(), Error> {
+    let content_len = alg.encoded_len() + tlv_len(public_key.len() + 1);
+    write_header(out, TAG_SEQUENCE, content_len)?;
+    alg.write(out)?;
+    write_bit_string(out, public_key)
+}
+
+/// Write a bit string with no unused bits
+pub(crate) fn write_bit_string(out: &mut dyn WriteBuffer, data: &[u8]) -> Result<(), Error> {
+    write_header(out, TAG_BIT_STRING, data.len() + 1)?;
+    out.buffer_write(&[0])?;
+    out.buffer_write(data)
+}
+
+/// Write a complete tag-length-value element
+pub(crate) fn write_tlv(out: &mut dyn WriteBuffer, tag: u8, data: &[u8]) -> Result<(), Error> {
+    write_header(out, tag, data.len())?;
+    out.buffer_write(data)
+}
+
+/// Write the tag and length of an element
+pub(crate) fn write_header(out: &mut dyn WriteBuffer, tag: u8, len: usize) -> Result<(), Error> {
+    if len < 0x80 {
+        out.buffer_write(&[tag, len as u8])
+    } else if len <= 0xff {
+        out.buffer_write(&[tag, 0x81, len as u8])
+    } else if len <= 0xffff {
+        out.buffer_write(&[tag, 0x82, (len >> 8) as u8, len as u8])
+    } else {
+        Err(err_msg!(Unsupported, "DER element too large"))
+    }
+}
+
+/// The encoded length of an element with the given content length
+pub(crate) const fn tlv_len(len: usize) -> usize {
+    if len < 0x80 {
+        2 + len
+    } else if len <= 0xff {
+        3 + len
+    } else {
+        4 + len
+    }
+}
+
+fn bit_string_contents(data: &[u8]) -> Result<&[u8], Error> {
+    match data.split_first() {
+        Some((0, rest)) => Ok(rest),
+        _ => Err(err_msg!(InvalidData, "Unsupported DER bit string")),
+    }
+}
+
+/// A reader over a sequence of DER elements
+#[derive(Debug)]
+pub(crate) struct DerReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> DerReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Read the contents of the next element, which must have the expected tag
+    pub fn read(&mut self, tag: u8) -> Result<&'a [u8], Error> {
+        let data = self.data;
+        if data.len() < 2 || data[0] != tag {
+            return Err(err_msg!(InvalidData, "Unexpected DER element"));
+        }
+        let (len, start) = match data[1] {
+            len if len < 0x80 => (len as usize, 2),
+            0x81 if data.len() > 2 => (data[2] as usize, 3),
+            0x82 if data.len() > 3 => (((data[2] as usize) << 8) | data[3] as usize, 4),
+            _ => return Err(err_msg!(InvalidData, "Invalid DER element length")),
+        };
+        let end = start + len;
+        if end > data.len() {
+            return Err(err_msg!(InvalidData, "Truncated DER element"));
+        }
+        self.data = &data[end..];
+        Ok(&data[start..end])
+    }
+
+    /// Ensure that all elements have been consumed
+    pub fn finish(self) -> Result<(), Error> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(err_msg!(InvalidData, "Unexpected trailing DER data"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_lengths() {
+        let mut buf = [0u8; 8];
+        for (len, expect) in &[
+            (0x10usize, &[0x30, 0x10][..]),
+            (0x87, &[0x30, 0x81, 0x87][..]),
+            (0x0123, &[0x30, 0x82, 0x01, 0x23][..]),
+        ] {
+            let mut w = crate::buffer::Writer::from_slice(&mut buf[..]);
+            write_header(&mut w, TAG_SEQUENCE, *len).unwrap();
+            let pos = w.position();
+            assert_eq!(&buf[..pos], *expect);
+            assert_eq!(tlv_len(*len), pos + *len);
+        }
+    }
+
+    #[test]
+    fn reader_rejects_truncated() {
+        let mut reader = DerReader::new(&[0x04, 0x05, 0x00]);
+        assert!(reader.read(TAG_OCTET_STRING).is_err());
+        let mut reader = DerReader::new(&[0x04, 0x01, 0x00, 0x00]);
+        assert_eq!(reader.read(TAG_OCTET_STRING).unwrap(), &[0x00]);
+        assert!(reader.finish().is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! PKCS#8 and SubjectPublicKeyInfo (SPKI) support
+//!
+//! Private keys are encoded as unencrypted PKCS#8 `PrivateKeyInfo` structures
+//! and public keys as X.509 `SubjectPublicKeyInfo` structures, in DER or PEM
+//! form, for exchange with OpenSSL-based systems. DER encoding is supported
+//! without the `alloc` feature using caller-provided output buffers.
+
+#[cfg(feature = "alloc")]
+use alloc::{string::String, vec::Vec};
+
+#[cfg(feature = "alloc")]
+use crate::buffer::{ResizeBuffer, SecretBytes};
+use crate::{buffer::WriteBuffer, error::Error};
+
+pub(crate) mod der;
+use self::der::{
+    tlv_len, write_private_key_info, write_tlv, AlgorithmId, DerReader, PrivateKeyInfo,
+    PublicKeyInfo, TAG_OCTET_STRING,
+};
+
+/// The PEM label of a PKCS#8 private key
+pub const PEM_LABEL_PRIVATE_KEY: &'static str = "PRIVATE KEY";
+/// The PEM label of a SubjectPublicKeyInfo public key
+pub const PEM_LABEL_PUBLIC_KEY: &'static str = "PUBLIC KEY";
+
+/// Support for converting a key into PKCS#8 and SPKI encodings
+pub trait ToPkcs8 {
+    /// Write the PKCS#8 DER encoding of the private key to a buffer
+    fn write_pkcs8_der(&self, out: &mut dyn WriteBuffer) -> Result<(), Error>;
+
+    /// Write the SPKI DER encoding of the public key to a buffer
+    fn write_spki_der(&self, out: &mut dyn WriteBuffer) -> Result<(), Error>;
+
+    /// Create the PKCS#8 DER encoding of the private key
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    fn to_pkcs8_der(&self) -> Result<SecretBytes, Error> {
+        let mut buf = SecretBytes::with_capacity(160);
+        self.write_pkcs8_der(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Create the PKCS#8 PEM encoding of the private key
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    fn to_pkcs8_pem(&self) -> Result<SecretBytes, Error> {
+        let der = self.to_pkcs8_der()?;
+        let mut buf = SecretBytes::with_capacity(256);
+        write_pem(PEM_LABEL_PRIVATE_KEY, der.as_ref(), &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Create the SPKI DER encoding of the public key
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    fn to_spki_der(&self) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::with_capacity(96);
+        self.write_spki_der(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Create the SPKI PEM encoding of the public key
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    fn to_spki_pem(&self) -> Result<String, Error> {
+        let der = self.to_spki_der()?;
+        let mut buf = Vec::with_capacity(192);
+        write_pem(PEM_LABEL_PUBLIC_KEY, &der, &mut buf)?;
+        Ok(String::from_utf8(buf).unwrap())
+    }
+}
+
+/// Support for loading a key from PKCS#8 and SPKI encodings
+pub trait FromPkcs8: Sized {
+    /// Import a keypair from a PKCS#8 DER encoding
+    fn from_pkcs8_der(der: &[u8]) -> Result<Self, Error>;
+
+    /// Import a public key from an SPKI DER encoding
+    fn from_spki_der(der: &[u8]) -> Result<Self, Error>;
+
+    /// Import a keypair from a PKCS#8 PEM encoding
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    fn from_pkcs8_pem(pem: &str) -> Result<Self, Error> {
+        let der = decode_pem(PEM_LABEL_PRIVATE_KEY, pem)?;
+        Self::from_pkcs8_der(der.as_ref())
+    }
+
+    /// Import a public key from an SPKI PEM encoding
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    fn from_spki_pem(pem: &str) -> Result<Self, Error> {
+        let der = decode_pem(PEM_LABEL_PUBLIC_KEY, pem)?;
+        Self::from_spki_der(der.as_ref())
+    }
+}
+
+/// Write a PEM document with the given label to a buffer
+pub fn write_pem(label: &str, der: &[u8], out: &mut dyn WriteBuffer) -> Result<(), Error> {
+    out.buffer_write(b"-----BEGIN ")?;
+    out.buffer_write(label.as_bytes())?;
+    out.buffer_write(b"-----\n")?;
+    let mut line = [0u8; 64];
+    for chunk in der.chunks(48) {
+        let len = base64::encode_config_slice(chunk, base64::STANDARD, &mut line[..]);
+        out.buffer_write(&line[..len])?;
+        out.buffer_write(b"\n")?;
+    }
+    out.buffer_write(b"-----END ")?;
+    out.buffer_write(label.as_bytes())?;
+    out.buffer_write(b"-----\n")
+}
+
+/// Decode the DER contents of a PEM document with the given label
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+pub fn decode_pem(label: &str, pem: &str) -> Result<SecretBytes, Error> {
+    let body = pem
+        .trim()
+        .strip_prefix("-----BEGIN ")
+        .and_then(|s| s.strip_prefix(label))
+        .and_then(|s| s.strip_prefix("-----"))
+        .ok_or_else(|| err_msg!(InvalidData, "Missing or unexpected PEM header"))?;
+    let end = body
+        .find("-----END ")
+        .filter(|pos| body[pos + 9..].strip_prefix(label).map(str::trim_end) == Some("-----"))
+        .ok_or_else(|| err_msg!(InvalidData, "Missing or unexpected PEM footer"))?;
+    let mut encoded = SecretBytes::with_capacity(end);
+    for c in body[..end].bytes().filter(|c| !c.is_ascii_whitespace()) {
+        encoded.buffer_write(&[c])?;
+    }
+    let mut der = SecretBytes::with_capacity(encoded.len() * 3 / 4 + 3);
+    let len = base64::decode_config_slice(
+        encoded.as_ref(),
+        base64::STANDARD,
+        der.buffer_extend(encoded.len() * 3 / 4 + 3)?,
+    )
+    .map_err(|_| err_msg!(InvalidData, "Invalid base64 in PEM document"))?;
+    der.buffer_resize(len)?;
+    Ok(der)
+}
+
+/// Write the PKCS#8 encoding of a curve private key (RFC 8410)
+pub(crate) fn write_curve_private_key(
+    out: &mut dyn WriteBuffer,
+    alg: AlgorithmId<'_>,
+    secret: Option<&[u8]>,
+) -> Result<(), Error> {
+    if let Some(sk) = secret {
+        write_private_key_info(out, alg, tlv_len(sk.len()), |out| {
+            write_tlv(out, TAG_OCTET_STRING, sk)
+        })
+    } else {
+        Err(err_msg!(MissingSecretKey))
+    }
+}
+
+/// Decode the PKCS#8 encoding of a curve private key (RFC 8410), returning
+/// the secret key and the public key, if present
+pub(crate) fn decode_curve_private_key<'a>(
+    der: &'a [u8],
+    alg: AlgorithmId<'_>,
+) -> Result<(&'a [u8], Option<&'a [u8]>), Error> {
+    let info = decode_private_key_info(der, alg)?;
+    let mut reader = DerReader::new(info.private_key);
+    let secret = reader.read(TAG_OCTET_STRING)?;
+    reader.finish()?;
+    Ok((secret, info.public_key))
+}
+
+/// Decode a PKCS#8 private key, checking the algorithm identifier
+pub(crate) fn decode_private_key_info<'a>(
+    der: &'a [u8],
+    alg: AlgorithmId<'_>,
+) -> Result<PrivateKeyInfo<'a>, Error> {
+    let info = PrivateKeyInfo::decode(der)?;
+    if info.alg != alg {
+        return Err(err_msg!(Unsupported, "Unsupported PKCS#8 key algorithm"));
+    }
+    Ok(info)
+}
+
+/// Decode an SPKI public key, checking the algorithm identifier
+pub(crate) fn decode_spki<'a>(der: &'a [u8], alg: AlgorithmId<'_>) -> Result<&'a [u8], Error> {
+    let info = PublicKeyInfo::decode(der)?;
+    if info.alg != alg {
+        return Err(err_msg!(Unsupported, "Unsupported SPKI key algorithm"));
+    }
+    Ok(info.public_key)
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pem_round_trip() {
+        let der = [7u8; 100];
+        let mut pem = Vec::new();
+        write_pem(PEM_LABEL_PUBLIC_KEY, &der, &mut pem).unwrap();
+        let pem = String::from_utf8(pem).unwrap();
+        assert!(pem.starts_with("-----BEGIN PUBLIC KEY-----\n"));
+        assert!(pem.ends_with("-----END PUBLIC KEY-----\n"));
+        assert_eq!(pem.lines().nth(1).unwrap().len(), 64);
+        let dec = decode_pem(PEM_LABEL_PUBLIC_KEY, &pem).unwrap();
+        assert_eq!(dec, &der[..]);
+        assert!(decode_pem(PEM_LABEL_PRIVATE_KEY, &pem).is_err());
+    }
+}
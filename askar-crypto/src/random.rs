@@ -1,11 +1,14 @@
 //! Support for random number generation
 
+use core::fmt::{self, Debug, Formatter};
+
 use aead::generic_array::{typenum::Unsigned, GenericArray};
 use chacha20::{
     cipher::{NewStreamCipher, SyncStreamCipher},
     ChaCha20,
 };
 use rand::{CryptoRng, RngCore};
+use zeroize::Zeroize;
 
 #[cfg(feature = "alloc")]
 use crate::buffer::SecretBytes;
@@ -19,12 +22,31 @@ pub trait Rng: CryptoRng + RngCore {}
 
 impl<T: CryptoRng + RngCore> Rng for T {}
 
+/// A source of key material for key generation
+pub trait KeyMaterial {
+    /// Fill the provided buffer with key material
+    fn read_okm(&mut self, buf: &mut [u8]);
+}
+
+impl<R: CryptoRng + RngCore> KeyMaterial for R {
+    #[inline(always)]
+    fn read_okm(&mut self, buf: &mut [u8]) {
+        self.fill_bytes(buf);
+    }
+}
+
+/// Obtain the default random number generator
+#[inline(always)]
+pub fn default_rng() -> impl Rng {
+    ::rand::rngs::OsRng
+}
+
 /// Perform an operation with a reference to the random number generator
 #[inline(always)]
 pub fn with_rng<O>(f: impl FnOnce(&mut dyn Rng) -> O) -> O {
     // FIXME may wish to support platforms without 'getrandom' by adding
     // a method to initialize with a custom RNG (or fill_bytes function)
-    f(&mut ::rand::rngs::OsRng)
+    f(&mut default_rng())
 }
 
 /// Fill a mutable slice with random data using the
@@ -48,6 +70,42 @@ pub fn fill_random_deterministic(seed: &[u8], output: &mut [u8]) -> Result<(), E
     Ok(())
 }
 
+/// A deterministic source of key material, compatible with
+/// `fill_random_deterministic`. Successive reads continue the same
+/// output stream, making this suitable for reproducible test fixtures
+/// and known-answer tests.
+pub struct RandomDet {
+    cipher: ChaCha20,
+}
+
+impl RandomDet {
+    /// Create a new instance from a seed of `DETERMINISTIC_SEED_LENGTH` bytes
+    pub fn new(seed: &[u8]) -> Result<Self, Error> {
+        if seed.len() != DETERMINISTIC_SEED_LENGTH {
+            return Err(err_msg!(Usage, "Invalid length for seed"));
+        }
+        Ok(Self {
+            cipher: ChaCha20::new(
+                GenericArray::from_slice(seed),
+                GenericArray::from_slice(b"LibsodiumDRG"),
+            ),
+        })
+    }
+}
+
+impl KeyMaterial for RandomDet {
+    fn read_okm(&mut self, buf: &mut [u8]) {
+        buf.zeroize();
+        self.cipher.apply_keystream(buf);
+    }
+}
+
+impl Debug for RandomDet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RandomDet").finish()
+    }
+}
+
 #[cfg(feature = "alloc")]
 #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
 /// Create a new `SecretBytes` instance with random data.
@@ -72,4 +130,17 @@ mod tests {
             "b1923a011cd1adbe89552db9862470c29512a8f51d184dfd778bfe7f845390d1"
         );
     }
+
+    #[test]
+    fn random_det_matches_fill() {
+        let seed = b"testseed000000000000000000000001";
+        let mut expected = [0u8; 48];
+        fill_random_deterministic(seed, &mut expected).unwrap();
+        let mut rng = RandomDet::new(seed).unwrap();
+        let mut output = [0xffu8; 48];
+        rng.read_okm(&mut output[..20]);
+        rng.read_okm(&mut output[20..]);
+        assert_eq!(output, expected);
+        assert!(RandomDet::new(b"short").is_err());
+    }
 }
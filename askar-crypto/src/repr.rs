@@ -6,6 +6,7 @@ use crate::{
     buffer::WriteBuffer,
     error::Error,
     generic_array::{typenum::Unsigned, ArrayLength},
+    random::{default_rng, KeyMaterial},
 };
 
 /// A seed used in key generation
@@ -35,11 +36,20 @@ pub enum SeedMethod {
 
 /// Key generation operations
 pub trait KeyGen {
-    /// Generate a new random key.
-    fn generate() -> Result<Self, Error>
+    /// Generate a new key from a source of key material, such as a
+    /// random number generator.
+    fn generate_with_rng(rng: impl KeyMaterial) -> Result<Self, Error>
     where
         Self: Sized;
 
+    /// Generate a new random key using the default random number generator.
+    fn generate() -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        Self::generate_with_rng(default_rng())
+    }
+
     /// Generate a new deterministic key.
     fn from_seed(_seed: Seed<'_>) -> Result<Self, Error>
     where
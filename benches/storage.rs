@@ -0,0 +1,184 @@
+#[macro_use]
+extern crate criterion;
+
+use aries_askar::{
+    future::block_on,
+    generate_raw_store_key,
+    sqlite::{SqliteStore, SqliteStoreOptions},
+    EntryTag, Store, StoreKeyMethod, TagFilter,
+};
+
+use criterion::{black_box, BenchmarkId, Criterion, Throughput};
+
+const CATEGORY: &str = "bench-category";
+
+const VALUE_SIZES: &[usize] = &[32, 1024, 16384];
+
+const TAG_COUNTS: &[usize] = &[0, 4, 16];
+
+const SCAN_ENTRIES: usize = 500;
+
+fn provision() -> Store<SqliteStore> {
+    let key = generate_raw_store_key(None).unwrap();
+    block_on(SqliteStoreOptions::in_memory().provision(StoreKeyMethod::RawKey, key, None, false))
+        .unwrap()
+}
+
+fn make_tags(count: usize) -> Vec<EntryTag> {
+    (0..count)
+        .map(|idx| {
+            if idx % 2 == 0 {
+                EntryTag::Encrypted(format!("tag{}", idx), format!("value{}", idx))
+            } else {
+                EntryTag::Plaintext(format!("tag{}", idx), format!("value{}", idx))
+            }
+        })
+        .collect()
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let store = provision();
+
+    {
+        let mut group = c.benchmark_group("insert");
+        for size in VALUE_SIZES {
+            for tag_count in TAG_COUNTS {
+                let value = vec![0x55u8; *size];
+                let tags = make_tags(*tag_count);
+                let mut idx = 0usize;
+                group.throughput(Throughput::Bytes(*size as u64));
+                group.bench_with_input(
+                    BenchmarkId::new(format!("{} tags", tag_count), size),
+                    size,
+                    |b, size| {
+                        b.iter(|| {
+                            idx += 1;
+                            block_on(async {
+                                let mut session = store.session(None).await.unwrap();
+                                session
+                                    .insert(
+                                        CATEGORY,
+                                        &format!("insert-{}-{}-{}", size, tag_count, idx),
+                                        black_box(&value),
+                                        Some(tags.as_slice()),
+                                        None,
+                                    )
+                                    .await
+                                    .unwrap();
+                            })
+                        })
+                    },
+                );
+            }
+        }
+        group.finish();
+    }
+
+    {
+        let mut group = c.benchmark_group("fetch");
+        for size in VALUE_SIZES {
+            for tag_count in TAG_COUNTS {
+                let name = format!("fetch-{}-{}", size, tag_count);
+                block_on(async {
+                    let mut session = store.session(None).await.unwrap();
+                    session
+                        .insert(
+                            CATEGORY,
+                            &name,
+                            &vec![0x55u8; *size],
+                            Some(make_tags(*tag_count).as_slice()),
+                            None,
+                        )
+                        .await
+                        .unwrap();
+                });
+                group.throughput(Throughput::Bytes(*size as u64));
+                group.bench_with_input(
+                    BenchmarkId::new(format!("{} tags", tag_count), size),
+                    &name,
+                    |b, name| {
+                        b.iter(|| {
+                            block_on(async {
+                                let mut session = store.session(None).await.unwrap();
+                                session
+                                    .fetch(CATEGORY, black_box(name), false)
+                                    .await
+                                    .unwrap()
+                                    .unwrap()
+                            })
+                        })
+                    },
+                );
+            }
+        }
+        group.finish();
+    }
+
+    {
+        let category = "bench-scan";
+        let tags = make_tags(TAG_COUNTS[1]);
+        block_on(async {
+            let mut txn = store.transaction(None).await.unwrap();
+            for idx in 0..SCAN_ENTRIES {
+                let mut entry_tags = tags.clone();
+                entry_tags.push(EntryTag::Encrypted(
+                    "parity".to_string(),
+                    (idx % 2).to_string(),
+                ));
+                txn.insert(
+                    category,
+                    &format!("scan-{}", idx),
+                    &vec![0x55u8; VALUE_SIZES[1]],
+                    Some(entry_tags.as_slice()),
+                    None,
+                )
+                .await
+                .unwrap();
+            }
+            txn.commit().await.unwrap();
+        });
+
+        let mut group = c.benchmark_group("scan");
+        group.throughput(Throughput::Elements(SCAN_ENTRIES as u64));
+        group.bench_function("all", |b| {
+            b.iter(|| {
+                block_on(async {
+                    let mut scan = store
+                        .scan(None, category.to_string(), None, None, None)
+                        .await
+                        .unwrap();
+                    let mut count = 0;
+                    while let Some(rows) = scan.fetch_next().await.unwrap() {
+                        count += rows.len();
+                    }
+                    assert_eq!(count, SCAN_ENTRIES);
+                })
+            })
+        });
+        group.bench_function("tag filter", |b| {
+            b.iter(|| {
+                block_on(async {
+                    let filter = TagFilter::all_of(vec![
+                        TagFilter::is_eq("parity", "0"),
+                        TagFilter::is_eq("~tag1", "value1"),
+                    ]);
+                    let mut scan = store
+                        .scan(None, category.to_string(), Some(filter), None, None)
+                        .await
+                        .unwrap();
+                    let mut count = 0;
+                    while let Some(rows) = scan.fetch_next().await.unwrap() {
+                        count += rows.len();
+                    }
+                    assert_eq!(count, SCAN_ENTRIES / 2);
+                })
+            })
+        });
+        group.finish();
+    }
+
+    block_on(store.close()).unwrap();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
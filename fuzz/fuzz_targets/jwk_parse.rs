@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use aries_askar::fuzz::fuzz_jwk_parse;
+
+fuzz_target!(|data: &[u8]| {
+    fuzz_jwk_parse(data);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use aries_askar::fuzz::fuzz_tag_filter_parse;
+
+fuzz_target!(|data: &str| {
+    fuzz_tag_filter_parse(data);
+});
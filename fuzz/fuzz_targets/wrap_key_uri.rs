@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use aries_askar::fuzz::fuzz_wrap_key_uri;
+
+fuzz_target!(|data: &str| {
+    fuzz_wrap_key_uri(data);
+});
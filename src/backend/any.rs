@@ -1,13 +1,26 @@
-use super::{Backend, ManageBackend, QueryBackend};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use super::{AccessMode, Backend, Durability, MaintenanceMode, ManageBackend, QueryBackend};
 use crate::{
+    crypto::buffer::SecretBytes,
     error::Error,
     future::BoxFuture,
-    protect::{PassKey, StoreKeyMethod},
+    protect::{KeyCacheStats, PassKey, StoreKeyMethod, TenantKeyProvider},
     storage::{
-        Entry, EntryKind, EntryOperation, EntryTag, IntoOptions, Scan, Session, Store, TagFilter,
+        Entry, EntryExpiry, EntryHash, EntryId, EntryKind, EntryLink, EntryOperation, EntryTag,
+        EntryVersion, HistoryFilter, IntegrityReport, IntoOptions, ProfileShredding, Projection,
+        Scan, Session, StorageReport, Store, StoreCapabilities, StoreTuning, TagFilter,
+        UniqueConstraint,
     },
 };
 
+#[cfg(feature = "raw_query")]
+use crate::{
+    protect::{ProfileId, ProfileKey},
+    storage::{RawRow, RawValue},
+};
+
 #[cfg(feature = "postgres")]
 use super::postgres::{self, PostgresStore};
 
@@ -61,23 +74,47 @@ impl Backend for AnyBackend {
         with_backend!(self, store, store.get_profile_name())
     }
 
+    fn capabilities(&self) -> StoreCapabilities {
+        with_backend!(self, store, store.capabilities())
+    }
+
     fn remove_profile(&self, name: String) -> BoxFuture<'_, Result<bool, Error>> {
         with_backend!(self, store, store.remove_profile(name))
     }
 
+    fn shred_profile(
+        &self,
+        name: String,
+    ) -> BoxFuture<'_, Result<Option<ProfileShredding>, Error>> {
+        with_backend!(self, store, store.shred_profile(name))
+    }
+
     fn scan(
         &self,
         profile: Option<String>,
         kind: EntryKind,
         category: String,
         tag_filter: Option<TagFilter>,
+        history_filter: HistoryFilter,
         offset: Option<i64>,
         limit: Option<i64>,
-    ) -> BoxFuture<'_, Result<Scan<'static, Entry>, Error>> {
+        after_id: Option<i64>,
+        projection: Projection,
+    ) -> BoxFuture<'_, Result<Scan<Entry>, Error>> {
         with_backend!(
             self,
             store,
-            store.scan(profile, kind, category, tag_filter, offset, limit)
+            store.scan(
+                profile,
+                kind,
+                category,
+                tag_filter,
+                history_filter,
+                offset,
+                limit,
+                after_id,
+                projection
+            )
         )
     }
 
@@ -107,6 +144,80 @@ impl Backend for AnyBackend {
         with_backend!(self, store, store.rekey_backend(method, pass_key))
     }
 
+    fn storage_report(&self) -> BoxFuture<'_, Result<StorageReport, Error>> {
+        with_backend!(self, store, store.storage_report())
+    }
+
+    fn verify_integrity(
+        &self,
+        profile: Option<String>,
+    ) -> BoxFuture<'_, Result<IntegrityReport, Error>> {
+        with_backend!(self, store, store.verify_integrity(profile))
+    }
+
+    fn backup_to(&self, path: String) -> BoxFuture<'_, Result<(), Error>> {
+        with_backend!(self, store, store.backup_to(path))
+    }
+
+    fn fork_to(&self, uri: String) -> BoxFuture<'_, Result<Self, Error>> {
+        Box::pin(async move {
+            match self {
+                #[cfg(feature = "postgres")]
+                Self::Postgres(store) => Ok(Self::Postgres(store.fork_to(uri).await?)),
+
+                #[cfg(feature = "sqlite")]
+                Self::Sqlite(store) => Ok(Self::Sqlite(store.fork_to(uri).await?)),
+
+                _ => unreachable!(),
+            }
+        })
+    }
+
+    fn maintenance(&self, mode: MaintenanceMode) -> BoxFuture<'_, Result<(), Error>> {
+        with_backend!(self, store, store.maintenance(mode))
+    }
+
+    fn access_mode(&self) -> BoxFuture<'_, Result<AccessMode, Error>> {
+        with_backend!(self, store, store.access_mode())
+    }
+
+    fn set_access_mode(&self, mode: AccessMode) -> BoxFuture<'_, Result<(), Error>> {
+        with_backend!(self, store, store.set_access_mode(mode))
+    }
+
+    #[cfg(feature = "raw_query")]
+    fn raw_query(
+        &self,
+        sql: String,
+        params: Vec<RawValue>,
+    ) -> BoxFuture<'_, Result<Vec<RawRow>, Error>> {
+        with_backend!(self, store, store.raw_query(sql, params))
+    }
+
+    #[cfg(feature = "raw_query")]
+    fn profile_key(
+        &self,
+        profile: Option<String>,
+    ) -> BoxFuture<'_, Result<(ProfileId, Arc<ProfileKey>), Error>> {
+        with_backend!(self, store, store.profile_key(profile))
+    }
+
+    fn set_tenant_keys(&mut self, provider: Option<Arc<dyn TenantKeyProvider>>) {
+        with_backend!(self, store, store.set_tenant_keys(provider))
+    }
+
+    fn set_unique_constraints(&mut self, constraints: Arc<Vec<UniqueConstraint>>) {
+        with_backend!(self, store, store.set_unique_constraints(constraints))
+    }
+
+    fn key_cache_stats(&self) -> KeyCacheStats {
+        with_backend!(self, store, store.key_cache_stats())
+    }
+
+    fn reconfigure(&self, tuning: &StoreTuning) -> Result<(), Error> {
+        with_backend!(self, store, store.reconfigure(tuning))
+    }
+
     fn close(&self) -> BoxFuture<'_, Result<(), Error>> {
         with_backend!(self, store, store.close())
     }
@@ -164,6 +275,93 @@ impl QueryBackend for AnyQueryBackend {
         }
     }
 
+    fn fetch_into<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        output: &'q mut SecretBytes,
+    ) -> BoxFuture<'q, Result<bool, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.fetch_into(kind, category, name, output),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.fetch_into(kind, category, name, output),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn exists<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+    ) -> BoxFuture<'q, Result<bool, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.exists(kind, category, name),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.exists(kind, category, name),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn fetch_by_id(
+        &mut self,
+        kind: EntryKind,
+        id: EntryId,
+    ) -> BoxFuture<'_, Result<Option<Entry>, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.fetch_by_id(kind, id),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.fetch_by_id(kind, id),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn fetch_at<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        timestamp: SystemTime,
+    ) -> BoxFuture<'q, Result<Option<Entry>, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.fetch_at(kind, category, name, timestamp),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.fetch_at(kind, category, name, timestamp),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn fetch_history<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        limit: Option<i64>,
+    ) -> BoxFuture<'q, Result<Vec<EntryVersion>, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.fetch_history(kind, category, name, limit),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.fetch_history(kind, category, name, limit),
+
+            _ => unreachable!(),
+        }
+    }
+
     fn fetch_all<'q>(
         &'q mut self,
         kind: EntryKind,
@@ -171,22 +369,41 @@ impl QueryBackend for AnyQueryBackend {
         tag_filter: Option<TagFilter>,
         limit: Option<i64>,
         for_update: bool,
+        projection: Projection,
     ) -> BoxFuture<'q, Result<Vec<Entry>, Error>> {
         match self {
             #[cfg(feature = "postgres")]
             Self::PostgresSession(session) => {
-                session.fetch_all(kind, category, tag_filter, limit, for_update)
+                session.fetch_all(kind, category, tag_filter, limit, for_update, projection)
             }
 
             #[cfg(feature = "sqlite")]
             Self::SqliteSession(session) => {
-                session.fetch_all(kind, category, tag_filter, limit, for_update)
+                session.fetch_all(kind, category, tag_filter, limit, for_update, projection)
             }
 
             _ => unreachable!(),
         }
     }
 
+    fn search<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        terms: Vec<String>,
+        limit: Option<i64>,
+    ) -> BoxFuture<'q, Result<Vec<Entry>, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.search(kind, category, terms, limit),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.search(kind, category, terms, limit),
+
+            _ => unreachable!(),
+        }
+    }
+
     fn remove_all<'q>(
         &'q mut self,
         kind: EntryKind,
@@ -204,6 +421,46 @@ impl QueryBackend for AnyQueryBackend {
         }
     }
 
+    fn insert_all<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        entries: Vec<Entry>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.insert_all(kind, entries),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.insert_all(kind, entries),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn touch_all<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        tag_filter: Option<TagFilter>,
+        offset: Option<i64>,
+        limit: Option<i64>,
+        expiry: EntryExpiry,
+    ) -> BoxFuture<'q, Result<i64, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => {
+                session.touch_all(kind, category, tag_filter, offset, limit, expiry)
+            }
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => {
+                session.touch_all(kind, category, tag_filter, offset, limit, expiry)
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
     fn update<'q>(
         &'q mut self,
         kind: EntryKind,
@@ -212,23 +469,338 @@ impl QueryBackend for AnyQueryBackend {
         name: &'q str,
         value: Option<&'q [u8]>,
         tags: Option<&'q [EntryTag]>,
-        expiry_ms: Option<i64>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => {
+                session.update(kind, operation, category, name, value, tags, expiry)
+            }
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => {
+                session.update(kind, operation, category, name, value, tags, expiry)
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn insert_returning_id<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<EntryId, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => {
+                session.insert_returning_id(kind, category, name, value, tags, expiry)
+            }
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => {
+                session.insert_returning_id(kind, category, name, value, tags, expiry)
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn insert_or_fetch<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<Option<Entry>, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => {
+                session.insert_or_fetch(kind, category, name, value, tags, expiry)
+            }
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => {
+                session.insert_or_fetch(kind, category, name, value, tags, expiry)
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn replace_checked<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+        expected: Option<EntryHash>,
+    ) -> BoxFuture<'q, Result<EntryHash, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => {
+                session.replace_checked(kind, category, name, value, tags, expiry, expected)
+            }
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => {
+                session.replace_checked(kind, category, name, value, tags, expiry, expected)
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn update_search_terms<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        terms: Vec<String>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => {
+                session.update_search_terms(kind, category, name, terms)
+            }
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => {
+                session.update_search_terms(kind, category, name, terms)
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn fetch_metadata<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+    ) -> BoxFuture<'q, Result<Option<SecretBytes>, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.fetch_metadata(kind, category, name),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.fetch_metadata(kind, category, name),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn update_metadata<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        metadata: Option<&'q [u8]>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => {
+                session.update_metadata(kind, category, name, metadata)
+            }
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.update_metadata(kind, category, name, metadata),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn update_link<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        link: EntryLink,
+        remove: bool,
+    ) -> BoxFuture<'q, Result<bool, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => {
+                session.update_link(kind, category, name, link, remove)
+            }
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.update_link(kind, category, name, link, remove),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn list_links<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        rel_type: Option<String>,
+        incoming: bool,
+    ) -> BoxFuture<'q, Result<Vec<EntryLink>, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => {
+                session.list_links(kind, category, name, rel_type, incoming)
+            }
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => {
+                session.list_links(kind, category, name, rel_type, incoming)
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn update_collection<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        collection: Option<&'q str>,
     ) -> BoxFuture<'q, Result<(), Error>> {
         match self {
             #[cfg(feature = "postgres")]
             Self::PostgresSession(session) => {
-                session.update(kind, operation, category, name, value, tags, expiry_ms)
+                session.update_collection(kind, category, name, collection)
             }
 
             #[cfg(feature = "sqlite")]
             Self::SqliteSession(session) => {
-                session.update(kind, operation, category, name, value, tags, expiry_ms)
+                session.update_collection(kind, category, name, collection)
             }
 
             _ => unreachable!(),
         }
     }
 
+    fn remove_collection<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        collection: &'q str,
+        skip: &'q [(String, String)],
+    ) -> BoxFuture<'q, Result<i64, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.remove_collection(kind, collection, skip),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.remove_collection(kind, collection, skip),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn collection_members<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        collection: &'q str,
+    ) -> BoxFuture<'q, Result<Vec<(String, String)>, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.collection_members(kind, collection),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.collection_members(kind, collection),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn begin_atomic(&mut self) -> BoxFuture<'_, Result<bool, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.begin_atomic(),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.begin_atomic(),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn end_atomic(&mut self, commit: bool) -> BoxFuture<'_, Result<(), Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.end_atomic(commit),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.end_atomic(commit),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn set_durability(&mut self, durability: Durability) -> BoxFuture<'_, Result<(), Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.set_durability(durability),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.set_durability(durability),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn access_mode(&mut self) -> BoxFuture<'_, Result<AccessMode, Error>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.access_mode(),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.access_mode(),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn reset_connection(&mut self, discard: bool) -> bool {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.reset_connection(discard),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.reset_connection(discard),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn backend_name(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.backend_name(),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.backend_name(),
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn crypto_time(&self) -> Duration {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgresSession(session) => session.crypto_time(),
+
+            #[cfg(feature = "sqlite")]
+            Self::SqliteSession(session) => session.crypto_time(),
+
+            _ => unreachable!(),
+        }
+    }
+
     fn close(self, commit: bool) -> BoxFuture<'static, Result<(), Error>> {
         match self {
             #[cfg(feature = "postgres")]
@@ -1,33 +1,113 @@
+use std::collections::HashMap;
 use std::future::Future;
 use std::ops::{Deref, DerefMut};
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicI64, AtomicU64, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use sqlx::{
     database::HasArguments, pool::PoolConnection, Arguments, Database, Encode, Error as SqlxError,
     IntoArguments, Pool, TransactionManager, Type,
 };
+use tokio::sync::{mpsc, oneshot};
+use unicode_normalization::UnicodeNormalization;
 
+use super::{AccessMode, Durability};
 use crate::{
+    crypto::buffer::SecretBytes,
     error::Error,
-    future::BoxFuture,
-    protect::{EntryEncryptor, KeyCache, PassKey, ProfileId, ProfileKey, StoreKey, StoreKeyMethod},
+    future::{spawn_ok, timeout, unblock, BoxFuture},
+    protect::{
+        EntryEncryptor, IdStrategy, KeyCache, NonceStrategy, PassKey, ProfileId, ProfileKey,
+        StoreKey, StoreKeyMethod,
+    },
     storage::{
         wql::{
             sql::TagSqlEncoder,
             tags::{tag_query, TagQueryEncoder},
         },
-        {EncEntryTag, Entry, EntryTag, TagFilter},
+        {
+            split_namespace, CategoryUsage, EncEntryTag, Entry, EntryExpiry, EntryKind, EntryLink,
+            EntryOperation, EntrySeq, EntryTag, EntryVersion, HistoryFilter, IntegrityIssue,
+            IntegrityIssueKind, IntegrityReport, StorageReport, TagFilter, UniqueConstraint,
+        },
     },
 };
 
 pub const PAGE_SIZE: usize = 32;
 
+/// The maximum total size of the encrypted records in a page of scan results.
+/// A page is returned early when this is exceeded, bounding the memory held
+/// by a scan whose consumer is slow to process large records
+pub const PAGE_BYTES: usize = 1024 * 1024;
+
+/// The number of profile keys re-wrapped per statement when rekeying
+pub const REKEY_PAGE_SIZE: usize = 256;
+
+/// The number of records written per statement by a batch insert
+pub const INSERT_BATCH_SIZE: usize = 1000;
+
 pub type Expiry = chrono::DateTime<chrono::Utc>;
 
+/// An encrypted entry update submitted by a non-transactional session, to be
+/// committed together with other concurrent updates
+pub(crate) struct CoalescedWrite {
+    pub profile_id: ProfileId,
+    pub key: Arc<ProfileKey>,
+    pub kind: EntryKind,
+    pub operation: EntryOperation,
+    pub category: Vec<u8>,
+    pub name: Vec<u8>,
+    pub value: Option<Vec<u8>>,
+    pub checksum: Option<Vec<u8>>,
+    pub tags: Option<Vec<EncEntryTag>>,
+    pub expiry: Option<EntryExpiry>,
+    pub unique_key: Option<Vec<u8>>,
+}
+
+/// The queue accepting coalesced writes, along with a channel for the result
+pub(crate) type WriteQueue =
+    mpsc::UnboundedSender<(CoalescedWrite, oneshot::Sender<Result<(), Error>>)>;
+
+/// Accumulates the time spent encrypting and decrypting the records of a
+/// session, which is performed on the blocking thread pool
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CryptoTimer(Arc<AtomicU64>);
+
+impl CryptoTimer {
+    /// Run a blocking task, adding its running time to the total
+    pub fn unblock<F, T>(&self, f: F) -> impl Future<Output = T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let total = self.0.clone();
+        unblock(move || {
+            let start = Instant::now();
+            let result = f();
+            total.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+            result
+        })
+    }
+
+    /// The total running time of the completed tasks
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum DbSessionState<DB: ExtDatabase> {
-    Active { conn: PoolConnection<DB> },
-    Pending { pool: Pool<DB> },
+    Active {
+        conn: PoolConnection<DB>,
+        pool: Pool<DB>,
+    },
+    Pending {
+        pool: Pool<DB>,
+    },
+    Lost,
 }
 
 unsafe impl<DB: ExtDatabase> Sync for DbSessionState<DB> where DB::Connection: Send {}
@@ -37,6 +117,24 @@ pub struct DbSession<DB: ExtDatabase> {
     profile_key: DbSessionKey,
     state: DbSessionState<DB>,
     transaction: bool,
+    atomic: Option<Pool<DB>>,
+    begin_pending: bool,
+    history: bool,
+    soft_delete: bool,
+    partitioned: bool,
+    value_checksum: bool,
+    entry_metadata: bool,
+    entry_links: bool,
+    collections: bool,
+    unique_keys: bool,
+    unique_constraints: Arc<Vec<UniqueConstraint>>,
+    id_strategy: IdStrategy,
+    page_size: usize,
+    crypto_timer: CryptoTimer,
+    write_queue: Option<WriteQueue>,
+    durability: Option<Durability>,
+    session_durability: Option<Durability>,
+    durability_changed: bool,
 }
 
 impl<DB: ExtDatabase> DbSession<DB> {
@@ -45,6 +143,7 @@ impl<DB: ExtDatabase> DbSession<DB> {
         cache: Arc<KeyCache>,
         profile: String,
         transaction: bool,
+        history: bool,
     ) -> Self
     where
         DB: Database,
@@ -53,12 +152,129 @@ impl<DB: ExtDatabase> DbSession<DB> {
             profile_key: DbSessionKey::Pending { cache, profile },
             state: DbSessionState::Pending { pool },
             transaction,
+            atomic: None,
+            begin_pending: false,
+            history,
+            soft_delete: false,
+            partitioned: false,
+            value_checksum: false,
+            entry_metadata: false,
+            entry_links: false,
+            collections: false,
+            unique_keys: false,
+            unique_constraints: Arc::new(Vec::new()),
+            id_strategy: IdStrategy::Serial,
+            page_size: PAGE_SIZE,
+            crypto_timer: CryptoTimer::default(),
+            write_queue: None,
+            durability: None,
+            session_durability: None,
+            durability_changed: false,
         }
     }
 
+    /// Set whether removed entries are marked as expired rather than deleted
+    #[allow(unused)]
+    #[inline]
+    pub(crate) fn with_soft_delete(mut self, soft_delete: bool) -> Self {
+        self.soft_delete = soft_delete;
+        self
+    }
+
+    /// Set whether the item and tag tables are partitioned by profile
+    #[allow(unused)]
+    #[inline]
+    pub(crate) fn with_partitioned(mut self, partitioned: bool) -> Self {
+        self.partitioned = partitioned;
+        self
+    }
+
+    /// Set whether a checksum is stored alongside each entry value
+    #[inline]
+    pub(crate) fn with_value_checksum(mut self, value_checksum: bool) -> Self {
+        self.value_checksum = value_checksum;
+        self
+    }
+
+    /// Set whether the item table has a column for entry metadata
+    #[inline]
+    pub(crate) fn with_entry_metadata(mut self, entry_metadata: bool) -> Self {
+        self.entry_metadata = entry_metadata;
+        self
+    }
+
+    /// Set whether the store has a table for entry links
+    #[inline]
+    pub(crate) fn with_entry_links(mut self, entry_links: bool) -> Self {
+        self.entry_links = entry_links;
+        self
+    }
+
+    /// Set whether the item table has a column for entry collections
+    #[inline]
+    pub(crate) fn with_collections(mut self, collections: bool) -> Self {
+        self.collections = collections;
+        self
+    }
+
+    /// Set whether the item table has a column for unique keys
+    #[inline]
+    pub(crate) fn with_unique_keys(mut self, unique_keys: bool) -> Self {
+        self.unique_keys = unique_keys;
+        self
+    }
+
+    /// Set the unique constraints applied to the records written by the session
+    #[inline]
+    pub(crate) fn with_unique_constraints(
+        mut self,
+        unique_constraints: Arc<Vec<UniqueConstraint>>,
+    ) -> Self {
+        self.unique_constraints = unique_constraints;
+        self
+    }
+
+    /// Set the strategy used to generate the identifiers of new records
+    #[allow(unused)]
+    #[inline]
+    pub(crate) fn with_id_strategy(mut self, id_strategy: IdStrategy) -> Self {
+        self.id_strategy = id_strategy;
+        self
+    }
+
+    /// Set the number of records in each page of a scan
+    #[inline]
+    pub(crate) fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Submit the updates of a non-transactional session to a write queue
+    #[allow(unused)]
+    #[inline]
+    pub(crate) fn with_write_queue(mut self, write_queue: Option<WriteQueue>) -> Self {
+        self.write_queue = write_queue;
+        self
+    }
+
+    /// Set the durability level applied to new connections by the store
+    #[inline]
+    pub(crate) fn with_durability(mut self, durability: Option<Durability>) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Use a profile which has already been resolved
+    #[allow(unused)]
+    #[inline]
+    pub(crate) fn with_profile_key(mut self, profile_id: ProfileId, key: Arc<ProfileKey>) -> Self {
+        self.profile_key = DbSessionKey::Active { profile_id, key };
+        self
+    }
+
     #[inline]
     fn connection_mut(&mut self) -> Option<&mut PoolConnection<DB>> {
-        if let DbSessionState::Active { conn } = &mut self.state {
+        if let DbSessionState::Active { conn, .. } = &mut self.state {
             Some(conn)
         } else {
             None
@@ -70,6 +286,109 @@ impl<DB: ExtDatabase> DbSession<DB> {
         self.transaction
     }
 
+    /// Whether previous versions of entries are retained
+    #[inline]
+    pub fn history(&self) -> bool {
+        self.history
+    }
+
+    /// Whether removed entries are marked as expired rather than deleted
+    #[allow(unused)]
+    #[inline]
+    pub fn soft_delete(&self) -> bool {
+        self.soft_delete
+    }
+
+    /// Whether the item and tag tables are partitioned by profile
+    #[allow(unused)]
+    #[inline]
+    pub fn partitioned(&self) -> bool {
+        self.partitioned
+    }
+
+    /// Whether a checksum is stored alongside each entry value
+    #[inline]
+    pub fn value_checksum(&self) -> bool {
+        self.value_checksum
+    }
+
+    /// Whether entries may carry encrypted metadata
+    #[inline]
+    pub fn entry_metadata(&self) -> bool {
+        self.entry_metadata
+    }
+
+    /// Whether entries may be linked to other entries
+    #[inline]
+    pub fn entry_links(&self) -> bool {
+        self.entry_links
+    }
+
+    /// Whether entries may be grouped into collections
+    #[inline]
+    pub fn collections(&self) -> bool {
+        self.collections
+    }
+
+    /// Whether entries may be subject to unique constraints
+    #[inline]
+    pub fn unique_keys(&self) -> bool {
+        self.unique_keys
+    }
+
+    /// The number of records in each page of a scan
+    #[inline]
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Derive the unique key stored alongside a record, when a unique
+    /// constraint applies to its category and the record has all of the
+    /// constrained tags. Key management records are never constrained
+    pub(crate) fn unique_key(
+        &self,
+        kind: EntryKind,
+        category: &str,
+        tags: Option<&[EntryTag]>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        if kind == EntryKind::Kms {
+            return Ok(None);
+        }
+        let constraint = match self
+            .unique_constraints
+            .iter()
+            .find(|c| c.category() == split_namespace(category).1)
+        {
+            Some(constraint) => constraint,
+            None => return Ok(None),
+        };
+        if !self.unique_keys {
+            return Err(err_msg!(
+                Unsupported,
+                "Unique constraints are not supported by this store"
+            ));
+        }
+        constraint.unique_key(tags.unwrap_or_default())
+    }
+
+    /// The timer recording the encryption work performed by the session
+    #[inline]
+    pub(crate) fn crypto_timer(&self) -> CryptoTimer {
+        self.crypto_timer.clone()
+    }
+
+    /// The queue accepting the updates of this session, when they are
+    /// coalesced with concurrent updates. Transactions are never coalesced
+    #[allow(unused)]
+    #[inline]
+    pub(crate) fn write_queue(&self) -> Option<&WriteQueue> {
+        if self.transaction {
+            None
+        } else {
+            self.write_queue.as_ref()
+        }
+    }
+
     #[inline]
     fn pool(&self) -> Option<&Pool<DB>> {
         if let DbSessionState::Pending { pool, .. } = &self.state {
@@ -79,6 +398,100 @@ impl<DB: ExtDatabase> DbSession<DB> {
         }
     }
 
+    /// Release the active connection after the database connection has been
+    /// lost or an operation was abandoned. When `discard` is set, the connection
+    /// is closed instead of being returned to the pool.
+    ///
+    /// Returns `true` if the session may continue using a new connection from
+    /// the pool. Transactions cannot be resumed, and subsequent operations on
+    /// the session will fail with a `ConnectionLost` error.
+    pub(crate) fn reset_connection(&mut self, discard: bool) -> bool {
+        match std::mem::replace(&mut self.state, DbSessionState::Lost) {
+            DbSessionState::Active { mut conn, pool } => {
+                if discard || self.begin_pending {
+                    warn!("Discarding pool connection");
+                    self.begin_pending = false;
+                    drop(conn.release());
+                } else {
+                    // roll back any transaction left open by the abandoned operation
+                    DB::TransactionManager::start_rollback(&mut conn);
+                    self.release(conn);
+                }
+                if self.transaction {
+                    false
+                } else {
+                    self.state = DbSessionState::Pending { pool };
+                    true
+                }
+            }
+            DbSessionState::Pending { pool } => {
+                // the connection was lost before a transaction was started
+                self.state = DbSessionState::Pending { pool };
+                true
+            }
+            DbSessionState::Lost => false,
+        }
+    }
+
+    /// Return the connection of a non-transactional session to the pool. A
+    /// new connection is acquired by the next operation on the session
+    #[allow(unused)]
+    pub(crate) fn release_connection(&mut self) {
+        if self.transaction || self.begin_pending {
+            return;
+        }
+        if let DbSessionState::Active { pool, .. } = &self.state {
+            let pool = pool.clone();
+            if let DbSessionState::Active { conn, .. } =
+                std::mem::replace(&mut self.state, DbSessionState::Pending { pool })
+            {
+                self.release(conn);
+            }
+        }
+    }
+
+    /// Return a connection to the pool, first restoring the durability level
+    /// of the store if it was changed for this session
+    fn release(&mut self, conn: PoolConnection<DB>) {
+        if !self.durability_changed {
+            return;
+        }
+        self.durability_changed = false;
+        let durability = self.durability;
+        spawn_ok(async move {
+            let mut conn = conn;
+            if let Err(err) = DB::set_durability(&mut conn, durability).await {
+                warn!("Error restoring connection durability: {}", err);
+                drop(conn.release());
+            }
+        });
+    }
+
+    /// Set the durability level of the changes committed by this session
+    pub(crate) async fn set_durability(&mut self, durability: Durability) -> Result<(), Error> {
+        let level = if Some(durability) != self.durability {
+            Some(durability)
+        } else if self.durability_changed {
+            self.durability
+        } else {
+            self.session_durability = Some(durability);
+            return Ok(());
+        };
+        if let DbSessionState::Active { conn, .. } = &mut self.state {
+            if self.transaction {
+                return Err(err_msg!(
+                    Unsupported,
+                    "The durability of a transaction must be set before it is started"
+                ));
+            }
+            DB::set_durability(conn, level).await?;
+            self.durability_changed = level != self.durability;
+        }
+        // otherwise applied when a connection is acquired
+        self.session_durability = Some(durability);
+        Ok(())
+    }
+
     pub(crate) fn profile_and_key(&mut self) -> Option<(ProfileId, Arc<ProfileKey>)> {
         if let DbSessionKey::Active {
             profile_id,
@@ -98,22 +511,37 @@ impl<DB: ExtDatabase> DbSession<DB> {
     where
         I: for<'a> GetProfileKey<'a, DB>,
     {
+        if matches!(self.state, DbSessionState::Lost) {
+            return Err(err_msg!(
+                ConnectionLost,
+                "The transaction was aborted due to a lost connection or timeout"
+            ));
+        }
         if matches!(self.state, DbSessionState::Pending { .. }) {
             info!("Acquire pool connection");
-            let mut conn = self.pool().unwrap().acquire().await?;
+            let pool = self.pool().unwrap().clone();
+            let mut conn = pool.acquire().await?;
+            if let Some(durability) = self
+                .session_durability
+                .filter(|d| Some(*d) != self.durability)
+            {
+                // applied before starting a transaction, as SQLite does not
+                // permit the change within one
+                DB::set_durability(&mut conn, Some(durability)).await?;
+                self.durability_changed = true;
+            }
+            self.state = DbSessionState::Active { conn, pool };
             if self.transaction {
                 info!("Start transaction");
-                DB::start_transaction(&mut conn, false).await?;
+                self.start_transaction(false).await?;
             }
-            self.state = DbSessionState::Active { conn };
         }
         let profile_id = match &mut self.profile_key {
             DbSessionKey::Pending { cache, profile } => {
-                let cache = cache.clone();
-                let mut get_profile = String::new();
-                std::mem::swap(profile, &mut get_profile);
+                // the profile name is retained in case this future is dropped
+                let (cache, profile) = (cache.clone(), profile.clone());
                 let (profile_id, key) = init_key
-                    .call_once(self.connection_mut().unwrap(), cache, get_profile)
+                    .call_once(self.connection_mut().unwrap(), cache, profile)
                     .await?;
                 self.profile_key = DbSessionKey::Active { profile_id, key };
                 profile_id
@@ -125,10 +553,23 @@ impl<DB: ExtDatabase> DbSession<DB> {
             inner: self,
             profile_id,
             txn_depth,
-            false_txn: false,
+            // the session transaction is committed or rolled back on close
+            false_txn: self.transaction,
         })
     }
 
+    /// Start a transaction on the active connection.
+    ///
+    /// If this future is dropped before the transaction is confirmed, the state
+    /// of the connection is unknown and it will be closed instead of being
+    /// returned to the pool.
+    async fn start_transaction(&mut self, nested: bool) -> Result<(), Error> {
+        self.begin_pending = true;
+        DB::start_transaction(self.connection_mut().unwrap(), nested).await?;
+        self.begin_pending = false;
+        Ok(())
+    }
+
     #[inline]
     pub(crate) fn borrow_mut(&mut self) -> DbSessionRef<'_, DB> {
         DbSessionRef::Borrowed(self)
@@ -139,7 +580,83 @@ impl<DB: ExtDatabase> DbSession<DB> {
         DbSessionRef::Owned(self)
     }
 
+    /// Start a transaction for a group of operations which must be applied
+    /// atomically. Returns `false` if the session is already a transaction,
+    /// in which case the operations are applied with the transaction
+    pub(crate) async fn begin_atomic(&mut self) -> Result<bool, Error> {
+        if self.transaction {
+            return Ok(false);
+        }
+        let pool = match &self.state {
+            DbSessionState::Active { pool, .. } | DbSessionState::Pending { pool } => pool.clone(),
+            DbSessionState::Lost => {
+                return Err(err_msg!(
+                    ConnectionLost,
+                    "The session was aborted due to a lost connection or timeout"
+                ))
+            }
+        };
+        if matches!(self.state, DbSessionState::Active { .. }) {
+            info!("Start atomic transaction");
+            self.start_transaction(false).await?;
+        }
+        // otherwise started when a connection is acquired
+        self.transaction = true;
+        self.atomic = Some(pool);
+        Ok(true)
+    }
+
+    /// Commit or roll back the transaction started by `begin_atomic`. The
+    /// session may continue to be used after a lost connection, as it is
+    /// not a transaction
+    pub(crate) async fn end_atomic(&mut self, commit: bool) -> Result<(), Error> {
+        let pool = match self.atomic.take() {
+            Some(pool) => pool,
+            None => return Ok(()),
+        };
+        self.transaction = false;
+        if matches!(self.state, DbSessionState::Lost) {
+            self.state = DbSessionState::Pending { pool };
+            return if commit {
+                Err(err_msg!(
+                    ConnectionLost,
+                    "The operation was aborted due to a lost connection or timeout"
+                ))
+            } else {
+                Ok(())
+            };
+        }
+        if let DbSessionState::Active { conn, .. } = &mut self.state {
+            // the connection is discarded if this future is dropped
+            self.begin_pending = true;
+            let result = if commit {
+                info!("Commit atomic transaction");
+                DB::TransactionManager::commit(conn).await
+            } else {
+                info!("Roll-back atomic transaction");
+                DB::TransactionManager::rollback(conn).await
+            };
+            self.begin_pending = false;
+            if let Err(err) = result {
+                self.reset_connection(true);
+                return Err(err_msg!(Backend, "Error closing transaction").with_cause(err));
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) async fn close(mut self, commit: bool) -> Result<(), Error> {
+        // an unfinished atomic group is rolled back
+        self.end_atomic(false).await?;
+        if self.transaction && matches!(self.state, DbSessionState::Lost) {
+            self.transaction = false;
+            if commit {
+                return Err(err_msg!(
+                    ConnectionLost,
+                    "The transaction was aborted due to a lost connection or timeout"
+                ));
+            }
+        }
         if self.transaction {
             if let Some(conn) = self.connection_mut() {
                 if commit {
@@ -159,13 +676,27 @@ impl<DB: ExtDatabase> DbSession<DB> {
 
 impl<'q, DB: ExtDatabase> Drop for DbSession<DB> {
     fn drop(&mut self) {
-        if self.transaction {
-            if let Some(conn) = self.connection_mut() {
-                info!("Dropped transaction: roll-back");
-                DB::TransactionManager::start_rollback(conn);
+        if self.begin_pending {
+            if let DbSessionState::Active { conn, .. } =
+                std::mem::replace(&mut self.state, DbSessionState::Lost)
+            {
+                info!("Dropped session while starting transaction: closing connection");
+                drop(conn.release());
             }
         } else {
-            info!("Dropped pool connection")
+            if self.transaction {
+                if let Some(conn) = self.connection_mut() {
+                    info!("Dropped transaction: roll-back");
+                    DB::TransactionManager::start_rollback(conn);
+                }
+            } else {
+                info!("Dropped pool connection")
+            }
+            if let DbSessionState::Active { conn, .. } =
+                std::mem::replace(&mut self.state, DbSessionState::Lost)
+            {
+                self.release(conn);
+            }
         }
     }
 }
@@ -215,6 +746,13 @@ pub trait ExtDatabase: Database {
     ) -> BoxFuture<'_, Result<(), SqlxError>> {
         <Self as Database>::TransactionManager::begin(conn)
     }
+
+    /// Apply a durability level to a connection, or restore the default of
+    /// the database when `None`
+    fn set_durability(
+        conn: &mut PoolConnection<Self>,
+        durability: Option<Durability>,
+    ) -> BoxFuture<'_, Result<(), SqlxError>>;
 }
 
 pub enum DbSessionRef<'q, DB: ExtDatabase> {
@@ -271,13 +809,46 @@ impl<'q, DB: ExtDatabase> DbSessionActive<'q, DB> {
         self.txn_depth > 0
     }
 
+    #[inline]
+    pub fn history(&self) -> bool {
+        self.inner.history
+    }
+
+    /// Whether removed entries are marked as expired rather than deleted
+    #[allow(unused)]
+    #[inline]
+    pub fn soft_delete(&self) -> bool {
+        self.inner.soft_delete
+    }
+
+    /// Whether the item and tag tables are partitioned by profile
+    #[allow(unused)]
+    #[inline]
+    pub fn partitioned(&self) -> bool {
+        self.inner.partitioned
+    }
+
+    /// Whether the item table has a column for unique keys
+    #[allow(unused)]
+    #[inline]
+    pub fn unique_keys(&self) -> bool {
+        self.inner.unique_keys
+    }
+
+    /// The strategy used to generate the identifiers of new records
+    #[allow(unused)]
+    #[inline]
+    pub fn id_strategy(&self) -> IdStrategy {
+        self.inner.id_strategy
+    }
+
     #[allow(unused)]
     pub async fn transaction<'t>(&'t mut self) -> Result<DbSessionActive<'t, DB>, Error>
     where
         'q: 't,
     {
         info!("Start nested transaction");
-        DB::start_transaction(self.connection_mut(), true).await?;
+        self.inner.start_transaction(true).await?;
         Ok(DbSessionActive {
             inner: &mut *self.inner,
             profile_id: self.profile_id,
@@ -292,7 +863,7 @@ impl<'q, DB: ExtDatabase> DbSessionActive<'q, DB> {
     {
         if self.txn_depth == 0 {
             info!("Start transaction");
-            DB::start_transaction(self.connection_mut(), false).await?;
+            self.inner.start_transaction(false).await?;
             Ok(DbSessionActive {
                 inner: &mut *self.inner,
                 profile_id: self.profile_id,
@@ -312,9 +883,13 @@ impl<'q, DB: ExtDatabase> DbSessionActive<'q, DB> {
 
 impl<'a, DB: ExtDatabase> Drop for DbSessionActive<'a, DB> {
     fn drop(&mut self) {
-        if self.txn_depth > 1 && !self.false_txn {
-            info!("Roll-back dropped nested transaction");
-            DB::TransactionManager::start_rollback(self.connection_mut());
+        // roll back a transaction started by this instance which was not committed,
+        // including when the associated operation was cancelled
+        if self.txn_depth > 0 && !self.false_txn {
+            if let Some(conn) = self.inner.connection_mut() {
+                info!("Roll-back dropped transaction");
+                DB::TransactionManager::start_rollback(conn);
+            }
         }
     }
 }
@@ -336,9 +911,54 @@ where
 }
 
 pub struct EncScanEntry {
+    /// The insertion sequence number of the record
+    pub seq: i64,
     pub name: Vec<u8>,
     pub value: Vec<u8>,
     pub tags: Vec<u8>,
+    pub expiry: Option<chrono::NaiveDateTime>,
+}
+
+impl EncScanEntry {
+    /// The total size of the encrypted record
+    pub fn byte_len(&self) -> usize {
+        self.name.len() + self.value.len() + self.tags.len()
+    }
+}
+
+/// An encrypted record to be written by a batch insert
+pub struct EncInsertEntry {
+    pub category: Vec<u8>,
+    pub name: Vec<u8>,
+    pub value: Vec<u8>,
+    pub checksum: Option<Vec<u8>>,
+    pub tags: Vec<EncEntryTag>,
+    pub unique_key: Option<Vec<u8>>,
+}
+
+/// Aggregated storage usage for the records of a single encrypted category
+pub struct EncCategoryUsage {
+    pub profile_id: ProfileId,
+    pub kind: i16,
+    pub category: Vec<u8>,
+    pub count: i64,
+    pub bytes: i64,
+}
+
+/// A stored record to be verified by an integrity check
+pub struct EncIntegrityEntry {
+    pub kind: i16,
+    pub category: Vec<u8>,
+    pub name: Vec<u8>,
+    pub value: Vec<u8>,
+    pub checksum: Option<Vec<u8>>,
+}
+
+pub struct EncHistoryEntry {
+    pub value: Vec<u8>,
+    pub tags: Option<Vec<u8>>,
+    pub valid_from: chrono::NaiveDateTime,
+    pub valid_to: Option<chrono::NaiveDateTime>,
 }
 
 pub struct QueryParams<'q, DB: Database> {
@@ -395,6 +1015,10 @@ pub trait QueryPrepare {
         format!("?{}", index)
     }
 
+    fn fold_case(expr: &str) -> String {
+        format!("LOWER(CAST({} AS TEXT))", expr)
+    }
+
     fn limit_query<'q>(
         mut query: String,
         args: &mut QueryParams<'q, Self::DB>,
@@ -498,6 +1122,137 @@ pub(crate) fn decode_tags(tags: Vec<u8>) -> Result<Vec<EncEntryTag>, ()> {
     Ok(enc_tags)
 }
 
+/// Decrypt the category names of a storage usage summary. The categories of
+/// profiles whose keys are not available are reported without a name.
+pub fn decrypt_storage_report(
+    profiles: Vec<(ProfileId, String, Option<Arc<ProfileKey>>)>,
+    usage: Vec<EncCategoryUsage>,
+) -> Result<StorageReport, Error> {
+    let mut report = StorageReport::default();
+    let mut profile_keys = HashMap::with_capacity(profiles.len());
+    for (pid, name, key) in profiles {
+        report.add_profile(&name);
+        profile_keys.insert(pid, (name, key));
+    }
+    for row in usage {
+        let (name, key) = match profile_keys.get(&row.profile_id) {
+            Some(found) => found,
+            None => continue,
+        };
+        let kind = EntryKind::from_code(row.kind)
+            .ok_or_else(|| err_msg!(Unexpected, "Unknown entry kind: {}", row.kind))?;
+        let stored = key
+            .as_ref()
+            .map(|key| key.decrypt_entry_category(row.category))
+            .transpose()?;
+        let (namespace, category) = match stored.as_deref().map(split_namespace) {
+            Some((namespace, category)) => {
+                (namespace.map(str::to_string), Some(category.to_string()))
+            }
+            None => (None, None),
+        };
+        report.add(
+            name,
+            CategoryUsage {
+                kind,
+                namespace,
+                category,
+                count: row.count,
+                bytes: row.bytes,
+            },
+        );
+    }
+    Ok(report)
+}
+
+/// Decrypt a batch of stored records, comparing each value with its stored
+/// checksum when present
+pub fn check_integrity_batch(
+    enc_rows: Vec<EncIntegrityEntry>,
+    key: &ProfileKey,
+) -> Result<IntegrityReport, Error> {
+    let mut report = IntegrityReport::default();
+    for row in enc_rows {
+        report.checked += 1;
+        let kind = EntryKind::from_code(row.kind)
+            .ok_or_else(|| err_msg!(Unexpected, "Unknown entry kind: {}", row.kind))?;
+        let (category, name) = match (
+            key.decrypt_entry_category(row.category),
+            key.decrypt_entry_name(row.name),
+        ) {
+            (Ok(category), Ok(name)) => (category, name),
+            (category, name) => {
+                report.issues.push(IntegrityIssue {
+                    kind,
+                    category: category.ok(),
+                    name: name.ok(),
+                    reason: IntegrityIssueKind::WrongKey,
+                });
+                continue;
+            }
+        };
+        let reason = match key.decrypt_entry_value(category.as_bytes(), name.as_bytes(), row.value)
+        {
+            Err(_) => Some(IntegrityIssueKind::Corrupted),
+            Ok(value) => match row.checksum {
+                Some(checksum) => {
+                    let expected =
+                        key.value_checksum(category.as_bytes(), name.as_bytes(), &value)?;
+                    if expected == checksum {
+                        report.verified += 1;
+                        None
+                    } else {
+                        Some(IntegrityIssueKind::ChecksumMismatch)
+                    }
+                }
+                None => {
+                    report.unverified += 1;
+                    None
+                }
+            },
+        };
+        if let Some(reason) = reason {
+            report.issues.push(IntegrityIssue {
+                kind,
+                category: Some(category),
+                name: Some(name),
+                reason,
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Encrypt a batch of records for insertion, computing the value checksums
+/// when enabled for the store. Each record is paired with its unique key
+pub fn encrypt_insert_batch(
+    entries: Vec<(Entry, Option<Vec<u8>>)>,
+    key: &ProfileKey,
+    checksum: bool,
+) -> Result<Vec<EncInsertEntry>, Error> {
+    let mut batch = Vec::with_capacity(entries.len());
+    for (entry, unique_key) in entries {
+        let category = ProfileKey::prepare_input(entry.category.as_bytes());
+        let name = ProfileKey::prepare_input(entry.name.as_bytes());
+        let value = ProfileKey::prepare_input(entry.value.as_ref());
+        let checksum = if checksum {
+            Some(key.value_checksum(category.as_ref(), name.as_ref(), value.as_ref())?)
+        } else {
+            None
+        };
+        let value = key.encrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+        batch.push(EncInsertEntry {
+            category: key.encrypt_entry_category(category)?,
+            name: key.encrypt_entry_name(name)?,
+            value,
+            checksum,
+            tags: key.encrypt_entry_tags(prepare_tags(&entry.tags)?)?,
+            unique_key,
+        });
+    }
+    Ok(batch)
+}
+
 pub fn decrypt_scan_batch(
     category: String,
     enc_rows: Vec<EncScanEntry>,
@@ -510,23 +1265,138 @@ pub fn decrypt_scan_batch(
     Ok(batch)
 }
 
+/// Decrypt a page of scan results, retaining the row identifier of each
+/// record to track the position of the scan
+pub fn decrypt_scan_page(
+    category: String,
+    enc_rows: Vec<EncScanEntry>,
+    key: &ProfileKey,
+) -> Result<Vec<(i64, Entry)>, Error> {
+    let mut batch = Vec::with_capacity(enc_rows.len());
+    for enc_entry in enc_rows {
+        let seq = enc_entry.seq;
+        batch.push((seq, decrypt_scan_entry(category.clone(), enc_entry, key)?));
+    }
+    Ok(batch)
+}
+
 pub fn decrypt_scan_entry(
     category: String,
     enc_entry: EncScanEntry,
     key: &ProfileKey,
 ) -> Result<Entry, Error> {
     let name = key.decrypt_entry_name(enc_entry.name)?;
-    let value = key.decrypt_entry_value(category.as_bytes(), name.as_bytes(), enc_entry.value)?;
+    // encrypted values are never empty, so an empty value has been
+    // excluded by the projection of the scan
+    let value = if enc_entry.value.is_empty() {
+        SecretBytes::default()
+    } else {
+        key.decrypt_entry_value(category.as_bytes(), name.as_bytes(), enc_entry.value)?
+    };
     let tags = key.decrypt_entry_tags(
         decode_tags(enc_entry.tags).map_err(|_| err_msg!(Unexpected, "Error decoding tags"))?,
     )?;
-    Ok(Entry::new(category.to_string(), name, value, tags))
+    Ok(Entry::new(split_namespace(&category).1, name, value, tags)
+        .with_seq(EntrySeq::new(enc_entry.seq))
+        .with_expiry(enc_entry.expiry.map(from_history_timestamp).transpose()?))
 }
 
-pub fn expiry_timestamp(expire_ms: i64) -> Result<Expiry, Error> {
-    chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::milliseconds(expire_ms))
-        .ok_or_else(|| err_msg!(Unexpected, "Invalid expiry timestamp"))
+/// Decrypt the relation types and linked records of a set of entry links
+pub fn decrypt_links(
+    enc_links: Vec<(Vec<u8>, Vec<u8>, Vec<u8>)>,
+    key: &ProfileKey,
+) -> Result<Vec<EntryLink>, Error> {
+    let mut links = Vec::with_capacity(enc_links.len());
+    for (enc_rel_type, enc_category, enc_name) in enc_links {
+        let rel_type = String::from_utf8(key.decrypt_link_type(enc_rel_type)?.into_vec())
+            .map_err(err_map!(Encryption, "Invalid link relation type"))?;
+        let category = key.decrypt_entry_category(enc_category)?;
+        links.push(EntryLink::new(
+            rel_type,
+            split_namespace(&category).1,
+            key.decrypt_entry_name(enc_name)?,
+        ));
+    }
+    Ok(links)
+}
+
+pub fn decrypt_history_batch(
+    category: String,
+    name: String,
+    enc_rows: Vec<EncHistoryEntry>,
+    key: &ProfileKey,
+) -> Result<Vec<EntryVersion>, Error> {
+    let mut batch = Vec::with_capacity(enc_rows.len());
+    for enc_entry in enc_rows {
+        let value =
+            key.decrypt_entry_value(category.as_bytes(), name.as_bytes(), enc_entry.value)?;
+        let tags = if let Some(enc_tags) = enc_entry.tags {
+            key.decrypt_entry_tags(
+                decode_tags(enc_tags).map_err(|_| err_msg!(Unexpected, "Error decoding tags"))?,
+            )?
+        } else {
+            Vec::new()
+        };
+        batch.push(EntryVersion {
+            entry: Entry::new(split_namespace(&category).1, name.clone(), value, tags),
+            valid_from: from_history_timestamp(enc_entry.valid_from)?,
+            valid_to: enc_entry.valid_to.map(from_history_timestamp).transpose()?,
+        });
+    }
+    Ok(batch)
+}
+
+pub fn expiry_timestamp(expiry: EntryExpiry) -> Result<Expiry, Error> {
+    match expiry {
+        EntryExpiry::Relative(expire_ms) | EntryExpiry::Sliding(expire_ms) => {
+            chrono::Utc::now().checked_add_signed(chrono::Duration::milliseconds(expire_ms))
+        }
+        EntryExpiry::Absolute(timestamp_ms) => chrono::NaiveDateTime::from_timestamp_opt(
+            timestamp_ms.div_euclid(1000),
+            (timestamp_ms.rem_euclid(1000) * 1_000_000) as u32,
+        )
+        .map(|time| chrono::DateTime::from_utc(time, chrono::Utc)),
+    }
+    .ok_or_else(|| err_msg!(Unexpected, "Invalid expiry timestamp"))
+}
+
+/// The most recent timestamp assigned to an entry version, in microseconds
+static LAST_HISTORY_TIMESTAMP: AtomicI64 = AtomicI64::new(0);
+
+/// Get the timestamp of a new entry version. Timestamps are strictly
+/// increasing at the microsecond precision retained by the backends, so that
+/// the removal of an entry and a later insert by the same process are never
+/// mistaken for a replacement, which ends the previous version exactly when
+/// the new one begins
+pub fn history_timestamp() -> chrono::NaiveDateTime {
+    let now = chrono::Utc::now().timestamp_micros();
+    let prev = LAST_HISTORY_TIMESTAMP
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| {
+            Some(now.max(last + 1))
+        })
+        .unwrap_or(now);
+    let micros = now.max(prev + 1);
+    chrono::NaiveDateTime::from_timestamp_opt(
+        micros.div_euclid(1_000_000),
+        (micros.rem_euclid(1_000_000) * 1000) as u32,
+    )
+    .unwrap_or_else(|| chrono::Utc::now().naive_utc())
+}
+
+pub fn to_history_timestamp(time: SystemTime) -> Result<chrono::NaiveDateTime, Error> {
+    let since = time
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| err_msg!(Input, "Invalid history timestamp"))?;
+    chrono::NaiveDateTime::from_timestamp_opt(since.as_secs() as i64, since.subsec_nanos())
+        .ok_or_else(|| err_msg!(Input, "Invalid history timestamp"))
+}
+
+pub fn from_history_timestamp(time: chrono::NaiveDateTime) -> Result<SystemTime, Error> {
+    let secs = time.timestamp();
+    if secs < 0 {
+        return Err(err_msg!(Unexpected, "Invalid history timestamp"));
+    }
+    Ok(UNIX_EPOCH + Duration::new(secs as u64, time.timestamp_subsec_nanos()))
 }
 
 pub fn encode_tag_filter<Q: QueryPrepare>(
@@ -535,11 +1405,16 @@ pub fn encode_tag_filter<Q: QueryPrepare>(
     offset: usize,
 ) -> Result<Option<(String, Vec<Vec<u8>>)>, Error> {
     if let Some(tag_filter) = tag_filter {
+        let (fold_case, normalize) = (tag_filter.fold_case, tag_filter.normalize);
         let tag_query = tag_query(tag_filter.query)?;
         let mut enc = TagSqlEncoder::new(
             |name| Ok(key.encrypt_tag_name(ProfileKey::prepare_input(name.as_bytes()))?),
             |value| Ok(key.encrypt_tag_value(ProfileKey::prepare_input(value.as_bytes()))?),
-        );
+        )
+        .with_normalize(normalize);
+        if fold_case {
+            enc = enc.with_fold_case(Q::fold_case);
+        }
         if let Some(filter) = enc.encode_query(&tag_query)? {
             let filter = replace_arg_placeholders::<Q>(&filter, (offset as i64) + 1);
             Ok(Some((filter, enc.arguments)))
@@ -552,18 +1427,20 @@ pub fn encode_tag_filter<Q: QueryPrepare>(
 }
 
 // convert a slice of tags into a Vec, when ensuring there is
-// adequate space in the allocations to reuse them during encryption
+// adequate space in the allocations to reuse them during encryption.
+// plaintext values are NFC normalized, so that they may be matched by
+// normalized tag filters
 pub fn prepare_tags(tags: &[EntryTag]) -> Result<Vec<EntryTag>, Error> {
     let mut result = Vec::with_capacity(tags.len());
     for tag in tags {
-        result.push(match tag {
+        let tag = match tag {
             EntryTag::Plaintext(name, value) => EntryTag::Plaintext(
                 unsafe {
                     String::from_utf8_unchecked(
                         ProfileKey::prepare_input(name.as_bytes()).into_vec(),
                     )
                 },
-                value.clone(),
+                value.nfc().collect(),
             ),
             EntryTag::Encrypted(name, value) => EntryTag::Encrypted(
                 unsafe {
@@ -577,7 +1454,12 @@ pub fn prepare_tags(tags: &[EntryTag]) -> Result<Vec<EntryTag>, Error> {
                     )
                 },
             ),
-        });
+        };
+        // a tag name may have multiple values, but repeated values are
+        // only stored once
+        if !result.contains(&tag) {
+            result.push(tag);
+        }
     }
     Ok(result)
 }
@@ -605,12 +1487,84 @@ where
     Ok(query)
 }
 
+/// Normalize a set of search terms before deriving their tokens, removing
+/// empty and repeated terms
+pub fn prepare_search_terms(mut terms: Vec<String>) -> Vec<String> {
+    terms.retain(|t| !t.is_empty());
+    terms.sort_unstable();
+    terms.dedup();
+    terms
+}
+
+/// Build a condition on the `items` table matching the records indexed with
+/// every one of a set of distinct search tokens
+pub fn search_clause<'q, Q: QueryPrepare>(
+    args: &mut QueryParams<'q, Q::DB>,
+    tokens: Vec<Vec<u8>>,
+) -> String
+where
+    Vec<u8>: for<'e> Encode<'e, Q::DB> + Type<Q::DB>,
+{
+    let count = tokens.len();
+    let start_idx = (args.len() + 1) as i64;
+    args.extend(tokens);
+    format!(
+        " AND i.id IN (SELECT item_id FROM items_search WHERE token IN ({})
+        GROUP BY item_id HAVING COUNT(*) = {})",
+        replace_arg_placeholders::<Q>(&vec!["$$"; count].join(", "), start_idx),
+        count
+    )
+}
+
+const HISTORY_CURRENT_CLAUSE: &'static str = " AND EXISTS (SELECT 1 FROM items_history h
+    WHERE h.profile_id = i.profile_id AND h.kind = i.kind AND h.category = i.category
+    AND h.name = i.name AND h.valid_to IS NULL AND h.valid_from";
+// a version which does not begin exactly when a previous version ended was
+// written by an insert rather than a replace
+const HISTORY_CREATED_CLAUSE: &'static str = " AND EXISTS (SELECT 1 FROM items_history h
+    WHERE h.profile_id = i.profile_id AND h.kind = i.kind AND h.category = i.category
+    AND h.name = i.name AND h.valid_from > $$
+    AND NOT EXISTS (SELECT 1 FROM items_history p
+        WHERE p.profile_id = h.profile_id AND p.kind = h.kind AND p.category = h.category
+        AND p.name = h.name AND p.valid_to = h.valid_from))";
+
+/// Build a condition on the `items` table matching the records whose entry
+/// history falls within the bounds of a history filter
+pub fn history_clause<'q, Q: QueryPrepare>(
+    args: &mut QueryParams<'q, Q::DB>,
+    filter: HistoryFilter,
+) -> Result<String, Error>
+where
+    chrono::NaiveDateTime: for<'e> Encode<'e, Q::DB> + Type<Q::DB>,
+{
+    let mut clause = String::new();
+    if let Some(after) = filter.created_after {
+        args.push(to_history_timestamp(after)?);
+        clause.push_str(&replace_arg_placeholders::<Q>(
+            HISTORY_CREATED_CLAUSE,
+            args.len() as i64,
+        ));
+    }
+    if let Some(since) = filter.updated_since {
+        args.push(to_history_timestamp(since)?);
+        clause.push_str(HISTORY_CURRENT_CLAUSE);
+        clause.push_str(&replace_arg_placeholders::<Q>(" >= $$)", args.len() as i64));
+    }
+    if let Some(before) = filter.updated_before {
+        args.push(to_history_timestamp(before)?);
+        clause.push_str(HISTORY_CURRENT_CLAUSE);
+        clause.push_str(&replace_arg_placeholders::<Q>(" < $$)", args.len() as i64));
+    }
+    Ok(clause)
+}
+
 pub fn init_keys<'a>(
     method: StoreKeyMethod,
     pass_key: PassKey<'a>,
+    nonce_strategy: NonceStrategy,
 ) -> Result<(ProfileKey, Vec<u8>, StoreKey, String), Error> {
     let (store_key, store_key_ref) = method.resolve(pass_key)?;
-    let profile_key = ProfileKey::new()?;
+    let profile_key = ProfileKey::new_with_strategy(nonce_strategy)?;
     let enc_profile_key = encode_profile_key(&profile_key, &store_key)?;
     Ok((
         profile_key,
@@ -627,7 +1581,159 @@ pub fn encode_profile_key(
     store_key.wrap_data(profile_key.to_bytes()?)
 }
 
+/// The value encrypted with the store key to produce the `key_check`
+/// configuration value
+const KEY_CHECK_VALUE: &[u8] = b"askar:key_check";
+
+/// Encode the key check value stored in the configuration, which allows an
+/// incorrect pass key to be detected when the store is opened
+pub fn encode_key_check(store_key: &StoreKey) -> Result<String, Error> {
+    Ok(hex::encode(
+        store_key.wrap_data(SecretBytes::from_slice(KEY_CHECK_VALUE))?,
+    ))
+}
+
+/// Verify the key check value stored in the configuration against the
+/// resolved store key
+pub fn verify_key_check(store_key: &StoreKey, key_check: &str) -> Result<(), Error> {
+    let enc_check = hex::decode(key_check)
+        .map_err(err_map!(SchemaMismatch, "Invalid store key check value"))?;
+    match store_key.unwrap_data(enc_check) {
+        Ok(check) if &*check == KEY_CHECK_VALUE => Ok(()),
+        _ => Err(err_msg!(
+            WrongPassKey,
+            "The pass key does not match the store key"
+        )),
+    }
+}
+
+/// Limit the time taken to open a store, including establishing the
+/// connection and deriving the store key
+pub async fn with_open_timeout<T>(
+    open_timeout: Option<Duration>,
+    f: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    if let Some(dur) = open_timeout {
+        timeout(dur, f)
+            .await
+            .unwrap_or_else(|| Err(err_msg!(Busy, "Timed out opening the store")))
+    } else {
+        f.await
+    }
+}
+
+/// Re-wrap a batch of encrypted profile keys under a new store key.
+/// The profile keys themselves are not decoded.
+pub fn rewrap_profile_keys(
+    keys: Vec<(ProfileId, Vec<u8>)>,
+    prev_key: &StoreKey,
+    store_key: &StoreKey,
+) -> Result<Vec<(ProfileId, Vec<u8>)>, Error> {
+    keys.into_iter()
+        .map(|(pid, enc_key)| {
+            let key = prev_key
+                .unwrap_data(enc_key)
+                .map_err(err_map!(Encryption, "Error decrypting profile key"))?;
+            Ok((pid, store_key.wrap_data(key)?))
+        })
+        .collect()
+}
+
+/// Expand a row template (using `$$` placeholders) into a VALUES list
+pub fn batch_values_clause<Q: QueryPrepare>(row: &str, rows: usize, row_params: i64) -> String {
+    let mut clause = String::with_capacity((row.len() + 2) * rows);
+    for idx in 0..rows {
+        if idx > 0 {
+            clause.push_str(", ");
+        }
+        clause.push_str(&replace_arg_placeholders::<Q>(
+            row,
+            (idx as i64) * row_params + 1,
+        ));
+    }
+    clause
+}
+
 #[inline]
 pub fn random_profile_name() -> String {
     uuid::Uuid::new_v4().to_string()
 }
+
+/// The tables created when provisioning a store
+pub const STORE_TABLES: &[&str] = &[
+    "config",
+    "profiles",
+    "items",
+    "items_tags",
+    "items_search",
+    "items_history",
+];
+
+/// The detected state of the store tables in a database
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemaState {
+    /// No store tables are present
+    Empty,
+    /// Some store tables are present, but the store configuration is incomplete.
+    /// This is normally the result of an interrupted provisioning attempt.
+    Partial {
+        /// Whether any records have been written to the `items` table
+        has_items: bool,
+    },
+    /// All store tables are present and empty, but the store configuration
+    /// has not been written. This is the case when the schema is created
+    /// from a provisioning script.
+    Unconfigured,
+    /// The store tables and configuration are present
+    Complete,
+}
+
+impl SchemaState {
+    /// Determine the schema state from the set of store tables which exist,
+    /// the number of required configuration values, and whether any items exist
+    pub fn detect(tables: &[String], config_count: i64, has_items: bool) -> Self {
+        if tables.is_empty() {
+            Self::Empty
+        } else if tables.len() == STORE_TABLES.len() {
+            if config_count == REQUIRED_CONFIG.len() as i64 {
+                Self::Complete
+            } else if !has_items {
+                Self::Unconfigured
+            } else {
+                Self::Partial { has_items }
+            }
+        } else {
+            Self::Partial { has_items }
+        }
+    }
+
+    /// Check that an existing store may be opened
+    pub fn check_open(self) -> Result<(), Error> {
+        match self {
+            Self::Complete => Ok(()),
+            Self::Empty | Self::Unconfigured => {
+                Err(err_msg!(NotFound, "The store has not been provisioned"))
+            }
+            Self::Partial { .. } => Err(err_msg!(
+                SchemaMismatch,
+                "The store schema is incomplete: provisioning may have been interrupted"
+            )),
+        }
+    }
+}
+
+/// The configuration values which must be present in a complete store
+pub const REQUIRED_CONFIG: &[&str] = &["default_profile", "key", "version"];
+
+/// Read the access mode recorded in the store configuration
+pub const ACCESS_MODE_QUERY: &'static str = "SELECT value FROM config WHERE name='access_mode'";
+
+/// Parse the access mode read from the store configuration, which permits
+/// modifications when it has not been set
+pub fn parse_access_mode(value: Option<String>) -> Result<AccessMode, Error> {
+    value
+        .as_deref()
+        .map(AccessMode::parse)
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
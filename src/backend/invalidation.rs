@@ -0,0 +1,35 @@
+use std::fmt::Debug;
+
+use crate::{protect::ProfileId, storage::EntryKind};
+
+/// A record which was inserted, replaced or removed through any instance of
+/// the store, including the instance receiving the notification.
+///
+/// The category and name are provided in their encrypted form, as stored.
+/// These are the same for every instance sharing the store key, so that the
+/// plaintext values are never broadcast.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordInvalidation {
+    /// The identifier of the profile containing the record
+    pub profile_id: ProfileId,
+    /// The kind of the record
+    pub kind: EntryKind,
+    /// The encrypted record category
+    pub category: Vec<u8>,
+    /// The encrypted record name
+    pub name: Vec<u8>,
+}
+
+/// A cache layer notified when records are modified by another replica of
+/// the store.
+///
+/// The methods are called from the subscriber task of the store, and must not
+/// block for extended periods.
+pub trait InvalidationHook: Debug + Send + Sync {
+    /// Discard any cached copy of a modified record
+    fn invalidate(&self, record: &RecordInvalidation);
+
+    /// Discard all cached records, as notifications may have been missed
+    /// while the subscriber was reconnecting
+    fn reset(&self);
+}
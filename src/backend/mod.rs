@@ -18,5 +18,13 @@ pub mod postgres;
 /// Sqlite database support
 pub mod sqlite;
 
+mod invalidation;
+pub use self::invalidation::{InvalidationHook, RecordInvalidation};
+
+mod scoped;
+pub use self::scoped::ScopedQueryBackend;
+
 mod types;
-pub use self::types::{Backend, ManageBackend, QueryBackend};
+pub use self::types::{
+    AccessMode, Backend, Durability, MaintenanceMode, ManageBackend, QueryBackend,
+};
@@ -1,6 +1,12 @@
-use std::collections::BTreeMap;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryInto;
 use std::fmt::{self, Debug, Formatter};
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::time::{Duration, SystemTime};
 
 use async_stream::try_stream;
 
@@ -10,62 +16,241 @@ use futures_lite::{
 };
 
 use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
     pool::PoolConnection,
-    postgres::{PgPool, Postgres},
-    Row,
+    postgres::{PgArgumentBuffer, PgConnection, PgPool, PgTypeInfo, PgValueRef, Postgres},
+    Connection, Decode, Encode, Error as SqlxError, Executor, Row, Type, TypeInfo, ValueRef,
 };
+use zeroize::Zeroize;
 
 use crate::{
     backend::{
         db_utils::{
-            decode_tags, decrypt_scan_batch, encode_profile_key, encode_tag_filter,
-            expiry_timestamp, extend_query, prepare_tags, random_profile_name,
-            replace_arg_placeholders, DbSession, DbSessionActive, DbSessionRef, EncScanEntry,
-            ExtDatabase, QueryParams, QueryPrepare, PAGE_SIZE,
+            batch_values_clause, check_integrity_batch, decode_tags, decrypt_history_batch,
+            decrypt_links, decrypt_scan_batch, decrypt_scan_page, decrypt_storage_report,
+            encode_key_check, encode_tag_filter, encrypt_insert_batch, expiry_timestamp,
+            extend_query, from_history_timestamp, history_clause, history_timestamp,
+            parse_access_mode, prepare_search_terms, prepare_tags, random_profile_name,
+            replace_arg_placeholders, rewrap_profile_keys, search_clause, to_history_timestamp,
+            DbSession, DbSessionActive, DbSessionRef, EncCategoryUsage, EncHistoryEntry,
+            EncInsertEntry, EncIntegrityEntry, EncScanEntry, ExtDatabase, QueryParams,
+            QueryPrepare, ACCESS_MODE_QUERY, INSERT_BATCH_SIZE, PAGE_BYTES, PAGE_SIZE,
+            REKEY_PAGE_SIZE, STORE_TABLES,
         },
-        types::{Backend, QueryBackend},
+        types::{AccessMode, Backend, Durability, MaintenanceMode, QueryBackend},
     },
-    error::Error,
+    crypto::buffer::SecretBytes,
+    error::{Error, ErrorKind},
     future::{unblock, BoxFuture},
-    protect::{EntryEncryptor, KeyCache, PassKey, ProfileId, ProfileKey, StoreKeyMethod},
-    storage::{EncEntryTag, Entry, EntryKind, EntryOperation, EntryTag, Scan, TagFilter},
+    protect::{
+        EntryEncryptor, KeyCache, KeyCacheStats, NonceStrategy, PassKey, ProfileId, ProfileKey,
+        Protected, StoreKeyMethod, TenantKeyProvider,
+    },
+    storage::{
+        split_namespace, EncEntryTag, Entry, EntryExpiry, EntryHash, EntryId, EntryKind, EntryLink,
+        EntryOperation, EntrySeq, EntryTag, EntryVersion, HistoryFilter, IntegrityReport,
+        ProfileShredding, Projection, Scan, StorageReport, StoreCapabilities, StoreTuning,
+        TagFilter, UniqueConstraint,
+    },
 };
 
+#[cfg(feature = "raw_query")]
+use crate::storage::{RawRow, RawValue};
+
 const COUNT_QUERY: &'static str = "SELECT COUNT(*) FROM items i
     WHERE profile_id = $1 AND kind = $2 AND category = $3
     AND (expiry IS NULL OR expiry > CURRENT_TIMESTAMP)";
 const DELETE_QUERY: &'static str = "DELETE FROM items
     WHERE profile_id = $1 AND kind = $2 AND category = $3 AND name = $4";
-const FETCH_QUERY: &'static str = "SELECT id, value,
+const FETCH_QUERY: &'static str = "SELECT seq, value,
     (SELECT ARRAY_TO_STRING(ARRAY_AGG(it.plaintext || ':'
         || ENCODE(it.name, 'hex') || ':' || ENCODE(it.value, 'hex')), ',')
-        FROM items_tags it WHERE it.item_id = i.id) tags
+        FROM items_tags it WHERE it.item_id = i.id) tags, expiry_sliding, expiry
     FROM items i
     WHERE profile_id = $1 AND kind = $2 AND category = $3 AND name = $4
     AND (expiry IS NULL OR expiry > CURRENT_TIMESTAMP)";
-const FETCH_QUERY_UPDATE: &'static str = "SELECT id, value,
+const FETCH_QUERY_UPDATE: &'static str = "SELECT seq, value,
     (SELECT ARRAY_TO_STRING(ARRAY_AGG(it.plaintext || ':'
         || ENCODE(it.name, 'hex') || ':' || ENCODE(it.value, 'hex')), ',')
-        FROM items_tags it WHERE it.item_id = i.id) tags
+        FROM items_tags it WHERE it.item_id = i.id) tags, expiry_sliding, expiry
     FROM items i
     WHERE profile_id = $1 AND kind = $2 AND category = $3 AND name = $4
     AND (expiry IS NULL OR expiry > CURRENT_TIMESTAMP) FOR UPDATE";
-const INSERT_QUERY: &'static str =
-    "INSERT INTO items (profile_id, kind, category, name, value, expiry)
-    VALUES ($1, $2, $3, $4, $5, $6)
-    ON CONFLICT DO NOTHING RETURNING id";
-const SCAN_QUERY: &'static str = "SELECT id, name, value,
+const FETCH_BY_ID_QUERY: &'static str = "SELECT seq, category, name, value,
     (SELECT ARRAY_TO_STRING(ARRAY_AGG(it.plaintext || ':'
         || ENCODE(it.name, 'hex') || ':' || ENCODE(it.value, 'hex')), ',')
-        FROM items_tags it WHERE it.item_id = i.id) tags
-    FROM items i WHERE profile_id = $1 AND kind = $2 AND category = $3
+        FROM items_tags it WHERE it.item_id = i.id) tags, expiry_sliding, expiry
+    FROM items i
+    WHERE profile_id = $1 AND kind = $2 AND id = $3
+    AND (expiry IS NULL OR expiry > CURRENT_TIMESTAMP)";
+const FETCH_VALUE_QUERY: &'static str = "SELECT value FROM items
+    WHERE profile_id = $1 AND kind = $2 AND category = $3 AND name = $4
+    AND (expiry IS NULL OR expiry > CURRENT_TIMESTAMP)";
+const UNIQUE_CONFLICT_QUERY: &'static str = "SELECT EXISTS(SELECT 1 FROM items
+    WHERE profile_id = $1 AND kind = $2 AND category = $3 AND unique_key = $4
+    AND name != $5)";
+const EXISTS_QUERY: &'static str = "SELECT 1 FROM items
+    WHERE profile_id = $1 AND kind = $2 AND category = $3 AND name = $4
+    AND (expiry IS NULL OR expiry > CURRENT_TIMESTAMP) LIMIT 1";
+const EXTEND_EXPIRY_QUERY: &'static str = "UPDATE items SET expiry = $3
+    WHERE profile_id = $1 AND seq = $2";
+const COLLECTION_UPDATE_QUERY: &'static str = "UPDATE items SET collection = $5
+    WHERE profile_id = $1 AND kind = $2 AND category = $3 AND name = $4
+    AND (expiry IS NULL OR expiry > CURRENT_TIMESTAMP)";
+const COLLECTION_MEMBERS_QUERY: &'static str = "SELECT category, name FROM items
+    WHERE profile_id = $1 AND kind = $2 AND collection = $3
+    AND (expiry IS NULL OR expiry > CURRENT_TIMESTAMP)";
+const LINK_INSERT_QUERY: &'static str = "INSERT INTO items_links
+    (item_id, target_id, rel_type) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING";
+const LINK_INSERT_PARTITIONED_QUERY: &'static str = "INSERT INTO items_links
+    (item_id, target_id, rel_type, profile_id) VALUES ($1, $2, $3, $4)
+    ON CONFLICT DO NOTHING";
+const LINK_DELETE_QUERY: &'static str = "DELETE FROM items_links
+    WHERE item_id = $1 AND target_id = $2 AND rel_type = $3";
+const LINKS_QUERY: &'static str = "SELECT l.rel_type, t.category, t.name
+    FROM items_links l JOIN items t ON t.id = l.target_id
+    WHERE l.item_id = $1 AND (t.expiry IS NULL OR t.expiry > CURRENT_TIMESTAMP)";
+const LINKS_INCOMING_QUERY: &'static str = "SELECT l.rel_type, t.category, t.name
+    FROM items_links l JOIN items t ON t.id = l.item_id
+    WHERE l.target_id = $1 AND (t.expiry IS NULL OR t.expiry > CURRENT_TIMESTAMP)";
+const METADATA_FETCH_QUERY: &'static str = "SELECT metadata FROM items
+    WHERE profile_id = $1 AND kind = $2 AND category = $3 AND name = $4
+    AND (expiry IS NULL OR expiry > CURRENT_TIMESTAMP)";
+const METADATA_UPDATE_QUERY: &'static str = "UPDATE items SET metadata = $5
+    WHERE profile_id = $1 AND kind = $2 AND category = $3 AND name = $4
     AND (expiry IS NULL OR expiry > CURRENT_TIMESTAMP)";
+const INSERT_QUERY: &'static str =
+    "INSERT INTO items (profile_id, kind, category, name, value, expiry, expiry_sliding)
+    VALUES ($1, $2, $3, $4, $5, $6, $7)
+    ON CONFLICT DO NOTHING RETURNING id";
+const INSERT_CHECKSUM_QUERY: &'static str = "INSERT INTO items
+    (profile_id, kind, category, name, value, expiry, expiry_sliding, checksum)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+    ON CONFLICT DO NOTHING RETURNING id";
+const INSERT_UNIQUE_QUERY: &'static str = "INSERT INTO items
+    (profile_id, kind, category, name, value, expiry, expiry_sliding, unique_key)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+    ON CONFLICT DO NOTHING RETURNING id";
+const INSERT_CHECKSUM_UNIQUE_QUERY: &'static str = "INSERT INTO items
+    (profile_id, kind, category, name, value, expiry, expiry_sliding, checksum, unique_key)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+    ON CONFLICT DO NOTHING RETURNING id";
+const INTEGRITY_QUERY: &'static str = "SELECT kind, category, name, value, checksum
+    FROM items WHERE profile_id = $1
+    AND (expiry IS NULL OR expiry > CURRENT_TIMESTAMP) ORDER BY id";
+const INTEGRITY_NO_CHECKSUM_QUERY: &'static str = "SELECT kind, category, name, value, NULL::bytea
+    FROM items WHERE profile_id = $1
+    AND (expiry IS NULL OR expiry > CURRENT_TIMESTAMP) ORDER BY id";
+const SCAN_TAGS_COLUMN: &'static str = "(SELECT ARRAY_TO_STRING(ARRAY_AGG(it.plaintext || ':'
+        || ENCODE(it.name, 'hex') || ':' || ENCODE(it.value, 'hex')), ',')
+        FROM items_tags it WHERE it.item_id = i.id)";
 const DELETE_ALL_QUERY: &'static str = "DELETE FROM items i
     WHERE i.profile_id = $1 AND i.kind = $2 AND i.category = $3";
+// removed entries are expired and renamed, so that the name may be reused
+const SOFT_DELETE_QUERY: &'static str = "UPDATE items
+    SET expiry = CURRENT_TIMESTAMP, name = CONVERT_TO('removed:' || id::text, 'UTF8')
+    WHERE profile_id = $1 AND kind = $2 AND category = $3 AND name = $4";
+const SOFT_DELETE_ALL_QUERY: &'static str = "UPDATE items i
+    SET expiry = CURRENT_TIMESTAMP, name = CONVERT_TO('removed:' || i.id::text, 'UTF8')
+    WHERE i.profile_id = $1 AND i.kind = $2 AND i.category = $3
+    AND (i.expiry IS NULL OR i.expiry > CURRENT_TIMESTAMP)";
+// the unique keys of removed entries are also released
+const SOFT_DELETE_UNIQUE_QUERY: &'static str = "UPDATE items
+    SET expiry = CURRENT_TIMESTAMP, name = CONVERT_TO('removed:' || id::text, 'UTF8'),
+    unique_key = NULL
+    WHERE profile_id = $1 AND kind = $2 AND category = $3 AND name = $4";
+const SOFT_DELETE_ALL_UNIQUE_QUERY: &'static str = "UPDATE items i
+    SET expiry = CURRENT_TIMESTAMP, name = CONVERT_TO('removed:' || i.id::text, 'UTF8'),
+    unique_key = NULL
+    WHERE i.profile_id = $1 AND i.kind = $2 AND i.category = $3
+    AND (i.expiry IS NULL OR i.expiry > CURRENT_TIMESTAMP)";
+// each batch is inserted using a single statement over array parameters
+const INSERT_BATCH_QUERY: &'static str = "INSERT INTO items
+    (profile_id, kind, category, name, value)
+    SELECT $1::bigint, $2::smallint, * FROM UNNEST($3::bytea[], $4::bytea[], $5::bytea[])
+    ON CONFLICT DO NOTHING RETURNING id, category, name";
+const INSERT_BATCH_CHECKSUM_QUERY: &'static str = "INSERT INTO items
+    (profile_id, kind, category, name, value, checksum)
+    SELECT $1::bigint, $2::smallint, *
+    FROM UNNEST($3::bytea[], $4::bytea[], $5::bytea[], $6::bytea[])
+    ON CONFLICT DO NOTHING RETURNING id, category, name";
+const INSERT_BATCH_UNIQUE_QUERY: &'static str = "INSERT INTO items
+    (profile_id, kind, category, name, value, unique_key)
+    SELECT $1::bigint, $2::smallint, *
+    FROM UNNEST($3::bytea[], $4::bytea[], $5::bytea[], $6::bytea[])
+    ON CONFLICT DO NOTHING RETURNING id, category, name";
+const INSERT_BATCH_CHECKSUM_UNIQUE_QUERY: &'static str = "INSERT INTO items
+    (profile_id, kind, category, name, value, checksum, unique_key)
+    SELECT $1::bigint, $2::smallint, *
+    FROM UNNEST($3::bytea[], $4::bytea[], $5::bytea[], $6::bytea[], $7::bytea[])
+    ON CONFLICT DO NOTHING RETURNING id, category, name";
+const TAG_INSERT_BATCH_QUERY: &'static str = "INSERT INTO items_tags
+    (item_id, name, value, plaintext)
+    SELECT * FROM UNNEST($1::bigint[], $2::bytea[], $3::bytea[], $4::smallint[])";
+const TAG_INSERT_BATCH_PARTITIONED_QUERY: &'static str = "INSERT INTO items_tags
+    (item_id, name, value, plaintext, profile_id)
+    SELECT *, $5::bigint FROM UNNEST($1::bigint[], $2::bytea[], $3::bytea[], $4::smallint[])";
+const TOUCH_SELECT_QUERY: &'static str = "SELECT i.id FROM items i
+    WHERE i.profile_id = $1 AND i.kind = $2 AND i.category = $3
+    AND (i.expiry IS NULL OR i.expiry > CURRENT_TIMESTAMP)";
 const TAG_INSERT_QUERY: &'static str = "INSERT INTO items_tags
     (item_id, name, value, plaintext) VALUES ($1, $2, $3, $4)";
+const TAG_INSERT_PARTITIONED_QUERY: &'static str = "INSERT INTO items_tags
+    (item_id, name, value, plaintext, profile_id) VALUES ($1, $2, $3, $4, $5)";
+const SEARCH_ITEM_QUERY: &'static str = "SELECT id FROM items
+    WHERE profile_id = $1 AND kind = $2 AND category = $3 AND name = $4
+    AND (expiry IS NULL OR expiry > CURRENT_TIMESTAMP)";
+const SEARCH_DELETE_QUERY: &'static str = "DELETE FROM items_search WHERE item_id = $1";
+const SEARCH_INSERT_QUERY: &'static str = "INSERT INTO items_search
+    (item_id, token) VALUES ($1, $2)";
+const SEARCH_INSERT_PARTITIONED_QUERY: &'static str = "INSERT INTO items_search
+    (item_id, token, profile_id) VALUES ($1, $2, $3)";
+const HISTORY_INSERT_QUERY: &'static str = "INSERT INTO items_history
+    (profile_id, kind, category, name, value, tags, valid_from)
+    SELECT i.profile_id, i.kind, i.category, i.name, i.value,
+    (SELECT ARRAY_TO_STRING(ARRAY_AGG(it.plaintext || ':'
+        || ENCODE(it.name, 'hex') || ':' || ENCODE(it.value, 'hex')), ',')
+        FROM items_tags it WHERE it.item_id = i.id), $2
+    FROM items i WHERE i.id = $1 AND i.profile_id = $3";
+const HISTORY_INSERT_BATCH_QUERY: &'static str = "INSERT INTO items_history
+    (profile_id, kind, category, name, value, tags, valid_from)
+    SELECT i.profile_id, i.kind, i.category, i.name, i.value,
+    (SELECT ARRAY_TO_STRING(ARRAY_AGG(it.plaintext || ':'
+        || ENCODE(it.name, 'hex') || ':' || ENCODE(it.value, 'hex')), ',')
+        FROM items_tags it WHERE it.item_id = i.id), $2
+    FROM items i WHERE i.id = ANY($1) AND i.profile_id = $3";
+const HISTORY_CLOSE_QUERY: &'static str = "UPDATE items_history SET valid_to = $5
+    WHERE profile_id = $1 AND kind = $2 AND category = $3 AND name = $4
+    AND valid_to IS NULL";
+const HISTORY_CLOSE_ALL_QUERY: &'static str = "SELECT i.profile_id, i.kind, i.category, i.name
+    FROM items i WHERE i.profile_id = $1 AND i.kind = $2 AND i.category = $3";
+const HISTORY_FETCH_AT_QUERY: &'static str = "SELECT value, tags, valid_from, valid_to
+    FROM items_history
+    WHERE profile_id = $1 AND kind = $2 AND category = $3 AND name = $4
+    AND valid_from <= $5 AND (valid_to IS NULL OR valid_to > $5)
+    ORDER BY valid_from DESC, id DESC LIMIT 1";
+const HISTORY_LIST_QUERY: &'static str = "SELECT value, tags, valid_from, valid_to
+    FROM items_history
+    WHERE profile_id = $1 AND kind = $2 AND category = $3 AND name = $4
+    ORDER BY valid_from DESC, id DESC";
+const PROFILE_KEYS_QUERY: &'static str = "SELECT id, name, reference, profile_key
+    FROM profiles WHERE profile_key IS NOT NULL";
+const STORAGE_REPORT_QUERY: &'static str = "SELECT i.profile_id, i.kind, i.category, COUNT(*),
+    SUM(LENGTH(i.category) + LENGTH(i.name) + LENGTH(i.value)
+        + (SELECT COALESCE(SUM(LENGTH(it.name) + LENGTH(it.value)), 0)
+        FROM items_tags it WHERE it.item_id = i.id))::BIGINT
+    FROM items i GROUP BY i.profile_id, i.kind, i.category";
+const REKEY_FETCH_QUERY: &'static str = "SELECT id, profile_key FROM profiles
+    WHERE profile_key IS NOT NULL AND reference IS NULL ORDER BY id LIMIT $1";
+const REKEY_FETCH_NEXT_QUERY: &'static str = "SELECT id, profile_key FROM profiles
+    WHERE id > $1 AND profile_key IS NOT NULL AND reference IS NULL ORDER BY id LIMIT $2";
+
+mod notify;
+use notify::InvalidationListener;
 
 mod provision;
+use provision::AdminConnect;
 pub use provision::PostgresStoreOptions;
 
 #[cfg(any(test, feature = "pg_test"))]
@@ -78,6 +263,17 @@ pub struct PostgresStore {
     key_cache: Arc<KeyCache>,
     host: String,
     name: String,
+    history: bool,
+    nonce_strategy: NonceStrategy,
+    soft_delete: bool,
+    partitioned: bool,
+    value_checksum: bool,
+    capabilities: StoreCapabilities,
+    unique_constraints: Arc<Vec<UniqueConstraint>>,
+    page_size: AtomicUsize,
+    admin: Option<AdminConnect>,
+    durability: Option<Durability>,
+    invalidation: Option<InvalidationListener>,
 }
 
 impl PostgresStore {
@@ -87,6 +283,8 @@ impl PostgresStore {
         key_cache: KeyCache,
         host: String,
         name: String,
+        history: bool,
+        nonce_strategy: NonceStrategy,
     ) -> Self {
         Self {
             conn_pool,
@@ -94,8 +292,64 @@ impl PostgresStore {
             key_cache: Arc::new(key_cache),
             host,
             name,
+            history,
+            nonce_strategy,
+            soft_delete: false,
+            partitioned: false,
+            value_checksum: false,
+            capabilities: StoreCapabilities::provisioned(history, false),
+            unique_constraints: Arc::new(Vec::new()),
+            page_size: AtomicUsize::new(PAGE_SIZE),
+            admin: None,
+            durability: None,
+            invalidation: None,
         }
     }
+
+    /// Set whether entries and profiles are removed using `UPDATE` statements
+    /// only, for roles without `DELETE` privileges
+    pub(crate) fn with_soft_delete(mut self, soft_delete: bool) -> Self {
+        self.soft_delete = soft_delete;
+        self
+    }
+
+    /// Set whether the item and tag tables are hash-partitioned by profile,
+    /// in which case the tag table carries the profile identifier
+    pub(crate) fn with_partitioned(mut self, partitioned: bool) -> Self {
+        self.partitioned = partitioned;
+        self
+    }
+
+    /// Set whether a checksum is stored alongside each entry value
+    pub(crate) fn with_value_checksum(mut self, value_checksum: bool) -> Self {
+        self.value_checksum = value_checksum;
+        self
+    }
+
+    /// Set the optional features recorded in the store configuration
+    pub(crate) fn with_capabilities(mut self, capabilities: StoreCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Set the durability level applied to each connection by the pool
+    pub(crate) fn with_durability(mut self, durability: Option<Durability>) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Set the subscriber task relaying record notifications to a cache layer
+    pub(crate) fn with_invalidation(mut self, listener: Option<InvalidationListener>) -> Self {
+        self.invalidation = listener;
+        self
+    }
+
+    /// Set the connection details used for administrative operations such as
+    /// rekeying, when these use separate credentials from the connection pool
+    pub(crate) fn with_admin(mut self, admin: Option<AdminConnect>) -> Self {
+        self.admin = admin;
+        self
+    }
 }
 
 impl Backend for PostgresStore {
@@ -104,15 +358,16 @@ impl Backend for PostgresStore {
     fn create_profile(&self, name: Option<String>) -> BoxFuture<'_, Result<String, Error>> {
         let name = name.unwrap_or_else(random_profile_name);
         Box::pin(async move {
-            let key = ProfileKey::new()?;
-            let enc_key = key.to_bytes()?;
+            let key = ProfileKey::new_with_strategy(self.nonce_strategy)?;
+            let (enc_key, reference) = self.key_cache.wrap_key(name.clone(), &key).await?;
             let mut conn = self.conn_pool.acquire().await?;
             if let Some(pid) = sqlx::query_scalar(
-                "INSERT INTO profiles (name, profile_key) VALUES ($1, $2) 
+                "INSERT INTO profiles (name, profile_key, reference) VALUES ($1, $2, $3)
                 ON CONFLICT DO NOTHING RETURNING id",
             )
             .bind(&name)
-            .bind(enc_key.as_ref())
+            .bind(enc_key.as_slice())
+            .bind(reference)
             .fetch_optional(&mut conn)
             .await?
             {
@@ -130,15 +385,63 @@ impl Backend for PostgresStore {
         self.default_profile.as_str()
     }
 
+    fn capabilities(&self) -> StoreCapabilities {
+        // the table layout and removal strategy are detected when opening
+        self.capabilities
+            .with_partitioned(self.partitioned)
+            .with_soft_delete(self.soft_delete)
+    }
+
     fn remove_profile(&self, name: String) -> BoxFuture<'_, Result<bool, Error>> {
         Box::pin(async move {
             let mut conn = self.conn_pool.acquire().await?;
-            Ok(sqlx::query("DELETE FROM profiles WHERE name=$1")
+            let query = if self.soft_delete {
+                // discarding the profile key renders the profile's entries unreadable
+                "UPDATE profiles SET name = 'removed:' || id::text, profile_key = NULL
+                WHERE name=$1"
+            } else {
+                "DELETE FROM profiles WHERE name=$1"
+            };
+            let removed = sqlx::query(query)
                 .bind(&name)
                 .execute(&mut conn)
                 .await?
                 .rows_affected()
-                != 0)
+                != 0;
+            self.key_cache.remove_profile(&name);
+            Ok(removed)
+        })
+    }
+
+    fn shred_profile(
+        &self,
+        name: String,
+    ) -> BoxFuture<'_, Result<Option<ProfileShredding>, Error>> {
+        Box::pin(async move {
+            let mut txn = self.conn_pool.begin().await?;
+            // the profile is always deleted, regardless of the soft delete option.
+            // Deleted rows are retained until the table is vacuumed
+            let row =
+                sqlx::query("DELETE FROM profiles WHERE name=$1 RETURNING profile_key, reference")
+                    .bind(&name)
+                    .fetch_optional(&mut txn)
+                    .await?;
+            let (enc_key, reference) = match row {
+                Some(row) => (
+                    row.try_get::<Option<Vec<u8>>, _>(0)?.unwrap_or_default(),
+                    row.try_get::<Option<String>, _>(1)?,
+                ),
+                None => return Ok(None),
+            };
+            txn.commit().await?;
+            let tenant_key = reference.is_some();
+            let destroyed = self
+                .key_cache
+                .destroy_profile_key(name.clone(), reference)
+                .await?;
+            Ok(Some(ProfileShredding::new(
+                name, &enc_key, tenant_key, destroyed,
+            )))
         })
     }
 
@@ -150,33 +453,52 @@ impl Backend for PostgresStore {
         let pass_key = pass_key.into_owned();
         Box::pin(async move {
             let (store_key, store_key_ref) = unblock(move || method.resolve(pass_key)).await?;
-            let store_key = Arc::new(store_key);
-            let mut txn = self.conn_pool.begin().await?;
-            let mut rows = sqlx::query("SELECT id, profile_key FROM profiles").fetch(&mut txn);
-            let mut upd_keys = BTreeMap::<ProfileId, Vec<u8>>::new();
-            while let Some(row) = rows.next().await {
-                let row = row?;
-                let pid = row.try_get(0)?;
-                let enc_key = row.try_get(1)?;
-                let profile_key = self.key_cache.load_key(enc_key).await?;
-                let upd_key = unblock({
+            let store_key = Arc::new(Protected::new(store_key));
+            let prev_key = self.key_cache.store_key.clone();
+            let mut admin_conn = match self.admin.as_ref() {
+                Some(admin) => Some(admin.connect().await?),
+                None => None,
+            };
+            let mut txn = if let Some(conn) = admin_conn.as_mut() {
+                conn.begin().await?
+            } else {
+                self.conn_pool.begin().await?
+            };
+            let mut last_id: Option<ProfileId> = None;
+            loop {
+                let rows = if let Some(last_id) = last_id {
+                    sqlx::query(REKEY_FETCH_NEXT_QUERY).bind(last_id)
+                } else {
+                    sqlx::query(REKEY_FETCH_QUERY)
+                }
+                .bind(REKEY_PAGE_SIZE as i64)
+                .fetch_all(&mut txn)
+                .await?;
+                let page_len = rows.len();
+                if page_len == 0 {
+                    break;
+                }
+                let mut enc_keys = Vec::with_capacity(page_len);
+                for row in rows {
+                    enc_keys.push((row.try_get::<ProfileId, _>(0)?, row.try_get(1)?));
+                }
+                last_id.replace(enc_keys[page_len - 1].0);
+                let upd_keys = unblock({
+                    let prev_key = prev_key.clone();
                     let store_key = store_key.clone();
-                    move || encode_profile_key(&profile_key, &store_key)
+                    move || rewrap_profile_keys(enc_keys, &prev_key, &store_key)
                 })
                 .await?;
-                upd_keys.insert(pid, upd_key);
-            }
-            drop(rows);
-            for (pid, key) in upd_keys {
-                if sqlx::query("UPDATE profiles SET profile_key=$1 WHERE id=$2")
-                    .bind(key)
-                    .bind(pid)
-                    .execute(&mut txn)
-                    .await?
-                    .rows_affected()
-                    != 1
-                {
-                    return Err(err_msg!(Backend, "Error updating profile key"));
+                let upd_query = rekey_update_query(page_len);
+                let mut query = sqlx::query(&upd_query);
+                for (pid, key) in upd_keys {
+                    query = query.bind(pid).bind(key);
+                }
+                if query.execute(&mut txn).await?.rows_affected() != page_len as u64 {
+                    return Err(err_msg!(Backend, "Error updating profile keys"));
+                }
+                if page_len < REKEY_PAGE_SIZE {
+                    break;
                 }
             }
             if sqlx::query("UPDATE config SET value=$1 WHERE name='key'")
@@ -188,8 +510,21 @@ impl Backend for PostgresStore {
             {
                 return Err(err_msg!(Backend, "Error updating store key"));
             }
+            sqlx::query(
+                "INSERT INTO config (name, value) VALUES ('key_check', $1)
+                ON CONFLICT (name) DO UPDATE SET value = EXCLUDED.value",
+            )
+            .bind(encode_key_check(&store_key)?)
+            .execute(&mut txn)
+            .await?;
             txn.commit().await?;
-            self.key_cache = Arc::new(KeyCache::new(store_key));
+            if let Some(conn) = admin_conn {
+                conn.close().await?;
+            }
+            self.key_cache = Arc::new(
+                self.key_cache
+                    .replace_keys(store_key, self.key_cache.tenant_keys.clone()),
+            );
             Ok(())
         })
     }
@@ -200,11 +535,21 @@ impl Backend for PostgresStore {
         kind: EntryKind,
         category: String,
         tag_filter: Option<TagFilter>,
+        history_filter: HistoryFilter,
         offset: Option<i64>,
         limit: Option<i64>,
-    ) -> BoxFuture<'_, Result<Scan<'static, Entry>, Error>> {
+        after_id: Option<i64>,
+        projection: Projection,
+    ) -> BoxFuture<'_, Result<Scan<Entry>, Error>> {
         Box::pin(async move {
             let session = self.session(profile, false)?;
+            if !history_filter.is_empty() && !session.history() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Filtering records by modification time requires entry history"
+                ));
+            }
+            let page_size = session.page_size();
             let mut active = session.owned_ref();
             let (profile_id, key) = acquire_key(&mut *active).await?;
             let scan = perform_scan(
@@ -214,16 +559,19 @@ impl Backend for PostgresStore {
                 kind,
                 category.clone(),
                 tag_filter,
+                history_filter,
                 offset,
                 limit,
+                after_id,
                 false,
+                projection,
             );
             let stream = scan.then(move |enc_rows| {
                 let category = category.clone();
                 let key = key.clone();
-                unblock(move || decrypt_scan_batch(category, enc_rows?, &key))
+                unblock(move || decrypt_scan_page(category, enc_rows?, &key))
             });
-            Ok(Scan::new(stream, PAGE_SIZE))
+            Ok(Scan::new_positioned(stream, page_size))
         })
     }
 
@@ -233,7 +581,253 @@ impl Backend for PostgresStore {
             self.key_cache.clone(),
             profile.unwrap_or_else(|| self.default_profile.clone()),
             transaction,
-        ))
+            self.history,
+        )
+        .with_soft_delete(self.soft_delete)
+        .with_partitioned(self.partitioned)
+        .with_value_checksum(self.value_checksum)
+        .with_entry_metadata(self.capabilities.entry_metadata)
+        .with_entry_links(self.capabilities.entry_links)
+        .with_collections(self.capabilities.collections)
+        .with_unique_keys(self.capabilities.unique_keys)
+        .with_unique_constraints(self.unique_constraints.clone())
+        .with_page_size(self.page_size.load(Ordering::Relaxed))
+        .with_durability(self.durability))
+    }
+
+    fn storage_report(&self) -> BoxFuture<'_, Result<StorageReport, Error>> {
+        Box::pin(async move {
+            let mut conn = self.conn_pool.acquire().await?;
+            let mut profiles = Vec::new();
+            for row in sqlx::query(PROFILE_KEYS_QUERY).fetch_all(&mut conn).await? {
+                let name: String = row.try_get(1)?;
+                let key = match self.key_cache.get_profile(&name).await {
+                    Some((_, key)) => Some(key),
+                    // the categories of profiles wrapped by an unavailable
+                    // tenant key are reported without names
+                    None => self
+                        .key_cache
+                        .load_key(name.clone(), row.try_get(2)?, row.try_get(3)?)
+                        .await
+                        .ok()
+                        .map(Arc::new),
+                };
+                profiles.push((row.try_get(0)?, name, key));
+            }
+            let mut usage = Vec::new();
+            let query = partition_query(STORAGE_REPORT_QUERY, self.partitioned);
+            let mut rows = sqlx::query(query.as_ref()).fetch(&mut conn);
+            while let Some(row) = rows.try_next().await? {
+                usage.push(EncCategoryUsage {
+                    profile_id: row.try_get(0)?,
+                    kind: row.try_get(1)?,
+                    category: row.try_get(2)?,
+                    count: row.try_get(3)?,
+                    bytes: row.try_get(4)?,
+                });
+            }
+            drop(rows);
+            unblock(move || decrypt_storage_report(profiles, usage)).await
+        })
+    }
+
+    fn verify_integrity(
+        &self,
+        profile: Option<String>,
+    ) -> BoxFuture<'_, Result<IntegrityReport, Error>> {
+        Box::pin(async move {
+            let mut session = self.session(profile, false)?;
+            let (profile_id, key) = acquire_key(&mut session).await?;
+            session.close(false).await?;
+            let mut conn = self.conn_pool.acquire().await?;
+            let mut rows = sqlx::query(if self.value_checksum {
+                INTEGRITY_QUERY
+            } else {
+                INTEGRITY_NO_CHECKSUM_QUERY
+            })
+            .bind(profile_id)
+            .fetch(&mut conn);
+            let mut report = IntegrityReport::default();
+            let mut batch = Vec::with_capacity(PAGE_SIZE);
+            loop {
+                let row = rows.try_next().await?;
+                let done = row.is_none();
+                if let Some(row) = row {
+                    batch.push(EncIntegrityEntry {
+                        kind: row.try_get(0)?,
+                        category: row.try_get(1)?,
+                        name: row.try_get(2)?,
+                        value: row.try_get(3)?,
+                        checksum: row.try_get(4)?,
+                    });
+                }
+                if batch.len() == PAGE_SIZE || (done && !batch.is_empty()) {
+                    let enc_rows = std::mem::replace(&mut batch, Vec::with_capacity(PAGE_SIZE));
+                    let key = key.clone();
+                    report.append(unblock(move || check_integrity_batch(enc_rows, &key)).await?);
+                }
+                if done {
+                    break;
+                }
+            }
+            Ok(report)
+        })
+    }
+
+    fn backup_to(&self, _path: String) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            Err(err_msg!(
+                Unsupported,
+                "Database backups are not supported for Postgres stores"
+            ))
+        })
+    }
+
+    fn fork_to(&self, _uri: String) -> BoxFuture<'_, Result<Self, Error>> {
+        Box::pin(async move {
+            Err(err_msg!(
+                Unsupported,
+                "Forking is not supported for Postgres stores"
+            ))
+        })
+    }
+
+    fn maintenance(&self, mode: MaintenanceMode) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            // VACUUM requires ownership of the tables, so the admin account is
+            // used when one is configured
+            let mut admin_conn = match self.admin.as_ref() {
+                Some(admin) => Some(admin.connect().await?),
+                None => None,
+            };
+            let mut pool_conn = if admin_conn.is_none() {
+                Some(self.conn_pool.acquire().await?)
+            } else {
+                None
+            };
+            let conn: &mut PgConnection = match admin_conn.as_mut() {
+                Some(conn) => conn,
+                None => &mut *pool_conn.as_mut().unwrap(),
+            };
+            let tables = STORE_TABLES.join(", ");
+            match mode {
+                MaintenanceMode::Online => {
+                    // space held by dead rows is only made available for reuse
+                    // by an online vacuum, not returned to the operating system
+                    let bloated: Vec<String> = sqlx::query_scalar(
+                        "SELECT relname::text FROM pg_stat_user_tables
+                        WHERE schemaname = current_schema()
+                        AND n_dead_tup > 10000 AND n_dead_tup > n_live_tup / 5",
+                    )
+                    .fetch_all(&mut *conn)
+                    .await?;
+                    if !bloated.is_empty() {
+                        info!(
+                            "Tables with a high proportion of dead rows: {}; \
+                            full maintenance is recommended to rebuild them and their indexes",
+                            bloated.join(", ")
+                        );
+                    }
+                    conn.execute(format!("VACUUM (ANALYZE) {}", tables).as_str())
+                        .await?;
+                }
+                MaintenanceMode::Full => {
+                    conn.execute(format!("VACUUM (FULL, ANALYZE) {}", tables).as_str())
+                        .await?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn access_mode(&self) -> BoxFuture<'_, Result<AccessMode, Error>> {
+        Box::pin(async move {
+            let mut conn = self.conn_pool.acquire().await?;
+            let mode: Option<String> = sqlx::query_scalar(ACCESS_MODE_QUERY)
+                .fetch_optional(&mut conn)
+                .await?;
+            parse_access_mode(mode)
+        })
+    }
+
+    fn set_access_mode(&self, mode: AccessMode) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let mut conn = self.conn_pool.acquire().await?;
+            sqlx::query(
+                "INSERT INTO config (name, value) VALUES ('access_mode', $1)
+                ON CONFLICT (name) DO UPDATE SET value = EXCLUDED.value",
+            )
+            .bind(mode.as_str())
+            .execute(&mut conn)
+            .await?;
+            Ok(())
+        })
+    }
+
+    #[cfg(feature = "raw_query")]
+    fn raw_query(
+        &self,
+        sql: String,
+        params: Vec<RawValue>,
+    ) -> BoxFuture<'_, Result<Vec<RawRow>, Error>> {
+        Box::pin(async move {
+            let mut txn = self.conn_pool.begin().await?;
+            sqlx::query("SET TRANSACTION READ ONLY")
+                .execute(&mut txn)
+                .await?;
+            let mut query = sqlx::query(sql.as_str());
+            for param in params {
+                query = match param {
+                    RawValue::Null => query.bind(Option::<i64>::None),
+                    RawValue::Integer(value) => query.bind(value),
+                    RawValue::Real(value) => query.bind(value),
+                    RawValue::Text(value) => query.bind(value),
+                    RawValue::Blob(value) => query.bind(value),
+                };
+            }
+            let rows = query.fetch_all(&mut txn).await?;
+            txn.rollback().await?;
+            rows.iter().map(decode_raw_row).collect()
+        })
+    }
+
+    #[cfg(feature = "raw_query")]
+    fn profile_key(
+        &self,
+        profile: Option<String>,
+    ) -> BoxFuture<'_, Result<(ProfileId, Arc<ProfileKey>), Error>> {
+        Box::pin(async move {
+            let mut session = self.session(profile, false)?;
+            let ret = acquire_key(&mut session).await?;
+            session.close(false).await?;
+            Ok(ret)
+        })
+    }
+
+    fn set_tenant_keys(&mut self, provider: Option<Arc<dyn TenantKeyProvider>>) {
+        // cached profile keys are discarded and reloaded using the new provider
+        self.key_cache = Arc::new(
+            self.key_cache
+                .replace_keys(self.key_cache.store_key.clone(), provider),
+        );
+    }
+
+    fn set_unique_constraints(&mut self, constraints: Arc<Vec<UniqueConstraint>>) {
+        self.unique_constraints = constraints;
+    }
+
+    fn key_cache_stats(&self) -> KeyCacheStats {
+        self.key_cache.stats()
+    }
+
+    fn reconfigure(&self, tuning: &StoreTuning) -> Result<(), Error> {
+        if let Some(capacity) = tuning.key_cache_capacity() {
+            self.key_cache.resize(capacity)?;
+        }
+        if let Some(page_size) = tuning.scan_page_size {
+            self.page_size.store(page_size, Ordering::Relaxed);
+        }
+        Ok(())
     }
 
     fn close(&self) -> BoxFuture<'_, Result<(), Error>> {
@@ -250,6 +844,15 @@ impl Debug for PostgresStore {
             .field("default_profile", &self.default_profile)
             .field("host", &self.host)
             .field("name", &self.name)
+            .field("history", &self.history)
+            .field("nonce_strategy", &self.nonce_strategy)
+            .field("soft_delete", &self.soft_delete)
+            .field("partitioned", &self.partitioned)
+            .field("value_checksum", &self.value_checksum)
+            .field("admin", &self.admin)
+            .field("durability", &self.durability)
+            .field("page_size", &self.page_size)
+            .field("invalidation", &self.invalidation.is_some())
             .finish()
     }
 }
@@ -264,25 +867,28 @@ impl QueryBackend for DbSession<Postgres> {
         let category = ProfileKey::prepare_input(category.as_bytes());
 
         Box::pin(async move {
+            let timer = self.crypto_timer();
             let (profile_id, key) = acquire_key(&mut *self).await?;
             let mut params = QueryParams::new();
             params.push(profile_id);
-            params.push(kind as i16);
-            let (enc_category, tag_filter) = unblock({
-                let params_len = params.len() + 1; // plus category
-                move || {
-                    Result::<_, Error>::Ok((
-                        key.encrypt_entry_category(category)?,
-                        encode_tag_filter::<PostgresStore>(tag_filter, &key, params_len)?,
-                    ))
-                }
-            })
-            .await?;
+            params.push(kind.code());
+            let (enc_category, tag_filter) = timer
+                .unblock({
+                    let params_len = params.len() + 1; // plus category
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            encode_tag_filter::<PostgresStore>(tag_filter, &key, params_len)?,
+                        ))
+                    }
+                })
+                .await?;
             params.push(enc_category);
             let query =
                 extend_query::<PostgresStore>(COUNT_QUERY, &mut params, tag_filter, None, None)?;
+            let query = partition_query(&query, self.partitioned());
             let mut active = acquire_session(&mut *self).await?;
-            let count = sqlx::query_scalar_with(query.as_str(), params)
+            let count = sqlx::query_scalar_with(query.as_ref(), params)
                 .fetch_one(active.connection_mut())
                 .await?;
             Ok(count)
@@ -299,10 +905,150 @@ impl QueryBackend for DbSession<Postgres> {
         let category = category.to_string();
         let name = name.to_string();
 
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let query = partition_query(
+                if for_update && active.is_transaction() {
+                    FETCH_QUERY_UPDATE
+                } else {
+                    FETCH_QUERY
+                },
+                active.partitioned(),
+            );
+            if let Some(row) = sqlx::query(query.as_ref())
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .fetch_optional(active.connection_mut())
+                .await?
+            {
+                let seq: i64 = row.try_get(0)?;
+                let value = row.try_get(1)?;
+                let tags = row.try_get::<Option<String>, _>(2)?.map(String::into_bytes);
+                let mut expiry = row.try_get::<Option<chrono::NaiveDateTime>, _>(4)?;
+                if let Some(sliding_ms) = row.try_get::<Option<i64>, _>(3)? {
+                    let extended = expiry_timestamp(EntryExpiry::Sliding(sliding_ms))?;
+                    sqlx::query(EXTEND_EXPIRY_QUERY)
+                        .bind(profile_id)
+                        .bind(seq)
+                        .bind(extended)
+                        .execute(active.connection_mut())
+                        .await?;
+                    expiry.replace(extended.naive_utc());
+                }
+                let expiry = expiry.map(from_history_timestamp).transpose()?;
+                let (category, name, value, tags) = timer
+                    .unblock(move || {
+                        let value =
+                            key.decrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+                        let tags = if let Some(enc_tags) = tags {
+                            key.decrypt_entry_tags(
+                                decode_tags(enc_tags)
+                                    .map_err(|_| err_msg!(Unexpected, "Error decoding tags"))?,
+                            )?
+                        } else {
+                            Vec::new()
+                        };
+                        Result::<_, Error>::Ok((category, name, value, tags))
+                    })
+                    .await?;
+                Ok(Some(
+                    Entry::new(split_namespace(&category).1, name, value, tags)
+                        .with_seq(EntrySeq::new(seq))
+                        .with_expiry(expiry),
+                ))
+            } else {
+                Ok(None)
+            }
+        })
+    }
+
+    fn fetch_into<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        output: &'q mut SecretBytes,
+    ) -> BoxFuture<'q, Result<bool, Error>> {
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            output.zeroize();
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            if let Some(row) = sqlx::query(FETCH_VALUE_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .fetch_optional(active.connection_mut())
+                .await?
+            {
+                // the ciphertext is copied into the output buffer and
+                // decrypted in place
+                output.extend_from_slice(row.try_get::<&[u8], _>(0)?);
+                drop(row);
+                let mut buffer = std::mem::take(output);
+                let (category, name) = (category.to_string(), name.to_string());
+                let (buffer, result) = timer
+                    .unblock(move || {
+                        let result = key.decrypt_entry_value_in_place(
+                            category.as_bytes(),
+                            name.as_bytes(),
+                            &mut buffer,
+                        );
+                        (buffer, result)
+                    })
+                    .await;
+                *output = buffer;
+                if let Err(err) = result {
+                    output.zeroize();
+                    return Err(err);
+                }
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        })
+    }
+
+    fn exists<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+    ) -> BoxFuture<'q, Result<bool, Error>> {
         Box::pin(async move {
             let (profile_id, key) = acquire_key(&mut *self).await?;
             let (enc_category, enc_name) = unblock({
-                let key = key.clone();
                 let category = ProfileKey::prepare_input(category.as_bytes());
                 let name = ProfileKey::prepare_input(name.as_bytes());
                 move || {
@@ -314,50 +1060,208 @@ impl QueryBackend for DbSession<Postgres> {
             })
             .await?;
             let mut active = acquire_session(&mut *self).await?;
-            if let Some(row) = sqlx::query(if for_update && active.is_transaction() {
-                FETCH_QUERY_UPDATE
-            } else {
-                FETCH_QUERY
-            })
-            .bind(profile_id)
-            .bind(kind as i16)
-            .bind(enc_category)
-            .bind(enc_name)
-            .fetch_optional(active.connection_mut())
-            .await?
-            {
-                let value = row.try_get(1)?;
-                let tags = row.try_get::<Option<String>, _>(2)?.map(String::into_bytes);
-                let (category, name, value, tags) = unblock(move || {
-                    let value = key.decrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
-                    let tags = if let Some(enc_tags) = tags {
-                        key.decrypt_entry_tags(
-                            decode_tags(enc_tags)
-                                .map_err(|_| err_msg!(Unexpected, "Error decoding tags"))?,
-                        )?
-                    } else {
-                        Vec::new()
-                    };
-                    Result::<_, Error>::Ok((category, name, value, tags))
-                })
+            let found = sqlx::query(EXISTS_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .fetch_optional(active.connection_mut())
                 .await?;
-                Ok(Some(Entry::new(category, name, value, tags)))
-            } else {
-                Ok(None)
-            }
+            Ok(found.is_some())
         })
     }
 
-    fn fetch_all<'q>(
-        &'q mut self,
+    fn fetch_by_id(
+        &mut self,
+        kind: EntryKind,
+        id: EntryId,
+    ) -> BoxFuture<'_, Result<Option<Entry>, Error>> {
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let query = partition_query(FETCH_BY_ID_QUERY, active.partitioned());
+            if let Some(row) = sqlx::query(query.as_ref())
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(id.value())
+                .fetch_optional(active.connection_mut())
+                .await?
+            {
+                let seq: i64 = row.try_get(0)?;
+                let enc_category = row.try_get(1)?;
+                let enc_name = row.try_get(2)?;
+                let value = row.try_get(3)?;
+                let tags = row.try_get::<Option<String>, _>(4)?.map(String::into_bytes);
+                let mut expiry = row.try_get::<Option<chrono::NaiveDateTime>, _>(6)?;
+                if let Some(sliding_ms) = row.try_get::<Option<i64>, _>(5)? {
+                    let extended = expiry_timestamp(EntryExpiry::Sliding(sliding_ms))?;
+                    sqlx::query(EXTEND_EXPIRY_QUERY)
+                        .bind(profile_id)
+                        .bind(seq)
+                        .bind(extended)
+                        .execute(active.connection_mut())
+                        .await?;
+                    expiry.replace(extended.naive_utc());
+                }
+                let expiry = expiry.map(from_history_timestamp).transpose()?;
+                let (category, name, value, tags) = timer
+                    .unblock(move || {
+                        let category = key.decrypt_entry_category(enc_category)?;
+                        let name = key.decrypt_entry_name(enc_name)?;
+                        let value =
+                            key.decrypt_entry_value(category.as_bytes(), name.as_bytes(), value)?;
+                        let tags = if let Some(enc_tags) = tags {
+                            key.decrypt_entry_tags(
+                                decode_tags(enc_tags)
+                                    .map_err(|_| err_msg!(Unexpected, "Error decoding tags"))?,
+                            )?
+                        } else {
+                            Vec::new()
+                        };
+                        Result::<_, Error>::Ok((category, name, value, tags))
+                    })
+                    .await?;
+                Ok(Some(
+                    Entry::new(category, name, value, tags)
+                        .with_seq(EntrySeq::new(seq))
+                        .with_expiry(expiry),
+                ))
+            } else {
+                Ok(None)
+            }
+        })
+    }
+
+    fn fetch_at<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        timestamp: SystemTime,
+    ) -> BoxFuture<'q, Result<Option<Entry>, Error>> {
+        let category = category.to_string();
+        let name = name.to_string();
+
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.history() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Entry history is not enabled for this store"
+                ));
+            }
+            let timestamp = to_history_timestamp(timestamp)?;
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            if let Some(row) = sqlx::query(HISTORY_FETCH_AT_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .bind(timestamp)
+                .fetch_optional(active.connection_mut())
+                .await?
+            {
+                let enc_entry = EncHistoryEntry {
+                    value: row.try_get(0)?,
+                    tags: row.try_get::<Option<String>, _>(1)?.map(String::into_bytes),
+                    valid_from: row.try_get(2)?,
+                    valid_to: row.try_get(3)?,
+                };
+                let mut found = timer
+                    .unblock(move || decrypt_history_batch(category, name, vec![enc_entry], &key))
+                    .await?;
+                Ok(found.pop().map(|version| version.entry))
+            } else {
+                Ok(None)
+            }
+        })
+    }
+
+    fn fetch_history<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        limit: Option<i64>,
+    ) -> BoxFuture<'q, Result<Vec<EntryVersion>, Error>> {
+        let category = category.to_string();
+        let name = name.to_string();
+
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.history() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Entry history is not enabled for this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut params = QueryParams::new();
+            params.push(profile_id);
+            params.push(kind.code());
+            params.push(enc_category);
+            params.push(enc_name);
+            let query =
+                extend_query::<PostgresStore>(HISTORY_LIST_QUERY, &mut params, None, None, limit)?;
+            let mut active = acquire_session(&mut *self).await?;
+            let rows = sqlx::query_with(query.as_str(), params)
+                .fetch_all(active.connection_mut())
+                .await?;
+            let mut enc_rows = Vec::with_capacity(rows.len());
+            for row in rows {
+                enc_rows.push(EncHistoryEntry {
+                    value: row.try_get(0)?,
+                    tags: row.try_get::<Option<String>, _>(1)?.map(String::into_bytes),
+                    valid_from: row.try_get(2)?,
+                    valid_to: row.try_get(3)?,
+                });
+            }
+            timer
+                .unblock(move || decrypt_history_batch(category, name, enc_rows, &key))
+                .await
+        })
+    }
+
+    fn fetch_all<'q>(
+        &'q mut self,
         kind: EntryKind,
         category: &'q str,
         tag_filter: Option<TagFilter>,
         limit: Option<i64>,
         for_update: bool,
+        projection: Projection,
     ) -> BoxFuture<'q, Result<Vec<Entry>, Error>> {
         let category = category.to_string();
         Box::pin(async move {
+            let timer = self.crypto_timer();
             let for_update = for_update && self.is_transaction();
             let mut active = self.borrow_mut();
             let (profile_id, key) = acquire_key(&mut *active).await?;
@@ -368,9 +1272,12 @@ impl QueryBackend for DbSession<Postgres> {
                 kind,
                 category.clone(),
                 tag_filter,
+                HistoryFilter::default(),
                 None,
                 limit,
+                None,
                 for_update,
+                projection,
             );
             pin!(scan);
             let mut enc_rows = vec![];
@@ -381,7 +1288,77 @@ impl QueryBackend for DbSession<Postgres> {
                     break;
                 }
             }
-            unblock(move || decrypt_scan_batch(category, enc_rows, &key)).await
+            timer
+                .unblock(move || decrypt_scan_batch(category, enc_rows, &key))
+                .await
+        })
+    }
+
+    fn search<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        terms: Vec<String>,
+        limit: Option<i64>,
+    ) -> BoxFuture<'q, Result<Vec<Entry>, Error>> {
+        let category = category.to_string();
+        let terms = prepare_search_terms(terms);
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if terms.is_empty() {
+                return Ok(vec![]);
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, tokens) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = category.clone();
+                    move || {
+                        let tokens = terms
+                            .iter()
+                            .map(|term| {
+                                key.encrypt_search_token(category.as_bytes(), term.as_bytes())
+                            })
+                            .collect::<Result<Vec<_>, Error>>()?;
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(ProfileKey::prepare_input(
+                                category.as_bytes(),
+                            ))?,
+                            tokens,
+                        ))
+                    }
+                })
+                .await?;
+            let mut params = QueryParams::new();
+            params.push(profile_id);
+            params.push(kind.code());
+            params.push(enc_category);
+            let mut query = scan_query(Projection::Full);
+            query.push_str(&search_clause::<PostgresStore>(&mut params, tokens));
+            query.push_str(" ORDER BY i.seq");
+            let query = PostgresStore::limit_query(query, &mut params, None, limit);
+            let query = partition_query(&query, self.partitioned()).into_owned();
+            let mut active = acquire_session(&mut *self).await?;
+            let mut enc_rows = vec![];
+            for row in sqlx::query_with(query.as_str(), params)
+                .fetch_all(active.connection_mut())
+                .await?
+            {
+                let tags = row
+                    .try_get::<Option<String>, _>(3)?
+                    .map(String::into_bytes)
+                    .unwrap_or_default();
+                enc_rows.push(EncScanEntry {
+                    seq: row.try_get(0)?,
+                    name: row.try_get(1)?,
+                    value: row.try_get(2)?,
+                    tags,
+                    expiry: row.try_get(4)?,
+                });
+            }
+            timer
+                .unblock(move || decrypt_scan_batch(category, enc_rows, &key))
+                .await
         })
     }
 
@@ -394,136 +1371,1168 @@ impl QueryBackend for DbSession<Postgres> {
         let category = ProfileKey::prepare_input(category.as_bytes());
 
         Box::pin(async move {
+            let timer = self.crypto_timer();
             let (profile_id, key) = acquire_key(&mut *self).await?;
             let mut params = QueryParams::new();
             params.push(profile_id);
-            params.push(kind as i16);
-            let (enc_category, tag_filter) = unblock({
-                let params_len = params.len() + 1; // plus category
-                move || {
-                    Result::<_, Error>::Ok((
-                        key.encrypt_entry_category(category)?,
-                        encode_tag_filter::<PostgresStore>(tag_filter, &key, params_len)?,
-                    ))
-                }
-            })
-            .await?;
-            params.push(enc_category);
+            params.push(kind.code());
+            let (enc_category, tag_filter) = timer
+                .unblock({
+                    let params_len = params.len() + 1; // plus category
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            encode_tag_filter::<PostgresStore>(tag_filter, &key, params_len)?,
+                        ))
+                    }
+                })
+                .await?;
+            params.push(enc_category.clone());
+            let history_close = if self.history() {
+                let mut hist_params = QueryParams::new();
+                hist_params.push(profile_id);
+                hist_params.push(kind.code());
+                hist_params.push(enc_category);
+                let select = extend_query::<PostgresStore>(
+                    HISTORY_CLOSE_ALL_QUERY,
+                    &mut hist_params,
+                    tag_filter.clone(),
+                    None,
+                    None,
+                )?;
+                let ts_idx = (hist_params.len() + 1) as i64;
+                hist_params.push(history_timestamp());
+                Some((
+                    format!(
+                        "UPDATE items_history SET valid_to = {}
+                        WHERE valid_to IS NULL
+                        AND (profile_id, kind, category, name) IN ({})",
+                        PostgresStore::placeholder(ts_idx),
+                        select
+                    ),
+                    hist_params,
+                ))
+            } else {
+                None
+            };
             let query = extend_query::<PostgresStore>(
-                DELETE_ALL_QUERY,
+                match (self.soft_delete(), self.unique_keys()) {
+                    (false, _) => DELETE_ALL_QUERY,
+                    (true, false) => SOFT_DELETE_ALL_QUERY,
+                    (true, true) => SOFT_DELETE_ALL_UNIQUE_QUERY,
+                },
+                &mut params,
+                tag_filter,
+                None,
+                None,
+            )?;
+
+            let partitioned = self.partitioned();
+            let query = partition_query(&query, partitioned);
+
+            let mut active = acquire_session(&mut *self).await?;
+            let mut txn = active.as_transaction().await?;
+            if let Some((hist_query, hist_params)) = history_close {
+                sqlx::query_with(
+                    partition_query(&hist_query, partitioned).as_ref(),
+                    hist_params,
+                )
+                .execute(txn.connection_mut())
+                .await?;
+            }
+            let removed = sqlx::query_with(query.as_ref(), params)
+                .execute(txn.connection_mut())
+                .await?
+                .rows_affected();
+            txn.commit().await?;
+            Ok(removed as i64)
+        })
+    }
+
+    fn insert_all<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        entries: Vec<Entry>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        Box::pin(async move {
+            let entries = entries
+                .into_iter()
+                .map(|entry| {
+                    let unique_key =
+                        self.unique_key(kind, &entry.category, Some(entry.tags.as_slice()))?;
+                    Ok((entry, unique_key))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            let timer = self.crypto_timer();
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let checksum = self.value_checksum();
+            let enc_entries = timer
+                .unblock(move || encrypt_insert_batch(entries, &key, checksum))
+                .await?;
+            let partitioned = self.partitioned();
+            let mut active = acquire_session(&mut *self).await?;
+            let mut txn = active.as_transaction().await?;
+            let history = if txn.history() {
+                Some(history_timestamp())
+            } else {
+                None
+            };
+            for batch in enc_entries.chunks(INSERT_BATCH_SIZE) {
+                perform_insert_batch(&mut txn, profile_id, kind, batch, partitioned, history)
+                    .await?;
+            }
+            txn.commit().await?;
+            Ok(())
+        })
+    }
+
+    fn touch_all<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        tag_filter: Option<TagFilter>,
+        offset: Option<i64>,
+        limit: Option<i64>,
+        expiry: EntryExpiry,
+    ) -> BoxFuture<'q, Result<i64, Error>> {
+        let category = ProfileKey::prepare_input(category.as_bytes());
+
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let mut params = QueryParams::new();
+            params.push(profile_id);
+            params.push(kind.code());
+            let (enc_category, tag_filter) = timer
+                .unblock({
+                    let params_len = params.len() + 1; // plus category
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            encode_tag_filter::<PostgresStore>(tag_filter, &key, params_len)?,
+                        ))
+                    }
+                })
+                .await?;
+            params.push(enc_category);
+            let mut select = extend_query::<PostgresStore>(
+                TOUCH_SELECT_QUERY,
                 &mut params,
                 tag_filter,
                 None,
                 None,
             )?;
+            select.push_str(" ORDER BY i.seq");
+            let select = PostgresStore::limit_query(select, &mut params, offset, limit);
+            let expiry_idx = (params.len() + 1) as i64;
+            params.push(expiry_timestamp(expiry)?);
+            params.push(expiry.sliding_ms());
+            let query = format!(
+                "UPDATE items SET expiry = {}, expiry_sliding = {}
+                WHERE profile_id = $1 AND id IN ({})",
+                PostgresStore::placeholder(expiry_idx),
+                PostgresStore::placeholder(expiry_idx + 1),
+                select
+            );
+            let query = partition_query(&query, self.partitioned()).into_owned();
 
             let mut active = acquire_session(&mut *self).await?;
-            let removed = sqlx::query_with(query.as_str(), params)
+            let touched = sqlx::query_with(query.as_str(), params)
                 .execute(active.connection_mut())
                 .await?
                 .rows_affected();
-            Ok(removed as i64)
+            Ok(touched as i64)
+        })
+    }
+
+    fn update<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        operation: EntryOperation,
+        category: &'q str,
+        name: &'q str,
+        value: Option<&'q [u8]>,
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        let unique_key = match operation {
+            EntryOperation::Remove => Ok(None),
+            _ => self.unique_key(kind, category, tags),
+        };
+        let category = ProfileKey::prepare_input(category.as_bytes());
+        let name = ProfileKey::prepare_input(name.as_bytes());
+
+        let timer = self.crypto_timer();
+
+        match operation {
+            EntryOperation::Insert => {
+                let value = ProfileKey::prepare_input(value.unwrap());
+                let tags = tags.map(prepare_tags);
+                Box::pin(async move {
+                    let unique_key = unique_key?;
+                    let (_, key) = acquire_key(&mut *self).await?;
+                    let checksum = self.value_checksum();
+                    let (enc_category, enc_name, enc_value, checksum, enc_tags) = timer
+                        .unblock(move || {
+                            let checksum = if checksum {
+                                Some(key.value_checksum(
+                                    category.as_ref(),
+                                    name.as_ref(),
+                                    value.as_ref(),
+                                )?)
+                            } else {
+                                None
+                            };
+                            let enc_value =
+                                key.encrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+                            Result::<_, Error>::Ok((
+                                key.encrypt_entry_category(category)?,
+                                key.encrypt_entry_name(name)?,
+                                enc_value,
+                                checksum,
+                                tags.transpose()?
+                                    .map(|t| key.encrypt_entry_tags(t))
+                                    .transpose()?,
+                            ))
+                        })
+                        .await?;
+                    let mut active = acquire_session(&mut *self).await?;
+                    let mut txn = active.as_transaction().await?;
+                    let history = if txn.history() {
+                        Some(history_timestamp())
+                    } else {
+                        None
+                    };
+                    perform_insert(
+                        &mut txn,
+                        kind,
+                        &enc_category,
+                        &enc_name,
+                        &enc_value,
+                        checksum.as_deref(),
+                        unique_key.as_deref(),
+                        enc_tags,
+                        expiry,
+                        history,
+                    )
+                    .await?;
+                    txn.commit().await?;
+                    Ok(())
+                })
+            }
+            EntryOperation::Replace => {
+                let value = ProfileKey::prepare_input(value.unwrap());
+                let tags = tags.map(prepare_tags);
+                Box::pin(async move {
+                    let unique_key = unique_key?;
+                    let (_, key) = acquire_key(&mut *self).await?;
+                    let checksum = self.value_checksum();
+                    let (enc_category, enc_name, enc_value, checksum, enc_tags) = timer
+                        .unblock(move || {
+                            let checksum = if checksum {
+                                Some(key.value_checksum(
+                                    category.as_ref(),
+                                    name.as_ref(),
+                                    value.as_ref(),
+                                )?)
+                            } else {
+                                None
+                            };
+                            let enc_value =
+                                key.encrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+                            Result::<_, Error>::Ok((
+                                key.encrypt_entry_category(category)?,
+                                key.encrypt_entry_name(name)?,
+                                enc_value,
+                                checksum,
+                                tags.transpose()?
+                                    .map(|t| key.encrypt_entry_tags(t))
+                                    .transpose()?,
+                            ))
+                        })
+                        .await?;
+
+                    let mut active = acquire_session(&mut *self).await?;
+                    let mut txn = active.as_transaction().await?;
+                    // a replaced version ends exactly when the new one begins
+                    let history = if txn.history() {
+                        Some(history_timestamp())
+                    } else {
+                        None
+                    };
+                    check_unique_key(
+                        &mut txn,
+                        kind,
+                        &enc_category,
+                        &enc_name,
+                        unique_key.as_deref(),
+                    )
+                    .await?;
+                    perform_remove(&mut txn, kind, &enc_category, &enc_name, false, history)
+                        .await?;
+                    perform_insert(
+                        &mut txn,
+                        kind,
+                        &enc_category,
+                        &enc_name,
+                        &enc_value,
+                        checksum.as_deref(),
+                        unique_key.as_deref(),
+                        enc_tags,
+                        expiry,
+                        history,
+                    )
+                    .await?;
+                    txn.commit().await?;
+                    Ok(())
+                })
+            }
+
+            EntryOperation::Remove => Box::pin(async move {
+                let (_, key) = acquire_key(&mut *self).await?;
+                let (enc_category, enc_name) = timer
+                    .unblock(move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                        ))
+                    })
+                    .await?;
+                let mut active = acquire_session(&mut *self).await?;
+                if active.history() {
+                    let mut txn = active.as_transaction().await?;
+                    perform_remove(
+                        &mut txn,
+                        kind,
+                        &enc_category,
+                        &enc_name,
+                        false,
+                        Some(history_timestamp()),
+                    )
+                    .await?;
+                    txn.commit().await
+                } else {
+                    perform_remove(&mut active, kind, &enc_category, &enc_name, false, None).await
+                }
+            }),
+        }
+    }
+
+    fn insert_returning_id<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<EntryId, Error>> {
+        let unique_key = self.unique_key(kind, category, tags);
+        let category = ProfileKey::prepare_input(category.as_bytes());
+        let name = ProfileKey::prepare_input(name.as_bytes());
+        let value = ProfileKey::prepare_input(value);
+        let tags = tags.map(prepare_tags);
+        let timer = self.crypto_timer();
+
+        Box::pin(async move {
+            let unique_key = unique_key?;
+            let (_, key) = acquire_key(&mut *self).await?;
+            let checksum = self.value_checksum();
+            let (enc_category, enc_name, enc_value, checksum, enc_tags) = timer
+                .unblock(move || {
+                    let checksum = if checksum {
+                        Some(key.value_checksum(
+                            category.as_ref(),
+                            name.as_ref(),
+                            value.as_ref(),
+                        )?)
+                    } else {
+                        None
+                    };
+                    let enc_value =
+                        key.encrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+                    Result::<_, Error>::Ok((
+                        key.encrypt_entry_category(category)?,
+                        key.encrypt_entry_name(name)?,
+                        enc_value,
+                        checksum,
+                        tags.transpose()?
+                            .map(|t| key.encrypt_entry_tags(t))
+                            .transpose()?,
+                    ))
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let mut txn = active.as_transaction().await?;
+            let history = if txn.history() {
+                Some(history_timestamp())
+            } else {
+                None
+            };
+            let row_id = perform_insert(
+                &mut txn,
+                kind,
+                &enc_category,
+                &enc_name,
+                &enc_value,
+                checksum.as_deref(),
+                unique_key.as_deref(),
+                enc_tags,
+                expiry,
+                history,
+            )
+            .await?;
+            txn.commit().await?;
+            Ok(EntryId::new(row_id))
+        })
+    }
+
+    fn insert_or_fetch<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<Option<Entry>, Error>> {
+        let unique_key = self.unique_key(kind, category, tags);
+        let category = category.to_string();
+        let name = name.to_string();
+        let value = ProfileKey::prepare_input(value);
+        let tags = tags.map(prepare_tags);
+        let timer = self.crypto_timer();
+
+        Box::pin(async move {
+            let unique_key = unique_key?;
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let checksum = self.value_checksum();
+            let (enc_category, enc_name, enc_value, checksum, enc_tags) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        let checksum = if checksum {
+                            Some(key.value_checksum(
+                                category.as_ref(),
+                                name.as_ref(),
+                                value.as_ref(),
+                            )?)
+                        } else {
+                            None
+                        };
+                        let enc_value =
+                            key.encrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                            enc_value,
+                            checksum,
+                            tags.transpose()?
+                                .map(|t| key.encrypt_entry_tags(t))
+                                .transpose()?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let mut txn = active.as_transaction().await?;
+            let history = if txn.history() {
+                Some(history_timestamp())
+            } else {
+                None
+            };
+            match perform_insert(
+                &mut txn,
+                kind,
+                &enc_category,
+                &enc_name,
+                &enc_value,
+                checksum.as_deref(),
+                unique_key.as_deref(),
+                enc_tags,
+                expiry,
+                history,
+            )
+            .await
+            {
+                Ok(_) => {
+                    txn.commit().await?;
+                    return Ok(None);
+                }
+                Err(err) if err.kind() == ErrorKind::Duplicate => (),
+                Err(err) => return Err(err),
+            }
+            // the existing record is read within the same transaction
+            let query = partition_query(FETCH_QUERY_UPDATE, txn.partitioned());
+            let row = sqlx::query(query.as_ref())
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(&enc_category)
+                .bind(&enc_name)
+                .fetch_optional(txn.connection_mut())
+                .await?
+                .ok_or_else(|| err_msg!(Duplicate, "Duplicate row"))?;
+            let seq: i64 = row.try_get(0)?;
+            let value = row.try_get(1)?;
+            let tags = row.try_get::<Option<String>, _>(2)?.map(String::into_bytes);
+            let mut expiry = row.try_get::<Option<chrono::NaiveDateTime>, _>(4)?;
+            if let Some(sliding_ms) = row.try_get::<Option<i64>, _>(3)? {
+                let extended = expiry_timestamp(EntryExpiry::Sliding(sliding_ms))?;
+                sqlx::query(EXTEND_EXPIRY_QUERY)
+                    .bind(profile_id)
+                    .bind(seq)
+                    .bind(extended)
+                    .execute(txn.connection_mut())
+                    .await?;
+                expiry.replace(extended.naive_utc());
+            }
+            txn.commit().await?;
+            let expiry = expiry.map(from_history_timestamp).transpose()?;
+            let (category, name, value, tags) = timer
+                .unblock(move || {
+                    let value = key.decrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+                    let tags = if let Some(enc_tags) = tags {
+                        key.decrypt_entry_tags(
+                            decode_tags(enc_tags)
+                                .map_err(|_| err_msg!(Unexpected, "Error decoding tags"))?,
+                        )?
+                    } else {
+                        Vec::new()
+                    };
+                    Result::<_, Error>::Ok((category, name, value, tags))
+                })
+                .await?;
+            Ok(Some(
+                Entry::new(split_namespace(&category).1, name, value, tags)
+                    .with_seq(EntrySeq::new(seq))
+                    .with_expiry(expiry),
+            ))
+        })
+    }
+
+    fn replace_checked<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+        expected: Option<EntryHash>,
+    ) -> BoxFuture<'q, Result<EntryHash, Error>> {
+        let unique_key = self.unique_key(kind, category, tags);
+        let category = ProfileKey::prepare_input(category.as_bytes());
+        let name = ProfileKey::prepare_input(name.as_bytes());
+        let value = ProfileKey::prepare_input(value);
+        let tags = tags.map(prepare_tags);
+        Box::pin(async move {
+            let unique_key = unique_key?;
+            let timer = self.crypto_timer();
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let checksum = self.value_checksum();
+            let (enc_category, enc_name, enc_value, checksum, enc_tags) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = category.clone();
+                    let name = name.clone();
+                    move || {
+                        let checksum = if checksum {
+                            Some(key.value_checksum(
+                                category.as_ref(),
+                                name.as_ref(),
+                                value.as_ref(),
+                            )?)
+                        } else {
+                            None
+                        };
+                        let enc_value =
+                            key.encrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                            enc_value,
+                            checksum,
+                            tags.transpose()?
+                                .map(|t| key.encrypt_entry_tags(t))
+                                .transpose()?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let mut txn = active.as_transaction().await?;
+            let row = sqlx::query(partition_query(FETCH_QUERY_UPDATE, txn.partitioned()).as_ref())
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(&enc_category)
+                .bind(&enc_name)
+                .fetch_optional(txn.connection_mut())
+                .await?
+                .ok_or_else(|| err_msg!(NotFound, "Entry not found"))?;
+            let prev_value = row.try_get(1)?;
+            let prev_tags = row.try_get::<Option<String>, _>(2)?.map(String::into_bytes);
+            let prev_hash = timer
+                .unblock(move || {
+                    let value =
+                        key.decrypt_entry_value(category.as_ref(), name.as_ref(), prev_value)?;
+                    let tags = if let Some(enc_tags) = prev_tags {
+                        key.decrypt_entry_tags(
+                            decode_tags(enc_tags)
+                                .map_err(|_| err_msg!(Unexpected, "Error decoding tags"))?,
+                        )?
+                    } else {
+                        Vec::new()
+                    };
+                    Result::<_, Error>::Ok(EntryHash::compute(&value, &tags))
+                })
+                .await?;
+            if expected.map(|hash| hash != prev_hash).unwrap_or(false) {
+                return Err(err_msg!(
+                    Conflict,
+                    "Entry was modified since it was fetched"
+                ));
+            }
+            // a replaced version ends exactly when the new one begins
+            let history = if txn.history() {
+                Some(history_timestamp())
+            } else {
+                None
+            };
+            check_unique_key(
+                &mut txn,
+                kind,
+                &enc_category,
+                &enc_name,
+                unique_key.as_deref(),
+            )
+            .await?;
+            perform_remove(&mut txn, kind, &enc_category, &enc_name, false, history).await?;
+            perform_insert(
+                &mut txn,
+                kind,
+                &enc_category,
+                &enc_name,
+                &enc_value,
+                checksum.as_deref(),
+                unique_key.as_deref(),
+                enc_tags,
+                expiry,
+                history,
+            )
+            .await?;
+            txn.commit().await?;
+            Ok(prev_hash)
+        })
+    }
+
+    fn update_search_terms<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        terms: Vec<String>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        let terms = prepare_search_terms(terms);
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name, tokens) = timer
+                .unblock({
+                    let category = category.to_string();
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        let tokens = terms
+                            .iter()
+                            .map(|term| {
+                                key.encrypt_search_token(category.as_bytes(), term.as_bytes())
+                            })
+                            .collect::<Result<Vec<_>, Error>>()?;
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(ProfileKey::prepare_input(
+                                category.as_bytes(),
+                            ))?,
+                            key.encrypt_entry_name(name)?,
+                            tokens,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let partitioned = active.partitioned();
+            let mut txn = active.as_transaction().await?;
+            let item_id: ProfileId = sqlx::query_scalar(SEARCH_ITEM_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .fetch_optional(txn.connection_mut())
+                .await?
+                .ok_or_else(|| err_msg!(NotFound, "Entry not found"))?;
+            sqlx::query(SEARCH_DELETE_QUERY)
+                .bind(item_id)
+                .execute(txn.connection_mut())
+                .await?;
+            for token in tokens {
+                let mut query = sqlx::query(if partitioned {
+                    SEARCH_INSERT_PARTITIONED_QUERY
+                } else {
+                    SEARCH_INSERT_QUERY
+                })
+                .bind(item_id)
+                .bind(token);
+                if partitioned {
+                    query = query.bind(profile_id);
+                }
+                query.execute(txn.connection_mut()).await?;
+            }
+            txn.commit().await?;
+            Ok(())
+        })
+    }
+
+    fn fetch_metadata<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+    ) -> BoxFuture<'q, Result<Option<SecretBytes>, Error>> {
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.entry_metadata() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Entry metadata is not supported by this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let enc_metadata: Option<Vec<u8>> = sqlx::query_scalar(METADATA_FETCH_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .fetch_optional(active.connection_mut())
+                .await?
+                .ok_or_else(|| err_msg!(NotFound, "Entry not found"))?;
+            if let Some(enc_metadata) = enc_metadata {
+                let (category, name) = (category.to_string(), name.to_string());
+                Ok(Some(
+                    timer
+                        .unblock(move || {
+                            key.decrypt_entry_metadata(
+                                category.as_bytes(),
+                                name.as_bytes(),
+                                enc_metadata,
+                            )
+                        })
+                        .await?,
+                ))
+            } else {
+                Ok(None)
+            }
+        })
+    }
+
+    fn update_metadata<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        metadata: Option<&'q [u8]>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        let metadata = metadata.map(ProfileKey::prepare_input);
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.entry_metadata() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Entry metadata is not supported by this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name, enc_metadata) = timer
+                .unblock({
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        let enc_metadata = metadata
+                            .map(|m| {
+                                key.encrypt_entry_metadata(category.as_ref(), name.as_ref(), m)
+                            })
+                            .transpose()?;
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                            enc_metadata,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let updated = sqlx::query(METADATA_UPDATE_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .bind(enc_metadata)
+                .execute(active.connection_mut())
+                .await?
+                .rows_affected();
+            if updated == 0 {
+                return Err(err_msg!(NotFound, "Entry not found"));
+            }
+            Ok(())
+        })
+    }
+
+    fn update_link<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        link: EntryLink,
+        remove: bool,
+    ) -> BoxFuture<'q, Result<bool, Error>> {
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.entry_links() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Entry links are not supported by this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_source, enc_target, enc_rel_type) = timer
+                .unblock({
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        Result::<_, Error>::Ok((
+                            (
+                                key.encrypt_entry_category(category)?,
+                                key.encrypt_entry_name(name)?,
+                            ),
+                            (
+                                key.encrypt_entry_category(ProfileKey::prepare_input(
+                                    link.category.as_bytes(),
+                                ))?,
+                                key.encrypt_entry_name(ProfileKey::prepare_input(
+                                    link.name.as_bytes(),
+                                ))?,
+                            ),
+                            key.encrypt_link_type(ProfileKey::prepare_input(
+                                link.rel_type.as_bytes(),
+                            ))?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let partitioned = active.partitioned();
+            let mut txn = active.as_transaction().await?;
+            let mut item_ids = Vec::with_capacity(2);
+            for (enc_category, enc_name) in vec![enc_source, enc_target] {
+                let item_id: Option<ProfileId> = sqlx::query_scalar(SEARCH_ITEM_QUERY)
+                    .bind(profile_id)
+                    .bind(kind.code())
+                    .bind(enc_category)
+                    .bind(enc_name)
+                    .fetch_optional(txn.connection_mut())
+                    .await?;
+                match item_id {
+                    Some(item_id) => item_ids.push(item_id),
+                    None if remove => return Ok(false),
+                    None => return Err(err_msg!(NotFound, "Entry not found")),
+                }
+            }
+            let mut query = sqlx::query(if remove {
+                LINK_DELETE_QUERY
+            } else if partitioned {
+                LINK_INSERT_PARTITIONED_QUERY
+            } else {
+                LINK_INSERT_QUERY
+            })
+            .bind(item_ids[0])
+            .bind(item_ids[1])
+            .bind(enc_rel_type);
+            if partitioned && !remove {
+                query = query.bind(profile_id);
+            }
+            let updated = query.execute(txn.connection_mut()).await?.rows_affected();
+            txn.commit().await?;
+            Ok(updated != 0)
         })
     }
 
-    fn update<'q>(
+    fn list_links<'q>(
         &'q mut self,
         kind: EntryKind,
-        operation: EntryOperation,
         category: &'q str,
         name: &'q str,
-        value: Option<&'q [u8]>,
-        tags: Option<&'q [EntryTag]>,
-        expiry_ms: Option<i64>,
-    ) -> BoxFuture<'q, Result<(), Error>> {
-        let category = ProfileKey::prepare_input(category.as_bytes());
-        let name = ProfileKey::prepare_input(name.as_bytes());
-
-        match operation {
-            EntryOperation::Insert => {
-                let value = ProfileKey::prepare_input(value.unwrap());
-                let tags = tags.map(prepare_tags);
-                Box::pin(async move {
-                    let (_, key) = acquire_key(&mut *self).await?;
-                    let (enc_category, enc_name, enc_value, enc_tags) = unblock(move || {
-                        let enc_value =
-                            key.encrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+        rel_type: Option<String>,
+        incoming: bool,
+    ) -> BoxFuture<'q, Result<Vec<EntryLink>, Error>> {
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.entry_links() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Entry links are not supported by this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name, enc_rel_type) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
                         Result::<_, Error>::Ok((
                             key.encrypt_entry_category(category)?,
                             key.encrypt_entry_name(name)?,
-                            enc_value,
-                            tags.transpose()?
-                                .map(|t| key.encrypt_entry_tags(t))
+                            rel_type
+                                .map(|r| {
+                                    key.encrypt_link_type(ProfileKey::prepare_input(r.as_bytes()))
+                                })
                                 .transpose()?,
                         ))
-                    })
-                    .await?;
-                    let mut active = acquire_session(&mut *self).await?;
-                    let mut txn = active.as_transaction().await?;
-                    perform_insert(
-                        &mut txn,
-                        kind,
-                        &enc_category,
-                        &enc_name,
-                        &enc_value,
-                        enc_tags,
-                        expiry_ms,
-                    )
-                    .await?;
-                    txn.commit().await?;
-                    Ok(())
+                    }
                 })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let item_id: ProfileId = sqlx::query_scalar(SEARCH_ITEM_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .fetch_optional(active.connection_mut())
+                .await?
+                .ok_or_else(|| err_msg!(NotFound, "Entry not found"))?;
+            let mut query = String::from(if incoming {
+                LINKS_INCOMING_QUERY
+            } else {
+                LINKS_QUERY
+            });
+            if enc_rel_type.is_some() {
+                query.push_str(" AND l.rel_type = $2");
             }
-            EntryOperation::Replace => {
-                let value = ProfileKey::prepare_input(value.unwrap());
-                let tags = tags.map(prepare_tags);
-                Box::pin(async move {
-                    let (_, key) = acquire_key(&mut *self).await?;
-                    let (enc_category, enc_name, enc_value, enc_tags) = unblock(move || {
-                        let enc_value =
-                            key.encrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+            query.push_str(" ORDER BY t.id");
+            let mut links_query = sqlx::query(query.as_str()).bind(item_id);
+            if let Some(enc_rel_type) = enc_rel_type {
+                links_query = links_query.bind(enc_rel_type);
+            }
+            let mut enc_links = vec![];
+            for row in links_query.fetch_all(active.connection_mut()).await? {
+                enc_links.push((row.try_get(0)?, row.try_get(1)?, row.try_get(2)?));
+            }
+            timer.unblock(move || decrypt_links(enc_links, &key)).await
+        })
+    }
+
+    fn update_collection<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        collection: Option<&'q str>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.collections() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Collections are not supported by this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name, enc_collection) = timer
+                .unblock({
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    let collection = collection.map(|c| ProfileKey::prepare_input(c.as_bytes()));
+                    move || {
                         Result::<_, Error>::Ok((
                             key.encrypt_entry_category(category)?,
                             key.encrypt_entry_name(name)?,
-                            enc_value,
-                            tags.transpose()?
-                                .map(|t| key.encrypt_entry_tags(t))
+                            collection
+                                .map(|c| key.encrypt_collection_name(c))
                                 .transpose()?,
                         ))
-                    })
-                    .await?;
-
-                    let mut active = acquire_session(&mut *self).await?;
-                    let mut txn = active.as_transaction().await?;
-                    perform_remove(&mut txn, kind, &enc_category, &enc_name, false).await?;
-                    perform_insert(
-                        &mut txn,
-                        kind,
-                        &enc_category,
-                        &enc_name,
-                        &enc_value,
-                        enc_tags,
-                        expiry_ms,
-                    )
-                    .await?;
-                    txn.commit().await?;
-                    Ok(())
+                    }
                 })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let updated = sqlx::query(COLLECTION_UPDATE_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .bind(enc_collection)
+                .execute(active.connection_mut())
+                .await?
+                .rows_affected();
+            if updated == 0 {
+                return Err(err_msg!(NotFound, "Entry not found"));
+            }
+            Ok(())
+        })
+    }
+
+    fn collection_members<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        collection: &'q str,
+    ) -> BoxFuture<'q, Result<Vec<(String, String)>, Error>> {
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.collections() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Collections are not supported by this store"
+                ));
             }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let enc_collection = timer
+                .unblock({
+                    let collection = ProfileKey::prepare_input(collection.as_bytes());
+                    let key = key.clone();
+                    move || key.encrypt_collection_name(collection)
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let members: Vec<(Vec<u8>, Vec<u8>)> = sqlx::query_as(COLLECTION_MEMBERS_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_collection)
+                .fetch_all(active.connection_mut())
+                .await?;
+            timer
+                .unblock(move || {
+                    members
+                        .into_iter()
+                        .map(|(enc_category, enc_name)| {
+                            Ok((
+                                key.decrypt_entry_category(enc_category)?,
+                                key.decrypt_entry_name(enc_name)?,
+                            ))
+                        })
+                        .collect()
+                })
+                .await
+        })
+    }
 
-            EntryOperation::Remove => Box::pin(async move {
-                let (_, key) = acquire_key(&mut *self).await?;
-                let (enc_category, enc_name) = unblock(move || {
-                    Result::<_, Error>::Ok((
-                        key.encrypt_entry_category(category)?,
-                        key.encrypt_entry_name(name)?,
-                    ))
+    fn remove_collection<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        collection: &'q str,
+        skip: &'q [(String, String)],
+    ) -> BoxFuture<'q, Result<i64, Error>> {
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.collections() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Collections are not supported by this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_collection, enc_skip) = timer
+                .unblock({
+                    let collection = ProfileKey::prepare_input(collection.as_bytes());
+                    let skip = skip
+                        .iter()
+                        .map(|(category, name)| {
+                            (
+                                ProfileKey::prepare_input(category.as_bytes()),
+                                ProfileKey::prepare_input(name.as_bytes()),
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    move || {
+                        let enc_skip = skip
+                            .into_iter()
+                            .map(|(category, name)| {
+                                Ok((
+                                    key.encrypt_entry_category(category)?,
+                                    key.encrypt_entry_name(name)?,
+                                ))
+                            })
+                            .collect::<Result<Vec<_>, Error>>()?;
+                        Result::<_, Error>::Ok((key.encrypt_collection_name(collection)?, enc_skip))
+                    }
                 })
                 .await?;
-                let mut active = acquire_session(&mut *self).await?;
-                Ok(perform_remove(&mut active, kind, &enc_category, &enc_name, false).await?)
-            }),
-        }
+            let mut active = acquire_session(&mut *self).await?;
+            let mut txn = active.as_transaction().await?;
+            let history = if txn.history() {
+                Some(history_timestamp())
+            } else {
+                None
+            };
+            let mut members: Vec<(Vec<u8>, Vec<u8>)> = sqlx::query_as(COLLECTION_MEMBERS_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_collection)
+                .fetch_all(txn.connection_mut())
+                .await?;
+            members.retain(|member| !enc_skip.contains(member));
+            // each member is removed individually so that its history is closed
+            for (enc_category, enc_name) in members.iter() {
+                perform_remove(&mut txn, kind, enc_category, enc_name, true, history).await?;
+            }
+            txn.commit().await?;
+            Ok(members.len() as i64)
+        })
+    }
+
+    fn reset_connection(&mut self, discard: bool) -> bool {
+        DbSession::reset_connection(self, discard)
+    }
+
+    fn backend_name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn crypto_time(&self) -> Duration {
+        self.crypto_timer().elapsed()
+    }
+
+    fn set_durability(&mut self, durability: Durability) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(DbSession::set_durability(self, durability))
+    }
+
+    fn begin_atomic(&mut self) -> BoxFuture<'_, Result<bool, Error>> {
+        Box::pin(DbSession::begin_atomic(self))
+    }
+
+    fn end_atomic(&mut self, commit: bool) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(DbSession::end_atomic(self, commit))
+    }
+
+    fn access_mode(&mut self) -> BoxFuture<'_, Result<AccessMode, Error>> {
+        Box::pin(async move {
+            let mut active = acquire_session(&mut *self).await?;
+            let mode: Option<String> = sqlx::query_scalar(ACCESS_MODE_QUERY)
+                .fetch_optional(active.connection_mut())
+                .await?;
+            parse_access_mode(mode)
+        })
     }
 
     fn close(self, commit: bool) -> BoxFuture<'static, Result<(), Error>> {
@@ -531,7 +2540,70 @@ impl QueryBackend for DbSession<Postgres> {
     }
 }
 
-impl ExtDatabase for Postgres {}
+impl ExtDatabase for Postgres {
+    fn set_durability(
+        conn: &mut PoolConnection<Self>,
+        durability: Option<Durability>,
+    ) -> BoxFuture<'_, std::result::Result<(), SqlxError>> {
+        Box::pin(async move {
+            sqlx::query(synchronous_commit_statement(durability))
+                .execute(conn)
+                .await?;
+            Ok(())
+        })
+    }
+}
+
+/// The statement applying a durability level to a connection. In the
+/// absence of a level, the default for the database and role is restored
+pub(crate) fn synchronous_commit_statement(durability: Option<Durability>) -> &'static str {
+    match durability {
+        Some(Durability::Full) => "SET synchronous_commit = on",
+        Some(Durability::Normal) => "SET synchronous_commit = local",
+        Some(Durability::Relaxed) => "SET synchronous_commit = off",
+        None => "RESET synchronous_commit",
+    }
+}
+
+impl Type<Postgres> for ProfileId {
+    fn type_info() -> PgTypeInfo {
+        <i64 as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <i64 as Type<Postgres>>::compatible(ty) || ty.name().eq_ignore_ascii_case("uuid")
+    }
+}
+
+impl<'q> Encode<'q, Postgres> for ProfileId {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+        match self {
+            Self::Serial(id) => <i64 as Encode<'q, Postgres>>::encode_by_ref(id, buf),
+            Self::Uuid(id) => {
+                buf.extend_from_slice(&id[..]);
+                IsNull::No
+            }
+        }
+    }
+
+    fn produces(&self) -> Option<PgTypeInfo> {
+        match self {
+            Self::Serial(_) => None,
+            Self::Uuid(_) => Some(PgTypeInfo::with_name("uuid")),
+        }
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for ProfileId {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        if <i64 as Type<Postgres>>::compatible(&value.type_info()) {
+            Ok(Self::Serial(<i64 as Decode<'r, Postgres>>::decode(value)?))
+        } else {
+            let id = <&[u8] as Decode<'r, Postgres>>::decode(value)?;
+            Ok(Self::Uuid(id.try_into()?))
+        }
+    }
+}
 
 impl QueryPrepare for PostgresStore {
     type DB = Postgres;
@@ -540,6 +2612,10 @@ impl QueryPrepare for PostgresStore {
         format!("${}", index)
     }
 
+    fn fold_case(expr: &str) -> String {
+        format!("LOWER(convert_from({}, 'UTF8'))", expr)
+    }
+
     fn limit_query<'q>(
         mut query: String,
         args: &mut QueryParams<'q, Self::DB>,
@@ -560,6 +2636,45 @@ impl QueryPrepare for PostgresStore {
     }
 }
 
+#[cfg(feature = "raw_query")]
+fn decode_raw_row(row: &sqlx::postgres::PgRow) -> Result<RawRow, Error> {
+    use sqlx::Column;
+
+    let mut columns = Vec::with_capacity(row.len());
+    for (idx, column) in row.columns().iter().enumerate() {
+        let value = if row.try_get_raw(idx)?.is_null() {
+            RawValue::Null
+        } else {
+            match column.type_info().name() {
+                "BOOL" => RawValue::Integer(row.try_get::<bool, _>(idx)? as i64),
+                "INT2" => RawValue::Integer(row.try_get::<i16, _>(idx)?.into()),
+                "INT4" => RawValue::Integer(row.try_get::<i32, _>(idx)?.into()),
+                "INT8" => RawValue::Integer(row.try_get(idx)?),
+                "FLOAT4" => RawValue::Real(row.try_get::<f32, _>(idx)?.into()),
+                "FLOAT8" => RawValue::Real(row.try_get(idx)?),
+                "BYTEA" | "UUID" => RawValue::Blob(row.try_get_unchecked(idx)?),
+                "TEXT" | "VARCHAR" | "BPCHAR" | "NAME" => RawValue::Text(row.try_get(idx)?),
+                "TIMESTAMP" => {
+                    RawValue::Text(row.try_get::<chrono::NaiveDateTime, _>(idx)?.to_string())
+                }
+                "TIMESTAMPTZ" => RawValue::Text(
+                    row.try_get::<chrono::DateTime<chrono::Utc>, _>(idx)?
+                        .to_rfc3339(),
+                ),
+                other => {
+                    return Err(err_msg!(
+                        Unsupported,
+                        "Unsupported column type in raw query: {}",
+                        other
+                    ))
+                }
+            }
+        };
+        columns.push((column.name().to_string(), value));
+    }
+    Ok(RawRow::new(columns))
+}
+
 async fn acquire_key(
     session: &mut DbSession<Postgres>,
 ) -> Result<(ProfileId, Arc<ProfileKey>), Error> {
@@ -585,13 +2700,18 @@ async fn resolve_profile_key(
     if let Some((pid, key)) = cache.get_profile(profile.as_str()).await {
         Ok((pid, key))
     } else {
-        if let Some(row) = sqlx::query("SELECT id, profile_key FROM profiles WHERE name=?1")
-            .bind(profile.as_str())
-            .fetch_optional(conn)
-            .await?
+        if let Some(row) =
+            sqlx::query("SELECT id, profile_key, reference FROM profiles WHERE name=?1")
+                .bind(profile.as_str())
+                .fetch_optional(conn)
+                .await?
         {
             let pid = row.try_get(0)?;
-            let key = Arc::new(cache.load_key(row.try_get(1)?).await?);
+            let key = Arc::new(
+                cache
+                    .load_key(profile.clone(), row.try_get(2)?, row.try_get(1)?)
+                    .await?,
+            );
             cache.add_profile(profile, pid, key.clone()).await;
             Ok((pid, key))
         } else {
@@ -606,29 +2726,189 @@ async fn perform_insert<'q>(
     enc_category: &[u8],
     enc_name: &[u8],
     enc_value: &[u8],
+    checksum: Option<&[u8]>,
+    unique_key: Option<&[u8]>,
     enc_tags: Option<Vec<EncEntryTag>>,
-    expiry_ms: Option<i64>,
-) -> Result<(), Error> {
+    expiry: Option<EntryExpiry>,
+    history: Option<chrono::NaiveDateTime>,
+) -> Result<ProfileId, Error> {
     trace!("Insert entry");
-    let row_id: i64 = sqlx::query_scalar(INSERT_QUERY)
-        .bind(active.profile_id)
-        .bind(kind as i16)
-        .bind(enc_category)
-        .bind(enc_name)
-        .bind(enc_value)
-        .bind(expiry_ms.map(expiry_timestamp).transpose()?)
+    // a conflict on the unique key is ignored in the same way as a conflict
+    // on the record name, and reported as a duplicate
+    let mut query = sqlx::query_scalar(match (checksum.is_some(), unique_key.is_some()) {
+        (false, false) => INSERT_QUERY,
+        (true, false) => INSERT_CHECKSUM_QUERY,
+        (false, true) => INSERT_UNIQUE_QUERY,
+        (true, true) => INSERT_CHECKSUM_UNIQUE_QUERY,
+    })
+    .bind(active.profile_id)
+    .bind(kind.code())
+    .bind(enc_category)
+    .bind(enc_name)
+    .bind(enc_value)
+    .bind(expiry.map(expiry_timestamp).transpose()?)
+    .bind(expiry.and_then(|e| e.sliding_ms()));
+    if let Some(checksum) = checksum {
+        query = query.bind(checksum);
+    }
+    if let Some(unique_key) = unique_key {
+        query = query.bind(unique_key);
+    }
+    let row_id: ProfileId = query
         .fetch_optional(active.connection_mut())
         .await?
         .ok_or_else(|| err_msg!(Duplicate, "Duplicate row"))?;
+    let partitioned = active.partitioned();
     if let Some(tags) = enc_tags {
         for tag in tags {
-            sqlx::query(TAG_INSERT_QUERY)
-                .bind(row_id)
-                .bind(&tag.name)
-                .bind(&tag.value)
-                .bind(tag.plaintext as i16)
-                .execute(active.connection_mut())
-                .await?;
+            let mut query = sqlx::query(if partitioned {
+                TAG_INSERT_PARTITIONED_QUERY
+            } else {
+                TAG_INSERT_QUERY
+            })
+            .bind(row_id)
+            .bind(&tag.name)
+            .bind(&tag.value)
+            .bind(tag.plaintext as i16);
+            if partitioned {
+                query = query.bind(active.profile_id);
+            }
+            query.execute(active.connection_mut()).await?;
+        }
+    }
+    if let Some(valid_from) = history {
+        sqlx::query(partition_query(HISTORY_INSERT_QUERY, partitioned).as_ref())
+            .bind(row_id)
+            .bind(valid_from)
+            .bind(active.profile_id)
+            .execute(active.connection_mut())
+            .await?;
+    }
+    Ok(row_id)
+}
+
+async fn perform_insert_batch<'q>(
+    active: &mut DbSessionActive<'q, Postgres>,
+    profile_id: ProfileId,
+    kind: EntryKind,
+    batch: &[EncInsertEntry],
+    partitioned: bool,
+    history: Option<chrono::NaiveDateTime>,
+) -> Result<(), Error> {
+    trace!("Insert entry batch");
+    let checksum = batch.iter().all(|entry| entry.checksum.is_some());
+    let unique = batch.iter().any(|entry| entry.unique_key.is_some());
+    let mut query = sqlx::query(match (checksum, unique) {
+        (false, false) => INSERT_BATCH_QUERY,
+        (true, false) => INSERT_BATCH_CHECKSUM_QUERY,
+        (false, true) => INSERT_BATCH_UNIQUE_QUERY,
+        (true, true) => INSERT_BATCH_CHECKSUM_UNIQUE_QUERY,
+    })
+    .bind(profile_id)
+    .bind(kind.code())
+    .bind(
+        batch
+            .iter()
+            .map(|e| e.category.as_slice())
+            .collect::<Vec<_>>(),
+    )
+    .bind(batch.iter().map(|e| e.name.as_slice()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.value.as_slice()).collect::<Vec<_>>());
+    if checksum {
+        query = query.bind(
+            batch
+                .iter()
+                .filter_map(|e| e.checksum.as_deref())
+                .collect::<Vec<_>>(),
+        );
+    }
+    if unique {
+        query = query.bind(
+            batch
+                .iter()
+                .map(|e| e.unique_key.as_deref())
+                .collect::<Vec<_>>(),
+        );
+    }
+    let rows = query.fetch_all(active.connection_mut()).await?;
+    if rows.len() != batch.len() {
+        return Err(err_msg!(Duplicate, "Duplicate row"));
+    }
+    // the order of the returned rows is not guaranteed, so they are matched
+    // to the inserted records by their unique category and name
+    let mut row_ids = HashMap::with_capacity(rows.len());
+    for row in rows {
+        let row_id: ProfileId = row.try_get(0)?;
+        row_ids.insert(
+            (row.try_get::<Vec<u8>, _>(1)?, row.try_get::<Vec<u8>, _>(2)?),
+            row_id,
+        );
+    }
+
+    let mut tag_item_ids = Vec::new();
+    let mut tag_names = Vec::new();
+    let mut tag_values = Vec::new();
+    let mut tag_plaintext = Vec::new();
+    for entry in batch {
+        let row_id = row_ids
+            .get(&(entry.category.clone(), entry.name.clone()))
+            .copied()
+            .ok_or_else(|| err_msg!(Unexpected, "Missing inserted row"))?;
+        for tag in &entry.tags {
+            tag_item_ids.push(row_id);
+            tag_names.push(tag.name.as_slice());
+            tag_values.push(tag.value.as_slice());
+            tag_plaintext.push(tag.plaintext as i16);
+        }
+    }
+    if !tag_item_ids.is_empty() {
+        let mut query = sqlx::query(if partitioned {
+            TAG_INSERT_BATCH_PARTITIONED_QUERY
+        } else {
+            TAG_INSERT_BATCH_QUERY
+        })
+        .bind(tag_item_ids)
+        .bind(tag_names)
+        .bind(tag_values)
+        .bind(tag_plaintext);
+        if partitioned {
+            query = query.bind(profile_id);
+        }
+        query.execute(active.connection_mut()).await?;
+    }
+
+    if let Some(valid_from) = history {
+        sqlx::query(partition_query(HISTORY_INSERT_BATCH_QUERY, partitioned).as_ref())
+            .bind(row_ids.values().copied().collect::<Vec<ProfileId>>())
+            .bind(valid_from)
+            .bind(profile_id)
+            .execute(active.connection_mut())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Check that a replacement does not conflict with the unique key of another
+/// record. The conflict is detected before the previous version is removed,
+/// so that a failed replacement leaves the store unmodified
+async fn check_unique_key<'q>(
+    active: &mut DbSessionActive<'q, Postgres>,
+    kind: EntryKind,
+    enc_category: &[u8],
+    enc_name: &[u8],
+    unique_key: Option<&[u8]>,
+) -> Result<(), Error> {
+    if let Some(unique_key) = unique_key {
+        let conflict: bool = sqlx::query_scalar(UNIQUE_CONFLICT_QUERY)
+            .bind(active.profile_id)
+            .bind(kind.code())
+            .bind(enc_category)
+            .bind(unique_key)
+            .bind(enc_name)
+            .fetch_one(active.connection_mut())
+            .await?;
+        if conflict {
+            return Err(err_msg!(Duplicate, "Duplicate row"));
         }
     }
     Ok(())
@@ -640,15 +2920,30 @@ async fn perform_remove<'q>(
     enc_category: &[u8],
     enc_name: &[u8],
     ignore_error: bool,
+    history: Option<chrono::NaiveDateTime>,
 ) -> Result<(), Error> {
     trace!("Remove entry");
-    let done = sqlx::query(DELETE_QUERY)
-        .bind(active.profile_id)
-        .bind(kind as i16)
-        .bind(enc_category)
-        .bind(enc_name)
-        .execute(active.connection_mut())
-        .await?;
+    if let Some(valid_to) = history {
+        sqlx::query(HISTORY_CLOSE_QUERY)
+            .bind(active.profile_id)
+            .bind(kind.code())
+            .bind(enc_category)
+            .bind(enc_name)
+            .bind(valid_to)
+            .execute(active.connection_mut())
+            .await?;
+    }
+    let done = sqlx::query(match (active.soft_delete(), active.unique_keys()) {
+        (false, _) => DELETE_QUERY,
+        (true, false) => SOFT_DELETE_QUERY,
+        (true, true) => SOFT_DELETE_UNIQUE_QUERY,
+    })
+    .bind(active.profile_id)
+    .bind(kind.code())
+    .bind(enc_category)
+    .bind(enc_name)
+    .execute(active.connection_mut())
+    .await?;
     if done.rows_affected() == 0 && !ignore_error {
         Err(err_msg!(NotFound, "Entry not found"))
     } else {
@@ -656,6 +2951,59 @@ async fn perform_remove<'q>(
     }
 }
 
+/// Adjust a query for a store with partitioned item and tag tables, so that
+/// lookups against the tag and search tables are restricted to the partition
+/// of the profile. Queries accepting a tag filter or search terms must bind
+/// the profile ID as `$1`.
+fn partition_query(query: &str, partitioned: bool) -> Cow<'_, str> {
+    if partitioned {
+        Cow::Owned(
+            query
+                .replace(
+                    "FROM items_tags it WHERE it.item_id = i.id",
+                    "FROM items_tags it WHERE it.profile_id = i.profile_id AND it.item_id = i.id",
+                )
+                .replace(
+                    "SELECT item_id FROM items_tags WHERE ",
+                    "SELECT item_id FROM items_tags WHERE profile_id = $1 AND ",
+                )
+                .replace(
+                    "SELECT item_id FROM items_search WHERE ",
+                    "SELECT item_id FROM items_search WHERE profile_id = $1 AND ",
+                ),
+        )
+    } else {
+        Cow::Borrowed(query)
+    }
+}
+
+fn rekey_update_query(count: usize) -> String {
+    format!(
+        "UPDATE profiles SET profile_key = v.key
+        FROM (VALUES {}) AS v(id, key) WHERE profiles.id = v.id",
+        batch_values_clause::<PostgresStore>("($$, $$)", count, 2)
+    )
+}
+
+// columns excluded by the projection are selected as empty values
+fn scan_query(projection: Projection) -> String {
+    format!(
+        "SELECT seq, name, {}, {} tags, expiry
+        FROM items i WHERE profile_id = $1 AND kind = $2 AND category = $3
+        AND (expiry IS NULL OR expiry > CURRENT_TIMESTAMP)",
+        if projection.includes_value() {
+            "value"
+        } else {
+            "''::bytea"
+        },
+        if projection.includes_tags() {
+            SCAN_TAGS_COLUMN
+        } else {
+            "''"
+        },
+    )
+}
+
 fn perform_scan<'q>(
     mut active: DbSessionRef<'q, Postgres>,
     profile_id: ProfileId,
@@ -663,14 +3011,17 @@ fn perform_scan<'q>(
     kind: EntryKind,
     category: String,
     tag_filter: Option<TagFilter>,
+    history_filter: HistoryFilter,
     offset: Option<i64>,
     limit: Option<i64>,
+    after_id: Option<i64>,
     for_update: bool,
+    projection: Projection,
 ) -> impl Stream<Item = Result<Vec<EncScanEntry>, Error>> + 'q {
     try_stream! {
         let mut params = QueryParams::new();
         params.push(profile_id);
-        params.push(kind as i16);
+        params.push(kind.code());
         let (enc_category, tag_filter) = unblock({
             let key = key.clone();
             let category = ProfileKey::prepare_input(category.as_bytes());
@@ -683,20 +3034,34 @@ fn perform_scan<'q>(
             }
         }).await?;
         params.push(enc_category);
-        let mut query = extend_query::<PostgresStore>(SCAN_QUERY, &mut params, tag_filter, offset, limit)?;
+        let mut query = extend_query::<PostgresStore>(&scan_query(projection), &mut params, tag_filter, None, None)?;
+        query.push_str(&history_clause::<PostgresStore>(&mut params, history_filter)?);
+        if let Some(after_id) = after_id {
+            params.push(after_id);
+            query.push_str(&replace_arg_placeholders::<PostgresStore>(" AND i.seq > $$", params.len() as i64));
+        }
+        query.push_str(" ORDER BY i.seq");
+        let mut query = PostgresStore::limit_query(query, &mut params, offset, limit);
         if for_update {
             query.push_str(" FOR UPDATE");
         }
-        let mut batch = Vec::with_capacity(PAGE_SIZE);
+        let query = partition_query(&query, active.partitioned()).into_owned();
+        let page_size = active.page_size();
+        let mut batch = Vec::with_capacity(page_size);
 
         let mut acquired = acquire_session(&mut *active).await?;
         let mut rows = sqlx::query_with(query.as_str(), params).fetch(acquired.connection_mut());
+        let mut batch_bytes = 0;
         while let Some(row) = rows.try_next().await? {
             let tags = row.try_get::<Option<String>, _>(3)?.map(String::into_bytes).unwrap_or_default();
-            batch.push(EncScanEntry {
-                name: row.try_get(1)?, value: row.try_get(2)?, tags
-            });
-            if batch.len() == PAGE_SIZE {
+            let entry = EncScanEntry {
+                seq: row.try_get(0)?, name: row.try_get(1)?, value: row.try_get(2)?, tags,
+                expiry: row.try_get(4)?,
+            };
+            batch_bytes += entry.byte_len();
+            batch.push(entry);
+            if batch.len() == page_size || batch_bytes >= PAGE_BYTES {
+                batch_bytes = 0;
                 yield batch.split_off(0);
             }
         }
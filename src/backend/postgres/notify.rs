@@ -0,0 +1,149 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_lite::future;
+use sqlx::postgres::{PgListener, PgPool};
+use tokio::sync::oneshot;
+
+use crate::{
+    backend::{InvalidationHook, RecordInvalidation},
+    error::Error,
+    future::{sleep, spawn_ok},
+    protect::ProfileId,
+    storage::EntryKind,
+};
+
+/// The delay before reconnecting after the listener connection fails
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// The trigger announcing each modified record on the channel of the schema
+/// containing the store tables. Notifications are delivered when the
+/// transaction is committed, and duplicates within a transaction are merged
+pub(crate) const NOTIFY_TRIGGER_DDL: &str = "
+    CREATE OR REPLACE FUNCTION items_notify() RETURNS trigger AS $$
+    DECLARE
+        entry items%ROWTYPE;
+    BEGIN
+        IF TG_OP = 'INSERT' THEN
+            entry := NEW;
+        ELSE
+            entry := OLD;
+        END IF;
+        PERFORM pg_notify('askar_' || TG_TABLE_SCHEMA,
+            entry.profile_id || ':' || entry.kind || ':'
+            || encode(entry.category, 'hex') || ':' || encode(entry.name, 'hex'));
+        RETURN NULL;
+    END
+    $$ LANGUAGE plpgsql;
+    CREATE TRIGGER items_notify AFTER INSERT OR UPDATE OR DELETE ON items
+        FOR EACH ROW EXECUTE FUNCTION items_notify();
+";
+
+/// The notification channel for the store tables in a schema
+pub(crate) fn notify_channel(schema: &str) -> String {
+    format!("askar_{}", schema)
+}
+
+/// Parse the payload of a notification sent by the store trigger
+pub(crate) fn parse_notification(payload: &str) -> Result<RecordInvalidation, Error> {
+    let mut parts = payload.splitn(4, ':');
+    let (profile_id, kind, category, name) =
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(p), Some(k), Some(c), Some(n)) => (p, k, c, n),
+            _ => return Err(err_msg!(Unexpected, "Invalid record notification")),
+        };
+    let profile_id = if let Ok(id) = profile_id.parse::<i64>() {
+        ProfileId::Serial(id)
+    } else {
+        let id = uuid::Uuid::parse_str(profile_id).map_err(err_map!(
+            Unexpected,
+            "Invalid profile ID in record notification"
+        ))?;
+        ProfileId::Uuid(*id.as_bytes())
+    };
+    let kind = kind
+        .parse()
+        .ok()
+        .and_then(EntryKind::from_code)
+        .ok_or_else(|| err_msg!(Unexpected, "Invalid entry kind in record notification"))?;
+    Ok(RecordInvalidation {
+        profile_id,
+        kind,
+        category: hex::decode(category).map_err(err_map!(
+            Unexpected,
+            "Invalid category in record notification"
+        ))?,
+        name: hex::decode(name)
+            .map_err(err_map!(Unexpected, "Invalid name in record notification"))?,
+    })
+}
+
+/// The subscriber task relaying record notifications to an invalidation
+/// hook. The task is stopped when the listener is dropped
+#[derive(Debug)]
+pub(crate) struct InvalidationListener {
+    _stop: oneshot::Sender<()>,
+}
+
+impl InvalidationListener {
+    pub async fn start(
+        conn_pool: &PgPool,
+        channel: &str,
+        hook: Arc<dyn InvalidationHook>,
+    ) -> Result<Self, Error> {
+        let mut listener = PgListener::connect_with(conn_pool).await?;
+        listener.listen(channel).await?;
+        let (stop, mut stopped) = oneshot::channel::<()>();
+        spawn_ok(async move {
+            loop {
+                let next = future::or(async { Some(listener.try_recv().await) }, async {
+                    (&mut stopped).await.ok();
+                    None
+                })
+                .await;
+                match next {
+                    None => break,
+                    Some(Ok(Some(notification))) => {
+                        match parse_notification(notification.payload()) {
+                            Ok(record) => hook.invalidate(&record),
+                            Err(err) => warn!("{}", err),
+                        }
+                    }
+                    Some(Ok(None)) => {
+                        // the connection was lost, and is restored on the next call
+                        warn!("Record notification listener disconnected");
+                        hook.reset();
+                    }
+                    Some(Err(err)) => {
+                        warn!("Error receiving record notifications: {}", err);
+                        hook.reset();
+                        sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        });
+        Ok(Self { _stop: stop })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_record_notification() {
+        let record = parse_notification("12:2:0a0b:ff").unwrap();
+        assert_eq!(record.profile_id, ProfileId::Serial(12));
+        assert_eq!(record.kind, EntryKind::Item);
+        assert_eq!(record.category, vec![10, 11]);
+        assert_eq!(record.name, vec![255]);
+
+        let record = parse_notification("8c4f4b96-f6a8-4a3e-9b7e-0e6a4f1c2d3e:1:00:01").unwrap();
+        assert!(matches!(record.profile_id, ProfileId::Uuid(_)));
+        assert_eq!(record.kind, EntryKind::Kms);
+
+        assert!(parse_notification("12:2:0a0b").is_err());
+        assert!(parse_notification("12:0:0a0b:ff").is_err());
+        assert!(parse_notification("12:2:zz:ff").is_err());
+    }
+}
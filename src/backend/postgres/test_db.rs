@@ -1,86 +1,126 @@
 //! Store wrapper for running tests against a postgres database
 
-use sqlx::{
-    postgres::{PgConnection, Postgres},
-    Connection, Database, TransactionManager,
-};
+use sqlx::{postgres::PgConnection, Connection};
 use std::time::Duration;
 
-use super::provision::{init_db, reset_db, PostgresStoreOptions};
+use super::provision::{init_db, PostgresStoreOptions};
 use super::PostgresStore;
 use crate::{
-    backend::db_utils::{init_keys, random_profile_name},
+    backend::db_utils::{encode_key_check, init_keys, random_profile_name},
     error::Error,
-    future::{block_on, sleep, timeout, unblock},
+    future::{block_on, timeout, unblock},
     protect::{generate_raw_store_key, KeyCache, StoreKeyMethod},
-    storage::Store,
+    storage::{Store, StoreCapabilities, UniqueConstraint},
 };
 
 #[derive(Debug)]
-/// Postgres test database wrapper instance
+/// Postgres test database wrapper instance.
+///
+/// Each instance provisions its tables in a newly-created schema of the
+/// shared test database, so that tests may run in parallel. The schema is
+/// dropped when the instance is dropped.
 pub struct TestDB {
     inst: Option<Store<PostgresStore>>,
-    lock_txn: Option<PgConnection>,
+    schema: String,
+    uri: String,
 }
 
 impl TestDB {
-    /// Provision a new instance of the test database.
-    /// This method blocks until the database lock can be acquired.
+    /// Provision a new instance of the test database, using the connection
+    /// URL defined by the `POSTGRES_URL` environment variable.
     pub async fn provision() -> Result<TestDB, Error> {
         let path = match std::env::var("POSTGRES_URL") {
             Ok(p) if !p.is_empty() => p,
             _ => panic!("'POSTGRES_URL' must be defined"),
         };
+        Self::provision_with(path.as_str()).await
+    }
+
+    /// Provision a new instance of the test database in an isolated schema
+    /// of the database at `uri`. Any store options may be provided as query
+    /// parameters, with the exception of `schema`.
+    pub async fn provision_with(uri: &str) -> Result<TestDB, Error> {
+        let schema = format!("test_{}", uuid::Uuid::new_v4().to_simple());
+        let sep = if uri.contains('?') { '&' } else { '?' };
+        let mut opts =
+            PostgresStoreOptions::new(format!("{}{}schema={}", uri, sep, schema).as_str())?;
 
         let key = generate_raw_store_key(None)?;
+        let nonce_strategy = opts.nonce_strategy;
         let (profile_key, enc_profile_key, store_key, store_key_ref) =
-            unblock(|| init_keys(StoreKeyMethod::RawKey, key)).await?;
+            unblock(move || init_keys(StoreKeyMethod::RawKey, key, nonce_strategy)).await?;
+        let key_check = encode_key_check(&store_key)?;
         let default_profile = random_profile_name();
 
-        let opts = PostgresStoreOptions::new(path.as_str())?;
+        // creates the schema and selects it for all pool connections
         let conn_pool = opts.create_db_pool().await?;
 
-        // we hold a transaction open with a fixed advisory lock value.
-        // this will block until any existing TestDB instance is dropped
-        let lock_txn = loop {
-            // acquire a new connection free from the pool. this is to ensure that
-            // connections are being closed, in case postgres is near the
-            // configured connection limit.
-            let mut lock_txn = conn_pool.acquire().await?.release();
-            <Postgres as Database>::TransactionManager::begin(&mut lock_txn).await?;
-            if sqlx::query_scalar("SELECT pg_try_advisory_xact_lock(99999)")
-                .fetch_one(&mut lock_txn)
-                .await?
-            {
-                break lock_txn;
-            }
-            lock_txn.close().await?;
-            sleep(Duration::from_millis(50)).await;
-        };
-
-        let mut init_txn = conn_pool.begin().await?;
-        // delete existing tables
-        reset_db(&mut *init_txn).await?;
-
         // create tables and add default profile
-        let profile_id =
-            init_db(init_txn, &default_profile, store_key_ref, enc_profile_key).await?;
+        let init_txn = conn_pool.begin().await?;
+        let profile_id = init_db(
+            init_txn,
+            &default_profile,
+            store_key_ref,
+            key_check,
+            enc_profile_key,
+            opts.id_strategy,
+            opts.partitions,
+            opts.history,
+            opts.value_checksum,
+            opts.nonce_strategy,
+            "test",
+        )
+        .await?;
 
         let mut key_cache = KeyCache::new(store_key);
         key_cache.add_profile_mut(default_profile.clone(), profile_id, profile_key);
-        let inst = Store::new(PostgresStore::new(
-            conn_pool,
-            default_profile,
-            key_cache,
-            opts.host,
-            opts.name,
-        ));
+        let uri = std::mem::take(&mut opts.uri);
+        let inst = Store::new(
+            PostgresStore::new(
+                conn_pool,
+                default_profile,
+                key_cache,
+                opts.host,
+                opts.name,
+                opts.history,
+                opts.nonce_strategy,
+            )
+            .with_soft_delete(opts.soft_delete.unwrap_or(false))
+            .with_partitioned(opts.partitions.is_some())
+            .with_value_checksum(opts.value_checksum)
+            .with_capabilities(
+                StoreCapabilities::provisioned(opts.history, opts.value_checksum)
+                    .with_entry_metadata(true)
+                    .with_entry_links(true)
+                    .with_collections(true)
+                    .with_unique_keys(true)
+                    .with_change_notify(true),
+            ),
+        );
 
         Ok(TestDB {
             inst: Some(inst),
-            lock_txn: Some(lock_txn),
+            schema,
+            uri,
         })
     }
+
+    /// Accessor for the name of the schema used by this instance
+    pub fn schema(&self) -> &str {
+        self.schema.as_str()
+    }
+}
+
+impl TestDB {
+    /// Require the values of a set of plaintext tags to be unique within a
+    /// category, as performed by `Store::with_unique_constraint`
+    pub fn with_unique_constraint(mut self, constraint: UniqueConstraint) -> Self {
+        self.inst = self
+            .inst
+            .take()
+            .map(|inst| inst.with_unique_constraint(constraint));
+        self
+    }
 }
 
 impl std::ops::Deref for TestDB {
@@ -93,13 +133,20 @@ impl std::ops::Deref for TestDB {
 
 impl Drop for TestDB {
     fn drop(&mut self) {
-        if let Some(lock_txn) = self.lock_txn.take() {
-            block_on(lock_txn.close()).expect("Error closing database connection");
-        }
         if let Some(inst) = self.inst.take() {
             block_on(timeout(Duration::from_secs(30), inst.close()))
                 .expect("Timed out waiting for the pool connection to close")
                 .expect("Error closing connection pool");
         }
+        let drop_q = format!("DROP SCHEMA IF EXISTS \"{}\" CASCADE", self.schema);
+        block_on(async {
+            let mut conn = PgConnection::connect(self.uri.as_str()).await?;
+            sqlx::query(&drop_q)
+                .persistent(false)
+                .execute(&mut conn)
+                .await?;
+            conn.close().await
+        })
+        .expect("Error removing test schema");
     }
 }
@@ -0,0 +1,471 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use super::{AccessMode, Durability, QueryBackend};
+use crate::{
+    crypto::buffer::SecretBytes,
+    error::Error,
+    future::BoxFuture,
+    storage::{
+        split_namespace, Entry, EntryExpiry, EntryHash, EntryId, EntryKind, EntryLink,
+        EntryOperation, EntryTag, EntryVersion, Projection, TagFilter,
+    },
+};
+
+/// A session backend which restricts access to a set of record categories,
+/// optionally preventing any modifications
+#[derive(Debug)]
+pub struct ScopedQueryBackend<Q: QueryBackend> {
+    inner: Q,
+    categories: Option<Arc<HashSet<String>>>,
+    read_only: bool,
+}
+
+impl<Q: QueryBackend> ScopedQueryBackend<Q> {
+    pub(crate) fn new(inner: Q, categories: Option<Vec<String>>, read_only: bool) -> Self {
+        Self {
+            inner,
+            categories: categories.map(|c| Arc::new(c.into_iter().collect())),
+            read_only,
+        }
+    }
+
+    /// Accessor for the read-only flag
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Check whether a category is accessible within the session scope
+    pub fn allows_category(&self, category: &str) -> bool {
+        self.categories
+            .as_ref()
+            .map(|c| c.contains(category))
+            .unwrap_or(true)
+    }
+
+    fn check_read(&self, category: &str) -> Result<(), Error> {
+        // the scope applies to the record category within any namespace
+        let category = split_namespace(category).1;
+        if self.allows_category(category) {
+            Ok(())
+        } else {
+            Err(err_msg!(
+                Forbidden,
+                "Access to category '{}' is not permitted for this session",
+                category
+            ))
+        }
+    }
+
+    fn check_write(&self, category: &str) -> Result<(), Error> {
+        if self.read_only {
+            Err(err_msg!(Forbidden, "The session is read-only"))
+        } else {
+            self.check_read(category)
+        }
+    }
+}
+
+macro_rules! scoped {
+    ($check:expr, $op:expr) => {
+        match $check {
+            Ok(()) => $op,
+            Err(err) => Box::pin(async move { Err(err) }),
+        }
+    };
+}
+
+impl<Q: QueryBackend> QueryBackend for ScopedQueryBackend<Q> {
+    fn count<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        tag_filter: Option<TagFilter>,
+    ) -> BoxFuture<'q, Result<i64, Error>> {
+        scoped!(
+            self.check_read(category),
+            self.inner.count(kind, category, tag_filter)
+        )
+    }
+
+    fn fetch<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        for_update: bool,
+    ) -> BoxFuture<'q, Result<Option<Entry>, Error>> {
+        scoped!(
+            self.check_read(category),
+            self.inner.fetch(kind, category, name, for_update)
+        )
+    }
+
+    fn fetch_into<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        output: &'q mut SecretBytes,
+    ) -> BoxFuture<'q, Result<bool, Error>> {
+        scoped!(
+            self.check_read(category),
+            self.inner.fetch_into(kind, category, name, output)
+        )
+    }
+
+    fn exists<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+    ) -> BoxFuture<'q, Result<bool, Error>> {
+        scoped!(
+            self.check_read(category),
+            self.inner.exists(kind, category, name)
+        )
+    }
+
+    fn fetch_by_id(
+        &mut self,
+        kind: EntryKind,
+        id: EntryId,
+    ) -> BoxFuture<'_, Result<Option<Entry>, Error>> {
+        Box::pin(async move {
+            // the category is only known once the record has been fetched
+            let entry = self.inner.fetch_by_id(kind, id).await?;
+            if let Some(entry) = entry.as_ref() {
+                self.check_read(&entry.category)?;
+            }
+            Ok(entry)
+        })
+    }
+
+    fn fetch_at<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        timestamp: SystemTime,
+    ) -> BoxFuture<'q, Result<Option<Entry>, Error>> {
+        scoped!(
+            self.check_read(category),
+            self.inner.fetch_at(kind, category, name, timestamp)
+        )
+    }
+
+    fn fetch_history<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        limit: Option<i64>,
+    ) -> BoxFuture<'q, Result<Vec<EntryVersion>, Error>> {
+        scoped!(
+            self.check_read(category),
+            self.inner.fetch_history(kind, category, name, limit)
+        )
+    }
+
+    fn fetch_all<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        tag_filter: Option<TagFilter>,
+        limit: Option<i64>,
+        for_update: bool,
+        projection: Projection,
+    ) -> BoxFuture<'q, Result<Vec<Entry>, Error>> {
+        scoped!(
+            self.check_read(category),
+            self.inner
+                .fetch_all(kind, category, tag_filter, limit, for_update, projection)
+        )
+    }
+
+    fn search<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        terms: Vec<String>,
+        limit: Option<i64>,
+    ) -> BoxFuture<'q, Result<Vec<Entry>, Error>> {
+        scoped!(
+            self.check_read(category),
+            self.inner.search(kind, category, terms, limit)
+        )
+    }
+
+    fn remove_all<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        tag_filter: Option<TagFilter>,
+    ) -> BoxFuture<'q, Result<i64, Error>> {
+        scoped!(
+            self.check_write(category),
+            self.inner.remove_all(kind, category, tag_filter)
+        )
+    }
+
+    fn insert_all<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        entries: Vec<Entry>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        let checked = entries
+            .iter()
+            .map(|entry| self.check_write(&entry.category))
+            .collect::<Result<(), Error>>();
+        scoped!(checked, self.inner.insert_all(kind, entries))
+    }
+
+    fn touch_all<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        tag_filter: Option<TagFilter>,
+        offset: Option<i64>,
+        limit: Option<i64>,
+        expiry: EntryExpiry,
+    ) -> BoxFuture<'q, Result<i64, Error>> {
+        scoped!(
+            self.check_write(category),
+            self.inner
+                .touch_all(kind, category, tag_filter, offset, limit, expiry)
+        )
+    }
+
+    fn update<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        operation: EntryOperation,
+        category: &'q str,
+        name: &'q str,
+        value: Option<&'q [u8]>,
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        scoped!(
+            self.check_write(category),
+            self.inner
+                .update(kind, operation, category, name, value, tags, expiry)
+        )
+    }
+
+    fn insert_returning_id<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<EntryId, Error>> {
+        scoped!(
+            self.check_write(category),
+            self.inner
+                .insert_returning_id(kind, category, name, value, tags, expiry)
+        )
+    }
+
+    fn insert_or_fetch<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<Option<Entry>, Error>> {
+        scoped!(
+            self.check_write(category),
+            self.inner
+                .insert_or_fetch(kind, category, name, value, tags, expiry)
+        )
+    }
+
+    fn replace_checked<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+        expected: Option<EntryHash>,
+    ) -> BoxFuture<'q, Result<EntryHash, Error>> {
+        scoped!(
+            self.check_write(category),
+            self.inner
+                .replace_checked(kind, category, name, value, tags, expiry, expected)
+        )
+    }
+
+    fn update_search_terms<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        terms: Vec<String>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        scoped!(
+            self.check_write(category),
+            self.inner.update_search_terms(kind, category, name, terms)
+        )
+    }
+
+    fn fetch_metadata<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+    ) -> BoxFuture<'q, Result<Option<SecretBytes>, Error>> {
+        scoped!(
+            self.check_read(category),
+            self.inner.fetch_metadata(kind, category, name)
+        )
+    }
+
+    fn update_metadata<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        metadata: Option<&'q [u8]>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        scoped!(
+            self.check_write(category),
+            self.inner.update_metadata(kind, category, name, metadata)
+        )
+    }
+
+    fn update_link<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        link: EntryLink,
+        remove: bool,
+    ) -> BoxFuture<'q, Result<bool, Error>> {
+        scoped!(
+            self.check_write(category)
+                .and_then(|_| self.check_read(&link.category)),
+            self.inner.update_link(kind, category, name, link, remove)
+        )
+    }
+
+    fn list_links<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        rel_type: Option<String>,
+        incoming: bool,
+    ) -> BoxFuture<'q, Result<Vec<EntryLink>, Error>> {
+        if let Err(err) = self.check_read(category) {
+            return Box::pin(async move { Err(err) });
+        }
+        let categories = self.categories.clone();
+        Box::pin(async move {
+            let links = self
+                .inner
+                .list_links(kind, category, name, rel_type, incoming)
+                .await?;
+            // links to records outside of the session scope are omitted
+            Ok(links
+                .into_iter()
+                .filter(|link| {
+                    categories
+                        .as_ref()
+                        .map(|c| c.contains(&link.category))
+                        .unwrap_or(true)
+                })
+                .collect())
+        })
+    }
+
+    fn update_collection<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        collection: Option<&'q str>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        scoped!(
+            self.check_write(category),
+            self.inner
+                .update_collection(kind, category, name, collection)
+        )
+    }
+
+    fn remove_collection<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        collection: &'q str,
+        skip: &'q [(String, String)],
+    ) -> BoxFuture<'q, Result<i64, Error>> {
+        // the members of a collection may belong to any category
+        let check = if self.read_only {
+            Err(err_msg!(Forbidden, "The session is read-only"))
+        } else if self.categories.is_some() {
+            Err(err_msg!(
+                Forbidden,
+                "Collections cannot be removed by a session limited to specific categories"
+            ))
+        } else {
+            Ok(())
+        };
+        scoped!(check, self.inner.remove_collection(kind, collection, skip))
+    }
+
+    fn collection_members<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        collection: &'q str,
+    ) -> BoxFuture<'q, Result<Vec<(String, String)>, Error>> {
+        // the members of a collection may belong to any category
+        let check = if self.categories.is_some() {
+            Err(err_msg!(
+                Forbidden,
+                "Collections cannot be listed by a session limited to specific categories"
+            ))
+        } else {
+            Ok(())
+        };
+        scoped!(check, self.inner.collection_members(kind, collection))
+    }
+
+    fn set_durability(&mut self, durability: Durability) -> BoxFuture<'_, Result<(), Error>> {
+        self.inner.set_durability(durability)
+    }
+
+    fn begin_atomic(&mut self) -> BoxFuture<'_, Result<bool, Error>> {
+        self.inner.begin_atomic()
+    }
+
+    fn end_atomic(&mut self, commit: bool) -> BoxFuture<'_, Result<(), Error>> {
+        self.inner.end_atomic(commit)
+    }
+
+    fn access_mode(&mut self) -> BoxFuture<'_, Result<AccessMode, Error>> {
+        self.inner.access_mode()
+    }
+
+    fn reset_connection(&mut self, discard: bool) -> bool {
+        self.inner.reset_connection(discard)
+    }
+
+    fn backend_name(&self) -> &'static str {
+        self.inner.backend_name()
+    }
+
+    fn crypto_time(&self) -> Duration {
+        self.inner.crypto_time()
+    }
+
+    fn close(self, commit: bool) -> BoxFuture<'static, Result<(), Error>> {
+        self.inner.close(commit)
+    }
+}
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use sqlx::sqlite::{Sqlite, SqlitePool};
+use tokio::sync::{mpsc, oneshot};
+
+use super::{check_unique_key, perform_insert, perform_remove, resolve_profile_key};
+use crate::{
+    backend::db_utils::{
+        history_timestamp, CoalescedWrite, DbSession, DbSessionActive, WriteQueue,
+    },
+    error::Error,
+    future::{spawn_ok, timeout},
+    protect::{IdStrategy, KeyCache},
+    storage::EntryOperation,
+};
+
+/// The default maximum number of updates committed in one transaction
+pub(crate) const DEFAULT_COALESCE_OPS: usize = 100;
+
+/// The limits on the updates grouped into a single transaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct WriteCoalescing {
+    /// The longest time an update waits for other updates to arrive
+    pub max_delay: Duration,
+    /// The maximum number of updates committed together
+    pub max_ops: usize,
+}
+
+/// Start a task committing the submitted updates in batches
+pub(crate) fn spawn_write_coalescer(
+    conn_pool: SqlitePool,
+    key_cache: Arc<KeyCache>,
+    history: bool,
+    value_checksum: bool,
+    id_strategy: IdStrategy,
+    config: WriteCoalescing,
+) -> WriteQueue {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    spawn_ok(async move {
+        // the task ends once the store and its sessions have been dropped
+        while let Some(first) = receiver.recv().await {
+            let mut batch = vec![first];
+            let deadline = Instant::now() + config.max_delay;
+            while batch.len() < config.max_ops.max(1) {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                match timeout(remaining, receiver.recv()).await {
+                    Some(Some(write)) => batch.push(write),
+                    _ => break,
+                }
+            }
+            trace!("Commit {} coalesced updates", batch.len());
+            let session = DbSession::new(
+                conn_pool.clone(),
+                key_cache.clone(),
+                String::new(),
+                false,
+                history,
+            )
+            .with_value_checksum(value_checksum)
+            .with_id_strategy(id_strategy);
+            perform_batch(session, batch).await;
+        }
+    });
+    sender
+}
+
+/// Submit an update to the write queue and wait for its transaction to commit
+pub(crate) async fn submit_write(queue: &WriteQueue, write: CoalescedWrite) -> Result<(), Error> {
+    let (sender, receiver) = oneshot::channel();
+    queue
+        .send((write, sender))
+        .map_err(|_| err_msg!(Backend, "The write queue has been closed"))?;
+    receiver
+        .await
+        .map_err(|_| err_msg!(Backend, "The coalesced update was abandoned"))?
+}
+
+async fn perform_batch(
+    session: DbSession<Sqlite>,
+    batch: Vec<(CoalescedWrite, oneshot::Sender<Result<(), Error>>)>,
+) {
+    let (profile_id, key) = (batch[0].0.profile_id, batch[0].0.key.clone());
+    let mut session = session.with_profile_key(profile_id, key);
+    let mut results = Vec::with_capacity(batch.len());
+    let committed = async {
+        let mut active = session.make_active(&resolve_profile_key).await?;
+        let mut txn = active.as_transaction().await?;
+        let history = if txn.history() {
+            Some(history_timestamp())
+        } else {
+            None
+        };
+        for (write, sender) in batch {
+            // each update is applied within a savepoint, so that a failed
+            // update does not affect the others in the batch
+            let result = perform_write(&mut txn, write, history).await;
+            results.push((sender, result));
+        }
+        txn.commit().await
+    }
+    .await;
+    // updates which were not attempted are abandoned when the batch is dropped
+    for (sender, result) in results {
+        let result = match (&committed, result) {
+            (Ok(()), result) => result,
+            (Err(err), _) => Err(Error::from_msg(
+                err.kind(),
+                format!("Error committing coalesced updates: {}", err),
+            )),
+        };
+        sender.send(result).ok();
+    }
+}
+
+async fn perform_write(
+    txn: &mut DbSessionActive<'_, Sqlite>,
+    write: CoalescedWrite,
+    history: Option<chrono::NaiveDateTime>,
+) -> Result<(), Error> {
+    let mut savepoint = txn.transaction().await?;
+    savepoint.profile_id = write.profile_id;
+    match write.operation {
+        op @ EntryOperation::Insert | op @ EntryOperation::Replace => {
+            if op == EntryOperation::Replace {
+                check_unique_key(
+                    &mut savepoint,
+                    write.kind,
+                    &write.category,
+                    &write.name,
+                    write.unique_key.as_deref(),
+                )
+                .await?;
+                perform_remove(
+                    &mut savepoint,
+                    write.kind,
+                    &write.category,
+                    &write.name,
+                    false,
+                    history,
+                )
+                .await?;
+            }
+            perform_insert(
+                &mut savepoint,
+                write.kind,
+                &write.category,
+                &write.name,
+                write.value.as_deref().unwrap_or_default(),
+                write.checksum.as_deref(),
+                write.unique_key.as_deref(),
+                write.tags,
+                write.expiry,
+                history,
+            )
+            .await?;
+        }
+        EntryOperation::Remove => {
+            perform_remove(
+                &mut savepoint,
+                write.kind,
+                &write.category,
+                &write.name,
+                false,
+                history,
+            )
+            .await?;
+        }
+    }
+    savepoint.commit().await
+}
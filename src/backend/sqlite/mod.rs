@@ -1,6 +1,10 @@
-use std::collections::BTreeMap;
+use std::convert::TryInto;
 use std::fmt::{self, Debug, Formatter};
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::time::{Duration, SystemTime};
 
 use async_stream::try_stream;
 use futures_lite::{
@@ -9,27 +13,51 @@ use futures_lite::{
 };
 
 use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
     pool::PoolConnection,
-    sqlite::{Sqlite, SqlitePool},
-    Database, Error as SqlxError, Row, TransactionManager,
+    sqlite::{Sqlite, SqliteArgumentValue, SqlitePool, SqliteTypeInfo, SqliteValueRef},
+    Connection, Database, Decode, Encode, Error as SqlxError, Row, TransactionManager, Type,
+    ValueRef,
 };
+use zeroize::Zeroize;
 
 use crate::{
     backend::{
         db_utils::{
-            decode_tags, decrypt_scan_batch, encode_profile_key, encode_tag_filter,
-            expiry_timestamp, extend_query, prepare_tags, random_profile_name, DbSession,
-            DbSessionActive, DbSessionRef, EncScanEntry, ExtDatabase, QueryParams, QueryPrepare,
-            PAGE_SIZE,
+            batch_values_clause, check_integrity_batch, decode_tags, decrypt_history_batch,
+            decrypt_links, decrypt_scan_batch, decrypt_scan_page, decrypt_storage_report,
+            encode_key_check, encode_tag_filter, encrypt_insert_batch, expiry_timestamp,
+            extend_query, from_history_timestamp, history_clause, history_timestamp,
+            parse_access_mode, prepare_search_terms, prepare_tags, random_profile_name,
+            replace_arg_placeholders, rewrap_profile_keys, search_clause, to_history_timestamp,
+            CoalescedWrite, DbSession, DbSessionActive, DbSessionRef, EncCategoryUsage,
+            EncHistoryEntry, EncIntegrityEntry, EncScanEntry, Expiry, ExtDatabase, QueryParams,
+            QueryPrepare, WriteQueue, ACCESS_MODE_QUERY, PAGE_BYTES, PAGE_SIZE, REKEY_PAGE_SIZE,
         },
-        types::{Backend, QueryBackend},
+        types::{AccessMode, Backend, Durability, MaintenanceMode, QueryBackend},
     },
-    error::Error,
+    crypto::buffer::SecretBytes,
+    error::{Error, ErrorKind},
     future::{unblock, BoxFuture},
-    protect::{EntryEncryptor, KeyCache, PassKey, ProfileId, ProfileKey, StoreKeyMethod},
-    storage::{EncEntryTag, Entry, EntryKind, EntryOperation, EntryTag, Scan, TagFilter},
+    protect::{
+        EntryEncryptor, IdStrategy, KeyCache, KeyCacheStats, NonceStrategy, PassKey, ProfileId,
+        ProfileKey, Protected, StoreKeyMethod, TenantKeyProvider,
+    },
+    storage::{
+        split_namespace, EncEntryTag, Entry, EntryExpiry, EntryHash, EntryId, EntryKind, EntryLink,
+        EntryOperation, EntrySeq, EntryTag, EntryVersion, HistoryFilter, IntegrityReport,
+        ProfileShredding, Projection, Scan, StorageReport, StoreCapabilities, StoreTuning,
+        TagFilter, UniqueConstraint,
+    },
 };
 
+#[cfg(feature = "raw_query")]
+use crate::storage::{RawRow, RawValue};
+
+mod coalesce;
+use coalesce::{spawn_write_coalescer, submit_write, WriteCoalescing};
+
 mod provision;
 pub use provision::SqliteStoreOptions;
 
@@ -38,24 +66,118 @@ const COUNT_QUERY: &'static str = "SELECT COUNT(*) FROM items i
     AND (expiry IS NULL OR expiry > DATETIME('now'))";
 const DELETE_QUERY: &'static str = "DELETE FROM items
     WHERE profile_id = ?1 AND kind = ?2 AND category = ?3 AND name = ?4";
-const FETCH_QUERY: &'static str = "SELECT i.id, i.value,
+const FETCH_QUERY: &'static str = "SELECT i.rowid, i.value,
     (SELECT GROUP_CONCAT(it.plaintext || ':' || HEX(it.name) || ':' || HEX(it.value))
-        FROM items_tags it WHERE it.item_id = i.id) AS tags
+        FROM items_tags it WHERE it.item_id = i.id) AS tags, i.expiry_sliding, i.expiry
     FROM items i WHERE i.profile_id = ?1 AND i.kind = ?2
     AND i.category = ?3 AND i.name = ?4
     AND (i.expiry IS NULL OR i.expiry > DATETIME('now'))";
-const INSERT_QUERY: &'static str =
-    "INSERT OR IGNORE INTO items (profile_id, kind, category, name, value, expiry)
-    VALUES (?1, ?2, ?3, ?4, ?5, ?6)";
-const SCAN_QUERY: &'static str = "SELECT i.id, i.name, i.value,
+const FETCH_BY_ID_QUERY: &'static str = "SELECT i.rowid, i.category, i.name, i.value,
     (SELECT GROUP_CONCAT(it.plaintext || ':' || HEX(it.name) || ':' || HEX(it.value))
-        FROM items_tags it WHERE it.item_id = i.id) AS tags
-    FROM items i WHERE i.profile_id = ?1 AND i.kind = ?2 AND i.category = ?3
+        FROM items_tags it WHERE it.item_id = i.id) AS tags, i.expiry_sliding, i.expiry
+    FROM items i WHERE i.profile_id = ?1 AND i.kind = ?2 AND i.id = ?3
+    AND (i.expiry IS NULL OR i.expiry > DATETIME('now'))";
+const FETCH_VALUE_QUERY: &'static str = "SELECT i.value
+    FROM items i WHERE i.profile_id = ?1 AND i.kind = ?2
+    AND i.category = ?3 AND i.name = ?4
     AND (i.expiry IS NULL OR i.expiry > DATETIME('now'))";
+const EXISTS_QUERY: &'static str = "SELECT 1
+    FROM items i WHERE i.profile_id = ?1 AND i.kind = ?2
+    AND i.category = ?3 AND i.name = ?4
+    AND (i.expiry IS NULL OR i.expiry > DATETIME('now')) LIMIT 1";
+const INSERT_QUERY: &'static str =
+    "INSERT OR IGNORE INTO items (profile_id, kind, category, name, value, expiry, expiry_sliding)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)";
+const INSERT_CHECKSUM_QUERY: &'static str = "INSERT OR IGNORE INTO items
+    (profile_id, kind, category, name, value, expiry, expiry_sliding, checksum)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)";
+const INSERT_UNIQUE_QUERY: &'static str = "INSERT OR IGNORE INTO items
+    (profile_id, kind, category, name, value, expiry, expiry_sliding, unique_key)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)";
+const INSERT_CHECKSUM_UNIQUE_QUERY: &'static str = "INSERT OR IGNORE INTO items
+    (profile_id, kind, category, name, value, expiry, expiry_sliding, checksum, unique_key)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)";
+const EXTEND_EXPIRY_QUERY: &'static str = "UPDATE items SET expiry = ?2 WHERE rowid = ?1";
+const COLLECTION_UPDATE_QUERY: &'static str = "UPDATE items SET collection = ?5
+    WHERE profile_id = ?1 AND kind = ?2 AND category = ?3 AND name = ?4
+    AND (expiry IS NULL OR expiry > DATETIME('now'))";
+const COLLECTION_MEMBERS_QUERY: &'static str = "SELECT category, name FROM items
+    WHERE profile_id = ?1 AND kind = ?2 AND collection = ?3
+    AND (expiry IS NULL OR expiry > DATETIME('now'))";
+const LINK_INSERT_QUERY: &'static str = "INSERT OR IGNORE INTO items_links
+    (item_id, target_id, rel_type) VALUES (?1, ?2, ?3)";
+const LINK_DELETE_QUERY: &'static str = "DELETE FROM items_links
+    WHERE item_id = ?1 AND target_id = ?2 AND rel_type = ?3";
+const LINKS_QUERY: &'static str = "SELECT l.rel_type, t.category, t.name
+    FROM items_links l JOIN items t ON t.id = l.target_id
+    WHERE l.item_id = ?1 AND (t.expiry IS NULL OR t.expiry > DATETIME('now'))";
+const LINKS_INCOMING_QUERY: &'static str = "SELECT l.rel_type, t.category, t.name
+    FROM items_links l JOIN items t ON t.id = l.item_id
+    WHERE l.target_id = ?1 AND (t.expiry IS NULL OR t.expiry > DATETIME('now'))";
+const METADATA_FETCH_QUERY: &'static str = "SELECT metadata FROM items
+    WHERE profile_id = ?1 AND kind = ?2 AND category = ?3 AND name = ?4
+    AND (expiry IS NULL OR expiry > DATETIME('now'))";
+const METADATA_UPDATE_QUERY: &'static str = "UPDATE items SET metadata = ?5
+    WHERE profile_id = ?1 AND kind = ?2 AND category = ?3 AND name = ?4
+    AND (expiry IS NULL OR expiry > DATETIME('now'))";
+const INTEGRITY_QUERY: &'static str = "SELECT kind, category, name, value, checksum
+    FROM items WHERE profile_id = ?1
+    AND (expiry IS NULL OR expiry > DATETIME('now')) ORDER BY id";
+const INTEGRITY_NO_CHECKSUM_QUERY: &'static str = "SELECT kind, category, name, value, NULL
+    FROM items WHERE profile_id = ?1
+    AND (expiry IS NULL OR expiry > DATETIME('now')) ORDER BY id";
+const SCAN_TAGS_COLUMN: &'static str =
+    "(SELECT GROUP_CONCAT(it.plaintext || ':' || HEX(it.name) || ':' || HEX(it.value))
+        FROM items_tags it WHERE it.item_id = i.id)";
 const DELETE_ALL_QUERY: &'static str = "DELETE FROM items AS i
     WHERE i.profile_id = ?1 AND i.kind = ?2 AND i.category = ?3";
+const TOUCH_SELECT_QUERY: &'static str = "SELECT i.id FROM items i
+    WHERE i.profile_id = ?1 AND i.kind = ?2 AND i.category = ?3
+    AND (i.expiry IS NULL OR i.expiry > DATETIME('now'))";
 const TAG_INSERT_QUERY: &'static str = "INSERT INTO items_tags
     (item_id, name, value, plaintext) VALUES (?1, ?2, ?3, ?4)";
+const SEARCH_ITEM_QUERY: &'static str = "SELECT id FROM items
+    WHERE profile_id = ?1 AND kind = ?2 AND category = ?3 AND name = ?4
+    AND (expiry IS NULL OR expiry > DATETIME('now'))";
+const SEARCH_DELETE_QUERY: &'static str = "DELETE FROM items_search WHERE item_id = ?1";
+const SEARCH_INSERT_QUERY: &'static str = "INSERT INTO items_search
+    (item_id, token) VALUES (?1, ?2)";
+const HISTORY_INSERT_QUERY: &'static str = "INSERT INTO items_history
+    (profile_id, kind, category, name, value, tags, valid_from)
+    SELECT i.profile_id, i.kind, i.category, i.name, i.value,
+    (SELECT GROUP_CONCAT(it.plaintext || ':' || HEX(it.name) || ':' || HEX(it.value))
+        FROM items_tags it WHERE it.item_id = i.id), ?2
+    FROM items i WHERE i.id = ?1";
+const HISTORY_CLOSE_QUERY: &'static str = "UPDATE items_history SET valid_to = ?5
+    WHERE profile_id = ?1 AND kind = ?2 AND category = ?3 AND name = ?4
+    AND valid_to IS NULL";
+const HISTORY_CLOSE_ALL_QUERY: &'static str = "SELECT i.profile_id, i.kind, i.category, i.name
+    FROM items i WHERE i.profile_id = ?1 AND i.kind = ?2 AND i.category = ?3";
+const HISTORY_FETCH_AT_QUERY: &'static str = "SELECT value, tags, valid_from, valid_to
+    FROM items_history
+    WHERE profile_id = ?1 AND kind = ?2 AND category = ?3 AND name = ?4
+    AND valid_from <= ?5 AND (valid_to IS NULL OR valid_to > ?5)
+    ORDER BY valid_from DESC, id DESC LIMIT 1";
+const HISTORY_LIST_QUERY: &'static str = "SELECT value, tags, valid_from, valid_to
+    FROM items_history
+    WHERE profile_id = ?1 AND kind = ?2 AND category = ?3 AND name = ?4
+    ORDER BY valid_from DESC, id DESC";
+const ITEM_ID_QUERY: &'static str = "SELECT id FROM items WHERE rowid = ?1";
+const UNIQUE_CONFLICT_QUERY: &'static str = "SELECT EXISTS(SELECT 1 FROM items
+    WHERE profile_id = ?1 AND kind = ?2 AND category = ?3 AND unique_key = ?4
+    AND name != ?5)";
+const PROFILE_ID_QUERY: &'static str = "SELECT id FROM profiles WHERE rowid = ?1";
+const PROFILE_KEYS_QUERY: &'static str = "SELECT id, name, reference, profile_key
+    FROM profiles WHERE profile_key IS NOT NULL";
+const STORAGE_REPORT_QUERY: &'static str = "SELECT i.profile_id, i.kind, i.category, COUNT(*),
+    SUM(LENGTH(i.category) + LENGTH(i.name) + LENGTH(i.value)
+        + (SELECT COALESCE(SUM(LENGTH(it.name) + LENGTH(it.value)), 0)
+        FROM items_tags it WHERE it.item_id = i.id))
+    FROM items i GROUP BY i.profile_id, i.kind, i.category";
+const REKEY_FETCH_QUERY: &'static str = "SELECT id, profile_key FROM profiles
+    WHERE reference IS NULL ORDER BY id LIMIT ?1";
+const REKEY_FETCH_NEXT_QUERY: &'static str = "SELECT id, profile_key FROM profiles
+    WHERE id > ?1 AND reference IS NULL ORDER BY id LIMIT ?2";
 
 /// A Sqlite database store
 pub struct SqliteStore {
@@ -63,6 +185,15 @@ pub struct SqliteStore {
     default_profile: String,
     key_cache: Arc<KeyCache>,
     path: String,
+    history: bool,
+    nonce_strategy: NonceStrategy,
+    value_checksum: bool,
+    id_strategy: IdStrategy,
+    capabilities: StoreCapabilities,
+    unique_constraints: Arc<Vec<UniqueConstraint>>,
+    page_size: AtomicUsize,
+    write_queue: Option<WriteQueue>,
+    durability: Option<Durability>,
 }
 
 impl SqliteStore {
@@ -71,14 +202,64 @@ impl SqliteStore {
         default_profile: String,
         key_cache: KeyCache,
         path: String,
+        history: bool,
+        nonce_strategy: NonceStrategy,
     ) -> Self {
         Self {
             conn_pool,
             default_profile,
             key_cache: Arc::new(key_cache),
             path,
+            history,
+            nonce_strategy,
+            value_checksum: false,
+            id_strategy: IdStrategy::Serial,
+            capabilities: StoreCapabilities::provisioned(history, false),
+            unique_constraints: Arc::new(Vec::new()),
+            page_size: AtomicUsize::new(PAGE_SIZE),
+            write_queue: None,
+            durability: None,
         }
     }
+
+    /// Set whether a checksum is stored alongside each entry value
+    pub(crate) fn with_value_checksum(mut self, value_checksum: bool) -> Self {
+        self.value_checksum = value_checksum;
+        self
+    }
+
+    /// Set the strategy used to generate profile and item identifiers
+    pub(crate) fn with_id_strategy(mut self, id_strategy: IdStrategy) -> Self {
+        self.id_strategy = id_strategy;
+        self
+    }
+
+    /// Set the optional features supported by the store
+    pub(crate) fn with_capabilities(mut self, capabilities: StoreCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Set the durability level applied to each connection by the pool
+    pub(crate) fn with_durability(mut self, durability: Option<Durability>) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Commit the updates of non-transactional sessions in batches
+    pub(crate) fn with_write_coalescing(mut self, config: Option<WriteCoalescing>) -> Self {
+        self.write_queue = config.map(|config| {
+            spawn_write_coalescer(
+                self.conn_pool.clone(),
+                self.key_cache.clone(),
+                self.history,
+                self.value_checksum,
+                self.id_strategy,
+                config,
+            )
+        });
+        self
+    }
 }
 
 impl Debug for SqliteStore {
@@ -86,6 +267,13 @@ impl Debug for SqliteStore {
         f.debug_struct("SqliteStore")
             .field("default_profile", &self.default_profile)
             .field("path", &self.path)
+            .field("history", &self.history)
+            .field("nonce_strategy", &self.nonce_strategy)
+            .field("value_checksum", &self.value_checksum)
+            .field("id_strategy", &self.id_strategy)
+            .field("write_coalescing", &self.write_queue.is_some())
+            .field("durability", &self.durability)
+            .field("page_size", &self.page_size)
             .finish()
     }
 }
@@ -100,20 +288,33 @@ impl Backend for SqliteStore {
     fn create_profile(&self, name: Option<String>) -> BoxFuture<'_, Result<String, Error>> {
         let name = name.unwrap_or_else(random_profile_name);
         Box::pin(async move {
-            let key = ProfileKey::new()?;
-            let enc_key = key.to_bytes()?;
+            let key = ProfileKey::new_with_strategy(self.nonce_strategy)?;
+            let (enc_key, reference) = self.key_cache.wrap_key(name.clone(), &key).await?;
             let mut conn = self.conn_pool.acquire().await?;
-            let done =
-                sqlx::query("INSERT OR IGNORE INTO profiles (name, profile_key) VALUES (?1, ?2)")
-                    .bind(&name)
-                    .bind(enc_key.as_ref())
-                    .execute(&mut conn)
-                    .await?;
+            let done = sqlx::query(
+                "INSERT OR IGNORE INTO profiles (name, profile_key, reference)
+                    VALUES (?1, ?2, ?3)",
+            )
+            .bind(&name)
+            .bind(enc_key.as_slice())
+            .bind(reference)
+            .execute(&mut conn)
+            .await?;
             if done.rows_affected() == 0 {
                 return Err(err_msg!(Duplicate, "Duplicate profile name"));
             }
+            // a serial identifier is an alias for the row identifier
+            let pid = match self.id_strategy {
+                IdStrategy::Serial => ProfileId::from(done.last_insert_rowid()),
+                IdStrategy::Uuid => {
+                    sqlx::query_scalar(PROFILE_ID_QUERY)
+                        .bind(done.last_insert_rowid())
+                        .fetch_one(&mut conn)
+                        .await?
+                }
+            };
             self.key_cache
-                .add_profile(name.clone(), done.last_insert_rowid(), Arc::new(key))
+                .add_profile(name.clone(), pid, Arc::new(key))
                 .await;
             Ok(name)
         })
@@ -123,15 +324,65 @@ impl Backend for SqliteStore {
         self.default_profile.as_str()
     }
 
+    fn capabilities(&self) -> StoreCapabilities {
+        self.capabilities
+    }
+
     fn remove_profile(&self, name: String) -> BoxFuture<'_, Result<bool, Error>> {
         Box::pin(async move {
             let mut conn = self.conn_pool.acquire().await?;
-            Ok(sqlx::query("DELETE FROM profiles WHERE name=?")
+            let removed = sqlx::query("DELETE FROM profiles WHERE name=?")
                 .bind(&name)
                 .execute(&mut conn)
                 .await?
                 .rows_affected()
-                != 0)
+                != 0;
+            self.key_cache.remove_profile(&name);
+            Ok(removed)
+        })
+    }
+
+    fn shred_profile(
+        &self,
+        name: String,
+    ) -> BoxFuture<'_, Result<Option<ProfileShredding>, Error>> {
+        Box::pin(async move {
+            let mut conn = self.conn_pool.acquire().await?;
+            // overwrite the deleted content, so that the wrapped profile key
+            // does not remain in free pages of the database file
+            sqlx::query("PRAGMA secure_delete = ON")
+                .execute(&mut conn)
+                .await?;
+            let mut txn = conn.begin().await?;
+            let row = sqlx::query("SELECT profile_key, reference FROM profiles WHERE name=?1")
+                .bind(&name)
+                .fetch_optional(&mut txn)
+                .await?;
+            if row.is_some() {
+                sqlx::query("DELETE FROM profiles WHERE name=?1")
+                    .bind(&name)
+                    .execute(&mut txn)
+                    .await?;
+            }
+            txn.commit().await?;
+            sqlx::query("PRAGMA secure_delete = OFF")
+                .execute(&mut conn)
+                .await?;
+            let (enc_key, reference) = match row {
+                Some(row) => (
+                    row.try_get::<Option<Vec<u8>>, _>(0)?.unwrap_or_default(),
+                    row.try_get::<Option<String>, _>(1)?,
+                ),
+                None => return Ok(None),
+            };
+            let tenant_key = reference.is_some();
+            let destroyed = self
+                .key_cache
+                .destroy_profile_key(name.clone(), reference)
+                .await?;
+            Ok(Some(ProfileShredding::new(
+                name, &enc_key, tenant_key, destroyed,
+            )))
         })
     }
 
@@ -143,33 +394,44 @@ impl Backend for SqliteStore {
         let pass_key = pass_key.into_owned();
         Box::pin(async move {
             let (store_key, store_key_ref) = unblock(move || method.resolve(pass_key)).await?;
-            let store_key = Arc::new(store_key);
+            let store_key = Arc::new(Protected::new(store_key));
+            let prev_key = self.key_cache.store_key.clone();
             let mut txn = self.conn_pool.begin().await?;
-            let mut rows = sqlx::query("SELECT id, profile_key FROM profiles").fetch(&mut txn);
-            let mut upd_keys = BTreeMap::<ProfileId, Vec<u8>>::new();
-            while let Some(row) = rows.next().await {
-                let row = row?;
-                let pid = row.try_get(0)?;
-                let enc_key = row.try_get(1)?;
-                let profile_key = self.key_cache.load_key(enc_key).await?;
-                let upd_key = unblock({
+            let mut last_id: Option<ProfileId> = None;
+            loop {
+                let rows = if let Some(last_id) = last_id {
+                    sqlx::query(REKEY_FETCH_NEXT_QUERY).bind(last_id)
+                } else {
+                    sqlx::query(REKEY_FETCH_QUERY)
+                }
+                .bind(REKEY_PAGE_SIZE as i64)
+                .fetch_all(&mut txn)
+                .await?;
+                let page_len = rows.len();
+                if page_len == 0 {
+                    break;
+                }
+                let mut enc_keys = Vec::with_capacity(page_len);
+                for row in rows {
+                    enc_keys.push((row.try_get::<ProfileId, _>(0)?, row.try_get(1)?));
+                }
+                last_id.replace(enc_keys[page_len - 1].0);
+                let upd_keys = unblock({
+                    let prev_key = prev_key.clone();
                     let store_key = store_key.clone();
-                    move || encode_profile_key(&profile_key, &store_key)
+                    move || rewrap_profile_keys(enc_keys, &prev_key, &store_key)
                 })
                 .await?;
-                upd_keys.insert(pid, upd_key);
-            }
-            drop(rows);
-            for (pid, key) in upd_keys {
-                if sqlx::query("UPDATE profiles SET profile_key=?1 WHERE id=?2")
-                    .bind(key)
-                    .bind(pid)
-                    .execute(&mut txn)
-                    .await?
-                    .rows_affected()
-                    != 1
-                {
-                    return Err(err_msg!(Backend, "Error updating profile key"));
+                let upd_query = rekey_update_query(page_len);
+                let mut query = sqlx::query(&upd_query);
+                for (pid, key) in upd_keys {
+                    query = query.bind(pid).bind(key);
+                }
+                if query.execute(&mut txn).await?.rows_affected() != page_len as u64 {
+                    return Err(err_msg!(Backend, "Error updating profile keys"));
+                }
+                if page_len < REKEY_PAGE_SIZE {
+                    break;
                 }
             }
             if sqlx::query("UPDATE config SET value=?1 WHERE name='key'")
@@ -181,8 +443,15 @@ impl Backend for SqliteStore {
             {
                 return Err(err_msg!(Backend, "Error updating store key"));
             }
+            sqlx::query("INSERT OR REPLACE INTO config (name, value) VALUES ('key_check', ?1)")
+                .bind(encode_key_check(&store_key)?)
+                .execute(&mut txn)
+                .await?;
             txn.commit().await?;
-            self.key_cache = Arc::new(KeyCache::new(store_key));
+            self.key_cache = Arc::new(
+                self.key_cache
+                    .replace_keys(store_key, self.key_cache.tenant_keys.clone()),
+            );
             Ok(())
         })
     }
@@ -193,11 +462,21 @@ impl Backend for SqliteStore {
         kind: EntryKind,
         category: String,
         tag_filter: Option<TagFilter>,
+        history_filter: HistoryFilter,
         offset: Option<i64>,
         limit: Option<i64>,
-    ) -> BoxFuture<'_, Result<Scan<'static, Entry>, Error>> {
+        after_id: Option<i64>,
+        projection: Projection,
+    ) -> BoxFuture<'_, Result<Scan<Entry>, Error>> {
         Box::pin(async move {
             let session = self.session(profile, false)?;
+            if !history_filter.is_empty() && !session.history() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Filtering records by modification time requires entry history"
+                ));
+            }
+            let page_size = session.page_size();
             let mut active = session.owned_ref();
             let (profile_id, key) = acquire_key(&mut *active).await?;
             let scan = perform_scan(
@@ -207,15 +486,18 @@ impl Backend for SqliteStore {
                 kind,
                 category.clone(),
                 tag_filter,
+                history_filter,
                 offset,
                 limit,
+                after_id,
+                projection,
             );
             let stream = scan.then(move |enc_rows| {
                 let category = category.clone();
                 let key = key.clone();
-                unblock(move || decrypt_scan_batch(category, enc_rows?, &key))
+                unblock(move || decrypt_scan_page(category, enc_rows?, &key))
             });
-            Ok(Scan::new(stream, PAGE_SIZE))
+            Ok(Scan::new_positioned(stream, page_size))
         })
     }
 
@@ -225,7 +507,253 @@ impl Backend for SqliteStore {
             self.key_cache.clone(),
             profile.unwrap_or_else(|| self.default_profile.clone()),
             transaction,
-        ))
+            self.history,
+        )
+        .with_value_checksum(self.value_checksum)
+        .with_entry_metadata(self.capabilities.entry_metadata)
+        .with_entry_links(self.capabilities.entry_links)
+        .with_collections(self.capabilities.collections)
+        .with_unique_keys(self.capabilities.unique_keys)
+        .with_unique_constraints(self.unique_constraints.clone())
+        .with_id_strategy(self.id_strategy)
+        .with_page_size(self.page_size.load(Ordering::Relaxed))
+        .with_write_queue(self.write_queue.clone())
+        .with_durability(self.durability))
+    }
+
+    fn storage_report(&self) -> BoxFuture<'_, Result<StorageReport, Error>> {
+        Box::pin(async move {
+            let mut conn = self.conn_pool.acquire().await?;
+            let mut profiles = Vec::new();
+            for row in sqlx::query(PROFILE_KEYS_QUERY).fetch_all(&mut conn).await? {
+                let name: String = row.try_get(1)?;
+                let key = match self.key_cache.get_profile(&name).await {
+                    Some((_, key)) => Some(key),
+                    // the categories of profiles wrapped by an unavailable
+                    // tenant key are reported without names
+                    None => self
+                        .key_cache
+                        .load_key(name.clone(), row.try_get(2)?, row.try_get(3)?)
+                        .await
+                        .ok()
+                        .map(Arc::new),
+                };
+                profiles.push((row.try_get(0)?, name, key));
+            }
+            let mut usage = Vec::new();
+            let mut rows = sqlx::query(STORAGE_REPORT_QUERY).fetch(&mut conn);
+            while let Some(row) = rows.try_next().await? {
+                usage.push(EncCategoryUsage {
+                    profile_id: row.try_get(0)?,
+                    kind: row.try_get::<i64, _>(1)? as i16,
+                    category: row.try_get(2)?,
+                    count: row.try_get(3)?,
+                    bytes: row.try_get(4)?,
+                });
+            }
+            drop(rows);
+            unblock(move || decrypt_storage_report(profiles, usage)).await
+        })
+    }
+
+    fn verify_integrity(
+        &self,
+        profile: Option<String>,
+    ) -> BoxFuture<'_, Result<IntegrityReport, Error>> {
+        Box::pin(async move {
+            let mut session = self.session(profile, false)?;
+            let (profile_id, key) = acquire_key(&mut session).await?;
+            session.close(false).await?;
+            let mut conn = self.conn_pool.acquire().await?;
+            let mut rows = sqlx::query(if self.value_checksum {
+                INTEGRITY_QUERY
+            } else {
+                INTEGRITY_NO_CHECKSUM_QUERY
+            })
+            .bind(profile_id)
+            .fetch(&mut conn);
+            let mut report = IntegrityReport::default();
+            let mut batch = Vec::with_capacity(PAGE_SIZE);
+            loop {
+                let row = rows.try_next().await?;
+                let done = row.is_none();
+                if let Some(row) = row {
+                    batch.push(EncIntegrityEntry {
+                        kind: row.try_get::<i64, _>(0)? as i16,
+                        category: row.try_get(1)?,
+                        name: row.try_get(2)?,
+                        value: row.try_get(3)?,
+                        checksum: row.try_get(4)?,
+                    });
+                }
+                if batch.len() == PAGE_SIZE || (done && !batch.is_empty()) {
+                    let enc_rows = std::mem::replace(&mut batch, Vec::with_capacity(PAGE_SIZE));
+                    let key = key.clone();
+                    report.append(unblock(move || check_integrity_batch(enc_rows, &key)).await?);
+                }
+                if done {
+                    break;
+                }
+            }
+            Ok(report)
+        })
+    }
+
+    fn backup_to(&self, path: String) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let mut conn = self.conn_pool.acquire().await?;
+            // the copy is taken within a read transaction, so that writers
+            // on other connections are not blocked
+            sqlx::query("VACUUM INTO ?1")
+                .bind(path)
+                .execute(&mut conn)
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn fork_to(&self, uri: String) -> BoxFuture<'_, Result<Self, Error>> {
+        Box::pin(async move {
+            let opts = SqliteStoreOptions::new(uri.as_str())?;
+            if opts.in_memory {
+                return Err(err_msg!(
+                    Unsupported,
+                    "A store cannot be forked into an in-memory database"
+                ));
+            }
+            self.backup_to(opts.path.to_string()).await?;
+            let conn_pool = opts.pool(false).await?;
+            Ok(Self {
+                conn_pool,
+                default_profile: self.default_profile.clone(),
+                key_cache: Arc::new(self.key_cache.fork()),
+                path: opts.path.to_string(),
+                history: self.history,
+                nonce_strategy: self.nonce_strategy,
+                value_checksum: self.value_checksum,
+                capabilities: self.capabilities,
+                page_size: AtomicUsize::new(self.page_size.load(Ordering::Relaxed)),
+                write_queue: None,
+                durability: opts.durability,
+            })
+        })
+    }
+
+    fn maintenance(&self, mode: MaintenanceMode) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let mut conn = self.conn_pool.acquire().await?;
+            match mode {
+                MaintenanceMode::Online => {
+                    // only effective when the database uses incremental auto-vacuum
+                    sqlx::query("PRAGMA incremental_vacuum")
+                        .execute(&mut conn)
+                        .await?;
+                    sqlx::query("PRAGMA wal_checkpoint(PASSIVE)")
+                        .execute(&mut conn)
+                        .await?;
+                }
+                MaintenanceMode::Full => {
+                    sqlx::query("VACUUM").execute(&mut conn).await?;
+                    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+                        .execute(&mut conn)
+                        .await?;
+                }
+            }
+            sqlx::query("PRAGMA optimize").execute(&mut conn).await?;
+            Ok(())
+        })
+    }
+
+    fn access_mode(&self) -> BoxFuture<'_, Result<AccessMode, Error>> {
+        Box::pin(async move {
+            let mut conn = self.conn_pool.acquire().await?;
+            let mode: Option<String> = sqlx::query_scalar(ACCESS_MODE_QUERY)
+                .fetch_optional(&mut conn)
+                .await?;
+            parse_access_mode(mode)
+        })
+    }
+
+    fn set_access_mode(&self, mode: AccessMode) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let mut conn = self.conn_pool.acquire().await?;
+            sqlx::query("INSERT OR REPLACE INTO config (name, value) VALUES ('access_mode', ?1)")
+                .bind(mode.as_str())
+                .execute(&mut conn)
+                .await?;
+            Ok(())
+        })
+    }
+
+    #[cfg(feature = "raw_query")]
+    fn raw_query(
+        &self,
+        sql: String,
+        params: Vec<RawValue>,
+    ) -> BoxFuture<'_, Result<Vec<RawRow>, Error>> {
+        Box::pin(async move {
+            let sql = replace_arg_placeholders::<Self>(&sql, 1);
+            let mut conn = self.conn_pool.acquire().await?;
+            sqlx::query("PRAGMA query_only = ON")
+                .execute(&mut conn)
+                .await?;
+            let mut query = sqlx::query(sql.as_str());
+            for param in params {
+                query = match param {
+                    RawValue::Null => query.bind(Option::<i64>::None),
+                    RawValue::Integer(value) => query.bind(value),
+                    RawValue::Real(value) => query.bind(value),
+                    RawValue::Text(value) => query.bind(value),
+                    RawValue::Blob(value) => query.bind(value),
+                };
+            }
+            let rows = query.fetch_all(&mut conn).await;
+            // the connection is returned to the pool, so writes must be re-enabled
+            // regardless of the query result
+            sqlx::query("PRAGMA query_only = OFF")
+                .execute(&mut conn)
+                .await?;
+            rows?.iter().map(decode_raw_row).collect()
+        })
+    }
+
+    #[cfg(feature = "raw_query")]
+    fn profile_key(
+        &self,
+        profile: Option<String>,
+    ) -> BoxFuture<'_, Result<(ProfileId, Arc<ProfileKey>), Error>> {
+        Box::pin(async move {
+            let mut session = self.session(profile, false)?;
+            let ret = acquire_key(&mut session).await?;
+            session.close(false).await?;
+            Ok(ret)
+        })
+    }
+
+    fn set_tenant_keys(&mut self, provider: Option<Arc<dyn TenantKeyProvider>>) {
+        // cached profile keys are discarded and reloaded using the new provider
+        self.key_cache = Arc::new(
+            self.key_cache
+                .replace_keys(self.key_cache.store_key.clone(), provider),
+        );
+    }
+
+    fn set_unique_constraints(&mut self, constraints: Arc<Vec<UniqueConstraint>>) {
+        self.unique_constraints = constraints;
+    }
+
+    fn key_cache_stats(&self) -> KeyCacheStats {
+        self.key_cache.stats()
+    }
+
+    fn reconfigure(&self, tuning: &StoreTuning) -> Result<(), Error> {
+        if let Some(capacity) = tuning.key_cache_capacity() {
+            self.key_cache.resize(capacity)?;
+        }
+        if let Some(page_size) = tuning.scan_page_size {
+            self.page_size.store(page_size, Ordering::Relaxed);
+        }
+        Ok(())
     }
 
     fn close(&self) -> BoxFuture<'_, Result<(), Error>> {
@@ -246,20 +774,22 @@ impl QueryBackend for DbSession<Sqlite> {
         let category = ProfileKey::prepare_input(category.as_bytes());
 
         Box::pin(async move {
+            let timer = self.crypto_timer();
             let (profile_id, key) = acquire_key(&mut *self).await?;
             let mut params = QueryParams::new();
             params.push(profile_id);
-            params.push(kind as i16);
-            let (enc_category, tag_filter) = unblock({
-                let params_len = params.len() + 1; // plus category
-                move || {
-                    Result::<_, Error>::Ok((
-                        key.encrypt_entry_category(category)?,
-                        encode_tag_filter::<SqliteStore>(tag_filter, &key, params_len)?,
-                    ))
-                }
-            })
-            .await?;
+            params.push(kind.code());
+            let (enc_category, tag_filter) = timer
+                .unblock({
+                    let params_len = params.len() + 1; // plus category
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            encode_tag_filter::<SqliteStore>(tag_filter, &key, params_len)?,
+                        ))
+                    }
+                })
+                .await?;
             params.push(enc_category);
             let query =
                 extend_query::<SqliteStore>(COUNT_QUERY, &mut params, tag_filter, None, None)?;
@@ -282,181 +812,1582 @@ impl QueryBackend for DbSession<Sqlite> {
         let name = name.to_string();
 
         Box::pin(async move {
+            let timer = self.crypto_timer();
             let (profile_id, key) = acquire_key(&mut *self).await?;
-            let (enc_category, enc_name) = unblock({
-                let key = key.clone();
-                let category = ProfileKey::prepare_input(category.as_bytes());
-                let name = ProfileKey::prepare_input(name.as_bytes());
-                move || {
-                    Result::<_, Error>::Ok((
-                        key.encrypt_entry_category(category)?,
-                        key.encrypt_entry_name(name)?,
-                    ))
-                }
-            })
-            .await?;
+            let (enc_category, enc_name) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                        ))
+                    }
+                })
+                .await?;
             let mut active = acquire_session(&mut *self).await?;
             if let Some(row) = sqlx::query(FETCH_QUERY)
                 .bind(profile_id)
-                .bind(kind as i16)
+                .bind(kind.code())
                 .bind(enc_category)
                 .bind(enc_name)
                 .fetch_optional(active.connection_mut())
                 .await?
             {
+                let seq = row.try_get(0)?;
                 let value = row.try_get(1)?;
                 let tags = row.try_get(2)?;
-                let (category, name, value, tags) = unblock(move || {
-                    let value = key.decrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
-                    let enc_tags = decode_tags(tags)
-                        .map_err(|_| err_msg!(Unexpected, "Error decoding entry tags"))?;
-                    let tags = key.decrypt_entry_tags(enc_tags)?;
-                    Result::<_, Error>::Ok((category, name, value, tags))
-                })
-                .await?;
-                Ok(Some(Entry::new(category, name, value, tags)))
+                let mut expiry = row.try_get::<Option<Expiry>, _>(4)?;
+                if let Some(sliding_ms) = row.try_get::<Option<i64>, _>(3)? {
+                    let extended = expiry_timestamp(EntryExpiry::Sliding(sliding_ms))?;
+                    sqlx::query(EXTEND_EXPIRY_QUERY)
+                        .bind(seq)
+                        .bind(extended)
+                        .execute(active.connection_mut())
+                        .await?;
+                    expiry.replace(extended);
+                }
+                let expiry = expiry
+                    .map(|e| from_history_timestamp(e.naive_utc()))
+                    .transpose()?;
+                let (category, name, value, tags) = timer
+                    .unblock(move || {
+                        let value =
+                            key.decrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+                        let enc_tags = decode_tags(tags)
+                            .map_err(|_| err_msg!(Unexpected, "Error decoding entry tags"))?;
+                        let tags = key.decrypt_entry_tags(enc_tags)?;
+                        Result::<_, Error>::Ok((category, name, value, tags))
+                    })
+                    .await?;
+                Ok(Some(
+                    Entry::new(split_namespace(&category).1, name, value, tags)
+                        .with_seq(EntrySeq::new(seq))
+                        .with_expiry(expiry),
+                ))
             } else {
                 Ok(None)
             }
         })
     }
 
-    fn fetch_all<'q>(
+    fn fetch_into<'q>(
         &'q mut self,
         kind: EntryKind,
         category: &'q str,
-        tag_filter: Option<TagFilter>,
-        limit: Option<i64>,
-        _for_update: bool,
-    ) -> BoxFuture<'q, Result<Vec<Entry>, Error>> {
-        let category = category.to_string();
+        name: &'q str,
+        output: &'q mut SecretBytes,
+    ) -> BoxFuture<'q, Result<bool, Error>> {
         Box::pin(async move {
-            let mut active = self.borrow_mut();
-            let (profile_id, key) = acquire_key(&mut *active).await?;
-            let scan = perform_scan(
-                active,
-                profile_id,
-                key.clone(),
-                kind,
-                category.clone(),
-                tag_filter,
-                None,
-                limit,
-            );
-            pin!(scan);
-            let mut enc_rows = vec![];
-            loop {
-                if let Some(rows) = scan.try_next().await? {
-                    enc_rows.extend(rows)
-                } else {
-                    break;
+            let timer = self.crypto_timer();
+            output.zeroize();
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            if let Some(row) = sqlx::query(FETCH_VALUE_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .fetch_optional(active.connection_mut())
+                .await?
+            {
+                // the ciphertext is copied into the output buffer and
+                // decrypted in place
+                output.extend_from_slice(row.try_get::<&[u8], _>(0)?);
+                drop(row);
+                let mut buffer = std::mem::take(output);
+                let (category, name) = (category.to_string(), name.to_string());
+                let (buffer, result) = timer
+                    .unblock(move || {
+                        let result = key.decrypt_entry_value_in_place(
+                            category.as_bytes(),
+                            name.as_bytes(),
+                            &mut buffer,
+                        );
+                        (buffer, result)
+                    })
+                    .await;
+                *output = buffer;
+                if let Err(err) = result {
+                    output.zeroize();
+                    return Err(err);
                 }
+                Ok(true)
+            } else {
+                Ok(false)
             }
-            unblock(move || decrypt_scan_batch(category, enc_rows, &key)).await
         })
     }
 
-    fn remove_all<'q>(
+    fn exists<'q>(
         &'q mut self,
         kind: EntryKind,
         category: &'q str,
-        tag_filter: Option<TagFilter>,
-    ) -> BoxFuture<'q, Result<i64, Error>> {
-        let category = ProfileKey::prepare_input(category.as_bytes());
-
+        name: &'q str,
+    ) -> BoxFuture<'q, Result<bool, Error>> {
         Box::pin(async move {
             let (profile_id, key) = acquire_key(&mut *self).await?;
-            let mut params = QueryParams::new();
-            params.push(profile_id);
-            params.push(kind as i16);
-            let (enc_category, tag_filter) = unblock({
-                let params_len = params.len() + 1; // plus category
-                move || {
-                    Result::<_, Error>::Ok((
+            let (enc_category, enc_name) = unblock({
+                let category = ProfileKey::prepare_input(category.as_bytes());
+                let name = ProfileKey::prepare_input(name.as_bytes());
+                move || {
+                    Result::<_, Error>::Ok((
+                        key.encrypt_entry_category(category)?,
+                        key.encrypt_entry_name(name)?,
+                    ))
+                }
+            })
+            .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let found = sqlx::query(EXISTS_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .fetch_optional(active.connection_mut())
+                .await?;
+            Ok(found.is_some())
+        })
+    }
+
+    fn fetch_by_id(
+        &mut self,
+        kind: EntryKind,
+        id: EntryId,
+    ) -> BoxFuture<'_, Result<Option<Entry>, Error>> {
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let mut active = acquire_session(&mut *self).await?;
+            if let Some(row) = sqlx::query(FETCH_BY_ID_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(id.value())
+                .fetch_optional(active.connection_mut())
+                .await?
+            {
+                let seq = row.try_get(0)?;
+                let enc_category = row.try_get(1)?;
+                let enc_name = row.try_get(2)?;
+                let value = row.try_get(3)?;
+                let tags = row.try_get(4)?;
+                let mut expiry = row.try_get::<Option<Expiry>, _>(6)?;
+                if let Some(sliding_ms) = row.try_get::<Option<i64>, _>(5)? {
+                    let extended = expiry_timestamp(EntryExpiry::Sliding(sliding_ms))?;
+                    sqlx::query(EXTEND_EXPIRY_QUERY)
+                        .bind(seq)
+                        .bind(extended)
+                        .execute(active.connection_mut())
+                        .await?;
+                    expiry.replace(extended);
+                }
+                let expiry = expiry
+                    .map(|e| from_history_timestamp(e.naive_utc()))
+                    .transpose()?;
+                let (category, name, value, tags) = timer
+                    .unblock(move || {
+                        let category = key.decrypt_entry_category(enc_category)?;
+                        let name = key.decrypt_entry_name(enc_name)?;
+                        let value =
+                            key.decrypt_entry_value(category.as_bytes(), name.as_bytes(), value)?;
+                        let enc_tags = decode_tags(tags)
+                            .map_err(|_| err_msg!(Unexpected, "Error decoding entry tags"))?;
+                        let tags = key.decrypt_entry_tags(enc_tags)?;
+                        Result::<_, Error>::Ok((category, name, value, tags))
+                    })
+                    .await?;
+                Ok(Some(
+                    Entry::new(category, name, value, tags)
+                        .with_seq(EntrySeq::new(seq))
+                        .with_expiry(expiry),
+                ))
+            } else {
+                Ok(None)
+            }
+        })
+    }
+
+    fn fetch_at<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        timestamp: SystemTime,
+    ) -> BoxFuture<'q, Result<Option<Entry>, Error>> {
+        let unique_key = self.unique_key(kind, category, tags);
+        let category = category.to_string();
+        let name = name.to_string();
+
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.history() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Entry history is not enabled for this store"
+                ));
+            }
+            let timestamp = to_history_timestamp(timestamp)?;
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            if let Some(row) = sqlx::query(HISTORY_FETCH_AT_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .bind(timestamp)
+                .fetch_optional(active.connection_mut())
+                .await?
+            {
+                let enc_entry = EncHistoryEntry {
+                    value: row.try_get(0)?,
+                    tags: row.try_get(1)?,
+                    valid_from: row.try_get(2)?,
+                    valid_to: row.try_get(3)?,
+                };
+                let mut found = timer
+                    .unblock(move || decrypt_history_batch(category, name, vec![enc_entry], &key))
+                    .await?;
+                Ok(found.pop().map(|version| version.entry))
+            } else {
+                Ok(None)
+            }
+        })
+    }
+
+    fn fetch_history<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        limit: Option<i64>,
+    ) -> BoxFuture<'q, Result<Vec<EntryVersion>, Error>> {
+        let category = category.to_string();
+        let name = name.to_string();
+
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.history() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Entry history is not enabled for this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut params = QueryParams::new();
+            params.push(profile_id);
+            params.push(kind.code());
+            params.push(enc_category);
+            params.push(enc_name);
+            let query =
+                extend_query::<SqliteStore>(HISTORY_LIST_QUERY, &mut params, None, None, limit)?;
+            let mut active = acquire_session(&mut *self).await?;
+            let rows = sqlx::query_with(query.as_str(), params)
+                .fetch_all(active.connection_mut())
+                .await?;
+            let mut enc_rows = Vec::with_capacity(rows.len());
+            for row in rows {
+                enc_rows.push(EncHistoryEntry {
+                    value: row.try_get(0)?,
+                    tags: row.try_get(1)?,
+                    valid_from: row.try_get(2)?,
+                    valid_to: row.try_get(3)?,
+                });
+            }
+            timer
+                .unblock(move || decrypt_history_batch(category, name, enc_rows, &key))
+                .await
+        })
+    }
+
+    fn fetch_all<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        tag_filter: Option<TagFilter>,
+        limit: Option<i64>,
+        _for_update: bool,
+        projection: Projection,
+    ) -> BoxFuture<'q, Result<Vec<Entry>, Error>> {
+        let category = category.to_string();
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            let mut active = self.borrow_mut();
+            let (profile_id, key) = acquire_key(&mut *active).await?;
+            let scan = perform_scan(
+                active,
+                profile_id,
+                key.clone(),
+                kind,
+                category.clone(),
+                tag_filter,
+                HistoryFilter::default(),
+                None,
+                limit,
+                None,
+                projection,
+            );
+            pin!(scan);
+            let mut enc_rows = vec![];
+            loop {
+                if let Some(rows) = scan.try_next().await? {
+                    enc_rows.extend(rows)
+                } else {
+                    break;
+                }
+            }
+            timer
+                .unblock(move || decrypt_scan_batch(category, enc_rows, &key))
+                .await
+        })
+    }
+
+    fn search<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        terms: Vec<String>,
+        limit: Option<i64>,
+    ) -> BoxFuture<'q, Result<Vec<Entry>, Error>> {
+        let category = category.to_string();
+        let terms = prepare_search_terms(terms);
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if terms.is_empty() {
+                return Ok(vec![]);
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, tokens) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = category.clone();
+                    move || {
+                        let tokens = terms
+                            .iter()
+                            .map(|term| {
+                                key.encrypt_search_token(category.as_bytes(), term.as_bytes())
+                            })
+                            .collect::<Result<Vec<_>, Error>>()?;
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(ProfileKey::prepare_input(
+                                category.as_bytes(),
+                            ))?,
+                            tokens,
+                        ))
+                    }
+                })
+                .await?;
+            let mut params = QueryParams::new();
+            params.push(profile_id);
+            params.push(kind.code());
+            params.push(enc_category);
+            let mut query = scan_query(Projection::Full);
+            query.push_str(&search_clause::<SqliteStore>(&mut params, tokens));
+            query.push_str(" ORDER BY i.rowid");
+            let query = SqliteStore::limit_query(query, &mut params, None, limit);
+            let mut active = acquire_session(&mut *self).await?;
+            let mut enc_rows = vec![];
+            for row in sqlx::query_with(query.as_str(), params)
+                .fetch_all(active.connection_mut())
+                .await?
+            {
+                enc_rows.push(EncScanEntry {
+                    seq: row.try_get(0)?,
+                    name: row.try_get(1)?,
+                    value: row.try_get(2)?,
+                    tags: row.try_get(3)?,
+                    expiry: row.try_get::<Option<Expiry>, _>(4)?.map(|e| e.naive_utc()),
+                });
+            }
+            timer
+                .unblock(move || decrypt_scan_batch(category, enc_rows, &key))
+                .await
+        })
+    }
+
+    fn remove_all<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        tag_filter: Option<TagFilter>,
+    ) -> BoxFuture<'q, Result<i64, Error>> {
+        let category = ProfileKey::prepare_input(category.as_bytes());
+
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let mut params = QueryParams::new();
+            params.push(profile_id);
+            params.push(kind.code());
+            let (enc_category, tag_filter) = timer
+                .unblock({
+                    let params_len = params.len() + 1; // plus category
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            encode_tag_filter::<SqliteStore>(tag_filter, &key, params_len)?,
+                        ))
+                    }
+                })
+                .await?;
+            params.push(enc_category.clone());
+            let history_close = if self.history() {
+                let mut hist_params = QueryParams::new();
+                hist_params.push(profile_id);
+                hist_params.push(kind.code());
+                hist_params.push(enc_category);
+                let select = extend_query::<SqliteStore>(
+                    HISTORY_CLOSE_ALL_QUERY,
+                    &mut hist_params,
+                    tag_filter.clone(),
+                    None,
+                    None,
+                )?;
+                let ts_idx = (hist_params.len() + 1) as i64;
+                hist_params.push(history_timestamp());
+                Some((
+                    format!(
+                        "UPDATE items_history SET valid_to = {}
+                        WHERE valid_to IS NULL
+                        AND (profile_id, kind, category, name) IN ({})",
+                        SqliteStore::placeholder(ts_idx),
+                        select
+                    ),
+                    hist_params,
+                ))
+            } else {
+                None
+            };
+            let query =
+                extend_query::<SqliteStore>(DELETE_ALL_QUERY, &mut params, tag_filter, None, None)?;
+
+            let mut active = acquire_session(&mut *self).await?;
+            let mut txn = active.as_transaction().await?;
+            if let Some((hist_query, hist_params)) = history_close {
+                sqlx::query_with(hist_query.as_str(), hist_params)
+                    .execute(txn.connection_mut())
+                    .await?;
+            }
+            let removed = sqlx::query_with(query.as_str(), params)
+                .execute(txn.connection_mut())
+                .await?
+                .rows_affected();
+            txn.commit().await?;
+            Ok(removed as i64)
+        })
+    }
+
+    fn insert_all<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        entries: Vec<Entry>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        Box::pin(async move {
+            let entries = entries
+                .into_iter()
+                .map(|entry| {
+                    let unique_key =
+                        self.unique_key(kind, &entry.category, Some(entry.tags.as_slice()))?;
+                    Ok((entry, unique_key))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            let timer = self.crypto_timer();
+            let (_, key) = acquire_key(&mut *self).await?;
+            let checksum = self.value_checksum();
+            let enc_entries = timer
+                .unblock(move || encrypt_insert_batch(entries, &key, checksum))
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let mut txn = active.as_transaction().await?;
+            let history = if txn.history() {
+                Some(history_timestamp())
+            } else {
+                None
+            };
+            // statements are cheap within a transaction, as SQLite only
+            // synchronizes the database file when the transaction commits
+            for entry in enc_entries {
+                perform_insert(
+                    &mut txn,
+                    kind,
+                    &entry.category,
+                    &entry.name,
+                    &entry.value,
+                    entry.checksum.as_deref(),
+                    entry.unique_key.as_deref(),
+                    Some(entry.tags),
+                    None,
+                    history,
+                )
+                .await?;
+            }
+            txn.commit().await?;
+            Ok(())
+        })
+    }
+
+    fn touch_all<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        tag_filter: Option<TagFilter>,
+        offset: Option<i64>,
+        limit: Option<i64>,
+        expiry: EntryExpiry,
+    ) -> BoxFuture<'q, Result<i64, Error>> {
+        let category = ProfileKey::prepare_input(category.as_bytes());
+
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let mut params = QueryParams::new();
+            params.push(profile_id);
+            params.push(kind.code());
+            let (enc_category, tag_filter) = timer
+                .unblock({
+                    let params_len = params.len() + 1; // plus category
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            encode_tag_filter::<SqliteStore>(tag_filter, &key, params_len)?,
+                        ))
+                    }
+                })
+                .await?;
+            params.push(enc_category);
+            let mut select = extend_query::<SqliteStore>(
+                TOUCH_SELECT_QUERY,
+                &mut params,
+                tag_filter,
+                None,
+                None,
+            )?;
+            select.push_str(" ORDER BY i.rowid");
+            let select = SqliteStore::limit_query(select, &mut params, offset, limit);
+            let expiry_idx = (params.len() + 1) as i64;
+            params.push(expiry_timestamp(expiry)?);
+            params.push(expiry.sliding_ms());
+            let query = format!(
+                "UPDATE items SET expiry = {}, expiry_sliding = {} WHERE id IN ({})",
+                SqliteStore::placeholder(expiry_idx),
+                SqliteStore::placeholder(expiry_idx + 1),
+                select
+            );
+
+            let mut active = acquire_session(&mut *self).await?;
+            let touched = sqlx::query_with(query.as_str(), params)
+                .execute(active.connection_mut())
+                .await?
+                .rows_affected();
+            Ok(touched as i64)
+        })
+    }
+
+    fn update<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        operation: EntryOperation,
+        category: &'q str,
+        name: &'q str,
+        value: Option<&'q [u8]>,
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        let unique_key = match operation {
+            EntryOperation::Remove => Ok(None),
+            _ => self.unique_key(kind, category, tags),
+        };
+        let category = ProfileKey::prepare_input(category.as_bytes());
+        let name = ProfileKey::prepare_input(name.as_bytes());
+
+        let timer = self.crypto_timer();
+
+        match operation {
+            op @ EntryOperation::Insert | op @ EntryOperation::Replace => {
+                let value = ProfileKey::prepare_input(value.unwrap());
+                let tags = tags.map(prepare_tags);
+                Box::pin(async move {
+                    let unique_key = unique_key?;
+                    let (profile_id, key) = acquire_key(&mut *self).await?;
+                    let checksum = self.value_checksum();
+                    let (enc_category, enc_name, enc_value, checksum, enc_tags) = timer
+                        .unblock({
+                            let key = key.clone();
+                            move || {
+                                let checksum = if checksum {
+                                    Some(key.value_checksum(
+                                        category.as_ref(),
+                                        name.as_ref(),
+                                        value.as_ref(),
+                                    )?)
+                                } else {
+                                    None
+                                };
+                                let enc_value = key.encrypt_entry_value(
+                                    category.as_ref(),
+                                    name.as_ref(),
+                                    value,
+                                )?;
+                                Result::<_, Error>::Ok((
+                                    key.encrypt_entry_category(category)?,
+                                    key.encrypt_entry_name(name)?,
+                                    enc_value,
+                                    checksum,
+                                    tags.transpose()?
+                                        .map(|t| key.encrypt_entry_tags(t))
+                                        .transpose()?,
+                                ))
+                            }
+                        })
+                        .await?;
+                    if let Some(queue) = self.write_queue().cloned() {
+                        // the connection is not needed while waiting for the batch
+                        self.release_connection();
+                        return submit_write(
+                            &queue,
+                            CoalescedWrite {
+                                profile_id,
+                                key,
+                                kind,
+                                operation: op,
+                                category: enc_category,
+                                name: enc_name,
+                                value: Some(enc_value),
+                                checksum,
+                                tags: enc_tags,
+                                expiry,
+                                unique_key,
+                            },
+                        )
+                        .await;
+                    }
+                    let mut active = acquire_session(&mut *self).await?;
+                    let mut txn = active.as_transaction().await?;
+                    // a replaced version ends exactly when the new one begins
+                    let history = if txn.history() {
+                        Some(history_timestamp())
+                    } else {
+                        None
+                    };
+                    if op == EntryOperation::Replace {
+                        check_unique_key(
+                            &mut txn,
+                            kind,
+                            &enc_category,
+                            &enc_name,
+                            unique_key.as_deref(),
+                        )
+                        .await?;
+                        perform_remove(&mut txn, kind, &enc_category, &enc_name, false, history)
+                            .await?;
+                    }
+                    perform_insert(
+                        &mut txn,
+                        kind,
+                        &enc_category,
+                        &enc_name,
+                        &enc_value,
+                        checksum.as_deref(),
+                        unique_key.as_deref(),
+                        enc_tags,
+                        expiry,
+                        history,
+                    )
+                    .await?;
+                    txn.commit().await?;
+                    Ok(())
+                })
+            }
+
+            EntryOperation::Remove => Box::pin(async move {
+                let (profile_id, key) = acquire_key(&mut *self).await?;
+                let (enc_category, enc_name) = timer
+                    .unblock({
+                        let key = key.clone();
+                        move || {
+                            Result::<_, Error>::Ok((
+                                key.encrypt_entry_category(category)?,
+                                key.encrypt_entry_name(name)?,
+                            ))
+                        }
+                    })
+                    .await?;
+                if let Some(queue) = self.write_queue().cloned() {
+                    // the connection is not needed while waiting for the batch
+                    self.release_connection();
+                    return submit_write(
+                        &queue,
+                        CoalescedWrite {
+                            profile_id,
+                            key,
+                            kind,
+                            operation: EntryOperation::Remove,
+                            category: enc_category,
+                            name: enc_name,
+                            value: None,
+                            checksum: None,
+                            tags: None,
+                            expiry: None,
+                            unique_key: None,
+                        },
+                    )
+                    .await;
+                }
+                let mut active = acquire_session(&mut *self).await?;
+                if active.history() {
+                    let mut txn = active.as_transaction().await?;
+                    perform_remove(
+                        &mut txn,
+                        kind,
+                        &enc_category,
+                        &enc_name,
+                        false,
+                        Some(history_timestamp()),
+                    )
+                    .await?;
+                    txn.commit().await
+                } else {
+                    perform_remove(&mut active, kind, &enc_category, &enc_name, false, None).await
+                }
+            }),
+        }
+    }
+
+    fn insert_returning_id<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<EntryId, Error>> {
+        let unique_key = self.unique_key(kind, category, tags);
+        let category = ProfileKey::prepare_input(category.as_bytes());
+        let name = ProfileKey::prepare_input(name.as_bytes());
+        let value = ProfileKey::prepare_input(value);
+        let tags = tags.map(prepare_tags);
+        let timer = self.crypto_timer();
+
+        // the write is not coalesced, as the identity of the inserted row
+        // must be reported to the caller
+        Box::pin(async move {
+            let unique_key = unique_key?;
+            let (_, key) = acquire_key(&mut *self).await?;
+            let checksum = self.value_checksum();
+            let (enc_category, enc_name, enc_value, checksum, enc_tags) = timer
+                .unblock(move || {
+                    let checksum = if checksum {
+                        Some(key.value_checksum(
+                            category.as_ref(),
+                            name.as_ref(),
+                            value.as_ref(),
+                        )?)
+                    } else {
+                        None
+                    };
+                    let enc_value =
+                        key.encrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+                    Result::<_, Error>::Ok((
                         key.encrypt_entry_category(category)?,
-                        encode_tag_filter::<SqliteStore>(tag_filter, &key, params_len)?,
+                        key.encrypt_entry_name(name)?,
+                        enc_value,
+                        checksum,
+                        tags.transpose()?
+                            .map(|t| key.encrypt_entry_tags(t))
+                            .transpose()?,
                     ))
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let mut txn = active.as_transaction().await?;
+            let history = if txn.history() {
+                Some(history_timestamp())
+            } else {
+                None
+            };
+            let row_id = perform_insert(
+                &mut txn,
+                kind,
+                &enc_category,
+                &enc_name,
+                &enc_value,
+                checksum.as_deref(),
+                unique_key.as_deref(),
+                enc_tags,
+                expiry,
+                history,
+            )
+            .await?;
+            txn.commit().await?;
+            Ok(EntryId::new(row_id))
+        })
+    }
+
+    fn insert_or_fetch<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<Option<Entry>, Error>> {
+        let category = category.to_string();
+        let name = name.to_string();
+        let value = ProfileKey::prepare_input(value);
+        let tags = tags.map(prepare_tags);
+        let timer = self.crypto_timer();
+
+        Box::pin(async move {
+            let unique_key = unique_key?;
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let checksum = self.value_checksum();
+            let (enc_category, enc_name, enc_value, checksum, enc_tags) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        let checksum = if checksum {
+                            Some(key.value_checksum(
+                                category.as_ref(),
+                                name.as_ref(),
+                                value.as_ref(),
+                            )?)
+                        } else {
+                            None
+                        };
+                        let enc_value =
+                            key.encrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                            enc_value,
+                            checksum,
+                            tags.transpose()?
+                                .map(|t| key.encrypt_entry_tags(t))
+                                .transpose()?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let mut txn = active.as_transaction().await?;
+            let history = if txn.history() {
+                Some(history_timestamp())
+            } else {
+                None
+            };
+            match perform_insert(
+                &mut txn,
+                kind,
+                &enc_category,
+                &enc_name,
+                &enc_value,
+                checksum.as_deref(),
+                unique_key.as_deref(),
+                enc_tags,
+                expiry,
+                history,
+            )
+            .await
+            {
+                Ok(_) => {
+                    txn.commit().await?;
+                    return Ok(None);
+                }
+                Err(err) if err.kind() == ErrorKind::Duplicate => (),
+                Err(err) => return Err(err),
+            }
+            // the existing record is read within the same transaction
+            let row = sqlx::query(FETCH_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(&enc_category)
+                .bind(&enc_name)
+                .fetch_optional(txn.connection_mut())
+                .await?
+                .ok_or_else(|| err_msg!(Duplicate, "Duplicate row"))?;
+            let seq = row.try_get(0)?;
+            let value = row.try_get(1)?;
+            let tags = row.try_get(2)?;
+            let mut expiry = row.try_get::<Option<Expiry>, _>(4)?;
+            if let Some(sliding_ms) = row.try_get::<Option<i64>, _>(3)? {
+                let extended = expiry_timestamp(EntryExpiry::Sliding(sliding_ms))?;
+                sqlx::query(EXTEND_EXPIRY_QUERY)
+                    .bind(seq)
+                    .bind(extended)
+                    .execute(txn.connection_mut())
+                    .await?;
+                expiry.replace(extended);
+            }
+            txn.commit().await?;
+            let expiry = expiry
+                .map(|e| from_history_timestamp(e.naive_utc()))
+                .transpose()?;
+            let (category, name, value, tags) = timer
+                .unblock(move || {
+                    let value = key.decrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+                    let enc_tags = decode_tags(tags)
+                        .map_err(|_| err_msg!(Unexpected, "Error decoding entry tags"))?;
+                    let tags = key.decrypt_entry_tags(enc_tags)?;
+                    Result::<_, Error>::Ok((category, name, value, tags))
+                })
+                .await?;
+            Ok(Some(
+                Entry::new(split_namespace(&category).1, name, value, tags)
+                    .with_seq(EntrySeq::new(seq))
+                    .with_expiry(expiry),
+            ))
+        })
+    }
+
+    fn replace_checked<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+        expected: Option<EntryHash>,
+    ) -> BoxFuture<'q, Result<EntryHash, Error>> {
+        let unique_key = self.unique_key(kind, category, tags);
+        let category = ProfileKey::prepare_input(category.as_bytes());
+        let name = ProfileKey::prepare_input(name.as_bytes());
+        let value = ProfileKey::prepare_input(value);
+        let tags = tags.map(prepare_tags);
+        Box::pin(async move {
+            let unique_key = unique_key?;
+            let timer = self.crypto_timer();
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let checksum = self.value_checksum();
+            let (enc_category, enc_name, enc_value, checksum, enc_tags) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = category.clone();
+                    let name = name.clone();
+                    move || {
+                        let checksum = if checksum {
+                            Some(key.value_checksum(
+                                category.as_ref(),
+                                name.as_ref(),
+                                value.as_ref(),
+                            )?)
+                        } else {
+                            None
+                        };
+                        let enc_value =
+                            key.encrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                            enc_value,
+                            checksum,
+                            tags.transpose()?
+                                .map(|t| key.encrypt_entry_tags(t))
+                                .transpose()?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let mut txn = active.as_transaction().await?;
+            let row = sqlx::query(FETCH_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(&enc_category)
+                .bind(&enc_name)
+                .fetch_optional(txn.connection_mut())
+                .await?
+                .ok_or_else(|| err_msg!(NotFound, "Entry not found"))?;
+            let prev_value = row.try_get(1)?;
+            let prev_tags = row.try_get(2)?;
+            let prev_hash = timer
+                .unblock(move || {
+                    let value =
+                        key.decrypt_entry_value(category.as_ref(), name.as_ref(), prev_value)?;
+                    let enc_tags = decode_tags(prev_tags)
+                        .map_err(|_| err_msg!(Unexpected, "Error decoding entry tags"))?;
+                    let tags = key.decrypt_entry_tags(enc_tags)?;
+                    Result::<_, Error>::Ok(EntryHash::compute(&value, &tags))
+                })
+                .await?;
+            if expected.map(|hash| hash != prev_hash).unwrap_or(false) {
+                return Err(err_msg!(
+                    Conflict,
+                    "Entry was modified since it was fetched"
+                ));
+            }
+            // a replaced version ends exactly when the new one begins
+            let history = if txn.history() {
+                Some(history_timestamp())
+            } else {
+                None
+            };
+            check_unique_key(
+                &mut txn,
+                kind,
+                &enc_category,
+                &enc_name,
+                unique_key.as_deref(),
+            )
+            .await?;
+            perform_remove(&mut txn, kind, &enc_category, &enc_name, false, history).await?;
+            perform_insert(
+                &mut txn,
+                kind,
+                &enc_category,
+                &enc_name,
+                &enc_value,
+                checksum.as_deref(),
+                unique_key.as_deref(),
+                enc_tags,
+                expiry,
+                history,
+            )
+            .await?;
+            txn.commit().await?;
+            Ok(prev_hash)
+        })
+    }
+
+    fn update_search_terms<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        terms: Vec<String>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        let terms = prepare_search_terms(terms);
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name, tokens) = timer
+                .unblock({
+                    let category = category.to_string();
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        let tokens = terms
+                            .iter()
+                            .map(|term| {
+                                key.encrypt_search_token(category.as_bytes(), term.as_bytes())
+                            })
+                            .collect::<Result<Vec<_>, Error>>()?;
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(ProfileKey::prepare_input(
+                                category.as_bytes(),
+                            ))?,
+                            key.encrypt_entry_name(name)?,
+                            tokens,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let mut txn = active.as_transaction().await?;
+            let item_id: ProfileId = sqlx::query_scalar(SEARCH_ITEM_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .fetch_optional(txn.connection_mut())
+                .await?
+                .ok_or_else(|| err_msg!(NotFound, "Entry not found"))?;
+            sqlx::query(SEARCH_DELETE_QUERY)
+                .bind(item_id)
+                .execute(txn.connection_mut())
+                .await?;
+            for token in tokens {
+                sqlx::query(SEARCH_INSERT_QUERY)
+                    .bind(item_id)
+                    .bind(token)
+                    .execute(txn.connection_mut())
+                    .await?;
+            }
+            txn.commit().await?;
+            Ok(())
+        })
+    }
+
+    fn fetch_metadata<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+    ) -> BoxFuture<'q, Result<Option<SecretBytes>, Error>> {
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.entry_metadata() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Entry metadata is not supported by this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let enc_metadata: Option<Vec<u8>> = sqlx::query_scalar(METADATA_FETCH_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .fetch_optional(active.connection_mut())
+                .await?
+                .ok_or_else(|| err_msg!(NotFound, "Entry not found"))?;
+            if let Some(enc_metadata) = enc_metadata {
+                let (category, name) = (category.to_string(), name.to_string());
+                Ok(Some(
+                    timer
+                        .unblock(move || {
+                            key.decrypt_entry_metadata(
+                                category.as_bytes(),
+                                name.as_bytes(),
+                                enc_metadata,
+                            )
+                        })
+                        .await?,
+                ))
+            } else {
+                Ok(None)
+            }
+        })
+    }
+
+    fn update_metadata<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        metadata: Option<&'q [u8]>,
+    ) -> BoxFuture<'q, Result<(), Error>> {
+        let metadata = metadata.map(ProfileKey::prepare_input);
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.entry_metadata() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Entry metadata is not supported by this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name, enc_metadata) = timer
+                .unblock({
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        let enc_metadata = metadata
+                            .map(|m| {
+                                key.encrypt_entry_metadata(category.as_ref(), name.as_ref(), m)
+                            })
+                            .transpose()?;
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                            enc_metadata,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let updated = sqlx::query(METADATA_UPDATE_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .bind(enc_metadata)
+                .execute(active.connection_mut())
+                .await?
+                .rows_affected();
+            if updated == 0 {
+                return Err(err_msg!(NotFound, "Entry not found"));
+            }
+            Ok(())
+        })
+    }
+
+    fn update_link<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        link: EntryLink,
+        remove: bool,
+    ) -> BoxFuture<'q, Result<bool, Error>> {
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.entry_links() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Entry links are not supported by this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_source, enc_target, enc_rel_type) = timer
+                .unblock({
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        Result::<_, Error>::Ok((
+                            (
+                                key.encrypt_entry_category(category)?,
+                                key.encrypt_entry_name(name)?,
+                            ),
+                            (
+                                key.encrypt_entry_category(ProfileKey::prepare_input(
+                                    link.category.as_bytes(),
+                                ))?,
+                                key.encrypt_entry_name(ProfileKey::prepare_input(
+                                    link.name.as_bytes(),
+                                ))?,
+                            ),
+                            key.encrypt_link_type(ProfileKey::prepare_input(
+                                link.rel_type.as_bytes(),
+                            ))?,
+                        ))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let mut txn = active.as_transaction().await?;
+            let mut item_ids = Vec::with_capacity(2);
+            for (enc_category, enc_name) in vec![enc_source, enc_target] {
+                let item_id: Option<ProfileId> = sqlx::query_scalar(SEARCH_ITEM_QUERY)
+                    .bind(profile_id)
+                    .bind(kind.code())
+                    .bind(enc_category)
+                    .bind(enc_name)
+                    .fetch_optional(txn.connection_mut())
+                    .await?;
+                match item_id {
+                    Some(item_id) => item_ids.push(item_id),
+                    None if remove => return Ok(false),
+                    None => return Err(err_msg!(NotFound, "Entry not found")),
                 }
+            }
+            let updated = sqlx::query(if remove {
+                LINK_DELETE_QUERY
+            } else {
+                LINK_INSERT_QUERY
             })
-            .await?;
-            params.push(enc_category);
-            let query =
-                extend_query::<SqliteStore>(DELETE_ALL_QUERY, &mut params, tag_filter, None, None)?;
+            .bind(item_ids[0])
+            .bind(item_ids[1])
+            .bind(enc_rel_type)
+            .execute(txn.connection_mut())
+            .await?
+            .rows_affected();
+            txn.commit().await?;
+            Ok(updated != 0)
+        })
+    }
 
+    fn list_links<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        rel_type: Option<String>,
+        incoming: bool,
+    ) -> BoxFuture<'q, Result<Vec<EntryLink>, Error>> {
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.entry_links() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Entry links are not supported by this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name, enc_rel_type) = timer
+                .unblock({
+                    let key = key.clone();
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    move || {
+                        Result::<_, Error>::Ok((
+                            key.encrypt_entry_category(category)?,
+                            key.encrypt_entry_name(name)?,
+                            rel_type
+                                .map(|r| {
+                                    key.encrypt_link_type(ProfileKey::prepare_input(r.as_bytes()))
+                                })
+                                .transpose()?,
+                        ))
+                    }
+                })
+                .await?;
             let mut active = acquire_session(&mut *self).await?;
-            let removed = sqlx::query_with(query.as_str(), params)
-                .execute(active.connection_mut())
+            let item_id: ProfileId = sqlx::query_scalar(SEARCH_ITEM_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .fetch_optional(active.connection_mut())
                 .await?
-                .rows_affected();
-            Ok(removed as i64)
+                .ok_or_else(|| err_msg!(NotFound, "Entry not found"))?;
+            let mut query = String::from(if incoming {
+                LINKS_INCOMING_QUERY
+            } else {
+                LINKS_QUERY
+            });
+            if enc_rel_type.is_some() {
+                query.push_str(" AND l.rel_type = ?2");
+            }
+            query.push_str(" ORDER BY t.id");
+            let mut links_query = sqlx::query(query.as_str()).bind(item_id);
+            if let Some(enc_rel_type) = enc_rel_type {
+                links_query = links_query.bind(enc_rel_type);
+            }
+            let mut enc_links = vec![];
+            for row in links_query.fetch_all(active.connection_mut()).await? {
+                enc_links.push((row.try_get(0)?, row.try_get(1)?, row.try_get(2)?));
+            }
+            timer.unblock(move || decrypt_links(enc_links, &key)).await
         })
     }
 
-    fn update<'q>(
+    fn update_collection<'q>(
         &'q mut self,
         kind: EntryKind,
-        operation: EntryOperation,
         category: &'q str,
         name: &'q str,
-        value: Option<&'q [u8]>,
-        tags: Option<&'q [EntryTag]>,
-        expiry_ms: Option<i64>,
+        collection: Option<&'q str>,
     ) -> BoxFuture<'q, Result<(), Error>> {
-        let category = ProfileKey::prepare_input(category.as_bytes());
-        let name = ProfileKey::prepare_input(name.as_bytes());
-
-        match operation {
-            op @ EntryOperation::Insert | op @ EntryOperation::Replace => {
-                let value = ProfileKey::prepare_input(value.unwrap());
-                let tags = tags.map(prepare_tags);
-                Box::pin(async move {
-                    let (_, key) = acquire_key(&mut *self).await?;
-                    let (enc_category, enc_name, enc_value, enc_tags) = unblock(move || {
-                        let enc_value =
-                            key.encrypt_entry_value(category.as_ref(), name.as_ref(), value)?;
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.collections() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Collections are not supported by this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_category, enc_name, enc_collection) = timer
+                .unblock({
+                    let category = ProfileKey::prepare_input(category.as_bytes());
+                    let name = ProfileKey::prepare_input(name.as_bytes());
+                    let collection = collection.map(|c| ProfileKey::prepare_input(c.as_bytes()));
+                    move || {
                         Result::<_, Error>::Ok((
                             key.encrypt_entry_category(category)?,
                             key.encrypt_entry_name(name)?,
-                            enc_value,
-                            tags.transpose()?
-                                .map(|t| key.encrypt_entry_tags(t))
+                            collection
+                                .map(|c| key.encrypt_collection_name(c))
                                 .transpose()?,
                         ))
-                    })
-                    .await?;
-                    let mut active = acquire_session(&mut *self).await?;
-                    let mut txn = active.as_transaction().await?;
-                    if op == EntryOperation::Replace {
-                        perform_remove(&mut txn, kind, &enc_category, &enc_name, false).await?;
                     }
-                    perform_insert(
-                        &mut txn,
-                        kind,
-                        &enc_category,
-                        &enc_name,
-                        &enc_value,
-                        enc_tags,
-                        expiry_ms,
-                    )
-                    .await?;
-                    txn.commit().await?;
-                    Ok(())
                 })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let updated = sqlx::query(COLLECTION_UPDATE_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_category)
+                .bind(enc_name)
+                .bind(enc_collection)
+                .execute(active.connection_mut())
+                .await?
+                .rows_affected();
+            if updated == 0 {
+                return Err(err_msg!(NotFound, "Entry not found"));
             }
+            Ok(())
+        })
+    }
 
-            EntryOperation::Remove => Box::pin(async move {
-                let (_, key) = acquire_key(&mut *self).await?;
-                let (enc_category, enc_name) = unblock(move || {
-                    Result::<_, Error>::Ok((
-                        key.encrypt_entry_category(category)?,
-                        key.encrypt_entry_name(name)?,
-                    ))
+    fn collection_members<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        collection: &'q str,
+    ) -> BoxFuture<'q, Result<Vec<(String, String)>, Error>> {
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.collections() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Collections are not supported by this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let enc_collection = timer
+                .unblock({
+                    let collection = ProfileKey::prepare_input(collection.as_bytes());
+                    let key = key.clone();
+                    move || key.encrypt_collection_name(collection)
                 })
                 .await?;
-                let mut active = acquire_session(&mut *self).await?;
-                Ok(perform_remove(&mut active, kind, &enc_category, &enc_name, false).await?)
-            }),
-        }
+            let mut active = acquire_session(&mut *self).await?;
+            let members: Vec<(Vec<u8>, Vec<u8>)> = sqlx::query_as(COLLECTION_MEMBERS_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_collection)
+                .fetch_all(active.connection_mut())
+                .await?;
+            timer
+                .unblock(move || {
+                    members
+                        .into_iter()
+                        .map(|(enc_category, enc_name)| {
+                            Ok((
+                                key.decrypt_entry_category(enc_category)?,
+                                key.decrypt_entry_name(enc_name)?,
+                            ))
+                        })
+                        .collect()
+                })
+                .await
+        })
+    }
+
+    fn remove_collection<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        collection: &'q str,
+        skip: &'q [(String, String)],
+    ) -> BoxFuture<'q, Result<i64, Error>> {
+        Box::pin(async move {
+            let timer = self.crypto_timer();
+            if !self.collections() {
+                return Err(err_msg!(
+                    Unsupported,
+                    "Collections are not supported by this store"
+                ));
+            }
+            let (profile_id, key) = acquire_key(&mut *self).await?;
+            let (enc_collection, enc_skip) = timer
+                .unblock({
+                    let collection = ProfileKey::prepare_input(collection.as_bytes());
+                    let skip = skip
+                        .iter()
+                        .map(|(category, name)| {
+                            (
+                                ProfileKey::prepare_input(category.as_bytes()),
+                                ProfileKey::prepare_input(name.as_bytes()),
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    move || {
+                        let enc_skip = skip
+                            .into_iter()
+                            .map(|(category, name)| {
+                                Ok((
+                                    key.encrypt_entry_category(category)?,
+                                    key.encrypt_entry_name(name)?,
+                                ))
+                            })
+                            .collect::<Result<Vec<_>, Error>>()?;
+                        Result::<_, Error>::Ok((key.encrypt_collection_name(collection)?, enc_skip))
+                    }
+                })
+                .await?;
+            let mut active = acquire_session(&mut *self).await?;
+            let mut txn = active.as_transaction().await?;
+            let history = if txn.history() {
+                Some(history_timestamp())
+            } else {
+                None
+            };
+            let mut members: Vec<(Vec<u8>, Vec<u8>)> = sqlx::query_as(COLLECTION_MEMBERS_QUERY)
+                .bind(profile_id)
+                .bind(kind.code())
+                .bind(enc_collection)
+                .fetch_all(txn.connection_mut())
+                .await?;
+            members.retain(|member| !enc_skip.contains(member));
+            // each member is removed individually so that its history is closed
+            for (enc_category, enc_name) in members.iter() {
+                perform_remove(&mut txn, kind, enc_category, enc_name, true, history).await?;
+            }
+            txn.commit().await?;
+            Ok(members.len() as i64)
+        })
+    }
+
+    fn reset_connection(&mut self, discard: bool) -> bool {
+        DbSession::reset_connection(self, discard)
+    }
+
+    fn backend_name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn crypto_time(&self) -> Duration {
+        self.crypto_timer().elapsed()
+    }
+
+    fn set_durability(&mut self, durability: Durability) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(DbSession::set_durability(self, durability))
+    }
+
+    fn begin_atomic(&mut self) -> BoxFuture<'_, Result<bool, Error>> {
+        Box::pin(DbSession::begin_atomic(self))
+    }
+
+    fn end_atomic(&mut self, commit: bool) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(DbSession::end_atomic(self, commit))
+    }
+
+    fn access_mode(&mut self) -> BoxFuture<'_, Result<AccessMode, Error>> {
+        Box::pin(async move {
+            let mut active = acquire_session(&mut *self).await?;
+            let mode: Option<String> = sqlx::query_scalar(ACCESS_MODE_QUERY)
+                .fetch_optional(active.connection_mut())
+                .await?;
+            parse_access_mode(mode)
+        })
     }
 
     fn close(self, commit: bool) -> BoxFuture<'static, Result<(), Error>> {
@@ -464,6 +2395,43 @@ impl QueryBackend for DbSession<Sqlite> {
     }
 }
 
+impl Type<Sqlite> for ProfileId {
+    fn type_info() -> SqliteTypeInfo {
+        <i64 as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <i64 as Type<Sqlite>>::compatible(ty) || <Vec<u8> as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for ProfileId {
+    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        match self {
+            Self::Serial(id) => <i64 as Encode<'q, Sqlite>>::encode_by_ref(id, args),
+            Self::Uuid(id) => <Vec<u8> as Encode<'q, Sqlite>>::encode(id.to_vec(), args),
+        }
+    }
+
+    fn produces(&self) -> Option<SqliteTypeInfo> {
+        match self {
+            Self::Serial(_) => None,
+            Self::Uuid(_) => Some(<Vec<u8> as Type<Sqlite>>::type_info()),
+        }
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for ProfileId {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        if <i64 as Type<Sqlite>>::compatible(&value.type_info()) {
+            Ok(Self::Serial(<i64 as Decode<'r, Sqlite>>::decode(value)?))
+        } else {
+            let id = <&[u8] as Decode<'r, Sqlite>>::decode(value)?;
+            Ok(Self::Uuid(id.try_into()?))
+        }
+    }
+}
+
 impl ExtDatabase for Sqlite {
     fn start_transaction(
         conn: &mut PoolConnection<Self>,
@@ -481,6 +2449,50 @@ impl ExtDatabase for Sqlite {
             Ok(())
         })
     }
+
+    fn set_durability(
+        conn: &mut PoolConnection<Self>,
+        durability: Option<Durability>,
+    ) -> BoxFuture<'_, std::result::Result<(), SqlxError>> {
+        Box::pin(async move {
+            sqlx::query(synchronous_pragma(durability))
+                .execute(conn)
+                .await?;
+            Ok(())
+        })
+    }
+}
+
+/// The statement applying a durability level to a connection. SQLite
+/// defaults to `FULL` synchronization in the absence of a level
+pub(crate) fn synchronous_pragma(durability: Option<Durability>) -> &'static str {
+    match durability {
+        Some(Durability::Full) | None => "PRAGMA synchronous = FULL",
+        Some(Durability::Normal) => "PRAGMA synchronous = NORMAL",
+        Some(Durability::Relaxed) => "PRAGMA synchronous = OFF",
+    }
+}
+
+#[cfg(feature = "raw_query")]
+fn decode_raw_row(row: &sqlx::sqlite::SqliteRow) -> Result<RawRow, Error> {
+    use sqlx::{Column, TypeInfo};
+
+    let mut columns = Vec::with_capacity(row.len());
+    for (idx, column) in row.columns().iter().enumerate() {
+        let value = if row.try_get_raw(idx)?.is_null() {
+            RawValue::Null
+        } else {
+            // the storage class of the value, rather than the declared type of the column
+            match row.try_get_raw(idx)?.type_info().name() {
+                "INTEGER" | "BOOLEAN" => RawValue::Integer(row.try_get_unchecked(idx)?),
+                "REAL" => RawValue::Real(row.try_get_unchecked(idx)?),
+                "BLOB" => RawValue::Blob(row.try_get_unchecked(idx)?),
+                _ => RawValue::Text(row.try_get_unchecked(idx)?),
+            }
+        };
+        columns.push((column.name().to_string(), value));
+    }
+    Ok(RawRow::new(columns))
 }
 
 async fn acquire_key(
@@ -508,13 +2520,18 @@ async fn resolve_profile_key(
     if let Some((pid, key)) = cache.get_profile(profile.as_str()).await {
         Ok((pid, key))
     } else {
-        if let Some(row) = sqlx::query("SELECT id, profile_key FROM profiles WHERE name=?1")
-            .bind(profile.as_str())
-            .fetch_optional(conn)
-            .await?
+        if let Some(row) =
+            sqlx::query("SELECT id, profile_key, reference FROM profiles WHERE name=?1")
+                .bind(profile.as_str())
+                .fetch_optional(conn)
+                .await?
         {
             let pid = row.try_get(0)?;
-            let key = Arc::new(cache.load_key(row.try_get(1)?).await?);
+            let key = Arc::new(
+                cache
+                    .load_key(profile.clone(), row.try_get(2)?, row.try_get(1)?)
+                    .await?,
+            );
             cache.add_profile(profile, pid, key.clone()).await;
             Ok((pid, key))
         } else {
@@ -529,23 +2546,48 @@ async fn perform_insert<'q>(
     enc_category: &[u8],
     enc_name: &[u8],
     enc_value: &[u8],
+    checksum: Option<&[u8]>,
+    unique_key: Option<&[u8]>,
     enc_tags: Option<Vec<EncEntryTag>>,
-    expiry_ms: Option<i64>,
-) -> Result<(), Error> {
+    expiry: Option<EntryExpiry>,
+    history: Option<chrono::NaiveDateTime>,
+) -> Result<ProfileId, Error> {
     trace!("Insert entry");
-    let done = sqlx::query(INSERT_QUERY)
-        .bind(active.profile_id)
-        .bind(kind as i16)
-        .bind(enc_category)
-        .bind(enc_name)
-        .bind(enc_value)
-        .bind(expiry_ms.map(expiry_timestamp).transpose()?)
-        .execute(active.connection_mut())
-        .await?;
+    // a conflict on the unique key is ignored in the same way as a conflict
+    // on the record name, and reported as a duplicate
+    let mut query = sqlx::query(match (checksum.is_some(), unique_key.is_some()) {
+        (false, false) => INSERT_QUERY,
+        (true, false) => INSERT_CHECKSUM_QUERY,
+        (false, true) => INSERT_UNIQUE_QUERY,
+        (true, true) => INSERT_CHECKSUM_UNIQUE_QUERY,
+    })
+    .bind(active.profile_id)
+    .bind(kind.code())
+    .bind(enc_category)
+    .bind(enc_name)
+    .bind(enc_value)
+    .bind(expiry.map(expiry_timestamp).transpose()?)
+    .bind(expiry.and_then(|e| e.sliding_ms()));
+    if let Some(checksum) = checksum {
+        query = query.bind(checksum);
+    }
+    if let Some(unique_key) = unique_key {
+        query = query.bind(unique_key);
+    }
+    let done = query.execute(active.connection_mut()).await?;
     if done.rows_affected() == 0 {
         return Err(err_msg!(Duplicate, "Duplicate row"));
     }
-    let row_id = done.last_insert_rowid();
+    // a serial identifier is an alias for the row identifier
+    let row_id = match active.id_strategy() {
+        IdStrategy::Serial => ProfileId::from(done.last_insert_rowid()),
+        IdStrategy::Uuid => {
+            sqlx::query_scalar(ITEM_ID_QUERY)
+                .bind(done.last_insert_rowid())
+                .fetch_one(active.connection_mut())
+                .await?
+        }
+    };
     if let Some(tags) = enc_tags {
         for tag in tags {
             sqlx::query(TAG_INSERT_QUERY)
@@ -557,6 +2599,39 @@ async fn perform_insert<'q>(
                 .await?;
         }
     }
+    if let Some(valid_from) = history {
+        sqlx::query(HISTORY_INSERT_QUERY)
+            .bind(row_id)
+            .bind(valid_from)
+            .execute(active.connection_mut())
+            .await?;
+    }
+    Ok(row_id)
+}
+
+/// Check that a replacement does not conflict with the unique key of another
+/// record. The conflict is detected before the previous version is removed,
+/// so that a failed replacement leaves the store unmodified
+async fn check_unique_key<'q>(
+    active: &mut DbSessionActive<'q, Sqlite>,
+    kind: EntryKind,
+    enc_category: &[u8],
+    enc_name: &[u8],
+    unique_key: Option<&[u8]>,
+) -> Result<(), Error> {
+    if let Some(unique_key) = unique_key {
+        let conflict: bool = sqlx::query_scalar(UNIQUE_CONFLICT_QUERY)
+            .bind(active.profile_id)
+            .bind(kind.code())
+            .bind(enc_category)
+            .bind(unique_key)
+            .bind(enc_name)
+            .fetch_one(active.connection_mut())
+            .await?;
+        if conflict {
+            return Err(err_msg!(Duplicate, "Duplicate row"));
+        }
+    }
     Ok(())
 }
 
@@ -566,11 +2641,22 @@ async fn perform_remove<'q>(
     enc_category: &[u8],
     enc_name: &[u8],
     ignore_error: bool,
+    history: Option<chrono::NaiveDateTime>,
 ) -> Result<(), Error> {
     trace!("Remove entry");
+    if let Some(valid_to) = history {
+        sqlx::query(HISTORY_CLOSE_QUERY)
+            .bind(active.profile_id)
+            .bind(kind.code())
+            .bind(enc_category)
+            .bind(enc_name)
+            .bind(valid_to)
+            .execute(active.connection_mut())
+            .await?;
+    }
     let done = sqlx::query(DELETE_QUERY)
         .bind(active.profile_id)
-        .bind(kind as i16)
+        .bind(kind.code())
         .bind(enc_category)
         .bind(enc_name)
         .execute(active.connection_mut())
@@ -582,6 +2668,34 @@ async fn perform_remove<'q>(
     }
 }
 
+fn rekey_update_query(count: usize) -> String {
+    format!(
+        "WITH v(id, key) AS (VALUES {})
+        UPDATE profiles SET profile_key = (SELECT key FROM v WHERE v.id = profiles.id)
+        WHERE id IN (SELECT id FROM v)",
+        batch_values_clause::<SqliteStore>("($$, $$)", count, 2)
+    )
+}
+
+// columns excluded by the projection are selected as empty values
+fn scan_query(projection: Projection) -> String {
+    format!(
+        "SELECT i.rowid, i.name, {}, {} AS tags, i.expiry
+        FROM items i WHERE i.profile_id = ?1 AND i.kind = ?2 AND i.category = ?3
+        AND (i.expiry IS NULL OR i.expiry > DATETIME('now'))",
+        if projection.includes_value() {
+            "i.value"
+        } else {
+            "X''"
+        },
+        if projection.includes_tags() {
+            SCAN_TAGS_COLUMN
+        } else {
+            "''"
+        },
+    )
+}
+
 fn perform_scan<'q>(
     mut active: DbSessionRef<'q, Sqlite>,
     profile_id: ProfileId,
@@ -589,13 +2703,16 @@ fn perform_scan<'q>(
     kind: EntryKind,
     category: String,
     tag_filter: Option<TagFilter>,
+    history_filter: HistoryFilter,
     offset: Option<i64>,
     limit: Option<i64>,
+    after_id: Option<i64>,
+    projection: Projection,
 ) -> impl Stream<Item = Result<Vec<EncScanEntry>, Error>> + 'q {
     try_stream! {
         let mut params = QueryParams::new();
         params.push(profile_id);
-        params.push(kind as i16);
+        params.push(kind.code());
         let (enc_category, tag_filter) = unblock({
             let key = key.clone();
             let category = ProfileKey::prepare_input(category.as_bytes());
@@ -608,17 +2725,30 @@ fn perform_scan<'q>(
             }
         }).await?;
         params.push(enc_category);
-        let query = extend_query::<SqliteStore>(SCAN_QUERY, &mut params, tag_filter, offset, limit)?;
+        let mut query = extend_query::<SqliteStore>(&scan_query(projection), &mut params, tag_filter, None, None)?;
+        query.push_str(&history_clause::<SqliteStore>(&mut params, history_filter)?);
+        if let Some(after_id) = after_id {
+            params.push(after_id);
+            query.push_str(&replace_arg_placeholders::<SqliteStore>(" AND i.rowid > $$", params.len() as i64));
+        }
+        query.push_str(" ORDER BY i.rowid");
+        let query = SqliteStore::limit_query(query, &mut params, offset, limit);
 
-        let mut batch = Vec::with_capacity(PAGE_SIZE);
+        let page_size = active.page_size();
+        let mut batch = Vec::with_capacity(page_size);
 
         let mut acquired = acquire_session(&mut *active).await?;
         let mut rows = sqlx::query_with(query.as_str(), params).fetch(acquired.connection_mut());
+        let mut batch_bytes = 0;
         while let Some(row) = rows.try_next().await? {
-            batch.push(EncScanEntry {
-                name: row.try_get(1)?, value: row.try_get(2)?, tags: row.try_get(3)?
-            });
-            if batch.len() == PAGE_SIZE {
+            let entry = EncScanEntry {
+                seq: row.try_get(0)?, name: row.try_get(1)?, value: row.try_get(2)?, tags: row.try_get(3)?,
+                expiry: row.try_get::<Option<Expiry>, _>(4)?.map(|e| e.naive_utc()),
+            };
+            batch_bytes += entry.byte_len();
+            batch.push(entry);
+            if batch.len() == page_size || batch_bytes >= PAGE_BYTES {
+                batch_bytes = 0;
                 yield batch.split_off(0);
             }
         }
@@ -635,7 +2765,6 @@ fn perform_scan<'q>(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::backend::db_utils::replace_arg_placeholders;
     use crate::future::block_on;
     use crate::protect::{generate_raw_store_key, StoreKeyMethod};
 
@@ -646,7 +2775,7 @@ mod tests {
             let db = SqliteStoreOptions::in_memory()
                 .provision(StoreKeyMethod::RawKey, key, None, false)
                 .await?;
-            let ts = expiry_timestamp(1000).unwrap();
+            let ts = expiry_timestamp(EntryExpiry::Relative(1000)).unwrap();
             let check = sqlx::query("SELECT datetime('now'), ?1, ?1 > datetime('now')")
                 .bind(ts)
                 .fetch_one(&db.inner().conn_pool)
@@ -657,6 +2786,54 @@ mod tests {
             if !cmp {
                 panic!("now ({}) > expiry timestamp ({})", now, cmp_ts);
             }
+
+            // an absolute expiry in the past compares before the current time
+            let ts = expiry_timestamp(EntryExpiry::Absolute(1_000_500)).unwrap();
+            let check = sqlx::query("SELECT ?1, ?1 < datetime('now')")
+                .bind(ts)
+                .fetch_one(&db.inner().conn_pool)
+                .await?;
+            let cmp_ts: String = check.try_get(0)?;
+            let cmp: bool = check.try_get(1)?;
+            if !cmp {
+                panic!("now < expiry timestamp ({})", cmp_ts);
+            }
+            Result::<_, Error>::Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn sqlite_reset_lost_connection() {
+        let path = std::env::temp_dir().join(format!("askar-{}.db", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_string();
+        block_on(async {
+            let key = generate_raw_store_key(None)?;
+            let db = SqliteStoreOptions::from_path(&path)
+                .provision(StoreKeyMethod::RawKey, key, None, false)
+                .await?;
+
+            // a session outside of a transaction resumes on a new connection
+            let mut session = db.inner().session(None, false)?;
+            session.count(EntryKind::Item, "cat", None).await?;
+            assert!(session.reset_connection(true));
+            assert_eq!(session.count(EntryKind::Item, "cat", None).await?, 0);
+            session.close(false).await?;
+
+            // a transaction cannot be resumed
+            let mut txn = db.inner().session(None, true)?;
+            txn.count(EntryKind::Item, "cat", None).await?;
+            assert!(!txn.reset_connection(true));
+            let err = txn
+                .count(EntryKind::Item, "cat", None)
+                .await
+                .expect_err("Expected lost transaction");
+            assert_eq!(err.kind(), ErrorKind::ConnectionLost);
+            let err = txn.close(true).await.expect_err("Expected commit failure");
+            assert_eq!(err.kind(), ErrorKind::ConnectionLost);
+
+            db.close().await?;
+            SqliteStoreOptions::from_path(&path).remove().await?;
             Result::<_, Error>::Ok(())
         })
         .unwrap();
@@ -2,30 +2,77 @@ use std::borrow::Cow;
 use std::fs::remove_file;
 use std::io::ErrorKind as IoErrorKind;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use sqlx::{
-    sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions},
-    ConnectOptions, Error as SqlxError, Row,
+    sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePool, SqlitePoolOptions},
+    ConnectOptions, Error as SqlxError, Executor, Row,
 };
 
-use super::SqliteStore;
+use super::{
+    coalesce::{WriteCoalescing, DEFAULT_COALESCE_OPS},
+    synchronous_pragma, SqliteStore,
+};
 use crate::{
     backend::{
-        db_utils::{init_keys, random_profile_name},
-        types::ManageBackend,
+        db_utils::{
+            encode_key_check, init_keys, random_profile_name, verify_key_check, with_open_timeout,
+            SchemaState,
+        },
+        types::{Durability, ManageBackend},
     },
     error::Error,
     future::{unblock, BoxFuture},
-    protect::{KeyCache, PassKey, StoreKeyMethod, StoreKeyReference},
-    storage::{IntoOptions, Options, Store},
+    protect::{
+        check_pass_key, request_unlock, resolve_pass_key, IdStrategy, KeyCache, NonceStrategy,
+        PassKey, PassKeyPolicy, ProfileKeyCache, SecretResolver, StoreKeyMethod, StoreKeyReference,
+        UnlockProvider, UnlockReason, TENANT_KEY_REFERENCE,
+    },
+    storage::{IntoOptions, Options, Store, StoreCapabilities},
 };
 
 /// Configuration options for Sqlite stores
+///
+/// The `busy_timeout` parameter (in milliseconds) determines how long a
+/// connection waits for a database lock held by another connection before
+/// failing with a `Busy` error.
+///
+/// The `open_timeout` parameter (in milliseconds) limits the total time
+/// taken to open the store, including the derivation of the store key,
+/// after which opening fails with a `Busy` error.
+///
+/// When provisioned with `value_checksum=1`, a keyed checksum of each entry
+/// value is stored alongside the ciphertext, allowing `verify_integrity` to
+/// detect modified values.
+///
+/// The `write_coalesce_ms` parameter enables write coalescing, in which the
+/// updates of concurrent non-transactional sessions are committed together
+/// in a single transaction. An update waits up to the given number of
+/// milliseconds for others to arrive, or until `write_coalesce_ops` updates
+/// (100 by default) are pending. Each update still succeeds or fails on its
+/// own, but is only confirmed once the shared transaction has been committed.
+///
+/// The `durability` parameter (`full`, `normal` or `relaxed`) sets the
+/// `synchronous` pragma of each connection, and may be overridden for
+/// individual sessions.
 #[derive(Debug)]
 pub struct SqliteStoreOptions {
     pub(crate) in_memory: bool,
     pub(crate) path: String,
     pub(crate) max_connections: u32,
+    pub(crate) busy_timeout: Option<Duration>,
+    pub(crate) open_timeout: Option<Duration>,
+    pub(crate) id_strategy: IdStrategy,
+    pub(crate) history: bool,
+    pub(crate) value_checksum: bool,
+    pub(crate) write_coalescing: Option<WriteCoalescing>,
+    pub(crate) durability: Option<Durability>,
+    pub(crate) key_cache: Option<Arc<dyn ProfileKeyCache>>,
+    pub(crate) nonce_strategy: NonceStrategy,
+    pub(crate) secret_resolver: Option<Arc<dyn SecretResolver>>,
+    pub(crate) pass_key_policy: Option<Arc<PassKeyPolicy>>,
+    pub(crate) unlock_provider: Option<Arc<dyn UnlockProvider>>,
 }
 
 impl SqliteStoreOptions {
@@ -39,25 +86,156 @@ impl SqliteStoreOptions {
         } else {
             num_cpus::get() as u32
         };
+        let busy_timeout = if let Some(timeout) = opts.query.remove("busy_timeout") {
+            Some(Duration::from_millis(timeout.parse().map_err(err_map!(
+                Input,
+                "Error parsing 'busy_timeout' parameter"
+            ))?))
+        } else {
+            None
+        };
+        let open_timeout = if let Some(timeout) = opts.query.remove("open_timeout") {
+            Some(Duration::from_millis(timeout.parse().map_err(err_map!(
+                Input,
+                "Error parsing 'open_timeout' parameter"
+            ))?))
+        } else {
+            None
+        };
+        let id_strategy = if let Some(strategy) = opts.query.remove("id_strategy") {
+            IdStrategy::parse(&strategy)?
+        } else {
+            IdStrategy::default()
+        };
+        let nonce_strategy = if let Some(strategy) = opts.query.remove("nonce_strategy") {
+            NonceStrategy::parse(&strategy)?
+        } else {
+            NonceStrategy::default()
+        };
+        let history = if let Some(history) = opts.query.remove("history") {
+            match history.as_str() {
+                "1" | "true" => true,
+                "0" | "false" => false,
+                _ => return Err(err_msg!(Input, "Error parsing 'history' parameter")),
+            }
+        } else {
+            false
+        };
+        let value_checksum = if let Some(checksum) = opts.query.remove("value_checksum") {
+            match checksum.as_str() {
+                "1" | "true" => true,
+                "0" | "false" => false,
+                _ => return Err(err_msg!(Input, "Error parsing 'value_checksum' parameter")),
+            }
+        } else {
+            false
+        };
+        let coalesce_ops = if let Some(ops) = opts.query.remove("write_coalesce_ops") {
+            let ops: usize = ops.parse().map_err(err_map!(
+                Input,
+                "Error parsing 'write_coalesce_ops' parameter"
+            ))?;
+            if ops == 0 {
+                return Err(err_msg!(
+                    Input,
+                    "Error parsing 'write_coalesce_ops' parameter"
+                ));
+            }
+            ops
+        } else {
+            DEFAULT_COALESCE_OPS
+        };
+        let write_coalescing = if let Some(delay) = opts.query.remove("write_coalesce_ms") {
+            Some(WriteCoalescing {
+                max_delay: Duration::from_millis(delay.parse().map_err(err_map!(
+                    Input,
+                    "Error parsing 'write_coalesce_ms' parameter"
+                ))?),
+                max_ops: coalesce_ops,
+            })
+        } else {
+            None
+        };
+        let durability = if let Some(durability) = opts.query.remove("durability") {
+            Some(Durability::parse(&durability)?)
+        } else {
+            None
+        };
         let mut path = opts.host.to_string();
         path.push_str(&*opts.path);
         Ok(Self {
             in_memory: path == ":memory:",
             path,
             max_connections,
+            busy_timeout,
+            open_timeout,
+            id_strategy,
+            history,
+            value_checksum,
+            write_coalescing,
+            durability,
+            key_cache: None,
+            nonce_strategy,
+            secret_resolver: None,
+            pass_key_policy: None,
+            unlock_provider: None,
         })
     }
 
-    async fn pool(&self, auto_create: bool) -> std::result::Result<SqlitePool, SqlxError> {
+    /// Cache the decrypted profile keys using a custom implementation, such
+    /// as a cache shared between processes
+    pub fn with_key_cache(mut self, cache: Arc<dyn ProfileKeyCache>) -> Self {
+        self.key_cache = Some(cache);
+        self
+    }
+
+    /// Resolve the pass key using a `SecretResolver` when it is not provided
+    /// to `open` or `provision`
+    pub fn with_secret_resolver(mut self, resolver: Arc<dyn SecretResolver>) -> Self {
+        self.secret_resolver = Some(resolver);
+        self
+    }
+
+    /// Check the pass key against a policy when provisioning the store. The
+    /// policy is also applied when the opened store is rekeyed
+    pub fn with_pass_key_policy(mut self, policy: Arc<PassKeyPolicy>) -> Self {
+        self.pass_key_policy = Some(policy);
+        self
+    }
+
+    /// Request the pass key from an `UnlockProvider` when it is not provided
+    /// to `open` or `provision`, or by the secret resolver. The provider is
+    /// also used when the opened store is rekeyed
+    pub fn with_unlock_provider(mut self, provider: Arc<dyn UnlockProvider>) -> Self {
+        self.unlock_provider = Some(provider);
+        self
+    }
+
+    pub(crate) async fn pool(
+        &self,
+        auto_create: bool,
+    ) -> std::result::Result<SqlitePool, SqlxError> {
         #[allow(unused_mut)]
         let mut conn_opts =
             SqliteConnectOptions::from_str(self.path.as_ref())?.create_if_missing(auto_create);
+        if let Some(timeout) = self.busy_timeout {
+            conn_opts = conn_opts.busy_timeout(timeout);
+        }
         #[cfg(feature = "log")]
         {
             conn_opts.log_statements(log::LevelFilter::Debug);
             conn_opts.log_slow_statements(log::LevelFilter::Debug, Default::default());
         }
-        SqlitePoolOptions::default()
+        let mut pool_opts = SqlitePoolOptions::default();
+        if let Some(durability) = self.durability {
+            pool_opts = pool_opts.after_connect(move |conn| {
+                Box::pin(async move {
+                    conn.execute(synchronous_pragma(Some(durability))).await?;
+                    Ok(())
+                })
+            });
+        }
+        pool_opts
             // maintains at least 1 connection.
             // for an in-memory database this is required to avoid dropping the database,
             // for a file database this signals other instances that the database is in use
@@ -68,7 +246,12 @@ impl SqliteStoreOptions {
             .await
     }
 
-    /// Provision a new Sqlite store from these configuration options
+    /// Provision a new Sqlite store from these configuration options.
+    ///
+    /// If the store already exists then it is opened, unless `recreate` is
+    /// set in which case the database file is replaced. Tables left behind
+    /// by an interrupted provisioning attempt are removed automatically, as
+    /// long as no records have been written.
     pub async fn provision(
         self,
         method: StoreKeyMethod,
@@ -76,42 +259,74 @@ impl SqliteStoreOptions {
         profile: Option<&'_ str>,
         recreate: bool,
     ) -> Result<Store<SqliteStore>, Error> {
+        let pass_key = resolve_pass_key(self.secret_resolver.as_deref(), pass_key).await?;
+        let pass_key = request_unlock(
+            self.unlock_provider.as_deref(),
+            UnlockReason::Provision,
+            Some(&method),
+            pass_key,
+        )
+        .await?;
+        check_pass_key(self.pass_key_policy.as_deref(), &method, &pass_key)?;
         if recreate && !self.in_memory {
             try_remove_file(self.path.to_string()).await?;
         }
         let conn_pool = self.pool(true).await?;
 
         if !recreate {
-            if sqlx::query_scalar::<_, i64>(
-                "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='config'",
-            )
-            .fetch_one(&conn_pool)
-            .await?
-                == 1
-            {
-                return open_db(
-                    conn_pool,
-                    Some(method),
-                    pass_key,
-                    profile,
-                    self.path.to_string(),
-                )
-                .await;
+            let mut conn = conn_pool.acquire().await?;
+            match schema_state(&mut conn).await? {
+                SchemaState::Complete => {
+                    drop(conn);
+                    return Ok(open_db(conn_pool, Some(method), pass_key, profile, &self)
+                        .await?
+                        .with_optional_pass_key_policy(self.pass_key_policy)
+                        .with_optional_unlock_provider(self.unlock_provider));
+                }
+                SchemaState::Empty => (),
+                SchemaState::Partial { has_items: false } | SchemaState::Unconfigured => {
+                    // a previous provisioning attempt was interrupted,
+                    // remove the incomplete tables and start over
+                    reset_db(&mut conn).await?;
+                }
+                SchemaState::Partial { has_items: true } => {
+                    return Err(err_msg!(
+                        Unsupported,
+                        "The store schema is incomplete but contains records: \
+                        provision with 'recreate' to discard them"
+                    ));
+                }
             }
-            // no 'config' table, assume empty database
         }
 
         let default_profile = profile
             .map(str::to_string)
             .unwrap_or_else(random_profile_name);
-        let key_cache = init_db(&conn_pool, &default_profile, method, pass_key).await?;
-
-        Ok(Store::new(SqliteStore::new(
-            conn_pool,
-            default_profile,
-            key_cache,
-            self.path.to_string(),
-        )))
+        let key_cache = init_db(&conn_pool, &default_profile, method, pass_key, &self).await?;
+
+        Ok(Store::new(
+            SqliteStore::new(
+                conn_pool,
+                default_profile,
+                key_cache,
+                self.path.to_string(),
+                self.history,
+                self.nonce_strategy,
+            )
+            .with_value_checksum(self.value_checksum)
+            .with_id_strategy(self.id_strategy)
+            .with_capabilities(
+                StoreCapabilities::provisioned(self.history, self.value_checksum)
+                    .with_entry_metadata(true)
+                    .with_entry_links(true)
+                    .with_collections(true)
+                    .with_unique_keys(true),
+            )
+            .with_durability(self.durability)
+            .with_write_coalescing(self.write_coalescing),
+        )
+        .with_optional_pass_key_policy(self.pass_key_policy)
+        .with_optional_unlock_provider(self.unlock_provider))
     }
 
     /// Open an existing Sqlite store from this set of configuration options
@@ -121,22 +336,36 @@ impl SqliteStoreOptions {
         pass_key: PassKey<'_>,
         profile: Option<&'_ str>,
     ) -> Result<Store<SqliteStore>, Error> {
-        let conn_pool = match self.pool(false).await {
-            Ok(pool) => Ok(pool),
-            Err(SqlxError::Database(db_err)) => {
-                if db_err.code().expect("Expected SQLite error code") == "14" {
-                    // SQLITE_CANTOPEN error
-                    Err(err_msg!(
-                        NotFound,
-                        "The requested database path was not found"
-                    ))
-                } else {
-                    Err(SqlxError::Database(db_err).into())
+        let pass_key = resolve_pass_key(self.secret_resolver.as_deref(), pass_key).await?;
+        let pass_key = request_unlock(
+            self.unlock_provider.as_deref(),
+            UnlockReason::Open,
+            method.as_ref(),
+            pass_key,
+        )
+        .await?;
+        let store = with_open_timeout(self.open_timeout, async {
+            let conn_pool = match self.pool(false).await {
+                Ok(pool) => Ok(pool),
+                Err(SqlxError::Database(db_err)) => {
+                    if db_err.code().expect("Expected SQLite error code") == "14" {
+                        // SQLITE_CANTOPEN error
+                        Err(err_msg!(
+                            NotFound,
+                            "The requested database path was not found"
+                        ))
+                    } else {
+                        Err(SqlxError::Database(db_err).into())
+                    }
                 }
-            }
-            Err(err) => Err(err.into()),
-        }?;
-        Ok(open_db(conn_pool, method, pass_key, profile, self.path.to_string()).await?)
+                Err(err) => Err(err.into()),
+            }?;
+            open_db(conn_pool, method, pass_key, profile, &self).await
+        })
+        .await?;
+        Ok(store
+            .with_optional_pass_key_policy(self.pass_key_policy)
+            .with_optional_unlock_provider(self.unlock_provider))
     }
 
     /// Remove the Sqlite store defined by these configuration options
@@ -195,16 +424,27 @@ async fn init_db(
     profile_name: &str,
     method: StoreKeyMethod,
     pass_key: PassKey<'_>,
+    options: &SqliteStoreOptions,
 ) -> Result<KeyCache, Error> {
+    let (history, value_checksum, nonce_strategy) = (
+        options.history,
+        options.value_checksum,
+        options.nonce_strategy,
+    );
+    let (id_type, ref_type) = match options.id_strategy {
+        IdStrategy::Serial => ("INTEGER NOT NULL", "INTEGER"),
+        IdStrategy::Uuid => ("BLOB NOT NULL DEFAULT (randomblob(16))", "BLOB"),
+    };
     let (profile_key, enc_profile_key, store_key, store_key_ref) = unblock({
         let pass_key = pass_key.into_owned();
-        move || init_keys(method, pass_key)
+        move || init_keys(method, pass_key, nonce_strategy)
     })
     .await?;
+    let key_check = encode_key_check(&store_key)?;
 
     let mut conn = conn_pool.acquire().await?;
 
-    sqlx::query(
+    let init_query = format!(
         r#"
         BEGIN EXCLUSIVE TRANSACTION;
 
@@ -214,12 +454,18 @@ async fn init_db(
             PRIMARY KEY (name)
         );
         INSERT INTO config (name, value) VALUES
+            ("capabilities", ?9),
             ("default_profile", ?1),
             ("key", ?2),
+            ("key_check", ?7),
+            ("id_strategy", ?4),
+            ("history", ?5),
+            ("nonce_strategy", ?6),
+            ("value_checksum", ?8),
             ("version", "1");
 
         CREATE TABLE profiles (
-            id INTEGER NOT NULL,
+            id {id_type},
             name TEXT NOT NULL,
             reference TEXT NULL,
             profile_key BLOB NULL,
@@ -228,22 +474,31 @@ async fn init_db(
         CREATE UNIQUE INDEX ix_profile_name ON profiles (name);
 
         CREATE TABLE items (
-            id INTEGER NOT NULL,
-            profile_id INTEGER NOT NULL,
+            id {id_type},
+            profile_id {ref_type} NOT NULL,
             kind INTEGER NOT NULL,
             category BLOB NOT NULL,
             name BLOB NOT NULL,
             value BLOB NOT NULL,
             expiry DATETIME NULL,
+            expiry_sliding INTEGER NULL,
+            checksum BLOB NULL,
+            metadata BLOB NULL,
+            collection BLOB NULL,
+            unique_key BLOB NULL,
             PRIMARY KEY (id),
             FOREIGN KEY (profile_id) REFERENCES profiles (id)
                 ON DELETE CASCADE ON UPDATE CASCADE
         );
         CREATE UNIQUE INDEX ix_items_uniq ON items (profile_id, kind, category, name);
+        CREATE INDEX ix_items_collection ON items (profile_id, kind, collection)
+            WHERE collection IS NOT NULL;
+        CREATE UNIQUE INDEX ix_items_unique_key ON items (profile_id, kind, category, unique_key)
+            WHERE unique_key IS NOT NULL;
 
         CREATE TABLE items_tags (
             id INTEGER NOT NULL,
-            item_id INTEGER NOT NULL,
+            item_id {ref_type} NOT NULL,
             name BLOB NOT NULL,
             value BLOB NOT NULL,
             plaintext BOOLEAN NOT NULL,
@@ -255,19 +510,72 @@ async fn init_db(
         CREATE INDEX ix_items_tags_name_enc ON items_tags (name, SUBSTR(value, 1, 12)) WHERE plaintext=0;
         CREATE INDEX ix_items_tags_name_plain ON items_tags (name, value) WHERE plaintext=1;
 
+        CREATE TABLE items_search (
+            item_id {ref_type} NOT NULL,
+            token BLOB NOT NULL,
+            PRIMARY KEY (item_id, token),
+            FOREIGN KEY (item_id) REFERENCES items (id)
+                ON DELETE CASCADE ON UPDATE CASCADE
+        );
+        CREATE INDEX ix_items_search_token ON items_search (token);
+
+        CREATE TABLE items_links (
+            item_id {ref_type} NOT NULL,
+            target_id {ref_type} NOT NULL,
+            rel_type BLOB NOT NULL,
+            PRIMARY KEY (item_id, target_id, rel_type),
+            FOREIGN KEY (item_id) REFERENCES items (id)
+                ON DELETE CASCADE ON UPDATE CASCADE,
+            FOREIGN KEY (target_id) REFERENCES items (id)
+                ON DELETE CASCADE ON UPDATE CASCADE
+        );
+        CREATE INDEX ix_items_links_target ON items_links (target_id);
+
+        CREATE TABLE items_history (
+            id INTEGER NOT NULL,
+            profile_id {ref_type} NOT NULL,
+            kind INTEGER NOT NULL,
+            category BLOB NOT NULL,
+            name BLOB NOT NULL,
+            value BLOB NOT NULL,
+            tags TEXT NULL,
+            valid_from DATETIME NOT NULL,
+            valid_to DATETIME NULL,
+            PRIMARY KEY (id),
+            FOREIGN KEY (profile_id) REFERENCES profiles (id)
+                ON DELETE CASCADE ON UPDATE CASCADE
+        );
+        CREATE INDEX ix_items_history_entry ON items_history (profile_id, kind, category, name, valid_from);
+        CREATE INDEX ix_items_history_valid_to ON items_history (profile_id, kind, category, name, valid_to);
+
         INSERT INTO profiles (name, profile_key) VALUES (?1, ?3);
 
         COMMIT;
     "#,
-    )
-    .persistent(false)
-    .bind(profile_name)
-    .bind(store_key_ref)
-    .bind(enc_profile_key)
-    .execute(&mut conn)
-    .await?;
+        id_type = id_type,
+        ref_type = ref_type
+    );
+    if let Err(err) = sqlx::query(&init_query)
+        .persistent(false)
+        .bind(profile_name)
+        .bind(store_key_ref)
+        .bind(enc_profile_key)
+        .bind(id_strategy.as_str())
+        .bind(if history { "1" } else { "0" })
+        .bind(nonce_strategy.as_str())
+        .bind(key_check)
+        .bind(if value_checksum { "1" } else { "0" })
+        .bind(StoreCapabilities::provisioned(history, value_checksum).to_config())
+        .execute(&mut conn)
+        .await
+    {
+        // the script may have stopped within the transaction, which must not
+        // be left open on a pooled connection. this fails if it was never started
+        conn.execute("ROLLBACK").await.ok();
+        return Err(err.into());
+    }
 
-    let mut key_cache = KeyCache::new(store_key);
+    let mut key_cache = KeyCache::new(store_key).with_profile_cache(options.key_cache.clone());
 
     let row = sqlx::query("SELECT id FROM profiles WHERE name = ?1")
         .persistent(false)
@@ -279,35 +587,151 @@ async fn init_db(
     Ok(key_cache)
 }
 
+async fn schema_state(conn: &mut SqliteConnection) -> Result<SchemaState, Error> {
+    let tables: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type='table' AND name IN
+            ('config', 'profiles', 'items', 'items_tags', 'items_search', 'items_history')",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let config_count = if tables.iter().any(|t| t == "config") {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM config WHERE name IN ('default_profile', 'key', 'version')",
+        )
+        .fetch_one(&mut *conn)
+        .await?
+    } else {
+        0
+    };
+    let has_items = if tables.iter().any(|t| t == "items") {
+        sqlx::query_scalar::<_, i64>("SELECT EXISTS(SELECT 1 FROM items)")
+            .fetch_one(&mut *conn)
+            .await?
+            != 0
+    } else {
+        false
+    };
+    Ok(SchemaState::detect(&tables, config_count, has_items))
+}
+
+/// Determine whether the item table has a column for entry metadata, which
+/// is absent from stores provisioned by earlier releases
+async fn has_metadata_column(conn: &mut SqliteConnection) -> Result<bool, Error> {
+    Ok(sqlx::query_scalar::<_, i64>(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info('items') WHERE name='metadata')",
+    )
+    .fetch_one(conn)
+    .await?
+        != 0)
+}
+
+/// Determine whether the item table has a column for entry collections,
+/// which is absent from stores provisioned by earlier releases
+async fn has_collection_column(conn: &mut SqliteConnection) -> Result<bool, Error> {
+    Ok(sqlx::query_scalar::<_, i64>(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info('items') WHERE name='collection')",
+    )
+    .fetch_one(conn)
+    .await?
+        != 0)
+}
+
+/// Determine whether the item table has a uniquely indexed column for unique
+/// keys, which is absent from stores provisioned by earlier releases
+async fn has_unique_key_index(conn: &mut SqliteConnection) -> Result<bool, Error> {
+    Ok(sqlx::query_scalar::<_, i64>(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master
+        WHERE type='index' AND name='ix_items_unique_key')",
+    )
+    .fetch_one(conn)
+    .await?
+        != 0)
+}
+
+/// Determine whether the store has a table for entry links, which is absent
+/// from stores provisioned by earlier releases
+async fn has_links_table(conn: &mut SqliteConnection) -> Result<bool, Error> {
+    Ok(sqlx::query_scalar::<_, i64>(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type='table' AND name='items_links')",
+    )
+    .fetch_one(conn)
+    .await?
+        != 0)
+}
+
+async fn reset_db(conn: &mut SqliteConnection) -> Result<(), Error> {
+    conn.execute(
+        "
+        BEGIN EXCLUSIVE TRANSACTION;
+        DROP TABLE IF EXISTS items_history;
+        DROP TABLE IF EXISTS items_links;
+        DROP TABLE IF EXISTS items_search;
+        DROP TABLE IF EXISTS items_tags;
+        DROP TABLE IF EXISTS items;
+        DROP TABLE IF EXISTS profiles;
+        DROP TABLE IF EXISTS config;
+        COMMIT;
+        ",
+    )
+    .await?;
+    Ok(())
+}
+
 async fn open_db(
     conn_pool: SqlitePool,
     method: Option<StoreKeyMethod>,
     pass_key: PassKey<'_>,
     profile: Option<&str>,
-    path: String,
+    options: &SqliteStoreOptions,
 ) -> Result<Store<SqliteStore>, Error> {
     let mut conn = conn_pool.acquire().await?;
+    schema_state(&mut conn).await?.check_open()?;
     let mut ver_ok = false;
     let mut default_profile: Option<String> = None;
     let mut store_key_ref: Option<String> = None;
+    let mut key_check: Option<String> = None;
+    let mut history = false;
+    let mut value_checksum = false;
+    let mut capabilities = None;
+    let mut nonce_strategy = NonceStrategy::default();
+    let mut id_strategy = IdStrategy::default();
 
     let config = sqlx::query(
         r#"SELECT name, value FROM config
-        WHERE name IN ("default_profile", "key", "version")"#,
+        WHERE name IN ("capabilities", "default_profile", "history", "id_strategy", "key",
+            "key_check", "nonce_strategy", "value_checksum", "version")"#,
     )
     .fetch_all(&mut conn)
     .await?;
     for row in config {
         match row.try_get(0)? {
+            "capabilities" => {
+                capabilities.replace(StoreCapabilities::parse_config(row.try_get(1)?));
+            }
             "default_profile" => {
                 default_profile.replace(row.try_get(1)?);
             }
+            "history" => {
+                history = row.try_get::<&str, _>(1)? == "1";
+            }
+            "id_strategy" => {
+                id_strategy = IdStrategy::parse(row.try_get(1)?)?;
+            }
             "key" => {
                 store_key_ref.replace(row.try_get(1)?);
             }
+            "key_check" => {
+                key_check.replace(row.try_get(1)?);
+            }
+            "nonce_strategy" => {
+                nonce_strategy = NonceStrategy::parse(row.try_get(1)?)?;
+            }
+            "value_checksum" => {
+                value_checksum = row.try_get::<&str, _>(1)? == "1";
+            }
             "version" => {
                 if row.try_get::<&str, _>(1)? != "1" {
-                    return Err(err_msg!(Unsupported, "Unsupported store version"));
+                    return Err(err_msg!(SchemaMismatch, "Unsupported store version"));
                 }
                 ver_ok = true;
             }
@@ -315,8 +739,15 @@ async fn open_db(
         }
     }
     if !ver_ok {
-        return Err(err_msg!(Unsupported, "Store version not found"));
+        return Err(err_msg!(SchemaMismatch, "Store version not found"));
     }
+    // stores provisioned by earlier releases do not record their capabilities
+    let capabilities = capabilities
+        .unwrap_or_else(|| StoreCapabilities::provisioned(history, value_checksum))
+        .with_entry_metadata(has_metadata_column(&mut conn).await?)
+        .with_entry_links(has_links_table(&mut conn).await?)
+        .with_collections(has_collection_column(&mut conn).await?)
+        .with_unique_keys(has_unique_key_index(&mut conn).await?);
     let profile = profile
         .map(str::to_string)
         .or(default_profile)
@@ -328,27 +759,51 @@ async fn open_db(
                 return Err(err_msg!(Input, "Store key method mismatch"));
             }
         }
-        unblock({
+        let store_key = unblock({
             let pass_key = pass_key.into_owned();
             move || wrap_ref.resolve(pass_key)
         })
-        .await?
+        .await?;
+        // stores provisioned by earlier releases have no key check value,
+        // in which case an incorrect pass key fails when loading the profile key
+        if let Some(key_check) = key_check {
+            verify_key_check(&store_key, &key_check)?;
+        }
+        store_key
     } else {
-        return Err(err_msg!(Unsupported, "Store key not found"));
+        return Err(err_msg!(SchemaMismatch, "Store key not found"));
     };
-    let mut key_cache = KeyCache::new(store_key);
+    let mut key_cache = KeyCache::new(store_key).with_profile_cache(options.key_cache.clone());
 
-    let row = sqlx::query("SELECT id, profile_key FROM profiles WHERE name = ?1")
+    let row = sqlx::query("SELECT id, profile_key, reference FROM profiles WHERE name = ?1")
         .bind(&profile)
         .fetch_one(&mut conn)
         .await?;
-    let profile_id = row.try_get(0)?;
-    let profile_key = key_cache.load_key(row.try_get(1)?).await?;
-    key_cache.add_profile_mut(profile.clone(), profile_id, profile_key);
+    let reference: Option<String> = row.try_get(2)?;
+    // profiles wrapped by a tenant key are resolved when a session is opened
+    if reference.as_deref() != Some(TENANT_KEY_REFERENCE) {
+        let profile_id = row.try_get(0)?;
+        let profile_key = key_cache
+            .load_key(profile.clone(), reference, row.try_get(1)?)
+            .await?;
+        key_cache.add_profile_mut(profile.clone(), profile_id, profile_key);
+    }
 
-    Ok(Store::new(SqliteStore::new(
-        conn_pool, profile, key_cache, path,
-    )))
+    Ok(Store::new(
+        SqliteStore::new(
+            conn_pool,
+            profile,
+            key_cache,
+            options.path.to_string(),
+            history,
+            nonce_strategy,
+        )
+        .with_value_checksum(value_checksum)
+        .with_id_strategy(id_strategy)
+        .with_capabilities(capabilities)
+        .with_durability(options.durability)
+        .with_write_coalescing(options.write_coalescing),
+    ))
 }
 
 async fn try_remove_file(path: String) -> Result<bool, Error> {
@@ -359,3 +814,56 @@ async fn try_remove_file(path: String) -> Result<bool, Error> {
     })
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{future::block_on, protect::generate_raw_store_key, ErrorKind};
+
+    #[test]
+    fn sqlite_parse_busy_timeout() {
+        let opts = SqliteStoreOptions::new("sqlite://:memory:?busy_timeout=2500").unwrap();
+        assert_eq!(opts.busy_timeout, Some(Duration::from_millis(2500)));
+        assert!(SqliteStoreOptions::new("sqlite://:memory:?busy_timeout=x").is_err());
+        assert_eq!(SqliteStoreOptions::in_memory().busy_timeout, None);
+    }
+
+    #[test]
+    fn provision_recover_partial_schema() {
+        let path = std::env::temp_dir().join(format!("askar-{}.db", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_string();
+        block_on(async {
+            let opts = SqliteStoreOptions::from_path(&path);
+            let pool = opts.pool(true).await.unwrap();
+            // simulate an interrupted provisioning attempt
+            sqlx::query("CREATE TABLE profiles (id INTEGER NOT NULL, name TEXT NOT NULL)")
+                .execute(&pool)
+                .await
+                .unwrap();
+            pool.close().await;
+
+            let key = generate_raw_store_key(None).unwrap();
+            let err = SqliteStoreOptions::from_path(&path)
+                .open(None, key.as_ref(), None)
+                .await
+                .expect_err("Expected open to fail for partial schema");
+            assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+            let store = SqliteStoreOptions::from_path(&path)
+                .provision(StoreKeyMethod::RawKey, key.as_ref(), None, false)
+                .await
+                .expect("Error provisioning over partial schema");
+            store.close().await.unwrap();
+
+            SqliteStoreOptions::from_path(&path)
+                .open(None, key.as_ref(), None)
+                .await
+                .expect("Error opening recovered store")
+                .close()
+                .await
+                .unwrap();
+
+            SqliteStoreOptions::from_path(&path).remove().await.unwrap();
+        });
+    }
+}
@@ -1,8 +1,22 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
 use crate::{
+    crypto::buffer::SecretBytes,
     error::Error,
     future::BoxFuture,
-    protect::{PassKey, StoreKeyMethod},
-    storage::{Entry, EntryKind, EntryOperation, EntryTag, Scan, TagFilter},
+    protect::{KeyCacheStats, PassKey, StoreKeyMethod, TenantKeyProvider},
+    storage::{
+        Entry, EntryExpiry, EntryHash, EntryId, EntryKind, EntryLink, EntryOperation, EntryTag,
+        EntryVersion, HistoryFilter, IntegrityReport, ProfileShredding, Projection, Scan,
+        StorageReport, StoreCapabilities, StoreTuning, TagFilter, UniqueConstraint,
+    },
+};
+
+#[cfg(feature = "raw_query")]
+use crate::{
+    protect::{ProfileId, ProfileKey},
+    storage::{RawRow, RawValue},
 };
 
 /// Represents a generic backend implementation
@@ -16,19 +30,36 @@ pub trait Backend: Send + Sync {
     /// Get the name of the active profile
     fn get_profile_name(&self) -> &str;
 
+    /// Get the optional features supported by the store
+    fn capabilities(&self) -> StoreCapabilities;
+
     /// Remove an existing profile
     fn remove_profile(&self, name: String) -> BoxFuture<'_, Result<bool, Error>>;
 
-    /// Create a [`Scan`] against the store
+    /// Remove a profile along with its wrapped profile key, returning the
+    /// attestation of the erasure if the profile existed
+    fn shred_profile(&self, name: String)
+        -> BoxFuture<'_, Result<Option<ProfileShredding>, Error>>;
+
+    /// Create a [`Scan`] against the store.
+    ///
+    /// Records are returned in order of their sequence numbers. When `after_id`
+    /// is provided, only records following the given sequence number are
+    /// returned. Records outside the bounds of `history_filter` are excluded
+    /// using the entry history. Only the parts of each record selected by
+    /// `projection` are read and decrypted
     fn scan(
         &self,
         profile: Option<String>,
         kind: EntryKind,
         category: String,
         tag_filter: Option<TagFilter>,
+        history_filter: HistoryFilter,
         offset: Option<i64>,
         limit: Option<i64>,
-    ) -> BoxFuture<'_, Result<Scan<'static, Entry>, Error>>;
+        after_id: Option<i64>,
+        projection: Projection,
+    ) -> BoxFuture<'_, Result<Scan<Entry>, Error>>;
 
     /// Create a new session against the store
     fn session(&self, profile: Option<String>, transaction: bool) -> Result<Self::Session, Error>;
@@ -40,10 +71,163 @@ pub trait Backend: Send + Sync {
         key: PassKey<'_>,
     ) -> BoxFuture<'_, Result<(), Error>>;
 
+    /// Summarize the storage used by the records of each profile
+    fn storage_report(&self) -> BoxFuture<'_, Result<StorageReport, Error>>;
+
+    /// Decrypt the records of a profile, comparing each value with its
+    /// stored checksum when present
+    fn verify_integrity(
+        &self,
+        profile: Option<String>,
+    ) -> BoxFuture<'_, Result<IntegrityReport, Error>>;
+
+    /// Write a consistent copy of the store database to a new file, without
+    /// blocking other connections
+    fn backup_to(&self, path: String) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Copy the contents of the store into a new database at `uri`, returning
+    /// a store instance for the copy which shares the same keys
+    fn fork_to(&self, uri: String) -> BoxFuture<'_, Result<Self, Error>>
+    where
+        Self: Sized;
+
+    /// Perform backend-specific housekeeping on the store
+    fn maintenance(&self, mode: MaintenanceMode) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Read the access mode recorded in the store configuration
+    fn access_mode(&self) -> BoxFuture<'_, Result<AccessMode, Error>>;
+
+    /// Record a new access mode in the store configuration
+    fn set_access_mode(&self, mode: AccessMode) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Run a read-only query against the store tables, returning the rows
+    /// without decrypting them. The query must already have been checked by
+    /// the caller
+    #[cfg(feature = "raw_query")]
+    fn raw_query(
+        &self,
+        sql: String,
+        params: Vec<RawValue>,
+    ) -> BoxFuture<'_, Result<Vec<RawRow>, Error>>;
+
+    /// Resolve the identifier and key of a profile, for use in decrypting the
+    /// results of a raw query
+    #[cfg(feature = "raw_query")]
+    fn profile_key(
+        &self,
+        profile: Option<String>,
+    ) -> BoxFuture<'_, Result<(ProfileId, Arc<ProfileKey>), Error>>;
+
+    /// Set the provider of tenant keys used to wrap individual profile keys
+    fn set_tenant_keys(&mut self, provider: Option<Arc<dyn TenantKeyProvider>>);
+
+    /// Set the unique constraints applied to the records written by sessions
+    fn set_unique_constraints(&mut self, constraints: Arc<Vec<UniqueConstraint>>);
+
+    /// Get the hit, miss and eviction counters of the profile key cache
+    fn key_cache_stats(&self) -> KeyCacheStats;
+
+    /// Apply changes to the runtime options of the store
+    fn reconfigure(&self, tuning: &StoreTuning) -> Result<(), Error>;
+
     /// Close the store instance
     fn close(&self) -> BoxFuture<'_, Result<(), Error>>;
 }
 
+/// The extent of the housekeeping performed by a store maintenance run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaintenanceMode {
+    /// Reclaim space and refresh planner statistics without blocking
+    /// concurrent access to the store
+    Online,
+    /// Rebuild the store tables and indexes, which may block other
+    /// connections for the duration of the operation
+    Full,
+}
+
+impl Default for MaintenanceMode {
+    fn default() -> Self {
+        Self::Online
+    }
+}
+
+/// The level of durability requested for committed changes.
+///
+/// This maps to `synchronous_commit` for PostgreSQL stores, and to
+/// `PRAGMA synchronous` for SQLite stores. Lower levels may lose the most
+/// recently committed changes after a crash, in exchange for faster commits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Durability {
+    /// Each commit waits until the changes have been flushed to durable
+    /// storage, including any synchronous replicas
+    Full,
+    /// Each commit waits until the changes have been flushed locally. For
+    /// SQLite stores in WAL mode, a crash may roll back recent commits
+    Normal,
+    /// Commits do not wait for the changes to be flushed, so recent commits
+    /// may be lost after a crash. The database is not corrupted on Postgres,
+    /// but SQLite databases may be corrupted by a power failure
+    Relaxed,
+}
+
+impl Durability {
+    /// Get the string representation of the durability level
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Normal => "normal",
+            Self::Relaxed => "relaxed",
+        }
+    }
+
+    pub(crate) fn parse(value: &str) -> Result<Self, Error> {
+        match value {
+            "full" => Ok(Self::Full),
+            "normal" => Ok(Self::Normal),
+            "relaxed" => Ok(Self::Relaxed),
+            _ => Err(err_msg!(Input, "Unknown durability level: {}", value)),
+        }
+    }
+}
+
+/// The access permitted to the store by every handle, as recorded in the
+/// store configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessMode {
+    /// Records may be read and modified
+    ReadWrite,
+    /// Records may be read, but modifications are rejected
+    ReadOnly,
+    /// No sessions or scans may be started
+    Offline,
+}
+
+impl Default for AccessMode {
+    fn default() -> Self {
+        Self::ReadWrite
+    }
+}
+
+impl AccessMode {
+    /// Get the string representation of the access mode
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ReadWrite => "read_write",
+            Self::ReadOnly => "read_only",
+            Self::Offline => "offline",
+        }
+    }
+
+    pub(crate) fn parse(value: &str) -> Result<Self, Error> {
+        match value {
+            "read_write" => Ok(Self::ReadWrite),
+            "read_only" => Ok(Self::ReadOnly),
+            "offline" => Ok(Self::Offline),
+            _ => Err(err_msg!(Input, "Unknown access mode: {}", value)),
+        }
+    }
+}
+
 /// Create, open, or remove a generic backend implementation
 pub trait ManageBackend<'a> {
     /// The type of store being managed
@@ -89,7 +273,56 @@ pub trait QueryBackend: Send {
         for_update: bool,
     ) -> BoxFuture<'q, Result<Option<Entry>, Error>>;
 
-    /// Fetch all matching records from the store
+    /// Fetch the value of a single record by category and name, decrypting it
+    /// into a caller-provided buffer. The previous contents of the buffer are
+    /// cleared, and `false` is returned if the record was not found
+    fn fetch_into<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        output: &'q mut SecretBytes,
+    ) -> BoxFuture<'q, Result<bool, Error>>;
+
+    /// Check whether a record exists by category and name, without reading
+    /// or decrypting its value
+    fn exists<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+    ) -> BoxFuture<'q, Result<bool, Error>>;
+
+    /// Fetch a single record by the identity assigned when it was inserted.
+    /// The category of the returned record includes its namespace, if any
+    fn fetch_by_id(
+        &mut self,
+        kind: EntryKind,
+        id: EntryId,
+    ) -> BoxFuture<'_, Result<Option<Entry>, Error>>;
+
+    /// Fetch a single record as it existed at a given time.
+    /// Requires entry history to be enabled for the store
+    fn fetch_at<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        timestamp: SystemTime,
+    ) -> BoxFuture<'q, Result<Option<Entry>, Error>>;
+
+    /// List the recorded versions of a single record, most recent first.
+    /// Requires entry history to be enabled for the store
+    fn fetch_history<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        limit: Option<i64>,
+    ) -> BoxFuture<'q, Result<Vec<EntryVersion>, Error>>;
+
+    /// Fetch all matching records from the store, returning the parts of each
+    /// record selected by `projection`
     fn fetch_all<'q>(
         &'q mut self,
         kind: EntryKind,
@@ -97,6 +330,17 @@ pub trait QueryBackend: Send {
         tag_filter: Option<TagFilter>,
         limit: Option<i64>,
         for_update: bool,
+        projection: Projection,
+    ) -> BoxFuture<'q, Result<Vec<Entry>, Error>>;
+
+    /// Fetch the records in a category which were indexed with all of the
+    /// given search terms
+    fn search<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        terms: Vec<String>,
+        limit: Option<i64>,
     ) -> BoxFuture<'q, Result<Vec<Entry>, Error>>;
 
     /// Remove all matching records from the store
@@ -107,7 +351,41 @@ pub trait QueryBackend: Send {
         tag_filter: Option<TagFilter>,
     ) -> BoxFuture<'q, Result<i64, Error>>;
 
-    /// Insert or replace a record in the store
+    /// Insert a batch of new records of a single kind, such as when importing
+    /// a large number of records. The records are written within a database
+    /// transaction, which is started if the session is not already in one,
+    /// and a `Duplicate` error is returned if any of the records exist
+    fn insert_all<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        entries: Vec<Entry>,
+    ) -> BoxFuture<'q, Result<(), Error>>;
+
+    /// Replace the expiry of the records matching a category and tag filter,
+    /// selected in scan order, using a single statement. Returns the number
+    /// of records updated
+    fn touch_all<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        tag_filter: Option<TagFilter>,
+        offset: Option<i64>,
+        limit: Option<i64>,
+        expiry: EntryExpiry,
+    ) -> BoxFuture<'q, Result<i64, Error>>;
+
+    /// Insert or replace a record in the store.
+    ///
+    /// An update which requires several statements, such as a replacement
+    /// (removing the previous version and inserting the new one, along with
+    /// its tags) must be performed within a database transaction, which is
+    /// started for the update if the session is not already in one. An
+    /// update interrupted by a crash is then rolled back by the database
+    /// when it is next opened, and no separate journal is required.
+    ///
+    /// This applies to a single call only. Callers combining several calls
+    /// into one operation, such as removing a record along with its
+    /// protection marker, group them with `begin_atomic` and `end_atomic`.
     fn update<'q>(
         &'q mut self,
         kind: EntryKind,
@@ -116,9 +394,163 @@ pub trait QueryBackend: Send {
         name: &'q str,
         value: Option<&'q [u8]>,
         tags: Option<&'q [EntryTag]>,
-        expiry_ms: Option<i64>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<(), Error>>;
+
+    /// Insert a single record, returning the identity assigned to it
+    fn insert_returning_id<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<EntryId, Error>>;
+
+    /// Insert a single record, or fetch the current record with the same
+    /// category and name within the same transaction. Returns `None` if the
+    /// record was inserted
+    fn insert_or_fetch<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+    ) -> BoxFuture<'q, Result<Option<Entry>, Error>>;
+
+    /// Replace a single record, returning the content hash of the previous
+    /// version. When `expected` is provided, the record is only replaced if
+    /// the hash of its current contents matches, and otherwise a `Conflict`
+    /// error is returned
+    fn replace_checked<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        value: &'q [u8],
+        tags: Option<&'q [EntryTag]>,
+        expiry: Option<EntryExpiry>,
+        expected: Option<EntryHash>,
+    ) -> BoxFuture<'q, Result<EntryHash, Error>>;
+
+    /// Replace the search tokens indexed for a single record with those
+    /// derived from a set of search terms
+    fn update_search_terms<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        terms: Vec<String>,
+    ) -> BoxFuture<'q, Result<(), Error>>;
+
+    /// Fetch the metadata of a single record, returning `None` if no metadata
+    /// has been set. A `NotFound` error is returned if the record does not exist
+    fn fetch_metadata<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+    ) -> BoxFuture<'q, Result<Option<SecretBytes>, Error>>;
+
+    /// Replace or clear the metadata of a single record, without modifying
+    /// its value or tags
+    fn update_metadata<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        metadata: Option<&'q [u8]>,
     ) -> BoxFuture<'q, Result<(), Error>>;
 
+    /// Add or remove a typed link from one record to another record of the
+    /// same kind. Returns `true` if a link was added or removed
+    fn update_link<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        link: EntryLink,
+        remove: bool,
+    ) -> BoxFuture<'q, Result<bool, Error>>;
+
+    /// List the links from a record to other records, optionally filtered by
+    /// relation type. When `incoming` is set, the links to the record from
+    /// other records are listed instead
+    fn list_links<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        rel_type: Option<String>,
+        incoming: bool,
+    ) -> BoxFuture<'q, Result<Vec<EntryLink>, Error>>;
+
+    /// Add a single record to a named collection, or remove it from its
+    /// current collection
+    fn update_collection<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        category: &'q str,
+        name: &'q str,
+        collection: Option<&'q str>,
+    ) -> BoxFuture<'q, Result<(), Error>>;
+
+    /// List the stored categories and names of the records in a named
+    /// collection
+    fn collection_members<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        collection: &'q str,
+    ) -> BoxFuture<'q, Result<Vec<(String, String)>, Error>>;
+
+    /// Remove all of the records in a named collection within a database
+    /// transaction, returning the number of records removed. Members matching
+    /// one of the `(category, name)` pairs in `skip` are retained
+    fn remove_collection<'q>(
+        &'q mut self,
+        kind: EntryKind,
+        collection: &'q str,
+        skip: &'q [(String, String)],
+    ) -> BoxFuture<'q, Result<i64, Error>>;
+
+    /// Set the durability of the changes committed by this session, overriding
+    /// the default of the store. For a transaction, this must be set before
+    /// the transaction is started by the first operation
+    fn set_durability(&mut self, durability: Durability) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Start a transaction for a group of operations which must be applied
+    /// atomically, when the session is not already a transaction. Returns
+    /// `false` if the session is a transaction, which already applies the
+    /// operations atomically
+    fn begin_atomic(&mut self) -> BoxFuture<'_, Result<bool, Error>>;
+
+    /// Commit or roll back the transaction started by `begin_atomic`
+    fn end_atomic(&mut self, commit: bool) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Read the access mode recorded in the store configuration using the
+    /// session connection
+    fn access_mode(&mut self) -> BoxFuture<'_, Result<AccessMode, Error>>;
+
+    /// Release the session connection after it has been lost or an operation
+    /// was abandoned. When `discard` is set, the connection is closed rather
+    /// than being returned to the pool.
+    ///
+    /// Returns `true` if the failed operation may be retried on a new connection.
+    fn reset_connection(&mut self, _discard: bool) -> bool {
+        false
+    }
+
+    /// The name of the store backend, as recorded in error contexts
+    fn backend_name(&self) -> &'static str;
+
+    /// The total time spent encrypting and decrypting records for this session
+    fn crypto_time(&self) -> Duration {
+        Duration::default()
+    }
+
     /// Close the current store session
     fn close(self, commit: bool) -> BoxFuture<'static, Result<(), Error>>;
 }
@@ -1,7 +1,12 @@
 use std::error::Error as StdError;
 use std::fmt::{self, Display, Formatter};
 
-use crate::crypto::{Error as CryptoError, ErrorKind as CryptoErrorKind};
+use hmac::{Hmac, Mac, NewMac};
+use once_cell::sync::Lazy;
+use sha2::Sha256;
+
+use crate::crypto::{random::fill_random, Error as CryptoError, ErrorKind as CryptoErrorKind};
+use crate::storage::EntryKind;
 
 /// The possible kinds of error produced by the crate
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -12,23 +17,53 @@ pub enum ErrorKind {
     /// The store backend was too busy to handle the request
     Busy,
 
+    /// A write conflicted with a concurrent modification of the record
+    Conflict,
+
+    /// The connection to the database was lost during the operation
+    ConnectionLost,
+
     /// An insert operation failed due to a unique key conflict
     Duplicate,
 
     /// An encryption or decryption operation failed
     Encryption,
 
+    /// The requested operation is not permitted for the store or session
+    Forbidden,
+
     /// The input parameters to the method were incorrect
     Input,
 
     /// The requested record was not found
     NotFound,
 
+    /// A record was rejected by the store policy
+    PolicyViolation,
+
+    /// A session was abandoned by a panic during a previous operation
+    Poisoned,
+
+    /// A tag filter exceeded the complexity limits of the store
+    QueryTooComplex,
+
+    /// The store schema or version is not compatible with this release
+    SchemaMismatch,
+
     /// An unexpected error occurred
     Unexpected,
 
     /// An unsupported operation was requested
     Unsupported,
+
+    /// The store key wrapping method is not supported by this release
+    UnsupportedWrapMethod,
+
+    /// A pass key did not satisfy the pass key policy
+    WeakPassKey,
+
+    /// The pass key provided did not match the store key
+    WrongPassKey,
 }
 
 impl ErrorKind {
@@ -37,12 +72,22 @@ impl ErrorKind {
         match self {
             Self::Backend => "Backend error",
             Self::Busy => "Busy",
+            Self::Conflict => "Conflict",
+            Self::ConnectionLost => "Connection lost",
             Self::Duplicate => "Duplicate",
             Self::Encryption => "Encryption error",
+            Self::Forbidden => "Forbidden",
             Self::Input => "Input error",
             Self::NotFound => "Not found",
+            Self::PolicyViolation => "Policy violation",
+            Self::Poisoned => "Poisoned",
+            Self::QueryTooComplex => "Query too complex",
+            Self::SchemaMismatch => "Schema mismatch",
             Self::Unexpected => "Unexpected error",
             Self::Unsupported => "Unsupported",
+            Self::UnsupportedWrapMethod => "Unsupported wrap method",
+            Self::WeakPassKey => "Weak pass key",
+            Self::WrongPassKey => "Wrong pass key",
         }
     }
 }
@@ -53,12 +98,95 @@ impl Display for ErrorKind {
     }
 }
 
+/// The key used to hash identifiers recorded in error contexts
+static CONTEXT_HASH_KEY: Lazy<[u8; 32]> = Lazy::new(|| {
+    let mut key = [0u8; 32];
+    fill_random(&mut key);
+    key
+});
+
+/// Metadata describing the store operation which produced an error.
+///
+/// Profile names and record categories are recorded as short keyed hashes,
+/// which are consistent within a process but do not reveal the identifiers
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub(crate) operation: Option<&'static str>,
+    pub(crate) backend: Option<&'static str>,
+    pub(crate) entry_kind: Option<EntryKind>,
+    pub(crate) profile_hash: Option<String>,
+    pub(crate) category_hash: Option<String>,
+}
+
+impl ErrorContext {
+    /// The name of the session operation
+    pub fn operation(&self) -> Option<&str> {
+        self.operation
+    }
+
+    /// The name of the store backend
+    pub fn backend(&self) -> Option<&str> {
+        self.backend
+    }
+
+    /// The kind of entry being accessed
+    pub fn entry_kind(&self) -> Option<EntryKind> {
+        self.entry_kind
+    }
+
+    /// The hashed name of the profile
+    pub fn profile_hash(&self) -> Option<&str> {
+        self.profile_hash.as_deref()
+    }
+
+    /// The hashed record category, including any session namespace
+    pub fn category_hash(&self) -> Option<&str> {
+        self.category_hash.as_deref()
+    }
+
+    /// Hash an identifier for inclusion in an error context
+    pub(crate) fn hash_identifier(value: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&*CONTEXT_HASH_KEY)
+            .expect("HMAC accepts keys of any length");
+        mac.update(value.as_bytes());
+        hex::encode(&mac.finalize().into_bytes()[..8])
+    }
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut sep = "";
+        let mut field = |f: &mut Formatter<'_>, name: &str, value: &dyn Display| {
+            let res = write!(f, "{}{}={}", sep, name, value);
+            sep = " ";
+            res
+        };
+        if let Some(operation) = self.operation {
+            field(f, "operation", &operation)?;
+        }
+        if let Some(backend) = self.backend {
+            field(f, "backend", &backend)?;
+        }
+        if let Some(kind) = self.entry_kind {
+            field(f, "kind", &kind.code())?;
+        }
+        if let Some(profile) = self.profile_hash.as_ref() {
+            field(f, "profile", profile)?;
+        }
+        if let Some(category) = self.category_hash.as_ref() {
+            field(f, "category", category)?;
+        }
+        Ok(())
+    }
+}
+
 /// The standard crate error type
 #[derive(Debug)]
 pub struct Error {
     pub(crate) kind: ErrorKind,
     pub(crate) cause: Option<Box<dyn StdError + Send + Sync + 'static>>,
     pub(crate) message: Option<String>,
+    pub(crate) context: Option<Box<ErrorContext>>,
 }
 
 impl Error {
@@ -67,6 +195,7 @@ impl Error {
             kind,
             cause: None,
             message: Some(msg.into()),
+            context: None,
         }
     }
 
@@ -80,10 +209,24 @@ impl Error {
         self.message.as_ref().map(String::as_str)
     }
 
+    /// Accessor for the operation metadata attached to the error
+    pub fn context(&self) -> Option<&ErrorContext> {
+        self.context.as_deref()
+    }
+
     pub(crate) fn with_cause<T: Into<Box<dyn StdError + Send + Sync>>>(mut self, err: T) -> Self {
         self.cause = Some(err.into());
         self
     }
+
+    /// Attach operation metadata, unless the error already carries it from a
+    /// nested operation
+    pub(crate) fn with_context(mut self, context: ErrorContext) -> Self {
+        if self.context.is_none() {
+            self.context = Some(Box::new(context));
+        }
+        self
+    }
 }
 
 impl Display for Error {
@@ -120,6 +263,7 @@ impl From<ErrorKind> for Error {
             kind,
             cause: None,
             message: None,
+            context: None,
         }
     }
 }
@@ -129,7 +273,45 @@ impl From<ErrorKind> for Error {
 #[cfg(any(feature = "indy_compat", feature = "postgres", feature = "sqlite"))]
 impl From<sqlx::Error> for Error {
     fn from(err: sqlx::Error) -> Self {
-        Error::from(ErrorKind::Backend).with_cause(err)
+        let kind = if is_connection_lost(&err) {
+            ErrorKind::ConnectionLost
+        } else if is_timeout(&err) {
+            ErrorKind::Busy
+        } else {
+            ErrorKind::Backend
+        };
+        Error::from(kind).with_cause(err)
+    }
+}
+
+/// Determine whether a backend error indicates a statement timeout or lock contention
+#[cfg(any(feature = "indy_compat", feature = "postgres", feature = "sqlite"))]
+fn is_timeout(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(db_err) => match db_err.code() {
+            // postgres query canceled (statement timeout), sqlite busy or locked
+            Some(code) => code == "57014" || code == "5" || code == "6",
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// Determine whether a backend error indicates a broken database connection
+#[cfg(any(feature = "indy_compat", feature = "postgres", feature = "sqlite"))]
+fn is_connection_lost(err: &sqlx::Error) -> bool {
+    match err {
+        // protocol errors may indicate a decoding failure on a healthy
+        // connection, and are not retried
+        sqlx::Error::Io(_) => true,
+        sqlx::Error::Database(db_err) => match db_err.code() {
+            // postgres connection exception (class 08), or operator intervention
+            // such as a server shutdown (57P0x)
+            Some(code) => code.starts_with("08") || code.starts_with("57P0"),
+            None => false,
+        },
+        _ => false,
     }
 }
 
@@ -169,3 +351,16 @@ macro_rules! err_map {
         |err| err_msg!($($params)*).with_cause(err)
     };
 }
+
+#[cfg(all(test, any(feature = "postgres", feature = "sqlite")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_lost_classification() {
+        let err = sqlx::Error::Io(std::io::ErrorKind::ConnectionReset.into());
+        assert_eq!(Error::from(err).kind(), ErrorKind::ConnectionLost);
+        let err = sqlx::Error::Protocol("unexpected response".to_string());
+        assert_eq!(Error::from(err).kind(), ErrorKind::Backend);
+    }
+}
@@ -21,6 +21,16 @@ pub enum ErrorCode {
     NotFound = 6,
     Unexpected = 7,
     Unsupported = 8,
+    ConnectionLost = 9,
+    PolicyViolation = 10,
+    Forbidden = 11,
+    Conflict = 12,
+    QueryTooComplex = 13,
+    WeakPassKey = 14,
+    WrongPassKey = 15,
+    UnsupportedWrapMethod = 16,
+    SchemaMismatch = 17,
+    Poisoned = 18,
 }
 
 impl From<ErrorKind> for ErrorCode {
@@ -28,12 +38,22 @@ impl From<ErrorKind> for ErrorCode {
         match kind {
             ErrorKind::Backend => ErrorCode::Backend,
             ErrorKind::Busy => ErrorCode::Busy,
+            ErrorKind::Conflict => ErrorCode::Conflict,
+            ErrorKind::ConnectionLost => ErrorCode::ConnectionLost,
             ErrorKind::Duplicate => ErrorCode::Duplicate,
             ErrorKind::Encryption => ErrorCode::Encryption,
+            ErrorKind::Forbidden => ErrorCode::Forbidden,
             ErrorKind::Input => ErrorCode::Input,
             ErrorKind::NotFound => ErrorCode::NotFound,
+            ErrorKind::PolicyViolation => ErrorCode::PolicyViolation,
+            ErrorKind::Poisoned => ErrorCode::Poisoned,
+            ErrorKind::QueryTooComplex => ErrorCode::QueryTooComplex,
+            ErrorKind::SchemaMismatch => ErrorCode::SchemaMismatch,
             ErrorKind::Unexpected => ErrorCode::Unexpected,
             ErrorKind::Unsupported => ErrorCode::Unsupported,
+            ErrorKind::UnsupportedWrapMethod => ErrorCode::UnsupportedWrapMethod,
+            ErrorKind::WeakPassKey => ErrorCode::WeakPassKey,
+            ErrorKind::WrongPassKey => ErrorCode::WrongPassKey,
         }
     }
 }
@@ -0,0 +1,67 @@
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use std::sync::Arc;
+
+use super::error::ErrorCode;
+use crate::{
+    error::Error,
+    protect::{
+        keychain::{set_keystore_hook, KeystoreHook},
+        PassKey,
+    },
+};
+
+/// Load a secret from the platform keystore, returning a null pointer when
+/// the secret is not found. The returned string must remain valid until it
+/// is passed to the release callback
+pub type KeystoreLoadCallback = extern "C" fn(
+    context: *const c_void,
+    service: *const c_char,
+    account: *const c_char,
+) -> *const c_char;
+
+pub type KeystoreReleaseCallback = extern "C" fn(context: *const c_void, value: *const c_char);
+
+pub struct CustomKeystore {
+    context: *const c_void,
+    load: KeystoreLoadCallback,
+    release: Option<KeystoreReleaseCallback>,
+}
+
+impl KeystoreHook for CustomKeystore {
+    fn load(&self, service: &str, account: &str) -> Result<Option<PassKey<'static>>, Error> {
+        let service = CString::new(service).map_err(err_map!(Input, "Invalid service name"))?;
+        let account = CString::new(account).map_err(err_map!(Input, "Invalid account name"))?;
+        let value = (self.load)(self.context, service.as_ptr(), account.as_ptr());
+        if value.is_null() {
+            return Ok(None);
+        }
+        let result = unsafe { CStr::from_ptr(value) }
+            .to_str()
+            .map(|secret| PassKey::from(secret.to_string()))
+            .map_err(err_map!(Input, "Invalid keystore secret"));
+        if let Some(release) = self.release {
+            release(self.context, value);
+        }
+        result.map(Some)
+    }
+}
+
+unsafe impl Send for CustomKeystore {}
+unsafe impl Sync for CustomKeystore {}
+
+#[no_mangle]
+pub extern "C" fn askar_set_keystore_callback(
+    context: *const c_void,
+    load: Option<KeystoreLoadCallback>,
+    release: Option<KeystoreReleaseCallback>,
+) -> ErrorCode {
+    catch_err! {
+        let hook = load.map(|load| {
+            Arc::new(CustomKeystore { context, load, release }) as Arc<dyn KeystoreHook>
+        });
+        set_keystore_hook(hook);
+        debug!("Updated keystore callback");
+        Ok(ErrorCode::Success)
+    }
+}
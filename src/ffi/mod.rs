@@ -18,6 +18,8 @@ mod macros;
 
 mod error;
 mod key;
+#[cfg(feature = "keychain")]
+mod keychain;
 mod log;
 mod result_list;
 mod secret;
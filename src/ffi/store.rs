@@ -30,7 +30,7 @@ static FFI_STORES: Lazy<RwLock<BTreeMap<StoreHandle, Arc<AnyStore>>>> =
     Lazy::new(|| RwLock::new(BTreeMap::new()));
 static FFI_SESSIONS: Lazy<StoreResourceMap<SessionHandle, AnySession>> =
     Lazy::new(|| StoreResourceMap::new());
-static FFI_SCANS: Lazy<StoreResourceMap<ScanHandle, Scan<'static, Entry>>> =
+static FFI_SCANS: Lazy<StoreResourceMap<ScanHandle, Scan<Entry>>> =
     Lazy::new(|| StoreResourceMap::new());
 
 impl StoreHandle {
@@ -23,6 +23,23 @@ where
         .expect("Error running blocking task")
 }
 
+/// Run a set of blocking tasks in parallel, returning the results in order
+pub async fn unblock_all<F, T>(tasks: Vec<F>) -> Vec<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let handles = tasks
+        .into_iter()
+        .map(|f| RUNTIME.spawn_blocking(f))
+        .collect::<Vec<_>>();
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        results.push(handle.await.expect("Error running blocking task"));
+    }
+    results
+}
+
 #[inline]
 pub fn spawn_ok(fut: impl Future<Output = ()> + Send + 'static) {
     RUNTIME.spawn(fut);
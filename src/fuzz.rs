@@ -0,0 +1,71 @@
+//! Entry points for fuzz testing the parsers which handle untrusted input.
+//!
+//! These functions are exposed for use by cargo-fuzz harnesses only, and
+//! discard the results of parsing.
+
+use std::str::FromStr;
+
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+use crate::backend::db_utils::decode_tags;
+use crate::{
+    kms::LocalKey,
+    protect::{StoreKeyMethod, StoreKeyReference},
+    storage::{
+        wql::{
+            sql::TagSqlEncoder,
+            tags::{tag_query, TagQueryEncoder},
+        },
+        TagFilter,
+    },
+};
+
+/// Decode the binary tag representation returned by the database backends
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "postgres", feature = "sqlite"))))]
+pub fn fuzz_decode_tags(data: &[u8]) {
+    let _ = decode_tags(data.to_vec());
+}
+
+/// Parse a WQL tag filter and encode it as an SQL clause
+pub fn fuzz_tag_filter_parse(data: &str) {
+    if let Ok(filter) = TagFilter::from_str(data) {
+        if let Ok(query) = tag_query(filter.query) {
+            let mut enc = TagSqlEncoder::new(
+                |name: &str| Ok(name.as_bytes().to_vec()),
+                |value: &str| Ok(value.as_bytes().to_vec()),
+            );
+            let _ = enc.encode_query(&query);
+        }
+    }
+}
+
+/// Parse a JWK and load the corresponding key
+pub fn fuzz_jwk_parse(data: &[u8]) {
+    let _ = LocalKey::from_jwk_slice(data);
+}
+
+/// Parse a store key method or wrap key reference URI
+pub fn fuzz_wrap_key_uri(data: &str) {
+    let _ = StoreKeyMethod::parse_uri(data);
+    let _ = StoreKeyReference::parse_uri(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_inputs() {
+        #[cfg(any(feature = "postgres", feature = "sqlite"))]
+        for input in &[&b""[..], b"1", b"1:", b"0:,", b"1:zz:00", b"0:6e:76,1:6e"] {
+            fuzz_decode_tags(input);
+        }
+        for input in &["", "{", "[]", r#"{"$not": {}}"#, r#"{"a": {"$in": []}}"#] {
+            fuzz_tag_filter_parse(input);
+        }
+        fuzz_jwk_parse(br#"{"kty": "OKP", "crv": "Ed25519", "x": ""}"#);
+        for input in &["", ":", "kdf:", "kdf:argon2i:", "raw:x", "none"] {
+            fuzz_wrap_key_uri(input);
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Support for building `did:key` and `did:peer` DID documents from keys
+
+use serde::Serialize;
+use serde_json::Value;
+
+use super::local_key::{KeyAlg, LocalKey};
+use crate::{
+    crypto::alg::{BlsCurves, EcCurves},
+    error::Error,
+};
+
+const DID_CONTEXT: &str = "https://www.w3.org/ns/did/v1";
+
+/// The multicodec prefix (as an unsigned varint) for a public key algorithm
+fn multicodec_prefix(alg: KeyAlg) -> Result<&'static [u8], Error> {
+    Ok(match alg {
+        KeyAlg::Ed25519 => &[0xed, 0x01],
+        KeyAlg::X25519 => &[0xec, 0x01],
+        KeyAlg::EcCurve(EcCurves::Secp256k1) => &[0xe7, 0x01],
+        KeyAlg::EcCurve(EcCurves::Secp256r1) => &[0x80, 0x24],
+        KeyAlg::Bls12_381(BlsCurves::G1) => &[0xea, 0x01],
+        KeyAlg::Bls12_381(BlsCurves::G2) => &[0xeb, 0x01],
+        _ => {
+            return Err(err_msg!(
+                Unsupported,
+                "Unsupported key algorithm for multicodec encoding: {}",
+                alg
+            ))
+        }
+    })
+}
+
+const MULTICODEC_ALGS: &[KeyAlg] = &[
+    KeyAlg::Ed25519,
+    KeyAlg::X25519,
+    KeyAlg::EcCurve(EcCurves::Secp256k1),
+    KeyAlg::EcCurve(EcCurves::Secp256r1),
+    KeyAlg::Bls12_381(BlsCurves::G1),
+    KeyAlg::Bls12_381(BlsCurves::G2),
+];
+
+/// Encode a public key in multibase format, using base58-btc and a
+/// multicodec prefix identifying the key algorithm
+pub fn to_multibase(key: &LocalKey) -> Result<String, Error> {
+    let prefix = multicodec_prefix(key.algorithm())?;
+    let mut buf = prefix.to_vec();
+    buf.extend_from_slice(&key.to_public_bytes()?);
+    Ok(format!("z{}", bs58::encode(buf).into_string()))
+}
+
+/// Decode a public key from its multibase format
+pub fn from_multibase(value: &str) -> Result<LocalKey, Error> {
+    let encoded = value
+        .strip_prefix('z')
+        .ok_or_else(|| err_msg!(Input, "Unsupported multibase encoding"))?;
+    let decoded = bs58::decode(encoded)
+        .into_vec()
+        .map_err(|_| err_msg!(Input, "Invalid base58 encoding"))?;
+    for alg in MULTICODEC_ALGS {
+        let prefix = multicodec_prefix(*alg)?;
+        if decoded.starts_with(prefix) {
+            return LocalKey::from_public_bytes(*alg, &decoded[prefix.len()..]);
+        }
+    }
+    Err(err_msg!(Unsupported, "Unsupported multicodec key type"))
+}
+
+/// Get the `did:key` identifier for a public key
+pub fn did_key(key: &LocalKey) -> Result<String, Error> {
+    Ok(format!("did:key:{}", to_multibase(key)?))
+}
+
+/// Resolve the public key from a `did:key` identifier
+pub fn from_did_key(did: &str) -> Result<LocalKey, Error> {
+    let value = did
+        .strip_prefix("did:key:")
+        .ok_or_else(|| err_msg!(Input, "Expected a did:key identifier"))?;
+    from_multibase(value)
+}
+
+/// A verification method within a DID document
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationMethod {
+    /// The identifier of the verification method
+    pub id: String,
+    /// The verification method type
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// The DID of the controller
+    pub controller: String,
+    /// The public key in multibase format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key_multibase: Option<String>,
+    /// The public key in JWK format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key_jwk: Option<Value>,
+}
+
+impl VerificationMethod {
+    /// Create a verification method for a public key. Ed25519 and X25519 keys
+    /// use the multibase encoding, while other keys are represented as JWKs
+    pub fn new(id: String, controller: String, key: &LocalKey) -> Result<Self, Error> {
+        let (type_, multibase, jwk) = match key.algorithm() {
+            KeyAlg::Ed25519 => ("Ed25519VerificationKey2020", Some(to_multibase(key)?), None),
+            KeyAlg::X25519 => ("X25519KeyAgreementKey2020", Some(to_multibase(key)?), None),
+            _ => {
+                let jwk = serde_json::from_str(&key.to_jwk_public(None)?)
+                    .map_err(err_map!(Unexpected, "Error parsing public key JWK"))?;
+                ("JsonWebKey2020", None, Some(jwk))
+            }
+        };
+        Ok(Self {
+            id,
+            type_: type_.to_string(),
+            controller,
+            public_key_multibase: multibase,
+            public_key_jwk: jwk,
+        })
+    }
+}
+
+/// A DID document describing the keys of a `did:key` or `did:peer` identifier
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidDocument {
+    /// The JSON-LD context
+    #[serde(rename = "@context")]
+    pub context: Vec<String>,
+    /// The DID
+    pub id: String,
+    /// The verification methods of the document
+    pub verification_method: Vec<VerificationMethod>,
+    /// References to the verification methods used for authentication
+    pub authentication: Vec<String>,
+    /// References to the verification methods used for assertions
+    pub assertion_method: Vec<String>,
+    /// References to the verification methods used for key agreement
+    pub key_agreement: Vec<String>,
+}
+
+impl DidDocument {
+    fn new(id: String) -> Self {
+        Self {
+            context: vec![DID_CONTEXT.to_string()],
+            id,
+            verification_method: Vec::new(),
+            authentication: Vec::new(),
+            assertion_method: Vec::new(),
+            key_agreement: Vec::new(),
+        }
+    }
+
+    fn add_method(&mut self, fragment: &str, key: &LocalKey) -> Result<String, Error> {
+        let id = format!("{}#{}", self.id, fragment);
+        self.verification_method
+            .push(VerificationMethod::new(id.clone(), self.id.clone(), key)?);
+        Ok(id)
+    }
+
+    /// Build the DID document for a `did:key` identifier.
+    ///
+    /// The key is used for authentication and assertions, except for X25519
+    /// keys which are used for key agreement. For Ed25519 keys, the derived
+    /// X25519 key is added as a key agreement method
+    pub fn did_key(key: &LocalKey) -> Result<Self, Error> {
+        Self::from_single_key(did_key(key)?, key)
+    }
+
+    /// Build the DID document for a `did:peer` identifier using method 0,
+    /// which wraps a single inception key
+    pub fn did_peer_0(key: &LocalKey) -> Result<Self, Error> {
+        Self::from_single_key(format!("did:peer:0{}", to_multibase(key)?), key)
+    }
+
+    fn from_single_key(did: String, key: &LocalKey) -> Result<Self, Error> {
+        let mut doc = Self::new(did);
+        let fragment = to_multibase(key)?;
+        let id = doc.add_method(&fragment, key)?;
+        if key.algorithm() == KeyAlg::X25519 {
+            doc.key_agreement.push(id);
+        } else {
+            doc.authentication.push(id.clone());
+            doc.assertion_method.push(id);
+            if key.algorithm() == KeyAlg::Ed25519 {
+                let xkey = key.convert_key(KeyAlg::X25519)?;
+                let fragment = to_multibase(&xkey)?;
+                let id = doc.add_method(&fragment, &xkey)?;
+                doc.key_agreement.push(id);
+            }
+        }
+        Ok(doc)
+    }
+
+    /// Build the DID document for a `did:peer` identifier using method 2,
+    /// combining a set of authentication keys and key agreement keys
+    pub fn did_peer_2(
+        authentication: &[&LocalKey],
+        key_agreement: &[&LocalKey],
+    ) -> Result<Self, Error> {
+        if authentication.is_empty() && key_agreement.is_empty() {
+            return Err(err_msg!(Input, "At least one key is required"));
+        }
+        let mut did = "did:peer:2".to_string();
+        for key in key_agreement {
+            did.push_str(".E");
+            did.push_str(&to_multibase(key)?);
+        }
+        for key in authentication {
+            did.push_str(".V");
+            did.push_str(&to_multibase(key)?);
+        }
+        let mut doc = Self::new(did);
+        let mut idx = 0;
+        for key in key_agreement {
+            idx += 1;
+            let id = doc.add_method(&format!("key-{}", idx), key)?;
+            doc.key_agreement.push(id);
+        }
+        for key in authentication {
+            idx += 1;
+            let id = doc.add_method(&format!("key-{}", idx), key)?;
+            doc.authentication.push(id.clone());
+            doc.assertion_method.push(id);
+        }
+        Ok(doc)
+    }
+
+    /// Convert the DID document to JSON format
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string(self).map_err(err_map!(Unexpected, "Error encoding DID document"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn did_key_ed25519() {
+        // test vector from the did:key method specification
+        let did = "did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK";
+        let key = from_did_key(did).unwrap();
+        assert_eq!(key.algorithm(), KeyAlg::Ed25519);
+        assert_eq!(did_key(&key).unwrap(), did);
+
+        let doc = DidDocument::did_key(&key).unwrap();
+        assert_eq!(doc.id, did);
+        assert_eq!(doc.verification_method.len(), 2);
+        assert_eq!(
+            doc.authentication,
+            vec![format!(
+                "{}#z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK",
+                did
+            )]
+        );
+        assert_eq!(
+            doc.verification_method[1].public_key_multibase.as_deref(),
+            Some("z6LSj72tK8brWgZja8NLRwPigth2T9QRiG1uH9oKZuKjdh9p")
+        );
+        assert_eq!(
+            doc.key_agreement,
+            vec![doc.verification_method[1].id.clone()]
+        );
+    }
+
+    #[test]
+    fn did_peer_2_keys() {
+        let auth = LocalKey::generate(KeyAlg::Ed25519, false).unwrap();
+        let agree = LocalKey::generate(KeyAlg::X25519, false).unwrap();
+        let doc = DidDocument::did_peer_2(&[&auth], &[&agree]).unwrap();
+        assert_eq!(
+            doc.id,
+            format!(
+                "did:peer:2.E{}.V{}",
+                to_multibase(&agree).unwrap(),
+                to_multibase(&auth).unwrap()
+            )
+        );
+        assert_eq!(doc.key_agreement, vec![format!("{}#key-1", doc.id)]);
+        assert_eq!(doc.authentication, vec![format!("{}#key-2", doc.id)]);
+    }
+
+    #[test]
+    fn multibase_jwk_methods() {
+        let key = LocalKey::generate(KeyAlg::EcCurve(EcCurves::Secp256r1), false).unwrap();
+        let decoded = from_multibase(&to_multibase(&key).unwrap()).unwrap();
+        assert_eq!(
+            decoded.to_public_bytes().unwrap(),
+            key.to_public_bytes().unwrap()
+        );
+        let method = VerificationMethod::new("id".into(), "did".into(), &key).unwrap();
+        assert_eq!(method.type_, "JsonWebKey2020");
+        assert_eq!(method.public_key_jwk.unwrap()["crv"], "P-256");
+    }
+}
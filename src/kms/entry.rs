@@ -1,6 +1,15 @@
-use super::local_key::LocalKey;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{
+    local_key::{KeyExportFormat, LocalKey},
+    provenance::KeyProvenance,
+};
 use crate::{
-    crypto::{alg::AnyKey, buffer::SecretBytes, jwk::FromJwk},
+    crypto::{
+        alg::AnyKey,
+        buffer::SecretBytes,
+        jwk::{FromJwk, KeyOpsSet},
+    },
     error::Error,
     storage::{Entry, EntryTag},
 };
@@ -16,12 +25,60 @@ pub struct KeyParams {
     #[serde(default, rename = "ref", skip_serializing_if = "Option::is_none")]
     pub reference: Option<String>,
 
+    /// The permitted operations for the key, if restricted
+    #[serde(default, rename = "ops", skip_serializing_if = "Option::is_none")]
+    pub key_ops: Option<KeyOpsSet>,
+
+    /// The creation time of the key, in seconds since the Unix epoch
+    #[serde(default, rename = "created", skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<i64>,
+
+    /// The expiry time of the key, in seconds since the Unix epoch
+    #[serde(default, rename = "exp", skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+
+    /// The number of seconds after creation at which the key is due for rotation
+    #[serde(default, rename = "rot", skip_serializing_if = "Option::is_none")]
+    pub rotate_after: Option<i64>,
+
+    /// The name of the key entry replaced by this key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replaces: Option<String>,
+
+    /// The name of the key entry which replaced this key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replaced_by: Option<String>,
+
+    /// The provenance record of the key, if any
+    #[serde(default, rename = "prov", skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<KeyProvenance>,
+
     /// The associated key data (JWK)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub data: Option<SecretBytes>,
 }
 
 impl KeyParams {
+    /// Determine whether the key has expired at the given time
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        matches!(self.expires_at, Some(exp) if exp <= to_timestamp(now))
+    }
+
+    /// Determine whether the key is due for rotation at the given time.
+    /// Keys which have already been replaced are never due.
+    pub fn rotation_due(&self, now: SystemTime) -> bool {
+        if self.replaced_by.is_some() {
+            return false;
+        }
+        if self.is_expired(now) {
+            return true;
+        }
+        match (self.created_at, self.rotate_after) {
+            (Some(created), Some(after)) => created.saturating_add(after) <= to_timestamp(now),
+            _ => false,
+        }
+    }
+
     pub(crate) fn to_bytes(&self) -> Result<SecretBytes, Error> {
         serde_cbor::to_vec(self)
             .map(SecretBytes::from)
@@ -66,6 +123,48 @@ impl KeyEntry {
         self.name.as_str()
     }
 
+    /// Accessor for the permitted key operations, if restricted
+    pub fn key_ops(&self) -> Option<KeyOpsSet> {
+        self.params.key_ops
+    }
+
+    /// Accessor for the key creation time, if recorded
+    pub fn created_at(&self) -> Option<SystemTime> {
+        self.params.created_at.map(from_timestamp)
+    }
+
+    /// Accessor for the key expiry time
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.params.expires_at.map(from_timestamp)
+    }
+
+    /// Accessor for the rotation interval of the key
+    pub fn rotate_after(&self) -> Option<Duration> {
+        self.params
+            .rotate_after
+            .map(|secs| Duration::from_secs(secs.max(0) as u64))
+    }
+
+    /// Accessor for the name of the key entry replaced by this key
+    pub fn replaces(&self) -> Option<&str> {
+        self.params.replaces.as_ref().map(String::as_ref)
+    }
+
+    /// Accessor for the name of the key entry which replaced this key
+    pub fn replaced_by(&self) -> Option<&str> {
+        self.params.replaced_by.as_ref().map(String::as_ref)
+    }
+
+    /// Determine whether the key has expired
+    pub fn is_expired(&self) -> bool {
+        self.params.is_expired(SystemTime::now())
+    }
+
+    /// Accessor for the provenance record of the key
+    pub fn provenance(&self) -> Option<&KeyProvenance> {
+        self.params.provenance.as_ref()
+    }
+
     /// Determine if a key entry refers to a local or external key
     pub fn is_local(&self) -> bool {
         self.params.reference.is_none()
@@ -111,6 +210,7 @@ impl KeyEntry {
             Ok(LocalKey {
                 inner,
                 ephemeral: false,
+                key_ops: self.params.key_ops,
             })
         } else {
             Err(err_msg!("Missing key data"))
@@ -118,19 +218,79 @@ impl KeyEntry {
     }
 }
 
+/// A key exported from the store
+#[derive(Clone, Debug)]
+pub struct ExportedKey {
+    /// The encoded key
+    pub data: SecretBytes,
+    /// The format of the encoded key
+    pub format: KeyExportFormat,
+    /// The provenance record of the stored key, if any
+    pub provenance: Option<KeyProvenance>,
+}
+
+pub(crate) fn to_timestamp(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn from_timestamp(ts: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(ts.max(0) as u64)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::jwk::KeyOps;
 
     #[test]
     fn key_params_roundtrip() {
         let params = KeyParams {
             metadata: Some("meta".to_string()),
             reference: None,
+            key_ops: Some(KeyOps::Sign | KeyOps::Verify),
+            created_at: Some(1000),
+            expires_at: None,
+            rotate_after: Some(3600),
+            replaces: Some("prev".to_string()),
+            replaced_by: None,
+            provenance: Some(KeyProvenance {
+                device: Some("device".to_string()),
+                attestation: Some(vec![1, 2, 3]),
+                ..Default::default()
+            }),
             data: Some(SecretBytes::from(vec![0, 0, 0, 0])),
         };
         let enc_params = params.to_bytes().unwrap();
         let p2 = KeyParams::from_slice(&enc_params).unwrap();
         assert_eq!(p2, params);
     }
+
+    #[test]
+    fn key_rotation_due() {
+        let mut params = KeyParams {
+            metadata: None,
+            reference: None,
+            key_ops: None,
+            created_at: Some(1000),
+            expires_at: None,
+            rotate_after: Some(3600),
+            replaces: None,
+            replaced_by: None,
+            provenance: None,
+            data: None,
+        };
+        assert!(!params.rotation_due(from_timestamp(4599)));
+        assert!(params.rotation_due(from_timestamp(4600)));
+
+        params.rotate_after = None;
+        params.expires_at = Some(2000);
+        assert!(!params.rotation_due(from_timestamp(1999)));
+        assert!(params.is_expired(from_timestamp(2000)));
+        assert!(params.rotation_due(from_timestamp(2000)));
+
+        params.replaced_by = Some("next".to_string());
+        assert!(!params.rotation_due(from_timestamp(2000)));
+    }
 }
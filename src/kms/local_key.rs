@@ -11,28 +11,73 @@ use crate::{
     crypto::{
         alg::{AnyKey, AnyKeyCreate, BlsCurves},
         encrypt::KeyAeadInPlace,
-        jwk::{FromJwk, ToJwk},
+        jwk::{FromJwk, JwkParts, KeyOps, KeyOpsSet, ToJwk},
         kdf::{KeyDerivation, KeyExchange},
-        random::fill_random,
+        pkcs8::{FromPkcs8, ToPkcs8},
+        random::{fill_random, KeyMaterial},
         repr::{ToPublicBytes, ToSecretBytes},
         sign::{KeySigVerify, KeySign, SignatureType},
-        Error as CryptoError,
+        Error as CryptoError, ErrorKind as CryptoErrorKind,
     },
     error::Error,
 };
 
+/// Supported encodings for exporting a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyExportFormat {
+    /// A JWK including the private key
+    Jwk,
+    /// A JWK of the public key
+    JwkPublic,
+    /// A PKCS#8 DER encoding of the private key
+    Pkcs8Der,
+    /// A PKCS#8 PEM encoding of the private key
+    Pkcs8Pem,
+    /// A SubjectPublicKeyInfo DER encoding of the public key
+    SpkiDer,
+    /// A SubjectPublicKeyInfo PEM encoding of the public key
+    SpkiPem,
+}
+
+impl KeyExportFormat {
+    /// Determine whether the format includes the private key
+    pub fn is_secret(&self) -> bool {
+        matches!(self, Self::Jwk | Self::Pkcs8Der | Self::Pkcs8Pem)
+    }
+}
+
 /// A stored key entry
 #[derive(Debug)]
 pub struct LocalKey {
     pub(crate) inner: Box<AnyKey>,
     pub(crate) ephemeral: bool,
+    pub(crate) key_ops: Option<KeyOpsSet>,
 }
 
 impl LocalKey {
     /// Create a new random key or keypair
     pub fn generate(alg: KeyAlg, ephemeral: bool) -> Result<Self, Error> {
         let inner = Box::<AnyKey>::generate(alg)?;
-        Ok(Self { inner, ephemeral })
+        Ok(Self {
+            inner,
+            ephemeral,
+            key_ops: None,
+        })
+    }
+
+    /// Create a new key or keypair using a provided source of key material,
+    /// such as a seeded random number generator
+    pub fn generate_with_rng(
+        alg: KeyAlg,
+        rng: impl KeyMaterial,
+        ephemeral: bool,
+    ) -> Result<Self, Error> {
+        let inner = Box::<AnyKey>::generate_with_rng(alg, rng)?;
+        Ok(Self {
+            inner,
+            ephemeral,
+            key_ops: None,
+        })
     }
 
     /// Create a new deterministic key or keypair
@@ -41,24 +86,68 @@ impl LocalKey {
         Ok(Self {
             inner,
             ephemeral: false,
+            key_ops: None,
         })
     }
 
     /// Import a key or keypair from a JWK in binary format
     pub fn from_jwk_slice(jwk: &[u8]) -> Result<Self, Error> {
-        let inner = Box::<AnyKey>::from_jwk_slice(jwk)?;
+        Self::from_jwk_parts(JwkParts::from_slice(jwk)?)
+    }
+
+    /// Import a key or keypair from a JWK
+    pub fn from_jwk(jwk: &str) -> Result<Self, Error> {
+        Self::from_jwk_parts(JwkParts::from_str(jwk)?)
+    }
+
+    fn from_jwk_parts(jwk: JwkParts<'_>) -> Result<Self, Error> {
+        // the permitted operations are taken from `key_ops` or `use`, if present
+        let key_ops = jwk.key_ops;
+        let inner = Box::<AnyKey>::from_jwk_parts(jwk)?;
         Ok(Self {
             inner,
             ephemeral: false,
+            key_ops,
         })
     }
 
-    /// Import a key or keypair from a JWK
-    pub fn from_jwk(jwk: &str) -> Result<Self, Error> {
-        let inner = Box::<AnyKey>::from_jwk(jwk)?;
+    /// Import a keypair from a PKCS#8 DER encoding
+    pub fn from_pkcs8_der(der: &[u8]) -> Result<Self, Error> {
+        let inner = Box::<AnyKey>::from_pkcs8_der(der)?;
+        Ok(Self {
+            inner,
+            ephemeral: false,
+            key_ops: None,
+        })
+    }
+
+    /// Import a keypair from a PKCS#8 PEM encoding
+    pub fn from_pkcs8_pem(pem: &str) -> Result<Self, Error> {
+        let inner = Box::<AnyKey>::from_pkcs8_pem(pem)?;
+        Ok(Self {
+            inner,
+            ephemeral: false,
+            key_ops: None,
+        })
+    }
+
+    /// Import a public key from a SubjectPublicKeyInfo DER encoding
+    pub fn from_spki_der(der: &[u8]) -> Result<Self, Error> {
+        let inner = Box::<AnyKey>::from_spki_der(der)?;
         Ok(Self {
             inner,
             ephemeral: false,
+            key_ops: None,
+        })
+    }
+
+    /// Import a public key from a SubjectPublicKeyInfo PEM encoding
+    pub fn from_spki_pem(pem: &str) -> Result<Self, Error> {
+        let inner = Box::<AnyKey>::from_spki_pem(pem)?;
+        Ok(Self {
+            inner,
+            ephemeral: false,
+            key_ops: None,
         })
     }
 
@@ -68,6 +157,7 @@ impl LocalKey {
         Ok(Self {
             inner,
             ephemeral: false,
+            key_ops: None,
         })
     }
 
@@ -82,6 +172,7 @@ impl LocalKey {
         Ok(Self {
             inner,
             ephemeral: false,
+            key_ops: None,
         })
     }
 
@@ -92,10 +183,12 @@ impl LocalKey {
 
     /// Derive a new key from a Diffie-Hellman exchange between this keypair and a public key
     pub fn to_key_exchange(&self, alg: KeyAlg, pk: &LocalKey) -> Result<Self, Error> {
+        self.check_op(KeyOps::DeriveKey)?;
         let inner = Box::<AnyKey>::from_key_exchange(alg, &*self.inner, &*pk.inner)?;
         Ok(Self {
             inner,
             ephemeral: self.ephemeral || pk.ephemeral,
+            key_ops: None,
         })
     }
 
@@ -107,6 +200,7 @@ impl LocalKey {
         Ok(Self {
             inner,
             ephemeral: false,
+            key_ops: None,
         })
     }
 
@@ -119,6 +213,29 @@ impl LocalKey {
         self.inner.algorithm()
     }
 
+    /// Accessor for the set of permitted key operations, if restricted
+    pub fn key_ops(&self) -> Option<KeyOpsSet> {
+        self.key_ops
+    }
+
+    /// Restrict the operations permitted for this key. When stored, the
+    /// operation set is persisted with the key and enforced after loading.
+    pub fn set_key_ops(&mut self, key_ops: Option<KeyOpsSet>) {
+        self.key_ops = key_ops;
+    }
+
+    /// Check that an operation is permitted for this key
+    pub fn check_op(&self, op: KeyOps) -> Result<(), Error> {
+        match self.key_ops {
+            Some(ops) if !ops.contains(op) => Err(err_msg!(
+                Input,
+                "Operation not permitted for this key: {}",
+                op
+            )),
+            _ => Ok(()),
+        }
+    }
+
     /// Get the public JWK representation for this key or keypair
     pub fn to_jwk_public(&self, alg: Option<KeyAlg>) -> Result<String, Error> {
         Ok(self.inner.to_jwk_public(alg)?)
@@ -129,6 +246,38 @@ impl LocalKey {
         Ok(self.inner.to_jwk_secret()?)
     }
 
+    /// Get the PKCS#8 DER encoding of this keypair
+    pub fn to_pkcs8_der(&self) -> Result<SecretBytes, Error> {
+        Ok(self.inner.to_pkcs8_der()?)
+    }
+
+    /// Get the PKCS#8 PEM encoding of this keypair
+    pub fn to_pkcs8_pem(&self) -> Result<SecretBytes, Error> {
+        Ok(self.inner.to_pkcs8_pem()?)
+    }
+
+    /// Get the SubjectPublicKeyInfo DER encoding of this public key
+    pub fn to_spki_der(&self) -> Result<Vec<u8>, Error> {
+        Ok(self.inner.to_spki_der()?)
+    }
+
+    /// Get the SubjectPublicKeyInfo PEM encoding of this public key
+    pub fn to_spki_pem(&self) -> Result<String, Error> {
+        Ok(self.inner.to_spki_pem()?)
+    }
+
+    /// Export this key in the given format
+    pub fn export(&self, format: KeyExportFormat) -> Result<SecretBytes, Error> {
+        match format {
+            KeyExportFormat::Jwk => self.to_jwk_secret(),
+            KeyExportFormat::JwkPublic => self.to_jwk_public(None).map(SecretBytes::from),
+            KeyExportFormat::Pkcs8Der => self.to_pkcs8_der(),
+            KeyExportFormat::Pkcs8Pem => self.to_pkcs8_pem(),
+            KeyExportFormat::SpkiDer => self.to_spki_der().map(SecretBytes::from),
+            KeyExportFormat::SpkiPem => self.to_spki_pem().map(SecretBytes::from),
+        }
+    }
+
     /// Get the JWK thumbprint for this key or keypair
     pub fn to_jwk_thumbprint(&self, alg: Option<KeyAlg>) -> Result<String, Error> {
         Ok(self.inner.to_jwk_thumbprint(alg)?)
@@ -154,6 +303,7 @@ impl LocalKey {
         Ok(Self {
             inner,
             ephemeral: self.ephemeral,
+            key_ops: self.key_ops,
         })
     }
 
@@ -193,6 +343,7 @@ impl LocalKey {
         nonce: &[u8],
         aad: &[u8],
     ) -> Result<Encrypted, Error> {
+        self.check_op(KeyOps::Encrypt)?;
         let params = self.inner.aead_params();
         let mut nonce = Cow::Borrowed(nonce);
         if nonce.is_empty() && params.nonce_length > 0 {
@@ -216,6 +367,7 @@ impl LocalKey {
         nonce: &[u8],
         aad: &[u8],
     ) -> Result<SecretBytes, Error> {
+        self.check_op(KeyOps::Decrypt)?;
         let mut buf = ciphertext.into().into_secret();
         self.inner.decrypt_in_place(&mut buf, nonce, aad)?;
         Ok(buf)
@@ -223,6 +375,7 @@ impl LocalKey {
 
     /// Sign a message with this private signing key
     pub fn sign_message(&self, message: &[u8], sig_type: Option<&str>) -> Result<Vec<u8>, Error> {
+        self.check_op(KeyOps::Sign)?;
         let mut sig = Vec::new();
         self.inner.write_signature(
             message,
@@ -239,6 +392,7 @@ impl LocalKey {
         signature: &[u8],
         sig_type: Option<&str>,
     ) -> Result<bool, Error> {
+        self.check_op(KeyOps::Verify)?;
         Ok(self.inner.verify_signature(
             message,
             signature,
@@ -248,6 +402,7 @@ impl LocalKey {
 
     /// Wrap another key using this key
     pub fn wrap_key(&self, key: &LocalKey, nonce: &[u8]) -> Result<Encrypted, Error> {
+        self.check_op(KeyOps::WrapKey)?;
         let params = self.inner.aead_params();
         let mut buf = SecretBytes::with_capacity(
             key.inner.secret_bytes_length()? + params.tag_length + params.nonce_length,
@@ -266,6 +421,7 @@ impl LocalKey {
         ciphertext: impl Into<ToDecrypt<'d>>,
         nonce: &[u8],
     ) -> Result<LocalKey, Error> {
+        self.check_op(KeyOps::UnwrapKey)?;
         let mut buf = ciphertext.into().into_secret();
         self.inner.decrypt_in_place(&mut buf, nonce, &[])?;
         Self::from_secret_bytes(alg, buf.as_ref())
@@ -278,6 +434,14 @@ impl KeyExchange for LocalKey {
         other: &LocalKey,
         out: &mut dyn WriteBuffer,
     ) -> Result<(), CryptoError> {
+        if let Some(ops) = self.key_ops {
+            if !ops.contains(KeyOps::DeriveKey) {
+                return Err(CryptoError::from_msg(
+                    CryptoErrorKind::Usage,
+                    "Key exchange not permitted for this key",
+                ));
+            }
+        }
         self.inner.write_key_exchange(&other.inner, out)
     }
 }
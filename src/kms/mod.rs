@@ -18,11 +18,17 @@ pub use self::envelope::{
     derive_key_ecdh_1pu, derive_key_ecdh_es,
 };
 
+pub mod did;
+
 mod entry;
-pub use self::entry::{KeyEntry, KeyParams};
+pub(crate) use self::entry::to_timestamp;
+pub use self::entry::{ExportedKey, KeyEntry, KeyParams};
 
 mod local_key;
-pub use self::local_key::{KeyAlg, LocalKey};
+pub use self::local_key::{KeyAlg, KeyExportFormat, LocalKey};
+
+mod provenance;
+pub use self::provenance::KeyProvenance;
 
 /// Supported categories of KMS entries
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Zeroize)]
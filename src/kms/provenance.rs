@@ -0,0 +1,115 @@
+use super::local_key::LocalKey;
+use crate::error::Error;
+
+/// Domain separation label for provenance signatures
+const PROVENANCE_LABEL: &str = "askar:provenance";
+
+/// Provenance metadata recording the origin of a stored key.
+///
+/// The record may be signed by an attestation key, binding it to the JWK
+/// thumbprint of the stored key so that it cannot be transferred to another
+/// key entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct KeyProvenance {
+    /// The device or host on which the key was generated
+    #[serde(default, rename = "dev", skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+
+    /// An attestation produced by the generating HSM or secure element
+    #[serde(
+        default,
+        rename = "att",
+        with = "serde_bytes",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub attestation: Option<Vec<u8>>,
+
+    /// The creation time of the key, in seconds since the Unix epoch
+    #[serde(default, rename = "created", skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<i64>,
+
+    /// The name and version of the software which generated the key
+    #[serde(default, rename = "gen", skip_serializing_if = "Option::is_none")]
+    pub generator: Option<String>,
+
+    /// The JWK thumbprint of the key which signed the record
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signer: Option<String>,
+
+    /// The signature over the record and the thumbprint of the stored key
+    #[serde(
+        default,
+        rename = "sig",
+        with = "serde_bytes",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub signature: Option<Vec<u8>>,
+}
+
+impl KeyProvenance {
+    /// Determine whether the record carries a signature
+    pub fn is_signed(&self) -> bool {
+        self.signature.is_some()
+    }
+
+    /// Sign the provenance record for a key using an attestation key
+    pub fn sign(&mut self, key: &LocalKey, signer: &LocalKey) -> Result<(), Error> {
+        self.signer = Some(signer.to_jwk_thumbprint(None)?);
+        self.signature = None;
+        let input = self.signing_input(key)?;
+        self.signature = Some(signer.sign_message(&input, None)?);
+        Ok(())
+    }
+
+    /// Verify the signature on the provenance record for a key. Returns
+    /// `false` if the record is unsigned or was signed by a different key.
+    pub fn verify(&self, key: &LocalKey, signer: &LocalKey) -> Result<bool, Error> {
+        let signature = match self.signature.as_ref() {
+            Some(sig) => sig,
+            None => return Ok(false),
+        };
+        if self.signer.as_deref() != Some(signer.to_jwk_thumbprint(None)?.as_str()) {
+            return Ok(false);
+        }
+        let mut unsigned = self.clone();
+        unsigned.signature = None;
+        let input = unsigned.signing_input(key)?;
+        signer.verify_signature(&input, signature, None)
+    }
+
+    fn signing_input(&self, key: &LocalKey) -> Result<Vec<u8>, Error> {
+        let thumbprint = key.to_jwk_thumbprint(None)?;
+        serde_cbor::to_vec(&(PROVENANCE_LABEL, thumbprint, self))
+            .map_err(err_map!(Unexpected, "Error serializing key provenance"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kms::KeyAlg;
+
+    #[test]
+    fn provenance_sign_verify() {
+        let key = LocalKey::generate(KeyAlg::Ed25519, false).unwrap();
+        let attest = LocalKey::generate(KeyAlg::Ed25519, false).unwrap();
+        let mut prov = KeyProvenance {
+            device: Some("device-1".to_string()),
+            attestation: Some(vec![1, 2, 3]),
+            created_at: Some(1000),
+            generator: Some("askar/test".to_string()),
+            ..Default::default()
+        };
+        assert!(!prov.verify(&key, &attest).unwrap());
+        prov.sign(&key, &attest).unwrap();
+        assert!(prov.is_signed());
+        assert!(prov.verify(&key, &attest).unwrap());
+
+        // the signature is bound to the stored key and the record contents
+        let other = LocalKey::generate(KeyAlg::Ed25519, false).unwrap();
+        assert!(!prov.verify(&other, &attest).unwrap());
+        assert!(!prov.verify(&key, &other).unwrap());
+        prov.device = Some("device-2".to_string());
+        assert!(!prov.verify(&key, &attest).unwrap());
+    }
+}
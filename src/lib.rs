@@ -5,7 +5,7 @@
 
 #[macro_use]
 mod error;
-pub use self::error::{Error, ErrorKind};
+pub use self::error::{Error, ErrorContext, ErrorKind};
 
 #[cfg(test)]
 #[macro_use]
@@ -22,7 +22,10 @@ extern crate log;
 extern crate serde;
 
 pub mod backend;
-pub use self::backend::{Backend, ManageBackend};
+pub use self::backend::{
+    AccessMode, Backend, Durability, InvalidationHook, MaintenanceMode, ManageBackend,
+    RecordInvalidation,
+};
 
 #[cfg(feature = "any")]
 pub use self::backend::any;
@@ -45,10 +48,46 @@ extern crate serde_json;
 #[cfg(feature = "ffi")]
 mod ffi;
 
+#[cfg(feature = "fuzz")]
+#[cfg_attr(docsrs, doc(cfg(feature = "fuzz")))]
+pub mod fuzz;
+
 pub mod kms;
 
 mod protect;
-pub use protect::{generate_raw_store_key, PassKey, StoreKeyMethod};
+pub use protect::{
+    generate_raw_store_key, migrate_raw_store_key, CachedProfileKey, IdStrategy, KeyCacheStats,
+    MemoryKeyCache, NonceStrategy, PassKey, PassKeyCheck, PassKeyPolicy, ProfileId,
+    ProfileKeyCache, SecretKind, SecretResolver, StoreKeyFormat, StoreKeyMethod, TenantKeyProvider,
+    UnlockProvider, UnlockReason,
+};
+
+#[cfg(feature = "keychain")]
+#[cfg_attr(docsrs, doc(cfg(feature = "keychain")))]
+pub use protect::keychain;
 
 mod storage;
-pub use storage::{Entry, EntryTag, Scan, Store, TagFilter};
+pub use storage::{
+    binary_identifier, verify_backup, BackupDestination, BackupHandle, BackupPolicy, BackupRun,
+    BackupSource, BackupSummary, CategoryUsage, Entry, EntryExpiry, EntryHash, EntryId, EntryKind,
+    EntryLink, EntryOperation, EntrySeq, EntryTag, EntryVersion, EntryWrite, ExportFilter,
+    FileBackupDestination, HistoryFilter, IntegrityIssue, IntegrityIssueKind, IntegrityReport,
+    KeyExport, Lease, OutboxMessage, PlaintextExport, ProfileNameFormat, ProfileNaming,
+    ProfileShredding, ProfileUsage, Projection, QueryLimits, Scan, ScanCheckpoint, SessionStats,
+    StorageReport, Store, StoreCapabilities, StorePolicy, StoreTuning, TagFilter, TagKind,
+    UniqueConstraint, MAX_PROFILE_NAME_LEN, RESERVED_CATEGORY_PREFIX,
+};
+
+pub use storage::sync;
+
+#[cfg(feature = "raw_query")]
+#[cfg_attr(docsrs, doc(cfg(feature = "raw_query")))]
+pub use storage::{RawDecryptor, RawRow, RawValue};
+
+#[cfg(feature = "any")]
+#[cfg_attr(docsrs, doc(cfg(feature = "any")))]
+pub use storage::{StoreConfig, StoreManager, TenantConfig};
+
+#[cfg(feature = "test_utils")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test_utils")))]
+pub mod test_utils;
@@ -14,7 +14,8 @@ use crate::{
         self,
         buffer::ArrayKey,
         generic_array::{typenum::Unsigned, ArrayLength, GenericArray},
-        kdf::KeyDerivation,
+        kdf::{FromKeyDerivation, KeyDerivation},
+        random::KeyMaterial,
         repr::KeyGen,
     },
     error::Error,
@@ -70,8 +71,17 @@ impl<H, L: ArrayLength<u8>> PartialEq for HmacKey<H, L> {
 impl<H, L: ArrayLength<u8>> Eq for HmacKey<H, L> {}
 
 impl<H, L: ArrayLength<u8>> KeyGen for HmacKey<H, L> {
-    fn generate() -> Result<Self, crate::crypto::Error> {
-        Ok(Self(ArrayKey::random(), PhantomData))
+    fn generate_with_rng(rng: impl KeyMaterial) -> Result<Self, crate::crypto::Error> {
+        Ok(Self(ArrayKey::generate(rng), PhantomData))
+    }
+}
+
+impl<H, L: ArrayLength<u8>> FromKeyDerivation for HmacKey<H, L> {
+    fn from_key_derivation<D: KeyDerivation>(derive: D) -> Result<Self, crypto::Error>
+    where
+        Self: Sized,
+    {
+        Ok(Self(ArrayKey::from_key_derivation(derive)?, PhantomData))
     }
 }
 
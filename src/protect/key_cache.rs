@@ -0,0 +1,291 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+
+#[cfg(feature = "memsec")]
+use super::memsec::SealingKey;
+use super::{ProfileId, ProfileKey};
+use crate::{crypto::buffer::SecretBytes, error::Error};
+
+/// A decrypted profile key held by a profile key cache
+#[derive(Clone, Debug)]
+pub struct CachedProfileKey(pub(crate) Arc<ProfileKey>);
+
+impl CachedProfileKey {
+    /// Serialize the key, for caches which share keys between processes
+    pub fn to_bytes(&self) -> Result<SecretBytes, Error> {
+        self.0.to_bytes()
+    }
+
+    /// Load a key serialized by `to_bytes`
+    pub fn from_bytes(key: &[u8]) -> Result<Self, Error> {
+        Ok(Self(Arc::new(ProfileKey::from_slice(key)?)))
+    }
+}
+
+/// A cache of decrypted profile keys, shared by the sessions of a store.
+///
+/// The methods are called from async tasks and must not block for extended
+/// periods. A custom implementation may share keys between processes, in
+/// which case it is responsible for protecting the keys at rest.
+pub trait ProfileKeyCache: Debug + Send + Sync {
+    /// Look up the identifier and key of a profile
+    fn get(&self, profile: &str) -> Option<(ProfileId, CachedProfileKey)>;
+
+    /// Add or replace the key of a profile, returning the number of other
+    /// keys evicted to make room for it
+    fn insert(&self, profile: String, id: ProfileId, key: CachedProfileKey) -> usize;
+
+    /// Remove the key of a profile, returning `true` if it was cached
+    fn remove(&self, profile: &str) -> bool;
+
+    /// Remove all cached keys, returning the number removed
+    fn clear(&self) -> usize;
+
+    /// Change the maximum number of cached keys, or remove the limit when
+    /// `capacity` is `None`. Returns the number of keys evicted to fit the
+    /// new capacity. Caches which cannot be resized return an `Unsupported`
+    /// error
+    fn resize(&self, _capacity: Option<usize>) -> Result<usize, Error> {
+        Err(err_msg!(
+            Unsupported,
+            "The profile key cache does not support resizing"
+        ))
+    }
+}
+
+#[derive(Clone, Debug)]
+enum CacheEntry {
+    Plain(CachedProfileKey),
+    #[cfg(feature = "memsec")]
+    Sealed(Vec<u8>),
+}
+
+#[derive(Debug, Default)]
+struct MemoryKeyCacheInner {
+    keys: HashMap<String, (ProfileId, CacheEntry)>,
+    order: VecDeque<String>,
+    capacity: Option<usize>,
+}
+
+impl MemoryKeyCacheInner {
+    /// Evict the earliest inserted keys until the cache is within capacity
+    fn evict(&mut self) -> usize {
+        let mut evicted = 0;
+        if let Some(capacity) = self.capacity {
+            while self.keys.len() > capacity {
+                if let Some(name) = self.order.pop_front() {
+                    self.keys.remove(&name);
+                    evicted += 1;
+                }
+            }
+        }
+        evicted
+    }
+}
+
+/// The default in-memory profile key cache
+#[derive(Debug, Default)]
+pub struct MemoryKeyCache {
+    inner: RwLock<MemoryKeyCacheInner>,
+    #[cfg(feature = "memsec")]
+    sealing_key: Option<SealingKey>,
+}
+
+impl MemoryKeyCache {
+    /// Create a new cache without a limit on the number of keys
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new cache holding at most `capacity` keys. The earliest
+    /// inserted key is evicted when the cache is full
+    pub fn with_capacity(capacity: usize) -> Self {
+        let cache = Self::default();
+        cache.inner.write().unwrap().capacity = Some(capacity.max(1));
+        cache
+    }
+
+    /// Keep the cached keys encrypted using a random key held in protected
+    /// memory. Each key is decrypted when it is retrieved from the cache,
+    /// so that only the keys in active use are held in plaintext
+    #[cfg(feature = "memsec")]
+    pub fn with_sealing(mut self) -> Result<Self, Error> {
+        self.sealing_key = Some(SealingKey::random()?);
+        Ok(self)
+    }
+
+    fn seal(&self, key: CachedProfileKey) -> Result<CacheEntry, Error> {
+        #[cfg(feature = "memsec")]
+        {
+            if let Some(sealing_key) = self.sealing_key.as_ref() {
+                return Ok(CacheEntry::Sealed(sealing_key.seal(key.to_bytes()?)?));
+            }
+        }
+        Ok(CacheEntry::Plain(key))
+    }
+
+    fn open(&self, entry: CacheEntry) -> Result<CachedProfileKey, Error> {
+        match entry {
+            CacheEntry::Plain(key) => Ok(key),
+            #[cfg(feature = "memsec")]
+            CacheEntry::Sealed(sealed) => {
+                let sealing_key = self
+                    .sealing_key
+                    .as_ref()
+                    .ok_or_else(|| err_msg!(Unexpected, "Missing cache sealing key"))?;
+                CachedProfileKey::from_bytes(sealing_key.open(&sealed)?.as_ref())
+            }
+        }
+    }
+}
+
+impl ProfileKeyCache for MemoryKeyCache {
+    fn get(&self, profile: &str) -> Option<(ProfileId, CachedProfileKey)> {
+        let (id, entry) = self.inner.read().unwrap().keys.get(profile).cloned()?;
+        match self.open(entry) {
+            Ok(key) => Some((id, key)),
+            Err(err) => {
+                warn!("Error opening cached profile key: {}", err);
+                None
+            }
+        }
+    }
+
+    fn insert(&self, profile: String, id: ProfileId, key: CachedProfileKey) -> usize {
+        let entry = match self.seal(key) {
+            Ok(entry) => entry,
+            Err(err) => {
+                // the key is reloaded from the store when it is next used
+                warn!("Error sealing cached profile key: {}", err);
+                return 0;
+            }
+        };
+        let mut inner = self.inner.write().unwrap();
+        if inner.keys.insert(profile.clone(), (id, entry)).is_some() {
+            return 0;
+        }
+        inner.order.push_back(profile);
+        inner.evict()
+    }
+
+    fn remove(&self, profile: &str) -> bool {
+        let mut inner = self.inner.write().unwrap();
+        if inner.keys.remove(profile).is_some() {
+            inner.order.retain(|name| name != profile);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn clear(&self) -> usize {
+        let mut inner = self.inner.write().unwrap();
+        inner.order.clear();
+        inner.keys.drain().count()
+    }
+
+    fn resize(&self, capacity: Option<usize>) -> Result<usize, Error> {
+        let mut inner = self.inner.write().unwrap();
+        inner.capacity = capacity.map(|c| c.max(1));
+        Ok(inner.evict())
+    }
+}
+
+/// Counters describing the use of the profile key cache of a store
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyCacheStats {
+    /// The number of profile keys found in the cache
+    pub hits: u64,
+    /// The number of profile keys which were not cached, and were loaded
+    /// from the store
+    pub misses: u64,
+    /// The number of profile keys removed from the cache, either to make
+    /// room for another key or because the profile was removed
+    pub evictions: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct KeyCacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl KeyCacheCounters {
+    pub fn record_lookup(&self, hit: bool) {
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_evictions(&self, count: usize) {
+        if count > 0 {
+            self.evictions.fetch_add(count as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> KeyCacheStats {
+        KeyCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_cache_capacity() {
+        let cache = MemoryKeyCache::with_capacity(2);
+        let key = CachedProfileKey(Arc::new(ProfileKey::new().unwrap()));
+        assert_eq!(cache.insert("a".to_string(), 1.into(), key.clone()), 0);
+        assert_eq!(cache.insert("b".to_string(), 2.into(), key.clone()), 0);
+        // replacing a key does not evict
+        assert_eq!(cache.insert("a".to_string(), 1.into(), key.clone()), 0);
+        assert_eq!(cache.insert("c".to_string(), 3.into(), key.clone()), 1);
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.get("b").map(|(id, _)| id), Some(2.into()));
+        assert!(cache.remove("b"));
+        assert!(!cache.remove("b"));
+        assert_eq!(cache.clear(), 1);
+        assert!(cache.get("c").is_none());
+
+        // shrinking the cache evicts the earliest inserted keys
+        cache.insert("d".to_string(), 4.into(), key.clone());
+        cache.insert("e".to_string(), 5.into(), key.clone());
+        assert_eq!(cache.resize(Some(1)).unwrap(), 1);
+        assert!(cache.get("d").is_none());
+        assert_eq!(cache.resize(None).unwrap(), 0);
+        assert_eq!(cache.insert("f".to_string(), 6.into(), key.clone()), 0);
+
+        let bytes = key.to_bytes().unwrap();
+        let loaded = CachedProfileKey::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.to_bytes().unwrap(), bytes);
+    }
+
+    #[cfg(feature = "memsec")]
+    #[test]
+    fn memory_cache_sealed() {
+        let cache = MemoryKeyCache::new().with_sealing().unwrap();
+        let key = CachedProfileKey(Arc::new(ProfileKey::new().unwrap()));
+        cache.insert("a".to_string(), 1.into(), key.clone());
+        match cache.inner.read().unwrap().keys.get("a") {
+            Some((_, CacheEntry::Sealed(_))) => (),
+            other => panic!("Expected sealed entry: {:?}", other),
+        }
+        let (id, found) = cache.get("a").unwrap();
+        assert_eq!(id, 1.into());
+        assert_eq!(found.0, key.0);
+    }
+}
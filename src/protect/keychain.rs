@@ -0,0 +1,237 @@
+//! Resolution of pass keys from platform secure storage.
+//!
+//! On macOS the pass key is kept in the Keychain, on Windows in the
+//! Credential Manager, and on Linux in a secret service such as GNOME Keyring.
+//! Platforms without native support, such as Android, must register a
+//! [`KeystoreHook`] which accesses the platform keystore on behalf of the
+//! store.
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::{Arc, RwLock},
+};
+
+use once_cell::sync::Lazy;
+
+use super::{PassKey, SecretKind, SecretResolver};
+use crate::{
+    error::Error,
+    future::{unblock, BoxFuture},
+};
+
+static KEYSTORE_HOOK: Lazy<RwLock<Option<Arc<dyn KeystoreHook>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Access to a platform keystore which is not supported natively.
+///
+/// The methods may block while the user is prompted, and are always called
+/// from a background thread
+pub trait KeystoreHook: Send + Sync {
+    /// Load the secret stored for a service and account, if any
+    fn load(&self, service: &str, account: &str) -> Result<Option<PassKey<'static>>, Error>;
+
+    /// Store a secret for a service and account, replacing any existing value
+    fn store(&self, _service: &str, _account: &str, _secret: &str) -> Result<(), Error> {
+        Err(err_msg!(
+            Unsupported,
+            "Storing secrets is not supported by the keystore"
+        ))
+    }
+
+    /// Remove the secret stored for a service and account, returning `false`
+    /// if it was not found
+    fn remove(&self, _service: &str, _account: &str) -> Result<bool, Error> {
+        Err(err_msg!(
+            Unsupported,
+            "Removing secrets is not supported by the keystore"
+        ))
+    }
+}
+
+/// Register a hook used to access the platform keystore in place of the
+/// native support, or remove it when `None` is provided
+pub fn set_keystore_hook(hook: Option<Arc<dyn KeystoreHook>>) {
+    *KEYSTORE_HOOK.write().unwrap() = hook;
+}
+
+fn keystore_hook() -> Option<Arc<dyn KeystoreHook>> {
+    KEYSTORE_HOOK.read().unwrap().clone()
+}
+
+/// A pass key held in platform secure storage, identified by a service name
+/// and an account name.
+///
+/// A `KeychainEntry` may be provided as the `SecretResolver` of the store
+/// options, so that the pass key is loaded from the keystore whenever the
+/// store is opened or provisioned without an explicit pass key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct KeychainEntry {
+    service: String,
+    account: String,
+}
+
+impl KeychainEntry {
+    /// Create a reference to a keychain entry
+    pub fn new(service: impl Into<String>, account: impl Into<String>) -> Self {
+        Self {
+            service: service.into(),
+            account: account.into(),
+        }
+    }
+
+    /// Load the pass key from the keychain. This method may block while the
+    /// user is prompted for access
+    pub fn load(&self) -> Result<Option<PassKey<'static>>, Error> {
+        if let Some(hook) = keystore_hook() {
+            hook.load(&self.service, &self.account)
+        } else {
+            native::load(&self.service, &self.account)
+        }
+    }
+
+    /// Save a pass key to the keychain, replacing any existing value
+    pub fn store(&self, pass_key: &PassKey<'_>) -> Result<(), Error> {
+        if let Some(hook) = keystore_hook() {
+            hook.store(&self.service, &self.account, pass_key)
+        } else {
+            native::store(&self.service, &self.account, pass_key)
+        }
+    }
+
+    /// Remove the pass key from the keychain, returning `false` if it was
+    /// not found
+    pub fn remove(&self) -> Result<bool, Error> {
+        if let Some(hook) = keystore_hook() {
+            hook.remove(&self.service, &self.account)
+        } else {
+            native::remove(&self.service, &self.account)
+        }
+    }
+}
+
+impl Debug for KeychainEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeychainEntry")
+            .field("service", &self.service)
+            .field("account", &self.account)
+            .finish()
+    }
+}
+
+impl SecretResolver for KeychainEntry {
+    fn resolve(&self, kind: SecretKind) -> BoxFuture<'_, Result<PassKey<'static>, Error>> {
+        let entry = self.clone();
+        Box::pin(async move {
+            match kind {
+                SecretKind::PassKey => Ok(unblock(move || entry.load())
+                    .await?
+                    .unwrap_or_else(PassKey::empty)),
+                _ => Ok(PassKey::empty()),
+            }
+        })
+    }
+}
+
+#[cfg(not(target_os = "android"))]
+mod native {
+    use keyring::{Keyring, KeyringError};
+
+    use super::PassKey;
+    use crate::error::Error;
+
+    pub fn load(service: &str, account: &str) -> Result<Option<PassKey<'static>>, Error> {
+        match Keyring::new(service, account).get_password() {
+            Ok(secret) => Ok(Some(PassKey::from(secret))),
+            Err(KeyringError::NoPasswordFound) => Ok(None),
+            Err(err) => Err(err_msg!(Backend, "Error loading keychain entry").with_cause(err)),
+        }
+    }
+
+    pub fn store(service: &str, account: &str, secret: &str) -> Result<(), Error> {
+        Keyring::new(service, account)
+            .set_password(secret)
+            .map_err(err_map!(Backend, "Error storing keychain entry"))
+    }
+
+    pub fn remove(service: &str, account: &str) -> Result<bool, Error> {
+        match Keyring::new(service, account).delete_password() {
+            Ok(()) => Ok(true),
+            Err(KeyringError::NoPasswordFound) => Ok(false),
+            Err(err) => Err(err_msg!(Backend, "Error removing keychain entry").with_cause(err)),
+        }
+    }
+}
+
+#[cfg(target_os = "android")]
+mod native {
+    use super::PassKey;
+    use crate::error::Error;
+
+    fn no_keystore() -> Error {
+        err_msg!(
+            Unsupported,
+            "No keystore hook has been registered for this platform"
+        )
+    }
+
+    pub fn load(_service: &str, _account: &str) -> Result<Option<PassKey<'static>>, Error> {
+        Err(no_keystore())
+    }
+
+    pub fn store(_service: &str, _account: &str, _secret: &str) -> Result<(), Error> {
+        Err(no_keystore())
+    }
+
+    pub fn remove(_service: &str, _account: &str) -> Result<bool, Error> {
+        Err(no_keystore())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use super::*;
+    use crate::future::block_on;
+
+    #[derive(Default)]
+    struct MemoryKeystore(Mutex<HashMap<(String, String), String>>);
+
+    impl KeystoreHook for MemoryKeystore {
+        fn load(&self, service: &str, account: &str) -> Result<Option<PassKey<'static>>, Error> {
+            Ok(self
+                .0
+                .lock()
+                .unwrap()
+                .get(&(service.to_string(), account.to_string()))
+                .map(|secret| PassKey::from(secret.clone())))
+        }
+
+        fn store(&self, service: &str, account: &str, secret: &str) -> Result<(), Error> {
+            self.0.lock().unwrap().insert(
+                (service.to_string(), account.to_string()),
+                secret.to_string(),
+            );
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn keystore_hook_resolver() {
+        set_keystore_hook(Some(Arc::new(MemoryKeystore::default())));
+        let entry = KeychainEntry::new("askar-test", "wallet");
+        assert!(entry.load().unwrap().is_none());
+        entry.store(&PassKey::from("secret")).unwrap();
+        let resolver: &dyn SecretResolver = &entry;
+        block_on(async {
+            let pass_key = resolver.resolve(SecretKind::PassKey).await.unwrap();
+            assert_eq!(&*pass_key, "secret");
+            let password = resolver
+                .resolve(SecretKind::DatabasePassword)
+                .await
+                .unwrap();
+            assert!(password.is_none());
+        });
+        assert!(entry.remove().is_err());
+        set_keystore_hook(None);
+    }
+}
@@ -0,0 +1,228 @@
+//! Protection of key material held in memory.
+//!
+//! When the `memsec` feature is enabled, the memory holding protected keys is
+//! locked to prevent it from being swapped to disk and, where supported,
+//! excluded from core dumps. Without the feature the wrapper is a plain heap
+//! allocation.
+
+use std::{
+    alloc::{self, Layout},
+    fmt::{self, Debug, Formatter},
+    ops::Deref,
+    ptr::{self, NonNull},
+};
+
+#[cfg(feature = "memsec")]
+use crate::{
+    crypto::{
+        alg::chacha20::{Chacha20Key, XC20P},
+        buffer::{ArrayKey, SecretBytes, WriteBuffer},
+        encrypt::{KeyAeadInPlace, KeyAeadMeta},
+        generic_array::typenum::Unsigned,
+        repr::KeyGen,
+    },
+    error::Error,
+};
+
+/// A heap-allocated value whose memory is protected for its lifetime.
+///
+/// When memory locking is enabled, each value is given its own page-aligned
+/// allocation spanning whole pages, so that unlocking the memory of one value
+/// never releases the lock held by another.
+pub(crate) struct Protected<T> {
+    value: NonNull<T>,
+    layout: Layout,
+}
+
+// the allocation is uniquely owned, in the same way as a `Box<T>`
+unsafe impl<T: Send> Send for Protected<T> {}
+unsafe impl<T: Sync> Sync for Protected<T> {}
+
+impl<T> Protected<T> {
+    pub fn new(value: T) -> Self {
+        let layout = region_layout::<T>();
+        let ptr = if layout.size() == 0 {
+            NonNull::dangling()
+        } else {
+            let ptr = unsafe { alloc::alloc(layout) } as *mut T;
+            NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout))
+        };
+        unsafe { ptr.as_ptr().write(value) };
+        let result = Self { value: ptr, layout };
+        lock_region(result.as_ptr(), layout.size());
+        result
+    }
+
+    fn as_ptr(&self) -> *const u8 {
+        self.value.as_ptr() as *const u8
+    }
+}
+
+impl<T> Deref for Protected<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.value.as_ref() }
+    }
+}
+
+impl<T> Drop for Protected<T> {
+    fn drop(&mut self) {
+        // the value is dropped (and zeroized) before the memory is unlocked
+        unsafe { ptr::drop_in_place(self.value.as_ptr()) };
+        unlock_region(self.as_ptr(), self.layout.size());
+        if self.layout.size() != 0 {
+            unsafe { alloc::dealloc(self.value.as_ptr() as *mut u8, self.layout) };
+        }
+    }
+}
+
+impl<T: Debug> Debug for Protected<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Protected").field(&**self).finish()
+    }
+}
+
+#[cfg(all(feature = "memsec", unix))]
+fn page_size() -> usize {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    }
+}
+
+/// The layout of the allocation holding a protected value, which is rounded
+/// up to whole pages so that it is not shared with any other allocation
+#[cfg(all(feature = "memsec", unix))]
+fn region_layout<T>() -> Layout {
+    let layout = Layout::new::<T>();
+    if layout.size() == 0 {
+        return layout;
+    }
+    let page = page_size();
+    let size = (layout.size() + page - 1) & !(page - 1);
+    Layout::from_size_align(size, page.max(layout.align())).expect("Invalid protected layout")
+}
+
+#[cfg(not(all(feature = "memsec", unix)))]
+#[inline]
+fn region_layout<T>() -> Layout {
+    Layout::new::<T>()
+}
+
+#[cfg(all(feature = "memsec", unix))]
+fn lock_region(ptr: *const u8, len: usize) {
+    if len == 0 {
+        return;
+    }
+    let start = ptr as *mut libc::c_void;
+    if unsafe { libc::mlock(start, len) } != 0 {
+        // commonly caused by a low RLIMIT_MEMLOCK, the key remains usable
+        debug!("Unable to lock protected memory");
+    }
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe {
+        libc::madvise(start, len, libc::MADV_DONTDUMP);
+    }
+}
+
+#[cfg(all(feature = "memsec", unix))]
+fn unlock_region(ptr: *const u8, len: usize) {
+    if len == 0 {
+        return;
+    }
+    // the pages are not shared with any other value, and are restored to
+    // their defaults before being returned to the allocator
+    let start = ptr as *mut libc::c_void;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe {
+        libc::madvise(start, len, libc::MADV_DODUMP);
+    }
+    unsafe {
+        libc::munlock(start, len);
+    }
+}
+
+#[cfg(not(all(feature = "memsec", unix)))]
+#[inline]
+fn lock_region(_ptr: *const u8, _len: usize) {}
+
+#[cfg(not(all(feature = "memsec", unix)))]
+#[inline]
+fn unlock_region(_ptr: *const u8, _len: usize) {}
+
+#[cfg(feature = "memsec")]
+type SealKey = Chacha20Key<XC20P>;
+
+/// A random key generated for the lifetime of a process, used to keep
+/// cached key material encrypted while it is not in use
+#[cfg(feature = "memsec")]
+#[derive(Debug)]
+pub(crate) struct SealingKey(Protected<SealKey>);
+
+#[cfg(feature = "memsec")]
+impl SealingKey {
+    pub fn random() -> Result<Self, Error> {
+        Ok(Self(Protected::new(SealKey::generate()?)))
+    }
+
+    /// Encrypt a buffer, returning the nonce followed by the ciphertext
+    pub fn seal(&self, mut buffer: SecretBytes) -> Result<Vec<u8>, Error> {
+        let nonce = ArrayKey::<<SealKey as KeyAeadMeta>::NonceSize>::random();
+        self.0.encrypt_in_place(&mut buffer, nonce.as_ref(), &[])?;
+        buffer.buffer_insert(0, nonce.as_ref())?;
+        Ok(buffer.into_vec())
+    }
+
+    /// Decrypt a buffer produced by `seal`
+    pub fn open(&self, sealed: &[u8]) -> Result<SecretBytes, Error> {
+        let nonce_len = <SealKey as KeyAeadMeta>::NonceSize::USIZE;
+        if sealed.len() < nonce_len {
+            return Err(err_msg!(Encryption, "Invalid sealed key"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(nonce_len);
+        let mut buffer = SecretBytes::from(ciphertext);
+        self.0.decrypt_in_place(&mut buffer, nonce, &[])?;
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protected_value() {
+        let value = Protected::new([1u8; 64]);
+        assert_eq!(*value, [1u8; 64]);
+        drop(value);
+        // zero-sized values are not locked
+        let _ = Protected::new(());
+    }
+
+    #[cfg(all(feature = "memsec", unix))]
+    #[test]
+    fn protected_pages_not_shared() {
+        let page = page_size();
+        let first = Protected::new([1u8; 32]);
+        let second = Protected::new([2u8; 32]);
+        for value in [&first, &second].iter() {
+            assert_eq!(value.as_ptr() as usize % page, 0);
+            assert_eq!(value.layout.size() % page, 0);
+        }
+        // releasing one value leaves the other intact
+        drop(first);
+        assert_eq!(*second, [2u8; 32]);
+    }
+
+    #[cfg(feature = "memsec")]
+    #[test]
+    fn seal_open() {
+        let key = SealingKey::random().unwrap();
+        let sealed = key.seal(SecretBytes::from(&b"profile key"[..])).unwrap();
+        assert_ne!(&sealed[..], &b"profile key"[..]);
+        assert_eq!(key.open(&sealed).unwrap().as_ref(), b"profile key");
+        let other = SealingKey::random().unwrap();
+        assert!(other.open(&sealed).is_err());
+    }
+}
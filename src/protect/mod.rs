@@ -1,66 +1,300 @@
-use std::{collections::HashMap, sync::Arc};
-
-use async_lock::RwLock;
+use std::sync::Arc;
 
 pub mod kdf;
 
 mod hmac_key;
 
+mod key_cache;
+use self::key_cache::KeyCacheCounters;
+pub use self::key_cache::{CachedProfileKey, KeyCacheStats, MemoryKeyCache, ProfileKeyCache};
+
+#[cfg(feature = "keychain")]
+pub mod keychain;
+
+mod memsec;
+pub(crate) use self::memsec::Protected;
+
 mod pass_key;
 pub use self::pass_key::PassKey;
 
+mod pass_key_policy;
+pub(crate) use self::pass_key_policy::check_pass_key;
+pub use self::pass_key_policy::{PassKeyCheck, PassKeyPolicy};
+
 mod profile_key;
 pub use self::profile_key::ProfileKey;
 
+mod secret;
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+pub(crate) use self::secret::resolve_pass_key;
+pub use self::secret::{SecretKind, SecretResolver};
+
 mod store_key;
-pub use self::store_key::{generate_raw_store_key, StoreKey, StoreKeyMethod, StoreKeyReference};
+pub use self::store_key::{
+    generate_raw_store_key, migrate_raw_store_key, StoreKey, StoreKeyFormat, StoreKeyMethod,
+    StoreKeyReference,
+};
+
+mod unlock;
+pub(crate) use self::unlock::request_unlock;
+pub use self::unlock::{UnlockProvider, UnlockReason};
+
+mod tenant_key;
+pub use self::tenant_key::TenantKeyProvider;
+pub(crate) use self::tenant_key::TENANT_KEY_REFERENCE;
 
 use crate::{
     crypto::buffer::SecretBytes,
     error::Error,
     future::unblock,
-    storage::{EncEntryTag, EntryTag},
+    storage::{binary_identifier_input, binary_identifier_output, EncEntryTag, EntryTag},
 };
 
-pub type ProfileId = i64;
+/// An identifier for a profile or item row
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProfileId {
+    /// A serial integer identifier assigned by the database
+    Serial(i64),
+    /// A randomly generated UUID
+    Uuid([u8; 16]),
+}
+
+impl From<i64> for ProfileId {
+    fn from(id: i64) -> Self {
+        Self::Serial(id)
+    }
+}
+
+/// The strategy used when generating profile and item identifiers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdStrategy {
+    /// Serial integer identifiers
+    Serial,
+    /// Random UUID identifiers, which may be generated without coordination
+    Uuid,
+}
+
+impl IdStrategy {
+    /// Get the string representation of the strategy
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Serial => "serial",
+            Self::Uuid => "uuid",
+        }
+    }
+
+    pub(crate) fn parse(value: &str) -> Result<Self, Error> {
+        match value {
+            "serial" => Ok(Self::Serial),
+            "uuid" => Ok(Self::Uuid),
+            _ => Err(err_msg!(Input, "Unknown id strategy: {}", value)),
+        }
+    }
+}
+
+impl Default for IdStrategy {
+    fn default() -> Self {
+        Self::Serial
+    }
+}
+
+/// The nonce strategy used when encrypting entries for new profiles.
+///
+/// Searchable fields (categories, names and tags) always use nonces derived
+/// from the plaintext by HMAC, while entry values use random nonces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NonceStrategy {
+    /// ChaCha20-Poly1305 with random 96-bit nonces
+    Standard,
+    /// XChaCha20-Poly1305 with random 192-bit nonces, making the chance
+    /// of a random nonce collision negligible
+    Extended,
+}
+
+impl NonceStrategy {
+    /// Get the string representation of the strategy
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::Extended => "extended",
+        }
+    }
+
+    pub(crate) fn parse(value: &str) -> Result<Self, Error> {
+        match value {
+            "standard" => Ok(Self::Standard),
+            "extended" => Ok(Self::Extended),
+            _ => Err(err_msg!(Input, "Unknown nonce strategy: {}", value)),
+        }
+    }
+}
+
+impl Default for NonceStrategy {
+    fn default() -> Self {
+        Self::Standard
+    }
+}
 
 #[derive(Debug)]
 pub struct KeyCache {
-    profile_info: RwLock<HashMap<String, (ProfileId, Arc<ProfileKey>)>>,
-    pub(crate) store_key: Arc<StoreKey>,
+    profiles: Arc<dyn ProfileKeyCache>,
+    counters: Arc<KeyCacheCounters>,
+    pub(crate) store_key: Arc<Protected<StoreKey>>,
+    pub(crate) tenant_keys: Option<Arc<dyn TenantKeyProvider>>,
 }
 
 impl KeyCache {
-    pub fn new(store_key: impl Into<Arc<StoreKey>>) -> Self {
+    pub fn new(store_key: StoreKey) -> Self {
+        Self::with_protected_key(Arc::new(Protected::new(store_key)))
+    }
+
+    fn with_protected_key(store_key: Arc<Protected<StoreKey>>) -> Self {
+        Self {
+            profiles: Arc::new(MemoryKeyCache::new()),
+            counters: Arc::default(),
+            store_key,
+            tenant_keys: None,
+        }
+    }
+
+    pub fn with_tenant_keys(mut self, tenant_keys: Option<Arc<dyn TenantKeyProvider>>) -> Self {
+        self.tenant_keys = tenant_keys;
+        self
+    }
+
+    /// Use a custom cache for the loaded profile keys
+    pub fn with_profile_cache(mut self, profiles: Option<Arc<dyn ProfileKeyCache>>) -> Self {
+        if let Some(profiles) = profiles {
+            self.profiles = profiles;
+        }
+        self
+    }
+
+    /// Create a cache using a new store key and tenant key provider. The
+    /// profile cache and its statistics are retained, but the cached keys
+    /// are discarded and reloaded as needed
+    pub fn replace_keys(
+        &self,
+        store_key: Arc<Protected<StoreKey>>,
+        tenant_keys: Option<Arc<dyn TenantKeyProvider>>,
+    ) -> Self {
+        self.counters.record_evictions(self.profiles.clear());
         Self {
-            profile_info: RwLock::new(HashMap::new()),
-            store_key: store_key.into(),
+            profiles: self.profiles.clone(),
+            counters: self.counters.clone(),
+            store_key,
+            tenant_keys,
         }
     }
 
-    pub async fn load_key(&self, ciphertext: Vec<u8>) -> Result<ProfileKey, Error> {
+    /// Get the hit and miss counters of the profile cache
+    pub fn stats(&self) -> KeyCacheStats {
+        self.counters.stats()
+    }
+
+    /// Change the maximum number of cached profile keys
+    pub fn resize(&self, capacity: Option<usize>) -> Result<(), Error> {
+        let evicted = self.profiles.resize(capacity)?;
+        self.counters.record_evictions(evicted);
+        Ok(())
+    }
+
+    /// Decrypt a profile key, using the tenant key for the profile when the
+    /// profile reference indicates that one was used to wrap it
+    pub async fn load_key(
+        &self,
+        profile: String,
+        reference: Option<String>,
+        ciphertext: Vec<u8>,
+    ) -> Result<ProfileKey, Error> {
         let store_key = self.store_key.clone();
+        let tenant_keys = self.tenant_keys.clone();
         unblock(move || {
-            let data = store_key
-                .unwrap_data(ciphertext)
-                .map_err(err_map!(Encryption, "Error decrypting profile key"))?;
+            let data = if reference.as_deref() == Some(TENANT_KEY_REFERENCE) {
+                let tenant_key = match tenant_keys {
+                    Some(provider) => tenant_key::resolve_tenant_key(&*provider, &profile)?,
+                    None => None,
+                }
+                .ok_or_else(|| err_msg!(Encryption, "No tenant key provided for profile"))?;
+                tenant_key.unwrap_data(ciphertext)
+            } else {
+                store_key.unwrap_data(ciphertext)
+            }
+            .map_err(err_map!(Encryption, "Error decrypting profile key"))?;
             Ok(ProfileKey::from_slice(data.as_ref())?)
         })
         .await
     }
 
+    /// Encrypt a new profile key, returning the profile reference to be stored
+    /// when it is wrapped by a tenant key
+    pub async fn wrap_key(
+        &self,
+        profile: String,
+        key: &ProfileKey,
+    ) -> Result<(Vec<u8>, Option<&'static str>), Error> {
+        let data = key.to_bytes()?;
+        let store_key = self.store_key.clone();
+        let tenant_keys = self.tenant_keys.clone();
+        unblock(move || {
+            let tenant_key = match tenant_keys {
+                Some(provider) => tenant_key::resolve_tenant_key(&*provider, &profile)?,
+                None => None,
+            };
+            if let Some(tenant_key) = tenant_key {
+                Ok((tenant_key.wrap_data(data)?, Some(TENANT_KEY_REFERENCE)))
+            } else {
+                Ok((store_key.wrap_data(data)?, None))
+            }
+        })
+        .await
+    }
+
     pub fn add_profile_mut(&mut self, ident: String, pid: ProfileId, key: ProfileKey) {
-        self.profile_info
-            .get_mut()
-            .insert(ident, (pid, Arc::new(key)));
+        let evicted = self
+            .profiles
+            .insert(ident, pid, CachedProfileKey(Arc::new(key)));
+        self.counters.record_evictions(evicted);
     }
 
     pub async fn add_profile(&self, ident: String, pid: ProfileId, key: Arc<ProfileKey>) {
-        self.profile_info.write().await.insert(ident, (pid, key));
+        let evicted = self.profiles.insert(ident, pid, CachedProfileKey(key));
+        self.counters.record_evictions(evicted);
     }
 
     pub async fn get_profile(&self, name: &str) -> Option<(ProfileId, Arc<ProfileKey>)> {
-        self.profile_info.read().await.get(name).cloned()
+        let found = self.profiles.get(name);
+        self.counters.record_lookup(found.is_some());
+        found.map(|(pid, key)| (pid, key.0))
+    }
+
+    /// Discard the key of a removed profile
+    pub fn remove_profile(&self, name: &str) {
+        if self.profiles.remove(name) {
+            self.counters.record_evictions(1);
+        }
+    }
+
+    /// Discard the key of a shredded profile, and ask the tenant key provider
+    /// to destroy the tenant key when one was used to wrap it
+    pub async fn destroy_profile_key(
+        &self,
+        name: String,
+        reference: Option<String>,
+    ) -> Result<bool, Error> {
+        self.remove_profile(&name);
+        match self.tenant_keys.clone() {
+            Some(provider) if reference.as_deref() == Some(TENANT_KEY_REFERENCE) => {
+                unblock(move || provider.destroy_tenant_key(&name)).await
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Create a separate cache sharing the store key, for a copy of the
+    /// store. Profile keys are loaded from the copy as needed
+    pub fn fork(&self) -> Self {
+        Self::with_protected_key(self.store_key.clone()).with_tenant_keys(self.tenant_keys.clone())
     }
 }
 
@@ -69,6 +303,11 @@ pub(crate) trait EntryEncryptor {
         SecretBytes::from(input)
     }
 
+    /// The nonce strategy in effect, if entries are encrypted
+    fn nonce_strategy(&self) -> Option<NonceStrategy> {
+        None
+    }
+
     fn encrypt_entry_category(&self, category: SecretBytes) -> Result<Vec<u8>, Error>;
     fn encrypt_entry_name(&self, name: SecretBytes) -> Result<Vec<u8>, Error>;
     fn encrypt_entry_value(
@@ -78,6 +317,7 @@ pub(crate) trait EntryEncryptor {
         value: SecretBytes,
     ) -> Result<Vec<u8>, Error>;
     fn encrypt_entry_tags(&self, tags: Vec<EntryTag>) -> Result<Vec<EncEntryTag>, Error>;
+    fn encrypt_search_token(&self, category: &[u8], term: &[u8]) -> Result<Vec<u8>, Error>;
 
     fn decrypt_entry_category(&self, enc_category: Vec<u8>) -> Result<String, Error>;
     fn decrypt_entry_name(&self, enc_name: Vec<u8>) -> Result<String, Error>;
@@ -87,6 +327,14 @@ pub(crate) trait EntryEncryptor {
         name: &[u8],
         enc_value: Vec<u8>,
     ) -> Result<SecretBytes, Error>;
+    /// Decrypt an entry value held in a caller-provided buffer, replacing the
+    /// ciphertext with the plaintext
+    fn decrypt_entry_value_in_place(
+        &self,
+        category: &[u8],
+        name: &[u8],
+        buffer: &mut SecretBytes,
+    ) -> Result<(), Error>;
     fn decrypt_entry_tags(&self, enc_tags: Vec<EncEntryTag>) -> Result<Vec<EntryTag>, Error>;
 }
 
@@ -94,10 +342,10 @@ pub struct NullEncryptor;
 
 impl EntryEncryptor for NullEncryptor {
     fn encrypt_entry_category(&self, category: SecretBytes) -> Result<Vec<u8>, Error> {
-        Ok(category.into_vec())
+        Ok(binary_identifier_input(category).into_vec())
     }
     fn encrypt_entry_name(&self, name: SecretBytes) -> Result<Vec<u8>, Error> {
-        Ok(name.into_vec())
+        Ok(binary_identifier_input(name).into_vec())
     }
     fn encrypt_entry_value(
         &self,
@@ -124,12 +372,15 @@ impl EntryEncryptor for NullEncryptor {
             })
             .collect())
     }
+    fn encrypt_search_token(&self, _category: &[u8], term: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(term.to_vec())
+    }
 
     fn decrypt_entry_category(&self, enc_category: Vec<u8>) -> Result<String, Error> {
-        Ok(String::from_utf8(enc_category).map_err(err_map!(Encryption))?)
+        Ok(binary_identifier_output(enc_category))
     }
     fn decrypt_entry_name(&self, enc_name: Vec<u8>) -> Result<String, Error> {
-        Ok(String::from_utf8(enc_name).map_err(err_map!(Encryption))?)
+        Ok(binary_identifier_output(enc_name))
     }
     fn decrypt_entry_value(
         &self,
@@ -139,6 +390,14 @@ impl EntryEncryptor for NullEncryptor {
     ) -> Result<SecretBytes, Error> {
         Ok(enc_value.into())
     }
+    fn decrypt_entry_value_in_place(
+        &self,
+        _category: &[u8],
+        _name: &[u8],
+        _buffer: &mut SecretBytes,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
     fn decrypt_entry_tags(&self, enc_tags: Vec<EncEntryTag>) -> Result<Vec<EntryTag>, Error> {
         Ok(enc_tags.into_iter().try_fold(vec![], |mut acc, tag| {
             let name = String::from_utf8(tag.name).map_err(err_map!(Encryption))?;
@@ -0,0 +1,217 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+};
+
+use super::{PassKey, StoreKeyMethod};
+use crate::error::Error;
+
+/// A custom check applied to pass keys, returning the reason for the
+/// rejection if the pass key is not permitted
+pub type PassKeyCheck = Arc<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+/// Requirements applied to the pass keys used to derive store keys.
+///
+/// The policy is checked when a store is provisioned or rekeyed using a key
+/// derivation method, and a rejected pass key fails with a `WeakPassKey`
+/// error. Raw keys are not checked, as they are generated rather than chosen.
+#[derive(Clone, Default)]
+pub struct PassKeyPolicy {
+    min_length: usize,
+    min_entropy: f64,
+    deny_list: HashSet<String>,
+    checks: Vec<PassKeyCheck>,
+}
+
+impl PassKeyPolicy {
+    /// Create a new policy without any requirements
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require a minimum number of characters
+    pub fn min_length(mut self, length: usize) -> Self {
+        self.min_length = length;
+        self
+    }
+
+    /// Require a minimum estimated entropy, in bits. The estimate is based on
+    /// the length of the pass key and the classes of characters it contains
+    pub fn min_entropy(mut self, bits: f64) -> Self {
+        self.min_entropy = bits;
+        self
+    }
+
+    /// Reject a set of pass keys, such as commonly used passwords. The
+    /// comparison is not case sensitive
+    pub fn deny<I>(mut self, pass_keys: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.deny_list
+            .extend(pass_keys.into_iter().map(|key| key.as_ref().to_lowercase()));
+        self
+    }
+
+    /// Add a custom check, applied after the built-in requirements
+    pub fn with_check<F>(mut self, check: F) -> Self
+    where
+        F: Fn(&str) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.checks.push(Arc::new(check));
+        self
+    }
+
+    /// Check a pass key against the policy
+    pub fn check(&self, pass_key: &str) -> Result<(), Error> {
+        let length = pass_key.chars().count();
+        if length < self.min_length {
+            return Err(err_msg!(
+                WeakPassKey,
+                "Pass key must contain at least {} characters",
+                self.min_length
+            ));
+        }
+        if estimate_entropy(pass_key) < self.min_entropy {
+            return Err(err_msg!(
+                WeakPassKey,
+                "Pass key does not meet the minimum estimated entropy of {} bits",
+                self.min_entropy
+            ));
+        }
+        if self.deny_list.contains(&pass_key.to_lowercase()) {
+            return Err(err_msg!(WeakPassKey, "Pass key is not permitted"));
+        }
+        for check in &self.checks {
+            check(pass_key).map_err(|reason| err_msg!(WeakPassKey, "{}", reason))?;
+        }
+        Ok(())
+    }
+
+    /// Check the pass key used with a store key method, when the method
+    /// derives the store key from the pass key
+    pub(crate) fn check_method(
+        &self,
+        method: &StoreKeyMethod,
+        pass_key: &PassKey<'_>,
+    ) -> Result<(), Error> {
+        match method {
+            StoreKeyMethod::DeriveKey(_) => self.check(pass_key),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Debug for PassKeyPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PassKeyPolicy")
+            .field("min_length", &self.min_length)
+            .field("min_entropy", &self.min_entropy)
+            .field("deny_list", &self.deny_list.len())
+            .field("checks", &self.checks.len())
+            .finish()
+    }
+}
+
+/// Estimate the entropy of a pass key in bits, assuming each character is
+/// chosen at random from the union of the character classes it uses
+fn estimate_entropy(pass_key: &str) -> f64 {
+    let (mut lower, mut upper, mut digit, mut symbol, mut other) =
+        (false, false, false, false, false);
+    let mut length = 0usize;
+    for c in pass_key.chars() {
+        length += 1;
+        if c.is_ascii_lowercase() {
+            lower = true;
+        } else if c.is_ascii_uppercase() {
+            upper = true;
+        } else if c.is_ascii_digit() {
+            digit = true;
+        } else if c.is_ascii() {
+            symbol = true;
+        } else {
+            other = true;
+        }
+    }
+    let pool = [
+        (lower, 26),
+        (upper, 26),
+        (digit, 10),
+        (symbol, 33),
+        (other, 100),
+    ]
+    .iter()
+    .filter(|(used, _)| *used)
+    .map(|(_, size)| *size)
+    .sum::<u32>();
+    if pool == 0 {
+        0.0
+    } else {
+        length as f64 * f64::from(pool).log2()
+    }
+}
+
+/// Check a pass key against an optional policy
+pub(crate) fn check_pass_key(
+    policy: Option<&PassKeyPolicy>,
+    method: &StoreKeyMethod,
+    pass_key: &PassKey<'_>,
+) -> Result<(), Error> {
+    match policy {
+        Some(policy) => policy.check_method(method, pass_key),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::ErrorKind, protect::kdf::KdfMethod};
+
+    #[test]
+    fn policy_requirements() {
+        let policy = PassKeyPolicy::new()
+            .min_length(8)
+            .min_entropy(50.0)
+            .deny(vec!["Password123"])
+            .with_check(|key| {
+                if key.contains("askar") {
+                    Err("Pass key must not contain the product name".to_string())
+                } else {
+                    Ok(())
+                }
+            });
+        assert!(policy.check("correct-Horse-7").is_ok());
+        for key in &["short", "aaaaaaaaa", "password123", "my-askar-key"] {
+            assert_eq!(
+                policy.check(key).unwrap_err().kind(),
+                ErrorKind::WeakPassKey,
+                "expected pass key to be rejected: {}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn entropy_estimate() {
+        assert_eq!(estimate_entropy(""), 0.0);
+        assert!((estimate_entropy("abcd") - 4.0 * 26f64.log2()).abs() < 1e-9);
+        assert!((estimate_entropy("aB1!") - 4.0 * 95f64.log2()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn raw_keys_not_checked() {
+        let policy = PassKeyPolicy::new().min_length(100);
+        assert!(policy
+            .check_method(&StoreKeyMethod::RawKey, &PassKey::from("short"))
+            .is_ok());
+        assert!(policy
+            .check_method(
+                &StoreKeyMethod::DeriveKey(KdfMethod::Argon2i(Default::default())),
+                &PassKey::from("short")
+            )
+            .is_err());
+    }
+}
@@ -2,21 +2,66 @@ use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 
 use super::hmac_key::{HmacDerive, HmacKey};
-use super::EntryEncryptor;
+use super::{EntryEncryptor, NonceStrategy};
 use crate::{
     crypto::{
-        alg::chacha20::{Chacha20Key, C20P},
+        alg::chacha20::{Chacha20Key, C20P, XC20P},
         buffer::{ArrayKey, ResizeBuffer, SecretBytes, WriteBuffer},
         encrypt::{KeyAeadInPlace, KeyAeadMeta},
-        generic_array::typenum::{Unsigned, U32},
+        generic_array::typenum::{Unsigned, U16, U32},
         kdf::FromKeyDerivation,
         repr::KeyGen,
     },
     error::Error,
-    storage::{EncEntryTag, EntryTag},
+    storage::{binary_identifier_input, binary_identifier_output, EncEntryTag, EntryTag},
 };
 
-pub type ProfileKey = ProfileKeyImpl<Chacha20Key<C20P>, HmacKey<Sha256, U32>>;
+pub type ProfileKeyV1 = ProfileKeyImpl<Chacha20Key<C20P>, HmacKey<Sha256, U32>>;
+
+pub type ProfileKeyV2 = ProfileKeyImpl<Chacha20Key<XC20P>, HmacKey<Sha256, U32>>;
+
+/// The versioned set of keys used to encrypt the entries of a profile
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "ver")]
+pub enum ProfileKey {
+    /// ChaCha20-Poly1305 with 96-bit nonces
+    #[serde(rename = "1")]
+    V1(ProfileKeyV1),
+    /// XChaCha20-Poly1305 with 192-bit nonces
+    #[serde(rename = "2")]
+    V2(ProfileKeyV2),
+}
+
+macro_rules! with_profile_key {
+    ($key:expr, $k:ident => $body:expr) => {
+        match $key {
+            ProfileKey::V1($k) => $body,
+            ProfileKey::V2($k) => $body,
+        }
+    };
+}
+
+/// Version prefix for entry values encrypted with a per-category value key.
+/// Values written before the key hierarchy was introduced carry no prefix.
+const VALUE_VERSION_CATEGORY: u8 = 1;
+
+/// Domain separation label for per-category key derivation
+const CATEGORY_KEY_LABEL: &[u8] = b"askar:category";
+
+/// Domain separation label for search token derivation
+const SEARCH_TOKEN_LABEL: &[u8] = b"askar:search";
+
+/// Domain separation label for value checksums
+const VALUE_CHECKSUM_LABEL: &[u8] = b"askar:checksum";
+
+/// Domain separation label for entry metadata keys
+const METADATA_KEY_LABEL: &[u8] = b"askar:metadata";
+
+/// Domain separation label for the entry link key
+const LINK_KEY_LABEL: &[u8] = b"askar:link";
+
+/// Domain separation label for the collection name key
+const COLLECTION_KEY_LABEL: &[u8] = b"askar:collection";
 
 /// A record combining the keys required to encrypt and decrypt storage entries
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -24,7 +69,6 @@ pub type ProfileKey = ProfileKeyImpl<Chacha20Key<C20P>, HmacKey<Sha256, U32>>;
     deserialize = "Key: for<'a> Deserialize<'a>, HmacKey: for<'a> Deserialize<'a>",
     serialize = "Key: Serialize, HmacKey: Serialize"
 ))]
-#[serde(tag = "ver", rename = "1")]
 pub struct ProfileKeyImpl<Key, HmacKey> {
     #[serde(rename = "ick")]
     pub category_key: Key,
@@ -57,11 +101,20 @@ where
     }
 }
 
-impl<Key, HmacKey> ProfileKeyImpl<Key, HmacKey>
-where
-    Key: Serialize + for<'de> Deserialize<'de>,
-    HmacKey: Serialize + for<'de> Deserialize<'de>,
-{
+impl ProfileKey {
+    /// Generate a new profile key using the standard nonce strategy
+    pub fn new() -> Result<Self, Error> {
+        Self::new_with_strategy(NonceStrategy::default())
+    }
+
+    /// Generate a new profile key using a specific nonce strategy
+    pub fn new_with_strategy(strategy: NonceStrategy) -> Result<Self, Error> {
+        Ok(match strategy {
+            NonceStrategy::Standard => Self::V1(ProfileKeyV1::new()?),
+            NonceStrategy::Extended => Self::V2(ProfileKeyV2::new()?),
+        })
+    }
+
     pub fn to_bytes(&self) -> Result<SecretBytes, Error> {
         serde_cbor::to_vec(self)
             .map(SecretBytes::from)
@@ -71,15 +124,146 @@ where
     pub fn from_slice(input: &[u8]) -> Result<Self, Error> {
         serde_cbor::from_slice(input).map_err(err_map!(Unsupported, "Invalid profile key"))
     }
+
+    pub fn derive_category_key(&self, category: &[u8]) -> Result<HmacKey<Sha256, U32>, Error> {
+        with_profile_key!(self, key => key.derive_category_key(category))
+    }
+
+    pub fn encrypt_tag_name(&self, name: SecretBytes) -> Result<Vec<u8>, Error> {
+        with_profile_key!(self, key => key.encrypt_tag_name(name))
+    }
+
+    pub fn encrypt_tag_value(&self, value: SecretBytes) -> Result<Vec<u8>, Error> {
+        with_profile_key!(self, key => key.encrypt_tag_value(value))
+    }
+
+    pub fn decrypt_tag_name(&self, enc_tag_name: Vec<u8>) -> Result<SecretBytes, Error> {
+        with_profile_key!(self, key => key.decrypt_tag_name(enc_tag_name))
+    }
+
+    pub fn decrypt_tag_value(&self, enc_tag_value: Vec<u8>) -> Result<SecretBytes, Error> {
+        with_profile_key!(self, key => key.decrypt_tag_value(enc_tag_value))
+    }
+
+    pub fn value_checksum(
+        &self,
+        category: &[u8],
+        name: &[u8],
+        value: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        with_profile_key!(self, key => key.value_checksum(category, name, value))
+    }
+
+    pub fn encrypt_entry_metadata(
+        &self,
+        category: &[u8],
+        name: &[u8],
+        metadata: SecretBytes,
+    ) -> Result<Vec<u8>, Error> {
+        with_profile_key!(self, key => key.encrypt_entry_metadata(category, name, metadata))
+    }
+
+    pub fn decrypt_entry_metadata(
+        &self,
+        category: &[u8],
+        name: &[u8],
+        enc_metadata: Vec<u8>,
+    ) -> Result<SecretBytes, Error> {
+        with_profile_key!(self, key => key.decrypt_entry_metadata(category, name, enc_metadata))
+    }
+
+    pub fn encrypt_link_type(&self, rel_type: SecretBytes) -> Result<Vec<u8>, Error> {
+        with_profile_key!(self, key => key.encrypt_link_type(rel_type))
+    }
+
+    pub fn decrypt_link_type(&self, enc_rel_type: Vec<u8>) -> Result<SecretBytes, Error> {
+        with_profile_key!(self, key => key.decrypt_link_type(enc_rel_type))
+    }
+
+    pub fn encrypt_collection_name(&self, collection: SecretBytes) -> Result<Vec<u8>, Error> {
+        with_profile_key!(self, key => key.encrypt_collection_name(collection))
+    }
+}
+
+impl EntryEncryptor for ProfileKey {
+    fn prepare_input(input: &[u8]) -> SecretBytes {
+        // reserve enough space for the largest supported nonce
+        let mut buf = SecretBytes::with_capacity(ProfileKeyV2::encrypted_size(input.len()));
+        buf.buffer_write(input).unwrap();
+        buf
+    }
+
+    fn nonce_strategy(&self) -> Option<NonceStrategy> {
+        Some(match self {
+            Self::V1(_) => NonceStrategy::Standard,
+            Self::V2(_) => NonceStrategy::Extended,
+        })
+    }
+
+    fn encrypt_entry_category(&self, category: SecretBytes) -> Result<Vec<u8>, Error> {
+        with_profile_key!(self, key => key.encrypt_entry_category(category))
+    }
+
+    fn encrypt_entry_name(&self, name: SecretBytes) -> Result<Vec<u8>, Error> {
+        with_profile_key!(self, key => key.encrypt_entry_name(name))
+    }
+
+    fn encrypt_entry_value(
+        &self,
+        category: &[u8],
+        name: &[u8],
+        value: SecretBytes,
+    ) -> Result<Vec<u8>, Error> {
+        with_profile_key!(self, key => key.encrypt_entry_value(category, name, value))
+    }
+
+    fn encrypt_entry_tags(&self, tags: Vec<EntryTag>) -> Result<Vec<EncEntryTag>, Error> {
+        with_profile_key!(self, key => key.encrypt_entry_tags(tags))
+    }
+
+    fn encrypt_search_token(&self, category: &[u8], term: &[u8]) -> Result<Vec<u8>, Error> {
+        with_profile_key!(self, key => key.encrypt_search_token(category, term))
+    }
+
+    fn decrypt_entry_category(&self, enc_category: Vec<u8>) -> Result<String, Error> {
+        with_profile_key!(self, key => key.decrypt_entry_category(enc_category))
+    }
+
+    fn decrypt_entry_name(&self, enc_name: Vec<u8>) -> Result<String, Error> {
+        with_profile_key!(self, key => key.decrypt_entry_name(enc_name))
+    }
+
+    fn decrypt_entry_value(
+        &self,
+        category: &[u8],
+        name: &[u8],
+        enc_value: Vec<u8>,
+    ) -> Result<SecretBytes, Error> {
+        with_profile_key!(self, key => key.decrypt_entry_value(category, name, enc_value))
+    }
+
+    fn decrypt_entry_value_in_place(
+        &self,
+        category: &[u8],
+        name: &[u8],
+        buffer: &mut SecretBytes,
+    ) -> Result<(), Error> {
+        with_profile_key!(self, key => key.decrypt_entry_value_in_place(category, name, buffer))
+    }
+
+    fn decrypt_entry_tags(&self, enc_tags: Vec<EncEntryTag>) -> Result<Vec<EntryTag>, Error> {
+        with_profile_key!(self, key => key.decrypt_entry_tags(enc_tags))
+    }
 }
 
 impl<Key, HmacKey> ProfileKeyImpl<Key, HmacKey>
 where
     Key: KeyAeadInPlace + KeyAeadMeta + FromKeyDerivation,
-    HmacKey: HmacDerive,
+    HmacKey: HmacDerive + FromKeyDerivation,
 {
     fn encrypted_size(len: usize) -> usize {
-        len + Key::NonceSize::USIZE + Key::TagSize::USIZE
+        // allow for the value version prefix
+        len + 1 + Key::NonceSize::USIZE + Key::TagSize::USIZE
     }
 
     /// Encrypt a value with a predictable nonce, making it searchable
@@ -104,17 +288,51 @@ where
     }
 
     fn decrypt(ciphertext: Vec<u8>, enc_key: &Key) -> Result<SecretBytes, Error> {
+        let mut buffer = SecretBytes::from(ciphertext);
+        Self::decrypt_buffer(&mut buffer, enc_key)?;
+        Ok(buffer)
+    }
+
+    /// Decrypt a buffer holding a nonce followed by the ciphertext. If
+    /// decryption fails then the buffer is left unchanged
+    fn decrypt_buffer(buffer: &mut SecretBytes, enc_key: &Key) -> Result<(), Error> {
         let nonce_len = Key::NonceSize::USIZE;
-        if ciphertext.len() < nonce_len {
+        if buffer.len() < nonce_len {
             return Err(err_msg!(Encryption, "invalid encrypted value"));
         }
-        let mut buffer = SecretBytes::from(ciphertext);
         let nonce = ArrayKey::<Key::NonceSize>::from_slice(&buffer.as_ref()[..nonce_len]);
         buffer.buffer_remove(0..nonce_len)?;
-        enc_key.decrypt_in_place(&mut buffer, nonce.as_ref(), &[])?;
-        Ok(buffer)
+        // the authentication tag is verified before the ciphertext is modified
+        if let Err(err) = enc_key.decrypt_in_place(buffer, nonce.as_ref(), &[]) {
+            buffer.buffer_insert(0, nonce.as_ref())?;
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    /// Derive the sub-key used for encrypting values within a single category.
+    /// This is HKDF-Expand (RFC 5869) with the item HMAC key as the
+    /// pseudorandom key and the category as part of the info parameter.
+    pub fn derive_category_key(&self, category: &[u8]) -> Result<HmacKey, Error> {
+        Ok(HmacKey::from_key_derivation(
+            self.item_hmac_key.hmac_deriver(&[
+                CATEGORY_KEY_LABEL,
+                &(category.len() as u32).to_be_bytes(),
+                category,
+                &[1u8],
+            ]),
+        )?)
     }
 
+    #[inline]
+    fn derive_category_value_key(category_key: &HmacKey, name: &[u8]) -> Result<Key, Error> {
+        Ok(Key::from_key_derivation(category_key.hmac_deriver(&[
+            &(name.len() as u32).to_be_bytes(),
+            name,
+        ]))?)
+    }
+
+    /// Derive the value key used before per-category keys were introduced
     #[inline]
     fn derive_value_key(&self, category: &[u8], name: &[u8]) -> Result<Key, Error> {
         Ok(Key::from_key_derivation(self.item_hmac_key.hmac_deriver(
@@ -127,6 +345,79 @@ where
         ))?)
     }
 
+    /// Compute a keyed checksum of an entry value. The checksum is bound to
+    /// the category and name of the entry, so that equal values stored in
+    /// different entries cannot be linked
+    pub fn value_checksum(
+        &self,
+        category: &[u8],
+        name: &[u8],
+        value: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let checksum = ArrayKey::<U16>::from_key_derivation(self.item_hmac_key.hmac_deriver(&[
+            VALUE_CHECKSUM_LABEL,
+            &(category.len() as u32).to_be_bytes(),
+            category,
+            &(name.len() as u32).to_be_bytes(),
+            name,
+            value,
+        ]))?;
+        Ok(checksum.as_ref().to_vec())
+    }
+
+    /// Derive the key used to encrypt the metadata of an entry, which is
+    /// distinct from the key used for its value
+    fn derive_metadata_key(&self, category: &[u8], name: &[u8]) -> Result<Key, Error> {
+        let category_key = self.derive_category_key(category)?;
+        Ok(Key::from_key_derivation(category_key.hmac_deriver(&[
+            METADATA_KEY_LABEL,
+            &(name.len() as u32).to_be_bytes(),
+            name,
+        ]))?)
+    }
+
+    pub fn encrypt_entry_metadata(
+        &self,
+        category: &[u8],
+        name: &[u8],
+        metadata: SecretBytes,
+    ) -> Result<Vec<u8>, Error> {
+        Self::encrypt(metadata, &self.derive_metadata_key(category, name)?)
+    }
+
+    pub fn decrypt_entry_metadata(
+        &self,
+        category: &[u8],
+        name: &[u8],
+        enc_metadata: Vec<u8>,
+    ) -> Result<SecretBytes, Error> {
+        Self::decrypt(enc_metadata, &self.derive_metadata_key(category, name)?)
+    }
+
+    /// Derive the key used to encrypt the relation types of entry links
+    fn derive_link_key(&self) -> Result<Key, Error> {
+        Ok(Key::from_key_derivation(
+            self.item_hmac_key.hmac_deriver(&[LINK_KEY_LABEL]),
+        )?)
+    }
+
+    /// Relation types are searchable, so that links may be filtered by type
+    pub fn encrypt_link_type(&self, rel_type: SecretBytes) -> Result<Vec<u8>, Error> {
+        Self::encrypt_searchable(rel_type, &self.derive_link_key()?, &self.item_hmac_key)
+    }
+
+    pub fn decrypt_link_type(&self, enc_rel_type: Vec<u8>) -> Result<SecretBytes, Error> {
+        Self::decrypt(enc_rel_type, &self.derive_link_key()?)
+    }
+
+    /// Collection names are encrypted deterministically, so that the members
+    /// of a collection may be selected
+    pub fn encrypt_collection_name(&self, collection: SecretBytes) -> Result<Vec<u8>, Error> {
+        let collection_key =
+            Key::from_key_derivation(self.item_hmac_key.hmac_deriver(&[COLLECTION_KEY_LABEL]))?;
+        Self::encrypt_searchable(collection, &collection_key, &self.item_hmac_key)
+    }
+
     pub fn encrypt_tag_name(&self, name: SecretBytes) -> Result<Vec<u8>, Error> {
         Self::encrypt_searchable(name, &self.tag_name_key, &self.tags_hmac_key)
     }
@@ -159,7 +450,7 @@ impl<Key: PartialEq, HmacKey: PartialEq> Eq for ProfileKeyImpl<Key, HmacKey> {}
 impl<Key, HmacKey> EntryEncryptor for ProfileKeyImpl<Key, HmacKey>
 where
     Key: KeyAeadInPlace + KeyAeadMeta + FromKeyDerivation,
-    HmacKey: HmacDerive,
+    HmacKey: HmacDerive + FromKeyDerivation,
 {
     fn prepare_input(input: &[u8]) -> SecretBytes {
         let mut buf = SecretBytes::with_capacity(Self::encrypted_size(input.len()));
@@ -168,11 +459,19 @@ where
     }
 
     fn encrypt_entry_category(&self, category: SecretBytes) -> Result<Vec<u8>, Error> {
-        Self::encrypt_searchable(category, &self.category_key, &self.item_hmac_key)
+        Self::encrypt_searchable(
+            binary_identifier_input(category),
+            &self.category_key,
+            &self.item_hmac_key,
+        )
     }
 
     fn encrypt_entry_name(&self, name: SecretBytes) -> Result<Vec<u8>, Error> {
-        Self::encrypt_searchable(name, &self.name_key, &self.item_hmac_key)
+        Self::encrypt_searchable(
+            binary_identifier_input(name),
+            &self.name_key,
+            &self.item_hmac_key,
+        )
     }
 
     fn encrypt_entry_value(
@@ -181,16 +480,23 @@ where
         name: &[u8],
         value: SecretBytes,
     ) -> Result<Vec<u8>, Error> {
-        let value_key = self.derive_value_key(category, name)?;
-        Self::encrypt(value, &value_key)
+        let category_key = self.derive_category_key(category)?;
+        let value_key = Self::derive_category_value_key(&category_key, name)?;
+        let mut enc_value = Self::encrypt(value, &value_key)?;
+        enc_value.insert(0, VALUE_VERSION_CATEGORY);
+        Ok(enc_value)
     }
 
     fn decrypt_entry_category(&self, enc_category: Vec<u8>) -> Result<String, Error> {
-        decode_utf8(Self::decrypt(enc_category, &self.category_key)?.into_vec())
+        Ok(binary_identifier_output(
+            Self::decrypt(enc_category, &self.category_key)?.into_vec(),
+        ))
     }
 
     fn decrypt_entry_name(&self, enc_name: Vec<u8>) -> Result<String, Error> {
-        decode_utf8(Self::decrypt(enc_name, &self.name_key)?.into_vec())
+        Ok(binary_identifier_output(
+            Self::decrypt(enc_name, &self.name_key)?.into_vec(),
+        ))
     }
 
     fn decrypt_entry_value(
@@ -199,8 +505,30 @@ where
         name: &[u8],
         enc_value: Vec<u8>,
     ) -> Result<SecretBytes, Error> {
+        let mut buffer = SecretBytes::from(enc_value);
+        self.decrypt_entry_value_in_place(category, name, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn decrypt_entry_value_in_place(
+        &self,
+        category: &[u8],
+        name: &[u8],
+        buffer: &mut SecretBytes,
+    ) -> Result<(), Error> {
+        if buffer.as_ref().first() == Some(&VALUE_VERSION_CATEGORY) {
+            // an unversioned value may begin with the same byte by chance,
+            // in which case authentication fails and the legacy key is tried
+            let category_key = self.derive_category_key(category)?;
+            let value_key = Self::derive_category_value_key(&category_key, name)?;
+            buffer.buffer_remove(0..1)?;
+            if Self::decrypt_buffer(buffer, &value_key).is_ok() {
+                return Ok(());
+            }
+            buffer.buffer_insert(0, &[VALUE_VERSION_CATEGORY])?;
+        }
         let value_key = self.derive_value_key(category, name)?;
-        Self::decrypt(enc_value, &value_key)
+        Self::decrypt_buffer(buffer, &value_key)
     }
 
     fn encrypt_entry_tags(&self, tags: Vec<EntryTag>) -> Result<Vec<EncEntryTag>, Error> {
@@ -227,6 +555,19 @@ where
             .collect()
     }
 
+    /// Search tokens are a keyed hash of the term, bound to the record
+    /// category so that matching terms are not linked across categories
+    fn encrypt_search_token(&self, category: &[u8], term: &[u8]) -> Result<Vec<u8>, Error> {
+        let token = ArrayKey::<U32>::from_key_derivation(self.tags_hmac_key.hmac_deriver(&[
+            SEARCH_TOKEN_LABEL,
+            &(category.len() as u32).to_be_bytes(),
+            category,
+            &(term.len() as u32).to_be_bytes(),
+            term,
+        ]))?;
+        Ok(token.as_ref().to_vec())
+    }
+
     fn decrypt_entry_tags(&self, enc_tags: Vec<EncEntryTag>) -> Result<Vec<EntryTag>, Error> {
         enc_tags.into_iter().try_fold(vec![], |mut acc, tag| {
             let name = decode_utf8(self.decrypt_tag_name(tag.name)?.into_vec())?;
@@ -250,6 +591,7 @@ fn decode_utf8(value: Vec<u8>) -> Result<String, Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::random::fill_random;
     use crate::storage::Entry;
 
     #[test]
@@ -296,25 +638,243 @@ mod tests {
         assert_eq!(test_record, cmp_record);
     }
 
+    #[test]
+    fn decrypt_legacy_value() {
+        let key = ProfileKeyV1::new().unwrap();
+        let value = SecretBytes::from(&b"value"[..]);
+        let enc_value = key
+            .encrypt_entry_value(b"category", b"name", value.clone())
+            .unwrap();
+        assert_eq!(enc_value[0], VALUE_VERSION_CATEGORY);
+
+        // values written without a version prefix remain readable,
+        // including those with a nonce resembling the version prefix
+        let legacy_key = key.derive_value_key(b"category", b"name").unwrap();
+        for first in [0u8, VALUE_VERSION_CATEGORY].iter() {
+            let nonce =
+                ArrayKey::<<Chacha20Key<C20P> as KeyAeadMeta>::NonceSize>::new_with(|buf| {
+                    fill_random(buf);
+                    buf[0] = *first;
+                });
+            let mut legacy_value = value.clone();
+            legacy_key
+                .encrypt_in_place(&mut legacy_value, nonce.as_ref(), &[])
+                .unwrap();
+            legacy_value.buffer_insert(0, nonce.as_ref()).unwrap();
+            assert_eq!(
+                key.decrypt_entry_value(b"category", b"name", legacy_value.into_vec())
+                    .unwrap(),
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn value_checksum_binding() {
+        let key = ProfileKey::new().unwrap();
+        let checksum = key.value_checksum(b"category", b"name", b"value").unwrap();
+        assert_eq!(checksum.len(), 16);
+        assert_eq!(
+            key.value_checksum(b"category", b"name", b"value").unwrap(),
+            checksum
+        );
+        assert_ne!(
+            key.value_checksum(b"category", b"name", b"other").unwrap(),
+            checksum
+        );
+        assert_ne!(
+            key.value_checksum(b"category", b"other", b"value").unwrap(),
+            checksum
+        );
+        assert_ne!(
+            ProfileKey::new()
+                .unwrap()
+                .value_checksum(b"category", b"name", b"value")
+                .unwrap(),
+            checksum
+        );
+    }
+
+    #[test]
+    fn entry_metadata_binding() {
+        let key = ProfileKey::new().unwrap();
+        let metadata = SecretBytes::from(&b"metadata"[..]);
+        let enc_metadata = key
+            .encrypt_entry_metadata(b"category", b"name", metadata.clone())
+            .unwrap();
+        assert_eq!(
+            key.decrypt_entry_metadata(b"category", b"name", enc_metadata.clone())
+                .unwrap(),
+            metadata
+        );
+        assert!(key
+            .decrypt_entry_metadata(b"category", b"other", enc_metadata.clone())
+            .is_err());
+        // metadata cannot be substituted for the entry value
+        assert!(key
+            .decrypt_entry_value(b"category", b"name", enc_metadata)
+            .is_err());
+    }
+
+    #[test]
+    fn link_type_round_trip() {
+        let key = ProfileKey::new().unwrap();
+        let enc_rel = key
+            .encrypt_link_type(SecretBytes::from(&b"connection"[..]))
+            .unwrap();
+        // relation types are encrypted deterministically
+        assert_eq!(
+            key.encrypt_link_type(SecretBytes::from(&b"connection"[..]))
+                .unwrap(),
+            enc_rel
+        );
+        assert_ne!(
+            key.encrypt_entry_category(SecretBytes::from(&b"connection"[..]))
+                .unwrap(),
+            enc_rel
+        );
+        assert_eq!(
+            key.decrypt_link_type(enc_rel).unwrap().as_ref(),
+            b"connection"
+        );
+    }
+
+    #[test]
+    fn search_token_derivation() {
+        let key = ProfileKey::new().unwrap();
+        let token = key.encrypt_search_token(b"category", b"term").unwrap();
+        assert_eq!(token.len(), 32);
+        assert_eq!(
+            key.encrypt_search_token(b"category", b"term").unwrap(),
+            token
+        );
+        assert_ne!(key.encrypt_search_token(b"other", b"term").unwrap(), token);
+        assert_ne!(
+            ProfileKey::new()
+                .unwrap()
+                .encrypt_search_token(b"category", b"term")
+                .unwrap(),
+            token
+        );
+    }
+
+    #[test]
+    fn decrypt_value_in_place() {
+        let key = ProfileKey::new().unwrap();
+        let value = SecretBytes::from(&b"value"[..]);
+        let enc_value = key
+            .encrypt_entry_value(b"category", b"name", value.clone())
+            .unwrap();
+
+        let mut buffer = SecretBytes::from(enc_value.as_slice());
+        key.decrypt_entry_value_in_place(b"category", b"name", &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, value);
+
+        // the ciphertext is preserved when decryption fails
+        let mut buffer = SecretBytes::from(enc_value.as_slice());
+        assert!(key
+            .decrypt_entry_value_in_place(b"category", b"other", &mut buffer)
+            .is_err());
+        assert_eq!(buffer, enc_value);
+    }
+
+    #[test]
+    fn category_keys_distinct() {
+        let key = ProfileKey::new().unwrap();
+        let cat1 = key.derive_category_key(b"cat1").unwrap();
+        let cat2 = key.derive_category_key(b"cat2").unwrap();
+        assert_ne!(cat1, cat2);
+        assert_eq!(cat1, key.derive_category_key(b"cat1").unwrap());
+
+        let enc_value = key
+            .encrypt_entry_value(b"cat1", b"name", SecretBytes::from(&b"value"[..]))
+            .unwrap();
+        assert!(key
+            .decrypt_entry_value(b"cat2", b"name", enc_value)
+            .is_err());
+    }
+
     #[test]
     fn check_encrypt_searchable() {
         let input = SecretBytes::from(&b"hello"[..]);
         let key = Chacha20Key::<C20P>::generate().unwrap();
         let hmac_key = HmacKey::generate().unwrap();
-        let enc1 = ProfileKey::encrypt_searchable(input.clone(), &key, &hmac_key).unwrap();
-        let enc2 = ProfileKey::encrypt_searchable(input.clone(), &key, &hmac_key).unwrap();
-        let enc3 = ProfileKey::encrypt(input.clone(), &key).unwrap();
+        let enc1 = ProfileKeyV1::encrypt_searchable(input.clone(), &key, &hmac_key).unwrap();
+        let enc2 = ProfileKeyV1::encrypt_searchable(input.clone(), &key, &hmac_key).unwrap();
+        let enc3 = ProfileKeyV1::encrypt(input.clone(), &key).unwrap();
         assert_eq!(&enc1, &enc2);
         assert_ne!(&enc1, &enc3);
-        let dec = ProfileKey::decrypt(enc1, &key).unwrap();
+        let dec = ProfileKeyV1::decrypt(enc1, &key).unwrap();
         assert_eq!(dec, input);
     }
 
     #[test]
     fn serialize_round_trip() {
-        let key = ProfileKey::new().unwrap();
-        let key_cbor = serde_cbor::to_vec(&key).unwrap();
-        let key_cmp = serde_cbor::from_slice(&key_cbor).unwrap();
-        assert_eq!(key, key_cmp);
+        for strategy in [NonceStrategy::Standard, NonceStrategy::Extended].iter() {
+            let key = ProfileKey::new_with_strategy(*strategy).unwrap();
+            let key_cbor = serde_cbor::to_vec(&key).unwrap();
+            let key_cmp: ProfileKey = serde_cbor::from_slice(&key_cbor).unwrap();
+            assert_eq!(key, key_cmp);
+            assert_eq!(key_cmp.nonce_strategy(), Some(*strategy));
+        }
+    }
+
+    #[test]
+    fn nonce_no_reuse() {
+        fn random_input(prefix: &str) -> String {
+            let mut buf = [0u8; 16];
+            fill_random(&mut buf);
+            format!("{}{}", prefix, hex::encode(buf))
+        }
+
+        for strategy in [NonceStrategy::Standard, NonceStrategy::Extended].iter() {
+            let key = ProfileKey::new_with_strategy(*strategy).unwrap();
+            let nonce_len = match key {
+                ProfileKey::V1(_) => <Chacha20Key<C20P> as KeyAeadMeta>::NonceSize::USIZE,
+                ProfileKey::V2(_) => <Chacha20Key<XC20P> as KeyAeadMeta>::NonceSize::USIZE,
+            };
+            let mut nonces = std::collections::HashSet::new();
+            let mut check_nonce = |enc: &[u8]| {
+                assert!(
+                    nonces.insert(enc[..nonce_len].to_vec()),
+                    "Nonce reused for {:?}",
+                    strategy
+                );
+            };
+
+            for _ in 0..256 {
+                let category = random_input("c");
+                let name = random_input("n");
+                let enc_category = key
+                    .encrypt_entry_category(ProfileKey::prepare_input(category.as_bytes()))
+                    .unwrap();
+                check_nonce(&enc_category);
+                let enc_name = key
+                    .encrypt_entry_name(ProfileKey::prepare_input(name.as_bytes()))
+                    .unwrap();
+                check_nonce(&enc_name);
+                // the same value is encrypted repeatedly under one value key
+                for _ in 0..2 {
+                    let enc_value = key
+                        .encrypt_entry_value(
+                            category.as_bytes(),
+                            name.as_bytes(),
+                            ProfileKey::prepare_input(b"value"),
+                        )
+                        .unwrap();
+                    // skip the version prefix
+                    check_nonce(&enc_value[1..]);
+                }
+                let enc_tags = key
+                    .encrypt_entry_tags(vec![EntryTag::Encrypted(
+                        random_input("tn"),
+                        random_input("tv"),
+                    )])
+                    .unwrap();
+                check_nonce(&enc_tags[0].name);
+                check_nonce(&enc_tags[0].value);
+            }
+        }
     }
 }
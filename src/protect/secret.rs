@@ -0,0 +1,90 @@
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+
+use super::PassKey;
+use crate::{error::Error, future::BoxFuture};
+
+/// The kinds of secret which may be requested from a `SecretResolver`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SecretKind {
+    /// The password for the database connection used by store sessions
+    DatabasePassword,
+    /// The password for the database connection used for administrative
+    /// operations such as provisioning and rekeying
+    AdminPassword,
+    /// The pass key used to unlock the store
+    PassKey,
+}
+
+/// A source of secrets which are resolved each time a connection is
+/// established, rather than being embedded in the store URI.
+///
+/// This allows credentials to be rotated by a secret manager without
+/// restarting the application. Any async function or closure accepting a
+/// `SecretKind` may be used as a resolver.
+pub trait SecretResolver: Send + Sync {
+    /// Resolve a secret. An empty `PassKey` indicates that no value is
+    /// available, in which case any value in the store URI is used
+    fn resolve(&self, kind: SecretKind) -> BoxFuture<'_, Result<PassKey<'static>, Error>>;
+}
+
+impl<F, Fut> SecretResolver for F
+where
+    F: Fn(SecretKind) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<PassKey<'static>, Error>> + Send + 'static,
+{
+    fn resolve(&self, kind: SecretKind) -> BoxFuture<'_, Result<PassKey<'static>, Error>> {
+        Box::pin(self(kind))
+    }
+}
+
+impl Debug for dyn SecretResolver {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretResolver")
+    }
+}
+
+/// Resolve a pass key from a secret resolver, when it is not provided directly
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+pub(crate) async fn resolve_pass_key<'a>(
+    resolver: Option<&dyn SecretResolver>,
+    pass_key: PassKey<'a>,
+) -> Result<PassKey<'a>, Error> {
+    match resolver {
+        Some(resolver) if pass_key.is_none() => resolver.resolve(SecretKind::PassKey).await,
+        _ => Ok(pass_key),
+    }
+}
+
+#[cfg(all(test, any(feature = "postgres", feature = "sqlite")))]
+mod tests {
+    use super::*;
+    use crate::future::block_on;
+
+    #[test]
+    fn closure_resolver() {
+        let resolver = |kind: SecretKind| async move {
+            match kind {
+                SecretKind::PassKey => Ok(PassKey::from("pass".to_string())),
+                _ => Ok(PassKey::empty()),
+            }
+        };
+        let resolver: &dyn SecretResolver = &resolver;
+        block_on(async {
+            let resolved = resolve_pass_key(Some(resolver), PassKey::empty())
+                .await
+                .unwrap();
+            assert_eq!(&*resolved, "pass");
+            // a provided pass key takes precedence
+            let resolved = resolve_pass_key(Some(resolver), PassKey::from("direct"))
+                .await
+                .unwrap();
+            assert_eq!(&*resolved, "direct");
+            assert!(resolver
+                .resolve(SecretKind::DatabasePassword)
+                .await
+                .unwrap()
+                .is_none());
+        });
+    }
+}
@@ -19,6 +19,43 @@ pub type StoreKeyType = Chacha20Key<C20P>;
 
 type StoreKeyNonce = ArrayKey<<StoreKeyType as KeyAeadMeta>::NonceSize>;
 
+/// Version prefix for raw store keys serialized in the V1 format
+pub const RAW_KEY_PREFIX_V1: &'static str = "v1:";
+
+/// Supported serialization formats for raw store keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StoreKeyFormat {
+    /// An unprefixed base58 encoding of the key, as produced by earlier releases
+    Legacy,
+    /// A base58 encoding of the key with an explicit `v1:` version prefix
+    V1,
+}
+
+impl StoreKeyFormat {
+    /// The format used when serializing new raw keys
+    pub const CURRENT: Self = Self::V1;
+
+    /// Determine the serialization format of an encoded raw key
+    pub fn detect(raw_key: &str) -> Result<Self, Error> {
+        match raw_key.find(':') {
+            None => Ok(Self::Legacy),
+            Some(pos) => match &raw_key[..=pos] {
+                RAW_KEY_PREFIX_V1 => Ok(Self::V1),
+                _ => Err(err_msg!(
+                    Unsupported,
+                    "Unsupported format version for encoded raw key"
+                )),
+            },
+        }
+    }
+}
+
+impl Default for StoreKeyFormat {
+    fn default() -> Self {
+        Self::CURRENT
+    }
+}
+
 /// Create a new raw (non-derived) store key
 pub fn generate_raw_store_key(seed: Option<&[u8]>) -> Result<PassKey<'static>, Error> {
     let key = if let Some(seed) = seed {
@@ -29,9 +66,21 @@ pub fn generate_raw_store_key(seed: Option<&[u8]>) -> Result<PassKey<'static>, E
     Ok(key.to_passkey())
 }
 
+/// Re-encode a raw store key in the current serialization format
+///
+/// Keys in any supported earlier format are accepted, so that existing
+/// raw keys can be upgraded without changing the underlying key material.
+pub fn migrate_raw_store_key(raw_key: &str) -> Result<PassKey<'static>, Error> {
+    Ok(parse_raw_store_key(raw_key)?.to_passkey())
+}
+
 pub fn parse_raw_store_key(raw_key: &str) -> Result<StoreKey, Error> {
+    let encoded = match StoreKeyFormat::detect(raw_key)? {
+        StoreKeyFormat::Legacy => raw_key,
+        StoreKeyFormat::V1 => &raw_key[RAW_KEY_PREFIX_V1.len()..],
+    };
     ArrayKey::<<StoreKeyType as KeyMeta>::KeySize>::temp(|key| {
-        let key_len = bs58::decode(raw_key)
+        let key_len = bs58::decode(encoded)
             .into(&mut *key)
             .map_err(|_| err_msg!(Input, "Error parsing raw key as base58 value"))?;
         if key_len != key.len() {
@@ -73,6 +122,9 @@ impl StoreKey {
     pub fn unwrap_data(&self, ciphertext: Vec<u8>) -> Result<SecretBytes, Error> {
         match &self.0 {
             Some(key) => {
+                if ciphertext.len() < StoreKeyNonce::SIZE {
+                    return Err(err_msg!(Encryption, "Invalid length for encrypted data"));
+                }
                 let nonce = StoreKeyNonce::from_slice(&ciphertext[..StoreKeyNonce::SIZE]);
                 let mut buffer = SecretBytes::from(ciphertext);
                 buffer.buffer_remove(0..StoreKeyNonce::SIZE)?;
@@ -84,8 +136,16 @@ impl StoreKey {
     }
 
     pub fn to_passkey(&self) -> PassKey<'static> {
+        self.to_passkey_with_format(StoreKeyFormat::CURRENT)
+    }
+
+    pub fn to_passkey_with_format(&self, format: StoreKeyFormat) -> PassKey<'static> {
         if let Some(key) = self.0.as_ref() {
-            PassKey::from(key.with_secret_bytes(|sk| bs58::encode(sk.unwrap()).into_string()))
+            let encoded = key.with_secret_bytes(|sk| bs58::encode(sk.unwrap()).into_string());
+            PassKey::from(match format {
+                StoreKeyFormat::Legacy => encoded,
+                StoreKeyFormat::V1 => format!("{}{}", RAW_KEY_PREFIX_V1, encoded),
+            })
         } else {
             PassKey::empty()
         }
@@ -179,13 +239,13 @@ impl StoreKeyReference {
             PREFIX_KDF => match KdfMethod::from_str(uri) {
                 Some((method, detail)) => Ok(Self::DeriveKey(method, detail)),
                 None => Err(err_msg!(
-                    Unsupported,
+                    UnsupportedWrapMethod,
                     "Invalid key derivation method for reference"
                 )),
             },
             PREFIX_NONE => Ok(Self::Unprotected),
             _ => Err(err_msg!(
-                Unsupported,
+                UnsupportedWrapMethod,
                 "Invalid store key method for reference"
             )),
         }
@@ -335,6 +395,30 @@ mod tests {
         assert_eq!(check_bad_key.is_err(), true);
     }
 
+    #[test]
+    fn raw_key_formats() {
+        let raw_key = generate_raw_store_key(Some(b"00000000000000000000000000000My1")).unwrap();
+        assert!(raw_key.starts_with(RAW_KEY_PREFIX_V1));
+        assert_eq!(StoreKeyFormat::detect(&raw_key), Ok(StoreKeyFormat::V1));
+
+        let key = parse_raw_store_key(&raw_key).expect("Error parsing raw key");
+        let legacy = key.to_passkey_with_format(StoreKeyFormat::Legacy);
+        assert_eq!(StoreKeyFormat::detect(&legacy), Ok(StoreKeyFormat::Legacy));
+        assert_eq!(&*legacy, &raw_key[RAW_KEY_PREFIX_V1.len()..]);
+
+        // legacy keys resolve to the same key material and migrate to the current format
+        let wrapped = key.wrap_data((&b"test data"[..]).into()).unwrap();
+        let legacy_key = parse_raw_store_key(&legacy).expect("Error parsing legacy raw key");
+        assert_eq!(legacy_key.unwrap_data(wrapped).unwrap(), &b"test data"[..]);
+        let migrated = migrate_raw_store_key(&legacy).expect("Error migrating raw key");
+        assert_eq!(&*migrated, &*raw_key);
+
+        assert_eq!(
+            parse_raw_store_key("v9:abc").unwrap_err().kind(),
+            ErrorKind::Unsupported
+        );
+    }
+
     #[test]
     fn unprotected_wrap() {
         let input = b"test data";
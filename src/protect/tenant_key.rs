@@ -0,0 +1,43 @@
+use std::fmt::Debug;
+
+use super::{
+    pass_key::PassKey,
+    store_key::{parse_raw_store_key, StoreKey},
+};
+use crate::error::Error;
+
+/// The profile reference recorded for profile keys wrapped by a tenant key
+pub(crate) const TENANT_KEY_REFERENCE: &str = "tenant";
+
+/// A source of tenant-supplied keys used to wrap individual profile keys.
+///
+/// When a provider is registered with a store, the key of each new profile is
+/// wrapped by the key returned for the profile name rather than the store wrap
+/// key. The provider is consulted again whenever a session is opened for such
+/// a profile, so a tenant's records cannot be decrypted without its key even
+/// when the store wrap key is known. Rekeying the store does not affect
+/// profiles wrapped by a tenant key.
+pub trait TenantKeyProvider: Debug + Send + Sync {
+    /// Resolve the wrapping key for a profile, in the format produced by
+    /// `generate_raw_store_key`, or `None` to use the store wrap key
+    fn tenant_key(&self, profile: &str) -> Result<Option<PassKey<'static>>, Error>;
+
+    /// Permanently destroy the wrapping key for a profile which has been
+    /// shredded, returning `false` if the provider does not support this
+    fn destroy_tenant_key(&self, _profile: &str) -> Result<bool, Error> {
+        Ok(false)
+    }
+}
+
+pub(crate) fn resolve_tenant_key(
+    provider: &dyn TenantKeyProvider,
+    profile: &str,
+) -> Result<Option<StoreKey>, Error> {
+    match provider.tenant_key(profile)? {
+        Some(key) if key.is_empty() => {
+            Err(err_msg!(Input, "Empty tenant key provided for profile"))
+        }
+        Some(key) => Ok(Some(parse_raw_store_key(&key)?)),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+
+use super::{PassKey, StoreKeyMethod};
+use crate::{error::Error, future::BoxFuture};
+
+/// The operations for which an `UnlockProvider` may be asked for the pass key
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UnlockReason {
+    /// An existing store is being opened
+    Open,
+    /// A new store is being provisioned
+    Provision,
+    /// The store is being rekeyed, and the new pass key is required
+    Rekey,
+}
+
+/// A source of the pass key which is consulted only when the store needs it,
+/// such as a biometric prompt on a mobile device.
+///
+/// The provider is called when a store is opened, provisioned or rekeyed
+/// without an explicit pass key, so that the pass key does not need to be
+/// cached by the application. Any async function or closure accepting an
+/// `UnlockReason` may be used as a provider. An error returned by the
+/// provider, for example when the user cancels the prompt, aborts the
+/// operation.
+pub trait UnlockProvider: Send + Sync {
+    /// Request the pass key
+    fn unlock(&self, reason: UnlockReason) -> BoxFuture<'_, Result<PassKey<'static>, Error>>;
+}
+
+impl<F, Fut> UnlockProvider for F
+where
+    F: Fn(UnlockReason) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<PassKey<'static>, Error>> + Send + 'static,
+{
+    fn unlock(&self, reason: UnlockReason) -> BoxFuture<'_, Result<PassKey<'static>, Error>> {
+        Box::pin(self(reason))
+    }
+}
+
+impl Debug for dyn UnlockProvider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("UnlockProvider")
+    }
+}
+
+/// Request the pass key from an unlock provider, when it is not provided
+/// directly and the store key method requires one
+pub(crate) async fn request_unlock<'a>(
+    provider: Option<&dyn UnlockProvider>,
+    reason: UnlockReason,
+    method: Option<&StoreKeyMethod>,
+    pass_key: PassKey<'a>,
+) -> Result<PassKey<'a>, Error> {
+    match provider {
+        Some(provider) if pass_key.is_none() && method != Some(&StoreKeyMethod::Unprotected) => {
+            provider.unlock(reason).await
+        }
+        _ => Ok(pass_key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::future::block_on;
+
+    #[test]
+    fn closure_provider() {
+        let provider = |reason: UnlockReason| async move {
+            match reason {
+                UnlockReason::Rekey => Err(err_msg!(Input, "Cancelled")),
+                _ => Ok(PassKey::from("pass".to_string())),
+            }
+        };
+        let provider: &dyn UnlockProvider = &provider;
+        block_on(async {
+            let pass_key =
+                request_unlock(Some(provider), UnlockReason::Open, None, PassKey::empty())
+                    .await
+                    .unwrap();
+            assert_eq!(&*pass_key, "pass");
+            // the provider is not consulted for unprotected stores
+            let pass_key = request_unlock(
+                Some(provider),
+                UnlockReason::Open,
+                Some(&StoreKeyMethod::Unprotected),
+                PassKey::empty(),
+            )
+            .await
+            .unwrap();
+            assert!(pass_key.is_none());
+            assert!(
+                request_unlock(Some(provider), UnlockReason::Rekey, None, PassKey::empty())
+                    .await
+                    .is_err()
+            );
+        });
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    fmt::Debug,
+    fs,
+    io::ErrorKind as IoErrorKind,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use super::store::Store;
+use crate::{
+    backend::Backend,
+    error::Error,
+    future::{sleep, spawn_ok, unblock},
+    protect::{PassKey, StoreKeyMethod},
+};
+
+/// The prefix of the names of backup artifacts
+const BACKUP_PREFIX: &str = "askar-backup-";
+
+/// A location to which backup artifacts are written.
+///
+/// The methods are called from a blocking task, so implementations may
+/// perform synchronous I/O, such as uploading to an object store.
+pub trait BackupDestination: Debug + Send + Sync {
+    /// Write a backup artifact, replacing any existing artifact of the same name
+    fn write(&self, name: &str, data: &[u8]) -> Result<(), Error>;
+
+    /// List the names of the stored artifacts
+    fn list(&self) -> Result<Vec<String>, Error>;
+
+    /// Remove a stored artifact
+    fn remove(&self, name: &str) -> Result<(), Error>;
+}
+
+/// A backup destination writing artifacts to a directory
+#[derive(Clone, Debug)]
+pub struct FileBackupDestination {
+    dir: PathBuf,
+}
+
+impl FileBackupDestination {
+    /// Create a destination for an existing directory
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+impl BackupDestination for FileBackupDestination {
+    fn write(&self, name: &str, data: &[u8]) -> Result<(), Error> {
+        // write to a temporary file first so that a partial artifact is
+        // never mistaken for a complete one
+        let tmp = self.dir.join(format!(".{}.tmp", name));
+        fs::write(&tmp, data).map_err(err_map!(Backend, "Error writing backup artifact"))?;
+        fs::rename(&tmp, self.dir.join(name))
+            .map_err(err_map!(Backend, "Error writing backup artifact"))
+    }
+
+    fn list(&self) -> Result<Vec<String>, Error> {
+        let mut names = Vec::new();
+        for entry in
+            fs::read_dir(&self.dir).map_err(err_map!(Backend, "Error listing backup artifacts"))?
+        {
+            let entry = entry.map_err(err_map!(Backend, "Error listing backup artifacts"))?;
+            if let Some(name) = entry.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+        Ok(names)
+    }
+
+    fn remove(&self, name: &str) -> Result<(), Error> {
+        match fs::remove_file(self.dir.join(name)) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == IoErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err_msg!(Backend, "Error removing backup artifact").with_cause(err)),
+        }
+    }
+}
+
+/// The contents of each backup
+#[derive(Clone, Debug)]
+pub enum BackupSource {
+    /// A consistent copy of the store database, which remains encrypted
+    /// under the store key. Only supported by SQLite stores
+    Database,
+    /// A bundle of each listed profile, as produced by `export_profile`,
+    /// wrapped by the key resolved from `method` and `pass_key`
+    Profiles {
+        /// The profiles to export
+        profiles: Vec<String>,
+        /// The method used to resolve the wrapping key
+        method: StoreKeyMethod,
+        /// The pass key used to resolve the wrapping key
+        pass_key: PassKey<'static>,
+    },
+}
+
+/// The configuration of scheduled store backups
+#[derive(Clone, Debug)]
+pub struct BackupPolicy {
+    /// The time between backups
+    pub interval: Duration,
+    /// The location to which backups are written
+    pub destination: Arc<dyn BackupDestination>,
+    /// The number of most recent backups to retain. Older backups are
+    /// removed after each successful backup
+    pub retain_n: usize,
+    /// The contents of each backup
+    pub source: BackupSource,
+}
+
+/// The outcome of a single backup
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackupRun {
+    /// The identifier of the backup, which prefixes the artifact names
+    pub id: String,
+    /// The names of the artifacts written
+    pub artifacts: Vec<String>,
+    /// The names of the artifacts removed by the retention policy
+    pub removed: Vec<String>,
+}
+
+/// A handle to a running backup scheduler. Backups are stopped when the
+/// handle is dropped
+#[derive(Debug)]
+pub struct BackupHandle {
+    stopped: Arc<AtomicBool>,
+}
+
+impl BackupHandle {
+    /// Stop performing backups. A backup already in progress is completed
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Release);
+    }
+
+    /// Check whether the scheduler has been stopped
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for BackupHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// The run identifier of a backup artifact, if it was created by a backup
+fn artifact_run_id(name: &str) -> Option<&str> {
+    let rest = name.strip_prefix(BACKUP_PREFIX)?;
+    let end = rest.find(|c: char| !c.is_ascii_digit())?;
+    if end == 0 {
+        None
+    } else {
+        Some(&name[..BACKUP_PREFIX.len() + end])
+    }
+}
+
+/// Select the artifacts which are not among the `retain_n` most recent runs
+fn expired_artifacts(names: Vec<String>, retain_n: usize) -> Vec<String> {
+    let mut runs: Vec<&str> = names.iter().filter_map(|n| artifact_run_id(n)).collect();
+    runs.sort_unstable();
+    runs.dedup();
+    let keep = &runs[runs.len().saturating_sub(retain_n)..];
+    names
+        .iter()
+        .filter(|n| matches!(artifact_run_id(n), Some(run) if !keep.contains(&run)))
+        .cloned()
+        .collect()
+}
+
+/// Perform a single backup according to the policy, then apply retention
+pub(crate) async fn run_backup<B: Backend>(
+    store: &Store<B>,
+    policy: &BackupPolicy,
+) -> Result<BackupRun, Error> {
+    let millis = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let id = format!("{}{:016}", BACKUP_PREFIX, millis);
+    let mut artifacts = Vec::new();
+    match &policy.source {
+        BackupSource::Database => {
+            let path = std::env::temp_dir().join(format!("{}.db", uuid::Uuid::new_v4()));
+            let path_str = path
+                .to_str()
+                .ok_or_else(|| err_msg!(Unexpected, "Invalid temporary path"))?
+                .to_string();
+            store.backup_database(path_str).await?;
+            let data = unblock({
+                let path = path.clone();
+                move || {
+                    let data = fs::read(&path);
+                    fs::remove_file(&path).ok();
+                    data
+                }
+            })
+            .await
+            .map_err(err_map!(Backend, "Error reading database backup"))?;
+            artifacts.push((format!("{}.db", id), data));
+        }
+        BackupSource::Profiles {
+            profiles,
+            method,
+            pass_key,
+        } => {
+            for (idx, profile) in profiles.iter().enumerate() {
+                let bundle = store
+                    .export_profile(profile.clone(), method.clone(), pass_key.as_ref())
+                    .await?;
+                artifacts.push((format!("{}-{}.bundle", id, idx), bundle));
+            }
+        }
+    }
+
+    let destination = policy.destination.clone();
+    let retain_n = policy.retain_n;
+    let (artifacts, removed) = unblock(move || {
+        let mut names = Vec::with_capacity(artifacts.len());
+        for (name, data) in artifacts {
+            destination.write(&name, &data)?;
+            names.push(name);
+        }
+        let removed = expired_artifacts(destination.list()?, retain_n.max(1));
+        for name in &removed {
+            destination.remove(name)?;
+        }
+        Result::<_, Error>::Ok((names, removed))
+    })
+    .await?;
+
+    Ok(BackupRun {
+        id,
+        artifacts,
+        removed,
+    })
+}
+
+/// Start performing backups on the policy interval, until the returned
+/// handle is stopped or dropped
+pub(crate) fn spawn_backups<B: Backend + 'static>(
+    store: Arc<Store<B>>,
+    policy: BackupPolicy,
+) -> BackupHandle {
+    let stopped = Arc::new(AtomicBool::new(false));
+    let handle = BackupHandle {
+        stopped: stopped.clone(),
+    };
+    spawn_ok(async move {
+        loop {
+            sleep(policy.interval).await;
+            if stopped.load(Ordering::Acquire) {
+                break;
+            }
+            if let Err(err) = run_backup(&store, &policy).await {
+                warn!("Scheduled store backup failed: {}", err);
+            }
+        }
+    });
+    handle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backup_retention() {
+        let names = vec![
+            "askar-backup-0000000000000001.db".to_string(),
+            "askar-backup-0000000000000002-0.bundle".to_string(),
+            "askar-backup-0000000000000002-1.bundle".to_string(),
+            "askar-backup-0000000000000003.db".to_string(),
+            "unrelated.txt".to_string(),
+        ];
+        assert_eq!(
+            expired_artifacts(names.clone(), 2),
+            vec!["askar-backup-0000000000000001.db".to_string()]
+        );
+        assert_eq!(
+            expired_artifacts(names.clone(), 1),
+            vec![
+                "askar-backup-0000000000000001.db".to_string(),
+                "askar-backup-0000000000000002-0.bundle".to_string(),
+                "askar-backup-0000000000000002-1.bundle".to_string(),
+            ]
+        );
+        assert!(expired_artifacts(names, 5).is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+const CAP_HISTORY: &str = "history";
+const CAP_BLIND_INDEX: &str = "blind_index";
+const CAP_CHANGE_NOTIFY: &str = "change_notify";
+const CAP_COLLECTIONS: &str = "collections";
+const CAP_ENTRY_LINKS: &str = "entry_links";
+const CAP_ENTRY_METADATA: &str = "entry_metadata";
+const CAP_PARTITIONED: &str = "partitioned";
+const CAP_SOFT_DELETE: &str = "soft_delete";
+const CAP_UNIQUE_KEYS: &str = "unique_keys";
+const CAP_VALUE_CHECKSUM: &str = "value_checksum";
+
+/// The optional features supported by an opened store.
+///
+/// The features selected when provisioning are recorded in the store
+/// configuration, while the table layout, removal strategy and support for
+/// entry metadata, links, collections, unique keys and change notifications
+/// are detected when the store is opened. Stores provisioned by earlier
+/// releases report the features implied by their remaining configuration
+/// values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StoreCapabilities {
+    /// Previous versions of entries are recorded in the history table
+    pub history: bool,
+    /// Entries may be indexed by blind search tokens
+    pub blind_index: bool,
+    /// Entries may carry encrypted metadata, updated separately from the value
+    pub entry_metadata: bool,
+    /// Entries may be linked to other entries by typed relations
+    pub entry_links: bool,
+    /// Entries may be grouped into named collections, removed together
+    pub collections: bool,
+    /// Entries may be subject to unique constraints over their plaintext tags
+    pub unique_keys: bool,
+    /// Modified records are announced to other replicas of the store
+    pub change_notify: bool,
+    /// The item tables are partitioned by profile
+    pub partitioned: bool,
+    /// Entries and profiles are removed by marking them as deleted
+    pub soft_delete: bool,
+    /// A checksum is stored alongside each entry value
+    pub value_checksum: bool,
+}
+
+impl StoreCapabilities {
+    /// Check whether a capability is supported, by its configuration name
+    pub fn supports(&self, name: &str) -> bool {
+        match name {
+            CAP_HISTORY => self.history,
+            CAP_BLIND_INDEX => self.blind_index,
+            CAP_ENTRY_METADATA => self.entry_metadata,
+            CAP_ENTRY_LINKS => self.entry_links,
+            CAP_COLLECTIONS => self.collections,
+            CAP_UNIQUE_KEYS => self.unique_keys,
+            CAP_CHANGE_NOTIFY => self.change_notify,
+            CAP_PARTITIONED => self.partitioned,
+            CAP_SOFT_DELETE => self.soft_delete,
+            CAP_VALUE_CHECKSUM => self.value_checksum,
+            _ => false,
+        }
+    }
+
+    /// Get the configuration names of the supported capabilities
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (CAP_HISTORY, self.history),
+            (CAP_BLIND_INDEX, self.blind_index),
+            (CAP_ENTRY_METADATA, self.entry_metadata),
+            (CAP_ENTRY_LINKS, self.entry_links),
+            (CAP_COLLECTIONS, self.collections),
+            (CAP_UNIQUE_KEYS, self.unique_keys),
+            (CAP_CHANGE_NOTIFY, self.change_notify),
+            (CAP_PARTITIONED, self.partitioned),
+            (CAP_SOFT_DELETE, self.soft_delete),
+            (CAP_VALUE_CHECKSUM, self.value_checksum),
+        ]
+        .iter()
+        .filter_map(|(name, enabled)| if *enabled { Some(*name) } else { None })
+        .collect()
+    }
+
+    /// The capabilities selected when provisioning a store
+    pub(crate) fn provisioned(history: bool, value_checksum: bool) -> Self {
+        Self {
+            history,
+            blind_index: true,
+            value_checksum,
+            ..Default::default()
+        }
+    }
+
+    /// Parse the recorded configuration value. Names added by later releases
+    /// are ignored.
+    pub(crate) fn parse_config(value: &str) -> Self {
+        let mut caps = Self::default();
+        for name in value.split(',').map(str::trim) {
+            match name {
+                CAP_HISTORY => caps.history = true,
+                CAP_BLIND_INDEX => caps.blind_index = true,
+                CAP_VALUE_CHECKSUM => caps.value_checksum = true,
+                _ => (),
+            }
+        }
+        caps
+    }
+
+    /// Format the configuration value recorded when provisioning. The table
+    /// layout, removal strategy, entry metadata, links, collections, unique
+    /// keys and change notifications are detected when opening the store.
+    pub(crate) fn to_config(&self) -> String {
+        const DETECTED: &[&str] = &[
+            CAP_PARTITIONED,
+            CAP_SOFT_DELETE,
+            CAP_ENTRY_METADATA,
+            CAP_ENTRY_LINKS,
+            CAP_COLLECTIONS,
+            CAP_UNIQUE_KEYS,
+            CAP_CHANGE_NOTIFY,
+        ];
+        self.names()
+            .into_iter()
+            .filter(|name| !DETECTED.contains(name))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Set whether the item tables are partitioned by profile
+    pub(crate) fn with_partitioned(mut self, partitioned: bool) -> Self {
+        self.partitioned = partitioned;
+        self
+    }
+
+    /// Set whether the item table has a column for entry metadata
+    pub(crate) fn with_entry_metadata(mut self, entry_metadata: bool) -> Self {
+        self.entry_metadata = entry_metadata;
+        self
+    }
+
+    /// Set whether the store has a table for entry links
+    pub(crate) fn with_entry_links(mut self, entry_links: bool) -> Self {
+        self.entry_links = entry_links;
+        self
+    }
+
+    /// Set whether the item table has a column for entry collections
+    pub(crate) fn with_collections(mut self, collections: bool) -> Self {
+        self.collections = collections;
+        self
+    }
+
+    /// Set whether the item table has a column for unique keys
+    pub(crate) fn with_unique_keys(mut self, unique_keys: bool) -> Self {
+        self.unique_keys = unique_keys;
+        self
+    }
+
+    /// Set whether modified records are announced by the store tables
+    pub(crate) fn with_change_notify(mut self, change_notify: bool) -> Self {
+        self.change_notify = change_notify;
+        self
+    }
+
+    /// Set whether entries are removed by marking them as deleted
+    pub(crate) fn with_soft_delete(mut self, soft_delete: bool) -> Self {
+        self.soft_delete = soft_delete;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities_config_round_trip() {
+        let caps = StoreCapabilities::provisioned(true, false)
+            .with_partitioned(true)
+            .with_soft_delete(true)
+            .with_entry_metadata(true)
+            .with_entry_links(true)
+            .with_collections(true)
+            .with_unique_keys(true)
+            .with_change_notify(true);
+        assert_eq!(caps.to_config(), "history,blind_index");
+        assert_eq!(
+            StoreCapabilities::parse_config(&caps.to_config()),
+            StoreCapabilities::provisioned(true, false)
+        );
+        assert!(caps.supports("partitioned"));
+        assert!(caps.supports("entry_metadata"));
+        assert!(caps.supports("entry_links"));
+        assert!(caps.supports("collections"));
+        assert!(caps.supports("unique_keys"));
+        assert!(caps.supports("change_notify"));
+        assert!(!caps.supports("value_checksum"));
+        assert!(!caps.supports("unknown"));
+
+        // names recorded by later releases are ignored
+        let caps = StoreCapabilities::parse_config("value_checksum, future_feature");
+        assert_eq!(caps.names(), vec!["value_checksum"]);
+    }
+}
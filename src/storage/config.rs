@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use super::{options::Options, store::Store};
+use crate::{
+    backend::{any::AnyBackend, Durability, ManageBackend},
+    error::Error,
+    protect::{PassKey, StoreKeyMethod},
+};
+
+/// The settings used to open a store, as read from an application
+/// configuration file or the environment.
+///
+/// Each setting given as a field is passed to the backend as the URI query
+/// parameter of the same name, and specifying a parameter both as a field and
+/// in the URI is an error. Other backend parameters may be given in `options`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+    /// The backend URI, such as `sqlite://path/to/db` or `postgres://host/db`
+    pub uri: String,
+    /// The method used to wrap the store key, such as `kdf:argon2i` or `raw`.
+    /// When provisioning, the default method is used if none is given
+    pub key_method: Option<String>,
+    /// The default profile of the store
+    pub profile: Option<String>,
+    /// Provision the store if it has not already been created
+    pub provision: bool,
+    /// The maximum number of pooled connections
+    pub max_connections: Option<u32>,
+    /// The minimum number of pooled connections
+    pub min_connections: Option<u32>,
+    /// The timeout for establishing a connection, in seconds
+    pub connect_timeout: Option<u64>,
+    /// The time before an idle connection is closed, in seconds
+    pub idle_timeout: Option<u64>,
+    /// The longest running time of a single query, in milliseconds
+    pub statement_timeout: Option<u64>,
+    /// The longest time taken to open the store, in milliseconds
+    pub open_timeout: Option<u64>,
+    /// The default timeout for session operations, in milliseconds
+    pub session_timeout: Option<u64>,
+    /// The duration of a session operation after which a warning is
+    /// logged, in milliseconds
+    pub slow_operation_threshold: Option<u64>,
+    /// Record the previous versions of entries when provisioning
+    pub history: Option<bool>,
+    /// Store a checksum alongside each entry value when provisioning
+    pub value_checksum: Option<bool>,
+    /// The durability level: `full`, `normal` or `relaxed`
+    pub durability: Option<String>,
+    /// Record removed entries for synchronization between stores
+    pub change_tracking: bool,
+    /// Additional backend parameters
+    pub options: HashMap<String, String>,
+}
+
+impl StoreConfig {
+    /// Create a new configuration for a backend URI
+    pub fn new(uri: impl Into<String>) -> Self {
+        Self {
+            uri: uri.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Load a configuration from the environment variables beginning with
+    /// `prefix`, such as `ASKAR_URI` and `ASKAR_MAX_CONNECTIONS` for the
+    /// prefix `ASKAR_`. Variables which do not correspond to a field are
+    /// added to the backend `options`
+    pub fn from_env(prefix: &str) -> Result<Self, Error> {
+        let mut config = Self::default();
+        for (name, value) in std::env::vars_os() {
+            // variables which are not valid unicode cannot be settings
+            let (name, value) = match (name.into_string(), value.into_string()) {
+                (Ok(name), Ok(value)) => (name, value),
+                _ => continue,
+            };
+            if let Some(name) = name.strip_prefix(prefix) {
+                if !name.is_empty() {
+                    config.set(&name.to_ascii_lowercase(), &value)?;
+                }
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Update a setting by its field name, parsing the value
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        fn parse<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, Error> {
+            value
+                .parse()
+                .map_err(|_| err_msg!(Input, "Error parsing '{}' setting", name))
+        }
+        fn parse_bool(name: &str, value: &str) -> Result<bool, Error> {
+            match value {
+                "1" | "true" => Ok(true),
+                "0" | "false" => Ok(false),
+                _ => Err(err_msg!(Input, "Error parsing '{}' setting", name)),
+            }
+        }
+        match name {
+            "uri" => self.uri = value.to_string(),
+            "key_method" => self.key_method = Some(value.to_string()),
+            "profile" => self.profile = Some(value.to_string()),
+            "provision" => self.provision = parse_bool(name, value)?,
+            "max_connections" => self.max_connections = Some(parse(name, value)?),
+            "min_connections" => self.min_connections = Some(parse(name, value)?),
+            "connect_timeout" => self.connect_timeout = Some(parse(name, value)?),
+            "idle_timeout" => self.idle_timeout = Some(parse(name, value)?),
+            "statement_timeout" => self.statement_timeout = Some(parse(name, value)?),
+            "open_timeout" => self.open_timeout = Some(parse(name, value)?),
+            "session_timeout" => self.session_timeout = Some(parse(name, value)?),
+            "slow_operation_threshold" => self.slow_operation_threshold = Some(parse(name, value)?),
+            "history" => self.history = Some(parse_bool(name, value)?),
+            "value_checksum" => self.value_checksum = Some(parse_bool(name, value)?),
+            "durability" => self.durability = Some(value.to_string()),
+            "change_tracking" => self.change_tracking = parse_bool(name, value)?,
+            _ => {
+                self.options.insert(name.to_string(), value.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the configuration for missing, invalid or conflicting settings
+    pub fn validate(&self) -> Result<(), Error> {
+        self.to_uri()?;
+        self.key_method()?;
+        Ok(())
+    }
+
+    fn key_method(&self) -> Result<Option<StoreKeyMethod>, Error> {
+        self.key_method
+            .as_deref()
+            .map(StoreKeyMethod::parse_uri)
+            .transpose()
+    }
+
+    /// Merge the settings into the query parameters of the backend URI
+    pub(crate) fn to_uri(&self) -> Result<String, Error> {
+        if self.uri.is_empty() {
+            return Err(err_msg!(Input, "Missing store URI"));
+        }
+        let mut opts = Options::parse_uri(&self.uri)?;
+        match opts.schema.as_ref() {
+            "postgres" | "sqlite" => (),
+            "" => return Err(err_msg!(Input, "Missing backend in store URI")),
+            other => return Err(err_msg!(Unsupported, "Invalid backend: {}", other)),
+        }
+        if let Some(durability) = self.durability.as_deref() {
+            Durability::parse(durability)?;
+        }
+        if let (Some(min), Some(max)) = (self.min_connections, self.max_connections) {
+            if min > max {
+                return Err(err_msg!(
+                    Input,
+                    "The 'min_connections' setting exceeds 'max_connections'"
+                ));
+            }
+        }
+        let flag = |enabled: bool| if enabled { "1" } else { "0" }.to_string();
+        let params = [
+            (
+                "max_connections",
+                self.max_connections.map(|v| v.to_string()),
+            ),
+            (
+                "min_connections",
+                self.min_connections.map(|v| v.to_string()),
+            ),
+            (
+                "connect_timeout",
+                self.connect_timeout.map(|v| v.to_string()),
+            ),
+            ("idle_timeout", self.idle_timeout.map(|v| v.to_string())),
+            (
+                "statement_timeout",
+                self.statement_timeout.map(|v| v.to_string()),
+            ),
+            ("open_timeout", self.open_timeout.map(|v| v.to_string())),
+            ("history", self.history.map(flag)),
+            ("value_checksum", self.value_checksum.map(flag)),
+            ("durability", self.durability.clone()),
+        ];
+        let options = self
+            .options
+            .iter()
+            .map(|(name, value)| (name.as_str(), Some(value.clone())));
+        for (name, value) in params.iter().cloned().chain(options) {
+            if let Some(value) = value {
+                if opts.query.insert(name.to_string(), value).is_some() {
+                    return Err(err_msg!(
+                        Input,
+                        "The '{}' parameter is specified more than once",
+                        name
+                    ));
+                }
+            }
+        }
+        Ok(opts.into_uri())
+    }
+}
+
+impl Store<AnyBackend> {
+    /// Open a store using a set of configuration settings, provisioning it
+    /// first if `provision` is set
+    pub async fn from_config(config: &StoreConfig, pass_key: PassKey<'_>) -> Result<Self, Error> {
+        let uri = config.to_uri()?;
+        let method = config.key_method()?;
+        let profile = config.profile.as_deref();
+        let store = if config.provision {
+            uri.as_str()
+                .provision_backend(method.unwrap_or_default(), pass_key, profile, false)
+                .await?
+        } else {
+            uri.as_str().open_backend(method, pass_key, profile).await?
+        };
+        Ok(store
+            .with_timeout(config.session_timeout.map(Duration::from_millis))
+            .with_slow_operation_threshold(
+                config.slow_operation_threshold.map(Duration::from_millis),
+            )
+            .with_change_tracking(config.change_tracking))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_deserialize() {
+        let config: StoreConfig = serde_json::from_str(
+            r#"{
+                "uri": "postgres://user@host/db",
+                "key_method": "kdf:argon2i:int",
+                "max_connections": 5,
+                "durability": "relaxed",
+                "change_tracking": true,
+                "options": {"schema": "agent"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.max_connections, Some(5));
+        assert!(config.change_tracking);
+        config.validate().unwrap();
+        let opts = Options::parse_uri(&config.to_uri().unwrap()).unwrap().query;
+        assert_eq!(opts.get("max_connections").map(String::as_str), Some("5"));
+        assert_eq!(opts.get("durability").map(String::as_str), Some("relaxed"));
+        assert_eq!(opts.get("schema").map(String::as_str), Some("agent"));
+
+        assert!(serde_json::from_str::<StoreConfig>(r#"{"uri": "", "unknown": 1}"#).is_err());
+    }
+
+    #[test]
+    fn config_validate() {
+        assert!(StoreConfig::default().validate().is_err());
+        assert!(StoreConfig::new("mysql://host/db").validate().is_err());
+        let mut config = StoreConfig::new("sqlite://:memory:?max_connections=2");
+        config.validate().unwrap();
+        // conflicts with the URI parameter
+        config.set("max_connections", "4").unwrap();
+        assert!(config.validate().is_err());
+
+        let mut config = StoreConfig::new("sqlite://:memory:");
+        config.set("durability", "maximum").unwrap();
+        assert!(config.validate().is_err());
+        assert!(config.set("history", "yes").is_err());
+        config.set("busy_timeout", "500").unwrap();
+        assert_eq!(
+            config.options.get("busy_timeout").map(String::as_str),
+            Some("500")
+        );
+
+        let mut config = StoreConfig::new("sqlite://:memory:");
+        config.key_method = Some("unknown".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn config_from_env() {
+        std::env::set_var("ASKAR_CONFIG_TEST_URI", "sqlite://:memory:");
+        std::env::set_var("ASKAR_CONFIG_TEST_SESSION_TIMEOUT", "250");
+        std::env::set_var("ASKAR_CONFIG_TEST_BUSY_TIMEOUT", "500");
+        let config = StoreConfig::from_env("ASKAR_CONFIG_TEST_").unwrap();
+        assert_eq!(config.uri, "sqlite://:memory:");
+        assert_eq!(config.session_timeout, Some(250));
+        assert_eq!(
+            config.options.get("busy_timeout").map(String::as_str),
+            Some("500")
+        );
+    }
+}
@@ -1,20 +1,26 @@
 use std::{
     borrow::Cow,
-    fmt::{self, Debug, Formatter},
+    collections::{BTreeMap, VecDeque},
+    convert::TryFrom,
+    fmt::{self, Debug, Display, Formatter},
     pin::Pin,
     str::FromStr,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
 };
 
+use async_stream::stream;
 use futures_lite::stream::{Stream, StreamExt};
 use serde::{
     de::{Error as SerdeError, MapAccess, SeqAccess, Visitor},
     ser::SerializeMap,
     Deserialize, Deserializer, Serialize, Serializer,
 };
+use sha2::{Digest, Sha256};
 use zeroize::Zeroize;
 
 use super::wql;
-use crate::{crypto::buffer::SecretBytes, error::Error};
+use crate::{crypto::buffer::SecretBytes, error::Error, future};
 
 pub(crate) fn sorted_tags(tags: &Vec<EntryTag>) -> Vec<&EntryTag> {
     if tags.len() > 0 {
@@ -40,6 +46,10 @@ pub struct Entry {
 
     /// Tags associated with the entry record
     pub tags: Vec<EntryTag>,
+
+    seq: Option<EntrySeq>,
+
+    expiry: Option<SystemTime>,
 }
 
 impl Entry {
@@ -56,12 +66,99 @@ impl Entry {
             name: name.into(),
             value: value.into(),
             tags,
+            seq: None,
+            expiry: None,
         }
     }
 
+    pub(crate) fn with_seq(mut self, seq: EntrySeq) -> Self {
+        self.seq.replace(seq);
+        self
+    }
+
+    pub(crate) fn with_expiry(mut self, expiry: Option<SystemTime>) -> Self {
+        self.expiry = expiry;
+        self
+    }
+
+    /// Get the insertion sequence number of a record loaded from the store
+    pub fn seq(&self) -> Option<EntrySeq> {
+        self.seq
+    }
+
+    /// Get the expiry time of a record loaded from the store, if any. For a
+    /// record with a sliding expiry, this reflects the extension applied when
+    /// the record was fetched
+    pub fn expiry(&self) -> Option<SystemTime> {
+        self.expiry
+    }
+
+    /// Get the time remaining before a record loaded from the store expires.
+    /// Returns `None` for a record without an expiry
+    pub fn ttl(&self) -> Option<Duration> {
+        self.expiry
+            .map(|expiry| expiry.duration_since(SystemTime::now()).unwrap_or_default())
+    }
+
+    /// Get the category of the record as bytes, decoding the string form of
+    /// a binary category
+    pub fn category_bytes(&self) -> Cow<'_, [u8]> {
+        match decode_binary_identifier(&self.category) {
+            Some(id) => Cow::Owned(id),
+            None => Cow::Borrowed(self.category.as_bytes()),
+        }
+    }
+
+    /// Get the name of the record as bytes, decoding the string form of a
+    /// binary name
+    pub fn name_bytes(&self) -> Cow<'_, [u8]> {
+        match decode_binary_identifier(&self.name) {
+            Some(id) => Cow::Owned(id),
+            None => Cow::Borrowed(self.name.as_bytes()),
+        }
+    }
+
+    /// Find the first tag with a given name
+    pub fn find_tag(&self, name: &str) -> Option<&EntryTag> {
+        self.tags.iter().find(|tag| tag.name() == name)
+    }
+
+    /// Get the value of the first tag with a given name
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.find_tag(name).map(EntryTag::value)
+    }
+
+    /// Iterate the values of all tags with a given name
+    pub fn tag_values<'e>(&'e self, name: &'e str) -> impl Iterator<Item = &'e str> + 'e {
+        self.tags
+            .iter()
+            .filter(move |tag| tag.name() == name)
+            .map(EntryTag::value)
+    }
+
+    /// Parse the value of the first tag with a given name
+    pub fn tag_parse<T: FromStr>(&self, name: &str) -> Result<Option<T>, Error> {
+        self.find_tag(name).map(EntryTag::parse_value).transpose()
+    }
+
+    /// Collect the tag values into a map indexed by tag name
+    pub fn tag_map(&self) -> BTreeMap<&str, Vec<&str>> {
+        let mut map = BTreeMap::<_, Vec<_>>::new();
+        for tag in &self.tags {
+            map.entry(tag.name()).or_default().push(tag.value());
+        }
+        map
+    }
+
     pub(crate) fn sorted_tags(&self) -> Vec<&EntryTag> {
         sorted_tags(&self.tags)
     }
+
+    /// Compute the hash of the value and tags of the record, which may be
+    /// passed to `Session::replace_if_unmodified`
+    pub fn content_hash(&self) -> EntryHash {
+        EntryHash::compute(&self.value, &self.tags)
+    }
 }
 
 impl PartialEq for Entry {
@@ -73,10 +170,325 @@ impl PartialEq for Entry {
     }
 }
 
+/// The position of a record in the write order of the store.
+///
+/// A record is assigned a new sequence number whenever it is inserted or
+/// replaced, and scans return records in sequence order. The value is opaque,
+/// but may be persisted in its string form in order to resume a scan with
+/// `Store::scan_since`. The SQLite backend may reassign the sequence number of
+/// the most recently written record after it has been removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EntrySeq(i64);
+
+impl EntrySeq {
+    pub(crate) fn new(seq: i64) -> Self {
+        Self(seq)
+    }
+
+    pub(crate) fn value(self) -> i64 {
+        self.0
+    }
+}
+
+impl Display for EntrySeq {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl FromStr for EntrySeq {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse()
+            .map(Self)
+            .map_err(|_| err_msg!(Input, "Invalid entry sequence number"))
+    }
+}
+
+/// The identity of a stored version of a record.
+///
+/// The identity is returned by `Session::insert_returning_id`, and allows the
+/// record to be fetched without encrypting its category and name. It remains
+/// valid until the record is replaced or removed. The value is opaque, but may
+/// be persisted in its string form.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EntryId(i64);
+
+impl EntryId {
+    pub(crate) fn new(id: i64) -> Self {
+        Self(id)
+    }
+
+    pub(crate) fn value(self) -> i64 {
+        self.0
+    }
+}
+
+impl Display for EntryId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl FromStr for EntryId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse()
+            .map(Self)
+            .map_err(|_| err_msg!(Input, "Invalid entry identity"))
+    }
+}
+
+/// A SHA-256 digest of the value and tags of a record, used to detect
+/// concurrent modifications. Tags are hashed in sorted order, so that the
+/// hash does not depend on the order in which they were provided.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EntryHash([u8; 32]);
+
+impl EntryHash {
+    pub(crate) fn compute(value: &[u8], tags: &[EntryTag]) -> Self {
+        let mut tags = tags.iter().collect::<Vec<_>>();
+        tags.sort();
+        let mut hasher = Sha256::new();
+        hasher.update(&(value.len() as u64).to_be_bytes());
+        hasher.update(value);
+        for tag in tags {
+            hasher.update(&[tag.is_plaintext() as u8]);
+            for part in &[tag.name(), tag.value()] {
+                hasher.update(&(part.len() as u64).to_be_bytes());
+                hasher.update(part.as_bytes());
+            }
+        }
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&hasher.finalize()[..]);
+        Self(hash)
+    }
+
+    /// Access the bytes of the digest
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl Debug for EntryHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("EntryHash")
+            .field(&hex::encode(&self.0))
+            .finish()
+    }
+}
+
+impl Display for EntryHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(&self.0))
+    }
+}
+
+impl FromStr for EntryHash {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut hash = [0u8; 32];
+        hex::decode_to_slice(s, &mut hash).map_err(|_| err_msg!(Input, "Invalid entry hash"))?;
+        Ok(Self(hash))
+    }
+}
+
+/// A historical version of a record in the store
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryVersion {
+    /// The record as it existed during this version
+    pub entry: Entry,
+
+    /// The time at which this version was written
+    pub valid_from: SystemTime,
+
+    /// The time at which this version was replaced or removed, if any
+    pub valid_to: Option<SystemTime>,
+}
+
+/// A typed relation from one record to another record of the same kind
+/// and profile
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EntryLink {
+    /// The type of the relation, such as `connection`
+    pub rel_type: String,
+
+    /// The category of the linked record
+    pub category: String,
+
+    /// The name of the linked record
+    pub name: String,
+}
+
+impl EntryLink {
+    /// Create a new `EntryLink`
+    pub fn new<R: Into<String>, C: Into<String>, N: Into<String>>(
+        rel_type: R,
+        category: C,
+        name: N,
+    ) -> Self {
+        Self {
+            rel_type: rel_type.into(),
+            category: category.into(),
+            name: name.into(),
+        }
+    }
+}
+
+/// The kind of an entry record.
+///
+/// Entries of each kind are stored separately, so that records managed by the
+/// store and by applications layered upon it do not conflict
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum EntryKind {
-    Kms = 1,
-    Item = 2,
+    /// Keys managed by the store
+    Kms,
+    /// Generic records
+    Item,
+    /// Messages queued in the transactional outbox of a profile
+    Outbox,
+    /// An application-defined kind, stored using the code `256 + n`
+    Custom(u8),
+}
+
+impl EntryKind {
+    /// The first numeric code reserved for application-defined kinds
+    pub const CUSTOM_CODE_START: i16 = 256;
+
+    /// Get the numeric code for the entry kind, as it is persisted by the store
+    pub fn code(self) -> i16 {
+        match self {
+            Self::Kms => 1,
+            Self::Item => 2,
+            Self::Outbox => 3,
+            Self::Custom(n) => Self::CUSTOM_CODE_START + n as i16,
+        }
+    }
+
+    /// Look up the entry kind for a persisted numeric code
+    pub fn from_code(code: i16) -> Option<Self> {
+        match code {
+            1 => Some(Self::Kms),
+            2 => Some(Self::Item),
+            3 => Some(Self::Outbox),
+            c if c >= Self::CUSTOM_CODE_START && c - Self::CUSTOM_CODE_START <= u8::MAX as i16 => {
+                Some(Self::Custom((c - Self::CUSTOM_CODE_START) as u8))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The separator between the namespace and the category of a record, within
+/// the category value which is encrypted and stored
+const NAMESPACE_SEPARATOR: char = '\0';
+
+/// Check that a namespace may be used to partition the records of a profile
+pub(crate) fn check_namespace(namespace: &str) -> Result<(), Error> {
+    if namespace.is_empty() {
+        Err(err_msg!(Input, "Namespace must not be empty"))
+    } else if namespace.contains(NAMESPACE_SEPARATOR) {
+        Err(err_msg!(Input, "Namespace must not contain NUL characters"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check that a record category cannot be mistaken for a namespaced category
+pub(crate) fn check_category(category: &str) -> Result<(), Error> {
+    if category.contains(NAMESPACE_SEPARATOR) {
+        Err(err_msg!(Input, "Category must not contain NUL characters"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Combine a namespace and record category into the stored category
+pub(crate) fn namespaced_category<'c>(namespace: Option<&str>, category: &'c str) -> Cow<'c, str> {
+    match namespace {
+        Some(namespace) => Cow::Owned(format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, category)),
+        None => Cow::Borrowed(category),
+    }
+}
+
+/// Split a stored category into its namespace and record category
+pub(crate) fn split_namespace(category: &str) -> (Option<&str>, &str) {
+    match category.find(NAMESPACE_SEPARATOR) {
+        Some(pos) => (Some(&category[..pos]), &category[(pos + 1)..]),
+        None => (None, category),
+    }
+}
+
+/// The prefix of the string form of a binary category or name. This is a
+/// Unicode noncharacter, which is reserved for internal use
+const BINARY_ID_PREFIX: char = '\u{FDD0}';
+
+/// The offset applied to each byte of a binary identifier in its string
+/// form, avoiding control characters including the namespace separator
+const BINARY_ID_OFFSET: u32 = 0x100;
+
+/// Get the string form of a binary category or name, which is accepted by
+/// all methods taking a category or name.
+///
+/// Identifiers which are valid UTF-8 are returned unchanged. Other values are
+/// converted to a reserved representation which is decoded before the
+/// identifier is encrypted, so that the stored value is the original binary
+/// identifier. Binary categories used within a session namespace are stored
+/// in their string form.
+pub fn binary_identifier(id: &[u8]) -> Cow<'_, str> {
+    match std::str::from_utf8(id) {
+        Ok(id) if !id.starts_with(BINARY_ID_PREFIX) => Cow::Borrowed(id),
+        _ => {
+            let mut result = String::with_capacity(3 + id.len() * 2);
+            result.push(BINARY_ID_PREFIX);
+            result.extend(
+                id.iter()
+                    .map(|b| char::from_u32(BINARY_ID_OFFSET + *b as u32).unwrap()),
+            );
+            Cow::Owned(result)
+        }
+    }
+}
+
+/// Decode the string form of a binary category or name, returning `None` if
+/// the identifier is not in the reserved binary representation
+pub(crate) fn decode_binary_identifier(id: &str) -> Option<Vec<u8>> {
+    let mut chars = id.chars();
+    if chars.next() != Some(BINARY_ID_PREFIX) {
+        return None;
+    }
+    chars
+        .map(|c| match (c as u32).checked_sub(BINARY_ID_OFFSET) {
+            Some(b) if b < 0x100 => Some(b as u8),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Prepare a category or name for encryption, decoding the string form of a
+/// binary identifier
+pub(crate) fn binary_identifier_input(input: SecretBytes) -> SecretBytes {
+    match std::str::from_utf8(input.as_ref())
+        .ok()
+        .and_then(decode_binary_identifier)
+    {
+        Some(id) => SecretBytes::from(id),
+        None => input,
+    }
+}
+
+/// Convert a decrypted category or name to its string form
+pub(crate) fn binary_identifier_output(output: Vec<u8>) -> String {
+    match String::from_utf8(output) {
+        Ok(id) if !id.starts_with(BINARY_ID_PREFIX) => id,
+        Ok(id) => binary_identifier(id.as_bytes()).into_owned(),
+        Err(err) => binary_identifier(err.as_bytes()).into_owned(),
+    }
 }
 
 /// Supported operations for entries in the store
@@ -90,7 +502,120 @@ pub enum EntryOperation {
     Remove,
 }
 
-/// A tag on an entry record in the store
+/// The parts of each record returned by a scan or query. Values which are
+/// not selected are neither read from the database nor decrypted, and are
+/// returned as empty
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Projection {
+    /// Only the record names
+    Names,
+    /// The record names and tags
+    NamesAndTags,
+    /// The complete records
+    Full,
+}
+
+impl Projection {
+    /// Check whether record values are selected
+    pub fn includes_value(&self) -> bool {
+        *self == Self::Full
+    }
+
+    /// Check whether record tags are selected
+    pub fn includes_tags(&self) -> bool {
+        *self != Self::Names
+    }
+
+    fn is_full(&self) -> bool {
+        *self == Self::Full
+    }
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Self::Full
+    }
+}
+
+/// Bounds on the creation and update times of the records returned by a scan.
+///
+/// The times are taken from the entry history, so a store must be opened
+/// with history enabled in order to apply them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryFilter {
+    /// Only return records created after the given time. A record which is
+    /// removed and inserted again is considered to be created again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_after: Option<SystemTime>,
+    /// Only return records updated at or after the given time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_since: Option<SystemTime>,
+    /// Only return records last updated before the given time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_before: Option<SystemTime>,
+}
+
+impl HistoryFilter {
+    /// Check whether the filter places no bounds on the records
+    pub fn is_empty(&self) -> bool {
+        self.created_after.is_none()
+            && self.updated_since.is_none()
+            && self.updated_before.is_none()
+    }
+}
+
+/// The expiry policy of an entry written to the store
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryExpiry {
+    /// The entry expires a number of milliseconds after it is written
+    Relative(i64),
+    /// The entry expires at a fixed time, in milliseconds since the Unix epoch
+    Absolute(i64),
+    /// The entry expires a number of milliseconds after it is written, and
+    /// the expiry is extended by the same interval each time it is fetched
+    Sliding(i64),
+}
+
+impl EntryExpiry {
+    /// Create an expiry from a number of milliseconds after the entry is written
+    pub fn from_ms(expiry_ms: Option<i64>) -> Option<Self> {
+        expiry_ms.map(Self::Relative)
+    }
+
+    /// Create an absolute expiry from a system time
+    pub fn at(time: SystemTime) -> Result<Self, Error> {
+        let since = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|_| err_msg!(Input, "Invalid expiry time"))?;
+        i64::try_from(since.as_millis())
+            .map(Self::Absolute)
+            .map_err(|_| err_msg!(Input, "Invalid expiry time"))
+    }
+
+    /// Accessor for the interval by which the expiry is extended when the
+    /// entry is fetched
+    pub fn sliding_ms(&self) -> Option<i64> {
+        match self {
+            Self::Sliding(ms) => Some(*ms),
+            _ => None,
+        }
+    }
+}
+
+/// The storage format of an entry tag
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TagKind {
+    /// The tag name and value are encrypted, supporting only equality queries
+    Encrypted,
+    /// The tag value is stored in plaintext, supporting ordered comparison
+    Plaintext,
+}
+
+/// A tag on an entry record in the store.
+///
+/// A record may have several tags with the same name and different values,
+/// while a repeated tag with the same name and value is only stored once
 #[derive(Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Zeroize)]
 pub enum EntryTag {
     /// An entry tag to be stored encrypted
@@ -100,6 +625,27 @@ pub enum EntryTag {
 }
 
 impl EntryTag {
+    /// Create a new tag of the given kind
+    pub fn new(kind: TagKind, name: impl Into<String>, value: impl Into<String>) -> Self {
+        match kind {
+            TagKind::Encrypted => Self::Encrypted(name.into(), value.into()),
+            TagKind::Plaintext => Self::Plaintext(name.into(), value.into()),
+        }
+    }
+
+    /// Accessor for the tag storage format
+    pub fn kind(&self) -> TagKind {
+        match self {
+            Self::Encrypted(..) => TagKind::Encrypted,
+            Self::Plaintext(..) => TagKind::Plaintext,
+        }
+    }
+
+    /// Check if the tag is stored in plaintext
+    pub fn is_plaintext(&self) -> bool {
+        matches!(self, Self::Plaintext(..))
+    }
+
     /// Accessor for the tag name
     pub fn name(&self) -> &str {
         match self {
@@ -134,6 +680,13 @@ impl EntryTag {
         }
     }
 
+    /// Parse the tag value as a given type
+    pub fn parse_value<T: FromStr>(&self) -> Result<T, Error> {
+        self.value()
+            .parse()
+            .map_err(|_| err_msg!(Input, "Error parsing value of tag '{}'", self.name()))
+    }
+
     /// Unwrap the tag value
     pub(crate) fn into_value(self) -> String {
         match self {
@@ -344,28 +897,46 @@ pub(crate) struct EncEntryTag {
     pub plaintext: bool,
 }
 
-/// A WQL filter used to restrict record queries
+/// A WQL filter used to restrict record queries.
+///
+/// Plaintext tag values may be compared without regard to case, or after
+/// NFC normalization, using `case_insensitive` and `unicode_normalized`.
+/// These options apply to the whole filter, including filters combined with
+/// it, and have no effect on encrypted tags.
+///
+/// When a record has several values for a tag name, each comparison matches
+/// if any one of the values satisfies it. An `IN` filter matches records
+/// having any of the given values, while `is_all` matches records having all
+/// of them. Negated comparisons are likewise applied to each value, so that
+/// a negated equality matches records having any other value for the tag.
 #[derive(Clone, Debug, PartialEq, Eq)]
-#[repr(transparent)]
 pub struct TagFilter {
     pub(crate) query: wql::Query,
+    pub(crate) fold_case: bool,
+    pub(crate) normalize: bool,
 }
 
 impl TagFilter {
+    fn combine(each: Vec<TagFilter>, f: impl FnOnce(Vec<wql::Query>) -> wql::Query) -> Self {
+        let fold_case = each.iter().any(|filter| filter.fold_case);
+        let normalize = each.iter().any(|filter| filter.normalize);
+        Self {
+            query: f(each.into_iter().map(|filter| filter.query).collect()),
+            fold_case,
+            normalize,
+        }
+    }
+
     /// Combine multiple tag filters using the `AND` operator
     #[inline]
     pub fn all_of(each: Vec<TagFilter>) -> Self {
-        Self {
-            query: wql::Query::And(unsafe { std::mem::transmute(each) }),
-        }
+        Self::combine(each, wql::Query::And)
     }
 
     /// Combine multiple tag filters using the `OR` operator
     #[inline]
     pub fn any_of(each: Vec<TagFilter>) -> Self {
-        Self {
-            query: wql::Query::Or(unsafe { std::mem::transmute(each) }),
-        }
+        Self::combine(each, wql::Query::Or)
     }
 
     /// Get the inverse of a tag filter
@@ -373,90 +944,150 @@ impl TagFilter {
     pub fn not(filter: TagFilter) -> Self {
         Self {
             query: wql::Query::Not(Box::new(filter.query)),
+            ..filter
         }
     }
 
+    /// Compare plaintext tag values without regard to case. Values are
+    /// lowercased by the database, which may only fold ASCII characters
+    #[inline]
+    pub fn case_insensitive(mut self) -> Self {
+        self.fold_case = true;
+        self
+    }
+
+    /// Apply NFC normalization to the plaintext tag values in the filter.
+    /// Plaintext tag values are normalized when they are written, so that
+    /// equivalent values in other normal forms are matched
+    #[inline]
+    pub fn unicode_normalized(mut self) -> Self {
+        self.normalize = true;
+        self
+    }
+
     /// Create an equality comparison tag filter
     #[inline]
     pub fn is_eq(name: impl Into<String>, value: impl Into<String>) -> Self {
-        Self {
-            query: wql::Query::Eq(name.into(), value.into()),
-        }
+        Self::from(wql::Query::Eq(name.into(), value.into()))
     }
 
     /// Create an inequality comparison tag filter
     #[inline]
     pub fn is_not_eq(name: impl Into<String>, value: impl Into<String>) -> Self {
-        Self {
-            query: wql::Query::Neq(name.into(), value.into()),
-        }
+        Self::from(wql::Query::Neq(name.into(), value.into()))
     }
 
     /// Create an greater-than comparison tag filter
     #[inline]
     pub fn is_gt(name: impl Into<String>, value: impl Into<String>) -> Self {
-        Self {
-            query: wql::Query::Gt(name.into(), value.into()),
-        }
+        Self::from(wql::Query::Gt(name.into(), value.into()))
     }
 
     /// Create an greater-than-or-equal comparison tag filter
     #[inline]
     pub fn is_gte(name: impl Into<String>, value: impl Into<String>) -> Self {
-        Self {
-            query: wql::Query::Gte(name.into(), value.into()),
-        }
+        Self::from(wql::Query::Gte(name.into(), value.into()))
     }
 
     /// Create an less-than comparison tag filter
     #[inline]
     pub fn is_lt(name: impl Into<String>, value: impl Into<String>) -> Self {
-        Self {
-            query: wql::Query::Lt(name.into(), value.into()),
-        }
+        Self::from(wql::Query::Lt(name.into(), value.into()))
     }
 
     /// Create an less-than-or-equal comparison tag filter
     #[inline]
     pub fn is_lte(name: impl Into<String>, value: impl Into<String>) -> Self {
-        Self {
-            query: wql::Query::Lte(name.into(), value.into()),
-        }
+        Self::from(wql::Query::Lte(name.into(), value.into()))
     }
 
     /// Create a LIKE comparison tag filter
     #[inline]
     pub fn is_like(name: impl Into<String>, value: impl Into<String>) -> Self {
-        Self {
-            query: wql::Query::Like(name.into(), value.into()),
-        }
+        Self::from(wql::Query::Like(name.into(), value.into()))
     }
 
     /// Create an IN comparison tag filter for a set of tag values
     #[inline]
     pub fn is_in(name: impl Into<String>, values: Vec<String>) -> Self {
-        Self {
-            query: wql::Query::In(name.into(), values),
+        Self::from(wql::Query::In(name.into(), values))
+    }
+
+    /// Create a tag filter matching records which have all of a set of values
+    /// for a tag name. Each value is compared separately, so that an empty
+    /// set of values matches all records
+    pub fn is_all(name: impl Into<String>, values: Vec<String>) -> Self {
+        let name = name.into();
+        let mut each = Vec::with_capacity(values.len());
+        for value in values {
+            let query = wql::Query::Eq(name.clone(), value);
+            if !each.contains(&query) {
+                each.push(query);
+            }
         }
+        Self::from(wql::Query::And(each))
     }
 
     /// Create an EXISTS tag filter for a set of tag names
     #[inline]
     pub fn exist(names: Vec<String>) -> Self {
-        Self {
-            query: wql::Query::Exist(names),
-        }
+        Self::from(wql::Query::Exist(names))
     }
 
     /// Convert the tag filter to JSON format
     pub fn to_string(&self) -> Result<String, Error> {
         serde_json::to_string(&self.query).map_err(err_map!("Error encoding tag filter"))
     }
+
+    /// Check the tag filter against a set of complexity limits, after
+    /// redundant nesting has been removed
+    pub(crate) fn check_limits(&self, limits: &QueryLimits) -> Result<(), Error> {
+        let (depth, clauses) =
+            wql::tags::query_complexity(&wql::tags::flatten_query(self.query.clone()));
+        if depth > limits.max_depth {
+            Err(err_msg!(
+                QueryTooComplex,
+                "Tag filter exceeds the maximum nesting depth of {}",
+                limits.max_depth
+            ))
+        } else if clauses > limits.max_clauses {
+            Err(err_msg!(
+                QueryTooComplex,
+                "Tag filter exceeds the maximum of {} clauses",
+                limits.max_clauses
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Limits on the complexity of the tag filters accepted by a store, protecting
+/// the database from queries with excessive numbers of subqueries
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueryLimits {
+    /// The maximum nesting depth of `$and`, `$or` and `$not` conditions
+    pub max_depth: usize,
+    /// The maximum number of tag comparisons
+    pub max_clauses: usize,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 8,
+            max_clauses: 64,
+        }
+    }
 }
 
 impl From<wql::Query> for TagFilter {
     fn from(query: wql::Query) -> Self {
-        Self { query }
+        Self {
+            query,
+            fold_case: false,
+            normalize: false,
+        }
     }
 }
 
@@ -464,47 +1095,304 @@ impl FromStr for TagFilter {
     type Err = Error;
 
     fn from_str(query: &str) -> Result<Self, Error> {
-        let query = serde_json::from_str(query).map_err(err_map!("Error parsing tag query"))?;
-        Ok(Self { query })
+        let query: wql::Query =
+            serde_json::from_str(query).map_err(err_map!("Error parsing tag query"))?;
+        Ok(Self::from(query))
+    }
+}
+
+/// A serializable position within a record scan.
+///
+/// A checkpoint records the parameters of the scan and the last record
+/// returned, so that an interrupted scan may be resumed with
+/// `Store::resume_scan`, including from another process. Records are
+/// returned in insertion order, and records added after the checkpoint
+/// was taken are included when the scan is resumed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanCheckpoint {
+    pub(crate) profile: String,
+    pub(crate) kind: i16,
+    pub(crate) category: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) tag_filter: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) fold_case: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) normalize: bool,
+    #[serde(default, skip_serializing_if = "HistoryFilter::is_empty")]
+    pub(crate) history_filter: HistoryFilter,
+    #[serde(default, skip_serializing_if = "Projection::is_full")]
+    pub(crate) projection: Projection,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) offset: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) limit: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) after_id: Option<i64>,
+}
+
+impl ScanCheckpoint {
+    pub(crate) fn new(
+        profile: String,
+        kind: EntryKind,
+        category: String,
+        tag_filter: Option<&TagFilter>,
+        offset: Option<i64>,
+        limit: Option<i64>,
+        projection: Projection,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            profile,
+            kind: kind.code(),
+            category,
+            tag_filter: tag_filter.map(TagFilter::to_string).transpose()?,
+            fold_case: tag_filter.map_or(false, |filter| filter.fold_case),
+            normalize: tag_filter.map_or(false, |filter| filter.normalize),
+            history_filter: HistoryFilter::default(),
+            projection,
+            offset,
+            limit,
+            after_id: None,
+        })
+    }
+
+    /// The profile name of the scan
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
+    /// The kind of the scanned records
+    pub fn kind(&self) -> Option<EntryKind> {
+        EntryKind::from_code(self.kind)
+    }
+
+    /// The namespace of the scanned records, if any
+    pub fn namespace(&self) -> Option<&str> {
+        split_namespace(&self.category).0
+    }
+
+    /// The category of the scanned records
+    pub fn category(&self) -> &str {
+        split_namespace(&self.category).1
+    }
+
+    /// The parts of each record returned by the scan
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    /// The bounds on the creation and update times of the scanned records
+    pub fn history_filter(&self) -> HistoryFilter {
+        self.history_filter
+    }
+
+    /// Parse the tag filter of the scan
+    pub fn tag_filter(&self) -> Result<Option<TagFilter>, Error> {
+        Ok(self
+            .tag_filter
+            .as_deref()
+            .map(TagFilter::from_str)
+            .transpose()?
+            .map(|filter| TagFilter {
+                fold_case: self.fold_case,
+                normalize: self.normalize,
+                ..filter
+            }))
+    }
+
+    /// Encode the checkpoint as a string token
+    pub fn to_token(&self) -> Result<String, Error> {
+        serde_json::to_string(self).map_err(err_map!("Error encoding scan checkpoint"))
+    }
+
+    /// Decode a checkpoint from a string token
+    pub fn from_token(token: &str) -> Result<Self, Error> {
+        serde_json::from_str(token).map_err(err_map!(Input, "Error parsing scan checkpoint"))
+    }
+
+    fn advance(&mut self, row_id: i64) {
+        self.after_id.replace(row_id);
+        self.offset = None;
+        if let Some(limit) = self.limit.as_mut() {
+            *limit = (*limit - 1).max(0);
+        }
     }
 }
 
-/// An active record scan of a store backend
-pub struct Scan<'s, T> {
-    stream: Option<Pin<Box<dyn Stream<Item = Result<Vec<T>, Error>> + Send + 's>>>,
+/// An active record scan of a store backend.
+///
+/// The scan owns the resources it requires, including a handle to the
+/// connection pool and the profile key, and may outlive the `Store` instance
+/// which created it. It may also be moved between tasks and threads, although
+/// the backend connection pool must remain open while results are fetched.
+///
+/// Results may be fetched a page at a time using `fetch_next`, or the scan
+/// may be consumed as a `Stream` of individual records. The backend cursor is
+/// only advanced when the next page is requested, and at most one page of
+/// records is held by the scan. Pages contain at most `page_size` records
+/// but may be shorter when the records are large, so the scan is only complete
+/// once `fetch_next` returns `None`.
+pub struct Scan<T> {
+    stream: SyncWrapper<Option<ScanStream<T>>>,
+    buffer: VecDeque<(i64, T)>,
     page_size: usize,
+    checkpoint: Option<ScanCheckpoint>,
+}
+
+type ScanStream<T> = Pin<Box<dyn Stream<Item = Result<Vec<(i64, T)>, Error>> + Send>>;
+
+/// A container which only provides access to its value through a mutable
+/// reference, so that it may be shared between threads when the value is
+/// `Send` but not `Sync`
+struct SyncWrapper<T>(T);
+
+impl<T> SyncWrapper<T> {
+    fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    fn get_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
 }
 
-impl<'s, T> Scan<'s, T> {
+// no reference to the value is available through a shared reference
+unsafe impl<T: Send> Sync for SyncWrapper<T> {}
+
+// the scan is never structurally pinned
+impl<T> Unpin for Scan<T> {}
+
+impl<T> Scan<T> {
+    #[allow(unused)]
     pub(crate) fn new<S>(stream: S, page_size: usize) -> Self
     where
-        S: Stream<Item = Result<Vec<T>, Error>> + Send + 's,
+        S: Stream<Item = Result<Vec<T>, Error>> + Send + 'static,
+        T: Send + 'static,
+    {
+        Self::new_positioned(
+            stream.map(|rows| rows.map(|rows| rows.into_iter().map(|row| (0, row)).collect())),
+            page_size,
+        )
+    }
+
+    /// Create a scan from a stream of rows paired with their row identifiers,
+    /// which are used to track the position of the scan
+    pub(crate) fn new_positioned<S>(stream: S, page_size: usize) -> Self
+    where
+        S: Stream<Item = Result<Vec<(i64, T)>, Error>> + Send + 'static,
     {
         Self {
-            stream: Some(stream.boxed()),
+            stream: SyncWrapper::new(Some(stream.boxed())),
+            buffer: VecDeque::new(),
             page_size,
+            checkpoint: None,
+        }
+    }
+
+    /// Track the position of the scan, so that it may be resumed
+    pub(crate) fn with_checkpoint(mut self, checkpoint: ScanCheckpoint) -> Self {
+        self.checkpoint.replace(checkpoint);
+        self
+    }
+
+    /// Get the position of the scan following the last record returned.
+    ///
+    /// This is only available for scans created by a `Store`
+    pub fn checkpoint(&self) -> Option<&ScanCheckpoint> {
+        self.checkpoint.as_ref()
+    }
+
+    fn advance(&mut self, row_id: i64) {
+        if let Some(checkpoint) = self.checkpoint.as_mut() {
+            checkpoint.advance(row_id);
+        }
+    }
+
+    /// Set the maximum duration for fetching each set of result rows
+    pub(crate) fn with_timeout(mut self, timeout: Option<Duration>) -> Self
+    where
+        T: Send + 'static,
+    {
+        if let (Some(timeout), Some(mut inner)) = (timeout, self.stream.get_mut().take()) {
+            *self.stream.get_mut() = Some(
+                stream! {
+                    loop {
+                        match future::timeout(timeout, inner.next()).await {
+                            Some(Some(rows)) => yield rows,
+                            Some(None) => break,
+                            None => {
+                                yield Err(err_msg!(Busy, "Store operation timed out"));
+                                break;
+                            }
+                        }
+                    }
+                }
+                .boxed(),
+            );
         }
+        self
     }
 
-    /// Fetch the next set of result rows
+    /// Fetch the next set of result rows.
+    ///
+    /// If the scan timeout is exceeded then a `Busy` error is returned and the
+    /// scan is closed
     pub async fn fetch_next(&mut self) -> Result<Option<Vec<T>>, Error> {
-        if let Some(mut s) = self.stream.take() {
+        let rows = if !self.buffer.is_empty() {
+            // return any rows left over from polling the scan as a stream
+            self.buffer.drain(..).collect::<Vec<_>>()
+        } else if let Some(mut s) = self.stream.get_mut().take() {
             match s.try_next().await? {
                 Some(val) => {
-                    if val.len() == self.page_size {
-                        self.stream.replace(s);
-                    }
-                    Ok(Some(val))
+                    self.stream.get_mut().replace(s);
+                    val
                 }
-                None => Ok(None),
+                None => return Ok(None),
             }
         } else {
-            Ok(None)
+            return Ok(None);
+        };
+        let mut page = Vec::with_capacity(rows.len());
+        for (row_id, row) in rows {
+            self.advance(row_id);
+            page.push(row);
         }
+        Ok(Some(page))
     }
 }
 
-impl<S> Debug for Scan<'_, S> {
+impl<T> Stream for Scan<T> {
+    type Item = Result<T, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let scan = self.get_mut();
+        loop {
+            if let Some((row_id, row)) = scan.buffer.pop_front() {
+                scan.advance(row_id);
+                return Poll::Ready(Some(Ok(row)));
+            }
+            let stream = match scan.stream.get_mut().as_mut() {
+                Some(stream) => stream,
+                None => return Poll::Ready(None),
+            };
+            match Stream::poll_next(stream.as_mut(), cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Ok(rows))) => {
+                    scan.buffer.extend(rows);
+                }
+                Poll::Ready(Some(Err(err))) => {
+                    scan.stream.get_mut().take();
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Poll::Ready(None) => {
+                    scan.stream.get_mut().take();
+                }
+            }
+        }
+    }
+}
+
+impl<S> Debug for Scan<S> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("Scan")
             .field("page_size", &self.page_size)
@@ -515,6 +1403,171 @@ impl<S> Debug for Scan<'_, S> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::future::block_on;
+
+    #[test]
+    fn binary_identifier_round_trip() {
+        assert_eq!(binary_identifier(b"name"), "name");
+        let id = [0u8, 0xff, 0x80, b'a'];
+        let encoded = binary_identifier(&id);
+        assert!(!encoded.contains(NAMESPACE_SEPARATOR));
+        assert_eq!(decode_binary_identifier(&encoded), Some(id.to_vec()));
+        assert_eq!(
+            binary_identifier_input(SecretBytes::from(encoded.as_bytes())).as_ref(),
+            &id[..]
+        );
+        assert_eq!(binary_identifier_output(id.to_vec()), encoded);
+        assert_eq!(binary_identifier_output(b"name".to_vec()), "name");
+        assert_eq!(decode_binary_identifier("name"), None);
+    }
+
+    #[test]
+    fn content_hash_tag_order() {
+        let tags = vec![
+            EntryTag::Encrypted("a".to_string(), "1".to_string()),
+            EntryTag::Plaintext("b".to_string(), "2".to_string()),
+        ];
+        let entry = Entry::new("category", "name", "value", tags.clone());
+        let reversed = Entry::new(
+            "category",
+            "name",
+            "value",
+            tags.into_iter().rev().collect(),
+        );
+        assert_eq!(entry.content_hash(), reversed.content_hash());
+        let hash = entry.content_hash();
+        assert_eq!(hash.to_string().parse::<EntryHash>().unwrap(), hash);
+
+        let plain = Entry::new(
+            "category",
+            "name",
+            "value",
+            vec![EntryTag::Plaintext("a".to_string(), "1".to_string())],
+        );
+        let encrypted = Entry::new(
+            "category",
+            "name",
+            "value",
+            vec![EntryTag::Encrypted("a".to_string(), "1".to_string())],
+        );
+        assert_ne!(plain.content_hash(), encrypted.content_hash());
+    }
+
+    #[test]
+    fn scan_stream_entries() {
+        let pages = vec![Ok(vec![1, 2]), Ok(vec![3, 4]), Ok(vec![5])];
+        let scan = Scan::new(futures_lite::stream::iter(pages), 2);
+        let rows = block_on(scan.try_collect::<_, _, Vec<_>>()).unwrap();
+        assert_eq!(rows, vec![1, 2, 3, 4, 5]);
+
+        // mixing individual records and pages
+        let pages = vec![Ok(vec![1, 2]), Ok(vec![3, 4]), Ok(vec![5])];
+        let mut scan = Scan::new(futures_lite::stream::iter(pages), 2);
+        block_on(async {
+            assert_eq!(scan.next().await.unwrap().unwrap(), 1);
+            assert_eq!(scan.fetch_next().await.unwrap(), Some(vec![2]));
+            assert_eq!(scan.fetch_next().await.unwrap(), Some(vec![3, 4]));
+            assert_eq!(scan.next().await.unwrap().unwrap(), 5);
+            assert!(scan.next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn scan_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Scan<Entry>>();
+    }
+
+    #[test]
+    fn scan_checkpoint_position() {
+        let pages = vec![Ok(vec![(3, 1), (5, 2)]), Ok(vec![(8, 3)])];
+        let checkpoint = ScanCheckpoint::new(
+            "profile".to_string(),
+            EntryKind::Item,
+            "category".to_string(),
+            Some(&TagFilter::is_eq("tag", "value").case_insensitive()),
+            Some(1),
+            Some(3),
+            Projection::NamesAndTags,
+        )
+        .unwrap();
+        let mut scan =
+            Scan::new_positioned(futures_lite::stream::iter(pages), 2).with_checkpoint(checkpoint);
+        block_on(async {
+            assert_eq!(scan.next().await.unwrap().unwrap(), 1);
+            assert_eq!(scan.checkpoint().unwrap().after_id, Some(3));
+            assert_eq!(scan.checkpoint().unwrap().offset, None);
+            assert_eq!(scan.fetch_next().await.unwrap(), Some(vec![2]));
+            assert_eq!(scan.checkpoint().unwrap().after_id, Some(5));
+            assert_eq!(scan.checkpoint().unwrap().limit, Some(1));
+        });
+        let token = scan.checkpoint().unwrap().to_token().unwrap();
+        let parsed = ScanCheckpoint::from_token(&token).unwrap();
+        assert_eq!(&parsed, scan.checkpoint().unwrap());
+        assert_eq!(
+            parsed.tag_filter().unwrap(),
+            Some(TagFilter::is_eq("tag", "value").case_insensitive())
+        );
+        assert_eq!(parsed.projection(), Projection::NamesAndTags);
+    }
+
+    #[test]
+    fn entry_kind_codes() {
+        for kind in &[
+            EntryKind::Kms,
+            EntryKind::Item,
+            EntryKind::Outbox,
+            EntryKind::Custom(0),
+            EntryKind::Custom(255),
+        ] {
+            assert_eq!(EntryKind::from_code(kind.code()), Some(*kind));
+        }
+        assert_eq!(EntryKind::Custom(3).code(), 259);
+        assert_eq!(EntryKind::from_code(0), None);
+        assert_eq!(EntryKind::from_code(512), None);
+    }
+
+    #[test]
+    fn namespace_categories() {
+        let stored = namespaced_category(Some("app"), "category");
+        assert_eq!(split_namespace(&stored), (Some("app"), "category"));
+        assert_eq!(split_namespace("category"), (None, "category"));
+        assert_eq!(namespaced_category(None, "category"), "category");
+        assert!(check_namespace("app").is_ok());
+        assert!(check_namespace("").is_err());
+        assert!(check_namespace("a\0b").is_err());
+        assert!(check_category(&stored).is_err());
+    }
+
+    #[test]
+    fn entry_tag_accessors() {
+        let entry = Entry::new(
+            "category",
+            "name",
+            "value",
+            vec![
+                EntryTag::new(TagKind::Encrypted, "role", "admin"),
+                EntryTag::new(TagKind::Plaintext, "count", "5"),
+                EntryTag::new(TagKind::Encrypted, "role", "user"),
+            ],
+        );
+        assert_eq!(entry.tag("role"), Some("admin"));
+        assert_eq!(entry.tag("missing"), None);
+        assert_eq!(
+            entry.tag_values("role").collect::<Vec<_>>(),
+            vec!["admin", "user"]
+        );
+        assert_eq!(entry.tag_parse::<u32>("count").unwrap(), Some(5));
+        assert!(entry.tag_parse::<u32>("role").is_err());
+        assert_eq!(entry.tag_parse::<u32>("missing").unwrap(), None);
+        assert_eq!(entry.find_tag("count").unwrap().kind(), TagKind::Plaintext);
+        assert!(!entry.find_tag("role").unwrap().is_plaintext());
+
+        let map = entry.tag_map();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["role"], vec!["admin", "user"]);
+        assert_eq!(map["count"], vec!["5"]);
+    }
 
     #[test]
     fn serialize_tags() {
@@ -528,4 +1581,19 @@ mod tests {
         let tags2 = serde_json::from_str(&ser).unwrap();
         assert_eq!(tags, tags2);
     }
+
+    #[test]
+    fn tag_filter_is_all() {
+        let filter = TagFilter::is_all(
+            "~role",
+            vec!["admin".to_string(), "user".to_string(), "admin".to_string()],
+        );
+        assert_eq!(
+            filter,
+            TagFilter::all_of(vec![
+                TagFilter::is_eq("~role", "admin"),
+                TagFilter::is_eq("~role", "user"),
+            ])
+        );
+    }
 }
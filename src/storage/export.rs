@@ -0,0 +1,527 @@
+//! Standalone encrypted bundles of the records of a single profile.
+//!
+//! A bundle consists of a manifest and a sequence of encrypted chunks, each
+//! holding a batch of records. The records are encrypted with a random bundle
+//! key, which is wrapped by a key resolved from a pass key in the same manner
+//! as a store key, so that the bundle may be imported into any store.
+//!
+//! Records may also be exported without encryption as newline-delimited
+//! JSON, for use by data pipelines and when debugging.
+
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::entry::{
+    check_category, check_namespace, Entry, EntryKind, EntryTag, EntryTagSet, HistoryFilter,
+    TagFilter,
+};
+use super::policy::check_reserved_category;
+use crate::{
+    crypto::alg::{Chacha20Types, KeyAlg},
+    error::Error,
+    future::unblock,
+    kms::LocalKey,
+    protect::{PassKey, StoreKeyMethod, StoreKeyReference},
+};
+
+const BUNDLE_AAD: &[u8] = b"askar:bundle";
+
+const BUNDLE_VERSION: u8 = 1;
+
+/// The number of records encrypted together in each chunk
+const BUNDLE_CHUNK_SIZE: usize = 256;
+
+/// The number of records inserted together when importing newline-delimited
+/// JSON
+pub(crate) const IMPORT_BATCH_SIZE: usize = 256;
+
+/// The maximum length of a line of newline-delimited JSON accepted on import
+pub(crate) const IMPORT_LINE_LIMIT: usize = 16 * 1024 * 1024;
+
+const BUNDLE_KEY_ALG: KeyAlg = KeyAlg::Chacha20(Chacha20Types::XC20P);
+
+/// The unencrypted description of a profile bundle
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct BundleManifest {
+    pub version: u8,
+    /// The name of the exported profile
+    pub profile: String,
+    /// The reference to the key wrapping the bundle key
+    pub key_ref: String,
+    #[serde(with = "serde_bytes")]
+    pub wrapped_key: Vec<u8>,
+    /// The total number of records
+    pub records: u64,
+    /// The number of encrypted chunks
+    pub chunks: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SealedBundle {
+    manifest: BundleManifest,
+    chunks: Vec<serde_bytes::ByteBuf>,
+}
+
+/// A single record of a profile bundle
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct BundleRecord {
+    /// The numeric code of the entry kind
+    pub kind: i16,
+    /// The stored category, including any namespace
+    pub category: String,
+    pub name: String,
+    #[serde(with = "serde_bytes")]
+    pub value: Vec<u8>,
+    #[serde(
+        serialize_with = "serialize_tags",
+        deserialize_with = "deserialize_tags"
+    )]
+    pub tags: Vec<EntryTag>,
+}
+
+/// Selects the records of a profile included in an export, such as the
+/// records of a single user for a data portability request
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExportFilter {
+    /// Only include records of these kinds
+    pub kinds: Option<Vec<EntryKind>>,
+    /// Only include records in these categories, within any namespace
+    pub categories: Option<Vec<String>>,
+    /// Only include records matching a tag filter. The user-defined tags of
+    /// key entries are stored with the prefix `user:`
+    pub tag_filter: Option<TagFilter>,
+    /// Only include records which were written at or after this time. This
+    /// requires a store provisioned with entry history
+    pub modified_since: Option<SystemTime>,
+}
+
+impl ExportFilter {
+    /// Check whether the records of a category are selected by the filter
+    pub(crate) fn includes_category(&self, kind: EntryKind, category: &str) -> bool {
+        self.kinds.as_ref().map_or(true, |k| k.contains(&kind))
+            && self
+                .categories
+                .as_ref()
+                .map_or(true, |c| c.iter().any(|c| c == category))
+    }
+
+    /// The bounds on the modification times of the exported records
+    pub(crate) fn history_filter(&self) -> HistoryFilter {
+        HistoryFilter {
+            updated_since: self.modified_since,
+            ..Default::default()
+        }
+    }
+}
+
+/// The acknowledgment required to export the records of a profile without
+/// encryption
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaintextExport {
+    /// The caller accepts responsibility for protecting the decrypted
+    /// records, which are written as they are read from the store
+    Acknowledged,
+}
+
+/// A single line of a newline-delimited JSON export
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct NdjsonRecord {
+    /// The entry kind: `kms`, `item`, `outbox` or `custom:<n>`
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    pub category: String,
+    pub name: String,
+    /// The record value, when it is valid UTF-8
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// The hex-encoded record value, when it is not valid UTF-8
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_hex: Option<String>,
+    #[serde(
+        default,
+        serialize_with = "serialize_tags",
+        deserialize_with = "deserialize_tags"
+    )]
+    pub tags: Vec<EntryTag>,
+}
+
+impl NdjsonRecord {
+    pub fn new(kind: EntryKind, namespace: Option<String>, entry: Entry) -> Self {
+        let kind = match kind {
+            EntryKind::Kms => "kms".to_string(),
+            EntryKind::Item => "item".to_string(),
+            EntryKind::Outbox => "outbox".to_string(),
+            EntryKind::Custom(n) => format!("custom:{}", n),
+        };
+        let (value, value_hex) = match std::str::from_utf8(entry.value.as_ref()) {
+            Ok(value) => (Some(value.to_string()), None),
+            Err(_) => (None, Some(hex::encode(entry.value.as_ref()))),
+        };
+        Self {
+            kind,
+            namespace,
+            category: entry.category,
+            name: entry.name,
+            value,
+            value_hex,
+            tags: entry.tags,
+        }
+    }
+
+    pub fn kind(&self) -> Result<EntryKind, Error> {
+        match self.kind.as_str() {
+            "kms" => Ok(EntryKind::Kms),
+            "item" => Ok(EntryKind::Item),
+            "outbox" => Ok(EntryKind::Outbox),
+            other => other
+                .strip_prefix("custom:")
+                .and_then(|n| n.parse().ok())
+                .map(EntryKind::Custom)
+                .ok_or_else(|| err_msg!(Input, "Unsupported entry kind in exported record")),
+        }
+    }
+
+    /// Convert the record into its namespace and an entry. Records in the
+    /// reserved categories are rejected, as they are only written by the store
+    pub fn into_entry(self) -> Result<(Option<String>, Entry), Error> {
+        let value = match (self.value, self.value_hex) {
+            (Some(value), None) => value.into_bytes(),
+            (None, Some(value)) => hex::decode(value)
+                .map_err(err_map!(Input, "Invalid hex value in exported record"))?,
+            _ => {
+                return Err(err_msg!(
+                    Input,
+                    "Exported record must have exactly one of 'value' and 'value_hex'"
+                ))
+            }
+        };
+        if let Some(namespace) = self.namespace.as_deref() {
+            check_namespace(namespace)?;
+        }
+        check_reserved_category(&self.category)?;
+        check_category(&self.category)?;
+        Ok((
+            self.namespace,
+            Entry::new(self.category, self.name, value, self.tags),
+        ))
+    }
+}
+
+fn serialize_tags<S>(tags: &[EntryTag], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    EntryTagSet::from(tags).serialize(serializer)
+}
+
+fn deserialize_tags<'de, D>(deserializer: D) -> Result<Vec<EntryTag>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    EntryTagSet::deserialize(deserializer).map(EntryTagSet::into_vec)
+}
+
+impl BundleRecord {
+    pub fn new(kind: EntryKind, entry: Entry) -> Self {
+        Self {
+            kind: kind.code(),
+            category: entry.category,
+            name: entry.name,
+            value: entry.value.into_vec(),
+            tags: entry.tags,
+        }
+    }
+
+    pub fn kind(&self) -> Result<EntryKind, Error> {
+        EntryKind::from_code(self.kind)
+            .ok_or_else(|| err_msg!(Input, "Unsupported entry kind in profile bundle"))
+    }
+
+    pub fn into_entry(self) -> Entry {
+        Entry::new(self.category, self.name, self.value, self.tags)
+    }
+}
+
+/// The associated data for a chunk, binding it to its position and to the
+/// format version, profile name and record and chunk counts of the manifest
+fn chunk_aad(manifest: &BundleManifest, index: u32) -> Vec<u8> {
+    let mut aad = BUNDLE_AAD.to_vec();
+    aad.push(manifest.version);
+    aad.extend_from_slice(&(manifest.profile.len() as u32).to_be_bytes());
+    aad.extend_from_slice(manifest.profile.as_bytes());
+    aad.extend_from_slice(&index.to_be_bytes());
+    aad.extend_from_slice(&manifest.chunks.to_be_bytes());
+    aad.extend_from_slice(&manifest.records.to_be_bytes());
+    aad
+}
+
+/// Encrypt the records of a profile into a bundle
+pub(crate) async fn seal_bundle(
+    profile: String,
+    records: Vec<BundleRecord>,
+    method: StoreKeyMethod,
+    pass_key: PassKey<'_>,
+) -> Result<Vec<u8>, Error> {
+    let pass_key = pass_key.into_owned();
+    unblock(move || {
+        let (wrap_key, key_ref) = method.resolve(pass_key)?;
+        let bundle_key = LocalKey::generate(BUNDLE_KEY_ALG, false)?;
+        let wrapped_key = wrap_key.wrap_data(bundle_key.to_secret_bytes()?)?;
+        let manifest = BundleManifest {
+            version: BUNDLE_VERSION,
+            profile,
+            key_ref: key_ref.into_uri(),
+            wrapped_key,
+            records: records.len() as u64,
+            chunks: ((records.len() + BUNDLE_CHUNK_SIZE - 1) / BUNDLE_CHUNK_SIZE) as u32,
+        };
+        let mut chunks = Vec::with_capacity(manifest.chunks as usize);
+        for (index, batch) in records.chunks(BUNDLE_CHUNK_SIZE).enumerate() {
+            let encoded = serde_cbor::to_vec(batch)
+                .map_err(err_map!(Unexpected, "Error encoding bundle records"))?;
+            let enc =
+                bundle_key.aead_encrypt(&encoded, &[], &chunk_aad(&manifest, index as u32))?;
+            chunks.push(serde_bytes::ByteBuf::from(enc.into_vec()));
+        }
+        serde_cbor::to_vec(&SealedBundle { manifest, chunks })
+            .map_err(err_map!(Unexpected, "Error encoding profile bundle"))
+    })
+    .await
+}
+
+/// Decrypt a bundle, returning its manifest and records. Every chunk is
+/// authenticated and the record counts are checked against the manifest
+pub(crate) async fn open_bundle(
+    bundle: &[u8],
+    pass_key: PassKey<'_>,
+) -> Result<(BundleManifest, Vec<BundleRecord>), Error> {
+    let (manifest, chunks) = parse_bundle(bundle)?;
+    let pass_key = pass_key.into_owned();
+    unblock(move || {
+        // the record count of the manifest is not trusted before the chunks
+        // are authenticated
+        let mut records = Vec::new();
+        decrypt_chunks(&manifest, &chunks, pass_key, |batch| {
+            records.extend(batch);
+            Ok(())
+        })?;
+        Ok((manifest, records))
+    })
+    .await
+}
+
+/// The outcome of verifying a backup archive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackupSummary {
+    /// The name of the exported profile
+    pub profile: String,
+    /// The number of records in the archive
+    pub records: u64,
+    /// The number of encrypted chunks in the archive
+    pub chunks: u32,
+}
+
+/// Verify the integrity of a profile bundle written to a file by a backup
+/// job, without importing it.
+///
+/// The manifest is parsed and the bundle key is unwrapped using `pass_key`,
+/// after which each chunk is authenticated and decoded and the record and
+/// chunk counts are compared with the manifest. The records are discarded
+/// as each chunk is verified.
+pub async fn verify_backup(
+    path: impl AsRef<Path>,
+    pass_key: PassKey<'_>,
+) -> Result<BackupSummary, Error> {
+    let path = path.as_ref().to_path_buf();
+    let bundle = unblock(move || std::fs::read(path))
+        .await
+        .map_err(err_map!(Input, "Error reading backup archive"))?;
+    verify_bundle(&bundle, pass_key).await
+}
+
+/// Verify the integrity of a profile bundle without importing it
+pub(crate) async fn verify_bundle(
+    bundle: &[u8],
+    pass_key: PassKey<'_>,
+) -> Result<BackupSummary, Error> {
+    let (manifest, chunks) = parse_bundle(bundle)?;
+    let pass_key = pass_key.into_owned();
+    unblock(move || {
+        decrypt_chunks(&manifest, &chunks, pass_key, |batch| {
+            for record in &batch {
+                record.kind()?;
+            }
+            Ok(())
+        })?;
+        Ok(BackupSummary {
+            profile: manifest.profile,
+            records: manifest.records,
+            chunks: manifest.chunks,
+        })
+    })
+    .await
+}
+
+/// Parse the manifest and chunks of a bundle, checking the format version
+/// and the number of chunks
+fn parse_bundle(bundle: &[u8]) -> Result<(BundleManifest, Vec<serde_bytes::ByteBuf>), Error> {
+    let SealedBundle { manifest, chunks } =
+        serde_cbor::from_slice(bundle).map_err(err_map!(Input, "Error parsing profile bundle"))?;
+    if manifest.version != BUNDLE_VERSION {
+        return Err(err_msg!(Unsupported, "Unsupported profile bundle version"));
+    }
+    if chunks.len() != manifest.chunks as usize {
+        return Err(err_msg!(Input, "Profile bundle is incomplete"));
+    }
+    Ok((manifest, chunks))
+}
+
+/// Unwrap the bundle key and authenticate each chunk in order, passing the
+/// decoded records to `visit`. The total record count is checked against
+/// the manifest
+fn decrypt_chunks(
+    manifest: &BundleManifest,
+    chunks: &[serde_bytes::ByteBuf],
+    pass_key: PassKey<'_>,
+    mut visit: impl FnMut(Vec<BundleRecord>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let key_ref = StoreKeyReference::parse_uri(&manifest.key_ref)?;
+    let wrap_key = key_ref.resolve(pass_key)?;
+    let bundle_key = wrap_key
+        .unwrap_data(manifest.wrapped_key.clone())
+        .map_err(|_| err_msg!(WrongPassKey, "Incorrect pass key for profile bundle"))?;
+    let bundle_key = LocalKey::from_secret_bytes(BUNDLE_KEY_ALG, bundle_key.as_ref())?;
+    let nonce_len = bundle_key.aead_params()?.nonce_length;
+    let mut count = 0u64;
+    for (index, chunk) in chunks.iter().enumerate() {
+        if chunk.len() < nonce_len {
+            return Err(err_msg!(Encryption, "Invalid profile bundle chunk"));
+        }
+        let (ciphertext, nonce) = chunk.split_at(chunk.len() - nonce_len);
+        let decrypted = bundle_key
+            .aead_decrypt(ciphertext, nonce, &chunk_aad(manifest, index as u32))
+            .map_err(|_| err_msg!(Encryption, "Profile bundle chunk failed verification"))?;
+        let batch: Vec<BundleRecord> = serde_cbor::from_slice(&decrypted)
+            .map_err(err_map!(Input, "Error parsing bundle records"))?;
+        count += batch.len() as u64;
+        visit(batch)?;
+    }
+    if count != manifest.records {
+        return Err(err_msg!(Input, "Profile bundle record count mismatch"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{future::block_on, protect::generate_raw_store_key};
+
+    #[test]
+    fn seal_open_bundle() {
+        block_on(async {
+            let records: Vec<_> = (0..300)
+                .map(|idx| BundleRecord {
+                    kind: EntryKind::Item.code(),
+                    category: "category".to_string(),
+                    name: format!("name-{}", idx),
+                    value: b"value".to_vec(),
+                    tags: vec![EntryTag::Plaintext("tag".to_string(), "a".to_string())],
+                })
+                .collect();
+            let pass_key = generate_raw_store_key(None).unwrap();
+            let bundle = seal_bundle(
+                "profile".to_string(),
+                records.clone(),
+                StoreKeyMethod::RawKey,
+                pass_key.as_ref(),
+            )
+            .await
+            .unwrap();
+            let (manifest, opened) = open_bundle(&bundle, pass_key.as_ref()).await.unwrap();
+            assert_eq!(manifest.profile, "profile");
+            assert_eq!(manifest.chunks, 2);
+            assert_eq!(opened, records);
+
+            let other_key = generate_raw_store_key(None).unwrap();
+            let err = open_bundle(&bundle, other_key.as_ref()).await.unwrap_err();
+            assert_eq!(err.kind(), crate::ErrorKind::WrongPassKey);
+
+            let summary = verify_bundle(&bundle, pass_key.as_ref()).await.unwrap();
+            assert_eq!(summary.records, 300);
+            assert_eq!(summary.chunks, 2);
+
+            // a modified chunk fails authentication
+            let mut sealed: SealedBundle = serde_cbor::from_slice(&bundle).unwrap();
+            sealed.chunks[1][0] ^= 1;
+            let modified = serde_cbor::to_vec(&sealed).unwrap();
+            let err = verify_bundle(&modified, pass_key.as_ref())
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), crate::ErrorKind::Encryption);
+
+            // a removed chunk is detected even if the manifest is adjusted
+            let mut sealed: SealedBundle = serde_cbor::from_slice(&bundle).unwrap();
+            sealed.chunks.pop();
+            sealed.manifest.chunks = 1;
+            sealed.manifest.records = 256;
+            let truncated = serde_cbor::to_vec(&sealed).unwrap();
+            assert!(verify_bundle(&truncated, pass_key.as_ref()).await.is_err());
+
+            // the chunks are bound to the profile name of the manifest
+            let mut sealed: SealedBundle = serde_cbor::from_slice(&bundle).unwrap();
+            sealed.manifest.profile = "other".to_string();
+            let renamed = serde_cbor::to_vec(&sealed).unwrap();
+            let err = open_bundle(&renamed, pass_key.as_ref()).await.unwrap_err();
+            assert_eq!(err.kind(), crate::ErrorKind::Encryption);
+        })
+    }
+
+    #[test]
+    fn export_filter_categories() {
+        let filter = ExportFilter::default();
+        assert!(filter.includes_category(EntryKind::Kms, "any"));
+        let filter = ExportFilter {
+            kinds: Some(vec![EntryKind::Item]),
+            categories: Some(vec!["credential".to_string()]),
+            ..Default::default()
+        };
+        assert!(filter.includes_category(EntryKind::Item, "credential"));
+        assert!(!filter.includes_category(EntryKind::Item, "other"));
+        assert!(!filter.includes_category(EntryKind::Kms, "credential"));
+    }
+
+    #[test]
+    fn ndjson_record_round_trip() {
+        let entry = Entry::new(
+            "category",
+            "name",
+            vec![0xffu8, 0x00],
+            vec![EntryTag::Plaintext("tag".to_string(), "a".to_string())],
+        );
+        let record = NdjsonRecord::new(EntryKind::Custom(4), Some("ns".to_string()), entry);
+        assert_eq!(record.value_hex.as_deref(), Some("ff00"));
+        let line = serde_json::to_string(&record).unwrap();
+        let parsed: NdjsonRecord = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.kind().unwrap(), EntryKind::Custom(4));
+        let (namespace, entry) = parsed.into_entry().unwrap();
+        assert_eq!(namespace.as_deref(), Some("ns"));
+        assert_eq!(entry.category, "category");
+        assert_eq!(entry.value.as_ref(), &[0xff, 0x00]);
+
+        let both = r#"{"kind":"item","category":"c","name":"n","value":"v","value_hex":"00"}"#;
+        let record: NdjsonRecord = serde_json::from_str(both).unwrap();
+        assert!(record.into_entry().is_err());
+        let reserved = r#"{"kind":"item","category":"askar:protected","name":"n","value":"v"}"#;
+        let record: NdjsonRecord = serde_json::from_str(reserved).unwrap();
+        assert!(record.into_entry().is_err());
+        let bad_kind = r#"{"kind":"other","category":"c","name":"n","value":"v"}"#;
+        let record: NdjsonRecord = serde_json::from_str(bad_kind).unwrap();
+        assert!(record.kind().is_err());
+    }
+}
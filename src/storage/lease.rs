@@ -0,0 +1,92 @@
+//! Leases coordinating work between the processes sharing a store.
+//!
+//! A lease is held by at most one process at a time until it expires, so that
+//! periodic jobs such as expiry sweeps or key rotation may be performed by a
+//! single worker. Each lease is acquired, renewed or released within a single
+//! transaction, and only by the holder of its current token.
+
+use std::time::{Duration, SystemTime};
+
+use super::entry::{Entry, EntryTag};
+use crate::error::Error;
+
+/// The reserved category of the lease records of a profile
+pub(crate) const LEASE_CATEGORY: &str = "askar:lease";
+
+const EXPIRES_TAG: &str = "expires";
+
+/// A lease acquired using `Store::acquire_lease`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lease {
+    /// The name of the lease
+    pub name: String,
+    /// A unique token identifying this holder of the lease, which may be
+    /// used to reject work performed under a lease that has since expired
+    pub token: String,
+    /// The time at which the lease expires unless it is renewed
+    pub expires: SystemTime,
+    pub(crate) profile: Option<String>,
+}
+
+impl Lease {
+    pub(crate) fn new(profile: Option<String>, name: &str, ttl: Duration) -> Self {
+        Self {
+            name: name.to_string(),
+            token: uuid::Uuid::new_v4().to_string(),
+            expires: SystemTime::now() + ttl,
+            profile,
+        }
+    }
+
+    /// Check whether the lease has expired, in which case it may have been
+    /// acquired by another holder
+    pub fn is_expired(&self) -> bool {
+        self.expires <= SystemTime::now()
+    }
+
+    pub(crate) fn tags(&self) -> Vec<EntryTag> {
+        let millis = self
+            .expires
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        vec![EntryTag::Plaintext(
+            EXPIRES_TAG.to_string(),
+            millis.to_string(),
+        )]
+    }
+}
+
+/// Read the token and expiry time of the current holder of a lease
+pub(crate) fn lease_holder(entry: &Entry) -> Result<(String, SystemTime), Error> {
+    let token = std::str::from_utf8(entry.value.as_ref())
+        .map_err(err_map!(Unexpected, "Invalid lease token"))?;
+    let millis = entry
+        .tag_parse::<u64>(EXPIRES_TAG)?
+        .ok_or_else(|| err_msg!(Unexpected, "Missing lease expiry"))?;
+    Ok((
+        token.to_string(),
+        SystemTime::UNIX_EPOCH + Duration::from_millis(millis),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lease_record_round_trip() {
+        let lease = Lease::new(None, "sweep", Duration::from_secs(30));
+        assert!(!lease.is_expired());
+        let entry = Entry::new(
+            LEASE_CATEGORY,
+            lease.name.as_str(),
+            lease.token.as_bytes(),
+            lease.tags(),
+        );
+        let (token, expires) = lease_holder(&entry).unwrap();
+        assert_eq!(token, lease.token);
+        // the expiry is stored with millisecond precision
+        assert!(lease.expires.duration_since(expires).unwrap() < Duration::from_millis(1));
+    }
+}
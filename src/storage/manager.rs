@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_lock::Mutex;
+
+use crate::{
+    backend::{any::AnyStore, ManageBackend},
+    error::Error,
+    protect::{PassKey, StoreKeyMethod},
+};
+
+/// The parameters used to open the store for a tenant
+#[derive(Clone, Debug)]
+pub struct TenantConfig {
+    uri: String,
+    key_method: Option<StoreKeyMethod>,
+    pass_key: PassKey<'static>,
+    profile: Option<String>,
+}
+
+impl TenantConfig {
+    /// Create a new tenant configuration for a store URI
+    pub fn new<'a>(
+        uri: impl Into<String>,
+        key_method: Option<StoreKeyMethod>,
+        pass_key: impl Into<PassKey<'a>>,
+    ) -> Self {
+        Self {
+            uri: uri.into(),
+            key_method,
+            pass_key: pass_key.into().into_owned(),
+            profile: None,
+        }
+    }
+
+    /// Set the default profile used by the store
+    pub fn with_profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    async fn open(&self) -> Result<AnyStore, Error> {
+        self.uri
+            .as_str()
+            .open_backend(
+                self.key_method.clone(),
+                self.pass_key.clone(),
+                self.profile.as_deref(),
+            )
+            .await
+    }
+}
+
+#[derive(Debug)]
+struct TenantEntry {
+    config: TenantConfig,
+    store: Option<Arc<AnyStore>>,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct ManagerState {
+    tenants: HashMap<String, TenantEntry>,
+    open_count: usize,
+    counter: u64,
+}
+
+impl ManagerState {
+    /// Take the least recently used open store which is not in use elsewhere
+    fn take_evictable(&mut self) -> Option<Arc<AnyStore>> {
+        let (_, entry) = self
+            .tenants
+            .iter_mut()
+            .filter(|(_, entry)| {
+                entry
+                    .store
+                    .as_ref()
+                    .map(|store| Arc::strong_count(store) == 1)
+                    .unwrap_or(false)
+            })
+            .min_by_key(|(_, entry)| entry.last_used)?;
+        self.open_count -= 1;
+        entry.store.take()
+    }
+}
+
+/// A manager for the stores of multiple tenants.
+///
+/// Tenant stores are opened on first access, and the least recently used
+/// stores are closed when the number of open stores exceeds the configured
+/// limit. Stores with outstanding handles are not closed, so the limit may be
+/// exceeded temporarily when many handles are held by the application.
+#[derive(Debug)]
+pub struct StoreManager {
+    max_open: usize,
+    state: Mutex<ManagerState>,
+}
+
+impl StoreManager {
+    /// Create a new store manager, limiting the number of open stores
+    pub fn new(max_open: usize) -> Self {
+        Self {
+            max_open: max_open.max(1),
+            state: Mutex::new(ManagerState::default()),
+        }
+    }
+
+    /// Register the configuration for a tenant, closing any store previously
+    /// opened for the same tenant identifier
+    pub async fn register(
+        &self,
+        tenant: impl Into<String>,
+        config: TenantConfig,
+    ) -> Result<(), Error> {
+        let prev = {
+            let mut state = self.state.lock().await;
+            let prev = state.tenants.insert(
+                tenant.into(),
+                TenantEntry {
+                    config,
+                    store: None,
+                    last_used: 0,
+                },
+            );
+            let prev = prev.and_then(|entry| entry.store);
+            if prev.is_some() {
+                state.open_count -= 1;
+            }
+            prev
+        };
+        if let Some(store) = prev {
+            store.arc_close().await?;
+        }
+        Ok(())
+    }
+
+    /// Remove a tenant, closing its store if it is open.
+    ///
+    /// Returns `false` if the tenant was not registered
+    pub async fn unregister(&self, tenant: &str) -> Result<bool, Error> {
+        let prev = {
+            let mut state = self.state.lock().await;
+            match state.tenants.remove(tenant) {
+                Some(entry) => {
+                    if entry.store.is_some() {
+                        state.open_count -= 1;
+                    }
+                    Some(entry.store)
+                }
+                None => None,
+            }
+        };
+        match prev {
+            Some(Some(store)) => {
+                store.arc_close().await?;
+                Ok(true)
+            }
+            Some(None) => Ok(true),
+            None => Ok(false),
+        }
+    }
+
+    /// Get a handle to the store for a tenant, opening it if necessary
+    pub async fn get(&self, tenant: &str) -> Result<Arc<AnyStore>, Error> {
+        let mut evicted = Vec::new();
+        let result = {
+            let mut state = self.state.lock().await;
+            state.counter += 1;
+            let counter = state.counter;
+            let entry = state
+                .tenants
+                .get_mut(tenant)
+                .ok_or_else(|| err_msg!(NotFound, "Unknown tenant: {}", tenant))?;
+            entry.last_used = counter;
+            if let Some(store) = entry.store.as_ref() {
+                Ok(store.clone())
+            } else {
+                // stores are opened while holding the lock, in order to avoid
+                // opening the same store concurrently
+                let store = Arc::new(entry.config.open().await?);
+                entry.store.replace(store.clone());
+                state.open_count += 1;
+                while state.open_count > self.max_open {
+                    match state.take_evictable() {
+                        Some(store) => evicted.push(store),
+                        None => break,
+                    }
+                }
+                Ok(store)
+            }
+        };
+        for store in evicted {
+            debug!("Closing least recently used store");
+            store.arc_close().await?;
+        }
+        result
+    }
+
+    /// Check whether the store for a tenant is currently open
+    pub async fn is_open(&self, tenant: &str) -> bool {
+        let state = self.state.lock().await;
+        state
+            .tenants
+            .get(tenant)
+            .map(|entry| entry.store.is_some())
+            .unwrap_or(false)
+    }
+
+    /// Get the number of currently open stores
+    pub async fn open_count(&self) -> usize {
+        self.state.lock().await.open_count
+    }
+
+    /// Close all open tenant stores
+    pub async fn close_all(&self) -> Result<(), Error> {
+        let stores = {
+            let mut state = self.state.lock().await;
+            state.open_count = 0;
+            state
+                .tenants
+                .values_mut()
+                .filter_map(|entry| entry.store.take())
+                .collect::<Vec<_>>()
+        };
+        for store in stores {
+            store.arc_close().await?;
+        }
+        Ok(())
+    }
+}
@@ -1,11 +1,76 @@
+mod backup;
+pub use self::backup::{
+    BackupDestination, BackupHandle, BackupPolicy, BackupRun, BackupSource, FileBackupDestination,
+};
+
+mod capabilities;
+pub use self::capabilities::StoreCapabilities;
+
+#[cfg(feature = "any")]
+mod config;
+#[cfg(feature = "any")]
+pub use self::config::StoreConfig;
+
 mod entry;
-pub(crate) use self::entry::{EncEntryTag, EntryTagSet};
-pub use self::entry::{Entry, EntryKind, EntryOperation, EntryTag, Scan, TagFilter};
+pub use self::entry::{
+    binary_identifier, Entry, EntryExpiry, EntryHash, EntryId, EntryKind, EntryLink,
+    EntryOperation, EntrySeq, EntryTag, EntryVersion, HistoryFilter, Projection, QueryLimits, Scan,
+    ScanCheckpoint, TagFilter, TagKind,
+};
+pub(crate) use self::entry::{
+    binary_identifier_input, binary_identifier_output, split_namespace, EncEntryTag, EntryTagSet,
+};
+
+mod export;
+pub use self::export::{verify_backup, BackupSummary, ExportFilter, PlaintextExport};
+
+mod lease;
+pub use self::lease::Lease;
+
+#[cfg(feature = "any")]
+mod manager;
+#[cfg(feature = "any")]
+pub use self::manager::{StoreManager, TenantConfig};
 
 mod options;
 pub(crate) use self::options::{IntoOptions, Options};
 
+mod outbox;
+pub use self::outbox::OutboxMessage;
+
+mod policy;
+pub use self::policy::{EntryWrite, KeyExport, StorePolicy, RESERVED_CATEGORY_PREFIX};
+
+mod profile_name;
+pub use self::profile_name::{ProfileNameFormat, ProfileNaming, MAX_PROFILE_NAME_LEN};
+
+#[cfg(feature = "raw_query")]
+mod raw;
+#[cfg(feature = "raw_query")]
+pub use self::raw::{RawDecryptor, RawRow, RawValue};
+
+mod protection;
+
+mod report;
+pub use self::report::{
+    CategoryUsage, IntegrityIssue, IntegrityIssueKind, IntegrityReport, ProfileUsage, StorageReport,
+};
+
+mod shred;
+pub use self::shred::ProfileShredding;
+
+mod stats;
+pub use self::stats::SessionStats;
+
 mod store;
 pub use self::store::{Session, Store};
 
+pub mod sync;
+
+mod tuning;
+pub use self::tuning::StoreTuning;
+
+mod unique;
+pub use self::unique::UniqueConstraint;
+
 pub(crate) mod wql;
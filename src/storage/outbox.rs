@@ -0,0 +1,98 @@
+//! Transactional outbox support.
+//!
+//! Messages are queued using `Session::queue_outbox`, within the same
+//! transaction as the records they describe, so that a message is only
+//! published when the transaction commits. A relay collects the pending
+//! messages with `Store::poll_outbox` and removes each message using
+//! `Session::ack_outbox` once it has been delivered.
+//!
+//! Delivery is at-least-once: a polled message is hidden from other pollers
+//! for the visibility timeout, after which it is returned again unless it has
+//! been acknowledged.
+
+use std::time::{Duration, SystemTime};
+
+use super::entry::{Entry, EntryTag, TagFilter};
+use crate::{crypto::buffer::SecretBytes, error::Error};
+
+/// The reserved category of the outbox messages of a profile
+pub(crate) const OUTBOX_CATEGORY: &str = "askar:outbox";
+
+const VISIBLE_TAG: &str = "visible";
+
+const ATTEMPTS_TAG: &str = "attempts";
+
+/// A message collected from the outbox of a profile
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutboxMessage {
+    /// The identifier assigned to the message when it was queued
+    pub id: String,
+    /// The message content
+    pub message: SecretBytes,
+    /// The number of times the message has been polled, including this time
+    pub attempts: u32,
+}
+
+/// The current time in milliseconds, encoded so that timestamps are ordered
+/// when compared as strings
+pub(crate) fn outbox_timestamp(after: Duration) -> String {
+    let time = SystemTime::now() + after;
+    let millis = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    format!("{:020}", millis)
+}
+
+/// The filter selecting the messages which are visible to pollers
+pub(crate) fn outbox_visible_filter() -> TagFilter {
+    TagFilter::is_lte(
+        format!("~{}", VISIBLE_TAG),
+        outbox_timestamp(Duration::default()),
+    )
+}
+
+/// The tags of an outbox message hidden until the time `visible`
+pub(crate) fn outbox_tags(visible: String, attempts: u32) -> Vec<EntryTag> {
+    vec![
+        EntryTag::Plaintext(VISIBLE_TAG.to_string(), visible),
+        EntryTag::Plaintext(ATTEMPTS_TAG.to_string(), attempts.to_string()),
+    ]
+}
+
+impl OutboxMessage {
+    /// Claim a message collected by a poller, returning the message and the
+    /// tags which hide it for the visibility timeout
+    pub(crate) fn claim(
+        entry: Entry,
+        visibility_timeout: Duration,
+    ) -> Result<(Self, Vec<EntryTag>), Error> {
+        let attempts = entry
+            .tag_parse::<u32>(ATTEMPTS_TAG)?
+            .unwrap_or_default()
+            .saturating_add(1);
+        let tags = outbox_tags(outbox_timestamp(visibility_timeout), attempts);
+        Ok((
+            Self {
+                id: entry.name,
+                message: entry.value,
+                attempts,
+            },
+            tags,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outbox_timestamp_order() {
+        let now = outbox_timestamp(Duration::default());
+        let later = outbox_timestamp(Duration::from_secs(30));
+        assert_eq!(now.len(), later.len());
+        assert!(now < later);
+        assert!(outbox_timestamp(Duration::default()) >= now);
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt::Debug;
+
+use super::entry::{EntryKind, EntryOperation, EntryTag};
+use crate::{error::Error, kms::KeyExportFormat};
+
+/// The category prefix reserved for records managed by the store
+pub const RESERVED_CATEGORY_PREFIX: &str = "askar:";
+
+/// A record which is about to be written to the store
+#[derive(Debug)]
+pub struct EntryWrite<'a> {
+    /// The kind of the entry record
+    pub kind: EntryKind,
+    /// The operation being performed
+    pub operation: EntryOperation,
+    /// The category of the entry record
+    pub category: &'a str,
+    /// The name of the entry record
+    pub name: &'a str,
+    /// The value of the entry record
+    pub value: &'a [u8],
+    /// Tags associated with the entry record
+    pub tags: &'a [EntryTag],
+}
+
+/// A stored key which is about to be exported
+#[derive(Debug)]
+pub struct KeyExport<'a> {
+    /// The name of the key entry
+    pub name: &'a str,
+    /// The algorithm of the key, if known
+    pub algorithm: Option<&'a str>,
+    /// The requested export format
+    pub format: KeyExportFormat,
+}
+
+/// A deployment-specific policy for validating records before they are written.
+///
+/// The policy is invoked before each record is inserted or replaced, and may be
+/// used to enforce category naming rules, required tags, or checks on the
+/// record value. A rejected write fails with a `PolicyViolation` error.
+pub trait StorePolicy: Debug + Send + Sync {
+    /// Validate a record, returning the reason for the rejection if it is not
+    /// permitted by the policy
+    fn validate(&self, entry: &EntryWrite<'_>) -> Result<(), String>;
+
+    /// Validate the export of a stored key, returning the reason for the
+    /// rejection if it is not permitted by the policy. All exports are
+    /// permitted by default.
+    fn validate_key_export(&self, _export: &KeyExport<'_>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Ensure that generic records are not written to the reserved namespace
+pub(crate) fn check_reserved_category(category: &str) -> Result<(), Error> {
+    if category.starts_with(RESERVED_CATEGORY_PREFIX) {
+        Err(err_msg!(
+            PolicyViolation,
+            "Categories beginning with '{}' are reserved",
+            RESERVED_CATEGORY_PREFIX
+        ))
+    } else {
+        Ok(())
+    }
+}
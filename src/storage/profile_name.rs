@@ -0,0 +1,157 @@
+use std::{
+    fmt::Debug,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{crypto::random::fill_random, error::Error};
+
+/// The maximum length of a profile name, in bytes
+pub const MAX_PROFILE_NAME_LEN: usize = 255;
+
+const CROCKFORD_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// A deployment-specific scheme for generating and validating profile names.
+///
+/// The generator is used when a profile is created without an explicit name.
+/// Every profile name passed to the store is checked by `validate`, after the
+/// basic checks shared by all schemes. A rejected name fails with an `Input`
+/// error.
+pub trait ProfileNaming: Debug + Send + Sync {
+    /// Generate the name of a new profile
+    fn generate(&self) -> String;
+
+    /// Validate a profile name, returning the reason for the rejection if it
+    /// is not permitted
+    fn validate(&self, name: &str) -> Result<(), String> {
+        check_name_chars(name)
+    }
+}
+
+/// The built-in profile naming schemes.
+///
+/// Names are restricted to ASCII letters, digits and the characters `-_.:`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProfileNameFormat {
+    /// A random (version 4) UUID, matching the names generated by default
+    Uuid,
+    /// A ULID, which sorts in order of creation time
+    Ulid,
+    /// A random UUID following a fixed prefix. Names without the prefix are
+    /// rejected
+    Prefixed(String),
+}
+
+impl Default for ProfileNameFormat {
+    fn default() -> Self {
+        Self::Uuid
+    }
+}
+
+impl ProfileNaming for ProfileNameFormat {
+    fn generate(&self) -> String {
+        match self {
+            Self::Uuid => uuid::Uuid::new_v4().to_string(),
+            Self::Ulid => generate_ulid(),
+            Self::Prefixed(prefix) => format!("{}{}", prefix, uuid::Uuid::new_v4()),
+        }
+    }
+
+    fn validate(&self, name: &str) -> Result<(), String> {
+        if let Self::Prefixed(prefix) = self {
+            if !name.starts_with(prefix.as_str()) || name.len() == prefix.len() {
+                return Err(format!("expected a name beginning with '{}'", prefix));
+            }
+        }
+        check_name_chars(name)
+    }
+}
+
+fn check_name_chars(name: &str) -> Result<(), String> {
+    match name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':')))
+    {
+        Some(c) => Err(format!("unsupported character {:?}", c)),
+        None => Ok(()),
+    }
+}
+
+/// Generate a ULID: a 48-bit millisecond timestamp followed by 80 random bits,
+/// encoded as 26 characters of Crockford base32
+fn generate_ulid() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u128)
+        .unwrap_or(0);
+    let mut rand = [0u8; 16];
+    fill_random(&mut rand[6..]);
+    let value = (millis & ((1 << 48) - 1)) << 80 | u128::from_be_bytes(rand);
+    (0..26)
+        .rev()
+        .map(|idx| CROCKFORD_ALPHABET[((value >> (idx * 5)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+/// Check a profile name against the rules shared by all naming schemes, and
+/// then against the configured scheme if any
+pub(crate) fn check_profile_name(
+    naming: Option<&dyn ProfileNaming>,
+    name: &str,
+) -> Result<(), Error> {
+    if name.is_empty() {
+        return Err(err_msg!(Input, "Profile name must not be empty"));
+    }
+    if name.len() > MAX_PROFILE_NAME_LEN {
+        return Err(err_msg!(
+            Input,
+            "Profile name exceeds the maximum length of {} bytes",
+            MAX_PROFILE_NAME_LEN
+        ));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(err_msg!(
+            Input,
+            "Profile name must not contain control characters"
+        ));
+    }
+    if let Some(naming) = naming {
+        naming
+            .validate(name)
+            .map_err(|reason| err_msg!(Input, "Invalid profile name: {}", reason))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ulid_format() {
+        let first = ProfileNameFormat::Ulid.generate();
+        assert_eq!(first.len(), 26);
+        assert!(ProfileNameFormat::Ulid.validate(&first).is_ok());
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let second = ProfileNameFormat::Ulid.generate();
+        assert!(first[..10] < second[..10]);
+    }
+
+    #[test]
+    fn prefixed_format() {
+        let format = ProfileNameFormat::Prefixed("tenant-".to_string());
+        let name = format.generate();
+        assert!(name.starts_with("tenant-"));
+        assert!(check_profile_name(Some(&format), &name).is_ok());
+        assert!(check_profile_name(Some(&format), "tenant-").is_err());
+        assert!(check_profile_name(Some(&format), "other-1").is_err());
+        assert!(check_profile_name(Some(&format), "tenant-a b").is_err());
+    }
+
+    #[test]
+    fn shared_checks() {
+        assert!(check_profile_name(None, "any name").is_ok());
+        assert!(check_profile_name(None, "").is_err());
+        assert!(check_profile_name(None, "line\nbreak").is_err());
+        assert!(check_profile_name(None, &"a".repeat(MAX_PROFILE_NAME_LEN + 1)).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+//! Protection of critical records from removal.
+//!
+//! A record marked using `Session::set_protected` is not removed by
+//! `Session::remove`, `Session::update`, `Session::remove_all` or
+//! `Session::remove_collection` unless the removal is forced, guarding
+//! records such as link secrets against accidental bulk deletes. The
+//! protection of a record is retained when it is replaced, and its marker is
+//! removed along with the record.
+//!
+//! As an expired record is no longer accessible, a record with an expiry may
+//! not be protected and an expiry may not be set on a protected record.
+
+use super::entry::EntryTag;
+use crate::error::Error;
+
+/// The reserved category of the markers recording protected records
+pub(crate) const PROTECTED_CATEGORY: &str = "askar:protected";
+
+/// The tag of a protection marker holding the category of the record
+pub(crate) const PROTECTED_CATEGORY_TAG: &str = "category";
+
+/// The name of the marker recording the protection of a record
+pub(crate) fn protection_marker(category: &str, name: &str) -> Result<String, Error> {
+    serde_json::to_string(&(category, name))
+        .map_err(err_map!(Unexpected, "Error encoding protection marker"))
+}
+
+/// Parse the name of a protection marker into the record category and name
+pub(crate) fn parse_protection_marker(marker: &str) -> Result<(String, String), Error> {
+    serde_json::from_str(marker).map_err(err_map!(Unexpected, "Error parsing protection marker"))
+}
+
+/// The tags of the marker for a protected record
+pub(crate) fn protection_tags(category: &str) -> Vec<EntryTag> {
+    vec![EntryTag::Encrypted(
+        PROTECTED_CATEGORY_TAG.to_string(),
+        category.to_string(),
+    )]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protection_marker_round_trip() {
+        let marker = protection_marker("cat:egory", "na\"me").unwrap();
+        assert_eq!(
+            parse_protection_marker(&marker).unwrap(),
+            ("cat:egory".to_string(), "na\"me".to_string())
+        );
+        assert!(parse_protection_marker("invalid").is_err());
+    }
+}
@@ -0,0 +1,457 @@
+//! Read-only access to the store tables for reporting.
+//!
+//! [`Store::raw_query`](crate::Store::raw_query) runs a single `SELECT`
+//! statement against the record tables and returns the rows as stored, with
+//! categories, names, values and tag names still encrypted. A [`RawDecryptor`]
+//! for a profile may then be used to decrypt the columns selected from the
+//! records of that profile.
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+};
+
+use crate::{
+    crypto::buffer::SecretBytes,
+    error::Error,
+    protect::{EntryEncryptor, ProfileId, ProfileKey},
+};
+
+/// The tables which may be referenced by a raw query
+pub(crate) const RAW_QUERY_TABLES: &[&str] = &[
+    "profiles",
+    "items",
+    "items_tags",
+    "items_search",
+    "items_history",
+];
+
+/// Keywords which may not appear in a raw query, because they would write
+/// to the database or read outside of the store tables
+const FORBIDDEN_WORDS: &[&str] = &[
+    "into",
+    "current_setting",
+    "dblink",
+    "information_schema",
+    "load_extension",
+    "readfile",
+    "set_config",
+    "writefile",
+];
+
+const FORBIDDEN_PREFIXES: &[&str] = &["pg_", "lo_", "sqlite_"];
+
+/// Keywords ending the list of tables following `FROM`
+const CLAUSE_WORDS: &[&str] = &[
+    "except",
+    "group",
+    "having",
+    "intersect",
+    "limit",
+    "offset",
+    "order",
+    "union",
+    "where",
+    "window",
+];
+
+/// A parameter of a raw query, or a column value of a returned row
+#[derive(Clone, Debug, PartialEq)]
+pub enum RawValue {
+    /// A null value
+    Null,
+    /// An integer value
+    Integer(i64),
+    /// A floating point value
+    Real(f64),
+    /// A text value
+    Text(String),
+    /// A binary value
+    Blob(Vec<u8>),
+}
+
+impl RawValue {
+    /// Check whether the value is null
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+
+    /// Get the value as an integer, if applicable
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Get the value as a string, if applicable
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Text(value) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Get the value as a byte slice, if applicable
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::Blob(value) => Some(value.as_slice()),
+            Self::Text(value) => Some(value.as_bytes()),
+            _ => None,
+        }
+    }
+}
+
+impl From<i64> for RawValue {
+    fn from(value: i64) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<f64> for RawValue {
+    fn from(value: f64) -> Self {
+        Self::Real(value)
+    }
+}
+
+impl From<&str> for RawValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+
+impl From<String> for RawValue {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<Vec<u8>> for RawValue {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Blob(value)
+    }
+}
+
+impl<T: Into<RawValue>> From<Option<T>> for RawValue {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Self::Null)
+    }
+}
+
+/// A row returned by a raw query
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RawRow {
+    columns: Vec<(String, RawValue)>,
+}
+
+impl RawRow {
+    #[allow(unused)]
+    pub(crate) fn new(columns: Vec<(String, RawValue)>) -> Self {
+        Self { columns }
+    }
+
+    /// The number of columns in the row
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Check whether the row has no columns
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Get a column value by its index
+    pub fn get(&self, index: usize) -> Option<&RawValue> {
+        self.columns.get(index).map(|(_, value)| value)
+    }
+
+    /// Get a column value by its name
+    pub fn get_named(&self, name: &str) -> Option<&RawValue> {
+        self.columns
+            .iter()
+            .find(|(col, _)| col == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Iterate the column names and values
+    pub fn columns(&self) -> impl Iterator<Item = (&str, &RawValue)> {
+        self.columns
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+}
+
+/// Decrypts the columns returned by a raw query for the records of a profile
+#[derive(Clone)]
+pub struct RawDecryptor {
+    profile_id: ProfileId,
+    key: Arc<ProfileKey>,
+}
+
+impl Debug for RawDecryptor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawDecryptor")
+            .field("profile_id", &self.profile_id)
+            .finish()
+    }
+}
+
+impl RawDecryptor {
+    pub(crate) fn new(profile_id: ProfileId, key: Arc<ProfileKey>) -> Self {
+        Self { profile_id, key }
+    }
+
+    /// The identifier of the profile, which may be compared with the
+    /// `profile_id` column of the record tables
+    pub fn profile_id(&self) -> ProfileId {
+        self.profile_id
+    }
+
+    /// Decrypt the `category` column of a record
+    pub fn decrypt_category(&self, enc_category: &[u8]) -> Result<String, Error> {
+        self.key.decrypt_entry_category(enc_category.to_vec())
+    }
+
+    /// Decrypt the `name` column of a record
+    pub fn decrypt_name(&self, enc_name: &[u8]) -> Result<String, Error> {
+        self.key.decrypt_entry_name(enc_name.to_vec())
+    }
+
+    /// Decrypt the `value` column of a record, given its decrypted category
+    /// and name
+    pub fn decrypt_value(
+        &self,
+        category: &str,
+        name: &str,
+        enc_value: &[u8],
+    ) -> Result<SecretBytes, Error> {
+        self.key
+            .decrypt_entry_value(category.as_bytes(), name.as_bytes(), enc_value.to_vec())
+    }
+
+    /// Decrypt the `name` column of a record tag
+    pub fn decrypt_tag_name(&self, enc_name: &[u8]) -> Result<String, Error> {
+        decode_utf8(self.key.decrypt_tag_name(enc_name.to_vec())?)
+    }
+
+    /// Decrypt the `value` column of a record tag. The values of plaintext
+    /// tags are returned as stored
+    pub fn decrypt_tag_value(&self, enc_value: &[u8], plaintext: bool) -> Result<String, Error> {
+        if plaintext {
+            decode_utf8(SecretBytes::from_slice(enc_value))
+        } else {
+            decode_utf8(self.key.decrypt_tag_value(enc_value.to_vec())?)
+        }
+    }
+}
+
+fn decode_utf8(value: SecretBytes) -> Result<String, Error> {
+    String::from_utf8(value.into_vec())
+        .map_err(err_map!(Encryption, "Error decoding decrypted value"))
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Symbol(char),
+    Literal,
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => (),
+            '\'' => loop {
+                match chars.next() {
+                    Some('\'') if chars.peek() == Some(&'\'') => {
+                        chars.next();
+                    }
+                    Some('\'') => {
+                        tokens.push(Token::Literal);
+                        break;
+                    }
+                    Some(_) => (),
+                    None => return Err(err_msg!(Input, "Unterminated string literal")),
+                }
+            },
+            '"' | '`' | '[' => {
+                return Err(err_msg!(
+                    Input,
+                    "Quoted identifiers are not supported in raw queries"
+                ))
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                return Err(err_msg!(Input, "Comments are not supported in raw queries"))
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                return Err(err_msg!(Input, "Comments are not supported in raw queries"))
+            }
+            ';' => {
+                return Err(err_msg!(
+                    Input,
+                    "Raw queries must consist of a single statement"
+                ))
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut word = c.to_ascii_lowercase().to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' || c == '$' {
+                        word.push(c.to_ascii_lowercase());
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Word(word));
+            }
+            c if c.is_ascii_digit() || c == '$' => {
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '.' {
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Literal);
+            }
+            c => tokens.push(Token::Symbol(c)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn check_word(word: &str) -> Result<(), Error> {
+    if FORBIDDEN_WORDS.contains(&word) || FORBIDDEN_PREFIXES.iter().any(|p| word.starts_with(p)) {
+        Err(err_msg!(
+            Forbidden,
+            "'{}' is not permitted in raw queries",
+            word
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn check_table(name: &str, next: Option<&Token>) -> Result<(), Error> {
+    if next == Some(&Token::Symbol('.')) {
+        return Err(err_msg!(
+            Input,
+            "Qualified table names are not supported in raw queries"
+        ));
+    }
+    if !RAW_QUERY_TABLES.contains(&name) {
+        return Err(err_msg!(
+            Forbidden,
+            "Table '{}' is not accessible to raw queries",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Check that a raw query consists of a single `SELECT` statement, which
+/// only reads from the record tables of the store
+pub(crate) fn check_raw_query(sql: &str) -> Result<(), Error> {
+    let tokens = tokenize(sql)?;
+    if tokens.first() != Some(&Token::Word("select".to_string())) {
+        return Err(err_msg!(Input, "Raw queries must be SELECT statements"));
+    }
+    // whether each level of nesting is within the table list of a FROM clause
+    let mut table_lists = vec![false];
+    let mut expect_table = false;
+    for (idx, token) in tokens.iter().enumerate() {
+        if expect_table {
+            expect_table = false;
+            match token {
+                Token::Word(name) => {
+                    check_table(name, tokens.get(idx + 1))?;
+                    continue;
+                }
+                Token::Symbol('(') => (),
+                _ => return Err(err_msg!(Input, "Expected a table name in raw query")),
+            }
+        }
+        match token {
+            Token::Symbol('(') => table_lists.push(false),
+            Token::Symbol(')') => {
+                table_lists.pop();
+                if table_lists.is_empty() {
+                    return Err(err_msg!(Input, "Unbalanced parentheses in raw query"));
+                }
+            }
+            Token::Symbol(',') => expect_table = table_lists[table_lists.len() - 1],
+            Token::Word(word) => {
+                check_word(word)?;
+                let in_list = table_lists.last_mut().unwrap();
+                if word == "from" {
+                    *in_list = true;
+                    expect_table = true;
+                } else if word == "join" {
+                    expect_table = true;
+                } else if CLAUSE_WORDS.contains(&word.as_str()) {
+                    *in_list = false;
+                }
+            }
+            _ => (),
+        }
+    }
+    if expect_table {
+        return Err(err_msg!(Input, "Expected a table name in raw query"));
+    }
+    if table_lists.len() != 1 {
+        return Err(err_msg!(Input, "Unbalanced parentheses in raw query"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    #[test]
+    fn check_raw_query_allowed() {
+        for sql in &[
+            "SELECT COUNT(*) FROM items WHERE profile_id = $1",
+            "select i.category, count(*) from items i group by i.category",
+            "SELECT i.id, t.name FROM items i JOIN items_tags t ON t.item_id = i.id",
+            "SELECT name FROM items, profiles p WHERE p.id = items.profile_id",
+            "SELECT x.n FROM (SELECT COUNT(*) AS n FROM items_history) x",
+            "SELECT id FROM items WHERE name = 'from config; --'",
+            "SELECT id FROM items WHERE id IN (1, 2, 3) ORDER BY id LIMIT 5",
+        ] {
+            assert!(
+                check_raw_query(sql).is_ok(),
+                "expected query to pass: {}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn check_raw_query_rejected() {
+        for (sql, kind) in &[
+            ("DELETE FROM items", ErrorKind::Input),
+            ("SELECT * FROM items; DELETE FROM items", ErrorKind::Input),
+            ("SELECT * FROM config", ErrorKind::Forbidden),
+            ("SELECT * FROM items, config", ErrorKind::Forbidden),
+            (
+                "SELECT * FROM items i JOIN config c ON 1=1",
+                ErrorKind::Forbidden,
+            ),
+            ("SELECT * FROM (SELECT 1) x, config", ErrorKind::Forbidden),
+            ("SELECT * FROM main.items", ErrorKind::Input),
+            ("SELECT * FROM \"config\"", ErrorKind::Input),
+            ("SELECT * INTO copy FROM items", ErrorKind::Forbidden),
+            ("SELECT pg_read_file('x')", ErrorKind::Forbidden),
+            ("SELECT * FROM sqlite_master", ErrorKind::Forbidden),
+            ("SELECT * FROM items -- comment", ErrorKind::Input),
+            ("SELECT * FROM (SELECT 1", ErrorKind::Input),
+        ] {
+            let err = check_raw_query(sql).expect_err(sql);
+            assert_eq!(err.kind(), *kind, "unexpected error for query: {}", sql);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use super::entry::EntryKind;
+
+/// Storage usage totals for the records of a store, grouped by profile
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StorageReport {
+    /// The usage of each profile containing records
+    pub profiles: Vec<ProfileUsage>,
+}
+
+impl StorageReport {
+    /// Get the usage of a single profile
+    pub fn profile(&self, name: &str) -> Option<&ProfileUsage> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    /// The total number of records in the store
+    pub fn count(&self) -> i64 {
+        self.profiles.iter().map(|p| p.count).sum()
+    }
+
+    /// The total size of the stored ciphertexts, in bytes
+    pub fn bytes(&self) -> i64 {
+        self.profiles.iter().map(|p| p.bytes).sum()
+    }
+
+    #[allow(unused)]
+    pub(crate) fn add_profile(&mut self, profile: &str) -> &mut ProfileUsage {
+        let idx = match self.profiles.iter().position(|p| p.name == profile) {
+            Some(idx) => idx,
+            None => {
+                self.profiles.push(ProfileUsage {
+                    name: profile.to_string(),
+                    ..Default::default()
+                });
+                self.profiles.len() - 1
+            }
+        };
+        &mut self.profiles[idx]
+    }
+
+    #[allow(unused)]
+    pub(crate) fn add(&mut self, profile: &str, usage: CategoryUsage) {
+        let entry = self.add_profile(profile);
+        entry.count += usage.count;
+        entry.bytes += usage.bytes;
+        entry.categories.push(usage);
+    }
+}
+
+/// Storage usage totals for a single profile
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProfileUsage {
+    /// The profile name
+    pub name: String,
+    /// The number of records in the profile
+    pub count: i64,
+    /// The total size of the stored ciphertexts, in bytes
+    pub bytes: i64,
+    /// The usage of each category of records in the profile
+    pub categories: Vec<CategoryUsage>,
+}
+
+impl ProfileUsage {
+    /// Get the usage of a single category of generic records
+    pub fn category(&self, category: &str) -> Option<&CategoryUsage> {
+        self.categories.iter().find(|c| {
+            c.kind == EntryKind::Item
+                && c.namespace.is_none()
+                && c.category.as_deref() == Some(category)
+        })
+    }
+
+    /// Get the usage of a single category of generic records within a namespace
+    pub fn namespace_category(&self, namespace: &str, category: &str) -> Option<&CategoryUsage> {
+        self.categories.iter().find(|c| {
+            c.kind == EntryKind::Item
+                && c.namespace.as_deref() == Some(namespace)
+                && c.category.as_deref() == Some(category)
+        })
+    }
+}
+
+/// Storage usage totals for a single category of records
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CategoryUsage {
+    /// The kind of the records
+    pub kind: EntryKind,
+    /// The namespace of the records, if any
+    pub namespace: Option<String>,
+    /// The record category, or `None` if the profile key was not available
+    /// to decrypt it
+    pub category: Option<String>,
+    /// The number of records in the category, including expired records which
+    /// have not yet been removed
+    pub count: i64,
+    /// The total size of the encrypted categories, names, values and tags
+    /// of the records, in bytes
+    pub bytes: i64,
+}
+
+/// The outcome of verifying the stored values of a profile
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// The number of records examined
+    pub checked: i64,
+    /// The number of records whose value matched the stored checksum
+    pub verified: i64,
+    /// The number of records which were decrypted successfully but have no
+    /// stored checksum, having been written while checksums were disabled
+    pub unverified: i64,
+    /// The records which failed verification
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    /// Determine whether every record passed verification
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    #[allow(unused)]
+    pub(crate) fn append(&mut self, other: IntegrityReport) {
+        self.checked += other.checked;
+        self.verified += other.verified;
+        self.unverified += other.unverified;
+        self.issues.extend(other.issues);
+    }
+}
+
+/// A record which failed verification
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IntegrityIssue {
+    /// The kind of the record
+    pub kind: EntryKind,
+    /// The record category, or `None` if it could not be decrypted
+    pub category: Option<String>,
+    /// The record name, or `None` if it could not be decrypted
+    pub name: Option<String>,
+    /// The reason for the failure
+    pub reason: IntegrityIssueKind,
+}
+
+/// The reason a record failed verification
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegrityIssueKind {
+    /// The category or name could not be decrypted with the profile key,
+    /// indicating that the record was not written using this key
+    WrongKey,
+    /// The category and name were decrypted, but the value could not be.
+    /// The ciphertext has been modified or truncated
+    Corrupted,
+    /// The value was decrypted, but does not match the stored checksum
+    ChecksumMismatch,
+}
@@ -0,0 +1,70 @@
+//! Cryptographic erasure of profiles.
+//!
+//! The records of a profile are encrypted by its profile key, which is only
+//! stored in wrapped form. Shredding a profile removes the wrapped key along
+//! with the profile records, and asks the tenant key provider to destroy the
+//! tenant key if one was used to wrap it. Once the tenant key is destroyed,
+//! copies of the profile in earlier database backups can no longer be
+//! decrypted. Profiles wrapped by the store key remain readable from backups
+//! taken before shredding, by anyone holding the store key.
+
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+
+/// The attestation returned by `Store::shred_profile`, which may be retained
+/// as a record of the erasure
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProfileShredding {
+    /// The name of the shredded profile
+    pub profile: String,
+    /// The time at which the profile was shredded
+    pub shredded_at: SystemTime,
+    /// The hex-encoded SHA-256 digest of the wrapped profile key as it was
+    /// stored, identifying the destroyed key without revealing it
+    pub key_fingerprint: String,
+    /// Whether the profile key was wrapped by a tenant key
+    pub tenant_key: bool,
+    /// Whether the tenant key provider destroyed the tenant key, after which
+    /// the profile cannot be recovered from backups
+    pub tenant_key_destroyed: bool,
+}
+
+impl ProfileShredding {
+    pub(crate) fn new(
+        profile: String,
+        wrapped_key: &[u8],
+        tenant_key: bool,
+        tenant_key_destroyed: bool,
+    ) -> Self {
+        Self {
+            profile,
+            shredded_at: SystemTime::now(),
+            key_fingerprint: hex::encode(Sha256::digest(wrapped_key)),
+            tenant_key,
+            tenant_key_destroyed,
+        }
+    }
+
+    /// Check whether copies of the profile in existing backups are also
+    /// unrecoverable
+    pub fn is_unrecoverable(&self) -> bool {
+        self.tenant_key && self.tenant_key_destroyed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shredding_fingerprint() {
+        let record = ProfileShredding::new("profile".to_string(), b"wrapped", false, false);
+        assert_eq!(record.key_fingerprint.len(), 64);
+        assert_eq!(
+            record.key_fingerprint,
+            ProfileShredding::new("other".to_string(), b"wrapped", true, true).key_fingerprint
+        );
+        assert!(!record.is_unrecoverable());
+    }
+}
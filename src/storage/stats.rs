@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+/// Counters describing the work performed by a session, used to identify
+/// the requests responsible for the load on a store
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SessionStats {
+    /// The number of backend operations performed
+    pub operations: u64,
+    /// The number of records and keys returned by fetch and search operations
+    pub rows_read: u64,
+    /// The number of records and keys inserted, replaced or removed
+    pub rows_written: u64,
+    /// The total duration of the backend operations
+    pub elapsed: Duration,
+    /// The total time spent encrypting and decrypting records
+    pub crypto_time: Duration,
+    /// The number of operations exceeding the slow operation threshold
+    pub slow_operations: u64,
+}
+
+impl SessionStats {
+    pub(crate) fn add_read(&mut self, rows: usize) {
+        self.rows_read += rows as u64;
+    }
+
+    pub(crate) fn add_written(&mut self, rows: usize) {
+        self.rows_written += rows as u64;
+    }
+}
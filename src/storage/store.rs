@@ -1,56 +1,793 @@
-use std::sync::Arc;
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+
+use futures_lite::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
-use super::entry::{Entry, EntryKind, EntryOperation, EntryTag, Scan, TagFilter};
+#[cfg(feature = "raw_query")]
+use super::raw::{check_raw_query, RawDecryptor, RawRow, RawValue};
+use super::{
+    backup::{run_backup, spawn_backups, BackupHandle, BackupPolicy, BackupRun},
+    capabilities::StoreCapabilities,
+    entry::{
+        binary_identifier, check_category, check_namespace, namespaced_category, split_namespace,
+        Entry, EntryExpiry, EntryHash, EntryId, EntryKind, EntryLink, EntryOperation, EntrySeq,
+        EntryTag, EntryVersion, HistoryFilter, Projection, QueryLimits, Scan, ScanCheckpoint,
+        TagFilter,
+    },
+    export::{
+        open_bundle, seal_bundle, BundleRecord, ExportFilter, NdjsonRecord, PlaintextExport,
+        IMPORT_BATCH_SIZE, IMPORT_LINE_LIMIT,
+    },
+    lease::{lease_holder, Lease, LEASE_CATEGORY},
+    outbox::{
+        outbox_tags, outbox_timestamp, outbox_visible_filter, OutboxMessage, OUTBOX_CATEGORY,
+    },
+    policy::{
+        check_reserved_category, EntryWrite, KeyExport, StorePolicy, RESERVED_CATEGORY_PREFIX,
+    },
+    profile_name::{check_profile_name, ProfileNaming},
+    protection::{
+        parse_protection_marker, protection_marker, protection_tags, PROTECTED_CATEGORY,
+        PROTECTED_CATEGORY_TAG,
+    },
+    report::{IntegrityReport, StorageReport},
+    shred::ProfileShredding,
+    stats::SessionStats,
+    sync::{removal_marker, REMOVED_CATEGORY},
+    tuning::StoreTuning,
+    unique::UniqueConstraint,
+};
 use crate::{
-    backend::{Backend, QueryBackend},
-    error::Error,
-    kms::{KeyEntry, KeyParams, KmsCategory, LocalKey},
-    protect::{PassKey, StoreKeyMethod},
+    backend::{AccessMode, Backend, Durability, MaintenanceMode, QueryBackend, ScopedQueryBackend},
+    crypto::{alg::KeyAlg, buffer::SecretBytes, jwk::KeyOps},
+    error::{Error, ErrorContext, ErrorKind},
+    future,
+    kms::{
+        did::DidDocument, to_timestamp, KeyEntry, KeyExportFormat, KeyParams, KmsCategory, LocalKey,
+    },
+    protect::{
+        check_pass_key, request_unlock, KeyCacheStats, PassKey, PassKeyPolicy, StoreKeyMethod,
+        TenantKeyProvider, UnlockProvider, UnlockReason,
+    },
 };
 
 #[derive(Debug)]
 /// An instance of an opened store
-pub struct Store<B: Backend>(B);
+pub struct Store<B: Backend> {
+    inner: B,
+    timeout: RwLock<Option<Duration>>,
+    policy: Option<Arc<dyn StorePolicy>>,
+    naming: Option<Arc<dyn ProfileNaming>>,
+    allowed_profiles: Option<Arc<HashSet<String>>>,
+    track_changes: bool,
+    unique_constraints: Arc<Vec<UniqueConstraint>>,
+    query_limits: QueryLimits,
+    slow_threshold: Option<Duration>,
+    pass_key_policy: Option<Arc<PassKeyPolicy>>,
+    unlock_provider: Option<Arc<dyn UnlockProvider>>,
+    access_mode: CachedAccessMode,
+}
 
 impl<B: Backend> Store<B> {
     pub(crate) fn new(inner: B) -> Self {
-        Self(inner)
+        Self {
+            inner,
+            timeout: RwLock::new(None),
+            policy: None,
+            naming: None,
+            allowed_profiles: None,
+            track_changes: false,
+            unique_constraints: Arc::new(Vec::new()),
+            query_limits: QueryLimits::default(),
+            slow_threshold: None,
+            pass_key_policy: None,
+            unlock_provider: None,
+            access_mode: Arc::new(RwLock::new(None)),
+        }
     }
 
     #[cfg(test)]
     #[allow(unused)]
     pub(crate) fn inner(&self) -> &B {
-        &self.0
+        &self.inner
     }
 
     pub(crate) fn into_inner(self) -> B {
-        self.0
+        self.inner
     }
 }
 
 impl<B: Backend> Store<B> {
     /// Get the default profile name used when starting a scan or a session
     pub fn get_profile_name(&self) -> &str {
-        self.0.get_profile_name()
+        self.inner.get_profile_name()
+    }
+
+    /// Get the optional features supported by the store, allowing
+    /// applications to detect features missing from older stores
+    pub fn capabilities(&self) -> StoreCapabilities {
+        self.inner.capabilities()
+    }
+
+    /// Get the hit, miss and eviction counts of the profile key cache
+    pub fn key_cache_stats(&self) -> KeyCacheStats {
+        self.inner.key_cache_stats()
+    }
+
+    /// Set the default maximum duration of each operation performed by
+    /// sessions and scans created from this store.
+    ///
+    /// This is enforced on the client side and should be combined with the
+    /// backend options (`statement_timeout` for Postgres, or `busy_timeout`
+    /// for SQLite) so that abandoned queries are also halted by the database
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        *self.timeout.get_mut().unwrap() = timeout;
+        self
+    }
+
+    /// Get the default maximum duration of each store operation
+    pub fn timeout(&self) -> Option<Duration> {
+        *self.timeout.read().unwrap()
+    }
+
+    /// Change the runtime options of the open store, leaving those which
+    /// are not provided unchanged. Sessions and scans which have already
+    /// been started keep their previous settings
+    pub fn reconfigure(&self, tuning: StoreTuning) -> Result<(), Error> {
+        tuning.validate()?;
+        self.inner.reconfigure(&tuning)?;
+        if let Some(timeout) = tuning.session_timeout() {
+            *self.timeout.write().unwrap() = timeout;
+        }
+        Ok(())
+    }
+
+    /// Set a policy used to validate records before they are written by
+    /// sessions created from this store
+    pub fn with_policy(mut self, policy: Arc<dyn StorePolicy>) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Set the scheme used to generate the names of new profiles, and to
+    /// validate the profile names passed to the store
+    pub fn with_profile_naming(mut self, naming: Arc<dyn ProfileNaming>) -> Self {
+        self.naming = Some(naming);
+        self
+    }
+
+    /// Restrict this store handle to a set of profiles.
+    ///
+    /// Sessions, scans and profile management for any other profile (including
+    /// the default profile, unless it is listed) fail with a `Forbidden` error
+    pub fn with_allowed_profiles<I>(mut self, profiles: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.allowed_profiles = Some(Arc::new(profiles.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    /// Record the removal of generic records by sessions created from this
+    /// store, so that removals are included in the change sets produced by
+    /// `sync::changes_since`.
+    ///
+    /// Removals performed through scoped sessions are not recorded
+    pub fn with_change_tracking(mut self, enabled: bool) -> Self {
+        self.track_changes = enabled;
+        self
+    }
+
+    /// Require the values of a set of plaintext tags to be unique among the
+    /// records of a category, for records written by sessions created from
+    /// this store. Writing a record which duplicates the values of another
+    /// record fails with a `Duplicate` error.
+    ///
+    /// Each category may have a single constraint, which replaces any
+    /// constraint previously declared for the category. Writing to a
+    /// constrained category fails with an `Unsupported` error when the store
+    /// was provisioned by an earlier release without support for unique keys
+    pub fn with_unique_constraint(mut self, constraint: UniqueConstraint) -> Self {
+        let constraints = Arc::make_mut(&mut self.unique_constraints);
+        constraints.retain(|c| c.category() != constraint.category());
+        constraints.push(constraint);
+        self.inner
+            .set_unique_constraints(self.unique_constraints.clone());
+        self
+    }
+
+    /// Set the limits on the complexity of the tag filters accepted by scans
+    /// and sessions created from this store. Filters exceeding the limits
+    /// are rejected with a `QueryTooComplex` error
+    pub fn with_query_limits(mut self, limits: QueryLimits) -> Self {
+        self.query_limits = limits;
+        self
+    }
+
+    /// Log a warning for each session operation which takes longer than
+    /// `threshold`, including the operation name and the hashed profile
+    /// and category as recorded in error contexts
+    pub fn with_slow_operation_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.slow_threshold = threshold;
+        self
+    }
+
+    /// Set the policy applied to the pass key when the store is rekeyed
+    pub fn with_pass_key_policy(mut self, policy: Arc<PassKeyPolicy>) -> Self {
+        self.pass_key_policy = Some(policy);
+        self
+    }
+
+    pub(crate) fn with_optional_pass_key_policy(
+        mut self,
+        policy: Option<Arc<PassKeyPolicy>>,
+    ) -> Self {
+        self.pass_key_policy = policy;
+        self
+    }
+
+    /// Set a provider which is asked for the new pass key when the store is
+    /// rekeyed without one
+    pub fn with_unlock_provider(mut self, provider: Arc<dyn UnlockProvider>) -> Self {
+        self.unlock_provider = Some(provider);
+        self
+    }
+
+    pub(crate) fn with_optional_unlock_provider(
+        mut self,
+        provider: Option<Arc<dyn UnlockProvider>>,
+    ) -> Self {
+        self.unlock_provider = provider;
+        self
+    }
+
+    /// Check whether this store handle may access a profile
+    pub fn is_profile_allowed(&self, profile: &str) -> bool {
+        match self.allowed_profiles.as_ref() {
+            Some(allowed) => allowed.contains(profile),
+            None => true,
+        }
+    }
+
+    fn check_profile(&self, profile: Option<&str>) -> Result<(), Error> {
+        if let Some(name) = profile {
+            check_profile_name(self.naming.as_deref(), name)?;
+        }
+        let name = profile.unwrap_or_else(|| self.inner.get_profile_name());
+        if self.is_profile_allowed(name) {
+            Ok(())
+        } else {
+            Err(err_msg!(
+                Forbidden,
+                "Access to the profile is not permitted by this store handle"
+            ))
+        }
+    }
+
+    /// Set a provider of tenant keys, used to wrap the keys of new profiles
+    /// and to unwrap them when a session is opened for the profile
+    pub fn with_tenant_keys(mut self, provider: Arc<dyn TenantKeyProvider>) -> Self {
+        self.inner.set_tenant_keys(Some(provider));
+        self
+    }
+
+    /// Summarize the storage used by each profile and record category.
+    ///
+    /// Totals are computed by the database from the sizes of the encrypted
+    /// records, so that only the category names are decrypted
+    pub async fn storage_report(&self) -> Result<StorageReport, Error> {
+        let mut report = self.inner.storage_report().await?;
+        if self.allowed_profiles.is_some() {
+            report.profiles.retain(|p| self.is_profile_allowed(&p.name));
+        }
+        Ok(report)
+    }
+
+    /// Decrypt every current record of a profile, reporting the records
+    /// which cannot be read.
+    ///
+    /// For stores provisioned with value checksums, each decrypted value is
+    /// also compared with the checksum written alongside it, so that modified
+    /// values are detected as well as undecryptable ones
+    pub async fn verify_integrity(
+        &self,
+        profile: Option<String>,
+    ) -> Result<IntegrityReport, Error> {
+        self.check_profile(profile.as_deref())?;
+        Ok(self.inner.verify_integrity(profile).await?)
+    }
+
+    /// Run backend-specific housekeeping, such as reclaiming the space used
+    /// by removed records and refreshing query planner statistics.
+    ///
+    /// `MaintenanceMode::Full` rebuilds the store tables and may block other
+    /// connections until it completes
+    pub async fn maintenance(&self, mode: MaintenanceMode) -> Result<(), Error> {
+        self.check_writable().await?;
+        Ok(self.inner.maintenance(mode).await?)
+    }
+
+    /// Get the access mode of the store. The mode recorded in the store
+    /// configuration is read again once the cached value is over a second old
+    pub async fn access_mode(&self) -> Result<AccessMode, Error> {
+        if let Some((mode, checked)) = *self.access_mode.read().unwrap() {
+            if checked.elapsed() < ACCESS_MODE_REFRESH {
+                return Ok(mode);
+            }
+        }
+        let mode = self.inner.access_mode().await?;
+        *self.access_mode.write().unwrap() = Some((mode, Instant::now()));
+        Ok(mode)
+    }
+
+    /// Freeze or restore access to the store for every handle, such as
+    /// while migrations or backups are performed.
+    ///
+    /// The mode is recorded in the store configuration, and is observed by
+    /// other handles to the store within a second. In `AccessMode::ReadOnly`,
+    /// sessions reject all modifications with a `Forbidden` error, while in
+    /// `AccessMode::Offline` no sessions or scans may be started. Sessions
+    /// which are already open read the mode again before a modification
+    /// once the mode cached by their handle is over a second old
+    pub async fn set_maintenance_mode(&self, mode: AccessMode) -> Result<(), Error> {
+        self.inner.set_access_mode(mode).await?;
+        *self.access_mode.write().unwrap() = Some((mode, Instant::now()));
+        Ok(())
+    }
+
+    /// Check that the store is not offline, returning whether modifications
+    /// are permitted
+    async fn check_access(&self) -> Result<bool, Error> {
+        match self.access_mode().await? {
+            AccessMode::Offline => Err(err_msg!(Forbidden, "The store is offline for maintenance")),
+            mode => Ok(mode == AccessMode::ReadWrite),
+        }
+    }
+
+    /// Check that modifications to the store are permitted
+    async fn check_writable(&self) -> Result<(), Error> {
+        if self.check_access().await? {
+            Ok(())
+        } else {
+            Err(err_msg!(
+                Forbidden,
+                "The store is read-only for maintenance"
+            ))
+        }
     }
 
-    /// Replace the wrapping key on a store
+    /// Run a read-only `SELECT` statement against the record tables of the
+    /// store, for reporting purposes.
+    ///
+    /// Parameters are referenced as `$1`, `$2` and so on. The returned rows
+    /// contain the columns as stored, so that categories, names, values and
+    /// non-plaintext tags must be decrypted using a [`RawDecryptor`] for the
+    /// profile. As a raw query may read the records of any profile, it is not
+    /// permitted for store handles restricted to a set of profiles
+    #[cfg(feature = "raw_query")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "raw_query")))]
+    pub async fn raw_query(&self, sql: &str, params: Vec<RawValue>) -> Result<Vec<RawRow>, Error> {
+        if self.allowed_profiles.is_some() {
+            return Err(err_msg!(
+                Forbidden,
+                "Raw queries are not permitted by this store handle"
+            ));
+        }
+        check_raw_query(sql)?;
+        Ok(self.inner.raw_query(sql.to_string(), params).await?)
+    }
+
+    /// Get a decryptor for the columns returned by a raw query for the records
+    /// of a profile
+    #[cfg(feature = "raw_query")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "raw_query")))]
+    pub async fn raw_decryptor(&self, profile: Option<String>) -> Result<RawDecryptor, Error> {
+        self.check_profile(profile.as_deref())?;
+        let (profile_id, key) = self.inner.profile_key(profile).await?;
+        Ok(RawDecryptor::new(profile_id, key))
+    }
+
+    /// Replace the wrapping key on a store.
+    ///
+    /// When a pass key policy is set, a pass key used to derive the new
+    /// wrapping key is checked against it before the store is modified
     pub async fn rekey(
         &mut self,
         method: StoreKeyMethod,
         pass_key: PassKey<'_>,
     ) -> Result<(), Error> {
-        Ok(self.0.rekey_backend(method, pass_key).await?)
+        let pass_key = request_unlock(
+            self.unlock_provider.as_deref(),
+            UnlockReason::Rekey,
+            Some(&method),
+            pass_key,
+        )
+        .await?;
+        check_pass_key(self.pass_key_policy.as_deref(), &method, &pass_key)?;
+        self.check_writable().await?;
+        Ok(self.inner.rekey_backend(method, pass_key).await?)
+    }
+
+    /// Export the records and keys of a profile as a standalone encrypted
+    /// bundle, which may be imported into another store using
+    /// `import_profile`.
+    ///
+    /// The records are encrypted with a random bundle key, which is wrapped
+    /// by the key resolved from `method` and `pass_key`. Search terms,
+    /// history and record expiry times are not included, nor are records in
+    /// the reserved categories, such as protection markers.
+    pub async fn export_profile(
+        &self,
+        profile: String,
+        method: StoreKeyMethod,
+        pass_key: PassKey<'_>,
+    ) -> Result<Vec<u8>, Error> {
+        self.export_profile_filtered(profile, &ExportFilter::default(), method, pass_key)
+            .await
+    }
+
+    /// Export the records of a profile selected by `filter` as a standalone
+    /// encrypted bundle, in the same manner as `export_profile`
+    pub async fn export_profile_filtered(
+        &self,
+        profile: String,
+        filter: &ExportFilter,
+        method: StoreKeyMethod,
+        pass_key: PassKey<'_>,
+    ) -> Result<Vec<u8>, Error> {
+        self.check_profile(Some(&profile))?;
+        check_pass_key(self.pass_key_policy.as_deref(), &method, &pass_key)?;
+        let mut records = Vec::new();
+        for (kind, namespace, category) in self.export_categories(&profile, filter).await? {
+            let category = namespaced_category(namespace.as_deref(), &category).into_owned();
+            let mut scan = self
+                .inner
+                .scan(
+                    Some(profile.clone()),
+                    kind,
+                    category.clone(),
+                    filter.tag_filter.clone(),
+                    filter.history_filter(),
+                    None,
+                    None,
+                    None,
+                    Projection::Full,
+                )
+                .await?;
+            while let Some(rows) = scan.fetch_next().await? {
+                records.extend(rows.into_iter().map(|entry| BundleRecord::new(kind, entry)));
+            }
+        }
+        seal_bundle(profile, records, method, pass_key).await
+    }
+
+    /// List the kinds, namespaces and categories of the records of a profile
+    /// which are selected by an export filter. The reserved categories are
+    /// excluded, as their records are only written by the store
+    async fn export_categories(
+        &self,
+        profile: &str,
+        filter: &ExportFilter,
+    ) -> Result<Vec<(EntryKind, Option<String>, String)>, Error> {
+        if filter.modified_since.is_some() && !self.inner.capabilities().history {
+            return Err(err_msg!(
+                Unsupported,
+                "Filtering exported records by modification time requires entry history"
+            ));
+        }
+        check_tag_filter(&self.query_limits, filter.tag_filter.as_ref())?;
+        let report = self.inner.storage_report().await?;
+        let mut categories = Vec::new();
+        if let Some(usage) = report.profile(profile) {
+            for usage in &usage.categories {
+                let category = usage.category.clone().ok_or_else(|| {
+                    err_msg!(Encryption, "Error decrypting profile record category")
+                })?;
+                if filter.includes_category(usage.kind, &category)
+                    && !category.starts_with(RESERVED_CATEGORY_PREFIX)
+                {
+                    categories.push((usage.kind, usage.namespace.clone(), category));
+                }
+            }
+        }
+        Ok(categories)
+    }
+
+    /// Write the decrypted records of a profile which are selected by
+    /// `filter` to `writer` as newline-delimited JSON, returning the number
+    /// of records written.
+    ///
+    /// Each line holds a single record with its kind, namespace, category,
+    /// name, value and tags. Values which are not valid UTF-8 are written in
+    /// hex form as `value_hex`. Key entries, which hold private key material,
+    /// are only included when `include_secrets` is set. Records in the
+    /// reserved categories, such as protection markers, are not included.
+    ///
+    /// The output is not encrypted, and must be protected by the caller.
+    /// Record expiry times, search terms and history are not included
+    pub async fn export_ndjson<W>(
+        &self,
+        profile: String,
+        filter: &ExportFilter,
+        mut writer: W,
+        include_secrets: bool,
+        _ack: PlaintextExport,
+    ) -> Result<u64, Error>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.check_profile(Some(&profile))?;
+        let mut count = 0;
+        for (kind, namespace, category) in self.export_categories(&profile, filter).await? {
+            if kind == EntryKind::Kms && !include_secrets {
+                continue;
+            }
+            let stored_category = namespaced_category(namespace.as_deref(), &category);
+            let mut scan = self
+                .inner
+                .scan(
+                    Some(profile.clone()),
+                    kind,
+                    stored_category.to_string(),
+                    filter.tag_filter.clone(),
+                    filter.history_filter(),
+                    None,
+                    None,
+                    None,
+                    Projection::Full,
+                )
+                .await?;
+            while let Some(rows) = scan.fetch_next().await? {
+                let mut lines = Vec::new();
+                for entry in rows {
+                    let record = NdjsonRecord::new(kind, namespace.clone(), entry);
+                    serde_json::to_writer(&mut lines, &record)
+                        .map_err(err_map!(Unexpected, "Error encoding exported record"))?;
+                    lines.push(b'\n');
+                    count += 1;
+                }
+                writer
+                    .write_all(&lines)
+                    .await
+                    .map_err(err_map!(Backend, "Error writing exported records"))?;
+            }
+        }
+        writer
+            .flush()
+            .await
+            .map_err(err_map!(Backend, "Error writing exported records"))?;
+        Ok(count)
+    }
+
+    /// Import records written by `export_ndjson` into an existing profile,
+    /// returning the number of records imported. Blank lines are ignored.
+    ///
+    /// The records are written by a transaction session, so that they are
+    /// checked against the reserved categories and the store policy, and no
+    /// records are imported if any line is invalid or any record already
+    /// exists
+    pub async fn import_ndjson<R>(
+        &self,
+        profile: Option<String>,
+        mut reader: R,
+        _ack: PlaintextExport,
+    ) -> Result<u64, Error>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        self.check_writable().await?;
+        let mut txn = self.transaction(profile).await?;
+        let mut count = 0;
+        let mut batch: Vec<Entry> = Vec::new();
+        let mut line = String::new();
+        let result = async {
+            loop {
+                line.clear();
+                let done = (&mut reader)
+                    .take(IMPORT_LINE_LIMIT as u64 + 1)
+                    .read_line(&mut line)
+                    .await
+                    .map_err(err_map!(Input, "Error reading exported records"))?
+                    == 0;
+                if line.len() > IMPORT_LINE_LIMIT {
+                    return Err(err_msg!(
+                        Input,
+                        "Exported record exceeds the maximum line length"
+                    ));
+                }
+                let record = if done || line.trim().is_empty() {
+                    None
+                } else {
+                    let record: NdjsonRecord = serde_json::from_str(&line)
+                        .map_err(err_map!(Input, "Error parsing exported record"))?;
+                    let kind = record.kind()?;
+                    let (namespace, entry) = record.into_entry()?;
+                    Some((kind, namespace, entry))
+                };
+                // consecutive records of the same kind and namespace are
+                // inserted together
+                let flush = match record.as_ref() {
+                    Some((kind, namespace, _)) => {
+                        *kind != txn.kind
+                            || *namespace != txn.namespace
+                            || batch.len() >= IMPORT_BATCH_SIZE
+                    }
+                    None => done,
+                };
+                if flush && !batch.is_empty() {
+                    count += batch.len() as u64;
+                    txn.insert_all(std::mem::take(&mut batch)).await?;
+                }
+                if let Some((kind, namespace, entry)) = record {
+                    // key entries are imported as generic records of their kind
+                    txn.kind = kind;
+                    txn.namespace = namespace;
+                    batch.push(entry);
+                }
+                if done {
+                    break;
+                }
+            }
+            Result::<_, Error>::Ok(())
+        }
+        .await;
+        match result {
+            Ok(()) => {
+                txn.commit().await?;
+                Ok(count)
+            }
+            Err(err) => {
+                txn.rollback().await.ok();
+                Err(err)
+            }
+        }
+    }
+
+    /// Import a bundle created by `export_profile` into a new profile,
+    /// returning the profile name. The name of the exported profile is used
+    /// unless `new_name` is provided.
+    ///
+    /// The records are written by a transaction session, so that they are
+    /// checked against the reserved categories and the store policy, and the
+    /// new profile is removed if the import fails
+    pub async fn import_profile(
+        &self,
+        bundle: &[u8],
+        pass_key: PassKey<'_>,
+        new_name: Option<String>,
+    ) -> Result<String, Error> {
+        let (manifest, records) = open_bundle(bundle, pass_key).await?;
+        let profile = self
+            .create_profile(Some(new_name.unwrap_or(manifest.profile)))
+            .await?;
+        let result = async {
+            let mut txn = self.transaction(Some(profile.clone())).await?;
+            // consecutive records of the same kind and namespace are inserted
+            // together
+            let mut batch = Vec::new();
+            for record in records {
+                let kind = record.kind()?;
+                let mut entry = record.into_entry();
+                let (namespace, category) = split_namespace(&entry.category);
+                let (namespace, category) = (namespace.map(str::to_string), category.to_string());
+                entry.category = category;
+                if (kind != txn.kind || namespace != txn.namespace) && !batch.is_empty() {
+                    txn.insert_all(std::mem::take(&mut batch)).await?;
+                }
+                // key entries are imported as generic records of their kind
+                txn.kind = kind;
+                txn.namespace = namespace;
+                batch.push(entry);
+            }
+            if !batch.is_empty() {
+                txn.insert_all(batch).await?;
+            }
+            txn.commit().await
+        }
+        .await;
+        if let Err(err) = result {
+            self.inner.remove_profile(profile).await.ok();
+            return Err(err);
+        }
+        Ok(profile)
+    }
+
+    /// Write a consistent copy of the store database to a new file at `path`.
+    ///
+    /// The copy remains encrypted under the store key. Only SQLite stores
+    /// support database backups
+    pub async fn backup_database(&self, path: String) -> Result<(), Error> {
+        Ok(self.inner.backup_to(path).await?)
+    }
+
+    /// Copy the contents of the store into a new store at `uri`, such as a
+    /// temporary SQLite database, which shares the same keys and settings.
+    ///
+    /// Changes to the copy do not affect this store, allowing tests and
+    /// what-if tooling to modify records freely. The copy may be reopened
+    /// using the pass key of this store. Only SQLite stores may be forked
+    pub async fn fork_to(&self, uri: &str) -> Result<Self, Error> {
+        let mut inner = self.inner.fork_to(uri.to_string()).await?;
+        inner.set_unique_constraints(self.unique_constraints.clone());
+        Ok(Self {
+            inner,
+            timeout: RwLock::new(self.timeout()),
+            policy: self.policy.clone(),
+            naming: self.naming.clone(),
+            allowed_profiles: self.allowed_profiles.clone(),
+            track_changes: self.track_changes,
+            unique_constraints: self.unique_constraints.clone(),
+            query_limits: self.query_limits,
+            slow_threshold: self.slow_threshold,
+            pass_key_policy: self.pass_key_policy.clone(),
+            unlock_provider: self.unlock_provider.clone(),
+            access_mode: Arc::new(RwLock::new(None)),
+        })
+    }
+
+    /// Perform a single backup according to a backup policy, removing the
+    /// backups which are no longer retained
+    pub async fn run_backup(&self, policy: &BackupPolicy) -> Result<BackupRun, Error> {
+        run_backup(self, policy).await
+    }
+
+    /// Start performing backups on the interval of a backup policy. Backups
+    /// continue until the returned handle is stopped or dropped, and
+    /// failures are logged
+    pub fn spawn_backups(self: Arc<Self>, policy: BackupPolicy) -> BackupHandle
+    where
+        B: 'static,
+    {
+        spawn_backups(self, policy)
     }
 
     /// Create a new profile with the given profile name
     pub async fn create_profile(&self, name: Option<String>) -> Result<String, Error> {
-        Ok(self.0.create_profile(name).await?)
+        let name = match (name, self.naming.as_ref()) {
+            (None, Some(naming)) => Some(naming.generate()),
+            (name, _) => name,
+        };
+        if name.is_none() && self.allowed_profiles.is_some() {
+            return Err(err_msg!(
+                Forbidden,
+                "A profile name is required when the store handle is restricted"
+            ));
+        }
+        self.check_profile(name.as_deref())?;
+        self.check_writable().await?;
+        Ok(self.inner.create_profile(name).await?)
     }
 
     /// Remove an existing profile with the given profile name
     pub async fn remove_profile(&self, name: String) -> Result<bool, Error> {
-        Ok(self.0.remove_profile(name).await?)
+        self.check_profile(Some(&name))?;
+        self.check_writable().await?;
+        Ok(self.inner.remove_profile(name).await?)
+    }
+
+    /// Cryptographically erase a profile, removing its records and its
+    /// wrapped profile key, and returning an attestation of the erasure if
+    /// the profile existed.
+    ///
+    /// When the profile key was wrapped by a tenant key, the tenant key
+    /// provider is asked to destroy the tenant key, rendering the profile
+    /// unrecoverable from earlier backups of the store. Otherwise, backups
+    /// taken before shredding remain readable using the store key.
+    pub async fn shred_profile(&self, name: String) -> Result<Option<ProfileShredding>, Error> {
+        self.check_profile(Some(&name))?;
+        self.check_writable().await?;
+        Ok(self.inner.shred_profile(name).await?)
     }
 
     /// Create a new scan instance against the store
@@ -63,167 +800,2228 @@ impl<B: Backend> Store<B> {
         tag_filter: Option<TagFilter>,
         offset: Option<i64>,
         limit: Option<i64>,
-    ) -> Result<Scan<'static, Entry>, Error> {
+    ) -> Result<Scan<Entry>, Error> {
+        self.scan_kind(
+            EntryKind::Item,
+            profile,
+            category,
+            tag_filter,
+            offset,
+            limit,
+        )
+        .await
+    }
+
+    /// Create a new scan instance against the store, after replacing the
+    /// expiry of the records to be scanned using a single update. This may be
+    /// used to keep active records alive without updating each one in turn
+    pub async fn scan_touch(
+        &self,
+        profile: Option<String>,
+        category: String,
+        tag_filter: Option<TagFilter>,
+        offset: Option<i64>,
+        limit: Option<i64>,
+        expiry: EntryExpiry,
+    ) -> Result<Scan<Entry>, Error> {
+        self.check_profile(profile.as_deref())?;
+        check_tag_filter(&self.query_limits, tag_filter.as_ref())?;
+        self.check_writable().await?;
+        let mut session = self.inner.session(profile.clone(), false)?;
+        session
+            .touch_all(
+                EntryKind::Item,
+                &category,
+                tag_filter.clone(),
+                offset,
+                limit,
+                expiry,
+            )
+            .await?;
+        session.close(false).await?;
+        self.scan(profile, category, tag_filter, offset, limit)
+            .await
+    }
+
+    /// Create a new scan instance for records within a namespace, as
+    /// selected for a session by `Session::set_namespace`
+    pub async fn scan_namespace(
+        &self,
+        profile: Option<String>,
+        namespace: &str,
+        category: String,
+        tag_filter: Option<TagFilter>,
+        offset: Option<i64>,
+        limit: Option<i64>,
+    ) -> Result<Scan<Entry>, Error> {
+        check_namespace(namespace)?;
+        self.scan_kind(
+            EntryKind::Item,
+            profile,
+            namespaced_category(Some(namespace), &category).into_owned(),
+            tag_filter,
+            offset,
+            limit,
+        )
+        .await
+    }
+
+    /// Create a new scan instance against the store, returning only the parts
+    /// of each record selected by `projection`. Scans which omit the record
+    /// values avoid reading and decrypting them, such as when listing records
+    pub async fn scan_projected(
+        &self,
+        profile: Option<String>,
+        category: String,
+        tag_filter: Option<TagFilter>,
+        offset: Option<i64>,
+        limit: Option<i64>,
+        projection: Projection,
+    ) -> Result<Scan<Entry>, Error> {
+        self.start_scan(
+            EntryKind::Item,
+            profile,
+            category,
+            tag_filter,
+            HistoryFilter::default(),
+            offset,
+            limit,
+            projection,
+        )
+        .await
+    }
+
+    /// Create a new scan instance for records whose creation and update times
+    /// fall within the bounds of `history_filter`. The times are taken from
+    /// the entry history, and `Unsupported` is returned when the store does
+    /// not record history
+    pub async fn scan_modified(
+        &self,
+        profile: Option<String>,
+        category: String,
+        tag_filter: Option<TagFilter>,
+        history_filter: HistoryFilter,
+        offset: Option<i64>,
+        limit: Option<i64>,
+    ) -> Result<Scan<Entry>, Error> {
+        self.start_scan(
+            EntryKind::Item,
+            profile,
+            category,
+            tag_filter,
+            history_filter,
+            offset,
+            limit,
+            Projection::Full,
+        )
+        .await
+    }
+
+    /// Create a new scan instance for entries of a given kind
+    pub async fn scan_kind(
+        &self,
+        kind: EntryKind,
+        profile: Option<String>,
+        category: String,
+        tag_filter: Option<TagFilter>,
+        offset: Option<i64>,
+        limit: Option<i64>,
+    ) -> Result<Scan<Entry>, Error> {
+        self.start_scan(
+            kind,
+            profile,
+            category,
+            tag_filter,
+            HistoryFilter::default(),
+            offset,
+            limit,
+            Projection::Full,
+        )
+        .await
+    }
+
+    async fn start_scan(
+        &self,
+        kind: EntryKind,
+        profile: Option<String>,
+        category: String,
+        tag_filter: Option<TagFilter>,
+        history_filter: HistoryFilter,
+        offset: Option<i64>,
+        limit: Option<i64>,
+        projection: Projection,
+    ) -> Result<Scan<Entry>, Error> {
+        if kind == EntryKind::Kms {
+            return Err(err_msg!(
+                Input,
+                "Key entries may not be accessed as generic records"
+            ));
+        }
+        self.check_profile(profile.as_deref())?;
+        check_tag_filter(&self.query_limits, tag_filter.as_ref())?;
+        self.check_access().await?;
+        let mut checkpoint = ScanCheckpoint::new(
+            profile
+                .clone()
+                .unwrap_or_else(|| self.get_profile_name().to_string()),
+            kind,
+            category.clone(),
+            tag_filter.as_ref(),
+            offset,
+            limit,
+            projection,
+        )?;
+        checkpoint.history_filter = history_filter;
         Ok(self
-            .0
+            .inner
             .scan(
                 profile,
-                EntryKind::Item,
+                kind,
                 category,
                 tag_filter,
+                history_filter,
                 offset,
                 limit,
+                None,
+                projection,
             )
-            .await?)
+            .await?
+            .with_checkpoint(checkpoint)
+            .with_timeout(self.timeout()))
     }
 
-    /// Create a new session against the store
-    pub async fn session(&self, profile: Option<String>) -> Result<Session<B::Session>, Error> {
-        // FIXME - add 'immediate' flag
-        Ok(Session::new(self.0.session(profile, false)?))
+    /// Create a new scan instance for records inserted or replaced after the
+    /// record with the given sequence number, or for all records when `since`
+    /// is `None`. Records are returned in sequence order, which allows a
+    /// consumer to follow the changes to a category by recording the sequence
+    /// number of the last record it has processed.
+    ///
+    /// Removed records are not reported. Sequence numbers are assigned when a
+    /// write is performed rather than when it is committed, so a record
+    /// written by a concurrent transaction may commit behind a sequence number
+    /// which has already been observed.
+    pub async fn scan_since(
+        &self,
+        profile: Option<String>,
+        category: String,
+        tag_filter: Option<TagFilter>,
+        since: Option<EntrySeq>,
+        limit: Option<i64>,
+    ) -> Result<Scan<Entry>, Error> {
+        self.check_profile(profile.as_deref())?;
+        check_tag_filter(&self.query_limits, tag_filter.as_ref())?;
+        self.check_access().await?;
+        let mut checkpoint = ScanCheckpoint::new(
+            profile
+                .clone()
+                .unwrap_or_else(|| self.get_profile_name().to_string()),
+            EntryKind::Item,
+            category.clone(),
+            tag_filter.as_ref(),
+            None,
+            limit,
+            Projection::Full,
+        )?;
+        let after_id = since.map(EntrySeq::value);
+        checkpoint.after_id = after_id;
+        Ok(self
+            .inner
+            .scan(
+                profile,
+                EntryKind::Item,
+                category,
+                tag_filter,
+                HistoryFilter::default(),
+                None,
+                limit,
+                after_id,
+                Projection::Full,
+            )
+            .await?
+            .with_checkpoint(checkpoint)
+            .with_timeout(self.timeout()))
     }
 
-    /// Create a new transaction session against the store
-    pub async fn transaction(&self, profile: Option<String>) -> Result<Session<B::Session>, Error> {
-        Ok(Session::new(self.0.session(profile, true)?))
+    /// Resume a scan from a checkpoint obtained from `Scan::checkpoint`,
+    /// returning the records following the last record returned before the
+    /// checkpoint was taken
+    pub async fn resume_scan(&self, checkpoint: ScanCheckpoint) -> Result<Scan<Entry>, Error> {
+        let kind = match checkpoint.kind() {
+            Some(EntryKind::Kms) | None => {
+                return Err(err_msg!(Input, "Invalid entry kind for scan checkpoint"));
+            }
+            Some(kind) => kind,
+        };
+        self.check_profile(Some(checkpoint.profile()))?;
+        let tag_filter = checkpoint.tag_filter()?;
+        check_tag_filter(&self.query_limits, tag_filter.as_ref())?;
+        self.check_access().await?;
+        Ok(self
+            .inner
+            .scan(
+                Some(checkpoint.profile.clone()),
+                kind,
+                checkpoint.category.clone(),
+                tag_filter,
+                checkpoint.history_filter,
+                checkpoint.offset,
+                checkpoint.limit,
+                checkpoint.after_id,
+                checkpoint.projection,
+            )
+            .await?
+            .with_checkpoint(checkpoint)
+            .with_timeout(self.timeout()))
     }
 
-    /// Close the store instance, waiting for any shutdown procedures to complete.
-    pub async fn close(self) -> Result<(), Error> {
-        Ok(self.0.close().await?)
+    /// Rotate all keys in a profile which have expired or passed their rotation
+    /// interval, generating successors as described for `Session::rotate_key`.
+    ///
+    /// Returns the names of the replaced and new key entries
+    pub async fn rotate_due_keys(
+        &self,
+        profile: Option<String>,
+        retain_old: bool,
+    ) -> Result<Vec<(String, String)>, Error> {
+        let mut txn = self.transaction(profile).await?;
+        let now = SystemTime::now();
+        let due = txn
+            .fetch_all_keys(None, None, None, None, true)
+            .await?
+            .into_iter()
+            .filter(|entry| entry.is_local() && entry.params.rotation_due(now))
+            .map(|entry| entry.name)
+            .collect::<Vec<_>>();
+        let mut rotated = Vec::with_capacity(due.len());
+        for name in due {
+            let new_name = txn.rotate_key(&name, retain_old).await?;
+            rotated.push((name, new_name));
+        }
+        txn.commit().await?;
+        Ok(rotated)
+    }
+
+    /// The name of the profile used by a new session, for error contexts
+    fn session_profile_name(&self, profile: Option<&str>) -> String {
+        profile
+            .unwrap_or_else(|| self.inner.get_profile_name())
+            .to_string()
+    }
+
+    /// Create a new session against the store
+    pub async fn session(&self, profile: Option<String>) -> Result<Session<B::Session>, Error> {
+        // FIXME - add 'immediate' flag
+        self.check_profile(profile.as_deref())?;
+        self.check_access().await?;
+        let profile_name = self.session_profile_name(profile.as_deref());
+        Ok(Session::new(
+            self.inner.session(profile, false)?,
+            self.timeout(),
+            self.policy.clone(),
+        )
+        .with_profile(profile_name)
+        .with_access_mode(self.access_mode.clone())
+        .with_change_tracking(self.track_changes)
+        .with_query_limits(self.query_limits)
+        .with_slow_threshold(self.slow_threshold))
+    }
+
+    /// Create a new session which is restricted to a set of record categories.
+    ///
+    /// When `allowed_categories` is provided, operations on any other category
+    /// (including the key management category) fail with a `Forbidden` error.
+    /// If `read_only` is set, then all modifications are rejected.
+    pub async fn scoped_session(
+        &self,
+        profile: Option<String>,
+        allowed_categories: Option<Vec<String>>,
+        read_only: bool,
+    ) -> Result<Session<ScopedQueryBackend<B::Session>>, Error> {
+        self.check_profile(profile.as_deref())?;
+        self.check_access().await?;
+        let profile_name = self.session_profile_name(profile.as_deref());
+        Ok(Session::new(
+            ScopedQueryBackend::new(
+                self.inner.session(profile, false)?,
+                allowed_categories,
+                read_only,
+            ),
+            self.timeout(),
+            self.policy.clone(),
+        )
+        .with_profile(profile_name)
+        .with_access_mode(self.access_mode.clone())
+        .with_query_limits(self.query_limits)
+        .with_slow_threshold(self.slow_threshold))
+    }
+
+    /// Create a new transaction session against the store
+    pub async fn transaction(&self, profile: Option<String>) -> Result<Session<B::Session>, Error> {
+        self.check_profile(profile.as_deref())?;
+        self.check_access().await?;
+        let profile_name = self.session_profile_name(profile.as_deref());
+        Ok(Session::new(
+            self.inner.session(profile, true)?,
+            self.timeout(),
+            self.policy.clone(),
+        )
+        .with_profile(profile_name)
+        .with_access_mode(self.access_mode.clone())
+        .with_change_tracking(self.track_changes)
+        .with_query_limits(self.query_limits)
+        .with_slow_threshold(self.slow_threshold))
+    }
+
+    /// Collect up to `batch` messages from the outbox of a profile, as queued
+    /// by `Session::queue_outbox`.
+    ///
+    /// The returned messages are hidden from other pollers for the
+    /// `visibility_timeout`, and are returned again once it elapses unless
+    /// they have been removed using `Session::ack_outbox`. Messages are
+    /// generally returned in the order they were queued, but the order is not
+    /// guaranteed once a message has been returned more than once
+    pub async fn poll_outbox(
+        &self,
+        profile: Option<String>,
+        batch: usize,
+        visibility_timeout: Duration,
+    ) -> Result<Vec<OutboxMessage>, Error> {
+        if batch == 0 {
+            return Ok(Vec::new());
+        }
+        let mut txn = self.transaction(profile).await?;
+        let messages = txn.claim_outbox(batch, visibility_timeout).await?;
+        txn.commit().await?;
+        Ok(messages)
+    }
+
+    /// Acquire a named lease for the duration `ttl`, if it is not currently
+    /// held. Returns `None` if the lease is held by another process and has
+    /// not expired.
+    ///
+    /// The holder should renew the lease before it expires, and release it
+    /// once the coordinated work is complete
+    pub async fn acquire_lease(
+        &self,
+        profile: Option<String>,
+        name: &str,
+        ttl: Duration,
+    ) -> Result<Option<Lease>, Error> {
+        let mut txn = self.transaction(profile.clone()).await?;
+        let operation = match txn.fetch_lease(name).await? {
+            None => EntryOperation::Insert,
+            Some((_, expires)) if expires <= SystemTime::now() => EntryOperation::Replace,
+            Some(_) => {
+                txn.rollback().await?;
+                return Ok(None);
+            }
+        };
+        let lease = Lease::new(profile, name, ttl);
+        match txn.write_lease(operation, &lease).await {
+            Ok(()) => (),
+            // the lease was acquired concurrently by another process
+            Err(err) if err.kind() == ErrorKind::Duplicate => {
+                txn.rollback().await?;
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        }
+        txn.commit().await?;
+        Ok(Some(lease))
+    }
+
+    /// Extend a held lease for the duration `ttl` from the current time.
+    /// Returns `false` if the lease has been acquired by another holder after
+    /// it expired, or has been released
+    pub async fn renew_lease(&self, lease: &mut Lease, ttl: Duration) -> Result<bool, Error> {
+        let mut txn = self.transaction(lease.profile.clone()).await?;
+        match txn.fetch_lease(&lease.name).await? {
+            Some((token, _)) if token == lease.token => (),
+            _ => {
+                txn.rollback().await?;
+                return Ok(false);
+            }
+        }
+        let renewed = Lease {
+            expires: SystemTime::now() + ttl,
+            ..lease.clone()
+        };
+        txn.write_lease(EntryOperation::Replace, &renewed).await?;
+        txn.commit().await?;
+        *lease = renewed;
+        Ok(true)
+    }
+
+    /// Release a held lease, allowing it to be acquired immediately by
+    /// another process. Returns `false` if the lease was no longer held
+    pub async fn release_lease(&self, lease: Lease) -> Result<bool, Error> {
+        let mut txn = self.transaction(lease.profile.clone()).await?;
+        match txn.fetch_lease(&lease.name).await? {
+            Some((token, _)) if token == lease.token => (),
+            _ => {
+                txn.rollback().await?;
+                return Ok(false);
+            }
+        }
+        txn.write_lease(EntryOperation::Remove, &lease).await?;
+        txn.commit().await?;
+        Ok(true)
+    }
+
+    /// Close the store instance, waiting for any shutdown procedures to complete.
+    pub async fn close(self) -> Result<(), Error> {
+        Ok(self.inner.close().await?)
     }
 
     pub(crate) async fn arc_close(self: Arc<Self>) -> Result<(), Error> {
-        Ok(self.0.close().await?)
+        Ok(self.inner.close().await?)
+    }
+}
+
+/// Reject a tag filter which exceeds the configured complexity limits
+fn check_tag_filter(limits: &QueryLimits, tag_filter: Option<&TagFilter>) -> Result<(), Error> {
+    if let Some(filter) = tag_filter {
+        filter.check_limits(limits)?;
+    }
+    Ok(())
+}
+
+/// The number of messages signed or verified by each background task in a
+/// batch operation
+const BATCH_CHUNK_SIZE: usize = 64;
+
+/// The time for which the access mode read from the store configuration is
+/// cached by each handle
+const ACCESS_MODE_REFRESH: Duration = Duration::from_secs(1);
+
+/// The access mode last read by a store handle, shared with its sessions
+type CachedAccessMode = Arc<RwLock<Option<(AccessMode, Instant)>>>;
+
+/// A backend operation which poisons its session if a panic unwinds
+/// through it, as the state of the operation is then unknown
+struct PoisonOnPanic<'p, F> {
+    fut: F,
+    poisoned: &'p mut bool,
+}
+
+impl<F: Future + Unpin> Future for PoisonOnPanic<'_, F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        struct Guard<'g>(&'g mut bool);
+
+        impl Drop for Guard<'_> {
+            fn drop(&mut self) {
+                if std::thread::panicking() {
+                    *self.0 = true;
+                }
+            }
+        }
+
+        let this = &mut *self;
+        let _guard = Guard(&mut *this.poisoned);
+        Pin::new(&mut this.fut).poll(cx)
+    }
+}
+
+/// A group of operations applied atomically by a session which is not a
+/// transaction. If the group is dropped before it is completed, such as when
+/// the future of an operation is cancelled, it is rolled back by the next
+/// operation on the session
+struct AtomicGroup {
+    abandoned: Arc<AtomicBool>,
+    completed: bool,
+}
+
+impl Drop for AtomicGroup {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.abandoned.store(true, Ordering::Release);
+        }
+    }
+}
+
+/// Perform a backend operation, releasing the connection if it does not
+/// complete within the session timeout. When the entry kind and category
+/// are provided, they are recorded in the context of a returned error
+macro_rules! timed_op {
+    (@run $session:expr, $write:expr, $method:ident($($arg:expr),*)) => {{
+        if $session.poisoned {
+            // the connection is closed, rolling back any open transaction
+            $session.inner.reset_connection(true);
+            Err(err_msg!(
+                Poisoned,
+                "The session was abandoned after a panic in a previous operation"
+            ))
+        } else if let Err(err) = $session.prepare_op($write).await {
+            Err(err)
+        } else {
+            let res = match $session.timeout {
+                Some(timeout) => {
+                    future::timeout(
+                        timeout,
+                        PoisonOnPanic {
+                            fut: $session.inner.$method($($arg),*),
+                            poisoned: &mut $session.poisoned,
+                        },
+                    )
+                    .await
+                }
+                None => Some(
+                    PoisonOnPanic {
+                        fut: $session.inner.$method($($arg),*),
+                        poisoned: &mut $session.poisoned,
+                    }
+                    .await,
+                ),
+            };
+            res.unwrap_or_else(|| {
+                $session.inner.reset_connection(false);
+                Err(err_msg!(Busy, "Store operation timed out"))
+            })
+        }
+    }};
+    ($session:expr, $write:expr, ($kind:expr, $category:expr), $method:ident($($arg:expr),*)) => {{
+        let category: &str = $category;
+        let start = Instant::now();
+        let res = timed_op!(@run $session, $write, $method($($arg),*));
+        $session.record_operation(stringify!($method), start.elapsed(), Some($kind), Some(category));
+        res.map_err(|err| {
+            $session.error_context(err, stringify!($method), Some($kind), Some(category))
+        })
+    }};
+    ($session:expr, $write:expr, $method:ident($($arg:expr),*)) => {{
+        let start = Instant::now();
+        let res = timed_op!(@run $session, $write, $method($($arg),*));
+        $session.record_operation(stringify!($method), start.elapsed(), None, None);
+        res.map_err(|err| $session.error_context(err, stringify!($method), None, None))
+    }};
+}
+
+/// Perform a backend operation which only reads from the store, retrying it
+/// once on a new connection if the database connection was lost outside of a
+/// transaction
+macro_rules! retry_lost {
+    ($session:expr, ($kind:expr, $category:expr), $method:ident($($arg:expr),* $(,)?)) => {
+        match timed_op!($session, false, ($kind, $category), $method($($arg),*)) {
+            Err(err)
+                if err.kind() == ErrorKind::ConnectionLost && $session.inner.reset_connection(true) =>
+            {
+                warn!("Retrying operation after lost connection: {}", err);
+                timed_op!($session, false, ($kind, $category), $method($($arg),*))
+            }
+            res => res,
+        }
+    };
+    ($session:expr, $method:ident($($arg:expr),* $(,)?)) => {
+        match timed_op!($session, false, $method($($arg),*)) {
+            Err(err)
+                if err.kind() == ErrorKind::ConnectionLost && $session.inner.reset_connection(true) =>
+            {
+                warn!("Retrying operation after lost connection: {}", err);
+                timed_op!($session, false, $method($($arg),*))
+            }
+            res => res,
+        }
+    };
+}
+
+/// Perform a backend operation which modifies the store. A write is not
+/// retried if the database connection is lost, as it may have been committed
+/// before the connection failed. The connection is released so that a
+/// subsequent operation outside of a transaction may use a new connection
+macro_rules! write_op {
+    ($session:expr, ($kind:expr, $category:expr), $method:ident($($arg:expr),* $(,)?)) => {
+        match timed_op!($session, true, ($kind, $category), $method($($arg),*)) {
+            Err(err) if err.kind() == ErrorKind::ConnectionLost => {
+                $session.inner.reset_connection(true);
+                Err(err)
+            }
+            res => res,
+        }
+    };
+    ($session:expr, $method:ident($($arg:expr),* $(,)?)) => {
+        match timed_op!($session, true, $method($($arg),*)) {
+            Err(err) if err.kind() == ErrorKind::ConnectionLost => {
+                $session.inner.reset_connection(true);
+                Err(err)
+            }
+            res => res,
+        }
+    };
+}
+
+/// An active connection to the store backend.
+///
+/// If the database connection is lost while the session is not in a
+/// transaction, a failed read operation is retried once on a new connection.
+/// A failed write results in a `ConnectionLost` error without being retried,
+/// as the write may have been committed before the connection was lost, and
+/// the caller must determine whether to repeat it. A lost connection within
+/// a transaction results in a `ConnectionLost` error, and the transaction
+/// must be restarted by the caller.
+///
+/// Each operation is applied atomically. An operation which performs several
+/// writes, such as removing a record along with its protection marker, is
+/// applied within a transaction which is started for the operation when the
+/// session is not a transaction. Separate operations are only isolated from
+/// one another within a transaction.
+///
+/// Operations which exceed the session timeout fail with a `Busy` error, and
+/// the associated connection is released. Within a transaction, this also
+/// aborts the transaction.
+///
+/// If a panic unwinds through an operation, the session is poisoned and
+/// subsequent operations fail with a `Poisoned` error. The connection is
+/// closed, so that any pending transaction is rolled back.
+#[derive(Debug)]
+pub struct Session<Q: QueryBackend> {
+    inner: Q,
+    poisoned: bool,
+    profile: Option<String>,
+    timeout: Option<Duration>,
+    kind: EntryKind,
+    namespace: Option<String>,
+    policy: Option<Arc<dyn StorePolicy>>,
+    track_changes: bool,
+    query_limits: QueryLimits,
+    slow_threshold: Option<Duration>,
+    stats: SessionStats,
+    access_mode: Option<CachedAccessMode>,
+    atomic_abandoned: Arc<AtomicBool>,
+}
+
+impl<Q: QueryBackend> Session<Q> {
+    pub(crate) fn new(
+        inner: Q,
+        timeout: Option<Duration>,
+        policy: Option<Arc<dyn StorePolicy>>,
+    ) -> Self {
+        Self {
+            inner,
+            poisoned: false,
+            profile: None,
+            timeout,
+            kind: EntryKind::Item,
+            namespace: None,
+            policy,
+            track_changes: false,
+            query_limits: QueryLimits::default(),
+            slow_threshold: None,
+            stats: SessionStats::default(),
+            access_mode: None,
+            atomic_abandoned: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn with_profile(mut self, profile: String) -> Self {
+        self.profile.replace(profile);
+        self
+    }
+
+    /// Describe an operation without revealing the profile or category
+    fn operation_context(
+        &self,
+        operation: &'static str,
+        kind: Option<EntryKind>,
+        category: Option<&str>,
+    ) -> ErrorContext {
+        ErrorContext {
+            operation: Some(operation),
+            backend: Some(self.inner.backend_name()),
+            entry_kind: kind,
+            profile_hash: self.profile.as_deref().map(ErrorContext::hash_identifier),
+            category_hash: category.map(ErrorContext::hash_identifier),
+        }
+    }
+
+    /// Attach the details of a failed operation to an error
+    fn error_context(
+        &self,
+        err: Error,
+        operation: &'static str,
+        kind: Option<EntryKind>,
+        category: Option<&str>,
+    ) -> Error {
+        err.with_context(self.operation_context(operation, kind, category))
+    }
+
+    /// Add a completed operation to the session statistics, logging a
+    /// warning if it exceeded the slow operation threshold
+    fn record_operation(
+        &mut self,
+        operation: &'static str,
+        elapsed: Duration,
+        kind: Option<EntryKind>,
+        category: Option<&str>,
+    ) {
+        self.stats.operations += 1;
+        self.stats.elapsed += elapsed;
+        if matches!(self.slow_threshold, Some(threshold) if elapsed >= threshold) {
+            self.stats.slow_operations += 1;
+            warn!(
+                "Slow store operation: elapsed_ms={} {}",
+                elapsed.as_millis(),
+                self.operation_context(operation, kind, category)
+            );
+        }
+    }
+
+    fn with_slow_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.slow_threshold = threshold;
+        self
+    }
+
+    /// Reject modifications while the access mode cached by the store handle
+    /// does not permit them
+    fn with_access_mode(mut self, access_mode: CachedAccessMode) -> Self {
+        self.access_mode.replace(access_mode);
+        self
+    }
+
+    /// Prepare the session for an operation, rolling back an abandoned group
+    /// of operations and checking that the store is writable for a
+    /// modification
+    async fn prepare_op(&mut self, write: bool) -> Result<(), Error> {
+        self.recover_atomic().await?;
+        if write {
+            self.check_writable().await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Start a group of operations which are applied atomically. Outside of
+    /// a transaction, a transaction is started for the group and must be
+    /// completed by `end_atomic`
+    async fn begin_atomic(&mut self) -> Result<Option<AtomicGroup>, Error> {
+        self.recover_atomic().await?;
+        let mut group = AtomicGroup {
+            abandoned: self.atomic_abandoned.clone(),
+            completed: false,
+        };
+        if self.inner.begin_atomic().await? {
+            Ok(Some(group))
+        } else {
+            group.completed = true;
+            Ok(None)
+        }
+    }
+
+    /// Complete a group of operations, committing its changes if all of the
+    /// operations succeeded and rolling them back otherwise
+    async fn end_atomic<T>(
+        &mut self,
+        group: Option<AtomicGroup>,
+        result: Result<T, Error>,
+    ) -> Result<T, Error> {
+        if let Some(mut group) = group {
+            let ended = self.inner.end_atomic(result.is_ok()).await;
+            group.completed = true;
+            // an error from the operations takes precedence over the roll-back
+            if result.is_ok() {
+                ended?;
+            }
+        }
+        result
+    }
+
+    /// Roll back a group of operations which was abandoned before it was
+    /// completed
+    async fn recover_atomic(&mut self) -> Result<(), Error> {
+        if self.atomic_abandoned.swap(false, Ordering::AcqRel) {
+            // closing the connection rolls back the transaction
+            self.inner.reset_connection(true);
+            self.inner.end_atomic(false).await?;
+        }
+        Ok(())
+    }
+
+    /// Check that the store is writable before a modification. The access
+    /// mode shared with the store handle is read again using the session
+    /// connection once it is over a second old, so that a long-lived session
+    /// observes a mode set by another handle
+    async fn check_writable(&mut self) -> Result<(), Error> {
+        let cached = match self.access_mode.as_ref() {
+            Some(cached) => cached.clone(),
+            None => return Ok(()),
+        };
+        let current = *cached.read().unwrap();
+        let mode = match current {
+            Some((mode, checked)) if checked.elapsed() < ACCESS_MODE_REFRESH => mode,
+            _ => {
+                let mode = self.inner.access_mode().await?;
+                *cached.write().unwrap() = Some((mode, Instant::now()));
+                mode
+            }
+        };
+        match mode {
+            AccessMode::ReadWrite => Ok(()),
+            AccessMode::ReadOnly => Err(err_msg!(
+                Forbidden,
+                "The store is read-only for maintenance"
+            )),
+            AccessMode::Offline => Err(err_msg!(Forbidden, "The store is offline for maintenance")),
+        }
+    }
+
+    fn with_change_tracking(mut self, enabled: bool) -> Self {
+        self.track_changes = enabled;
+        self
+    }
+
+    fn with_query_limits(mut self, limits: QueryLimits) -> Self {
+        self.query_limits = limits;
+        self
+    }
+
+    /// Check a record against the reserved categories and the store policy
+    /// before it is inserted or replaced
+    fn check_write(
+        &self,
+        operation: EntryOperation,
+        category: &str,
+        name: &str,
+        value: &[u8],
+        tags: Option<&[EntryTag]>,
+    ) -> Result<(), Error> {
+        check_reserved_category(category)?;
+        check_category(category)?;
+        if let Some(policy) = self.policy.as_ref() {
+            policy
+                .validate(&EntryWrite {
+                    kind: self.kind,
+                    operation,
+                    category,
+                    name,
+                    value,
+                    tags: tags.unwrap_or_default(),
+                })
+                .map_err(|reason| err_msg!(PolicyViolation, "{}", reason))?;
+        }
+        Ok(())
+    }
+
+    /// Get the kind of entries accessed by the record methods of this session
+    pub fn entry_kind(&self) -> EntryKind {
+        self.kind
+    }
+
+    /// Select the kind of entries accessed by the record methods of this
+    /// session, such as `fetch` and `insert`. Entries of each kind are stored
+    /// separately, allowing applications to segregate their records.
+    ///
+    /// Key entries may only be accessed using the key management methods
+    pub fn set_entry_kind(&mut self, kind: EntryKind) -> Result<(), Error> {
+        if kind == EntryKind::Kms {
+            return Err(err_msg!(
+                Input,
+                "Key entries may not be accessed as generic records"
+            ));
+        }
+        self.kind = kind;
+        Ok(())
+    }
+
+    /// Get the namespace of the records accessed by this session
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// Select the namespace of the records accessed by the record methods of
+    /// this session. Records in each namespace are stored separately, so that
+    /// applications sharing a profile may use the same categories without
+    /// conflicts. The namespace is encrypted along with the record category.
+    ///
+    /// Key entries are not affected by the namespace
+    pub fn set_namespace(&mut self, namespace: Option<String>) -> Result<(), Error> {
+        if let Some(namespace) = namespace.as_deref() {
+            check_namespace(namespace)?;
+        }
+        self.namespace = namespace;
+        Ok(())
+    }
+
+    /// Get the stored category of a record within the session namespace
+    fn stored_category<'c>(&self, category: &'c str) -> Cow<'c, str> {
+        namespaced_category(self.namespace.as_deref(), category)
+    }
+
+    /// Get the maximum duration of each operation performed by the session
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Set the maximum duration of subsequent operations performed by the
+    /// session, overriding the default timeout of the store
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Get the counters of the operations performed by the session so far.
+    /// These may be collected before the session is committed or dropped
+    pub fn stats(&self) -> SessionStats {
+        SessionStats {
+            crypto_time: self.inner.crypto_time(),
+            ..self.stats
+        }
+    }
+}
+
+impl<Q: QueryBackend> Session<Q> {
+    /// Set the durability of the changes committed by the session, overriding
+    /// the default of the store. Bulk jobs may select a relaxed level to
+    /// trade the durability of recent commits for faster writes.
+    ///
+    /// For a transaction, the level must be set before the first operation.
+    pub async fn set_durability(&mut self, durability: Durability) -> Result<(), Error> {
+        timed_op!(self, false, set_durability(durability))
+    }
+
+    /// Count the number of entries for a given record category
+    pub async fn count(
+        &mut self,
+        category: &str,
+        tag_filter: Option<TagFilter>,
+    ) -> Result<i64, Error> {
+        check_tag_filter(&self.query_limits, tag_filter.as_ref())?;
+        let category = self.stored_category(category);
+        Ok(retry_lost!(
+            self,
+            (self.kind, &category),
+            count(self.kind, &category, tag_filter.clone())
+        )?)
+    }
+
+    /// Retrieve the current record at `(category, name)`.
+    ///
+    /// Specify `for_update` when in a transaction to create an update lock on the
+    /// associated record, if supported by the store backend
+    pub async fn fetch(
+        &mut self,
+        category: &str,
+        name: &str,
+        for_update: bool,
+    ) -> Result<Option<Entry>, Error> {
+        let category = self.stored_category(category);
+        let entry = retry_lost!(
+            self,
+            (self.kind, &category),
+            fetch(self.kind, &category, name, for_update)
+        )?;
+        self.stats.add_read(entry.is_some() as usize);
+        Ok(entry)
+    }
+
+    /// Check whether a current record exists at `(category, name)`, without
+    /// fetching or decrypting its value
+    pub async fn exists(&mut self, category: &str, name: &str) -> Result<bool, Error> {
+        let category = self.stored_category(category);
+        let found = retry_lost!(
+            self,
+            (self.kind, &category),
+            exists(self.kind, &category, name)
+        )?;
+        self.stats.add_read(found as usize);
+        Ok(found)
+    }
+
+    /// Retrieve a record by the identity returned by `insert_returning_id`.
+    ///
+    /// Returns `None` if the record has since been replaced or removed, or if
+    /// it belongs to another namespace
+    pub async fn fetch_by_id(&mut self, id: EntryId) -> Result<Option<Entry>, Error> {
+        let entry = retry_lost!(self, fetch_by_id(self.kind, id))?.and_then(|mut entry| {
+            let (namespace, category) = split_namespace(&entry.category);
+            if namespace == self.namespace.as_deref() {
+                entry.category = category.to_string();
+                Some(entry)
+            } else {
+                None
+            }
+        });
+        self.stats.add_read(entry.is_some() as usize);
+        Ok(entry)
+    }
+
+    /// Retrieve the value of the current record at `(category, name)`, decrypting
+    /// it directly into a caller-provided buffer.
+    ///
+    /// The previous contents of the buffer are cleared. Returns `false` if the
+    /// record was not found
+    pub async fn fetch_into(
+        &mut self,
+        category: &str,
+        name: &str,
+        output: &mut SecretBytes,
+    ) -> Result<bool, Error> {
+        let category = self.stored_category(category);
+        let found = retry_lost!(
+            self,
+            (self.kind, &category),
+            fetch_into(self.kind, &category, name, &mut *output)
+        )?;
+        self.stats.add_read(found as usize);
+        Ok(found)
+    }
+
+    /// Retrieve the record at `(category, name)` as it existed at a given time.
+    ///
+    /// Only supported for stores provisioned with entry history enabled
+    pub async fn fetch_at(
+        &mut self,
+        category: &str,
+        name: &str,
+        timestamp: SystemTime,
+    ) -> Result<Option<Entry>, Error> {
+        let category = self.stored_category(category);
+        let entry = retry_lost!(
+            self,
+            (self.kind, &category),
+            fetch_at(self.kind, &category, name, timestamp)
+        )?;
+        self.stats.add_read(entry.is_some() as usize);
+        Ok(entry)
+    }
+
+    /// List the recorded versions of the record at `(category, name)`,
+    /// most recent first.
+    ///
+    /// Only supported for stores provisioned with entry history enabled
+    pub async fn fetch_history(
+        &mut self,
+        category: &str,
+        name: &str,
+        limit: Option<i64>,
+    ) -> Result<Vec<EntryVersion>, Error> {
+        let category = self.stored_category(category);
+        let versions = retry_lost!(
+            self,
+            (self.kind, &category),
+            fetch_history(self.kind, &category, name, limit)
+        )?;
+        self.stats.add_read(versions.len());
+        Ok(versions)
+    }
+
+    /// Retrieve all records matching the given `category` and `tag_filter`.
+    ///
+    /// Unlike `Store::scan`, this method may be used within a transaction. It should
+    /// not be used for very large result sets due to correspondingly large memory
+    /// requirements
+    pub async fn fetch_all(
+        &mut self,
+        category: &str,
+        tag_filter: Option<TagFilter>,
+        limit: Option<i64>,
+        for_update: bool,
+    ) -> Result<Vec<Entry>, Error> {
+        self.fetch_all_projected(category, tag_filter, limit, for_update, Projection::Full)
+            .await
+    }
+
+    /// Retrieve all records matching the given `category` and `tag_filter`,
+    /// returning only the parts of each record selected by `projection`
+    pub async fn fetch_all_projected(
+        &mut self,
+        category: &str,
+        tag_filter: Option<TagFilter>,
+        limit: Option<i64>,
+        for_update: bool,
+        projection: Projection,
+    ) -> Result<Vec<Entry>, Error> {
+        check_tag_filter(&self.query_limits, tag_filter.as_ref())?;
+        let category = self.stored_category(category);
+        let entries = retry_lost!(
+            self,
+            (self.kind, &category),
+            fetch_all(
+                self.kind,
+                &category,
+                tag_filter.clone(),
+                limit,
+                for_update,
+                projection
+            )
+        )?;
+        self.stats.add_read(entries.len());
+        Ok(entries)
+    }
+
+    /// Retrieve the records in a category which were indexed with all of the
+    /// given search terms, as provided to `insert_searchable`.
+    ///
+    /// Terms only match exactly as they were indexed: any normalization, such
+    /// as case folding or splitting text into words, is left to the caller
+    pub async fn search(
+        &mut self,
+        category: &str,
+        terms: &[&str],
+        limit: Option<i64>,
+    ) -> Result<Vec<Entry>, Error> {
+        if terms.iter().all(|t| t.is_empty()) {
+            return Err(err_msg!(Input, "At least one search term is required"));
+        }
+        let terms: Vec<String> = terms.iter().map(|t| t.to_string()).collect();
+        let category = self.stored_category(category);
+        let entries = retry_lost!(
+            self,
+            (self.kind, &category),
+            search(self.kind, &category, terms.clone(), limit)
+        )?;
+        self.stats.add_read(entries.len());
+        Ok(entries)
+    }
+
+    /// Insert a new record into the store
+    pub async fn insert(
+        &mut self,
+        category: &str,
+        name: &str,
+        value: &[u8],
+        tags: Option<&[EntryTag]>,
+        expiry_ms: Option<i64>,
+    ) -> Result<(), Error> {
+        self.check_write(EntryOperation::Insert, category, name, value, tags)?;
+        let category = self.stored_category(category);
+        write_op!(
+            self,
+            (self.kind, &category),
+            update(
+                self.kind,
+                EntryOperation::Insert,
+                &category,
+                name,
+                Some(value),
+                tags,
+                EntryExpiry::from_ms(expiry_ms),
+            )
+        )?;
+        self.stats.add_written(1);
+        Ok(())
+    }
+
+    /// Insert a new record into the store, returning its identity. The record
+    /// may then be retrieved using `fetch_by_id`, without encrypting its
+    /// category and name
+    pub async fn insert_returning_id(
+        &mut self,
+        category: &str,
+        name: &str,
+        value: &[u8],
+        tags: Option<&[EntryTag]>,
+        expiry_ms: Option<i64>,
+    ) -> Result<EntryId, Error> {
+        self.check_write(EntryOperation::Insert, category, name, value, tags)?;
+        let category = self.stored_category(category);
+        let id = write_op!(
+            self,
+            (self.kind, &category),
+            insert_returning_id(
+                self.kind,
+                &category,
+                name,
+                value,
+                tags,
+                EntryExpiry::from_ms(expiry_ms),
+            )
+        )?;
+        self.stats.add_written(1);
+        Ok(id)
+    }
+
+    /// Insert a new record into the store, or retrieve the current record with
+    /// the same category and name. The existing record is read within the
+    /// same transaction as the attempted insert, avoiding a race between
+    /// separate fetch and insert operations.
+    ///
+    /// Returns `None` if the record was inserted
+    pub async fn insert_or_fetch(
+        &mut self,
+        category: &str,
+        name: &str,
+        value: &[u8],
+        tags: Option<&[EntryTag]>,
+        expiry_ms: Option<i64>,
+    ) -> Result<Option<Entry>, Error> {
+        self.check_write(EntryOperation::Insert, category, name, value, tags)?;
+        let category = self.stored_category(category);
+        let existing = write_op!(
+            self,
+            (self.kind, &category),
+            insert_or_fetch(
+                self.kind,
+                &category,
+                name,
+                value,
+                tags,
+                EntryExpiry::from_ms(expiry_ms),
+            )
+        )?;
+        if existing.is_some() {
+            self.stats.add_read(1);
+        } else {
+            self.stats.add_written(1);
+        }
+        Ok(existing)
+    }
+
+    /// Insert a new record into the store, indexing it under a set of search
+    /// terms which may be matched by `search`.
+    ///
+    /// Only keyed hashes of the terms are stored. The record is inserted and
+    /// indexed atomically, even when the session is not a transaction
+    pub async fn insert_searchable(
+        &mut self,
+        category: &str,
+        name: &str,
+        value: &[u8],
+        tags: Option<&[EntryTag]>,
+        search_terms: &[&str],
+        expiry_ms: Option<i64>,
+    ) -> Result<(), Error> {
+        let group = self.begin_atomic().await?;
+        let result = async {
+            self.insert(category, name, value, tags, expiry_ms).await?;
+            self.update_search_terms(category, name, search_terms).await
+        }
+        .await;
+        self.end_atomic(group, result).await
+    }
+
+    /// Replace the search terms indexed for an existing record.
+    ///
+    /// Replacing the record itself clears its search terms
+    pub async fn update_search_terms(
+        &mut self,
+        category: &str,
+        name: &str,
+        search_terms: &[&str],
+    ) -> Result<(), Error> {
+        check_reserved_category(category)?;
+        let terms: Vec<String> = search_terms.iter().map(|t| t.to_string()).collect();
+        let category = self.stored_category(category);
+        Ok(write_op!(
+            self,
+            (self.kind, &category),
+            update_search_terms(self.kind, &category, name, terms.clone())
+        )?)
+    }
+
+    /// Retrieve the metadata of an existing record, returning `None` if no
+    /// metadata has been set
+    pub async fn fetch_metadata(
+        &mut self,
+        category: &str,
+        name: &str,
+    ) -> Result<Option<SecretBytes>, Error> {
+        let category = self.stored_category(category);
+        Ok(retry_lost!(
+            self,
+            (self.kind, &category),
+            fetch_metadata(self.kind, &category, name)
+        )?)
+    }
+
+    /// Replace or clear the metadata of an existing record, without
+    /// rewriting its value or tags. The metadata is encrypted separately
+    /// from the value, and is suited to bookkeeping such as sync state.
+    ///
+    /// Replacing the record itself clears its metadata. Stores provisioned by
+    /// earlier releases do not support entry metadata, in which case an
+    /// `Unsupported` error is returned
+    pub async fn update_metadata(
+        &mut self,
+        category: &str,
+        name: &str,
+        metadata: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        check_reserved_category(category)?;
+        let category = self.stored_category(category);
+        Ok(write_op!(
+            self,
+            (self.kind, &category),
+            update_metadata(self.kind, &category, name, metadata)
+        )?)
+    }
+
+    /// Link a record to another record by a relation type, such as a
+    /// credential to the connection it was received from. Returns `false` if
+    /// the link already exists.
+    ///
+    /// Links are removed along with either record, including when the record
+    /// is replaced. Stores provisioned by earlier releases do not support
+    /// entry links, in which case an `Unsupported` error is returned
+    pub async fn link(
+        &mut self,
+        category: &str,
+        name: &str,
+        target: EntryLink,
+    ) -> Result<bool, Error> {
+        self.modify_link(category, name, target, false).await
+    }
+
+    /// Remove a link between two records, returning `true` if it existed
+    pub async fn unlink(
+        &mut self,
+        category: &str,
+        name: &str,
+        target: EntryLink,
+    ) -> Result<bool, Error> {
+        self.modify_link(category, name, target, true).await
+    }
+
+    async fn modify_link(
+        &mut self,
+        category: &str,
+        name: &str,
+        mut target: EntryLink,
+        remove: bool,
+    ) -> Result<bool, Error> {
+        check_reserved_category(category)?;
+        check_reserved_category(&target.category)?;
+        let category = self.stored_category(category);
+        target.category = self.stored_category(&target.category).into_owned();
+        Ok(write_op!(
+            self,
+            (self.kind, &category),
+            update_link(self.kind, &category, name, target.clone(), remove)
+        )?)
+    }
+
+    /// List the links from a record to other records, optionally filtered by
+    /// relation type
+    pub async fn list_links(
+        &mut self,
+        category: &str,
+        name: &str,
+        rel_type: Option<&str>,
+    ) -> Result<Vec<EntryLink>, Error> {
+        let category = self.stored_category(category);
+        let rel_type = rel_type.map(str::to_string);
+        Ok(retry_lost!(
+            self,
+            (self.kind, &category),
+            list_links(self.kind, &category, name, rel_type.clone(), false)
+        )?)
+    }
+
+    /// List the links to a record from other records, optionally filtered by
+    /// relation type. The number of these links may be used as a reference
+    /// count for the record
+    pub async fn list_backlinks(
+        &mut self,
+        category: &str,
+        name: &str,
+        rel_type: Option<&str>,
+    ) -> Result<Vec<EntryLink>, Error> {
+        let category = self.stored_category(category);
+        let rel_type = rel_type.map(str::to_string);
+        Ok(retry_lost!(
+            self,
+            (self.kind, &category),
+            list_links(self.kind, &category, name, rel_type.clone(), true)
+        )?)
+    }
+
+    /// Add an existing record to a named collection, or remove it from its
+    /// collection when `collection` is `None`. A record belongs to at most
+    /// one collection, and replacing the record removes it from the
+    /// collection.
+    ///
+    /// Stores provisioned by earlier releases do not support collections, in
+    /// which case an `Unsupported` error is returned
+    pub async fn set_collection(
+        &mut self,
+        category: &str,
+        name: &str,
+        collection: Option<&str>,
+    ) -> Result<(), Error> {
+        check_reserved_category(category)?;
+        let category = self.stored_category(category);
+        // collection names are distinct within each namespace
+        let collection = collection.map(|c| self.stored_category(c));
+        Ok(write_op!(
+            self,
+            (self.kind, &category),
+            update_collection(self.kind, &category, name, collection.as_deref())
+        )?)
+    }
+
+    /// Remove all of the records in a named collection, returning the number
+    /// of records removed. The records are removed within a single
+    /// transaction, regardless of their categories. Protected records are skipped, and are not
+    /// included in the returned count. The removal of each record is
+    /// recorded when change tracking is enabled
+    pub async fn remove_collection(&mut self, collection: &str) -> Result<i64, Error> {
+        let collection = self.stored_category(collection);
+        let group = self.begin_atomic().await?;
+        let result = self.perform_remove_collection(&collection).await;
+        self.end_atomic(group, result).await
+    }
+
+    async fn perform_remove_collection(&mut self, collection: &str) -> Result<i64, Error> {
+        let members = retry_lost!(self, collection_members(self.kind, collection))?;
+        let mut protected = Vec::new();
+        let mut removed = Vec::new();
+        for (category, name) in members {
+            if self.protected(self.kind, &category, &name).await? {
+                protected.push((category, name));
+            } else {
+                removed.push((category, name));
+            }
+        }
+        let count = write_op!(self, remove_collection(self.kind, collection, &protected))?;
+        self.stats.add_written(count as usize);
+        for (category, name) in removed {
+            self.record_removal(self.kind, &category, &name).await?;
+        }
+        Ok(count)
+    }
+
+    /// Remove a record from the store. A `Forbidden` error is returned if
+    /// the record is protected
+    pub async fn remove(&mut self, category: &str, name: &str) -> Result<(), Error> {
+        self.remove_record(category, name, false).await
+    }
+
+    /// Remove a record from the store, along with its protection
+    pub async fn force_remove(&mut self, category: &str, name: &str) -> Result<(), Error> {
+        self.remove_record(category, name, true).await
+    }
+
+    /// Protect a record from removal, or remove its protection. Protected
+    /// records are only removed by `force_remove` and `force_remove_all`.
+    ///
+    /// A record with an expiry cannot be protected, and an expiry cannot be
+    /// set on a protected record
+    pub async fn set_protected(
+        &mut self,
+        category: &str,
+        name: &str,
+        protected: bool,
+    ) -> Result<(), Error> {
+        let category = self.stored_category(category);
+        self.update_protection(self.kind, &category, name, protected)
+            .await
+    }
+
+    /// Check whether a record is protected from removal
+    pub async fn is_protected(&mut self, category: &str, name: &str) -> Result<bool, Error> {
+        let category = self.stored_category(category);
+        self.protected(self.kind, &category, name).await
+    }
+
+    async fn remove_record(
+        &mut self,
+        category: &str,
+        name: &str,
+        force: bool,
+    ) -> Result<(), Error> {
+        let category = self.stored_category(category);
+        self.remove_entry(self.kind, &category, name, force).await
+    }
+
+    /// Remove a single record of a given kind, along with its protection
+    /// marker. Every path removing an individual record goes through this
+    /// method, so that protected records are only removed when forced.
+    ///
+    /// The record, its protection marker and the record of its removal are
+    /// updated atomically, even when the session is not a transaction
+    async fn remove_entry(
+        &mut self,
+        kind: EntryKind,
+        category: &str,
+        name: &str,
+        force: bool,
+    ) -> Result<(), Error> {
+        let group = self.begin_atomic().await?;
+        let result = self.perform_remove_entry(kind, category, name, force).await;
+        self.end_atomic(group, result).await
+    }
+
+    async fn perform_remove_entry(
+        &mut self,
+        kind: EntryKind,
+        category: &str,
+        name: &str,
+        force: bool,
+    ) -> Result<(), Error> {
+        let protected = self.check_removal(kind, category, name, force).await?;
+        write_op!(
+            self,
+            (kind, category),
+            update(
+                kind,
+                EntryOperation::Remove,
+                category,
+                name,
+                None,
+                None,
+                None,
+            )
+        )?;
+        self.stats.add_written(1);
+        if protected {
+            self.update_protection(kind, category, name, false).await?;
+        }
+        self.record_removal(kind, category, name).await
+    }
+
+    /// Check that a record may be removed, returning whether it is protected.
+    /// A `Forbidden` error is returned for a protected record, unless the
+    /// removal is forced
+    async fn check_removal(
+        &mut self,
+        kind: EntryKind,
+        category: &str,
+        name: &str,
+        force: bool,
+    ) -> Result<bool, Error> {
+        let protected = self.protected(kind, category, name).await?;
+        if protected && !force {
+            return Err(err_msg!(Forbidden, "Cannot remove a protected record"));
+        }
+        Ok(protected)
+    }
+
+    /// Reject an expiry for a protected record, which would otherwise be
+    /// removed once the expiry has passed
+    async fn check_expiry(
+        &mut self,
+        kind: EntryKind,
+        category: &str,
+        name: &str,
+        expiry: Option<EntryExpiry>,
+    ) -> Result<(), Error> {
+        if expiry.is_some() && self.protected(kind, category, name).await? {
+            return Err(err_msg!(
+                Forbidden,
+                "Cannot set an expiry on a protected record"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check whether a record of a given kind is protected from removal
+    async fn protected(
+        &mut self,
+        kind: EntryKind,
+        category: &str,
+        name: &str,
+    ) -> Result<bool, Error> {
+        let marker = protection_marker(category, name)?;
+        Ok(retry_lost!(
+            self,
+            (kind, PROTECTED_CATEGORY),
+            fetch(kind, PROTECTED_CATEGORY, &marker, false)
+        )?
+        .is_some())
+    }
+
+    /// List the names of the protected records in a category
+    async fn protected_names(
+        &mut self,
+        kind: EntryKind,
+        category: &str,
+    ) -> Result<HashSet<String>, Error> {
+        let tag_filter = TagFilter::is_eq(PROTECTED_CATEGORY_TAG, category);
+        let markers = retry_lost!(
+            self,
+            (kind, PROTECTED_CATEGORY),
+            fetch_all(
+                kind,
+                PROTECTED_CATEGORY,
+                Some(tag_filter.clone()),
+                None,
+                false,
+                Projection::Names,
+            )
+        )?;
+        markers
+            .iter()
+            .map(|marker| parse_protection_marker(&marker.name).map(|(_, name)| name))
+            .collect()
+    }
+
+    /// Add or remove the marker recording the protection of a record
+    async fn update_protection(
+        &mut self,
+        kind: EntryKind,
+        category: &str,
+        name: &str,
+        protected: bool,
+    ) -> Result<(), Error> {
+        let marker = protection_marker(category, name)?;
+        if protected {
+            match retry_lost!(self, (kind, category), fetch(kind, category, name, false))? {
+                None => return Err(err_msg!(NotFound, "Entry not found")),
+                // the record would be removed once it expires
+                Some(entry) if entry.expiry().is_some() => {
+                    return Err(err_msg!(
+                        Input,
+                        "A record with an expiry cannot be protected"
+                    ))
+                }
+                Some(_) => (),
+            }
+            let tags = protection_tags(category);
+            match write_op!(
+                self,
+                (kind, PROTECTED_CATEGORY),
+                update(
+                    kind,
+                    EntryOperation::Insert,
+                    PROTECTED_CATEGORY,
+                    &marker,
+                    Some(&[][..]),
+                    Some(&tags),
+                    None,
+                )
+            ) {
+                Err(err) if err.kind() != ErrorKind::Duplicate => Err(err),
+                _ => Ok(()),
+            }
+        } else {
+            match write_op!(
+                self,
+                (kind, PROTECTED_CATEGORY),
+                update(
+                    kind,
+                    EntryOperation::Remove,
+                    PROTECTED_CATEGORY,
+                    &marker,
+                    None,
+                    None,
+                    None,
+                )
+            ) {
+                Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    /// Queue a message in the outbox of the profile, returning the identifier
+    /// assigned to it.
+    ///
+    /// Within a transaction, the message is only made available to
+    /// `Store::poll_outbox` when the transaction is committed
+    pub async fn queue_outbox(&mut self, message: &[u8]) -> Result<String, Error> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let tags = outbox_tags(outbox_timestamp(Duration::default()), 0);
+        write_op!(
+            self,
+            (EntryKind::Outbox, OUTBOX_CATEGORY),
+            update(
+                EntryKind::Outbox,
+                EntryOperation::Insert,
+                OUTBOX_CATEGORY,
+                &id,
+                Some(message),
+                Some(tags.as_slice()),
+                None,
+            )
+        )?;
+        Ok(id)
+    }
+
+    /// Hide up to `batch` visible outbox messages for the visibility timeout,
+    /// returning the claimed messages
+    async fn claim_outbox(
+        &mut self,
+        batch: usize,
+        visibility_timeout: Duration,
+    ) -> Result<Vec<OutboxMessage>, Error> {
+        let entries = retry_lost!(
+            self,
+            (EntryKind::Outbox, OUTBOX_CATEGORY),
+            fetch_all(
+                EntryKind::Outbox,
+                OUTBOX_CATEGORY,
+                Some(outbox_visible_filter()),
+                Some(batch as i64),
+                true,
+                Projection::Full
+            )
+        )?;
+        let mut messages = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let (message, tags) = OutboxMessage::claim(entry, visibility_timeout)?;
+            write_op!(
+                self,
+                (EntryKind::Outbox, OUTBOX_CATEGORY),
+                update(
+                    EntryKind::Outbox,
+                    EntryOperation::Replace,
+                    OUTBOX_CATEGORY,
+                    &message.id,
+                    Some(message.message.as_ref()),
+                    Some(tags.as_slice()),
+                    None,
+                )
+            )?;
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+
+    /// Fetch the current holder of a lease for update
+    async fn fetch_lease(&mut self, name: &str) -> Result<Option<(String, SystemTime)>, Error> {
+        let entry = retry_lost!(
+            self,
+            (EntryKind::Item, LEASE_CATEGORY),
+            fetch(EntryKind::Item, LEASE_CATEGORY, name, true)
+        )?;
+        entry.as_ref().map(lease_holder).transpose()
+    }
+
+    /// Write or remove the record of a lease holder
+    async fn write_lease(&mut self, operation: EntryOperation, lease: &Lease) -> Result<(), Error> {
+        let (value, tags) = if operation == EntryOperation::Remove {
+            (None, None)
+        } else {
+            (Some(lease.token.as_bytes()), Some(lease.tags()))
+        };
+        Ok(write_op!(
+            self,
+            (EntryKind::Item, LEASE_CATEGORY),
+            update(
+                EntryKind::Item,
+                operation,
+                LEASE_CATEGORY,
+                &lease.name,
+                value,
+                tags.as_deref(),
+                None,
+            )
+        )?)
+    }
+
+    /// Remove a delivered message from the outbox of the profile. Returns
+    /// `false` if the message was not found, having already been removed
+    pub async fn ack_outbox(&mut self, id: &str) -> Result<bool, Error> {
+        match write_op!(
+            self,
+            (EntryKind::Outbox, OUTBOX_CATEGORY),
+            update(
+                EntryKind::Outbox,
+                EntryOperation::Remove,
+                OUTBOX_CATEGORY,
+                id,
+                None,
+                None,
+                None,
+            )
+        ) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Write a marker for a removed record when change tracking is enabled.
+    /// An existing marker is replaced so that it is assigned a new sequence
+    /// number
+    async fn record_removal(
+        &mut self,
+        kind: EntryKind,
+        category: &str,
+        name: &str,
+    ) -> Result<(), Error> {
+        if !self.track_changes || kind != EntryKind::Item {
+            return Ok(());
+        }
+        let marker = removal_marker(category, name)?;
+        match write_op!(
+            self,
+            (kind, REMOVED_CATEGORY),
+            update(
+                kind,
+                EntryOperation::Remove,
+                REMOVED_CATEGORY,
+                &marker,
+                None,
+                None,
+                None,
+            )
+        ) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+            _ => (),
+        }
+        Ok(write_op!(
+            self,
+            (kind, REMOVED_CATEGORY),
+            update(
+                kind,
+                EntryOperation::Insert,
+                REMOVED_CATEGORY,
+                &marker,
+                Some(&[][..]),
+                None,
+                None,
+            )
+        )?)
+    }
+
+    /// Replace the value and tags of a record in the store.
+    ///
+    /// The previous version is removed and the new version inserted by a
+    /// single backend update, which is applied within a transaction even when
+    /// the session is not a transaction
+    pub async fn replace(
+        &mut self,
+        category: &str,
+        name: &str,
+        value: &[u8],
+        tags: Option<&[EntryTag]>,
+        expiry_ms: Option<i64>,
+    ) -> Result<(), Error> {
+        self.check_write(EntryOperation::Replace, category, name, value, tags)?;
+        let category = self.stored_category(category);
+        self.check_expiry(self.kind, &category, name, EntryExpiry::from_ms(expiry_ms))
+            .await?;
+        write_op!(
+            self,
+            (self.kind, &category),
+            update(
+                self.kind,
+                EntryOperation::Replace,
+                &category,
+                name,
+                Some(value),
+                tags,
+                EntryExpiry::from_ms(expiry_ms),
+            )
+        )?;
+        self.stats.add_written(1);
+        Ok(())
+    }
+
+    /// Retrieve the current record with a binary category and name. The
+    /// category and name of the returned entry are in their string form, as
+    /// produced by `binary_identifier`
+    pub async fn fetch_binary(
+        &mut self,
+        category: &[u8],
+        name: &[u8],
+        for_update: bool,
+    ) -> Result<Option<Entry>, Error> {
+        self.fetch(
+            &binary_identifier(category),
+            &binary_identifier(name),
+            for_update,
+        )
+        .await
+    }
+
+    /// Insert a new record with a binary category and name
+    pub async fn insert_binary(
+        &mut self,
+        category: &[u8],
+        name: &[u8],
+        value: &[u8],
+        tags: Option<&[EntryTag]>,
+        expiry_ms: Option<i64>,
+    ) -> Result<(), Error> {
+        self.insert(
+            &binary_identifier(category),
+            &binary_identifier(name),
+            value,
+            tags,
+            expiry_ms,
+        )
+        .await
+    }
+
+    /// Replace the value and tags of a record with a binary category and name
+    pub async fn replace_binary(
+        &mut self,
+        category: &[u8],
+        name: &[u8],
+        value: &[u8],
+        tags: Option<&[EntryTag]>,
+        expiry_ms: Option<i64>,
+    ) -> Result<(), Error> {
+        self.replace(
+            &binary_identifier(category),
+            &binary_identifier(name),
+            value,
+            tags,
+            expiry_ms,
+        )
+        .await
+    }
+
+    /// Remove a record with a binary category and name
+    pub async fn remove_binary(&mut self, category: &[u8], name: &[u8]) -> Result<(), Error> {
+        self.remove(&binary_identifier(category), &binary_identifier(name))
+            .await
+    }
+
+    /// Replace the value and tags of a record in the store, returning the
+    /// content hash of the previous version
+    pub async fn replace_returning_hash(
+        &mut self,
+        category: &str,
+        name: &str,
+        value: &[u8],
+        tags: Option<&[EntryTag]>,
+        expiry_ms: Option<i64>,
+    ) -> Result<EntryHash, Error> {
+        self.check_write(EntryOperation::Replace, category, name, value, tags)?;
+        let category = self.stored_category(category);
+        self.check_expiry(self.kind, &category, name, EntryExpiry::from_ms(expiry_ms))
+            .await?;
+        Ok(write_op!(
+            self,
+            (self.kind, &category),
+            replace_checked(
+                self.kind,
+                &category,
+                name,
+                value,
+                tags,
+                EntryExpiry::from_ms(expiry_ms),
+                None
+            )
+        )?)
     }
-}
 
-/// An active connection to the store backend
-#[derive(Debug)]
-pub struct Session<Q: QueryBackend>(Q);
+    /// Replace a record with the value and tags of `entry`, only if the
+    /// stored record has not been modified since its contents matched
+    /// `expected_hash`, as obtained from `Entry::content_hash`.
+    ///
+    /// The comparison and the replacement are performed atomically. If the
+    /// stored record has changed, a `Conflict` error is returned and the
+    /// record is left unmodified. Returns the hash of the new contents.
+    pub async fn replace_if_unmodified(
+        &mut self,
+        entry: &Entry,
+        expected_hash: EntryHash,
+        expiry_ms: Option<i64>,
+    ) -> Result<EntryHash, Error> {
+        let tags = Some(entry.tags.as_slice());
+        self.check_write(
+            EntryOperation::Replace,
+            &entry.category,
+            &entry.name,
+            &entry.value,
+            tags,
+        )?;
+        let category = self.stored_category(&entry.category);
+        self.check_expiry(
+            self.kind,
+            &category,
+            &entry.name,
+            EntryExpiry::from_ms(expiry_ms),
+        )
+        .await?;
+        write_op!(
+            self,
+            (self.kind, &category),
+            replace_checked(
+                self.kind,
+                &category,
+                &entry.name,
+                &entry.value,
+                tags,
+                EntryExpiry::from_ms(expiry_ms),
+                Some(expected_hash)
+            )
+        )?;
+        Ok(entry.content_hash())
+    }
 
-impl<Q: QueryBackend> Session<Q> {
-    pub(crate) fn new(inner: Q) -> Self {
-        Self(inner)
+    /// Insert a batch of new records, which may belong to different categories,
+    /// such as when importing a large number of records.
+    ///
+    /// The records are written within a transaction, which is started if the
+    /// session is not already in one, and no records are written if any of
+    /// them already exist. Postgres sessions insert each batch of records with
+    /// a single statement, and other backends insert the records in turn
+    pub async fn insert_all(&mut self, entries: Vec<Entry>) -> Result<(), Error> {
+        let mut stored = Vec::with_capacity(entries.len());
+        for mut entry in entries {
+            self.check_write(
+                EntryOperation::Insert,
+                &entry.category,
+                &entry.name,
+                entry.value.as_ref(),
+                Some(entry.tags.as_slice()),
+            )?;
+            entry.category = self.stored_category(&entry.category).into_owned();
+            stored.push(entry);
+        }
+        let count = stored.len();
+        write_op!(self, insert_all(self.kind, stored))?;
+        self.stats.add_written(count);
+        Ok(())
     }
-}
 
-impl<Q: QueryBackend> Session<Q> {
-    /// Count the number of entries for a given record category
-    pub async fn count(
+    /// Remove all records in the store matching a given `category` and
+    /// `tag_filter`. Protected records are skipped, and are not included in
+    /// the returned count
+    pub async fn remove_all(
         &mut self,
         category: &str,
         tag_filter: Option<TagFilter>,
     ) -> Result<i64, Error> {
-        Ok(self.0.count(EntryKind::Item, category, tag_filter).await?)
+        self.remove_matching(category, tag_filter, false).await
     }
 
-    /// Retrieve the current record at `(category, name)`.
-    ///
-    /// Specify `for_update` when in a transaction to create an update lock on the
-    /// associated record, if supported by the store backend
-    pub async fn fetch(
+    /// Remove all records in the store matching a given `category` and
+    /// `tag_filter`, including protected records
+    pub async fn force_remove_all(
         &mut self,
         category: &str,
-        name: &str,
-        for_update: bool,
-    ) -> Result<Option<Entry>, Error> {
-        Ok(self
-            .0
-            .fetch(EntryKind::Item, category, name, for_update)
-            .await?)
+        tag_filter: Option<TagFilter>,
+    ) -> Result<i64, Error> {
+        self.remove_matching(category, tag_filter, true).await
     }
 
-    /// Retrieve all records matching the given `category` and `tag_filter`.
-    ///
-    /// Unlike `Store::scan`, this method may be used within a transaction. It should
-    /// not be used for very large result sets due to correspondingly large memory
-    /// requirements
-    pub async fn fetch_all(
+    async fn remove_matching(
         &mut self,
         category: &str,
         tag_filter: Option<TagFilter>,
-        limit: Option<i64>,
-        for_update: bool,
-    ) -> Result<Vec<Entry>, Error> {
-        Ok(self
-            .0
-            .fetch_all(EntryKind::Item, category, tag_filter, limit, for_update)
-            .await?)
+        force: bool,
+    ) -> Result<i64, Error> {
+        check_tag_filter(&self.query_limits, tag_filter.as_ref())?;
+        let category = self.stored_category(category);
+        let group = self.begin_atomic().await?;
+        let result = self
+            .perform_remove_matching(&category, tag_filter, force)
+            .await;
+        self.end_atomic(group, result).await
     }
 
-    /// Insert a new record into the store
-    pub async fn insert(
+    async fn perform_remove_matching(
         &mut self,
         category: &str,
-        name: &str,
-        value: &[u8],
-        tags: Option<&[EntryTag]>,
-        expiry_ms: Option<i64>,
-    ) -> Result<(), Error> {
-        Ok(self
-            .0
-            .update(
-                EntryKind::Item,
-                EntryOperation::Insert,
-                category,
-                name,
-                Some(value),
-                tags,
-                expiry_ms,
-            )
-            .await?)
-    }
-
-    /// Remove a record from the store
-    pub async fn remove(&mut self, category: &str, name: &str) -> Result<(), Error> {
-        Ok(self
-            .0
-            .update(
-                EntryKind::Item,
-                EntryOperation::Remove,
-                category,
-                name,
-                None,
-                None,
-                None,
-            )
-            .await?)
+        tag_filter: Option<TagFilter>,
+        force: bool,
+    ) -> Result<i64, Error> {
+        let protected = self.protected_names(self.kind, category).await?;
+        if !protected.is_empty() {
+            // the records are removed individually, so that protected records
+            // may be skipped and their markers removed
+            let rows = retry_lost!(
+                self,
+                (self.kind, category),
+                fetch_all(
+                    self.kind,
+                    category,
+                    tag_filter,
+                    None,
+                    true,
+                    Projection::Names
+                )
+            )?;
+            let mut count = 0;
+            for entry in rows {
+                if protected.contains(&entry.name) && !force {
+                    continue;
+                }
+                self.remove_entry(self.kind, category, &entry.name, force)
+                    .await?;
+                count += 1;
+            }
+            return Ok(count as i64);
+        }
+        let removed = if self.track_changes && self.kind == EntryKind::Item {
+            retry_lost!(
+                self,
+                (self.kind, category),
+                fetch_all(
+                    self.kind,
+                    category,
+                    tag_filter.clone(),
+                    None,
+                    true,
+                    Projection::Names
+                )
+            )?
+        } else {
+            Vec::new()
+        };
+        let count = write_op!(
+            self,
+            (self.kind, category),
+            remove_all(self.kind, category, tag_filter.clone())
+        )?;
+        self.stats.add_written(count as usize);
+        for entry in removed {
+            self.record_removal(self.kind, category, &entry.name)
+                .await?;
+        }
+        Ok(count)
     }
 
-    /// Replace the value and tags of a record in the store
-    pub async fn replace(
+    /// Replace the expiry of the records matching a given `category` and
+    /// `tag_filter`, up to `limit` records in scan order, using a single
+    /// update. Records without an expiry are also updated. Returns the number
+    /// of records updated
+    pub async fn touch_all(
         &mut self,
         category: &str,
-        name: &str,
-        value: &[u8],
-        tags: Option<&[EntryTag]>,
-        expiry_ms: Option<i64>,
-    ) -> Result<(), Error> {
-        Ok(self
-            .0
-            .update(
-                EntryKind::Item,
-                EntryOperation::Replace,
-                category,
-                name,
-                Some(value),
-                tags,
-                expiry_ms,
+        tag_filter: Option<TagFilter>,
+        limit: Option<i64>,
+        expiry: EntryExpiry,
+    ) -> Result<i64, Error> {
+        check_tag_filter(&self.query_limits, tag_filter.as_ref())?;
+        let category = self.stored_category(category);
+        let protected = self.protected_names(self.kind, &category).await?;
+        if !protected.is_empty() {
+            let rows = retry_lost!(
+                self,
+                (self.kind, &category),
+                fetch_all(
+                    self.kind,
+                    &category,
+                    tag_filter.clone(),
+                    limit,
+                    false,
+                    Projection::Names
+                )
+            )?;
+            if rows.iter().any(|entry| protected.contains(&entry.name)) {
+                return Err(err_msg!(
+                    Forbidden,
+                    "Cannot set an expiry on a protected record"
+                ));
+            }
+        }
+        let count = write_op!(
+            self,
+            (self.kind, &category),
+            touch_all(
+                self.kind,
+                &category,
+                tag_filter.clone(),
+                None,
+                limit,
+                expiry
             )
-            .await?)
+        )?;
+        self.stats.add_written(count as usize);
+        Ok(count)
     }
 
-    /// Remove all records in the store matching a given `category` and `tag_filter`
-    pub async fn remove_all(
+    /// Retrieve all records matching the given `category` and `tag_filter`,
+    /// after replacing their expiry as performed by `touch_all`. The returned
+    /// records report the updated expiry.
+    ///
+    /// Within a transaction, the records returned are exactly those updated
+    pub async fn fetch_all_touch(
         &mut self,
         category: &str,
         tag_filter: Option<TagFilter>,
-    ) -> Result<i64, Error> {
-        Ok(self
-            .0
-            .remove_all(EntryKind::Item, category, tag_filter)
-            .await?)
+        limit: Option<i64>,
+        expiry: EntryExpiry,
+    ) -> Result<Vec<Entry>, Error> {
+        self.touch_all(category, tag_filter.clone(), limit, expiry)
+            .await?;
+        self.fetch_all(category, tag_filter, limit, false).await
     }
 
     /// Perform a record update
@@ -239,18 +3037,47 @@ impl<Q: QueryBackend> Session<Q> {
         tags: Option<&[EntryTag]>,
         expiry_ms: Option<i64>,
     ) -> Result<(), Error> {
-        Ok(self
-            .0
-            .update(
-                EntryKind::Item,
-                operation,
-                category,
-                name,
-                value,
-                tags,
-                expiry_ms,
-            )
-            .await?)
+        self.update_with_expiry(
+            operation,
+            category,
+            name,
+            value,
+            tags,
+            EntryExpiry::from_ms(expiry_ms),
+        )
+        .await
+    }
+
+    /// Perform a record update with an absolute, relative or sliding expiry.
+    ///
+    /// An entry written with a sliding expiry has its expiry extended by the
+    /// same interval each time it is fetched
+    pub async fn update_with_expiry(
+        &mut self,
+        operation: EntryOperation,
+        category: &str,
+        name: &str,
+        value: Option<&[u8]>,
+        tags: Option<&[EntryTag]>,
+        expiry: Option<EntryExpiry>,
+    ) -> Result<(), Error> {
+        if operation == EntryOperation::Remove {
+            let category = self.stored_category(category);
+            return self.remove_entry(self.kind, &category, name, false).await;
+        }
+        self.check_write(operation, category, name, value.unwrap_or_default(), tags)?;
+        let category = self.stored_category(category);
+        if operation == EntryOperation::Replace {
+            self.check_expiry(self.kind, &category, name, expiry)
+                .await?;
+        }
+        write_op!(
+            self,
+            (self.kind, &category),
+            update(self.kind, operation, &category, name, value, tags, expiry)
+        )?;
+        self.stats.add_written(1);
+        Ok(())
     }
 
     /// Insert a local key instance into the store
@@ -262,12 +3089,57 @@ impl<Q: QueryBackend> Session<Q> {
         tags: Option<&[EntryTag]>,
         expiry_ms: Option<i64>,
     ) -> Result<(), Error> {
-        let data = key.encode()?;
         let params = KeyParams {
             metadata: metadata.map(str::to_string),
             reference: None,
-            data: Some(data),
+            key_ops: key.key_ops(),
+            created_at: Some(to_timestamp(SystemTime::now())),
+            expires_at: None,
+            rotate_after: None,
+            replaces: None,
+            replaced_by: None,
+            provenance: None,
+            data: Some(key.encode()?),
+        };
+        self.insert_key_params(name, key, params, tags, expiry_ms)
+            .await
+    }
+
+    /// Insert a new key into the store along with a provenance record
+    /// describing the origin of the key
+    pub async fn insert_key_with_provenance(
+        &mut self,
+        name: &str,
+        key: &LocalKey,
+        metadata: Option<&str>,
+        provenance: KeyProvenance,
+        tags: Option<&[EntryTag]>,
+        expiry_ms: Option<i64>,
+    ) -> Result<(), Error> {
+        let params = KeyParams {
+            metadata: metadata.map(str::to_string),
+            reference: None,
+            key_ops: key.key_ops(),
+            created_at: Some(to_timestamp(SystemTime::now())),
+            expires_at: None,
+            rotate_after: None,
+            replaces: None,
+            replaced_by: None,
+            provenance: Some(provenance),
+            data: Some(key.encode()?),
         };
+        self.insert_key_params(name, key, params, tags, expiry_ms)
+            .await
+    }
+
+    async fn insert_key_params(
+        &mut self,
+        name: &str,
+        key: &LocalKey,
+        params: KeyParams,
+        tags: Option<&[EntryTag]>,
+        expiry_ms: Option<i64>,
+    ) -> Result<(), Error> {
         let value = params.to_bytes()?;
         let mut ins_tags = Vec::with_capacity(10);
         let alg = key.algorithm().as_str();
@@ -283,17 +3155,20 @@ impl<Q: QueryBackend> Session<Q> {
                 ins_tags.push(t.map_ref(|k, v| (format!("user:{}", k), v.to_string())));
             }
         }
-        self.0
-            .update(
+        write_op!(
+            self,
+            (EntryKind::Kms, KmsCategory::CryptoKey.as_str()),
+            update(
                 EntryKind::Kms,
                 EntryOperation::Insert,
                 KmsCategory::CryptoKey.as_str(),
                 name,
                 Some(value.as_ref()),
                 Some(ins_tags.as_slice()),
-                expiry_ms,
+                EntryExpiry::from_ms(expiry_ms),
             )
-            .await?;
+        )?;
+        self.stats.add_written(1);
         Ok(())
     }
 
@@ -307,16 +3182,17 @@ impl<Q: QueryBackend> Session<Q> {
         for_update: bool,
     ) -> Result<Option<KeyEntry>, Error> {
         Ok(
-            if let Some(row) = self
-                .0
-                .fetch(
+            if let Some(row) = retry_lost!(
+                self,
+                (EntryKind::Kms, KmsCategory::CryptoKey.as_str()),
+                fetch(
                     EntryKind::Kms,
                     KmsCategory::CryptoKey.as_str(),
                     name,
                     for_update,
                 )
-                .await?
-            {
+            )? {
+                self.stats.add_read(1);
                 Some(KeyEntry::from_entry(row)?)
             } else {
                 None
@@ -324,6 +3200,43 @@ impl<Q: QueryBackend> Session<Q> {
         )
     }
 
+    /// Export a stored key in the given format, along with its provenance
+    /// record if any
+    ///
+    /// The private key of an expired key may not be exported. When a store
+    /// policy is set, it is consulted before the key is encoded and a rejected
+    /// export fails with a `PolicyViolation` error.
+    pub async fn export_key(
+        &mut self,
+        name: &str,
+        format: KeyExportFormat,
+    ) -> Result<ExportedKey, Error> {
+        let entry = self
+            .fetch_key(name, false)
+            .await?
+            .ok_or_else(|| err_msg!(NotFound, "Key entry not found"))?;
+        if format.is_secret() && entry.is_expired() {
+            return Err(err_msg!(
+                PolicyViolation,
+                "The private key of an expired key may not be exported"
+            ));
+        }
+        if let Some(policy) = self.policy.as_ref() {
+            policy
+                .validate_key_export(&KeyExport {
+                    name,
+                    algorithm: entry.algorithm(),
+                    format,
+                })
+                .map_err(|reason| err_msg!(PolicyViolation, "{}", reason))?;
+        }
+        Ok(ExportedKey {
+            data: entry.load_local_key()?.export(format)?,
+            format,
+            provenance: entry.params.provenance,
+        })
+    }
+
     /// Retrieve all keys matching the given filters.
     pub async fn fetch_all_keys(
         &mut self,
@@ -333,16 +3246,19 @@ impl<Q: QueryBackend> Session<Q> {
         limit: Option<i64>,
         for_update: bool,
     ) -> Result<Vec<KeyEntry>, Error> {
+        check_tag_filter(&self.query_limits, tag_filter.as_ref())?;
         let mut query_parts = Vec::with_capacity(3);
-        if let Some(query) = tag_filter.map(|f| f.query) {
-            query_parts.push(TagFilter::from(
-                query
+        if let Some(filter) = tag_filter {
+            query_parts.push(TagFilter {
+                query: filter
+                    .query
                     .map_names(|mut k| {
                         k.replace_range(0..0, "user:");
                         Result::<_, ()>::Ok(k)
                     })
                     .unwrap(),
-            ));
+                ..filter
+            });
         }
         if let Some(algorithm) = algorithm {
             query_parts.push(TagFilter::is_eq("alg", algorithm));
@@ -355,16 +3271,19 @@ impl<Q: QueryBackend> Session<Q> {
         } else {
             Some(TagFilter::all_of(query_parts))
         };
-        let rows = self
-            .0
-            .fetch_all(
+        let rows = retry_lost!(
+            self,
+            (EntryKind::Kms, KmsCategory::CryptoKey.as_str()),
+            fetch_all(
                 EntryKind::Kms,
                 KmsCategory::CryptoKey.as_str(),
-                tag_filter,
+                tag_filter.clone(),
                 limit,
                 for_update,
+                Projection::Full,
             )
-            .await?;
+        )?;
+        self.stats.add_read(rows.len());
         let mut entries = Vec::with_capacity(rows.len());
         for row in rows {
             entries.push(KeyEntry::from_entry(row)?)
@@ -372,21 +3291,24 @@ impl<Q: QueryBackend> Session<Q> {
         Ok(entries)
     }
 
-    /// Remove an existing key from the store
+    /// Remove an existing key from the store. A `Forbidden` error is
+    /// returned if the key is protected
     pub async fn remove_key(&mut self, name: &str) -> Result<(), Error> {
-        self.0
-            .update(
-                EntryKind::Kms,
-                EntryOperation::Remove,
-                KmsCategory::CryptoKey.as_str(),
-                name,
-                None,
-                None,
-                None,
-            )
+        self.remove_entry(EntryKind::Kms, KmsCategory::CryptoKey.as_str(), name, false)
             .await
     }
 
+    /// Protect a key from removal, or remove its protection
+    pub async fn set_key_protected(&mut self, name: &str, protected: bool) -> Result<(), Error> {
+        self.update_protection(
+            EntryKind::Kms,
+            KmsCategory::CryptoKey.as_str(),
+            name,
+            protected,
+        )
+        .await
+    }
+
     /// Replace the metadata and tags on an existing key in the store
     pub async fn update_key(
         &mut self,
@@ -395,11 +3317,19 @@ impl<Q: QueryBackend> Session<Q> {
         tags: Option<&[EntryTag]>,
         expiry_ms: Option<i64>,
     ) -> Result<(), Error> {
-        let row = self
-            .0
-            .fetch(EntryKind::Kms, KmsCategory::CryptoKey.as_str(), name, true)
-            .await?
-            .ok_or_else(|| err_msg!(NotFound, "Key entry not found"))?;
+        let row = retry_lost!(
+            self,
+            (EntryKind::Kms, KmsCategory::CryptoKey.as_str()),
+            fetch(EntryKind::Kms, KmsCategory::CryptoKey.as_str(), name, true)
+        )?
+        .ok_or_else(|| err_msg!(NotFound, "Key entry not found"))?;
+        self.check_expiry(
+            EntryKind::Kms,
+            KmsCategory::CryptoKey.as_str(),
+            name,
+            EntryExpiry::from_ms(expiry_ms),
+        )
+        .await?;
 
         let mut params = KeyParams::from_slice(&row.value)?;
         params.metadata = metadata.map(str::to_string);
@@ -417,28 +3347,286 @@ impl<Q: QueryBackend> Session<Q> {
             }
         }
 
-        self.0
-            .update(
+        write_op!(
+            self,
+            (EntryKind::Kms, KmsCategory::CryptoKey.as_str()),
+            update(
                 EntryKind::Kms,
                 EntryOperation::Replace,
                 KmsCategory::CryptoKey.as_str(),
                 name,
                 Some(value.as_ref()),
                 Some(upd_tags.as_slice()),
-                expiry_ms,
+                EntryExpiry::from_ms(expiry_ms),
             )
-            .await?;
+        )?;
+        self.stats.add_written(1);
 
         Ok(())
     }
 
+    /// Set the expiry time and rotation interval for an existing key in the store.
+    ///
+    /// Keys which have expired, or which were created more than `rotate_after`
+    /// ago, are replaced by `Store::rotate_due_keys`
+    pub async fn set_key_lifetime(
+        &mut self,
+        name: &str,
+        expires_at: Option<SystemTime>,
+        rotate_after: Option<Duration>,
+    ) -> Result<(), Error> {
+        self.update_key_params(name, |params| {
+            params.expires_at = expires_at.map(to_timestamp);
+            params.rotate_after = rotate_after.map(|d| d.as_secs() as i64);
+        })
+        .await
+    }
+
+    /// Attach a provenance record to an existing key in the store, replacing
+    /// any previous record
+    pub async fn set_key_provenance(
+        &mut self,
+        name: &str,
+        provenance: Option<KeyProvenance>,
+    ) -> Result<(), Error> {
+        self.update_key_params(name, |params| {
+            params.provenance = provenance;
+        })
+        .await
+    }
+
+    async fn update_key_params(
+        &mut self,
+        name: &str,
+        update: impl FnOnce(&mut KeyParams),
+    ) -> Result<(), Error> {
+        let row = retry_lost!(
+            self,
+            (EntryKind::Kms, KmsCategory::CryptoKey.as_str()),
+            fetch(EntryKind::Kms, KmsCategory::CryptoKey.as_str(), name, true)
+        )?
+        .ok_or_else(|| err_msg!(NotFound, "Key entry not found"))?;
+
+        let mut params = KeyParams::from_slice(&row.value)?;
+        update(&mut params);
+        let value = params.to_bytes()?;
+
+        write_op!(
+            self,
+            (EntryKind::Kms, KmsCategory::CryptoKey.as_str()),
+            update(
+                EntryKind::Kms,
+                EntryOperation::Replace,
+                KmsCategory::CryptoKey.as_str(),
+                name,
+                Some(value.as_ref()),
+                Some(row.tags.as_slice()),
+                None,
+            )
+        )
+    }
+
+    /// Replace a stored key with a newly generated successor of the same algorithm.
+    ///
+    /// The successor is stored under its JWK thumbprint and inherits the metadata,
+    /// tags, permitted operations and rotation interval of the previous key. The
+    /// previous key is either removed, or retained for signature verification only.
+    /// Returns the name of the new key entry.
+    pub async fn rotate_key(&mut self, name: &str, retain_old: bool) -> Result<String, Error> {
+        let entry = self
+            .fetch_key(name, true)
+            .await?
+            .ok_or_else(|| err_msg!(NotFound, "Key entry not found"))?;
+        if !entry.is_local() {
+            return Err(err_msg!(Unsupported, "Cannot rotate an external key"));
+        }
+        if let Some(next) = entry.replaced_by() {
+            return Err(err_msg!(
+                Input,
+                "Key has already been replaced by '{}'",
+                next
+            ));
+        }
+        let alg = entry
+            .algorithm()
+            .ok_or_else(|| err_msg!(Unexpected, "Missing algorithm for key entry"))?
+            .parse::<KeyAlg>()?;
+
+        let mut key = LocalKey::generate(alg, false)?;
+        key.set_key_ops(entry.key_ops());
+        let new_name = key.to_jwk_thumbprint(None)?;
+        let params = KeyParams {
+            metadata: entry.params.metadata.clone(),
+            reference: None,
+            key_ops: key.key_ops(),
+            created_at: Some(to_timestamp(SystemTime::now())),
+            expires_at: None,
+            rotate_after: entry.params.rotate_after,
+            replaces: Some(name.to_string()),
+            replaced_by: None,
+            provenance: None,
+            data: Some(key.encode()?),
+        };
+        self.insert_key_params(&new_name, &key, params, Some(entry.tags.as_slice()), None)
+            .await?;
+
+        if retain_old {
+            self.update_key_params(name, |params| {
+                params.replaced_by = Some(new_name.clone());
+                params.key_ops = Some(KeyOps::Verify.into());
+                params.rotate_after = None;
+            })
+            .await?;
+        } else {
+            self.remove_key(name).await?;
+        }
+        Ok(new_name)
+    }
+
+    /// Sign a batch of messages with a stored key.
+    ///
+    /// The key is loaded once, and the messages are divided between
+    /// background threads. Returns the signatures in the order of the messages
+    pub async fn sign_batch(
+        &mut self,
+        key_name: &str,
+        messages: &[&[u8]],
+        sig_type: Option<&str>,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        let key = Arc::new(self.load_stored_key(key_name).await?);
+        key.check_op(KeyOps::Sign)?;
+        let sig_type = sig_type.map(str::to_string);
+        let tasks = messages
+            .chunks(BATCH_CHUNK_SIZE)
+            .map(|chunk| {
+                let key = key.clone();
+                let sig_type = sig_type.clone();
+                let chunk = chunk.iter().map(|msg| msg.to_vec()).collect::<Vec<_>>();
+                move || {
+                    chunk
+                        .iter()
+                        .map(|msg| key.sign_message(msg, sig_type.as_deref()))
+                        .collect::<Result<Vec<_>, Error>>()
+                }
+            })
+            .collect();
+        let mut signatures = Vec::with_capacity(messages.len());
+        for result in future::unblock_all(tasks).await {
+            signatures.extend(result?);
+        }
+        Ok(signatures)
+    }
+
+    /// Verify a batch of message signatures with a stored key.
+    ///
+    /// The key is loaded once, and the signatures are divided between
+    /// background threads. Returns the result of each verification in the
+    /// order of the messages
+    pub async fn verify_batch(
+        &mut self,
+        key_name: &str,
+        messages: &[&[u8]],
+        signatures: &[&[u8]],
+        sig_type: Option<&str>,
+    ) -> Result<Vec<bool>, Error> {
+        if messages.len() != signatures.len() {
+            return Err(err_msg!(
+                Input,
+                "The number of signatures must match the number of messages"
+            ));
+        }
+        let key = Arc::new(self.load_stored_key(key_name).await?);
+        key.check_op(KeyOps::Verify)?;
+        let sig_type = sig_type.map(str::to_string);
+        let tasks = messages
+            .chunks(BATCH_CHUNK_SIZE)
+            .zip(signatures.chunks(BATCH_CHUNK_SIZE))
+            .map(|(msgs, sigs)| {
+                let key = key.clone();
+                let sig_type = sig_type.clone();
+                let chunk = msgs
+                    .iter()
+                    .zip(sigs)
+                    .map(|(msg, sig)| (msg.to_vec(), sig.to_vec()))
+                    .collect::<Vec<_>>();
+                move || {
+                    chunk
+                        .iter()
+                        .map(|(msg, sig)| key.verify_signature(msg, sig, sig_type.as_deref()))
+                        .collect::<Result<Vec<_>, Error>>()
+                }
+            })
+            .collect();
+        let mut verified = Vec::with_capacity(messages.len());
+        for result in future::unblock_all(tasks).await {
+            verified.extend(result?);
+        }
+        Ok(verified)
+    }
+
+    /// Build the `did:key` DID document for a stored key
+    pub async fn did_key_document(&mut self, key_name: &str) -> Result<DidDocument, Error> {
+        let key = self.load_stored_key(key_name).await?;
+        DidDocument::did_key(&key)
+    }
+
+    /// Build a `did:peer` (method 2) DID document from a set of stored
+    /// authentication keys and key agreement keys
+    pub async fn did_peer_document(
+        &mut self,
+        authentication: &[&str],
+        key_agreement: &[&str],
+    ) -> Result<DidDocument, Error> {
+        let mut auth_keys = Vec::with_capacity(authentication.len());
+        for name in authentication {
+            auth_keys.push(self.load_stored_key(name).await?);
+        }
+        let mut agree_keys = Vec::with_capacity(key_agreement.len());
+        for name in key_agreement {
+            agree_keys.push(self.load_stored_key(name).await?);
+        }
+        DidDocument::did_peer_2(
+            &auth_keys.iter().collect::<Vec<_>>(),
+            &agree_keys.iter().collect::<Vec<_>>(),
+        )
+    }
+
+    async fn load_stored_key(&mut self, name: &str) -> Result<LocalKey, Error> {
+        let entry = self
+            .fetch_key(name, false)
+            .await?
+            .ok_or_else(|| err_msg!(NotFound, "Key entry not found"))?;
+        if !entry.is_local() {
+            return Err(err_msg!(Unsupported, "Cannot load an external key"));
+        }
+        entry.load_local_key()
+    }
+
+    /// Check whether the session was abandoned after a panic during an
+    /// operation
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
     /// Commit the pending transaction
-    pub async fn commit(self) -> Result<(), Error> {
-        Ok(self.0.close(true).await?)
+    pub async fn commit(mut self) -> Result<(), Error> {
+        if self.poisoned {
+            self.inner.reset_connection(true);
+            return Err(err_msg!(
+                Poisoned,
+                "Cannot commit a transaction abandoned after a panic"
+            ));
+        }
+        Ok(self.inner.close(true).await?)
     }
 
     /// Roll back the pending transaction
-    pub async fn rollback(self) -> Result<(), Error> {
-        Ok(self.0.close(false).await?)
+    pub async fn rollback(mut self) -> Result<(), Error> {
+        if self.poisoned {
+            // closing the connection rolls back the transaction
+            self.inner.reset_connection(true);
+            return Ok(());
+        }
+        Ok(self.inner.close(false).await?)
     }
 }
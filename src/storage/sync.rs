@@ -0,0 +1,320 @@
+//! Exchange of record changes between stores.
+//!
+//! A [`ChangeSet`] collects the records of a profile which have been inserted,
+//! replaced or removed following a given sequence number. It may be sealed into
+//! an encrypted and signed bundle for transport, and applied to a replica
+//! store, allowing devices to synchronize their records while offline.
+//!
+//! Removals are only included for stores which record them, as enabled by
+//! `Store::with_change_tracking`.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{
+    entry::{Entry, EntrySeq, EntryTag, EntryTagSet},
+    policy::RESERVED_CATEGORY_PREFIX,
+    store::Store,
+};
+use crate::{backend::Backend, error::Error, kms::LocalKey};
+
+/// The reserved category of the markers recording removed records
+pub(crate) const REMOVED_CATEGORY: &str = "askar:removed";
+
+const CHANGE_SET_AAD: &[u8] = b"askar:sync";
+
+const CHANGE_SET_VERSION: u8 = 1;
+
+/// The name of the marker recording the removal of a record
+pub(crate) fn removal_marker(category: &str, name: &str) -> Result<String, Error> {
+    serde_json::to_string(&(category, name))
+        .map_err(err_map!(Unexpected, "Error encoding removal marker"))
+}
+
+fn parse_removal_marker(marker: &str) -> Result<(String, String), Error> {
+    serde_json::from_str(marker).map_err(err_map!(Unexpected, "Error parsing removal marker"))
+}
+
+/// The handling of records which were modified in the replica store since
+/// the previous synchronization
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// The incoming change is applied, replacing the local modification
+    LastWriterWins,
+    /// The changes are not applied, and a `Conflict` error is returned
+    Error,
+}
+
+/// A change to a single record
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordChange {
+    /// The sequence number of the change in the source store
+    pub seq: EntrySeq,
+    /// The record category
+    pub category: String,
+    /// The record name
+    pub name: String,
+    /// Whether the record was removed
+    pub removed: bool,
+    /// The record value, which is empty for removed records
+    #[serde(with = "serde_bytes")]
+    pub value: Vec<u8>,
+    /// The record tags
+    #[serde(
+        serialize_with = "serialize_tags",
+        deserialize_with = "deserialize_tags"
+    )]
+    pub tags: Vec<EntryTag>,
+}
+
+fn serialize_tags<S>(tags: &[EntryTag], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    EntryTagSet::from(tags).serialize(serializer)
+}
+
+fn deserialize_tags<'de, D>(deserializer: D) -> Result<Vec<EntryTag>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    EntryTagSet::deserialize(deserializer).map(EntryTagSet::into_vec)
+}
+
+impl RecordChange {
+    fn from_entry(entry: Entry) -> Result<Self, Error> {
+        Ok(Self {
+            seq: entry
+                .seq()
+                .ok_or_else(|| err_msg!(Unexpected, "Missing record sequence number"))?,
+            category: entry.category,
+            name: entry.name,
+            removed: false,
+            value: entry.value.into_vec(),
+            tags: entry.tags,
+        })
+    }
+}
+
+/// The changes to the records of a profile following a sequence number
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeSet {
+    /// The sequence number following which changes were collected
+    pub since: Option<EntrySeq>,
+    /// The sequence number of the last change, which should be provided as
+    /// `since` when collecting the next set of changes
+    pub until: Option<EntrySeq>,
+    /// The changes, in sequence order
+    pub changes: Vec<RecordChange>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SealedChangeSet {
+    version: u8,
+    #[serde(with = "serde_bytes")]
+    payload: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    signature: Vec<u8>,
+}
+
+impl ChangeSet {
+    /// Encrypt the change set with a symmetric key, and sign the result
+    pub fn seal(&self, enc_key: &LocalKey, sign_key: &LocalKey) -> Result<Vec<u8>, Error> {
+        let encoded =
+            serde_cbor::to_vec(self).map_err(err_map!(Unexpected, "Error encoding change set"))?;
+        let payload = enc_key
+            .aead_encrypt(&encoded, &[], CHANGE_SET_AAD)?
+            .into_vec();
+        let signature = sign_key.sign_message(&payload, None)?;
+        serde_cbor::to_vec(&SealedChangeSet {
+            version: CHANGE_SET_VERSION,
+            payload,
+            signature,
+        })
+        .map_err(err_map!(Unexpected, "Error encoding sealed change set"))
+    }
+
+    /// Verify the signature of a sealed change set and decrypt it
+    pub fn open(sealed: &[u8], enc_key: &LocalKey, verify_key: &LocalKey) -> Result<Self, Error> {
+        let sealed: SealedChangeSet = serde_cbor::from_slice(sealed)
+            .map_err(err_map!(Input, "Error parsing sealed change set"))?;
+        if sealed.version != CHANGE_SET_VERSION {
+            return Err(err_msg!(Unsupported, "Unsupported change set version"));
+        }
+        if !verify_key.verify_signature(&sealed.payload, &sealed.signature, None)? {
+            return Err(err_msg!(Encryption, "Invalid change set signature"));
+        }
+        let nonce_len = enc_key.aead_params()?.nonce_length;
+        if sealed.payload.len() < nonce_len {
+            return Err(err_msg!(Encryption, "Invalid change set payload"));
+        }
+        let (ciphertext, nonce) = sealed.payload.split_at(sealed.payload.len() - nonce_len);
+        let decrypted = enc_key.aead_decrypt(ciphertext, nonce, CHANGE_SET_AAD)?;
+        serde_cbor::from_slice(&decrypted).map_err(err_map!(Input, "Error parsing change set"))
+    }
+}
+
+/// Collect the changes to the records in a set of categories following the
+/// sequence number `since`, or all records when `since` is `None`.
+///
+/// Changes which are superseded by a later change to the same record within
+/// the change set are retained, so that the changes may be applied in order
+pub async fn changes_since<B: Backend>(
+    store: &Store<B>,
+    profile: Option<String>,
+    categories: &[&str],
+    since: Option<EntrySeq>,
+) -> Result<ChangeSet, Error> {
+    let mut changes = Vec::new();
+    for category in categories {
+        if category.starts_with(RESERVED_CATEGORY_PREFIX) {
+            return Err(err_msg!(
+                Input,
+                "Records in reserved categories cannot be synchronized"
+            ));
+        }
+        let mut scan = store
+            .scan_since(profile.clone(), category.to_string(), None, since, None)
+            .await?;
+        while let Some(rows) = scan.fetch_next().await? {
+            for entry in rows {
+                changes.push(RecordChange::from_entry(entry)?);
+            }
+        }
+    }
+    let mut scan = store
+        .scan_since(profile, REMOVED_CATEGORY.to_string(), None, since, None)
+        .await?;
+    while let Some(rows) = scan.fetch_next().await? {
+        for marker in rows {
+            let (category, name) = parse_removal_marker(&marker.name)?;
+            if categories.contains(&category.as_str()) {
+                changes.push(RecordChange {
+                    removed: true,
+                    value: Vec::new(),
+                    tags: Vec::new(),
+                    category,
+                    name,
+                    ..RecordChange::from_entry(marker)?
+                });
+            }
+        }
+    }
+    changes.sort_by_key(|change| change.seq);
+    Ok(ChangeSet {
+        since,
+        until: changes.last().map(|change| change.seq).or(since),
+        changes,
+    })
+}
+
+/// The outcome of applying a change set to a replica store
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ApplyResult {
+    /// The number of changes applied
+    pub applied: usize,
+    /// The number of local modifications which were replaced
+    pub overwritten: usize,
+    /// The highest sequence number assigned to a record written to the
+    /// replica, which should be provided as `local_since` when applying the
+    /// next change set
+    pub local_seq: Option<EntrySeq>,
+}
+
+/// Apply a change set to a profile of a replica store within a single
+/// transaction.
+///
+/// A record of the replica is considered to be in conflict when it has been
+/// written following the sequence number `local_since`, which should be the
+/// `local_seq` returned when the previous change set was applied. When
+/// `local_since` is `None`, any existing record is in conflict
+pub async fn apply_changes<B: Backend>(
+    store: &Store<B>,
+    profile: Option<String>,
+    change_set: &ChangeSet,
+    policy: ConflictPolicy,
+    local_since: Option<EntrySeq>,
+) -> Result<ApplyResult, Error> {
+    let mut result = ApplyResult {
+        local_seq: local_since,
+        ..Default::default()
+    };
+    let mut txn = store.transaction(profile).await?;
+    for change in &change_set.changes {
+        let existing = txn.fetch(&change.category, &change.name, true).await?;
+        if let Some(existing) = existing.as_ref() {
+            if local_since.is_none() || existing.seq() > local_since {
+                if policy == ConflictPolicy::Error {
+                    return Err(err_msg!(
+                        Conflict,
+                        "Record '{}' was modified in the replica",
+                        change.name
+                    ));
+                }
+                result.overwritten += 1;
+            }
+        }
+        if change.removed {
+            if existing.is_some() {
+                txn.remove(&change.category, &change.name).await?;
+            }
+        } else {
+            if existing.is_some() {
+                txn.replace(
+                    &change.category,
+                    &change.name,
+                    &change.value,
+                    Some(&change.tags),
+                    None,
+                )
+                .await?;
+            } else {
+                txn.insert(
+                    &change.category,
+                    &change.name,
+                    &change.value,
+                    Some(&change.tags),
+                    None,
+                )
+                .await?;
+            }
+            if let Some(entry) = txn.fetch(&change.category, &change.name, false).await? {
+                result.local_seq = result.local_seq.max(entry.seq());
+            }
+        }
+        result.applied += 1;
+    }
+    txn.commit().await?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{crypto::alg::Chacha20Types, kms::KeyAlg};
+
+    #[test]
+    fn seal_open_change_set() {
+        let change_set = ChangeSet {
+            since: None,
+            until: Some(EntrySeq::new(2)),
+            changes: vec![RecordChange {
+                seq: EntrySeq::new(2),
+                category: "category".to_string(),
+                name: "name".to_string(),
+                removed: false,
+                value: b"value".to_vec(),
+                tags: vec![EntryTag::Plaintext("tag".to_string(), "a".to_string())],
+            }],
+        };
+        let enc_key = LocalKey::generate(KeyAlg::Chacha20(Chacha20Types::C20P), false).unwrap();
+        let sign_key = LocalKey::generate(KeyAlg::Ed25519, false).unwrap();
+        let sealed = change_set.seal(&enc_key, &sign_key).unwrap();
+        assert_eq!(
+            ChangeSet::open(&sealed, &enc_key, &sign_key).unwrap(),
+            change_set
+        );
+
+        let other_key = LocalKey::generate(KeyAlg::Ed25519, false).unwrap();
+        assert!(ChangeSet::open(&sealed, &enc_key, &other_key).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::error::Error;
+
+/// A partial set of runtime options, applied to an open store using
+/// `Store::reconfigure`. Only the options which are provided are changed.
+///
+/// Connection pool sizes are fixed when the store is opened, and are not
+/// included.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreTuning {
+    /// The default timeout for the operations of new sessions, in
+    /// milliseconds. A value of zero removes the timeout
+    pub session_timeout: Option<u64>,
+    /// The number of records returned in each page of a scan started after
+    /// the change
+    pub scan_page_size: Option<usize>,
+    /// The maximum number of cached profile keys. A value of zero removes
+    /// the limit
+    pub key_cache_capacity: Option<usize>,
+}
+
+impl StoreTuning {
+    /// Check the provided options for invalid values
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.scan_page_size == Some(0) {
+            return Err(err_msg!(Input, "The scan page size must be positive"));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn session_timeout(&self) -> Option<Option<Duration>> {
+        self.session_timeout
+            .map(|ms| Some(ms).filter(|ms| *ms > 0).map(Duration::from_millis))
+    }
+
+    pub(crate) fn key_cache_capacity(&self) -> Option<Option<usize>> {
+        self.key_cache_capacity
+            .map(|capacity| Some(capacity).filter(|c| *c > 0))
+    }
+}
@@ -0,0 +1,113 @@
+//! Uniqueness constraints over the plaintext tags of records.
+//!
+//! A constraint declared using `Store::with_unique_constraint` prevents two
+//! records in a category from sharing the same values for a set of plaintext
+//! tags. Each record written to the category stores a unique key, derived
+//! from a hash of the constraint and tag values, in the same statement which
+//! inserts or replaces the record. A unique index over the key is enforced by
+//! the database, so that a write which would duplicate the values of another
+//! record fails with a `Duplicate` error, and the key is released along with
+//! the record when it is removed or replaced.
+
+use sha2::{Digest, Sha256};
+use unicode_normalization::UnicodeNormalization;
+
+use super::entry::EntryTag;
+use crate::error::Error;
+
+/// A constraint requiring the values of a set of plaintext tags to be unique
+/// among the records of a category
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UniqueConstraint {
+    name: String,
+    category: String,
+    tag_names: Vec<String>,
+}
+
+impl UniqueConstraint {
+    /// Create a new constraint over one or more plaintext tags, given
+    /// without the `~` prefix. Records which lack any of the tags are not
+    /// constrained
+    pub fn new<I>(name: impl Into<String>, category: impl Into<String>, tag_names: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Self {
+            name: name.into(),
+            category: category.into(),
+            tag_names: tag_names.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Accessor for the name of the constraint
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Accessor for the constrained category
+    pub fn category(&self) -> &str {
+        &self.category
+    }
+
+    /// Accessor for the names of the constrained plaintext tags
+    pub fn tag_names(&self) -> &[String] {
+        &self.tag_names
+    }
+
+    /// The unique key stored alongside a record in the constrained category,
+    /// or `None` if the record lacks any of the constrained tags
+    pub(crate) fn unique_key(&self, tags: &[EntryTag]) -> Result<Option<Vec<u8>>, Error> {
+        let mut values: Vec<String> = Vec::with_capacity(self.tag_names.len());
+        for tag_name in self.tag_names.iter() {
+            match tags.iter().find_map(|tag| match tag {
+                // plaintext values are normalized when they are written
+                EntryTag::Plaintext(name, value) if name == tag_name => {
+                    Some(value.nfc().collect::<String>())
+                }
+                _ => None,
+            }) {
+                Some(value) => values.push(value),
+                None => return Ok(None),
+            }
+        }
+        // the key is indexed together with the encrypted category
+        let input = serde_json::to_vec(&(&self.name, &values))
+            .map_err(err_map!(Unexpected, "Error encoding unique key"))?;
+        Ok(Some(Sha256::digest(&input).to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_key() {
+        let constraint =
+            UniqueConstraint::new("did_pair", "connection", vec!["my_did", "their_did"]);
+        let tags = |their_did: &str| {
+            vec![
+                EntryTag::Plaintext("my_did".to_string(), "did:a".to_string()),
+                EntryTag::Plaintext("their_did".to_string(), their_did.to_string()),
+                EntryTag::Encrypted("state".to_string(), "active".to_string()),
+            ]
+        };
+        let key = constraint.unique_key(&tags("did:b")).unwrap();
+        assert!(key.is_some());
+        assert_eq!(key, constraint.unique_key(&tags("did:b")).unwrap());
+        assert_ne!(key, constraint.unique_key(&tags("did:c")).unwrap());
+        let other = UniqueConstraint::new("other", "connection", vec!["my_did", "their_did"]);
+        assert_ne!(key, other.unique_key(&tags("did:b")).unwrap());
+        assert_eq!(
+            constraint.unique_key(&tags("Ame\u{301}lie")).unwrap(),
+            constraint.unique_key(&tags("Am\u{e9}lie")).unwrap()
+        );
+        // encrypted tags are not considered
+        let encrypted = vec![
+            EntryTag::Plaintext("my_did".to_string(), "did:a".to_string()),
+            EntryTag::Encrypted("their_did".to_string(), "did:b".to_string()),
+        ];
+        assert_eq!(constraint.unique_key(&encrypted).unwrap(), None);
+    }
+}
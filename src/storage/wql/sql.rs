@@ -1,6 +1,7 @@
 use std::marker::PhantomData;
 
 use itertools::Itertools;
+use unicode_normalization::UnicodeNormalization;
 
 use super::tags::{CompareOp, ConjunctionOp, TagName, TagQueryEncoder};
 use crate::error::Error;
@@ -9,6 +10,8 @@ pub struct TagSqlEncoder<'e, EN, EV> {
     pub enc_name: EN,
     pub enc_value: EV,
     pub arguments: Vec<Vec<u8>>,
+    pub fold_case: Option<fn(&str) -> String>,
+    pub normalize: bool,
     _pd: PhantomData<&'e ()>,
 }
 
@@ -22,9 +25,37 @@ where
             enc_name,
             enc_value,
             arguments: vec![],
+            fold_case: None,
+            normalize: false,
             _pd: PhantomData,
         }
     }
+
+    /// Compare plaintext tag values using an SQL expression which lowercases
+    /// its argument
+    pub fn with_fold_case(mut self, fold_case: fn(&str) -> String) -> Self {
+        self.fold_case.replace(fold_case);
+        self
+    }
+
+    /// Apply NFC normalization to plaintext tag values
+    pub fn with_normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// The expressions for the tag value column and a query argument, which
+    /// are lowercased when comparing plaintext values without regard to case
+    fn value_exprs(&self, arg: &str, is_plaintext: bool) -> (String, String) {
+        match self.fold_case {
+            // encrypted values in the same table are excluded before folding,
+            // as they may not be valid text
+            Some(fold) if is_plaintext => {
+                (fold("CASE WHEN plaintext = 1 THEN value END"), fold(arg))
+            }
+            _ => ("value".to_string(), arg.to_string()),
+        }
+    }
 }
 
 impl<'e, EN, EV> TagQueryEncoder for TagSqlEncoder<'e, EN, EV>
@@ -43,7 +74,11 @@ where
 
     fn encode_value(&mut self, value: &String, is_plaintext: bool) -> Result<Self::Arg, Error> {
         Ok(if is_plaintext {
-            value.as_bytes().to_vec()
+            if self.normalize {
+                value.nfc().collect::<String>().into_bytes()
+            } else {
+                value.as_bytes().to_vec()
+            }
         } else {
             (&self.enc_value)(value)?
         })
@@ -76,11 +111,13 @@ where
             self.arguments.push(v);
         }
 
+        let (value, arg) = self.value_exprs(&format!("${}", idx + 2), is_plaintext);
         let query = format!(
-            "i.id IN (SELECT item_id FROM items_tags WHERE name = ${} AND value {} ${}{} AND plaintext = {})",
+            "i.id IN (SELECT item_id FROM items_tags WHERE name = ${} AND {} {} {}{} AND plaintext = {})",
             idx + 1,
+            value,
             op.as_sql_str(),
-            idx + 2,
+            arg,
             op_prefix.as_str(),
             if is_plaintext { 1 } else { 0 }
         );
@@ -94,10 +131,13 @@ where
         is_plaintext: bool,
         negate: bool,
     ) -> Result<Option<Self::Clause>, Error> {
-        let args_in = Itertools::intersperse(std::iter::repeat("$$").take(enc_values.len()), ", ")
-            .collect::<String>();
+        let (value, arg) = self.value_exprs("$$", is_plaintext);
+        let args_in =
+            Itertools::intersperse(std::iter::repeat(arg.as_str()).take(enc_values.len()), ", ")
+                .collect::<String>();
         let query = format!(
-            "i.id IN (SELECT item_id FROM items_tags WHERE name = $$ AND value {} ({}) AND plaintext = {})",
+            "i.id IN (SELECT item_id FROM items_tags WHERE name = $$ AND {} {} ({}) AND plaintext = {})",
+            value,
             if negate { "NOT IN" } else { "IN" },
             args_in,
             if is_plaintext { 1 } else { 0 }
@@ -203,4 +243,31 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn tag_query_encode_fold_case() {
+        let query = TagQuery::And(vec![
+            TagQuery::Eq(
+                TagName::Plaintext("email".to_string()),
+                "Ame\u{301}lie@Example.com".to_string(),
+            ),
+            TagQuery::In(
+                TagName::Plaintext("domain".to_string()),
+                vec!["A.com".to_string(), "B.com".to_string()],
+            ),
+            TagQuery::Eq(
+                TagName::Encrypted("enctag".to_string()),
+                "encval".to_string(),
+            ),
+        ]);
+        let mut enc = TagSqlEncoder::new(
+            |name: &str| Ok(name.as_bytes().to_vec()),
+            |value: &str| Ok(value.as_bytes().to_vec()),
+        )
+        .with_fold_case(|expr| format!("LOWER({})", expr))
+        .with_normalize(true);
+        let query_str = enc.encode_query(&query).unwrap().unwrap();
+        assert_eq!(query_str, "(i.id IN (SELECT item_id FROM items_tags WHERE name = $1 AND LOWER(CASE WHEN plaintext = 1 THEN value END) = LOWER($2) AND plaintext = 1) AND i.id IN (SELECT item_id FROM items_tags WHERE name = $$ AND LOWER(CASE WHEN plaintext = 1 THEN value END) IN (LOWER($$), LOWER($$)) AND plaintext = 1) AND i.id IN (SELECT item_id FROM items_tags WHERE name = $6 AND value = $7 AND plaintext = 0))");
+        assert_eq!(enc.arguments[1], "Am\u{e9}lie@Example.com".as_bytes());
+    }
 }
@@ -13,10 +13,66 @@ pub fn tag_query(query: Query) -> Result<TagQuery, Error> {
             }
         })
         .unwrap();
+    let result = flatten_query(result);
     validate_tag_query(&result)?;
     Ok(result)
 }
 
+/// Simplify a query by merging nested conjunctions of the same kind, and
+/// removing conjunctions of a single clause and double negations, so that
+/// fewer nested subqueries are produced when it is encoded
+pub fn flatten_query<K, V>(query: AbstractQuery<K, V>) -> AbstractQuery<K, V> {
+    match query {
+        AbstractQuery::And(subqueries) => flatten_conj(ConjunctionOp::And, subqueries),
+        AbstractQuery::Or(subqueries) => flatten_conj(ConjunctionOp::Or, subqueries),
+        AbstractQuery::Not(subquery) => match flatten_query(*subquery) {
+            AbstractQuery::Not(inner) => *inner,
+            other => AbstractQuery::Not(Box::new(other)),
+        },
+        other => other,
+    }
+}
+
+fn flatten_conj<K, V>(
+    op: ConjunctionOp,
+    subqueries: Vec<AbstractQuery<K, V>>,
+) -> AbstractQuery<K, V> {
+    let mut flat = Vec::with_capacity(subqueries.len());
+    for subquery in subqueries {
+        match (op, flatten_query(subquery)) {
+            (ConjunctionOp::And, AbstractQuery::And(inner))
+            | (ConjunctionOp::Or, AbstractQuery::Or(inner)) => flat.extend(inner),
+            (_, other) => flat.push(other),
+        }
+    }
+    if flat.len() == 1 {
+        flat.pop().unwrap()
+    } else if op == ConjunctionOp::And {
+        AbstractQuery::And(flat)
+    } else {
+        AbstractQuery::Or(flat)
+    }
+}
+
+/// Determine the nesting depth of the conjunctions and negations in a query,
+/// and the number of tag comparisons it contains
+pub fn query_complexity<K, V>(query: &AbstractQuery<K, V>) -> (usize, usize) {
+    match query {
+        AbstractQuery::And(subqueries) | AbstractQuery::Or(subqueries) => subqueries
+            .iter()
+            .map(query_complexity)
+            .fold((1, 0), |(depth, clauses), (sub_depth, sub_clauses)| {
+                (depth.max(sub_depth + 1), clauses + sub_clauses)
+            }),
+        AbstractQuery::Not(subquery) => {
+            let (depth, clauses) = query_complexity(subquery);
+            (depth + 1, clauses)
+        }
+        AbstractQuery::Exist(names) => (0, names.len()),
+        _ => (0, 1),
+    }
+}
+
 pub fn validate_tag_query(_query: &TagQuery) -> Result<(), Error> {
     // FIXME only equality comparison supported for encrypted keys
     Ok(())
@@ -420,6 +476,29 @@ mod tests {
         assert_eq!(query_str, "((enctag = encval AND ~plaintag = plainval) OR (enctag = encval AND ~plaintag != eggs))")
     }
 
+    #[test]
+    fn test_flatten() {
+        let eq = |name: &str| TagQuery::Eq(TagName::Encrypted(name.to_string()), "v".to_string());
+        let query = TagQuery::And(vec![
+            TagQuery::And(vec![eq("a"), TagQuery::And(vec![eq("b")])]),
+            TagQuery::Not(Box::new(TagQuery::Not(Box::new(TagQuery::Or(vec![
+                eq("c"),
+                TagQuery::Or(vec![eq("d"), eq("e")]),
+            ]))))),
+        ]);
+        assert_eq!(query_complexity(&query), (5, 5));
+        let flat = flatten_query(query);
+        assert_eq!(
+            flat,
+            TagQuery::And(vec![
+                eq("a"),
+                eq("b"),
+                TagQuery::Or(vec![eq("c"), eq("d"), eq("e")])
+            ])
+        );
+        assert_eq!(query_complexity(&flat), (2, 5));
+    }
+
     #[test]
     fn test_negate_conj() {
         let condition_1 = TagQuery::And(vec![
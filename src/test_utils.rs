@@ -0,0 +1,174 @@
+//! Support for provisioning stores from declarative test fixtures
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Deserializer};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    backend::{
+        sqlite::{SqliteStore, SqliteStoreOptions},
+        Backend,
+    },
+    crypto::random::RandomDet,
+    error::Error,
+    kms::{KeyAlg, LocalKey},
+    protect::{generate_raw_store_key, StoreKeyMethod},
+    storage::{EntryTag, EntryTagSet, Store},
+};
+
+/// A declarative description of the initial contents of a store.
+///
+/// When a `seed` is provided, the store key and all generated keys are
+/// derived from it, so that repeated runs produce identical key material.
+/// Encrypted record contents are still randomized.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct StoreFixture {
+    /// An optional seed for deterministic key generation
+    #[serde(default)]
+    pub seed: Option<String>,
+    /// The profiles to be populated
+    #[serde(default)]
+    pub profiles: Vec<ProfileFixture>,
+}
+
+/// The contents of a single store profile
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ProfileFixture {
+    /// The profile name, or `None` for the default profile
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The records to insert
+    #[serde(default)]
+    pub entries: Vec<EntryFixture>,
+    /// The keys to generate and insert
+    #[serde(default)]
+    pub keys: Vec<KeyFixture>,
+}
+
+/// A record to be inserted into a profile
+#[derive(Clone, Debug, Deserialize)]
+pub struct EntryFixture {
+    /// The record category
+    pub category: String,
+    /// The record name
+    pub name: String,
+    /// The record value
+    pub value: String,
+    /// The record tags, in the JSON tag format (plaintext tag names are prefixed with `~`)
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    pub tags: Vec<EntryTag>,
+}
+
+/// A key to be generated and inserted into a profile
+#[derive(Clone, Debug, Deserialize)]
+pub struct KeyFixture {
+    /// The key entry name
+    pub name: String,
+    /// The key algorithm
+    pub alg: String,
+    /// Associated key metadata
+    #[serde(default)]
+    pub metadata: Option<String>,
+    /// The key tags, in the JSON tag format
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    pub tags: Vec<EntryTag>,
+}
+
+fn deserialize_tags<'de, D>(deserializer: D) -> Result<Vec<EntryTag>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    EntryTagSet::deserialize(deserializer).map(EntryTagSet::into_vec)
+}
+
+impl StoreFixture {
+    /// Parse a fixture from its JSON representation
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(err_map!(Input, "Error parsing store fixture"))
+    }
+
+    /// Provision a new in-memory SQLite store and populate it
+    pub async fn provision_memory(&self) -> Result<Store<SqliteStore>, Error> {
+        self.provision_sqlite(SqliteStoreOptions::in_memory()).await
+    }
+
+    /// Provision a new SQLite store in a temporary file and populate it.
+    ///
+    /// The caller is responsible for removing the returned path
+    pub async fn provision_temp_file(&self) -> Result<(Store<SqliteStore>, PathBuf), Error> {
+        let path = std::env::temp_dir().join(format!("askar-{}.db", uuid::Uuid::new_v4()));
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| err_msg!(Unexpected, "Invalid temporary file path"))?;
+        let store = self
+            .provision_sqlite(SqliteStoreOptions::from_path(path_str))
+            .await?;
+        Ok((store, path))
+    }
+
+    async fn provision_sqlite(
+        &self,
+        options: SqliteStoreOptions,
+    ) -> Result<Store<SqliteStore>, Error> {
+        let seed = self.derive_seed(b"store", b"");
+        let key = generate_raw_store_key(seed.as_ref().map(|s| &s[..]))?;
+        let store = options
+            .provision(StoreKeyMethod::RawKey, key, None, false)
+            .await?;
+        self.populate(&store).await?;
+        Ok(store)
+    }
+
+    /// Populate an existing store with the contents of this fixture
+    pub async fn populate<B: Backend>(&self, store: &Store<B>) -> Result<(), Error> {
+        for profile in &self.profiles {
+            if let Some(name) = profile.name.as_ref() {
+                if name != store.get_profile_name() {
+                    store.create_profile(Some(name.clone())).await?;
+                }
+            }
+            let mut txn = store.transaction(profile.name.clone()).await?;
+            for entry in &profile.entries {
+                txn.insert(
+                    &entry.category,
+                    &entry.name,
+                    entry.value.as_bytes(),
+                    Some(entry.tags.as_slice()),
+                    None,
+                )
+                .await?;
+            }
+            for key in &profile.keys {
+                let alg = key.alg.parse::<KeyAlg>()?;
+                let local_key = match self.derive_seed(b"key", key.name.as_bytes()) {
+                    Some(seed) => LocalKey::generate_with_rng(alg, RandomDet::new(&seed)?, false)?,
+                    None => LocalKey::generate(alg, false)?,
+                };
+                txn.insert_key(
+                    &key.name,
+                    &local_key,
+                    key.metadata.as_deref(),
+                    Some(key.tags.as_slice()),
+                    None,
+                )
+                .await?;
+            }
+            txn.commit().await?;
+        }
+        Ok(())
+    }
+
+    fn derive_seed(&self, kind: &[u8], name: &[u8]) -> Option<[u8; 32]> {
+        self.seed.as_ref().map(|seed| {
+            let mut hasher = Sha256::new();
+            for part in &[seed.as_bytes(), kind, name] {
+                hasher.update(&(part.len() as u32).to_be_bytes());
+                hasher.update(part);
+            }
+            let mut out = [0u8; 32];
+            out.copy_from_slice(&hasher.finalize()[..]);
+            out
+        })
+    }
+}
@@ -57,6 +57,84 @@ macro_rules! backend_tests {
             })
         }
 
+        #[test]
+        fn fetch_into() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_fetch_into(&db).await;
+            })
+        }
+
+        #[test]
+        fn exists() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_exists(&db).await;
+            })
+        }
+
+        #[test]
+        fn fetch_by_id() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_fetch_by_id(&db).await;
+            })
+        }
+
+        #[test]
+        fn insert_or_fetch() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_insert_or_fetch(&db).await;
+            })
+        }
+
+        #[test]
+        fn search_terms() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_search_terms(&db).await;
+            })
+        }
+
+        #[test]
+        fn namespaces() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_namespaces(&db).await;
+            })
+        }
+
+        #[test]
+        fn scan_since() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_scan_since(&db).await;
+            })
+        }
+
+        #[test]
+        fn replace_unique_conflict() {
+            block_on(async {
+                let db = $init
+                    .await
+                    .with_unique_constraint(aries_askar::UniqueConstraint::new(
+                        "did",
+                        "connection",
+                        vec!["did"],
+                    ));
+                super::utils::db_replace_unique_conflict(&db).await;
+            })
+        }
+
+        #[test]
+        fn replace_if_unmodified() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_replace_if_unmodified(&db).await;
+            })
+        }
+
         #[test]
         fn replace_fetch() {
             block_on(async {
@@ -97,6 +175,22 @@ macro_rules! backend_tests {
             })
         }
 
+        #[test]
+        fn scan_large_records() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_scan_large_records(&db).await;
+            })
+        }
+
+        #[test]
+        fn scan_checkpoint() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_scan_checkpoint(&db).await;
+            })
+        }
+
         #[test]
         fn remove_all() {
             block_on(async {
@@ -176,135 +270,2039 @@ macro_rules! backend_tests {
                 super::utils::db_txn_fetch_for_update(&db).await;
             })
         }
-    };
-}
 
-#[cfg(feature = "sqlite")]
-mod sqlite {
-    use aries_askar::backend::sqlite::{SqliteStore, SqliteStoreOptions};
-    use aries_askar::{generate_raw_store_key, ManageBackend, Store, StoreKeyMethod};
-    use std::path::Path;
+        #[test]
+        fn key_ops_enforced() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_key_ops_enforced(&db).await;
+            })
+        }
 
-    #[test]
-    fn create_remove_db() {
-        env_logger::builder().is_test(true).try_init().unwrap_or(());
-        let fname = format!("sqlite-test-{}.db", uuid::Uuid::new_v4().to_string());
-        assert_eq!(
-            Path::new(&fname).exists(),
-            false,
-            "Oops, should be a unique filename"
-        );
+        #[test]
+        fn key_sign_verify_batch() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_key_sign_verify_batch(&db).await;
+            })
+        }
 
-        let key = generate_raw_store_key(None).expect("Error creating raw key");
-        block_on(async move {
-            assert_eq!(
-                SqliteStoreOptions::new(fname.as_str())
-                    .expect("Error initializing sqlite store options")
-                    .remove_backend()
-                    .await
-                    .expect("Error removing sqlite store"),
-                false
-            );
+        #[test]
+        fn key_did_documents() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_key_did_documents(&db).await;
+            })
+        }
 
-            let store = SqliteStoreOptions::new(fname.as_str())
-                .expect("Error initializing sqlite store options")
-                .provision_backend(StoreKeyMethod::RawKey, key.as_ref(), None, false)
-                .await
-                .expect("Error provisioning sqlite store");
-            assert_eq!(Path::new(&fname).exists(), true);
+        #[test]
+        fn key_rotation() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_key_rotation(&db).await;
+            })
+        }
 
-            let store2 = SqliteStoreOptions::new(fname.as_str())
-                .expect("Error initializing sqlite store options")
-                .open_backend(Some(StoreKeyMethod::RawKey), key.as_ref(), None)
-                .await
-                .expect("Error opening sqlite store");
-            store2.close().await.expect("Error closing sqlite store");
+        #[test]
+        fn session_timeout() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_session_timeout(&db).await;
+            })
+        }
 
-            store.close().await.expect("Error closing sqlite store");
-            assert_eq!(Path::new(&fname).exists(), true);
+        #[test]
+        fn custom_entry_kind() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_custom_entry_kind(&db).await;
+            })
+        }
 
-            assert_eq!(
-                SqliteStoreOptions::new(fname.as_str())
-                    .expect("Error initializing sqlite store options")
-                    .remove_backend()
-                    .await
-                    .expect("Error removing sqlite store"),
-                true
-            );
-            assert_eq!(Path::new(&fname).exists(), false);
-        })
-    }
+        #[test]
+        fn scoped_session() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_scoped_session(&db).await;
+            })
+        }
 
-    #[test]
-    fn rekey_db() {
-        env_logger::builder().is_test(true).try_init().unwrap_or(());
-        let fname = format!("sqlite-test-{}.db", uuid::Uuid::new_v4().to_string());
-        let key1 = generate_raw_store_key(None).expect("Error creating raw key");
-        let key2 = generate_raw_store_key(None).expect("Error creating raw key");
-        assert_ne!(key1, key2);
+        #[test]
+        fn maintenance() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_maintenance(&db).await;
+            })
+        }
 
-        block_on(async move {
-            let mut store = SqliteStoreOptions::new(fname.as_str())
-                .expect("Error initializing sqlite store options")
-                .provision_backend(StoreKeyMethod::RawKey, key1.as_ref(), None, false)
-                .await
-                .expect("Error provisioning sqlite store");
+        #[test]
+        fn storage_report() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_storage_report(&db).await;
+            })
+        }
 
-            store
-                .rekey(StoreKeyMethod::RawKey, key2.as_ref())
-                .await
-                .expect("Error rekeying database");
+        #[test]
+        fn entry_expiry() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_entry_expiry(&db).await;
+            })
+        }
 
-            SqliteStoreOptions::new(fname.as_str())
-                .expect("Error initializing sqlite store options")
-                .open_backend(Some(StoreKeyMethod::RawKey), key2.as_ref(), None)
-                .await
-                .expect("Error opening rekeyed store")
-                .close()
-                .await
-                .expect("Error closing store");
+        #[test]
+        fn touch_all() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_touch_all(&db).await;
+            })
+        }
 
-            store.close().await.expect("Error closing store");
+        #[test]
+        fn insert_all() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_insert_all(&db).await;
+            })
+        }
 
-            SqliteStoreOptions::new(fname.as_str())
-                .expect("Error initializing sqlite store options")
-                .remove_backend()
-                .await
-                .expect("Error removing sqlite store");
-        })
-    }
+        #[test]
+        fn durability() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_durability(&db).await;
+            })
+        }
 
-    async fn init_db() -> Store<SqliteStore> {
-        env_logger::builder().is_test(true).try_init().unwrap_or(());
-        let key = generate_raw_store_key(None).expect("Error creating raw key");
-        SqliteStoreOptions::in_memory()
-            .provision(StoreKeyMethod::RawKey, key, None, false)
-            .await
-            .expect("Error provisioning sqlite store")
-    }
+        #[test]
+        fn key_cache_stats() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_key_cache_stats(&db).await;
+            })
+        }
 
-    backend_tests!(init_db());
+        #[test]
+        fn error_context() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_error_context(&db).await;
+            })
+        }
 
-    #[test]
-    fn provision_from_str() {
-        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        #[test]
+        fn binary_identifiers() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_binary_identifiers(&db).await;
+            })
+        }
 
-        block_on(async {
-            let db_url = "sqlite://:memory:";
-            let _db = db_url
-                .provision_backend(StoreKeyMethod::RawKey, key.as_ref(), None, false)
-                .await
-                .expect("Error provisioning store");
-        });
+        #[test]
+        fn entry_metadata() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_entry_metadata(&db).await;
+            })
+        }
 
-        block_on(async {
-            let db_url = "not-sqlite://test-db";
-            let _db = db_url
+        #[test]
+        fn entry_links() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_entry_links(&db).await;
+            })
+        }
+
+        #[test]
+        fn collections() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_collections(&db).await;
+            })
+        }
+
+        #[test]
+        fn outbox() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_outbox(&db).await;
+            })
+        }
+
+        #[test]
+        fn leases() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_leases(&db).await;
+            })
+        }
+
+        #[test]
+        fn reconfigure() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_reconfigure(&db).await;
+            })
+        }
+
+        #[test]
+        fn session_stats() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_session_stats(&db).await;
+            })
+        }
+
+        #[test]
+        fn export_ndjson() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_export_ndjson(&db).await;
+            })
+        }
+
+        #[test]
+        fn export_filtered() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_export_filtered(&db).await;
+            })
+        }
+
+        #[test]
+        fn scan_modified() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_scan_modified(&db).await;
+            })
+        }
+
+        #[test]
+        fn entry_protection() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_entry_protection(&db).await;
+            })
+        }
+
+        #[test]
+        fn maintenance_mode() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_maintenance_mode(&db).await;
+            })
+        }
+
+        #[test]
+        fn tag_match_options() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_tag_match_options(&db).await;
+            })
+        }
+
+        #[test]
+        fn multi_valued_tags() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_multi_valued_tags(&db).await;
+            })
+        }
+
+        #[test]
+        fn scan_projection() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_scan_projection(&db).await;
+            })
+        }
+
+        #[test]
+        fn scan_detached() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_scan_detached(&db).await;
+            })
+        }
+
+        #[test]
+        fn session_cancel() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_session_cancel(&db).await;
+            })
+        }
+
+        #[test]
+        fn atomic_cancel() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_atomic_cancel(&db).await;
+            })
+        }
+
+        #[test]
+        fn txn_cancel() {
+            block_on(async {
+                let db = $init.await;
+                super::utils::db_txn_cancel(&db).await;
+            })
+        }
+    };
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use aries_askar::backend::sqlite::{SqliteStore, SqliteStoreOptions};
+    use aries_askar::{
+        generate_raw_store_key, CachedProfileKey, EntryTag, EntryWrite, Error, ErrorKind,
+        KeyExport, ManageBackend, MemoryKeyCache, PassKey, PassKeyPolicy, PlaintextExport,
+        ProfileId, ProfileKeyCache, QueryLimits, Store, StoreKeyMethod, StorePolicy, TagFilter,
+        TenantKeyProvider, UniqueConstraint, UnlockReason, RESERVED_CATEGORY_PREFIX,
+    };
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn create_remove_db() {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let fname = format!("sqlite-test-{}.db", uuid::Uuid::new_v4().to_string());
+        assert_eq!(
+            Path::new(&fname).exists(),
+            false,
+            "Oops, should be a unique filename"
+        );
+
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        block_on(async move {
+            assert_eq!(
+                SqliteStoreOptions::new(fname.as_str())
+                    .expect("Error initializing sqlite store options")
+                    .remove_backend()
+                    .await
+                    .expect("Error removing sqlite store"),
+                false
+            );
+
+            let store = SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .provision_backend(StoreKeyMethod::RawKey, key.as_ref(), None, false)
+                .await
+                .expect("Error provisioning sqlite store");
+            assert_eq!(Path::new(&fname).exists(), true);
+
+            let store2 = SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .open_backend(Some(StoreKeyMethod::RawKey), key.as_ref(), None)
+                .await
+                .expect("Error opening sqlite store");
+            store2.close().await.expect("Error closing sqlite store");
+
+            store.close().await.expect("Error closing sqlite store");
+            assert_eq!(Path::new(&fname).exists(), true);
+
+            assert_eq!(
+                SqliteStoreOptions::new(fname.as_str())
+                    .expect("Error initializing sqlite store options")
+                    .remove_backend()
+                    .await
+                    .expect("Error removing sqlite store"),
+                true
+            );
+            assert_eq!(Path::new(&fname).exists(), false);
+        })
+    }
+
+    #[test]
+    fn rekey_db() {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let fname = format!("sqlite-test-{}.db", uuid::Uuid::new_v4().to_string());
+        let key1 = generate_raw_store_key(None).expect("Error creating raw key");
+        let key2 = generate_raw_store_key(None).expect("Error creating raw key");
+        assert_ne!(key1, key2);
+
+        block_on(async move {
+            let mut store = SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .provision_backend(StoreKeyMethod::RawKey, key1.as_ref(), None, false)
+                .await
+                .expect("Error provisioning sqlite store");
+
+            store
+                .rekey(StoreKeyMethod::RawKey, key2.as_ref())
+                .await
+                .expect("Error rekeying database");
+
+            SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .open_backend(Some(StoreKeyMethod::RawKey), key2.as_ref(), None)
+                .await
+                .expect("Error opening rekeyed store")
+                .close()
+                .await
+                .expect("Error closing store");
+
+            store.close().await.expect("Error closing store");
+
+            SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .remove_backend()
+                .await
+                .expect("Error removing sqlite store");
+        })
+    }
+
+    #[test]
+    fn store_capabilities() {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let fname = format!("sqlite-test-{}.db", uuid::Uuid::new_v4().to_string());
+        let uri = format!("sqlite://{}?history=1", fname);
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+
+        block_on(async move {
+            let store = SqliteStoreOptions::new(uri.as_str())
+                .expect("Error initializing sqlite store options")
+                .provision_backend(StoreKeyMethod::RawKey, key.as_ref(), None, false)
+                .await
+                .expect("Error provisioning sqlite store");
+            let caps = store.capabilities();
+            assert!(caps.history && caps.blind_index);
+            assert!(!caps.partitioned && !caps.soft_delete && !caps.value_checksum);
+            store.close().await.expect("Error closing sqlite store");
+
+            // the capabilities are read from the store configuration
+            let store = SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .open_backend(Some(StoreKeyMethod::RawKey), key.as_ref(), None)
+                .await
+                .expect("Error opening sqlite store");
+            assert_eq!(store.capabilities(), caps);
+            assert!(store.capabilities().supports("history"));
+            store.close().await.expect("Error closing sqlite store");
+
+            SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .remove_backend()
+                .await
+                .expect("Error removing sqlite store");
+        })
+    }
+
+    #[test]
+    fn open_wrong_pass_key() {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let fname = format!("sqlite-test-{}.db", uuid::Uuid::new_v4().to_string());
+        let key1 = generate_raw_store_key(None).expect("Error creating raw key");
+        let key2 = generate_raw_store_key(None).expect("Error creating raw key");
+
+        block_on(async move {
+            let mut store = SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .provision_backend(StoreKeyMethod::RawKey, key1.as_ref(), None, false)
+                .await
+                .expect("Error provisioning sqlite store");
+
+            let err = SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .open_backend(Some(StoreKeyMethod::RawKey), key2.as_ref(), None)
+                .await
+                .expect_err("Expected wrong pass key error");
+            assert_eq!(err.kind(), ErrorKind::WrongPassKey);
+
+            // the key check value is replaced when rekeying
+            store
+                .rekey(StoreKeyMethod::RawKey, key2.as_ref())
+                .await
+                .expect("Error rekeying database");
+            let err = SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .open_backend(Some(StoreKeyMethod::RawKey), key1.as_ref(), None)
+                .await
+                .expect_err("Expected wrong pass key error");
+            assert_eq!(err.kind(), ErrorKind::WrongPassKey);
+            SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .open_backend(Some(StoreKeyMethod::RawKey), key2.as_ref(), None)
+                .await
+                .expect("Error opening rekeyed store")
+                .close()
+                .await
+                .expect("Error closing store");
+
+            store.close().await.expect("Error closing store");
+            SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .remove_backend()
+                .await
+                .expect("Error removing sqlite store");
+        })
+    }
+
+    #[test]
+    fn rekey_db_paged() {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let key1 = generate_raw_store_key(None).expect("Error creating raw key");
+        let key2 = generate_raw_store_key(None).expect("Error creating raw key");
+
+        block_on(async move {
+            let mut store = SqliteStoreOptions::in_memory()
+                .provision(StoreKeyMethod::RawKey, key1, None, false)
+                .await
+                .expect("Error provisioning sqlite store");
+
+            // span multiple rekey batches
+            let mut profile = None;
+            for _ in 0..300 {
+                profile.replace(
+                    store
+                        .create_profile(None)
+                        .await
+                        .expect("Error creating profile"),
+                );
+            }
+            let mut conn = store
+                .session(profile.clone())
+                .await
+                .expect("Error starting session");
+            conn.insert("category", "name", b"value", None, None)
+                .await
+                .expect("Error inserting test row");
+            drop(conn);
+
+            store
+                .rekey(StoreKeyMethod::RawKey, key2.as_ref())
+                .await
+                .expect("Error rekeying database");
+
+            let mut conn = store
+                .session(profile)
+                .await
+                .expect("Error starting session");
+            let row = conn
+                .fetch("category", "name", false)
+                .await
+                .expect("Error fetching test row")
+                .expect("Expected row");
+            assert_eq!(row.value, &b"value"[..]);
+            drop(conn);
+
+            store.close().await.expect("Error closing store");
+        })
+    }
+
+    async fn init_db() -> Store<SqliteStore> {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        SqliteStoreOptions::in_memory()
+            .provision(StoreKeyMethod::RawKey, key, None, false)
+            .await
+            .expect("Error provisioning sqlite store")
+    }
+
+    backend_tests!(init_db());
+
+    #[derive(Debug)]
+    struct RequireOwnerTag;
+
+    impl StorePolicy for RequireOwnerTag {
+        fn validate(&self, entry: &EntryWrite<'_>) -> Result<(), String> {
+            if entry.tags.iter().any(|tag| tag.name() == "owner") {
+                Ok(())
+            } else {
+                Err(format!("Missing owner tag for record '{}'", entry.name))
+            }
+        }
+    }
+
+    #[test]
+    fn store_policy() {
+        block_on(async {
+            let db = init_db().await.with_policy(Arc::new(RequireOwnerTag));
+            let mut conn = db.session(None).await.expect("Error starting session");
+            let err = conn
+                .insert("category", "name", b"value", None, None)
+                .await
+                .expect_err("Expected policy violation");
+            assert_eq!(err.kind(), ErrorKind::PolicyViolation);
+
+            let tags = [EntryTag::Encrypted("owner".into(), "alice".into())];
+            conn.insert("category", "name", b"value", Some(&tags[..]), None)
+                .await
+                .expect("Error inserting record");
+
+            let err = conn
+                .insert(
+                    &format!("{}category", RESERVED_CATEGORY_PREFIX),
+                    "name",
+                    b"value",
+                    Some(&tags[..]),
+                    None,
+                )
+                .await
+                .expect_err("Expected reserved category error");
+            assert_eq!(err.kind(), ErrorKind::PolicyViolation);
+
+            // removals are not subject to the policy
+            conn.remove("category", "name")
+                .await
+                .expect("Error removing record");
+            drop(conn);
+
+            // imported records are also validated
+            let line = br#"{"kind":"item","category":"category","name":"name","value":"value"}"#;
+            let err = db
+                .import_ndjson(None, &line[..], PlaintextExport::Acknowledged)
+                .await
+                .expect_err("Expected policy violation");
+            assert_eq!(err.kind(), ErrorKind::PolicyViolation);
+        })
+    }
+
+    #[derive(Debug)]
+    struct PublicExportsOnly;
+
+    impl StorePolicy for PublicExportsOnly {
+        fn validate(&self, _entry: &EntryWrite<'_>) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn validate_key_export(&self, export: &KeyExport<'_>) -> Result<(), String> {
+            if export.format.is_secret() {
+                Err(format!("Private key export denied for '{}'", export.name))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn key_export_policy() {
+        use aries_askar::kms::{KeyAlg, KeyExportFormat, LocalKey};
+
+        block_on(async {
+            let db = init_db().await;
+            let key = LocalKey::generate(KeyAlg::Ed25519, false).expect("Error creating key");
+            let mut conn = db.session(None).await.expect("Error starting session");
+            conn.insert_key("key", &key, None, None, None)
+                .await
+                .expect("Error inserting key");
+            let der = conn
+                .export_key("key", KeyExportFormat::Pkcs8Der)
+                .await
+                .expect("Error exporting key");
+            assert_eq!(der.format, KeyExportFormat::Pkcs8Der);
+            let loaded = LocalKey::from_pkcs8_der(&der.data).expect("Error loading exported key");
+            assert_eq!(
+                loaded.to_jwk_secret().unwrap(),
+                key.to_jwk_secret().unwrap()
+            );
+            drop(conn);
+
+            let db = db.with_policy(Arc::new(PublicExportsOnly));
+            let mut conn = db.session(None).await.expect("Error starting session");
+            let pem = conn
+                .export_key("key", KeyExportFormat::SpkiPem)
+                .await
+                .expect("Error exporting public key");
+            let public = LocalKey::from_spki_pem(pem.data.as_opt_str().unwrap())
+                .expect("Error loading exported public key");
+            assert_eq!(
+                public.to_jwk_public(None).unwrap(),
+                key.to_jwk_public(None).unwrap()
+            );
+            let err = conn
+                .export_key("key", KeyExportFormat::Pkcs8Pem)
+                .await
+                .expect_err("Expected policy violation");
+            assert_eq!(err.kind(), ErrorKind::PolicyViolation);
+        })
+    }
+
+    #[test]
+    fn key_provenance() {
+        use aries_askar::kms::{KeyAlg, KeyExportFormat, KeyProvenance, LocalKey};
+
+        block_on(async {
+            let db = init_db().await;
+            let key = LocalKey::generate(KeyAlg::Ed25519, false).expect("Error creating key");
+            let attest = LocalKey::generate(KeyAlg::Ed25519, false).expect("Error creating key");
+            let mut prov = KeyProvenance {
+                device: Some("hsm-1".to_string()),
+                attestation: Some(b"attestation".to_vec()),
+                created_at: Some(1000),
+                generator: Some("keygen/1.0".to_string()),
+                ..Default::default()
+            };
+            prov.sign(&key, &attest).expect("Error signing provenance");
+
+            let mut conn = db.session(None).await.expect("Error starting session");
+            conn.insert_key_with_provenance("key", &key, None, prov.clone(), None, None)
+                .await
+                .expect("Error inserting key");
+            let entry = conn
+                .fetch_key("key", false)
+                .await
+                .expect("Error fetching key")
+                .expect("Key not found");
+            assert_eq!(entry.provenance(), Some(&prov));
+            let loaded = entry.load_local_key().expect("Error loading key");
+            assert!(entry
+                .provenance()
+                .unwrap()
+                .verify(&loaded, &attest)
+                .unwrap());
+
+            let export = conn
+                .export_key("key", KeyExportFormat::JwkPublic)
+                .await
+                .expect("Error exporting key");
+            assert_eq!(export.provenance, Some(prov));
+
+            conn.set_key_provenance("key", None)
+                .await
+                .expect("Error clearing provenance");
+            let entry = conn
+                .fetch_key("key", false)
+                .await
+                .expect("Error fetching key")
+                .expect("Key not found");
+            assert!(entry.provenance().is_none());
+        })
+    }
+
+    #[test]
+    fn export_import_profile() {
+        use aries_askar::kms::{KeyAlg, LocalKey};
+
+        block_on(async {
+            let source = init_db().await;
+            let target = init_db().await;
+            let profile = source
+                .create_profile(Some("tenant".to_string()))
+                .await
+                .expect("Error creating profile");
+            let mut conn = source
+                .session(Some(profile.clone()))
+                .await
+                .expect("Error starting session");
+            let tags = [EntryTag::Encrypted("owner".into(), "alice".into())];
+            for idx in 0..10 {
+                conn.insert(
+                    "category",
+                    &format!("name-{}", idx),
+                    b"value",
+                    Some(&tags[..]),
+                    None,
+                )
+                .await
+                .expect("Error inserting entry");
+            }
+            conn.set_namespace(Some("app".to_string()))
+                .expect("Error setting namespace");
+            conn.insert("category", "namespaced", b"ns-value", None, None)
+                .await
+                .expect("Error inserting entry");
+            let key = LocalKey::generate(KeyAlg::Ed25519, false).expect("Error creating key");
+            conn.insert_key("key", &key, None, None, None)
+                .await
+                .expect("Error inserting key");
+            drop(conn);
+
+            let pass_key = generate_raw_store_key(None).expect("Error creating raw key");
+            let bundle = source
+                .export_profile(profile.clone(), StoreKeyMethod::RawKey, pass_key.as_ref())
+                .await
+                .expect("Error exporting profile");
+
+            let other_key = generate_raw_store_key(None).expect("Error creating raw key");
+            let err = target
+                .import_profile(&bundle, other_key.as_ref(), None)
+                .await
+                .expect_err("Expected error for incorrect pass key");
+            assert_eq!(err.kind(), ErrorKind::WrongPassKey);
+
+            let imported = target
+                .import_profile(&bundle, pass_key.as_ref(), Some("moved".to_string()))
+                .await
+                .expect("Error importing profile");
+            assert_eq!(imported, "moved");
+
+            let mut conn = target
+                .session(Some(imported))
+                .await
+                .expect("Error starting session");
+            let rows = conn
+                .fetch_all("category", None, None, false)
+                .await
+                .expect("Error fetching entries");
+            assert_eq!(rows.len(), 10);
+            assert_eq!(rows[0].tags, tags.to_vec());
+            conn.set_namespace(Some("app".to_string()))
+                .expect("Error setting namespace");
+            assert!(conn
+                .fetch("category", "namespaced", false)
+                .await
+                .expect("Error fetching entry")
+                .is_some());
+            let entry = conn
+                .fetch_key("key", false)
+                .await
+                .expect("Error fetching key")
+                .expect("Key not found");
+            assert_eq!(
+                entry
+                    .load_local_key()
+                    .expect("Error loading key")
+                    .to_jwk_thumbprint(None)
+                    .unwrap(),
+                key.to_jwk_thumbprint(None).unwrap()
+            );
+            drop(conn);
+
+            // the imported records are checked against the store policy
+            let strict = init_db().await.with_policy(Arc::new(RequireOwnerTag));
+            let err = strict
+                .import_profile(&bundle, pass_key.as_ref(), None)
+                .await
+                .expect_err("Expected policy violation");
+            assert_eq!(err.kind(), ErrorKind::PolicyViolation);
+            // the new profile is removed
+            assert!(!strict
+                .remove_profile("tenant".to_string())
+                .await
+                .expect("Error removing profile"));
+        })
+    }
+
+    #[test]
+    fn scheduled_backup_retention() {
+        use aries_askar::{verify_backup, BackupPolicy, BackupSource, FileBackupDestination};
+        use std::time::Duration;
+
+        let dir = std::env::temp_dir().join(format!("askar-backups-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).expect("Error creating backup directory");
+
+        block_on(async {
+            let db = init_db().await;
+            let mut conn = db.session(None).await.expect("Error starting session");
+            conn.insert("category", "name", b"value", None, None)
+                .await
+                .expect("Error inserting entry");
+            drop(conn);
+
+            let pass_key = generate_raw_store_key(None).expect("Error creating raw key");
+            let mut policy = BackupPolicy {
+                interval: Duration::from_secs(3600),
+                destination: Arc::new(FileBackupDestination::new(&dir)),
+                retain_n: 1,
+                source: BackupSource::Database,
+            };
+            let first = db.run_backup(&policy).await.expect("Error running backup");
+            assert_eq!(first.artifacts.len(), 1);
+            assert!(dir.join(&first.artifacts[0]).exists());
+
+            std::thread::sleep(Duration::from_millis(2));
+            policy.source = BackupSource::Profiles {
+                profiles: vec![db.get_profile_name().to_string()],
+                method: StoreKeyMethod::RawKey,
+                pass_key: pass_key.clone(),
+            };
+            let second = db.run_backup(&policy).await.expect("Error running backup");
+            assert_eq!(second.removed, first.artifacts);
+            assert!(!dir.join(&first.artifacts[0]).exists());
+
+            let summary = verify_backup(dir.join(&second.artifacts[0]), pass_key.as_ref())
+                .await
+                .expect("Error verifying backup");
+            assert_eq!(summary.profile, db.get_profile_name());
+            assert_eq!(summary.records, 1);
+        });
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn fork_store() {
+        let fname = format!("sqlite-test-{}.db", uuid::Uuid::new_v4().to_string());
+        block_on(async {
+            let db = init_db().await;
+            let mut conn = db.session(None).await.expect("Error starting session");
+            conn.insert("category", "name", b"value", None, None)
+                .await
+                .expect("Error inserting entry");
+            drop(conn);
+
+            let err = db
+                .fork_to(":memory:")
+                .await
+                .expect_err("Expected fork error");
+            assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+            let fork = db.fork_to(&fname).await.expect("Error forking store");
+            let mut conn = fork.session(None).await.expect("Error starting session");
+            let entry = conn
+                .fetch("category", "name", false)
+                .await
+                .expect("Error fetching entry")
+                .expect("Entry not found");
+            assert_eq!(entry.value.as_ref(), b"value");
+            conn.replace("category", "name", b"changed", None, None)
+                .await
+                .expect("Error replacing entry");
+            conn.insert("category", "other", b"value", None, None)
+                .await
+                .expect("Error inserting entry");
+            drop(conn);
+
+            // the original store is not modified
+            let mut conn = db.session(None).await.expect("Error starting session");
+            let entry = conn
+                .fetch("category", "name", false)
+                .await
+                .expect("Error fetching entry")
+                .expect("Entry not found");
+            assert_eq!(entry.value.as_ref(), b"value");
+            assert!(conn
+                .fetch("category", "other", false)
+                .await
+                .expect("Error fetching entry")
+                .is_none());
+            drop(conn);
+
+            fork.close().await.expect("Error closing store");
+            SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .remove_backend()
+                .await
+                .expect("Error removing sqlite store");
+        })
+    }
+
+    #[test]
+    fn sync_changes() {
+        use aries_askar::{
+            kms::{KeyAlg, LocalKey},
+            sync::{apply_changes, changes_since, ChangeSet, ConflictPolicy},
+        };
+
+        block_on(async {
+            let source = init_db().await.with_change_tracking(true);
+            let replica = init_db().await.with_change_tracking(true);
+            let enc_key = LocalKey::generate("c20p".parse::<KeyAlg>().unwrap(), false)
+                .expect("Error creating key");
+            let sign_key = LocalKey::generate(KeyAlg::Ed25519, false).expect("Error creating key");
+
+            let mut conn = source.session(None).await.expect("Error starting session");
+            let tags = [EntryTag::Encrypted("owner".into(), "alice".into())];
+            for name in &["a", "b", "c"] {
+                conn.insert("category", name, b"value", Some(&tags[..]), None)
+                    .await
+                    .expect("Error inserting record");
+            }
+            drop(conn);
+
+            let changes = changes_since(&source, None, &["category"], None)
+                .await
+                .expect("Error collecting changes");
+            assert_eq!(changes.changes.len(), 3);
+            let sealed = changes
+                .seal(&enc_key, &sign_key)
+                .expect("Error sealing changes");
+            let opened =
+                ChangeSet::open(&sealed, &enc_key, &sign_key).expect("Error opening changes");
+            let applied = apply_changes(&replica, None, &opened, ConflictPolicy::Error, None)
+                .await
+                .expect("Error applying changes");
+            assert_eq!(applied.applied, 3);
+            let local_seq = applied.local_seq;
+
+            let mut conn = source.session(None).await.expect("Error starting session");
+            conn.remove("category", "a")
+                .await
+                .expect("Error removing record");
+            conn.replace("category", "b", b"updated", None, None)
+                .await
+                .expect("Error replacing record");
+            drop(conn);
+
+            let next = changes_since(&source, None, &["category"], changes.until)
+                .await
+                .expect("Error collecting changes");
+            assert_eq!(next.changes.len(), 2);
+            assert!(next.changes[0].removed);
+
+            // a local modification in the replica is a conflict
+            let mut conn = replica.session(None).await.expect("Error starting session");
+            conn.replace("category", "b", b"local", None, None)
+                .await
+                .expect("Error replacing record");
+            drop(conn);
+            let err = apply_changes(&replica, None, &next, ConflictPolicy::Error, local_seq)
+                .await
+                .expect_err("Expected conflict");
+            assert_eq!(err.kind(), ErrorKind::Conflict);
+            let applied = apply_changes(
+                &replica,
+                None,
+                &next,
+                ConflictPolicy::LastWriterWins,
+                local_seq,
+            )
+            .await
+            .expect("Error applying changes");
+            assert_eq!(applied.overwritten, 1);
+
+            let mut conn = replica.session(None).await.expect("Error starting session");
+            assert!(conn
+                .fetch("category", "a", false)
+                .await
+                .expect("Error fetching record")
+                .is_none());
+            let row = conn
+                .fetch("category", "b", false)
+                .await
+                .expect("Error fetching record")
+                .expect("Expected row");
+            assert_eq!(row.value, &b"updated"[..]);
+        })
+    }
+
+    #[test]
+    fn sync_collection_removal() {
+        use aries_askar::sync::changes_since;
+
+        block_on(async {
+            let source = init_db().await.with_change_tracking(true);
+            let mut conn = source.session(None).await.expect("Error starting session");
+            for name in &["a", "b", "c"] {
+                conn.insert("category", name, b"value", None, None)
+                    .await
+                    .expect("Error inserting record");
+            }
+            for name in &["a", "b"] {
+                conn.set_collection("category", name, Some("exchange"))
+                    .await
+                    .expect("Error setting collection");
+            }
+            drop(conn);
+            let changes = changes_since(&source, None, &["category"], None)
+                .await
+                .expect("Error collecting changes");
+
+            let mut conn = source.session(None).await.expect("Error starting session");
+            assert_eq!(
+                conn.remove_collection("exchange")
+                    .await
+                    .expect("Error removing collection"),
+                2
+            );
+            drop(conn);
+
+            let next = changes_since(&source, None, &["category"], changes.until)
+                .await
+                .expect("Error collecting changes");
+            let mut removed = next
+                .changes
+                .iter()
+                .filter(|change| change.removed)
+                .map(|change| change.name.as_str())
+                .collect::<Vec<_>>();
+            removed.sort();
+            assert_eq!(removed, vec!["a", "b"]);
+        })
+    }
+
+    #[derive(Debug)]
+    struct TenantKeys(PassKey<'static>);
+
+    impl TenantKeyProvider for TenantKeys {
+        fn tenant_key(&self, profile: &str) -> Result<Option<PassKey<'static>>, Error> {
+            if profile.starts_with("tenant") {
+                Ok(Some(self.0.clone()))
+            } else {
+                Ok(None)
+            }
+        }
+    }
+
+    #[test]
+    fn tenant_profile_keys() {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let fname = format!("sqlite-test-{}.db", uuid::Uuid::new_v4().to_string());
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        let tenant_key = generate_raw_store_key(None).expect("Error creating raw key");
+
+        block_on(async {
+            let store = SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .provision(StoreKeyMethod::RawKey, key.as_ref(), None, false)
+                .await
+                .expect("Error provisioning sqlite store")
+                .with_tenant_keys(Arc::new(TenantKeys(tenant_key.clone())));
+            let profile = store
+                .create_profile(Some("tenant-a".to_string()))
+                .await
+                .expect("Error creating profile");
+            let mut conn = store
+                .session(Some(profile.clone()))
+                .await
+                .expect("Error starting session");
+            conn.insert("category", "name", b"value", None, None)
+                .await
+                .expect("Error inserting record");
+            drop(conn);
+            store.close().await.expect("Error closing sqlite store");
+
+            // the tenant profile cannot be accessed without the tenant key
+            let mut store = SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .open(Some(StoreKeyMethod::RawKey), key.as_ref(), None)
+                .await
+                .expect("Error opening sqlite store");
+            let mut conn = store
+                .session(Some(profile.clone()))
+                .await
+                .expect("Error starting session");
+            let err = conn
+                .fetch("category", "name", false)
+                .await
+                .expect_err("Expected missing tenant key");
+            assert_eq!(err.kind(), ErrorKind::Encryption);
+            drop(conn);
+
+            // rekeying the store leaves the tenant profile key unchanged
+            let new_key = generate_raw_store_key(None).expect("Error creating raw key");
+            store
+                .rekey(StoreKeyMethod::RawKey, new_key.as_ref())
+                .await
+                .expect("Error rekeying store");
+            let store = store.with_tenant_keys(Arc::new(TenantKeys(tenant_key.clone())));
+            let mut conn = store
+                .session(Some(profile.clone()))
+                .await
+                .expect("Error starting session");
+            let found = conn
+                .fetch("category", "name", false)
+                .await
+                .expect("Error fetching record")
+                .expect("Record not found");
+            assert_eq!(found.value, &b"value"[..]);
+            drop(conn);
+
+            // the wrong tenant key is rejected
+            let other_key = generate_raw_store_key(None).expect("Error creating raw key");
+            let store = store.with_tenant_keys(Arc::new(TenantKeys(other_key)));
+            let mut conn = store
+                .session(Some(profile))
+                .await
+                .expect("Error starting session");
+            let err = conn
+                .fetch("category", "name", false)
+                .await
+                .expect_err("Expected invalid tenant key");
+            assert_eq!(err.kind(), ErrorKind::Encryption);
+            drop(conn);
+            store.close().await.expect("Error closing sqlite store");
+
+            SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .remove()
+                .await
+                .expect("Error removing sqlite store");
+        });
+    }
+
+    #[derive(Debug, Default)]
+    struct DestroyedKeys(Mutex<Vec<String>>);
+
+    #[derive(Debug)]
+    struct ShreddingTenantKeys(PassKey<'static>, Arc<DestroyedKeys>);
+
+    impl TenantKeyProvider for ShreddingTenantKeys {
+        fn tenant_key(&self, profile: &str) -> Result<Option<PassKey<'static>>, Error> {
+            TenantKeys(self.0.clone()).tenant_key(profile)
+        }
+
+        fn destroy_tenant_key(&self, profile: &str) -> Result<bool, Error> {
+            (self.1).0.lock().unwrap().push(profile.to_string());
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn shred_profile() {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        let tenant_key = generate_raw_store_key(None).expect("Error creating raw key");
+        let destroyed = Arc::new(DestroyedKeys::default());
+
+        block_on(async {
+            let store = SqliteStoreOptions::in_memory()
+                .provision(StoreKeyMethod::RawKey, key.as_ref(), None, false)
+                .await
+                .expect("Error provisioning sqlite store")
+                .with_tenant_keys(Arc::new(ShreddingTenantKeys(tenant_key, destroyed.clone())));
+            for profile in &["tenant-a", "local"] {
+                let profile = store
+                    .create_profile(Some(profile.to_string()))
+                    .await
+                    .expect("Error creating profile");
+                let mut conn = store
+                    .session(Some(profile))
+                    .await
+                    .expect("Error starting session");
+                conn.insert("category", "name", b"value", None, None)
+                    .await
+                    .expect("Error inserting record");
+            }
+
+            let record = store
+                .shred_profile("tenant-a".to_string())
+                .await
+                .expect("Error shredding profile")
+                .expect("Expected shredded profile");
+            assert_eq!(record.profile, "tenant-a");
+            assert!(record.is_unrecoverable());
+            assert_eq!(*destroyed.0.lock().unwrap(), vec!["tenant-a".to_string()]);
+            let report = store
+                .storage_report()
+                .await
+                .expect("Error fetching storage report");
+            assert!(report.profile("tenant-a").is_none());
+
+            // profiles wrapped by the store key remain readable from backups
+            let record = store
+                .shred_profile("local".to_string())
+                .await
+                .expect("Error shredding profile")
+                .expect("Expected shredded profile");
+            assert!(!record.tenant_key);
+            assert!(!record.is_unrecoverable());
+            assert_eq!(destroyed.0.lock().unwrap().len(), 1);
+
+            assert!(store
+                .shred_profile("local".to_string())
+                .await
+                .expect("Error shredding profile")
+                .is_none());
+        });
+    }
+
+    #[test]
+    fn unique_constraints() {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        let did_tags = |my_did: &str, their_did: &str| {
+            vec![
+                EntryTag::Plaintext("my_did".to_string(), my_did.to_string()),
+                EntryTag::Plaintext("their_did".to_string(), their_did.to_string()),
+            ]
+        };
+
+        block_on(async {
+            let store = SqliteStoreOptions::in_memory()
+                .provision(StoreKeyMethod::RawKey, key.as_ref(), None, false)
+                .await
+                .expect("Error provisioning sqlite store")
+                .with_unique_constraint(UniqueConstraint::new(
+                    "did_pair",
+                    "connection",
+                    vec!["my_did", "their_did"],
+                ));
+            let mut conn = store.session(None).await.expect("Error starting session");
+            conn.insert(
+                "connection",
+                "conn-1",
+                b"value",
+                Some(&did_tags("did:a", "did:b")),
+                None,
+            )
+            .await
+            .expect("Error inserting record");
+            let err = conn
+                .insert(
+                    "connection",
+                    "conn-2",
+                    b"value",
+                    Some(&did_tags("did:a", "did:b")),
+                    None,
+                )
+                .await
+                .expect_err("Expected duplicate error");
+            assert_eq!(err.kind(), ErrorKind::Duplicate);
+            assert!(conn
+                .fetch("connection", "conn-2", false)
+                .await
+                .expect("Error fetching record")
+                .is_none());
+
+            // other categories and partial tag sets are not constrained
+            conn.insert(
+                "other",
+                "conn-2",
+                b"value",
+                Some(&did_tags("did:a", "did:b")),
+                None,
+            )
+            .await
+            .expect("Error inserting record");
+            conn.insert(
+                "connection",
+                "conn-3",
+                b"value",
+                Some(&did_tags("did:a", "did:b")[..1]),
+                None,
+            )
+            .await
+            .expect("Error inserting record");
+
+            // replacing a record retains its own values
+            conn.replace(
+                "connection",
+                "conn-1",
+                b"updated",
+                Some(&did_tags("did:a", "did:b")),
+                None,
+            )
+            .await
+            .expect("Error replacing record");
+
+            // the values are released when the record is changed or removed
+            conn.replace(
+                "connection",
+                "conn-1",
+                b"updated",
+                Some(&did_tags("did:a", "did:c")),
+                None,
+            )
+            .await
+            .expect("Error replacing record");
+            conn.insert(
+                "connection",
+                "conn-2",
+                b"value",
+                Some(&did_tags("did:a", "did:b")),
+                None,
+            )
+            .await
+            .expect("Error inserting record");
+            conn.remove("connection", "conn-1")
+                .await
+                .expect("Error removing record");
+            conn.insert(
+                "connection",
+                "conn-4",
+                b"value",
+                Some(&did_tags("did:a", "did:c")),
+                None,
+            )
+            .await
+            .expect("Error inserting record");
+            let err = conn
+                .replace(
+                    "connection",
+                    "conn-4",
+                    b"value",
+                    Some(&did_tags("did:a", "did:b")),
+                    None,
+                )
+                .await
+                .expect_err("Expected duplicate error");
+            assert_eq!(err.kind(), ErrorKind::Duplicate);
+            // the failed replacement is rolled back
+            let entry = conn
+                .fetch("connection", "conn-4", false)
+                .await
+                .expect("Error fetching record")
+                .expect("Expected record");
+            assert!(entry.tags.contains(&EntryTag::Plaintext(
+                "their_did".to_string(),
+                "did:c".to_string()
+            )));
+        });
+    }
+
+    #[test]
+    fn profile_naming() {
+        use aries_askar::ProfileNameFormat;
+
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        block_on(async {
+            let store = SqliteStoreOptions::in_memory()
+                .provision(StoreKeyMethod::RawKey, key.as_ref(), None, false)
+                .await
+                .expect("Error provisioning sqlite store")
+                .with_profile_naming(Arc::new(ProfileNameFormat::Prefixed("tenant-".to_string())));
+            let profile = store
+                .create_profile(None)
+                .await
+                .expect("Error creating profile");
+            assert!(profile.starts_with("tenant-"));
+            store
+                .session(Some(profile))
+                .await
+                .expect("Error starting session");
+            let err = store
+                .create_profile(Some("other".to_string()))
+                .await
+                .expect_err("Expected invalid profile name");
+            assert_eq!(err.kind(), ErrorKind::Input);
+            let err = store
+                .session(Some("tenant-a b".to_string()))
+                .await
+                .expect_err("Expected invalid profile name");
+            assert_eq!(err.kind(), ErrorKind::Input);
+        });
+    }
+
+    #[test]
+    fn allowed_profiles() {
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        block_on(async {
+            let store = SqliteStoreOptions::in_memory()
+                .provision(StoreKeyMethod::RawKey, key.as_ref(), None, false)
+                .await
+                .expect("Error provisioning sqlite store")
+                .with_allowed_profiles(vec!["tenant-a"]);
+            store
+                .create_profile(Some("tenant-a".to_string()))
+                .await
+                .expect("Error creating profile");
+            store
+                .session(Some("tenant-a".to_string()))
+                .await
+                .expect("Error starting session");
+            let err = store
+                .session(None)
+                .await
+                .expect_err("Expected default profile to be forbidden");
+            assert_eq!(err.kind(), ErrorKind::Forbidden);
+            let err = store
+                .create_profile(Some("tenant-b".to_string()))
+                .await
+                .expect_err("Expected profile to be forbidden");
+            assert_eq!(err.kind(), ErrorKind::Forbidden);
+        });
+    }
+
+    #[test]
+    fn query_limits() {
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        block_on(async {
+            let store = SqliteStoreOptions::in_memory()
+                .provision(StoreKeyMethod::RawKey, key.as_ref(), None, false)
+                .await
+                .expect("Error provisioning sqlite store")
+                .with_query_limits(QueryLimits {
+                    max_depth: 2,
+                    max_clauses: 3,
+                });
+            let mut conn = store.session(None).await.expect("Error starting session");
+
+            // nested conjunctions are flattened before the limits are checked
+            let nested = TagFilter::all_of(vec![
+                TagFilter::is_eq("a", "1"),
+                TagFilter::all_of(vec![TagFilter::is_eq("b", "2"), TagFilter::is_eq("c", "3")]),
+            ]);
+            conn.count("category", Some(nested))
+                .await
+                .expect("Error counting records");
+
+            let wide = TagFilter::any_of(vec![
+                TagFilter::is_eq("a", "1"),
+                TagFilter::is_eq("b", "2"),
+                TagFilter::is_eq("c", "3"),
+                TagFilter::is_eq("d", "4"),
+            ]);
+            let err = conn
+                .fetch_all("category", Some(wide), None, false)
+                .await
+                .expect_err("Expected query to be rejected");
+            assert_eq!(err.kind(), ErrorKind::QueryTooComplex);
+
+            let deep = TagFilter::not(TagFilter::any_of(vec![
+                TagFilter::is_eq("a", "1"),
+                TagFilter::not(TagFilter::is_eq("b", "2")),
+            ]));
+            let err = store
+                .scan(None, "category".to_string(), Some(deep), None, None)
+                .await
+                .expect_err("Expected query to be rejected");
+            assert_eq!(err.kind(), ErrorKind::QueryTooComplex);
+        });
+    }
+
+    #[test]
+    fn pass_key_policy() {
+        let policy = Arc::new(
+            PassKeyPolicy::new()
+                .min_length(12)
+                .deny(vec!["password1234"]),
+        );
+        block_on(async {
+            let err = SqliteStoreOptions::in_memory()
+                .with_pass_key_policy(policy.clone())
+                .provision(StoreKeyMethod::default(), "secret".into(), None, false)
+                .await
+                .expect_err("Expected weak pass key to be rejected");
+            assert_eq!(err.kind(), ErrorKind::WeakPassKey);
+
+            // raw keys are not checked against the policy
+            let key = generate_raw_store_key(None).expect("Error creating raw key");
+            let mut store = SqliteStoreOptions::in_memory()
+                .with_pass_key_policy(policy)
+                .provision(StoreKeyMethod::RawKey, key.as_ref(), None, false)
+                .await
+                .expect("Error provisioning sqlite store");
+            let err = store
+                .rekey(StoreKeyMethod::default(), "Password1234".into())
+                .await
+                .expect_err("Expected denied pass key to be rejected");
+            assert_eq!(err.kind(), ErrorKind::WeakPassKey);
+        });
+    }
+
+    #[test]
+    fn unlock_provider() {
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        let provider = move |reason: UnlockReason| {
+            let key = key.clone();
+            async move {
+                match reason {
+                    UnlockReason::Provision => Ok(key),
+                    _ => Err(Error::from(ErrorKind::Input)),
+                }
+            }
+        };
+        block_on(async {
+            let mut store = SqliteStoreOptions::in_memory()
+                .with_unlock_provider(Arc::new(provider))
+                .provision(StoreKeyMethod::RawKey, PassKey::empty(), None, false)
+                .await
+                .expect("Error provisioning sqlite store");
+            let err = store
+                .rekey(StoreKeyMethod::RawKey, PassKey::empty())
+                .await
+                .expect_err("Expected cancelled unlock to abort the rekey");
+            assert_eq!(err.kind(), ErrorKind::Input);
+        });
+    }
+
+    #[cfg(feature = "raw_query")]
+    #[test]
+    fn raw_query() {
+        use aries_askar::{ProfileId, RawValue};
+
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        block_on(async {
+            let store = SqliteStoreOptions::in_memory()
+                .provision(StoreKeyMethod::RawKey, key.as_ref(), None, false)
+                .await
+                .expect("Error provisioning sqlite store");
+            let mut conn = store.session(None).await.expect("Error starting session");
+            conn.insert(
+                "category",
+                "name",
+                b"value",
+                Some(&[EntryTag::Plaintext("plain".to_string(), "a".to_string())]),
+                None,
+            )
+            .await
+            .expect("Error inserting record");
+            drop(conn);
+
+            let decryptor = store
+                .raw_decryptor(None)
+                .await
+                .expect("Error resolving profile key");
+            let profile_id = match decryptor.profile_id() {
+                ProfileId::Serial(id) => id,
+                _ => panic!("Expected a serial profile identifier"),
+            };
+            let rows = store
+                .raw_query(
+                    "SELECT i.category, i.name, i.value, t.name AS tag_name, t.value AS tag_value
+                    FROM items i JOIN items_tags t ON t.item_id = i.id
+                    WHERE i.profile_id = $1",
+                    vec![RawValue::Integer(profile_id)],
+                )
+                .await
+                .expect("Error running raw query");
+            assert_eq!(rows.len(), 1);
+            let row = &rows[0];
+            let column = |name: &str| row.get_named(name).and_then(RawValue::as_bytes).unwrap();
+            let category = decryptor.decrypt_category(column("category")).unwrap();
+            let name = decryptor.decrypt_name(column("name")).unwrap();
+            assert_eq!((category.as_str(), name.as_str()), ("category", "name"));
+            let value = decryptor
+                .decrypt_value(&category, &name, column("value"))
+                .unwrap();
+            assert_eq!(value, &b"value"[..]);
+            assert_eq!(
+                decryptor.decrypt_tag_name(column("tag_name")).unwrap(),
+                "plain"
+            );
+            assert_eq!(
+                decryptor
+                    .decrypt_tag_value(column("tag_value"), true)
+                    .unwrap(),
+                "a"
+            );
+
+            let err = store
+                .raw_query("SELECT * FROM config", vec![])
+                .await
+                .expect_err("Expected config table to be inaccessible");
+            assert_eq!(err.kind(), ErrorKind::Forbidden);
+            let err = store
+                .raw_query("DELETE FROM items", vec![])
+                .await
+                .expect_err("Expected write to be rejected");
+            assert_eq!(err.kind(), ErrorKind::Input);
+
+            // the connection used for the raw query may be reused for writes
+            let mut conn = store.session(None).await.expect("Error starting session");
+            conn.insert("category", "other", b"value", None, None)
+                .await
+                .expect("Error inserting record");
+        });
+    }
+
+    #[test]
+    fn provision_from_str() {
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+
+        block_on(async {
+            let db_url = "sqlite://:memory:";
+            let _db = db_url
+                .provision_backend(StoreKeyMethod::RawKey, key.as_ref(), None, false)
+                .await
+                .expect("Error provisioning store");
+        });
+
+        block_on(async {
+            let db_url = "not-sqlite://test-db";
+            let _db = db_url
                 .provision_backend(StoreKeyMethod::RawKey, key.as_ref(), None, false)
                 .await
                 .expect_err("Expected provision failure");
         });
     }
+
+    #[cfg(feature = "any")]
+    #[derive(Debug, Default)]
+    struct PanickingKeyCache {
+        inner: MemoryKeyCache,
+        panic: AtomicBool,
+    }
+
+    impl ProfileKeyCache for PanickingKeyCache {
+        fn get(&self, profile: &str) -> Option<(ProfileId, CachedProfileKey)> {
+            if self.panic.load(Ordering::SeqCst) {
+                panic!("Key cache failure");
+            }
+            self.inner.get(profile)
+        }
+
+        fn insert(&self, profile: String, id: ProfileId, key: CachedProfileKey) -> usize {
+            self.inner.insert(profile, id, key)
+        }
+
+        fn remove(&self, profile: &str) -> bool {
+            self.inner.remove(profile)
+        }
+
+        fn clear(&self) -> usize {
+            self.inner.clear()
+        }
+    }
+
+    #[test]
+    fn poisoned_session() {
+        use futures_lite::FutureExt;
+        use std::panic::AssertUnwindSafe;
+
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        let cache = Arc::new(PanickingKeyCache::default());
+        block_on(async {
+            let db = SqliteStoreOptions::in_memory()
+                .with_key_cache(cache.clone())
+                .provision(StoreKeyMethod::RawKey, key, None, false)
+                .await
+                .expect("Error provisioning sqlite store");
+
+            let mut txn = db
+                .transaction(None)
+                .await
+                .expect("Error starting transaction");
+            cache.panic.store(true, Ordering::SeqCst);
+            let result = AssertUnwindSafe(txn.insert("category", "name", b"value", None, None))
+                .catch_unwind()
+                .await;
+            assert!(result.is_err());
+            cache.panic.store(false, Ordering::SeqCst);
+
+            assert!(txn.is_poisoned());
+            let err = txn
+                .insert("category", "name", b"value", None, None)
+                .await
+                .expect_err("Expected error");
+            assert_eq!(err.kind(), ErrorKind::Poisoned);
+            let err = txn.commit().await.expect_err("Expected error");
+            assert_eq!(err.kind(), ErrorKind::Poisoned);
+
+            // the store remains usable
+            let mut conn = db.session(None).await.expect("Error starting session");
+            conn.insert("category", "name", b"value", None, None)
+                .await
+                .expect("Error inserting entry");
+            assert_eq!(
+                conn.count("category", None)
+                    .await
+                    .expect("Error counting entries"),
+                1
+            );
+        });
+    }
+
+    #[test]
+    fn store_manager_evict() {
+        use aries_askar::{StoreManager, TenantConfig};
+
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        let fnames = [
+            format!("sqlite-test-{}.db", uuid::Uuid::new_v4().to_string()),
+            format!("sqlite-test-{}.db", uuid::Uuid::new_v4().to_string()),
+        ];
+
+        block_on(async {
+            let manager = StoreManager::new(1);
+            for (idx, fname) in fnames.iter().enumerate() {
+                let store = SqliteStoreOptions::new(fname.as_str())
+                    .expect("Error initializing sqlite store options")
+                    .provision_backend(StoreKeyMethod::RawKey, key.as_ref(), None, false)
+                    .await
+                    .expect("Error provisioning sqlite store");
+                store.close().await.expect("Error closing sqlite store");
+                manager
+                    .register(
+                        format!("tenant-{}", idx),
+                        TenantConfig::new(
+                            format!("sqlite://{}", fname),
+                            Some(StoreKeyMethod::RawKey),
+                            key.as_ref(),
+                        ),
+                    )
+                    .await
+                    .expect("Error registering tenant");
+            }
+            assert_eq!(manager.open_count().await, 0);
+
+            let err = manager.get("unknown").await.expect_err("Expected error");
+            assert_eq!(err.kind(), ErrorKind::NotFound);
+
+            let store = manager.get("tenant-0").await.expect("Error opening store");
+            let mut conn = store.session(None).await.expect("Error starting session");
+            conn.insert("category", "name", b"value", None, None)
+                .await
+                .expect("Error inserting entry");
+            drop(conn);
+            drop(store);
+            assert!(manager.is_open("tenant-0").await);
+
+            // opening a second store closes the least recently used
+            let store = manager.get("tenant-1").await.expect("Error opening store");
+            assert_eq!(manager.open_count().await, 1);
+            assert!(!manager.is_open("tenant-0").await);
+
+            // stores in use are not closed
+            let store0 = manager.get("tenant-0").await.expect("Error opening store");
+            assert_eq!(manager.open_count().await, 2);
+            let mut conn = store0.session(None).await.expect("Error starting session");
+            assert!(conn
+                .fetch("category", "name", false)
+                .await
+                .expect("Error fetching entry")
+                .is_some());
+            drop(conn);
+            drop(store);
+            drop(store0);
+
+            manager.close_all().await.expect("Error closing stores");
+            assert_eq!(manager.open_count().await, 0);
+            assert!(manager
+                .unregister("tenant-0")
+                .await
+                .expect("Error removing tenant"));
+
+            for fname in fnames.iter() {
+                SqliteStoreOptions::new(fname.as_str())
+                    .expect("Error initializing sqlite store options")
+                    .remove_backend()
+                    .await
+                    .expect("Error removing sqlite store");
+            }
+        });
+    }
+
+    #[test]
+    fn maintenance_mode_handles() {
+        use aries_askar::AccessMode;
+        use std::time::Duration;
+
+        let fname = format!("sqlite-test-{}.db", uuid::Uuid::new_v4().to_string());
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        let key2 = generate_raw_store_key(None).expect("Error creating raw key");
+
+        block_on(async {
+            let mut store1 = SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .provision(StoreKeyMethod::RawKey, key.as_ref(), None, false)
+                .await
+                .expect("Error provisioning sqlite store");
+            let store2 = SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .open(Some(StoreKeyMethod::RawKey), key.as_ref(), None)
+                .await
+                .expect("Error opening sqlite store");
+
+            let mut conn = store2.session(None).await.expect("Error starting session");
+            conn.insert("category", "name", b"value", None, None)
+                .await
+                .expect("Error inserting entry");
+
+            // a session opened by another handle observes the new mode once
+            // the mode cached by its handle has expired
+            store1
+                .set_maintenance_mode(AccessMode::ReadOnly)
+                .await
+                .expect("Error setting maintenance mode");
+            std::thread::sleep(Duration::from_millis(1100));
+            let err = conn
+                .insert("category", "other", b"value", None, None)
+                .await
+                .expect_err("Expected error");
+            assert_eq!(err.kind(), ErrorKind::Forbidden);
+            drop(conn);
+
+            let err = store1
+                .rekey(StoreKeyMethod::RawKey, key2.as_ref())
+                .await
+                .expect_err("Expected error");
+            assert_eq!(err.kind(), ErrorKind::Forbidden);
+
+            store1
+                .set_maintenance_mode(AccessMode::ReadWrite)
+                .await
+                .expect("Error setting maintenance mode");
+            std::thread::sleep(Duration::from_millis(1100));
+            let mut conn = store2.session(None).await.expect("Error starting session");
+            conn.insert("category", "other", b"value", None, None)
+                .await
+                .expect("Error inserting entry");
+            drop(conn);
+
+            store2.close().await.expect("Error closing store");
+            store1.close().await.expect("Error closing store");
+            SqliteStoreOptions::new(fname.as_str())
+                .expect("Error initializing sqlite store options")
+                .remove_backend()
+                .await
+                .expect("Error removing sqlite store");
+        });
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite_uuid {
+    use aries_askar::backend::sqlite::{SqliteStore, SqliteStoreOptions};
+    use aries_askar::{generate_raw_store_key, Store, StoreKeyMethod};
+
+    async fn init_db() -> Store<SqliteStore> {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        SqliteStoreOptions::new("sqlite://:memory:?id_strategy=uuid")
+            .expect("Error initializing sqlite store options")
+            .provision(StoreKeyMethod::RawKey, key, None, false)
+            .await
+            .expect("Error provisioning sqlite store")
+    }
+
+    backend_tests!(init_db());
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite_extended_nonce {
+    use aries_askar::backend::sqlite::{SqliteStore, SqliteStoreOptions};
+    use aries_askar::{generate_raw_store_key, Store, StoreKeyMethod};
+
+    async fn init_db() -> Store<SqliteStore> {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        SqliteStoreOptions::new("sqlite://:memory:?nonce_strategy=extended")
+            .expect("Error initializing sqlite store options")
+            .provision(StoreKeyMethod::RawKey, key, None, false)
+            .await
+            .expect("Error provisioning sqlite store")
+    }
+
+    backend_tests!(init_db());
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite_history {
+    use aries_askar::backend::sqlite::{SqliteStore, SqliteStoreOptions};
+    use aries_askar::{generate_raw_store_key, Store, StoreKeyMethod};
+
+    async fn init_db() -> Store<SqliteStore> {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        SqliteStoreOptions::new("sqlite://:memory:?history=1")
+            .expect("Error initializing sqlite store options")
+            .provision(StoreKeyMethod::RawKey, key, None, false)
+            .await
+            .expect("Error provisioning sqlite store")
+    }
+
+    backend_tests!(init_db());
+
+    #[test]
+    fn history_fetch_at() {
+        block_on(async {
+            let db = init_db().await;
+            super::utils::db_history_fetch_at(&db).await;
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite_checksum {
+    use aries_askar::backend::sqlite::{SqliteStore, SqliteStoreOptions};
+    use aries_askar::{generate_raw_store_key, Store, StoreKeyMethod};
+
+    async fn init_db() -> Store<SqliteStore> {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        SqliteStoreOptions::new("sqlite://:memory:?value_checksum=1")
+            .expect("Error initializing sqlite store options")
+            .provision(StoreKeyMethod::RawKey, key, None, false)
+            .await
+            .expect("Error provisioning sqlite store")
+    }
+
+    backend_tests!(init_db());
+
+    #[test]
+    fn verify_integrity() {
+        block_on(async {
+            let db = init_db().await;
+            let mut conn = db.session(None).await.expect("Error starting session");
+            for idx in 0..40 {
+                conn.insert("category", &format!("name-{}", idx), b"value", None, None)
+                    .await
+                    .expect("Error inserting entry");
+            }
+            conn.replace("category", "name-0", b"replaced", None, None)
+                .await
+                .expect("Error replacing entry");
+            drop(conn);
+
+            let report = db
+                .verify_integrity(None)
+                .await
+                .expect("Error verifying integrity");
+            assert!(report.is_ok());
+            assert_eq!(report.checked, 40);
+            assert_eq!(report.verified, 40);
+            assert_eq!(report.unverified, 0);
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite_coalesced {
+    use std::sync::Arc;
+
+    use aries_askar::backend::sqlite::{SqliteStore, SqliteStoreOptions};
+    use aries_askar::{generate_raw_store_key, ErrorKind, Store, StoreKeyMethod};
+
+    async fn init_db() -> Store<SqliteStore> {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let key = generate_raw_store_key(None).expect("Error creating raw key");
+        SqliteStoreOptions::new(
+            "sqlite://:memory:?write_coalesce_ms=5&write_coalesce_ops=16&max_connections=2",
+        )
+        .expect("Error initializing sqlite store options")
+        .provision(StoreKeyMethod::RawKey, key, None, false)
+        .await
+        .expect("Error provisioning sqlite store")
+    }
+
+    backend_tests!(init_db());
+
+    #[test]
+    fn coalesced_writes() {
+        let db = Arc::new(block_on(init_db()));
+        // more concurrent sessions than pool connections
+        let workers = (0..8)
+            .map(|worker| {
+                let db = db.clone();
+                std::thread::spawn(move || {
+                    block_on(async move {
+                        let mut conn = db.session(None).await.expect("Error starting session");
+                        for idx in 0..5 {
+                            conn.insert(
+                                "category",
+                                &format!("name-{}-{}", worker, idx),
+                                b"value",
+                                None,
+                                None,
+                            )
+                            .await
+                            .expect("Error inserting entry");
+                        }
+                        // a failed update does not affect the others in its batch
+                        let err = conn
+                            .insert(
+                                "category",
+                                &format!("name-{}-0", worker),
+                                b"value",
+                                None,
+                                None,
+                            )
+                            .await
+                            .expect_err("Expected duplicate error");
+                        assert_eq!(err.kind(), ErrorKind::Duplicate);
+                        conn.remove("category", &format!("name-{}-4", worker))
+                            .await
+                            .expect("Error removing entry");
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        for worker in workers {
+            worker.join().expect("Error joining worker thread");
+        }
+
+        block_on(async {
+            let mut conn = db.session(None).await.expect("Error starting session");
+            assert_eq!(
+                conn.count("category", None)
+                    .await
+                    .expect("Error counting entries"),
+                32
+            );
+            let entry = conn
+                .fetch("category", "name-3-2", false)
+                .await
+                .expect("Error fetching entry")
+                .expect("Entry not found");
+            assert_eq!(entry.value.as_ref(), b"value");
+        })
+    }
 }
 
 #[cfg(feature = "pg_test")]
@@ -320,3 +2318,35 @@ mod postgres {
 
     backend_tests!(init_db());
 }
+
+#[cfg(feature = "pg_test")]
+mod postgres_soft_delete {
+    use aries_askar::backend::postgres::test_db::TestDB;
+
+    async fn init_db() -> TestDB {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let uri = std::env::var("POSTGRES_URL").expect("'POSTGRES_URL' must be defined");
+        let sep = if uri.contains('?') { '&' } else { '?' };
+        TestDB::provision_with(&format!("{}{}soft_delete=1", uri, sep))
+            .await
+            .expect("Error provisioning postgres test database")
+    }
+
+    backend_tests!(init_db());
+}
+
+#[cfg(feature = "pg_test")]
+mod postgres_partitioned {
+    use aries_askar::backend::postgres::test_db::TestDB;
+
+    async fn init_db() -> TestDB {
+        env_logger::builder().is_test(true).try_init().unwrap_or(());
+        let uri = std::env::var("POSTGRES_URL").expect("'POSTGRES_URL' must be defined");
+        let sep = if uri.contains('?') { '&' } else { '?' };
+        TestDB::provision_with(&format!("{}{}partitions=4&history=1", uri, sep))
+            .await
+            .expect("Error provisioning postgres test database")
+    }
+
+    backend_tests!(init_db());
+}
@@ -0,0 +1,89 @@
+#![cfg(feature = "test_utils")]
+
+use aries_askar::{future::block_on, test_utils::StoreFixture, TagFilter};
+
+const FIXTURE: &'static str = r#"{
+    "seed": "test fixture seed",
+    "profiles": [
+        {
+            "entries": [
+                {"category": "cat", "name": "one", "value": "v1", "tags": {"t1": "a", "~t2": "b"}},
+                {"category": "cat", "name": "two", "value": "v2", "tags": {"t1": ["a", "c"]}}
+            ],
+            "keys": [
+                {"name": "signing", "alg": "ed25519", "metadata": "meta", "tags": {"purpose": "test"}}
+            ]
+        },
+        {
+            "name": "other",
+            "entries": [
+                {"category": "cat", "name": "three", "value": "v3"}
+            ]
+        }
+    ]
+}"#;
+
+#[test]
+fn provision_fixture() {
+    block_on(async {
+        let fixture = StoreFixture::from_json(FIXTURE).expect("Error parsing fixture");
+        let store = fixture
+            .provision_memory()
+            .await
+            .expect("Error provisioning store");
+
+        let mut session = store.session(None).await.expect("Error starting session");
+        assert_eq!(
+            session
+                .count("cat", Some(TagFilter::is_eq("t1", "a")))
+                .await
+                .expect("Error performing count"),
+            2
+        );
+        let key = session
+            .fetch_key("signing", false)
+            .await
+            .expect("Error fetching key")
+            .expect("Expected key");
+        assert_eq!(key.metadata(), Some("meta"));
+        let jwk = key
+            .load_local_key()
+            .expect("Error loading key")
+            .to_jwk_public(None)
+            .expect("Error encoding key");
+        drop(session);
+
+        let mut session = store
+            .session(Some("other".to_string()))
+            .await
+            .expect("Error starting session");
+        assert!(session
+            .fetch("cat", "three", false)
+            .await
+            .expect("Error fetching entry")
+            .is_some());
+        drop(session);
+
+        // the same seed produces the same keys
+        let (store2, path) = fixture
+            .provision_temp_file()
+            .await
+            .expect("Error provisioning store");
+        let mut session = store2.session(None).await.expect("Error starting session");
+        let key2 = session
+            .fetch_key("signing", false)
+            .await
+            .expect("Error fetching key")
+            .expect("Expected key");
+        assert_eq!(
+            key2.load_local_key()
+                .expect("Error loading key")
+                .to_jwk_public(None)
+                .expect("Error encoding key"),
+            jwk
+        );
+        drop(session);
+        store2.close().await.expect("Error closing store");
+        std::fs::remove_file(path).ok();
+    })
+}
@@ -1,4 +1,21 @@
-use aries_askar::{Backend, Entry, EntryTag, ErrorKind, Store, TagFilter};
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+
+use aries_askar::{
+    crypto::{
+        buffer::SecretBytes,
+        jwk::{KeyOps, KeyOpsSet},
+    },
+    future::block_on,
+    kms::{KeyAlg, LocalKey},
+    AccessMode, Backend, Durability, Entry, EntryExpiry, EntryKind, EntryLink, EntryOperation,
+    EntrySeq, EntryTag, ErrorKind, ExportFilter, HistoryFilter, MaintenanceMode, PlaintextExport,
+    Projection, ScanCheckpoint, SessionStats, Store, StoreTuning, TagFilter,
+};
+use futures_lite::{
+    future::{poll_once, yield_now},
+    stream::StreamExt,
+};
 
 const ERR_PROFILE: &'static str = "Error creating profile";
 const ERR_SESSION: &'static str = "Error starting session";
@@ -10,11 +27,16 @@ const ERR_REQ_ROW: &'static str = "Expected row";
 const ERR_REQ_ERR: &'static str = "Expected error";
 const ERR_INSERT: &'static str = "Error inserting test row";
 const ERR_REPLACE: &'static str = "Error replacing test row";
+const ERR_REMOVE: &'static str = "Error removing test row";
 const ERR_REMOVE_ALL: &'static str = "Error removing test rows";
+const ERR_SEARCH: &'static str = "Error performing search";
 const ERR_SCAN: &'static str = "Error starting scan";
 const ERR_SCAN_NEXT: &'static str = "Error fetching scan rows";
+const ERR_FETCH_AT: &'static str = "Error fetching historical test row";
+const ERR_HISTORY: &'static str = "Error fetching test row history";
+const ERR_INSERT_KEY: &'static str = "Error inserting key";
+const ERR_FETCH_KEY: &'static str = "Error fetching key";
 // const ERR_CREATE_KEYPAIR: &'static str = "Error creating keypair";
-// const ERR_FETCH_KEY: &'static str = "Error fetching key";
 // const ERR_SIGN: &'static str = "Error signing message";
 // const ERR_VERIFY: &'static str = "Error verifying signature";
 
@@ -78,6 +100,397 @@ pub async fn db_insert_fetch<DB: Backend>(db: &Store<DB>) {
     assert_eq!(rows[0], test_row);
 }
 
+pub async fn db_fetch_into<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    conn.insert("category", "name", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+
+    let mut buffer = SecretBytes::from_slice(b"previous contents");
+    let found = conn
+        .fetch_into("category", "name", &mut buffer)
+        .await
+        .expect(ERR_FETCH);
+    assert!(found);
+    assert_eq!(buffer, &b"value"[..]);
+
+    let found = conn
+        .fetch_into("category", "missing", &mut buffer)
+        .await
+        .expect(ERR_FETCH);
+    assert!(!found);
+    assert_eq!(buffer.len(), 0);
+}
+
+pub async fn db_exists<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    conn.insert("category", "name", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    assert!(conn.exists("category", "name").await.expect(ERR_FETCH));
+    assert!(!conn.exists("category", "missing").await.expect(ERR_FETCH));
+    assert!(!conn.exists("other", "name").await.expect(ERR_FETCH));
+
+    conn.remove("category", "name").await.expect(ERR_REMOVE);
+    assert!(!conn.exists("category", "name").await.expect(ERR_FETCH));
+}
+
+pub async fn db_fetch_by_id<DB: Backend>(db: &Store<DB>) {
+    let tags = vec![EntryTag::Encrypted("t1".to_string(), "v1".to_string())];
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    let id = conn
+        .insert_returning_id("category", "name", b"value", Some(tags.as_slice()), None)
+        .await
+        .expect(ERR_INSERT);
+    let other_id = conn
+        .insert_returning_id("category", "other", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    assert_ne!(id, other_id);
+
+    let row = conn
+        .fetch_by_id(id)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(row, Entry::new("category", "name", "value", tags));
+    let parsed = id
+        .to_string()
+        .parse()
+        .expect("Error parsing entry identity");
+    assert_eq!(conn.fetch_by_id(parsed).await.expect(ERR_FETCH), Some(row));
+
+    // the identity is not visible from another namespace
+    conn.set_namespace(Some("ns".to_string()))
+        .expect("Error setting namespace");
+    assert_eq!(conn.fetch_by_id(id).await.expect(ERR_FETCH), None);
+    conn.set_namespace(None).expect("Error setting namespace");
+
+    conn.remove("category", "name").await.expect(ERR_REMOVE);
+    assert_eq!(conn.fetch_by_id(id).await.expect(ERR_FETCH), None);
+    assert!(conn.fetch_by_id(other_id).await.expect(ERR_FETCH).is_some());
+}
+
+pub async fn db_insert_or_fetch<DB: Backend>(db: &Store<DB>) {
+    let tags = vec![EntryTag::Plaintext("t1".to_string(), "v1".to_string())];
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    let existing = conn
+        .insert_or_fetch("category", "name", b"value", Some(tags.as_slice()), None)
+        .await
+        .expect(ERR_INSERT);
+    assert_eq!(existing, None);
+
+    // the existing record is returned unchanged
+    let existing = conn
+        .insert_or_fetch("category", "name", b"other", None, None)
+        .await
+        .expect(ERR_INSERT);
+    assert_eq!(
+        existing,
+        Some(Entry::new("category", "name", "value", tags.clone()))
+    );
+    let row = conn
+        .fetch("category", "name", false)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(row, Entry::new("category", "name", "value", tags));
+
+    // the same applies within a transaction
+    drop(conn);
+    let mut txn = db.transaction(None).await.expect(ERR_TRANSACTION);
+    assert!(txn
+        .insert_or_fetch("category", "name", b"other", None, None)
+        .await
+        .expect(ERR_INSERT)
+        .is_some());
+    assert_eq!(
+        txn.insert_or_fetch("category", "name2", b"value", None, None)
+            .await
+            .expect(ERR_INSERT),
+        None
+    );
+    txn.commit().await.expect("Error committing transaction");
+}
+
+pub async fn db_search_terms<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    conn.insert_searchable("category", "a", b"value", None, &["red", "round"], None)
+        .await
+        .expect(ERR_INSERT);
+    conn.insert_searchable("category", "b", b"value", None, &["red", "square"], None)
+        .await
+        .expect(ERR_INSERT);
+    conn.insert_searchable("other", "c", b"value", None, &["red"], None)
+        .await
+        .expect(ERR_INSERT);
+
+    let found = conn
+        .search("category", &["red"], None)
+        .await
+        .expect(ERR_SEARCH);
+    assert_eq!(
+        found.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(),
+        vec!["a", "b"]
+    );
+    let found = conn
+        .search("category", &["red", "square", "red"], None)
+        .await
+        .expect(ERR_SEARCH);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].name, "b");
+    assert!(conn
+        .search("category", &["RED"], None)
+        .await
+        .expect(ERR_SEARCH)
+        .is_empty());
+    let err = conn
+        .search("category", &[], None)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Input);
+
+    conn.update_search_terms("category", "a", &["blue"])
+        .await
+        .expect(ERR_SEARCH);
+    assert_eq!(
+        conn.search("category", &["red"], None)
+            .await
+            .expect(ERR_SEARCH)
+            .len(),
+        1
+    );
+
+    // replacing a record clears its search terms
+    conn.replace("category", "b", b"new value", None, None)
+        .await
+        .expect(ERR_REPLACE);
+    assert!(conn
+        .search("category", &["red"], None)
+        .await
+        .expect(ERR_SEARCH)
+        .is_empty());
+
+    let err = conn
+        .update_search_terms("category", "missing", &["red"])
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+/// Expects a unique constraint on the `did` tag of the `connection` category
+pub async fn db_replace_unique_conflict<DB: Backend>(db: &Store<DB>) {
+    let did_tags = |did: &str| vec![EntryTag::Plaintext("did".to_string(), did.to_string())];
+
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    conn.insert("connection", "a", b"first", Some(&did_tags("did:a")), None)
+        .await
+        .expect(ERR_INSERT);
+    conn.insert("connection", "b", b"second", Some(&did_tags("did:b")), None)
+        .await
+        .expect(ERR_INSERT);
+    let err = conn
+        .replace(
+            "connection",
+            "b",
+            b"updated",
+            Some(&did_tags("did:a")),
+            None,
+        )
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Duplicate);
+    // the failed replacement leaves the record unmodified
+    let row = conn
+        .fetch("connection", "b", false)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(row.value, &b"second"[..]);
+    assert_eq!(row.tags, did_tags("did:b"));
+    drop(conn);
+
+    let mut txn = db.transaction(None).await.expect(ERR_TRANSACTION);
+    let err = txn
+        .replace(
+            "connection",
+            "b",
+            b"updated",
+            Some(&did_tags("did:a")),
+            None,
+        )
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Duplicate);
+    txn.commit().await.expect("Error committing transaction");
+
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    let row = conn
+        .fetch("connection", "b", false)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(row.value, &b"second"[..]);
+}
+
+pub async fn db_namespaces<DB: Backend>(db: &Store<DB>) {
+    let mut first = db.session(None).await.expect(ERR_SESSION);
+    first.set_namespace(Some("first".to_string())).unwrap();
+    let mut second = db.session(None).await.expect(ERR_SESSION);
+    second.set_namespace(Some("second".to_string())).unwrap();
+    let mut plain = db.session(None).await.expect(ERR_SESSION);
+
+    first
+        .insert("category", "name", b"first", None, None)
+        .await
+        .expect(ERR_INSERT);
+    second
+        .insert("category", "name", b"second", None, None)
+        .await
+        .expect(ERR_INSERT);
+    second
+        .insert("category", "other", b"second", None, None)
+        .await
+        .expect(ERR_INSERT);
+
+    let row = first
+        .fetch("category", "name", false)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(row.category, "category");
+    assert_eq!(row.value, b"first".to_vec());
+    assert!(plain
+        .fetch("category", "name", false)
+        .await
+        .expect(ERR_FETCH)
+        .is_none());
+    assert_eq!(second.count("category", None).await.expect(ERR_COUNT), 2);
+
+    // removals only affect the records of the session namespace
+    assert_eq!(
+        second
+            .remove_all("category", None)
+            .await
+            .expect(ERR_REMOVE_ALL),
+        2
+    );
+    assert_eq!(first.count("category", None).await.expect(ERR_COUNT), 1);
+
+    let rows = db
+        .scan_namespace(None, "first", "category".to_string(), None, None, None)
+        .await
+        .expect(ERR_SCAN)
+        .fetch_next()
+        .await
+        .expect(ERR_SCAN_NEXT)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].category, "category");
+
+    let err = first
+        .set_namespace(Some("".to_string()))
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Input);
+    let err = plain
+        .insert("first\0category", "name", b"value", None, None)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Input);
+}
+
+pub async fn db_scan_since<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    for name in &["a", "b", "c"] {
+        conn.insert("category", name, b"value", None, None)
+            .await
+            .expect(ERR_INSERT);
+    }
+    let first = conn
+        .fetch("category", "a", false)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW)
+        .seq()
+        .expect(ERR_REQ_ROW);
+    let seq_str = first.to_string();
+    assert_eq!(seq_str.parse::<EntrySeq>().expect(ERR_REQ_ROW), first);
+
+    // a replaced record moves to the end of the sequence
+    conn.replace("category", "a", b"updated", None, None)
+        .await
+        .expect(ERR_REPLACE);
+    drop(conn);
+
+    let rows = db
+        .scan_since(None, "category".to_string(), None, Some(first), None)
+        .await
+        .expect(ERR_SCAN)
+        .fetch_next()
+        .await
+        .expect(ERR_SCAN_NEXT)
+        .expect(ERR_REQ_ROW);
+    let names = rows.iter().map(|r| r.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["b", "c", "a"]);
+    assert!(rows.windows(2).all(|w| w[0].seq() < w[1].seq()));
+
+    let last = rows[2].seq();
+    let mut scan = db
+        .scan_since(None, "category".to_string(), None, last, None)
+        .await
+        .expect(ERR_SCAN);
+    assert!(scan.fetch_next().await.expect(ERR_SCAN_NEXT).is_none());
+}
+
+pub async fn db_replace_if_unmodified<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    let tags = vec![EntryTag::Encrypted("t1".to_string(), "a".to_string())];
+    conn.insert("category", "name", b"value", Some(tags.as_slice()), None)
+        .await
+        .expect(ERR_INSERT);
+
+    let fetched = conn
+        .fetch("category", "name", false)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW);
+    let hash = fetched.content_hash();
+
+    let prev_hash = conn
+        .replace_returning_hash("category", "name", b"value", Some(tags.as_slice()), None)
+        .await
+        .expect(ERR_REPLACE);
+    assert_eq!(prev_hash, hash);
+
+    // the contents are unchanged, so the replacement proceeds
+    let mut update = Entry::new("category", "name", "first", tags.clone());
+    let new_hash = conn
+        .replace_if_unmodified(&update, hash, None)
+        .await
+        .expect(ERR_REPLACE);
+
+    // a stale hash results in a conflict
+    update.value = b"second".to_vec().into();
+    let err = conn
+        .replace_if_unmodified(&update, hash, None)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Conflict);
+    let row = conn
+        .fetch("category", "name", false)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(row.value, b"first".to_vec());
+    assert_eq!(row.content_hash(), new_hash);
+
+    let err = conn
+        .replace_if_unmodified(&Entry::new("category", "missing", "v", vec![]), hash, None)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
 pub async fn db_insert_duplicate<DB: Backend>(db: &Store<DB>) {
     let test_row = Entry::new("category", "name", "value", Vec::new());
 
@@ -400,70 +813,246 @@ pub async fn db_scan<DB: Backend>(db: &Store<DB>) {
     assert_eq!(rows, None);
 }
 
-pub async fn db_remove_all<DB: Backend>(db: &Store<DB>) {
-    let test_rows = vec![
-        Entry::new(
-            "category",
-            "item1",
-            "value",
-            vec![
-                EntryTag::Encrypted("t1".to_string(), "del".to_string()),
-                EntryTag::Plaintext("t2".to_string(), "del".to_string()),
-            ],
-        ),
-        Entry::new(
-            "category",
-            "item2",
-            "value",
-            vec![
-                EntryTag::Encrypted("t1".to_string(), "del".to_string()),
-                EntryTag::Plaintext("t2".to_string(), "del".to_string()),
-            ],
-        ),
-        Entry::new(
-            "category",
-            "item3",
-            "value",
-            vec![
-                EntryTag::Encrypted("t1".to_string(), "keep".to_string()),
-                EntryTag::Plaintext("t2".to_string(), "keep".to_string()),
-            ],
-        ),
-    ];
-
+pub async fn db_scan_detached<DB: Backend>(db: &Store<DB>) {
     let mut conn = db.session(None).await.expect(ERR_SESSION);
+    for idx in 0..50 {
+        conn.insert("category", &format!("item{}", idx), b"value", None, None)
+            .await
+            .expect(ERR_INSERT);
+    }
+    drop(conn);
 
-    for test_row in test_rows.iter() {
-        conn.insert(
-            &test_row.category,
-            &test_row.name,
-            &test_row.value,
-            Some(test_row.tags.as_slice()),
-            None,
-        )
+    let scan = db
+        .scan(None, "category".to_string(), None, None, None)
         .await
-        .expect(ERR_INSERT);
+        .expect(ERR_SCAN);
+
+    // the scan does not borrow from the store instance
+    let count = std::thread::spawn(move || {
+        block_on(async move {
+            let mut scan = scan;
+            let mut count = 0;
+            while let Some(rows) = scan.fetch_next().await.expect(ERR_SCAN_NEXT) {
+                count += rows.len();
+            }
+            count
+        })
+    })
+    .join()
+    .expect("Error joining scan thread");
+    assert_eq!(count, 50);
+}
+
+pub async fn db_scan_large_records<DB: Backend>(db: &Store<DB>) {
+    let value = vec![7u8; 300_000];
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    for idx in 0..8 {
+        conn.insert("category", &format!("item{}", idx), &value, None, None)
+            .await
+            .expect(ERR_INSERT);
     }
+    drop(conn);
 
-    // could detect that a second transaction would block here?
-    // depends on the backend. just checking that no SQL errors occur for now.
-    let removed = conn
-        .remove_all(
-            "category",
-            Some(TagFilter::all_of(vec![
-                TagFilter::is_eq("t1", "del"),
-                TagFilter::is_eq("~t2", "del"),
-            ])),
-        )
+    let mut scan = db
+        .scan(None, "category".to_string(), None, None, None)
         .await
-        .expect(ERR_REMOVE_ALL);
-    assert_eq!(removed, 2);
+        .expect(ERR_SCAN);
+    let mut pages = 0;
+    let mut count = 0;
+    while let Some(rows) = scan.fetch_next().await.expect(ERR_SCAN_NEXT) {
+        // pages are shortened to bound the size of the buffered records
+        assert!(rows.len() < 8);
+        assert!(rows.iter().all(|row| row.value == value));
+        pages += 1;
+        count += rows.len();
+    }
+    assert!(pages > 1);
+    assert_eq!(count, 8);
 }
 
-// pub async fn db_keypair_create_fetch<DB: Backend>(db: &Store<DB>) {
-//     let mut conn = db.session(None).await.expect(ERR_SESSION);
-
-//     let metadata = "meta".to_owned();
+pub async fn db_scan_checkpoint<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    for idx in 0..30 {
+        conn.insert("category", &format!("item{}", idx), b"value", None, None)
+            .await
+            .expect(ERR_INSERT);
+    }
+    drop(conn);
+
+    let mut scan = db
+        .scan(None, "category".to_string(), None, None, Some(25))
+        .await
+        .expect(ERR_SCAN);
+    for idx in 0..5 {
+        let row = scan.next().await.expect(ERR_REQ_ROW).expect(ERR_SCAN_NEXT);
+        assert_eq!(row.name, format!("item{}", idx));
+    }
+    let token = scan
+        .checkpoint()
+        .expect("Expected scan checkpoint")
+        .to_token()
+        .expect("Error encoding scan checkpoint");
+    drop(scan);
+
+    let checkpoint = ScanCheckpoint::from_token(&token).expect("Error parsing scan checkpoint");
+    let mut scan = db.resume_scan(checkpoint).await.expect(ERR_SCAN);
+    let mut names = vec![];
+    while let Some(rows) = scan.fetch_next().await.expect(ERR_SCAN_NEXT) {
+        names.extend(rows.into_iter().map(|row| row.name));
+    }
+    // the remainder of the limit is applied to the resumed scan
+    assert_eq!(
+        names,
+        (5..25)
+            .map(|idx| format!("item{}", idx))
+            .collect::<Vec<_>>()
+    );
+}
+
+pub async fn db_custom_entry_kind<DB: Backend>(db: &Store<DB>) {
+    let kind = EntryKind::Custom(1);
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    conn.set_entry_kind(kind).expect("Error setting entry kind");
+    conn.insert("category", "name", b"custom", None, None)
+        .await
+        .expect(ERR_INSERT);
+    assert_eq!(conn.count("category", None).await.expect(ERR_COUNT), 1);
+
+    // generic records are stored separately
+    let mut items = db.session(None).await.expect(ERR_SESSION);
+    assert_eq!(items.count("category", None).await.expect(ERR_COUNT), 0);
+    items
+        .insert("category", "name", b"item", None, None)
+        .await
+        .expect(ERR_INSERT);
+
+    let row = conn
+        .fetch("category", "name", false)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(row.value, &b"custom"[..]);
+
+    let mut scan = db
+        .scan_kind(kind, None, "category".to_string(), None, None, None)
+        .await
+        .expect(ERR_SCAN);
+    let rows = scan.fetch_next().await.expect(ERR_SCAN_NEXT).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].value, &b"custom"[..]);
+
+    assert!(conn.set_entry_kind(EntryKind::Kms).is_err());
+}
+
+pub async fn db_scoped_session<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    conn.insert("allowed", "name", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    conn.insert("other", "name", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+
+    let mut scoped = db
+        .scoped_session(None, Some(vec!["allowed".to_string()]), false)
+        .await
+        .expect(ERR_SESSION);
+    assert!(scoped
+        .fetch("allowed", "name", false)
+        .await
+        .expect(ERR_FETCH)
+        .is_some());
+    let err = scoped
+        .fetch("other", "name", false)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Forbidden);
+    scoped
+        .replace("allowed", "name", b"updated", None, None)
+        .await
+        .expect(ERR_REPLACE);
+
+    let mut read_only = db
+        .scoped_session(None, None, true)
+        .await
+        .expect(ERR_SESSION);
+    assert_eq!(read_only.count("other", None).await.expect(ERR_COUNT), 1);
+    let err = read_only
+        .remove("other", "name")
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Forbidden);
+    let err = read_only
+        .remove_all("other", None)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Forbidden);
+}
+
+pub async fn db_remove_all<DB: Backend>(db: &Store<DB>) {
+    let test_rows = vec![
+        Entry::new(
+            "category",
+            "item1",
+            "value",
+            vec![
+                EntryTag::Encrypted("t1".to_string(), "del".to_string()),
+                EntryTag::Plaintext("t2".to_string(), "del".to_string()),
+            ],
+        ),
+        Entry::new(
+            "category",
+            "item2",
+            "value",
+            vec![
+                EntryTag::Encrypted("t1".to_string(), "del".to_string()),
+                EntryTag::Plaintext("t2".to_string(), "del".to_string()),
+            ],
+        ),
+        Entry::new(
+            "category",
+            "item3",
+            "value",
+            vec![
+                EntryTag::Encrypted("t1".to_string(), "keep".to_string()),
+                EntryTag::Plaintext("t2".to_string(), "keep".to_string()),
+            ],
+        ),
+    ];
+
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+
+    for test_row in test_rows.iter() {
+        conn.insert(
+            &test_row.category,
+            &test_row.name,
+            &test_row.value,
+            Some(test_row.tags.as_slice()),
+            None,
+        )
+        .await
+        .expect(ERR_INSERT);
+    }
+
+    // could detect that a second transaction would block here?
+    // depends on the backend. just checking that no SQL errors occur for now.
+    let removed = conn
+        .remove_all(
+            "category",
+            Some(TagFilter::all_of(vec![
+                TagFilter::is_eq("t1", "del"),
+                TagFilter::is_eq("~t2", "del"),
+            ])),
+        )
+        .await
+        .expect(ERR_REMOVE_ALL);
+    assert_eq!(removed, 2);
+}
+
+// pub async fn db_keypair_create_fetch<DB: Backend>(db: &Store<DB>) {
+//     let mut conn = db.session(None).await.expect(ERR_SESSION);
+
+//     let metadata = "meta".to_owned();
 //     let key_info = conn
 //         .create_keypair(KeyAlg::Ed25519, Some(&metadata), None, None)
 //         .await
@@ -654,6 +1243,176 @@ pub async fn db_session_drop<DB: Backend>(db: &Store<DB>) {
     assert_eq!(row, Some(test_row));
 }
 
+pub async fn db_session_timeout<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+
+    conn.set_timeout(Some(Duration::from_nanos(1)));
+    let err = conn.count("category", None).await.expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Busy);
+
+    // the session remains usable after a timeout
+    conn.set_timeout(None);
+    assert_eq!(conn.count("category", None).await.expect(ERR_COUNT), 0);
+}
+
+/// The maximum number of polls before a cancelled operation is expected to complete
+const MAX_CANCEL_POLLS: usize = 1000;
+
+/// Poll a future at most `polls` times, dropping it if it has not completed
+async fn poll_then_drop<F: Future>(fut: F, polls: usize) -> Option<F::Output> {
+    futures_lite::pin!(fut);
+    for _ in 0..polls {
+        if let Some(output) = poll_once(&mut fut).await {
+            return Some(output);
+        }
+        yield_now().await;
+    }
+    None
+}
+
+/// Check that no transaction or lock was left behind by a cancelled operation
+async fn check_unlocked<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.transaction(None).await.expect(ERR_TRANSACTION);
+    conn.insert("check", "name", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    conn.remove("check", "name").await.expect(ERR_REMOVE);
+    conn.commit().await.expect("Error committing transaction");
+}
+
+pub async fn db_session_cancel<DB: Backend>(db: &Store<DB>) {
+    for polls in 0..MAX_CANCEL_POLLS {
+        let mut conn = db.session(None).await.expect(ERR_SESSION);
+        let done = poll_then_drop(
+            async move {
+                conn.insert("category", "name", b"value", None, None)
+                    .await
+                    .expect(ERR_INSERT);
+            },
+            polls,
+        )
+        .await;
+
+        check_unlocked(db).await;
+        let mut conn = db.session(None).await.expect(ERR_SESSION);
+        let count = conn.count("category", None).await.expect(ERR_COUNT);
+        assert!(count <= 1);
+        conn.remove_all("category", None)
+            .await
+            .expect(ERR_REMOVE_ALL);
+        if done.is_some() {
+            assert_eq!(count, 1);
+            return;
+        }
+    }
+    panic!("Session operation did not complete");
+}
+
+pub async fn db_txn_cancel<DB: Backend>(db: &Store<DB>) {
+    for polls in 0..MAX_CANCEL_POLLS {
+        let mut conn = db.transaction(None).await.expect(ERR_TRANSACTION);
+        let done = poll_then_drop(
+            async move {
+                conn.insert("category", "name", b"value", None, None)
+                    .await
+                    .expect(ERR_INSERT);
+                conn.insert("category", "name2", b"value", None, None)
+                    .await
+                    .expect(ERR_INSERT);
+                conn.commit().await.expect("Error committing transaction");
+            },
+            polls,
+        )
+        .await;
+
+        check_unlocked(db).await;
+        let mut conn = db.session(None).await.expect(ERR_SESSION);
+        let count = conn.count("category", None).await.expect(ERR_COUNT);
+        if done.is_some() {
+            assert_eq!(count, 2);
+            return;
+        }
+        // a cancelled commit may or may not have been applied
+        assert!(count == 0 || count == 2);
+        conn.remove_all("category", None)
+            .await
+            .expect(ERR_REMOVE_ALL);
+    }
+    panic!("Transaction did not complete");
+}
+
+pub async fn db_atomic_cancel<DB: Backend>(db: &Store<DB>) {
+    let mut removed = false;
+    for polls in 0..MAX_CANCEL_POLLS {
+        let mut conn = db.session(None).await.expect(ERR_SESSION);
+        conn.insert("category", "name", b"value", None, None)
+            .await
+            .expect(ERR_INSERT);
+        conn.set_protected("category", "name", true)
+            .await
+            .expect("Error protecting record");
+        let done = poll_then_drop(conn.force_remove("category", "name"), polls).await;
+
+        // the session rolls back the abandoned removal before its next operation
+        let found = conn
+            .fetch("category", "name", false)
+            .await
+            .expect(ERR_FETCH)
+            .is_some();
+        let protected = conn
+            .is_protected("category", "name")
+            .await
+            .expect("Error checking protection");
+        assert_eq!(found, protected);
+        if found {
+            conn.force_remove("category", "name")
+                .await
+                .expect(ERR_REMOVE);
+        }
+        drop(conn);
+        check_unlocked(db).await;
+        if let Some(result) = done {
+            result.expect(ERR_REMOVE);
+            assert!(!found);
+            removed = true;
+            break;
+        }
+    }
+    assert!(removed, "Removal did not complete");
+
+    for polls in 0..MAX_CANCEL_POLLS {
+        let mut conn = db.session(None).await.expect(ERR_SESSION);
+        let done = poll_then_drop(
+            conn.insert_searchable("category", "name", b"value", None, &["red"], None),
+            polls,
+        )
+        .await;
+
+        let found = conn
+            .fetch("category", "name", false)
+            .await
+            .expect(ERR_FETCH)
+            .is_some();
+        let indexed = !conn
+            .search("category", &["red"], None)
+            .await
+            .expect(ERR_SEARCH)
+            .is_empty();
+        assert_eq!(found, indexed);
+        conn.remove_all("category", None)
+            .await
+            .expect(ERR_REMOVE_ALL);
+        drop(conn);
+        check_unlocked(db).await;
+        if let Some(result) = done {
+            result.expect(ERR_INSERT);
+            assert!(found);
+            return;
+        }
+    }
+    panic!("Insert did not complete");
+}
+
 pub async fn db_txn_commit<DB: Backend>(db: &Store<DB>) {
     let test_row = Entry::new("category", "name", "value", Vec::new());
 
@@ -680,6 +1439,89 @@ pub async fn db_txn_commit<DB: Backend>(db: &Store<DB>) {
     assert_eq!(row, Some(test_row));
 }
 
+pub async fn db_history_fetch_at<DB: Backend>(db: &Store<DB>) {
+    let test_row = Entry::new(
+        "category",
+        "name",
+        "value",
+        vec![EntryTag::Encrypted("t1".to_string(), "v1".to_string())],
+    );
+
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+
+    conn.insert(
+        &test_row.category,
+        &test_row.name,
+        &test_row.value,
+        Some(test_row.tags.as_slice()),
+        None,
+    )
+    .await
+    .expect(ERR_INSERT);
+
+    std::thread::sleep(Duration::from_millis(10));
+    let inserted_at = SystemTime::now();
+    std::thread::sleep(Duration::from_millis(10));
+
+    let mut replace_row = test_row.clone();
+    replace_row.value = "new value".into();
+    replace_row.tags = vec![EntryTag::Plaintext("t2".to_string(), "v2".to_string())];
+    conn.replace(
+        &replace_row.category,
+        &replace_row.name,
+        &replace_row.value,
+        Some(replace_row.tags.as_slice()),
+        None,
+    )
+    .await
+    .expect(ERR_REPLACE);
+
+    std::thread::sleep(Duration::from_millis(10));
+    let replaced_at = SystemTime::now();
+    std::thread::sleep(Duration::from_millis(10));
+
+    conn.remove(&test_row.category, &test_row.name)
+        .await
+        .expect(ERR_REMOVE);
+
+    let row = conn
+        .fetch_at(&test_row.category, &test_row.name, inserted_at)
+        .await
+        .expect(ERR_FETCH_AT)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(row, test_row);
+
+    let row = conn
+        .fetch_at(&test_row.category, &test_row.name, replaced_at)
+        .await
+        .expect(ERR_FETCH_AT)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(row, replace_row);
+
+    let row = conn
+        .fetch_at(&test_row.category, &test_row.name, SystemTime::now())
+        .await
+        .expect(ERR_FETCH_AT);
+    assert!(row.is_none());
+
+    let history = conn
+        .fetch_history(&test_row.category, &test_row.name, None)
+        .await
+        .expect(ERR_HISTORY);
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].entry, replace_row);
+    assert_eq!(history[1].entry, test_row);
+    assert_eq!(history[1].valid_to, Some(history[0].valid_from));
+    assert!(history[0].valid_to.is_some());
+
+    let history = conn
+        .fetch_history(&test_row.category, &test_row.name, Some(1))
+        .await
+        .expect(ERR_HISTORY);
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].entry, replace_row);
+}
+
 pub async fn db_txn_fetch_for_update<DB: Backend>(db: &Store<DB>) {
     let test_row = Entry::new("category", "name", "value", Vec::new());
 
@@ -713,3 +1555,1752 @@ pub async fn db_txn_fetch_for_update<DB: Backend>(db: &Store<DB>) {
 
     conn.commit().await.expect("Error committing transaction");
 }
+
+pub async fn db_key_ops_enforced<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+
+    let mut key = LocalKey::generate(KeyAlg::Ed25519, false).expect("Error creating keypair");
+    key.set_key_ops(Some(KeyOpsSet::from(KeyOps::Verify)));
+    conn.insert_key("verify-only", &key, None, None, None)
+        .await
+        .expect(ERR_INSERT_KEY);
+
+    let entry = conn
+        .fetch_key("verify-only", false)
+        .await
+        .expect(ERR_FETCH_KEY)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(entry.key_ops(), Some(KeyOpsSet::from(KeyOps::Verify)));
+    let loaded = entry.load_local_key().expect("Error loading key");
+    let err = loaded
+        .sign_message(b"message", None)
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Input);
+
+    // unrestricted keys permit all operations
+    key.set_key_ops(None);
+    let sig = key
+        .sign_message(b"message", None)
+        .expect("Error signing message");
+    assert_eq!(
+        loaded
+            .verify_signature(b"message", &sig, None)
+            .expect("Error verifying signature"),
+        true
+    );
+}
+
+pub async fn db_key_sign_verify_batch<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+
+    let key = LocalKey::generate(KeyAlg::Ed25519, false).expect("Error creating keypair");
+    conn.insert_key("signing", &key, None, None, None)
+        .await
+        .expect(ERR_INSERT_KEY);
+
+    let messages = (0..150)
+        .map(|idx| format!("message {}", idx).into_bytes())
+        .collect::<Vec<_>>();
+    let messages = messages.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let mut signatures = conn
+        .sign_batch("signing", &messages, None)
+        .await
+        .expect("Error signing messages");
+    assert_eq!(signatures.len(), messages.len());
+    assert_eq!(
+        key.verify_signature(messages[149], &signatures[149], None)
+            .expect("Error verifying signature"),
+        true
+    );
+
+    signatures[70][0] ^= 1;
+    let sigs = signatures.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let verified = conn
+        .verify_batch("signing", &messages, &sigs, None)
+        .await
+        .expect("Error verifying signatures");
+    assert_eq!(verified.len(), messages.len());
+    assert!(verified
+        .iter()
+        .enumerate()
+        .all(|(idx, valid)| *valid == (idx != 70)));
+
+    let err = conn
+        .verify_batch("signing", &messages[..2], &sigs[..1], None)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Input);
+    let err = conn
+        .sign_batch("missing", &messages, None)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+pub async fn db_key_did_documents<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+
+    let sign_key = LocalKey::generate(KeyAlg::Ed25519, false).expect("Error creating keypair");
+    conn.insert_key("sign", &sign_key, None, None, None)
+        .await
+        .expect(ERR_INSERT_KEY);
+    let agree_key = LocalKey::generate(KeyAlg::X25519, false).expect("Error creating keypair");
+    conn.insert_key("agree", &agree_key, None, None, None)
+        .await
+        .expect(ERR_INSERT_KEY);
+
+    let doc = conn
+        .did_key_document("sign")
+        .await
+        .expect("Error building DID document");
+    assert!(doc.id.starts_with("did:key:z6Mk"));
+    assert_eq!(doc.authentication.len(), 1);
+    assert_eq!(doc.key_agreement.len(), 1);
+
+    let doc = conn
+        .did_peer_document(&["sign"], &["agree"])
+        .await
+        .expect("Error building DID document");
+    assert!(doc.id.starts_with("did:peer:2.E"));
+    assert_eq!(doc.verification_method.len(), 2);
+
+    let err = conn
+        .did_key_document("missing")
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+pub async fn db_key_rotation<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    let key = LocalKey::generate(KeyAlg::Ed25519, false).expect("Error creating keypair");
+    conn.insert_key("signing", &key, Some("meta"), None, None)
+        .await
+        .expect(ERR_INSERT_KEY);
+    conn.set_key_lifetime("signing", None, Some(Duration::from_secs(3600)))
+        .await
+        .expect("Error updating key lifetime");
+    drop(conn);
+
+    // not yet due for rotation
+    let rotated = db
+        .rotate_due_keys(None, true)
+        .await
+        .expect("Error rotating keys");
+    assert!(rotated.is_empty());
+
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    conn.set_key_lifetime(
+        "signing",
+        Some(SystemTime::now() - Duration::from_secs(1)),
+        None,
+    )
+    .await
+    .expect("Error updating key lifetime");
+    drop(conn);
+
+    let rotated = db
+        .rotate_due_keys(None, true)
+        .await
+        .expect("Error rotating keys");
+    assert_eq!(rotated.len(), 1);
+    assert_eq!(rotated[0].0, "signing");
+    let new_name = rotated[0].1.clone();
+
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    let old = conn
+        .fetch_key("signing", false)
+        .await
+        .expect(ERR_FETCH_KEY)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(old.replaced_by(), Some(new_name.as_str()));
+    assert_eq!(old.key_ops(), Some(KeyOpsSet::from(KeyOps::Verify)));
+
+    let new = conn
+        .fetch_key(&new_name, false)
+        .await
+        .expect(ERR_FETCH_KEY)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(new.replaces(), Some("signing"));
+    assert_eq!(new.metadata(), Some("meta"));
+    assert_eq!(new.algorithm(), Some(KeyAlg::Ed25519.as_str()));
+    assert!(!new.is_expired());
+    drop(conn);
+
+    // replaced keys are not rotated again
+    let rotated = db
+        .rotate_due_keys(None, true)
+        .await
+        .expect("Error rotating keys");
+    assert!(rotated.is_empty());
+}
+
+pub async fn db_maintenance<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    for idx in 0..10 {
+        conn.insert("category", &format!("name-{}", idx), b"value", None, None)
+            .await
+            .expect(ERR_INSERT);
+    }
+    conn.remove_all("category", None)
+        .await
+        .expect(ERR_REMOVE_ALL);
+    conn.insert("category", "retained", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    drop(conn);
+
+    db.maintenance(MaintenanceMode::Online)
+        .await
+        .expect("Error performing online maintenance");
+    db.maintenance(MaintenanceMode::Full)
+        .await
+        .expect("Error performing full maintenance");
+
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    assert_eq!(conn.count("category", None).await.expect(ERR_COUNT), 1);
+    assert!(conn
+        .fetch("category", "retained", false)
+        .await
+        .expect(ERR_FETCH)
+        .is_some());
+}
+
+pub async fn db_storage_report<DB: Backend>(db: &Store<DB>) {
+    let profile = db.create_profile(None).await.expect(ERR_PROFILE);
+
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    for idx in 0..3 {
+        conn.insert("first", &format!("name-{}", idx), b"value", None, None)
+            .await
+            .expect(ERR_INSERT);
+    }
+    let tags = [EntryTag::Encrypted("tag".to_string(), "value".to_string())];
+    conn.insert("second", "name", &[0u8; 100], Some(&tags[..]), None)
+        .await
+        .expect(ERR_INSERT);
+    drop(conn);
+
+    let mut conn = db.session(Some(profile.clone())).await.expect(ERR_SESSION);
+    conn.insert("first", "name", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    drop(conn);
+
+    let report = db
+        .storage_report()
+        .await
+        .expect("Error creating storage report");
+    assert_eq!(report.count(), 5);
+
+    let default = report
+        .profile(db.get_profile_name())
+        .expect("Missing default profile usage");
+    assert_eq!(default.count, 4);
+    let first = default.category("first").expect("Missing category usage");
+    assert_eq!(first.count, 3);
+    let second = default.category("second").expect("Missing category usage");
+    assert_eq!(second.count, 1);
+    assert!(second.bytes > 100);
+    assert!(second.bytes > first.bytes / 3);
+    assert_eq!(default.bytes, first.bytes + second.bytes);
+
+    let other = report.profile(&profile).expect("Missing profile usage");
+    assert_eq!(other.count, 1);
+    assert_eq!(other.categories.len(), 1);
+    assert_eq!(report.bytes(), default.bytes + other.bytes);
+}
+
+pub async fn db_entry_expiry<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+
+    let past = EntryExpiry::at(SystemTime::now() - Duration::from_secs(60)).unwrap();
+    let future = EntryExpiry::at(SystemTime::now() + Duration::from_secs(60)).unwrap();
+    for (name, expiry) in &[
+        ("past", past),
+        ("future", future),
+        ("sliding", EntryExpiry::Sliding(60_000)),
+    ] {
+        conn.update_with_expiry(
+            EntryOperation::Insert,
+            "category",
+            name,
+            Some(b"value".as_ref()),
+            None,
+            Some(*expiry),
+        )
+        .await
+        .expect(ERR_INSERT);
+    }
+
+    let row = conn
+        .fetch("category", "past", false)
+        .await
+        .expect(ERR_FETCH);
+    assert!(row.is_none());
+    let row = conn
+        .fetch("category", "future", false)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW);
+    let ttl = row.ttl().expect("Expected entry expiry");
+    assert!(ttl > Duration::from_secs(30) && ttl <= Duration::from_secs(60));
+
+    // fetching a sliding entry extends its expiry
+    for _ in 0..2 {
+        let row = conn
+            .fetch("category", "sliding", false)
+            .await
+            .expect(ERR_FETCH);
+        assert!(row.is_some());
+    }
+    assert_eq!(conn.count("category", None).await.expect(ERR_COUNT), 2);
+
+    conn.insert("category", "permanent", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    let rows = conn
+        .fetch_all("category", None, None, false)
+        .await
+        .expect(ERR_FETCH_ALL);
+    assert_eq!(rows.len(), 3);
+    for row in rows {
+        if row.name == "permanent" {
+            assert!(row.expiry().is_none());
+        } else {
+            assert!(row.expiry().expect("Expected entry expiry") > SystemTime::now());
+        }
+    }
+}
+
+pub async fn db_touch_all<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    let active = [EntryTag::Encrypted(
+        "state".to_string(),
+        "active".to_string(),
+    )];
+    for idx in 0..3 {
+        conn.insert(
+            "session",
+            &format!("name-{}", idx),
+            b"value",
+            Some(&active[..]),
+            Some(60_000),
+        )
+        .await
+        .expect(ERR_INSERT);
+    }
+    conn.insert("session", "idle", b"value", None, Some(60_000))
+        .await
+        .expect(ERR_INSERT);
+
+    let filter = TagFilter::is_eq("state", "active");
+    let touched = conn
+        .touch_all(
+            "session",
+            Some(filter.clone()),
+            Some(2),
+            EntryExpiry::Relative(3_600_000),
+        )
+        .await
+        .expect("Error touching test rows");
+    assert_eq!(touched, 2);
+
+    let rows = conn
+        .fetch_all("session", None, None, false)
+        .await
+        .expect(ERR_FETCH_ALL);
+    let extended = rows
+        .iter()
+        .filter(|row| row.ttl().unwrap() > Duration::from_secs(120))
+        .map(|row| row.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(extended, vec!["name-0", "name-1"]);
+
+    let rows = conn
+        .fetch_all_touch(
+            "session",
+            Some(filter.clone()),
+            None,
+            EntryExpiry::Relative(7_200_000),
+        )
+        .await
+        .expect(ERR_FETCH_ALL);
+    assert_eq!(rows.len(), 3);
+    for row in rows {
+        assert!(row.ttl().unwrap() > Duration::from_secs(3_600));
+    }
+    drop(conn);
+
+    let mut scan = db
+        .scan_touch(
+            None,
+            "session".to_string(),
+            None,
+            None,
+            None,
+            EntryExpiry::Relative(10_800_000),
+        )
+        .await
+        .expect(ERR_SCAN);
+    let rows = scan
+        .fetch_next()
+        .await
+        .expect(ERR_SCAN_NEXT)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(rows.len(), 4);
+    for row in rows {
+        assert!(row.ttl().unwrap() > Duration::from_secs(7_200));
+    }
+}
+
+pub async fn db_insert_all<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    let rows = (0..5)
+        .map(|idx| {
+            Entry::new(
+                if idx % 2 == 0 { "even" } else { "odd" },
+                format!("name-{}", idx),
+                format!("value-{}", idx),
+                vec![
+                    EntryTag::Encrypted("idx".to_string(), idx.to_string()),
+                    EntryTag::Plaintext("plain".to_string(), "tag".to_string()),
+                ],
+            )
+        })
+        .collect::<Vec<_>>();
+    conn.insert_all(rows.clone())
+        .await
+        .expect("Error inserting test rows");
+
+    assert_eq!(conn.count("even", None).await.expect(ERR_COUNT), 3);
+    assert_eq!(conn.count("odd", None).await.expect(ERR_COUNT), 2);
+    for row in &rows {
+        let found = conn
+            .fetch(&row.category, &row.name, false)
+            .await
+            .expect(ERR_FETCH)
+            .expect(ERR_REQ_ROW);
+        assert_eq!(&found, row);
+    }
+    let found = conn
+        .fetch_all("even", Some(TagFilter::is_eq("idx", "2")), None, false)
+        .await
+        .expect(ERR_FETCH_ALL);
+    assert_eq!(found, vec![rows[2].clone()]);
+
+    // a duplicate record prevents the whole batch from being written
+    let batch = vec![
+        Entry::new("odd", "name-7", "value", Vec::new()),
+        rows[1].clone(),
+    ];
+    let err = conn.insert_all(batch).await.expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Duplicate);
+    assert_eq!(conn.count("odd", None).await.expect(ERR_COUNT), 2);
+}
+
+pub async fn db_durability<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    conn.set_durability(Durability::Relaxed)
+        .await
+        .expect("Error setting durability");
+    conn.insert("category", "name-0", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    // the level may be changed on an active connection
+    conn.set_durability(Durability::Full)
+        .await
+        .expect("Error setting durability");
+    conn.insert("category", "name-1", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    drop(conn);
+
+    let mut txn = db.transaction(None).await.expect(ERR_TRANSACTION);
+    txn.set_durability(Durability::Relaxed)
+        .await
+        .expect("Error setting durability");
+    txn.insert("category", "name-2", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    let err = txn
+        .set_durability(Durability::Normal)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+    txn.commit().await.expect("Error committing transaction");
+
+    // connections returned to the pool are unaffected
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    assert_eq!(conn.count("category", None).await.expect(ERR_COUNT), 3);
+}
+
+pub async fn db_key_cache_stats<DB: Backend>(db: &Store<DB>) {
+    let profile = db.create_profile(None).await.expect(ERR_PROFILE);
+    let before = db.key_cache_stats();
+
+    let mut conn = db.session(Some(profile.clone())).await.expect(ERR_SESSION);
+    conn.count("category", None).await.expect(ERR_COUNT);
+    drop(conn);
+    let after = db.key_cache_stats();
+    assert_eq!(after.hits, before.hits + 1);
+    assert_eq!(after.misses, before.misses);
+
+    assert!(db.remove_profile(profile).await.expect(ERR_PROFILE));
+    assert_eq!(db.key_cache_stats().evictions, after.evictions + 1);
+}
+
+pub async fn db_error_context<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    conn.insert("category", "name", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    let err = conn
+        .insert("category", "name", b"value", None, None)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Duplicate);
+    let context = err.context().expect("Expected error context").clone();
+    assert_eq!(context.operation(), Some("update"));
+    assert!(matches!(
+        context.backend(),
+        Some("sqlite") | Some("postgres")
+    ));
+    assert_eq!(context.entry_kind(), Some(EntryKind::Item));
+    assert!(context.profile_hash().is_some());
+    let category = context.category_hash().expect("Expected category hash");
+    assert!(!category.contains("category"));
+
+    // identifiers hash consistently, without revealing their values
+    let err = conn
+        .remove("category", "other")
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    let other = err.context().expect("Expected error context");
+    assert_eq!(other.category_hash(), Some(category));
+    assert_eq!(other.profile_hash(), context.profile_hash());
+    let err = conn.remove("other", "name").await.expect_err(ERR_REQ_ERR);
+    assert_ne!(
+        err.context().and_then(|c| c.category_hash()),
+        Some(category)
+    );
+}
+
+pub async fn db_binary_identifiers<DB: Backend>(db: &Store<DB>) {
+    let category = [0u8, 0xff, 0x10];
+    let name = [0xc3u8, 0x28, 0x00, 0x01];
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    conn.insert_binary(&category, &name, b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    let err = conn
+        .insert_binary(&category, &name, b"value", None, None)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Duplicate);
+
+    let row = conn
+        .fetch_binary(&category, &name, false)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(&*row.category_bytes(), &category[..]);
+    assert_eq!(&*row.name_bytes(), &name[..]);
+    assert_eq!(row.value, b"value".to_vec());
+
+    // the string form is accepted by the other methods
+    let rows = conn
+        .fetch_all(&row.category, None, None, false)
+        .await
+        .expect(ERR_FETCH_ALL);
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].name, row.name);
+
+    // valid UTF-8 identifiers match the string methods
+    conn.insert_binary(b"category", b"name", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    assert!(conn
+        .fetch("category", "name", false)
+        .await
+        .expect(ERR_FETCH)
+        .is_some());
+
+    conn.replace_binary(&category, &name, b"updated", None, None)
+        .await
+        .expect(ERR_REPLACE);
+    conn.remove_binary(&category, &name)
+        .await
+        .expect(ERR_REMOVE);
+    assert!(conn
+        .fetch_binary(&category, &name, false)
+        .await
+        .expect(ERR_FETCH)
+        .is_none());
+}
+
+pub async fn db_entry_metadata<DB: Backend>(db: &Store<DB>) {
+    assert!(db.capabilities().entry_metadata);
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+
+    let err = conn
+        .update_metadata("category", "name", Some(b"sync"))
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    conn.insert("category", "name", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    assert_eq!(
+        conn.fetch_metadata("category", "name")
+            .await
+            .expect("Error fetching metadata"),
+        None
+    );
+
+    conn.update_metadata("category", "name", Some(b"sync"))
+        .await
+        .expect("Error updating metadata");
+    assert_eq!(
+        conn.fetch_metadata("category", "name")
+            .await
+            .expect("Error fetching metadata"),
+        Some(SecretBytes::from(&b"sync"[..]))
+    );
+    // the value is unchanged
+    let row = conn
+        .fetch("category", "name", false)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(row.value, b"value".to_vec());
+
+    conn.update_metadata("category", "name", None)
+        .await
+        .expect("Error clearing metadata");
+    assert_eq!(
+        conn.fetch_metadata("category", "name")
+            .await
+            .expect("Error fetching metadata"),
+        None
+    );
+
+    // replacing the record clears its metadata
+    conn.update_metadata("category", "name", Some(b"sync"))
+        .await
+        .expect("Error updating metadata");
+    conn.replace("category", "name", b"updated", None, None)
+        .await
+        .expect(ERR_REPLACE);
+    assert_eq!(
+        conn.fetch_metadata("category", "name")
+            .await
+            .expect("Error fetching metadata"),
+        None
+    );
+}
+
+pub async fn db_entry_links<DB: Backend>(db: &Store<DB>) {
+    assert!(db.capabilities().entry_links);
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+
+    for name in &["cred1", "cred2"] {
+        conn.insert("credential", name, b"value", None, None)
+            .await
+            .expect(ERR_INSERT);
+    }
+    conn.insert("connection", "conn", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+
+    let err = conn
+        .link(
+            "credential",
+            "cred1",
+            EntryLink::new("issuer", "connection", "missing"),
+        )
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    for name in &["cred1", "cred2"] {
+        assert!(conn
+            .link(
+                "credential",
+                name,
+                EntryLink::new("issuer", "connection", "conn")
+            )
+            .await
+            .expect("Error linking entries"));
+    }
+    // an existing link is not duplicated
+    assert!(!conn
+        .link(
+            "credential",
+            "cred1",
+            EntryLink::new("issuer", "connection", "conn")
+        )
+        .await
+        .expect("Error linking entries"));
+
+    let links = conn
+        .list_links("credential", "cred1", None)
+        .await
+        .expect("Error listing links");
+    assert_eq!(links, vec![EntryLink::new("issuer", "connection", "conn")]);
+    assert!(conn
+        .list_links("credential", "cred1", Some("holder"))
+        .await
+        .expect("Error listing links")
+        .is_empty());
+
+    let mut backlinks = conn
+        .list_backlinks("connection", "conn", Some("issuer"))
+        .await
+        .expect("Error listing links");
+    backlinks.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(
+        backlinks,
+        vec![
+            EntryLink::new("issuer", "credential", "cred1"),
+            EntryLink::new("issuer", "credential", "cred2"),
+        ]
+    );
+
+    assert!(conn
+        .unlink(
+            "credential",
+            "cred1",
+            EntryLink::new("issuer", "connection", "conn")
+        )
+        .await
+        .expect("Error unlinking entries"));
+    // removing a record removes its links
+    conn.remove("credential", "cred2").await.expect(ERR_REMOVE);
+    assert!(conn
+        .list_backlinks("connection", "conn", None)
+        .await
+        .expect("Error listing links")
+        .is_empty());
+}
+
+pub async fn db_collections<DB: Backend>(db: &Store<DB>) {
+    assert!(db.capabilities().collections);
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+
+    for (category, name) in &[("offer", "a"), ("request", "a"), ("offer", "b")] {
+        conn.insert(category, name, b"value", None, None)
+            .await
+            .expect(ERR_INSERT);
+    }
+    conn.set_collection("offer", "a", Some("exchange-1"))
+        .await
+        .expect("Error setting collection");
+    conn.set_collection("request", "a", Some("exchange-1"))
+        .await
+        .expect("Error setting collection");
+    conn.set_collection("offer", "b", Some("exchange-2"))
+        .await
+        .expect("Error setting collection");
+    let err = conn
+        .set_collection("offer", "missing", Some("exchange-1"))
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    assert_eq!(
+        conn.remove_collection("exchange-1")
+            .await
+            .expect("Error removing collection"),
+        2
+    );
+    assert!(conn
+        .fetch("offer", "a", false)
+        .await
+        .expect(ERR_FETCH)
+        .is_none());
+    assert!(conn
+        .fetch("request", "a", false)
+        .await
+        .expect(ERR_FETCH)
+        .is_none());
+    assert!(conn
+        .fetch("offer", "b", false)
+        .await
+        .expect(ERR_FETCH)
+        .is_some());
+
+    // a record removed from its collection is retained
+    conn.set_collection("offer", "b", None)
+        .await
+        .expect("Error setting collection");
+    assert_eq!(
+        conn.remove_collection("exchange-2")
+            .await
+            .expect("Error removing collection"),
+        0
+    );
+    assert!(conn
+        .fetch("offer", "b", false)
+        .await
+        .expect(ERR_FETCH)
+        .is_some());
+}
+
+pub async fn db_outbox<DB: Backend>(db: &Store<DB>) {
+    // messages queued in a transaction are written along with the records
+    let mut txn = db.transaction(None).await.expect(ERR_TRANSACTION);
+    txn.insert("order", "1", b"order", None, None)
+        .await
+        .expect(ERR_INSERT);
+    let first = txn
+        .queue_outbox(b"order created")
+        .await
+        .expect("Error queueing message");
+    txn.commit().await.expect("Error committing transaction");
+
+    // a rolled back message is discarded
+    let mut txn = db.transaction(None).await.expect(ERR_TRANSACTION);
+    txn.queue_outbox(b"discarded")
+        .await
+        .expect("Error queueing message");
+    txn.rollback()
+        .await
+        .expect("Error rolling back transaction");
+
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    let second = conn
+        .queue_outbox(b"order shipped")
+        .await
+        .expect("Error queueing message");
+
+    let polled = db
+        .poll_outbox(None, 1, Duration::from_secs(60))
+        .await
+        .expect("Error polling outbox");
+    assert_eq!(polled.len(), 1);
+    assert_eq!(polled[0].id, first);
+    assert_eq!(polled[0].message.as_ref(), b"order created");
+    assert_eq!(polled[0].attempts, 1);
+
+    // claimed messages are hidden until the visibility timeout elapses
+    let polled = db
+        .poll_outbox(None, 10, Duration::from_millis(100))
+        .await
+        .expect("Error polling outbox");
+    assert_eq!(polled.len(), 1);
+    assert_eq!(polled[0].id, second);
+    std::thread::sleep(Duration::from_millis(150));
+    let polled = db
+        .poll_outbox(None, 10, Duration::from_secs(60))
+        .await
+        .expect("Error polling outbox");
+    assert_eq!(polled.len(), 1);
+    assert_eq!(polled[0].id, second);
+    assert_eq!(polled[0].attempts, 2);
+
+    assert!(conn.ack_outbox(&second).await.expect("Error acknowledging"));
+    assert!(!conn.ack_outbox(&second).await.expect("Error acknowledging"));
+    assert!(conn.ack_outbox(&first).await.expect("Error acknowledging"));
+
+    // outbox messages are not visible as generic records
+    assert_eq!(conn.count("askar:outbox", None).await.expect(ERR_COUNT), 0);
+    drop(conn);
+    assert!(db
+        .poll_outbox(None, 10, Duration::from_secs(60))
+        .await
+        .expect("Error polling outbox")
+        .is_empty());
+}
+
+pub async fn db_leases<DB: Backend>(db: &Store<DB>) {
+    let mut lease = db
+        .acquire_lease(None, "sweep", Duration::from_millis(100))
+        .await
+        .expect("Error acquiring lease")
+        .expect("Expected lease");
+    assert!(db
+        .acquire_lease(None, "sweep", Duration::from_secs(60))
+        .await
+        .expect("Error acquiring lease")
+        .is_none());
+    // other leases are independent
+    let rotate = db
+        .acquire_lease(None, "rotate", Duration::from_secs(60))
+        .await
+        .expect("Error acquiring lease")
+        .expect("Expected lease");
+
+    assert!(db
+        .renew_lease(&mut lease, Duration::from_millis(100))
+        .await
+        .expect("Error renewing lease"));
+    std::thread::sleep(Duration::from_millis(150));
+    assert!(lease.is_expired());
+
+    // an expired lease may be acquired by another holder
+    let other = db
+        .acquire_lease(None, "sweep", Duration::from_secs(60))
+        .await
+        .expect("Error acquiring lease")
+        .expect("Expected lease");
+    assert_ne!(other.token, lease.token);
+    assert!(!db
+        .renew_lease(&mut lease, Duration::from_secs(60))
+        .await
+        .expect("Error renewing lease"));
+    assert!(!db
+        .release_lease(lease)
+        .await
+        .expect("Error releasing lease"));
+
+    assert!(db
+        .release_lease(other)
+        .await
+        .expect("Error releasing lease"));
+    assert!(db
+        .acquire_lease(None, "sweep", Duration::from_secs(60))
+        .await
+        .expect("Error acquiring lease")
+        .is_some());
+    assert!(db
+        .release_lease(rotate)
+        .await
+        .expect("Error releasing lease"));
+}
+
+pub async fn db_reconfigure<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    for idx in 0..5 {
+        conn.insert("category", &format!("item{}", idx), b"value", None, None)
+            .await
+            .expect(ERR_INSERT);
+    }
+    drop(conn);
+
+    db.reconfigure(StoreTuning {
+        session_timeout: Some(5000),
+        scan_page_size: Some(2),
+        key_cache_capacity: Some(4),
+    })
+    .expect("Error reconfiguring store");
+    assert_eq!(db.timeout(), Some(Duration::from_millis(5000)));
+
+    let mut scan = db
+        .scan(None, "category".to_string(), None, None, None)
+        .await
+        .expect(ERR_SCAN);
+    let mut pages = Vec::new();
+    while let Some(rows) = scan.fetch_next().await.expect(ERR_SCAN_NEXT) {
+        pages.push(rows.len());
+    }
+    assert_eq!(pages, vec![2, 2, 1]);
+
+    // options which are not provided are unchanged
+    db.reconfigure(StoreTuning {
+        key_cache_capacity: Some(0),
+        ..Default::default()
+    })
+    .expect("Error reconfiguring store");
+    assert_eq!(db.timeout(), Some(Duration::from_millis(5000)));
+    db.reconfigure(StoreTuning {
+        session_timeout: Some(0),
+        ..Default::default()
+    })
+    .expect("Error reconfiguring store");
+    assert_eq!(db.timeout(), None);
+
+    assert_eq!(
+        db.reconfigure(StoreTuning {
+            scan_page_size: Some(0),
+            ..Default::default()
+        })
+        .expect_err(ERR_REQ_ERR)
+        .kind(),
+        ErrorKind::Input
+    );
+}
+
+pub async fn db_session_stats<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    assert_eq!(conn.stats(), SessionStats::default());
+
+    for idx in 0..3 {
+        conn.insert("category", &format!("item{}", idx), b"value", None, None)
+            .await
+            .expect(ERR_INSERT);
+    }
+    assert!(conn
+        .fetch("category", "item0", false)
+        .await
+        .expect(ERR_FETCH)
+        .is_some());
+    assert!(conn
+        .fetch("category", "missing", false)
+        .await
+        .expect(ERR_FETCH)
+        .is_none());
+    let rows = conn
+        .fetch_all("category", None, None, false)
+        .await
+        .expect(ERR_FETCH_ALL);
+    assert_eq!(rows.len(), 3);
+    conn.remove("category", "item1").await.expect(ERR_REMOVE);
+
+    let stats = conn.stats();
+    assert_eq!(stats.operations, 7);
+    assert_eq!(stats.rows_read, 4);
+    assert_eq!(stats.rows_written, 4);
+    assert_eq!(stats.slow_operations, 0);
+    assert!(stats.elapsed > Duration::default());
+    assert!(stats.crypto_time > Duration::default());
+    assert!(stats.crypto_time <= stats.elapsed);
+}
+
+pub async fn db_export_ndjson<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    conn.insert(
+        "category",
+        "text",
+        b"value",
+        Some(&[EntryTag::Plaintext("plain".to_string(), "a".to_string())][..]),
+        None,
+    )
+    .await
+    .expect(ERR_INSERT);
+    conn.insert("category", "binary", &[0xff, 0x00], None, None)
+        .await
+        .expect(ERR_INSERT);
+    conn.set_namespace(Some("ns".to_string()))
+        .expect("Error setting namespace");
+    conn.insert("category", "namespaced", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    let key = LocalKey::generate(KeyAlg::Ed25519, false).expect("Error creating keypair");
+    conn.insert_key("key", &key, None, None, None)
+        .await
+        .expect(ERR_INSERT_KEY);
+    drop(conn);
+
+    let profile = db.get_profile_name().to_string();
+    let mut output = Vec::new();
+    let count = db
+        .export_ndjson(
+            profile.clone(),
+            &ExportFilter::default(),
+            &mut output,
+            false,
+            PlaintextExport::Acknowledged,
+        )
+        .await
+        .expect("Error exporting records");
+    assert_eq!(count, 3);
+    let text = String::from_utf8(output).expect("Invalid export encoding");
+    assert_eq!(text.lines().count(), 3);
+    assert!(text.contains(r#""value_hex":"ff00""#));
+    assert!(!text.contains(r#""kind":"kms""#));
+
+    let mut output = Vec::new();
+    let count = db
+        .export_ndjson(
+            profile,
+            &ExportFilter::default(),
+            &mut output,
+            true,
+            PlaintextExport::Acknowledged,
+        )
+        .await
+        .expect("Error exporting records");
+    assert_eq!(count, 4);
+
+    let target = db.create_profile(None).await.expect(ERR_PROFILE);
+    let count = db
+        .import_ndjson(
+            Some(target.clone()),
+            &output[..],
+            PlaintextExport::Acknowledged,
+        )
+        .await
+        .expect("Error importing records");
+    assert_eq!(count, 4);
+    let mut conn = db.session(Some(target.clone())).await.expect(ERR_SESSION);
+    let entry = conn
+        .fetch("category", "binary", false)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(entry.value.as_ref(), &[0xff, 0x00]);
+    let entry = conn
+        .fetch("category", "text", false)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(
+        entry.tags,
+        vec![EntryTag::Plaintext("plain".to_string(), "a".to_string())]
+    );
+    assert!(conn
+        .fetch_key("key", false)
+        .await
+        .expect(ERR_FETCH_KEY)
+        .is_some());
+    conn.set_namespace(Some("ns".to_string()))
+        .expect("Error setting namespace");
+    assert!(conn
+        .fetch("category", "namespaced", false)
+        .await
+        .expect(ERR_FETCH)
+        .is_some());
+    drop(conn);
+
+    // importing the same records again fails without writing any of them
+    let err = db
+        .import_ndjson(Some(target), &output[..], PlaintextExport::Acknowledged)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Duplicate);
+    assert!(db
+        .import_ndjson(
+            None,
+            &b"{\"kind\":\"item\"}\n"[..],
+            PlaintextExport::Acknowledged
+        )
+        .await
+        .is_err());
+
+    // records in the reserved categories are only written by the store
+    let line = br#"{"kind":"item","category":"askar:removed","name":"name","value":"value"}"#;
+    let err = db
+        .import_ndjson(None, &line[..], PlaintextExport::Acknowledged)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::PolicyViolation);
+
+    let line = vec![b' '; 16 * 1024 * 1024 + 1];
+    let err = db
+        .import_ndjson(None, &line[..], PlaintextExport::Acknowledged)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Input);
+}
+
+pub async fn db_export_filtered<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    for (category, name, user) in [
+        ("credential", "a", "alice"),
+        ("credential", "b", "bob"),
+        ("message", "c", "alice"),
+    ] {
+        conn.insert(
+            category,
+            name,
+            b"value",
+            Some(&[EntryTag::Encrypted("user".to_string(), user.to_string())][..]),
+            None,
+        )
+        .await
+        .expect(ERR_INSERT);
+    }
+    let key = LocalKey::generate(KeyAlg::Ed25519, false).expect("Error creating keypair");
+    conn.insert_key("key", &key, None, None, None)
+        .await
+        .expect(ERR_INSERT_KEY);
+    drop(conn);
+
+    let profile = db.get_profile_name().to_string();
+    let export = |filter: ExportFilter| {
+        let profile = profile.clone();
+        async move {
+            let mut output = Vec::new();
+            db.export_ndjson(
+                profile,
+                &filter,
+                &mut output,
+                true,
+                PlaintextExport::Acknowledged,
+            )
+            .await
+            .map(|count| {
+                (
+                    count,
+                    String::from_utf8(output).expect("Invalid export encoding"),
+                )
+            })
+        }
+    };
+
+    let (count, _) = export(ExportFilter {
+        kinds: Some(vec![EntryKind::Item]),
+        ..Default::default()
+    })
+    .await
+    .expect("Error exporting records");
+    assert_eq!(count, 3);
+
+    let (count, text) = export(ExportFilter {
+        categories: Some(vec!["credential".to_string()]),
+        tag_filter: Some(TagFilter::is_eq("user", "alice")),
+        ..Default::default()
+    })
+    .await
+    .expect("Error exporting records");
+    assert_eq!(count, 1);
+    assert!(text.contains(r#""name":"a""#));
+
+    if db.capabilities().history {
+        std::thread::sleep(Duration::from_millis(10));
+        let since = SystemTime::now();
+        std::thread::sleep(Duration::from_millis(10));
+        let mut conn = db.session(None).await.expect(ERR_SESSION);
+        conn.replace("message", "c", b"updated", None, None)
+            .await
+            .expect(ERR_REPLACE);
+        drop(conn);
+        let (count, text) = export(ExportFilter {
+            modified_since: Some(since),
+            ..Default::default()
+        })
+        .await
+        .expect("Error exporting records");
+        assert_eq!(count, 1);
+        assert!(text.contains(r#""name":"c""#));
+    } else {
+        let err = export(ExportFilter {
+            modified_since: Some(SystemTime::now()),
+            ..Default::default()
+        })
+        .await
+        .expect_err(ERR_REQ_ERR);
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+    }
+}
+
+pub async fn db_scan_modified<DB: Backend>(db: &Store<DB>) {
+    let scan_names = |history_filter: HistoryFilter| async move {
+        let mut scan = db
+            .scan_modified(
+                None,
+                "category".to_string(),
+                None,
+                history_filter,
+                None,
+                None,
+            )
+            .await?;
+        let mut names = Vec::new();
+        while let Some(rows) = scan.fetch_next().await? {
+            names.extend(rows.into_iter().map(|entry| entry.name));
+        }
+        names.sort();
+        Ok::<_, aries_askar::Error>(names)
+    };
+
+    if !db.capabilities().history {
+        let err = scan_names(HistoryFilter {
+            created_after: Some(SystemTime::now()),
+            ..Default::default()
+        })
+        .await
+        .expect_err(ERR_REQ_ERR);
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        return;
+    }
+
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    for name in &["a", "b"] {
+        conn.insert("category", name, b"value", None, None)
+            .await
+            .expect(ERR_INSERT);
+    }
+    std::thread::sleep(Duration::from_millis(10));
+    let first = SystemTime::now();
+    std::thread::sleep(Duration::from_millis(10));
+    conn.replace("category", "a", b"replaced", None, None)
+        .await
+        .expect(ERR_REPLACE);
+    conn.insert("category", "c", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    std::thread::sleep(Duration::from_millis(10));
+    let second = SystemTime::now();
+    std::thread::sleep(Duration::from_millis(10));
+    // a removed record which is inserted again is created again
+    conn.remove("category", "b").await.expect(ERR_REMOVE);
+    conn.insert("category", "b", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    drop(conn);
+
+    let names = scan_names(HistoryFilter {
+        created_after: Some(first),
+        ..Default::default()
+    })
+    .await
+    .expect(ERR_SCAN);
+    assert_eq!(names, vec!["b", "c"]);
+
+    let names = scan_names(HistoryFilter {
+        created_after: Some(second),
+        ..Default::default()
+    })
+    .await
+    .expect(ERR_SCAN);
+    assert_eq!(names, vec!["b"]);
+
+    let names = scan_names(HistoryFilter {
+        updated_before: Some(second),
+        ..Default::default()
+    })
+    .await
+    .expect(ERR_SCAN);
+    assert_eq!(names, vec!["a", "c"]);
+
+    let names = scan_names(HistoryFilter {
+        updated_before: Some(first),
+        ..Default::default()
+    })
+    .await
+    .expect(ERR_SCAN);
+    assert!(names.is_empty());
+
+    let names = scan_names(HistoryFilter {
+        created_after: Some(first),
+        updated_before: Some(second),
+        ..Default::default()
+    })
+    .await
+    .expect(ERR_SCAN);
+    assert_eq!(names, vec!["c"]);
+
+    // the bounds are retained when resuming the scan from a checkpoint
+    let history_filter = HistoryFilter {
+        updated_since: Some(first),
+        ..Default::default()
+    };
+    let mut scan = db
+        .scan_modified(
+            None,
+            "category".to_string(),
+            None,
+            history_filter,
+            None,
+            None,
+        )
+        .await
+        .expect(ERR_SCAN);
+    let checkpoint = scan.checkpoint().expect("Expected scan checkpoint").clone();
+    assert_eq!(checkpoint.history_filter(), history_filter);
+    let checkpoint = ScanCheckpoint::from_token(
+        &checkpoint
+            .to_token()
+            .expect("Error encoding scan checkpoint"),
+    )
+    .expect("Error decoding scan checkpoint");
+    assert_eq!(checkpoint.history_filter(), history_filter);
+    drop(scan);
+}
+
+pub async fn db_entry_protection<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    for name in &["secret", "other"] {
+        conn.insert("category", name, b"value", None, None)
+            .await
+            .expect(ERR_INSERT);
+    }
+    let err = conn
+        .set_protected("category", "missing", true)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    conn.set_protected("category", "secret", true)
+        .await
+        .expect("Error protecting record");
+    assert!(conn
+        .is_protected("category", "secret")
+        .await
+        .expect("Error checking protection"));
+
+    let err = conn
+        .remove("category", "secret")
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Forbidden);
+
+    // the protection is retained when the record is replaced
+    conn.replace("category", "secret", b"updated", None, None)
+        .await
+        .expect(ERR_REPLACE);
+    let removed = conn
+        .remove_all("category", None)
+        .await
+        .expect(ERR_REMOVE_ALL);
+    assert_eq!(removed, 1);
+    let rows = conn
+        .fetch_all("category", None, None, false)
+        .await
+        .expect(ERR_FETCH_ALL);
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].name, "secret");
+
+    let removed = conn
+        .force_remove_all("category", None)
+        .await
+        .expect(ERR_REMOVE_ALL);
+    assert_eq!(removed, 1);
+    assert!(!conn
+        .is_protected("category", "secret")
+        .await
+        .expect("Error checking protection"));
+
+    conn.insert("category", "secret", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    conn.set_protected("category", "secret", true)
+        .await
+        .expect("Error protecting record");
+    conn.force_remove("category", "secret")
+        .await
+        .expect(ERR_REMOVE);
+    assert_eq!(conn.count("category", None).await.expect(ERR_COUNT), 0);
+
+    // every removal path is subject to the protection of the record
+    conn.insert("category", "secret", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    assert!(!conn
+        .is_protected("category", "secret")
+        .await
+        .expect("Error checking protection"));
+    conn.set_protected("category", "secret", true)
+        .await
+        .expect("Error protecting record");
+    let err = conn
+        .update(
+            EntryOperation::Remove,
+            "category",
+            "secret",
+            None,
+            None,
+            None,
+        )
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Forbidden);
+    let err = conn
+        .replace("category", "secret", b"value", None, Some(1000))
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Forbidden);
+    conn.set_collection("category", "secret", Some("group"))
+        .await
+        .expect("Error setting collection");
+    assert_eq!(
+        conn.remove_collection("group")
+            .await
+            .expect("Error removing collection"),
+        0
+    );
+    assert!(conn
+        .fetch("category", "secret", false)
+        .await
+        .expect(ERR_FETCH)
+        .is_some());
+
+    conn.insert("category", "expiring", b"value", None, Some(60000))
+        .await
+        .expect(ERR_INSERT);
+    let err = conn
+        .set_protected("category", "expiring", true)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Input);
+}
+
+pub async fn db_maintenance_mode<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    conn.insert("category", "name", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+    drop(conn);
+    assert_eq!(
+        db.access_mode().await.expect("Error reading access mode"),
+        AccessMode::ReadWrite
+    );
+
+    db.set_maintenance_mode(AccessMode::ReadOnly)
+        .await
+        .expect("Error setting maintenance mode");
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    assert!(conn
+        .fetch("category", "name", false)
+        .await
+        .expect(ERR_FETCH)
+        .is_some());
+    let err = conn
+        .insert("category", "other", b"value", None, None)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Forbidden);
+    drop(conn);
+
+    // modifications performed by the store handle are also rejected
+    let err = db
+        .scan_touch(
+            None,
+            "category".to_string(),
+            None,
+            None,
+            None,
+            EntryExpiry::Relative(60_000),
+        )
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Forbidden);
+    let line = br#"{"kind":"item","category":"category","name":"imported","value":"value"}"#;
+    let err = db
+        .import_ndjson(None, &line[..], PlaintextExport::Acknowledged)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Forbidden);
+    let err = db
+        .maintenance(MaintenanceMode::Online)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Forbidden);
+
+    let checkpoint = db
+        .scan(None, "category".to_string(), None, None, None)
+        .await
+        .expect(ERR_SCAN)
+        .checkpoint()
+        .expect("Expected scan checkpoint")
+        .clone();
+
+    db.set_maintenance_mode(AccessMode::Offline)
+        .await
+        .expect("Error setting maintenance mode");
+    let err = db.session(None).await.expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Forbidden);
+    let err = db
+        .scan_since(None, "category".to_string(), None, None, None)
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Forbidden);
+    let err = db.resume_scan(checkpoint).await.expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Forbidden);
+
+    db.set_maintenance_mode(AccessMode::ReadWrite)
+        .await
+        .expect("Error setting maintenance mode");
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    conn.insert("category", "other", b"value", None, None)
+        .await
+        .expect(ERR_INSERT);
+
+    // sessions which are already open observe a change of mode
+    db.set_maintenance_mode(AccessMode::ReadOnly)
+        .await
+        .expect("Error setting maintenance mode");
+    let err = conn
+        .remove("category", "other")
+        .await
+        .expect_err(ERR_REQ_ERR);
+    assert_eq!(err.kind(), ErrorKind::Forbidden);
+    db.set_maintenance_mode(AccessMode::ReadWrite)
+        .await
+        .expect("Error setting maintenance mode");
+    conn.remove("category", "other").await.expect(ERR_REMOVE);
+}
+
+pub async fn db_tag_match_options<DB: Backend>(db: &Store<DB>) {
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    conn.insert(
+        "category",
+        "name",
+        b"value",
+        Some(&[EntryTag::Plaintext(
+            "email".to_string(),
+            "Ame\u{301}lie@Example.com".to_string(),
+        )]),
+        None,
+    )
+    .await
+    .expect(ERR_INSERT);
+
+    let filters = [
+        // plaintext values are normalized when written
+        (TagFilter::is_eq("~email", "Am\u{e9}lie@Example.com"), 1),
+        (TagFilter::is_eq("~email", "Ame\u{301}lie@Example.com"), 0),
+        (TagFilter::is_eq("~email", "am\u{e9}lie@example.com"), 0),
+        (
+            TagFilter::is_eq("~email", "am\u{e9}lie@example.com").case_insensitive(),
+            1,
+        ),
+        (
+            TagFilter::is_eq("~email", "ame\u{301}lie@EXAMPLE.COM")
+                .case_insensitive()
+                .unicode_normalized(),
+            1,
+        ),
+        (
+            TagFilter::any_of(vec![
+                TagFilter::is_in("~email", vec!["bob@example.com".to_string()]),
+                TagFilter::is_in("~email", vec!["AM\u{e9}LIE@EXAMPLE.COM".to_string()]),
+            ]),
+            0,
+        ),
+        (
+            TagFilter::any_of(vec![
+                TagFilter::is_in("~email", vec!["bob@example.com".to_string()]),
+                TagFilter::is_in("~email", vec!["am\u{e9}lie@EXAMPLE.COM".to_string()])
+                    .case_insensitive(),
+            ]),
+            1,
+        ),
+    ];
+    for (filter, expected) in filters.iter().cloned() {
+        assert_eq!(
+            conn.count("category", Some(filter)).await.expect(ERR_COUNT),
+            expected
+        );
+    }
+}
+
+pub async fn db_scan_projection<DB: Backend>(db: &Store<DB>) {
+    let tags = vec![
+        EntryTag::Encrypted("enc".to_string(), "v1".to_string()),
+        EntryTag::Plaintext("plain".to_string(), "v2".to_string()),
+    ];
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    for idx in 0..3 {
+        conn.insert(
+            "category",
+            &format!("item{}", idx),
+            b"value",
+            Some(tags.as_slice()),
+            None,
+        )
+        .await
+        .expect(ERR_INSERT);
+    }
+
+    let rows = conn
+        .fetch_all_projected("category", None, None, false, Projection::Names)
+        .await
+        .expect(ERR_FETCH_ALL);
+    assert_eq!(rows.len(), 3);
+    assert!(rows
+        .iter()
+        .all(|row| row.name.starts_with("item") && row.value.is_empty() && row.tags.is_empty()));
+
+    let rows = conn
+        .fetch_all_projected("category", None, None, false, Projection::NamesAndTags)
+        .await
+        .expect(ERR_FETCH_ALL);
+    assert_eq!(rows.len(), 3);
+    for row in rows {
+        assert!(row.value.is_empty());
+        let mut row_tags = row.tags.clone();
+        row_tags.sort();
+        assert_eq!(row_tags, tags);
+    }
+    drop(conn);
+
+    let mut scan = db
+        .scan_projected(
+            None,
+            "category".to_string(),
+            Some(TagFilter::is_eq("plain", "v2")),
+            None,
+            None,
+            Projection::Names,
+        )
+        .await
+        .expect(ERR_SCAN);
+    let mut names = vec![];
+    while let Some(rows) = scan.fetch_next().await.expect(ERR_SCAN_NEXT) {
+        assert!(rows.iter().all(|row| row.value.is_empty()));
+        names.extend(rows.into_iter().map(|row| row.name));
+    }
+    names.sort();
+    assert_eq!(names, vec!["item0", "item1", "item2"]);
+}
+
+pub async fn db_multi_valued_tags<DB: Backend>(db: &Store<DB>) {
+    let roles = |values: &[&str]| {
+        let mut tags = Vec::new();
+        for value in values {
+            tags.push(EntryTag::Encrypted("role".to_string(), value.to_string()));
+            tags.push(EntryTag::Plaintext("level".to_string(), value.to_string()));
+        }
+        tags
+    };
+    let mut conn = db.session(None).await.expect(ERR_SESSION);
+    for (name, values) in [
+        ("a", &["admin", "user"][..]),
+        ("b", &["user"][..]),
+        ("c", &["admin", "admin"][..]),
+    ] {
+        conn.insert(
+            "category",
+            name,
+            b"value",
+            Some(roles(values).as_slice()),
+            None,
+        )
+        .await
+        .expect(ERR_INSERT);
+    }
+
+    // repeated values are stored once
+    let row = conn
+        .fetch("category", "c", false)
+        .await
+        .expect(ERR_FETCH)
+        .expect(ERR_REQ_ROW);
+    assert_eq!(row.tags.len(), 2);
+
+    let values = || vec!["admin".to_string(), "user".to_string()];
+    for name in ["role", "~level"] {
+        for (filter, expected) in [
+            (TagFilter::is_eq(name, "user"), vec!["a", "b"]),
+            (TagFilter::is_in(name, values()), vec!["a", "b", "c"]),
+            (TagFilter::is_all(name, values()), vec!["a"]),
+            (
+                TagFilter::all_of(vec![
+                    TagFilter::is_all(name, values()),
+                    TagFilter::is_eq(name, "admin"),
+                ]),
+                vec!["a"],
+            ),
+        ] {
+            let mut found = conn
+                .fetch_all("category", Some(filter), None, false)
+                .await
+                .expect(ERR_FETCH_ALL)
+                .into_iter()
+                .map(|row| row.name)
+                .collect::<Vec<_>>();
+            found.sort();
+            assert_eq!(found, expected);
+        }
+    }
+
+    // replacing the record replaces all of the values
+    conn.replace(
+        "category",
+        "a",
+        b"value",
+        Some(roles(&["user"]).as_slice()),
+        None,
+    )
+    .await
+    .expect(ERR_REPLACE);
+    let count = conn
+        .count("category", Some(TagFilter::is_all("role", values())))
+        .await
+        .expect(ERR_COUNT);
+    assert_eq!(count, 0);
+}
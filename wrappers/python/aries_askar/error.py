@@ -13,6 +13,16 @@ class AskarErrorCode(IntEnum):
     NOT_FOUND = 6
     UNEXPECTED = 7
     UNSUPPORTED = 8
+    CONNECTION_LOST = 9
+    POLICY_VIOLATION = 10
+    FORBIDDEN = 11
+    CONFLICT = 12
+    QUERY_TOO_COMPLEX = 13
+    WEAK_PASS_KEY = 14
+    WRONG_PASS_KEY = 15
+    UNSUPPORTED_WRAP_METHOD = 16
+    SCHEMA_MISMATCH = 17
+    POISONED = 18
     WRAPPER = 99
 
 